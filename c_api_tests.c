@@ -0,0 +1,63 @@
+/* c_api_tests.c */
+#include "crdt_c.h"
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* Simple assertion helper */
+static void assert_true(int condition, const char *message) {
+  if (!condition) {
+    fprintf(stderr, "Assertion failed: %s\n", message);
+    exit(1);
+  }
+}
+
+int main(void) {
+  /* Test Case: Sync Through the C API */
+  {
+    crdt_handle *phone = crdt_new(1);
+    crdt_handle *server = crdt_new(2);
+    const char *columns[] = {"title", "done"};
+    const char *values[] = {"Buy milk", "false"};
+    assert_true(crdt_insert_or_update(phone, "todo1", columns, values, 2) == CRDT_OK, "C API: Insert should succeed");
+    assert_true(crdt_insert_or_update(phone, NULL, columns, values, 2) == CRDT_ERR_INVALID_ARGUMENT,
+                "C API: Null record ids should be rejected");
+
+    crdt_buffer changes;
+    assert_true(crdt_changes_since(phone, 0, &changes) == CRDT_OK, "C API: Extracting changes should succeed");
+    assert_true(crdt_merge(server, changes.data, changes.len) == CRDT_OK, "C API: Merging changes should succeed");
+    crdt_buffer_free(&changes);
+
+    crdt_buffer value;
+    assert_true(crdt_get_value(server, "todo1", "title", &value) == CRDT_OK, "C API: Merged value should be readable");
+    assert_true(value.len == 8 && strcmp((const char *)value.data, "Buy milk") == 0, "C API: Value should match");
+    crdt_buffer_free(&value);
+
+    static char garbage_pointer[] = "not a buffer";
+    uint64_t seen = crdt_current_version(phone);
+    assert_true(crdt_delete_record(phone, "todo1") == CRDT_OK, "C API: Delete should succeed");
+    assert_true(crdt_changes_since(phone, seen, &changes) == CRDT_OK, "C API: Incremental changes should succeed");
+    assert_true(crdt_merge(server, changes.data, changes.len) == CRDT_OK, "C API: Merging the deletion should succeed");
+    crdt_buffer_free(&changes);
+    value.data = (uint8_t *)garbage_pointer;
+    value.len = 3;
+    assert_true(crdt_get_value(server, "todo1", "title", &value) == CRDT_ERR_NOT_FOUND,
+                "C API: Deleted records should not be found");
+    assert_true(value.data == NULL && value.len == 0, "C API: A missing value should leave an empty buffer");
+    crdt_buffer_free(&value);
+    value.data = (uint8_t *)garbage_pointer;
+    assert_true(crdt_get_value(NULL, "todo1", "title", &value) == CRDT_ERR_INVALID_ARGUMENT && value.data == NULL,
+                "C API: Failed calls should leave an empty buffer");
+
+    const uint8_t garbage[] = {0xff, 0xff, 0xff};
+    assert_true(crdt_merge(server, garbage, sizeof(garbage)) == CRDT_ERR_DECODE, "C API: Malformed input should be rejected");
+
+    crdt_free(phone);
+    crdt_free(server);
+    printf("Test 'Sync Through the C API' passed.\n");
+  }
+
+  printf("All C API tests passed successfully!\n");
+  return 0;
+}
@@ -2,6 +2,21 @@
 #ifndef CRDT_HPP
 #define CRDT_HPP
 
+#include <compare>
+#include <cstdint>
+
+/// A 128-bit node identifier, so nodes can pick their own ids (see `CrdtUuid::random`) instead of being assigned unique
+/// integers. Define CRDT_UUID_NODE_IDS to use it as CrdtNodeId.
+struct CrdtUuid {
+  uint64_t high = 0;
+  uint64_t low = 0;
+
+  constexpr auto operator<=>(const CrdtUuid &) const = default;
+
+  /// Generates a random (version 4) UUID.
+  static CrdtUuid random();
+};
+
 // Define this if you want to override the default collection types
 // Basically define these before including this header and ensure this define is set before this header is included
 // in any other files that include this file
@@ -9,25 +24,129 @@
 #include <string>
 #include <unordered_map>
 #include <unordered_set>
+#include <map>
 #include <set>
 #include <vector>
 
 template <typename T> using CrdtVector = std::vector<T>;
 using CrdtString = std::string;
+// Define CRDT_ORDERED_COLLECTIONS to store records sorted by key, which makes range(), prefix() and latest() scans
+// logarithmic instead of linear. Keys then need operator< instead of std::hash.
+#ifdef CRDT_ORDERED_COLLECTIONS
+template <typename K, typename V, typename Hash = void, typename KeyEqual = void> using CrdtMap = std::map<K, V>;
+template <typename K, typename Hash = void, typename KeyEqual = void> using CrdtSet = std::set<K>;
+#else
 template <typename K, typename V, typename Hash = std::hash<K>, typename KeyEqual = std::equal_to<K>>
 using CrdtMap = std::unordered_map<K, V, Hash, KeyEqual>;
 template <typename K, typename Hash = std::hash<K>, typename KeyEqual = std::equal_to<K>>
 using CrdtSet = std::unordered_set<K, Hash, KeyEqual>;
+#endif
 template <typename T, typename Comparator> using CrdtSortedSet = std::set<T, Comparator>;
+#ifdef CRDT_UUID_NODE_IDS
+using CrdtNodeId = CrdtUuid;
+#else
 using CrdtNodeId = uint64_t;
 #endif
+#endif
 
 #include <algorithm>
+#include <iomanip>
 #include <iostream>
 #include <optional>
 #include <memory>
 #include <type_traits>
 #include <concepts>
+#include <charconv>
+#include <chrono>
+#include <deque>
+#include <functional>
+#include <cstring>
+#include <random>
+#include <ranges>
+#include <sstream>
+#include <exception>
+#include <stdexcept>
+#include <map>
+#include <thread>
+#include <string_view>
+#include <tuple>
+#include <utility>
+#include <variant>
+
+template <> struct std::hash<CrdtUuid> {
+  size_t operator()(const CrdtUuid &id) const noexcept {
+    return std::hash<uint64_t>()(id.high) ^ (std::hash<uint64_t>()(id.low) * 0x9e3779b97f4a7c15ULL);
+  }
+};
+
+inline CrdtUuid CrdtUuid::random() {
+  static thread_local std::mt19937_64 rng{(uint64_t{std::random_device{}()} << 32) ^ std::random_device{}()};
+  CrdtUuid id{rng(), rng()};
+  id.high = (id.high & ~0xf000ULL) | 0x4000ULL;                          // version 4
+  id.low = (id.low & 0x3fffffffffffffffULL) | 0x8000000000000000ULL; // RFC 4122 variant
+  return id;
+}
+
+/// Formats as the canonical 8-4-4-4-12 hex form.
+inline std::ostream &operator<<(std::ostream &os, const CrdtUuid &id) {
+  std::ios_base::fmtflags flags = os.flags();
+  char fill = os.fill('0');
+  os << std::hex << std::setw(8) << (id.high >> 32) << '-' << std::setw(4) << ((id.high >> 16) & 0xffff) << '-'
+     << std::setw(4) << (id.high & 0xffff) << '-' << std::setw(4) << (id.low >> 48) << '-' << std::setw(12)
+     << (id.low & 0xffffffffffffULL);
+  os.flags(flags);
+  os.fill(fill);
+  return os;
+}
+
+inline CrdtString to_string(const CrdtUuid &id) {
+  std::ostringstream os;
+  os << id;
+  return CrdtString(os.str());
+}
+
+/// Parses the canonical 8-4-4-4-12 hex form.
+inline std::optional<CrdtUuid> parse_uuid(std::string_view text) {
+  if (text.size() != 36 || text[8] != '-' || text[13] != '-' || text[18] != '-' || text[23] != '-') {
+    return std::nullopt;
+  }
+  CrdtUuid id;
+  unsigned digits = 0;
+  for (char c : text) {
+    if (c == '-') {
+      continue;
+    }
+    uint64_t nibble;
+    if (c >= '0' && c <= '9') {
+      nibble = static_cast<uint64_t>(c - '0');
+    } else if (c >= 'a' && c <= 'f') {
+      nibble = static_cast<uint64_t>(c - 'a' + 10);
+    } else if (c >= 'A' && c <= 'F') {
+      nibble = static_cast<uint64_t>(c - 'A' + 10);
+    } else {
+      return std::nullopt;
+    }
+    uint64_t &half = digits < 16 ? id.high : id.low;
+    half = (half << 4) | nibble;
+    ++digits;
+  }
+  return id;
+}
+
+/// What a change does. A change's kind follows from its column name and version: record-level changes have no column
+/// name, and are deletions or revivals depending on the incarnation they carry (see `CRDT::delete_record`).
+enum class ChangeKind {
+  Update, // writes a column, or deletes it when the value is std::nullopt
+  Delete, // deletes the record
+  Revive, // revives a deleted record, ahead of the columns written in its new incarnation
+};
+
+constexpr ChangeKind crdt_change_kind(bool has_column, uint64_t col_version) {
+  if (has_column) {
+    return ChangeKind::Update;
+  }
+  return col_version != 0 && col_version % 2 == 0 ? ChangeKind::Revive : ChangeKind::Delete;
+}
 
 /// Represents a single change in the CRDT.
 template <typename K, typename V> struct Change {
@@ -42,12 +161,45 @@ template <typename K, typename V> struct Change {
   // we record the local db_version when the change was created
   uint64_t local_db_version;
 
+  // record deletions only: the deleting node's wall clock (milliseconds since the Unix epoch), 0 if unknown
+  uint64_t wall_time = 0;
+
+  // the writing node's operation sequence number (see ColumnVersion::seq), 0 if unknown
+  uint64_t seq = 0;
+
   Change() = default;
 
   Change(K rid, std::optional<CrdtString> cname, std::optional<V> val, uint64_t cver, uint64_t dver, CrdtNodeId nid,
          uint64_t ldb_ver = 0)
       : record_id(std::move(rid)), col_name(std::move(cname)), value(std::move(val)), col_version(cver), db_version(dver),
         node_id(nid), local_db_version(ldb_ver) {}
+
+  ChangeKind kind() const { return crdt_change_kind(col_name.has_value(), col_version); }
+};
+
+/// A change borrowing its record id, column name and value from the CRDT, as returned by
+/// `CRDT::get_change_refs_since`. It stays valid until the CRDT is next modified.
+template <typename K, typename V> struct ChangeRef {
+  std::reference_wrapper<const K> record_id;
+  const CrdtString *col_name; // nullptr represents tombstone of the record
+  const V *value;             // nullptr represents deletion of the column (or a tombstone)
+  uint64_t col_version;
+  uint64_t db_version;
+  CrdtNodeId node_id;
+  uint64_t local_db_version;
+  uint64_t wall_time = 0;
+  uint64_t seq = 0;
+
+  /// Copies the borrowed data into an owning Change.
+  Change<K, V> to_owned() const {
+    Change<K, V> change(record_id.get(), col_name ? std::optional<CrdtString>(*col_name) : std::nullopt,
+                        value ? std::optional<V>(*value) : std::nullopt, col_version, db_version, node_id, local_db_version);
+    change.wall_time = wall_time;
+    change.seq = seq;
+    return change;
+  }
+
+  ChangeKind kind() const { return crdt_change_kind(col_name != nullptr, col_version); }
 };
 
 // Define a concept for a custom merge rule
@@ -68,8 +220,10 @@ template <typename K, typename V> struct DefaultMergeRule {
         return true;
       } else if (remote.db_version < local.db_version) {
         return false;
-      } else {
+      } else if (remote.node_id != local.node_id) {
         return (remote.node_id > local.node_id);
+      } else {
+        return (remote.seq > local.seq);
       }
     }
   }
@@ -96,6 +250,8 @@ template <typename K, typename V> struct DefaultChangeComparator {
       return a.db_version > b.db_version;
     if (a.node_id != b.node_id)
       return a.node_id > b.node_id;
+    if (a.seq != b.seq)
+      return a.seq > b.seq;
     return false; // Consider equal if all fields match
   }
 };
@@ -108,20 +264,80 @@ struct DefaultSort {
   }
 };
 
+/// A point where a fault can be injected for chaos testing. Define CRDT_FAULT_INJECTION and install a hook with
+/// `crdt_fault_hook()` to inject them; without CRDT_FAULT_INJECTION all fault points compile away.
+enum class CrdtFaultPoint {
+  StorageWrite, // a file is being written (change log, cursor store, hub): the write fails halfway
+  MergeChange,  // a merge is about to apply its next change: the merge is interrupted
+  ClockTick,    // the logical clock advances: it jumps forward first
+};
+
+/// Thrown at an injected StorageWrite or MergeChange fault.
+class CrdtInjectedFault : public std::runtime_error {
+public:
+  using std::runtime_error::runtime_error;
+};
+
+#ifdef CRDT_FAULT_INJECTION
+/// The process-wide fault hook, called at every fault point with the point and a detail: the number of changes the
+/// merge applied so far for MergeChange, the clock's time for ClockTick, 0 for StorageWrite. Returning non-zero
+/// injects the fault; for ClockTick the value is the number of versions the clock jumps forward.
+inline std::function<uint64_t(CrdtFaultPoint, uint64_t)> &crdt_fault_hook() {
+  static std::function<uint64_t(CrdtFaultPoint, uint64_t)> hook;
+  return hook;
+}
+
+inline uint64_t crdt_fault(CrdtFaultPoint point, uint64_t detail = 0) {
+  auto &hook = crdt_fault_hook();
+  return hook ? hook(point, detail) : 0;
+}
+#else
+constexpr uint64_t crdt_fault(CrdtFaultPoint, uint64_t = 0) { return 0; }
+#endif
+
+/// Throws CrdtInjectedFault if the fault hook injects a fault at `point`.
+inline void crdt_fault_check(CrdtFaultPoint point, uint64_t detail, const char *what) {
+  if (crdt_fault(point, detail) != 0) {
+    throw CrdtInjectedFault(what);
+  }
+}
+
 /// Represents a logical clock for maintaining causality.
+/// db_versions carry their epoch (see CRDT::start_epoch) in the high bits, so the counters restart in each epoch
+/// without being mistaken for the previous epoch's.
+constexpr unsigned CRDT_EPOCH_SHIFT = 40;
+
+/// The epoch a db_version belongs to.
+constexpr uint64_t crdt_epoch(uint64_t db_version) { return db_version >> CRDT_EPOCH_SHIFT; }
+
+/// The first db_version of an epoch, which the versions kept through the compaction starting it are rebased to.
+constexpr uint64_t crdt_epoch_start(uint64_t epoch) { return epoch << CRDT_EPOCH_SHIFT; }
+
 class LogicalClock {
 public:
   LogicalClock() : time_(0) {}
 
   /// Increments the clock for a local event.
-  constexpr uint64_t tick() { return ++time_; }
+  ///
+  /// Throws std::overflow_error once the clock has reached the last time of its epoch, rather than wrapping around or
+  /// running into the next epoch, which would corrupt the order of versions; start a new epoch to continue.
+  constexpr uint64_t tick() {
+    jump();
+    check_can_advance(time_);
+    return ++time_;
+  }
 
-  /// Updates the clock based on a received time.
+  /// Updates the clock based on a received time. Throws std::overflow_error like `tick`, leaving the clock unchanged.
   constexpr uint64_t update(uint64_t received_time) {
+    jump();
+    check_can_advance(std::max(time_, received_time));
     time_ = std::max(time_, received_time);
     return ++time_;
   }
 
+  /// Whether a clock at `time` can advance without leaving its epoch.
+  static constexpr bool can_advance(uint64_t time) { return crdt_epoch(time + 1) == crdt_epoch(time); }
+
   /// Sets the logical clock to a specific time.
   constexpr void set_time(uint64_t t) { time_ = t; }
 
@@ -130,6 +346,19 @@ public:
 
 private:
   uint64_t time_;
+
+  static constexpr void check_can_advance(uint64_t time) {
+    if (!can_advance(time)) {
+      throw std::overflow_error("logical clock exhausted; start a new epoch");
+    }
+  }
+
+  // Applies an injected clock jump
+  constexpr void jump() {
+    if (!std::is_constant_evaluated()) {
+      time_ += crdt_fault(CrdtFaultPoint::ClockTick, time_);
+    }
+  }
 };
 
 /// Represents the version information for a column.
@@ -142,14 +371,98 @@ struct ColumnVersion {
   // we record the local db_version when the change was created
   uint64_t local_db_version;
 
-  constexpr ColumnVersion(uint64_t c, uint64_t d, CrdtNodeId n, uint64_t ldb_ver = 0)
-      : col_version(c), db_version(d), node_id(n), local_db_version(ldb_ver) {}
+  // The writing node's sequence number for the operation: one per local operation, shared by all of its writes and
+  // strictly increasing per node. Unlike db_version, which is a Lamport clock and jumps forward on merges, it only
+  // counts the node's own operations, so (node_id, seq) identifies a write. 0 for versions that predate it
+  uint64_t seq = 0;
+
+  constexpr ColumnVersion(uint64_t c, uint64_t d, CrdtNodeId n, uint64_t ldb_ver = 0, uint64_t s = 0)
+      : col_version(c), db_version(d), node_id(n), local_db_version(ldb_ver), seq(s) {}
 };
 
+/// The highest db_version seen from each node, as kept by CRDT::max_seen_versions. db_versions are Lamport clocks,
+/// which skip values, so an entry is not a watermark: lower changes of that node may never have arrived.
+using CrdtVersionVector = CrdtMap<CrdtNodeId, uint64_t>;
+
+/// How two writes or states are causally related, as computed by `crdt_causal_order` and `crdt_column_order`.
+enum class CrdtCausality {
+  Before,     // the first happened before the second, which saw it
+  After,      // the second happened before the first
+  Equal,      // both are the same write or state
+  Concurrent, // neither saw the other
+};
+
+/// Whether `write` is part of `history`: the entry for its node has reached its db_version. Like `max_seen_versions`,
+/// this assumes each node's changes were received in order.
+///
+/// Complexity: O(1) average case
+inline bool crdt_happened_before(const ColumnVersion &write, const CrdtVersionVector &history) {
+  auto it = history.find(write.node_id);
+  return it != history.end() && it->second >= write.db_version;
+}
+
+/// Compares two version vectors, e.g. the `max_seen_versions` of two replicas: one is before the other if it has seen
+/// a subset of what the other has seen. Missing entries count as 0.
+///
+/// Complexity: O(a + b), where a and b are the number of entries of the vectors
+inline CrdtCausality crdt_causal_order(const CrdtVersionVector &a, const CrdtVersionVector &b) {
+  bool a_ahead = false;
+  bool b_ahead = false;
+  for (const auto &[node_id, version] : a) {
+    auto it = b.find(node_id);
+    uint64_t other = it != b.end() ? it->second : 0;
+    a_ahead = a_ahead || version > other;
+    b_ahead = b_ahead || version < other;
+  }
+  for (const auto &[node_id, version] : b) {
+    b_ahead = b_ahead || (version > 0 && a.find(node_id) == a.end());
+  }
+  if (a_ahead && b_ahead) {
+    return CrdtCausality::Concurrent;
+  }
+  return a_ahead ? CrdtCausality::After : (b_ahead ? CrdtCausality::Before : CrdtCausality::Equal);
+}
+
+/// Compares two writes given the history each writing node had when it wrote (its `max_seen_versions` at the time):
+/// a write happened before another if the other's history includes it.
+///
+/// Complexity: O(1) average case
+inline CrdtCausality crdt_causal_order(const ColumnVersion &a, const CrdtVersionVector &a_history, const ColumnVersion &b,
+                                       const CrdtVersionVector &b_history) {
+  if (a.node_id == b.node_id && a.db_version == b.db_version) {
+    return CrdtCausality::Equal;
+  }
+  if (crdt_happened_before(a, b_history)) {
+    return CrdtCausality::Before;
+  }
+  return crdt_happened_before(b, a_history) ? CrdtCausality::After : CrdtCausality::Concurrent;
+}
+
+/// Compares two writes of the same column from their versions alone, the way the merge sees them. A write raises the
+/// column's col_version past the one it overwrote, so a lower col_version came first; writes with the same col_version
+/// were made without seeing each other, and the merge rule picks a winner between them.
+///
+/// Complexity: O(1)
+inline CrdtCausality crdt_column_order(const ColumnVersion &a, const ColumnVersion &b) {
+  if (a.col_version != b.col_version) {
+    return a.col_version < b.col_version ? CrdtCausality::Before : CrdtCausality::After;
+  }
+  return a.db_version == b.db_version && a.node_id == b.node_id ? CrdtCausality::Equal : CrdtCausality::Concurrent;
+}
+
+/// Compares two changes to the same column; see `crdt_column_order` for column versions.
+template <typename K, typename V> CrdtCausality crdt_column_order(const Change<K, V> &a, const Change<K, V> &b) {
+  return crdt_column_order(ColumnVersion(a.col_version, a.db_version, a.node_id),
+                           ColumnVersion(b.col_version, b.db_version, b.node_id));
+}
+
 /// Represents a record in the CRDT.
 template <typename V> struct Record {
   CrdtMap<CrdtString, V> fields;
   CrdtMap<CrdtString, ColumnVersion> column_versions;
+  // The version of the record's last revival, if it was deleted and written again. It is kept apart from the columns,
+  // so any column name is available to users
+  std::optional<ColumnVersion> revival;
 
   Record() = default;
 
@@ -173,317 +486,4452 @@ template <typename V> constexpr bool operator==(const Record<V> &lhs, const Reco
   return true;
 }
 
-/// Represents the CRDT structure, generic over key (`K`) and value (`V`) types.
-template <typename K, typename V, MergeRule<K, V> MergeRuleType = DefaultMergeRule<K, V>,
-          ChangeComparator<K, V> ChangeComparatorType = DefaultChangeComparator<K, V>, typename SortFunctionType = DefaultSort>
-class CRDT : public std::enable_shared_from_this<CRDT<K, V, MergeRuleType, ChangeComparatorType, SortFunctionType>> {
-public:
-  // Create a new empty CRDT
-  // Complexity: O(1)
-  CRDT(CrdtNodeId node_id, std::shared_ptr<CRDT<K, V, MergeRuleType, ChangeComparatorType, SortFunctionType>> parent = nullptr,
-       MergeRuleType merge_rule = MergeRuleType(), ChangeComparatorType change_comparator = ChangeComparatorType(),
-       SortFunctionType sort_func = SortFunctionType())
-      : node_id_(node_id), clock_(), data_(), tombstones_(), parent_(parent), merge_rule_(std::move(merge_rule)),
-        change_comparator_(std::move(change_comparator)), sort_func_(std::move(sort_func)) {
-    if (parent_) {
-      // Set clock to parent's clock
-      clock_ = parent_->clock_;
-      // Capture the base version from the parent
-      base_version_ = parent_->clock_.current_time();
-    } else {
-      base_version_ = 0; // No parent, base_version_ is 0
-    }
-  }
+/// The kind of disagreement reported by CRDT::diff.
+enum class DivergenceKind {
+  MissingLocally,    // the record only exists in the other instance
+  MissingRemotely,   // the record only exists in this instance
+  TombstoneMismatch, // the record is tombstoned in one instance and live in the other
+  ValueMismatch,     // the column holds different values (or is missing on one side)
+  VersionMismatch,   // the column (or tombstone) holds the same value but different version metadata
+};
 
-  /// Create a CRDT from a list of changes (e.g., loaded from disk).
-  ///
-  /// # Arguments
-  ///
-  /// * `node_id` - The unique identifier for this CRDT node.
-  /// * `changes` - A list of changes to apply to reconstruct the CRDT state.
-  ///
-  /// Complexity: O(n), where n is the number of changes
-  constexpr CRDT(CrdtNodeId node_id, CrdtVector<Change<K, V>> &&changes) : node_id_(node_id), clock_(), data_(), tombstones_() {
-    apply_changes(std::move(changes));
-  }
+/// A record or column where two CRDT instances disagree. `col_name` is std::nullopt for record-level divergences.
+template <typename K, typename V> struct Divergence {
+  K record_id;
+  std::optional<CrdtString> col_name;
+  DivergenceKind kind;
+  std::optional<V> local_value;
+  std::optional<V> remote_value;
+  std::optional<ColumnVersion> local_version;
+  std::optional<ColumnVersion> remote_version;
+};
 
-  /// Resets the CRDT to a state as if it was constructed with the given changes.
-  ///
-  /// # Arguments
-  ///
-  /// * `changes` - A list of changes to apply to reconstruct the CRDT state.
-  ///
-  /// Complexity: O(n), where n is the number of changes
-  constexpr void reset(CrdtVector<Change<K, V>> &&changes) {
-    // Clear existing data
-    data_.clear();
-    tombstones_.clear();
+/// A modification reported to observers registered with CRDT::on_change.
+///
+/// Column writes carry the column name with the previous and new values (std::nullopt if the column was absent or was
+/// removed). Deleting a record reports each of its columns with `new_value` std::nullopt, followed by one event with
+/// `col_name` std::nullopt.
+template <typename K, typename V> struct ChangeEvent {
+  K record_id;
+  std::optional<CrdtString> col_name;
+  std::optional<V> old_value;
+  std::optional<V> new_value;
+  CrdtNodeId node_id; // the node that made the change
+  uint64_t db_version;
+  bool is_remote; // true if the change arrived through merge_changes
+};
 
-    // Reset the logical clock
-    clock_ = LogicalClock();
+/// One past or current value of a column, as kept by CRDT::history. `value` is std::nullopt for a column deletion.
+template <typename V> struct ColumnHistoryEntry {
+  std::optional<V> value;
+  ColumnVersion version;
+};
 
-    apply_changes(std::move(changes));
+/// A timed operation reported to the trace sink. Define CRDT_TRACING and install a sink with `crdt_trace_sink()` to
+/// forward these to a logging or tracing system; without CRDT_TRACING all instrumentation compiles away.
+struct CrdtTraceEvent {
+  const char *name = nullptr; // "merge_changes", "get_changes_since", "sync.hello", "sync.changes", "sync.ack"
+  std::chrono::nanoseconds duration{0};
+  size_t changes = 0;  // changes processed or produced
+  size_t accepted = 0; // merge: changes applied
+  size_t rejected = 0; // merge: changes older than the local state
+  uint64_t version = 0; // the db_version the operation started from or reached
+};
+
+#ifdef CRDT_TRACING
+/// The process-wide trace sink. Install it before any CRDT work starts; it is called from whichever thread runs the
+/// traced operation.
+inline std::function<void(const CrdtTraceEvent &)> &crdt_trace_sink() {
+  static std::function<void(const CrdtTraceEvent &)> sink;
+  return sink;
+}
+
+/// Times a scope and reports `event` to the trace sink when it ends.
+class CrdtTraceSpan {
+public:
+  explicit CrdtTraceSpan(const char *name) : start_(std::chrono::steady_clock::now()) { event.name = name; }
+  CrdtTraceSpan(const CrdtTraceSpan &) = delete;
+  CrdtTraceSpan &operator=(const CrdtTraceSpan &) = delete;
+  ~CrdtTraceSpan() {
+    if (auto &sink = crdt_trace_sink()) {
+      event.duration = std::chrono::steady_clock::now() - start_;
+      sink(event);
+    }
   }
 
-  /// Generates inverse changes for a given set of changes based on the parent state.
-  ///
-  /// # Arguments
-  ///
-  /// * `changes` - A vector of changes to invert.
-  ///
-  /// # Returns
-  ///
-  /// A vector of inverse `Change` objects.
-  /// Generates inverse changes for a given set of changes based on the specified source (parent or top-level CRDT).
-  ///
-  /// # Arguments
-  ///
-  /// * `changes` - A vector of changes to invert.
-  ///
-  /// # Returns
-  ///
-  /// A vector of inverse `Change` objects.
-  CrdtVector<Change<K, V>> invert_changes(const CrdtVector<Change<K, V>> &changes) {
-    CrdtVector<Change<K, V>> inverse_changes;
+  CrdtTraceEvent event;
 
-    for (const auto &change : changes) {
-      const K &record_id = change.record_id;
-      const std::optional<CrdtString> &col_name = change.col_name;
-      const std::optional<V> &value = change.value;
+private:
+  std::chrono::steady_clock::time_point start_;
+};
+#else
+class CrdtTraceSpan {
+public:
+  explicit CrdtTraceSpan(const char *) {}
 
-      if (!col_name.has_value()) {
-        // The change was a record deletion (tombstone)
-        // To revert, restore the record's state from the specified source
-        auto record_ptr = parent_ ? parent_->get_record_ptr(record_id) : get_record_ptr(record_id);
-        if (record_ptr) {
-          // Restore all fields from the record
-          for (const auto &[parent_col, parent_val] : record_ptr->fields) {
-            inverse_changes.emplace_back(Change<K, V>(record_id, parent_col, parent_val,
-                                                      record_ptr->column_versions.at(parent_col).col_version,
-                                                      record_ptr->column_versions.at(parent_col).db_version, node_id_,
-                                                      record_ptr->column_versions.at(parent_col).local_db_version));
-          }
-          // Remove the tombstone
-          inverse_changes.emplace_back(Change<K, V>(record_id, std::nullopt, std::nullopt,
-                                                    0, // Column version 0 signifies removal of tombstone
-                                                    clock_.current_time(), node_id_));
-        }
-      } else {
-        // The change was an insertion or update of a column
-        CrdtString col = *col_name;
-        auto record_ptr = parent_ ? parent_->get_record_ptr(record_id) : get_record_ptr(record_id);
-        if (record_ptr) {
-          auto field_it = record_ptr->fields.find(col);
-          if (field_it != record_ptr->fields.end()) {
-            // The record has a value for this column; set it back to the record's value
-            inverse_changes.emplace_back(Change<K, V>(
-                record_id, col, field_it->second, record_ptr->column_versions.at(col).col_version,
-                record_ptr->column_versions.at(col).db_version, node_id_, record_ptr->column_versions.at(col).local_db_version));
-          } else {
-            // The record does not have this column; delete it to revert
-            inverse_changes.emplace_back(Change<K, V>(record_id, col,
-                                                      std::nullopt, // Indicates deletion
-                                                      0,            // Column version 0 signifies deletion
-                                                      clock_.current_time(), node_id_));
-          }
-        } else {
-          // The record does not have the record; remove the entire record to revert
-          inverse_changes.emplace_back(Change<K, V>(record_id, std::nullopt, std::nullopt,
-                                                    0, // Column version 0 signifies a tombstone
-                                                    clock_.current_time(), node_id_));
-        }
-      }
-    }
+  CrdtTraceEvent event;
+};
+#endif
 
-    return inverse_changes;
-  }
+/// Whether a CRDT holds a record, as reported by CRDT::status.
+enum class RecordStatus {
+  Live,       // the record exists and can be read
+  Tombstoned, // the record was deleted; writes are ignored until it is revived
+  Unknown,    // the record was never seen by this instance
+};
 
-  /// Reverts all changes made by this CRDT since it was created from the parent.
-  ///
-  /// # Returns
-  ///
-  /// A vector of `Change` objects representing the inverse changes needed to undo the child’s changes.
-  ///
-  /// # Complexity
-  ///
-  /// O(c), where c is the number of changes since `base_version_`
-  constexpr CrdtVector<Change<K, V>> revert() {
-    if (!parent_) {
-      throw std::runtime_error("Cannot revert without a parent CRDT.");
-    }
+/// A deleted record, kept apart from the live records: the version of its deletion, and the deleting node's wall clock
+/// where known.
+struct CrdtTombstone {
+  ColumnVersion version;
+  uint64_t wall_time = 0; // milliseconds since the Unix epoch, 0 if unknown
+};
 
-    // Step 1: Retrieve all changes made by the child since base_version_
-    CrdtVector<Change<K, V>> child_changes = this->get_changes_since(base_version_);
+/// Who deleted a record and when, as reported by CRDT::deletion_info.
+struct CrdtDeletionInfo {
+  CrdtNodeId node_id;
+  uint64_t db_version;               // the deleting node's clock
+  std::optional<uint64_t> wall_time; // milliseconds since the Unix epoch, if the deleting node had a wall clock set
+};
 
-    // Step 2: Generate inverse changes using the generalized function
-    CrdtVector<Change<K, V>> inverse_changes = invert_changes(child_changes);
+/// A tombstone as listed by CRDT::tombstones.
+template <typename K> struct CrdtTombstoneInfo {
+  K record_id;
+  ColumnVersion version;
+  uint64_t age = 0;                  // local db_versions elapsed since the tombstone was stored
+  std::optional<uint64_t> wall_time; // milliseconds since the Unix epoch, if the deleting node had a wall clock set
+  std::optional<uint64_t> wall_age;  // milliseconds since the deletion, if both it and this node have a wall clock
+};
 
-    return inverse_changes;
-  }
+/// Tombstone counters reported by CRDT::tombstone_counts.
+struct CrdtTombstoneCounts {
+  size_t total = 0;
+  size_t gc_eligible = 0; // tombstones every peer has acknowledged, which a compaction could drop
+};
+
+/// The latest write to a record, as reported by CRDT::record_version.
+struct CrdtRecordVersion {
+  uint64_t db_version; // the highest db_version across the record's columns, or of its deletion
+  CrdtNodeId node_id;  // the node that made that write
+
+  bool operator==(const CrdtRecordVersion &) const = default;
+};
+
+/// The last write to a column, as reported by CRDT::last_writer.
+struct CrdtLastWriter {
+  CrdtNodeId node_id;
+  uint64_t db_version;  // the writer's clock when it wrote the column
+  uint64_t col_version; // the column's write sequence number
+};
+
+/// An incoming write to a manually resolved column, parked instead of merged, as listed by CRDT::pending_conflicts.
+template <typename K, typename V> struct CrdtConflict {
+  Change<K, V> remote;          // the parked incoming change
+  ColumnVersion local;          // the local version it conflicted with
+  std::optional<V> local_value; // the local value when it was parked, std::nullopt if the column was deleted
+};
+
+/// Counters and sizes reported by CRDT::stats.
+struct CrdtStats {
+  size_t live_records = 0;
+  size_t tombstones = 0;
+  size_t columns = 0; // column versions held by live records
+  uint64_t clock = 0;
+  size_t bytes_estimate = 0;
+
+  // Counted since this instance was constructed
+  uint64_t merges = 0;             // non-empty merge_changes calls
+  uint64_t changes_accepted = 0;   // incoming changes that were applied
+  uint64_t changes_rejected = 0;   // incoming changes that were older than (or duplicates of) the local state
+  uint64_t conflicts_resolved = 0; // incoming changes that met a different local version of the same column
+  uint64_t changes_denied = 0;     // incoming changes rejected by the write policy or to local-only columns
+  uint64_t changes_invalid = 0;    // incoming changes that violated the schema or validator (not counted as rejected)
+  uint64_t conflicts_parked = 0;   // incoming changes parked for manual resolution (not counted as rejected)
+  uint64_t changes_other_epoch = 0; // incoming changes from another epoch that were dropped (see CRDT::start_epoch)
+  uint64_t clock_anomalies = 0;     // received db_versions and local operations flagged by the clock safeguards
+  uint64_t changes_unregistered = 0; // incoming changes from unregistered sites quarantined in strict mode
+};
+
+template <typename T> struct CrdtIsVariant : std::false_type {};
+template <typename... Ts> struct CrdtIsVariant<std::variant<Ts...>> : std::true_type {};
+
+/// How CRDT::format_record and CRDT::format_diff lay out their output: indented lines, or a table with a row per column.
+enum class CrdtFormatStyle { Text, Table };
+
+inline std::ostream &operator<<(std::ostream &os, const ColumnVersion &version) {
+  return os << "col_version " << version.col_version << ", db_version " << version.db_version << ", node "
+            << version.node_id << ", local_db_version " << version.local_db_version;
+}
+
+inline std::ostream &operator<<(std::ostream &os, const CrdtTombstone &tombstone) {
+  os << "deleted (" << tombstone.version;
+  if (tombstone.wall_time != 0) {
+    os << ", wall_time " << tombstone.wall_time;
+  }
+  return os << ")";
+}
+
+inline std::ostream &operator<<(std::ostream &os, DivergenceKind kind) {
+  switch (kind) {
+  case DivergenceKind::MissingLocally:
+    return os << "missing locally";
+  case DivergenceKind::MissingRemotely:
+    return os << "missing remotely";
+  case DivergenceKind::TombstoneMismatch:
+    return os << "tombstone mismatch";
+  case DivergenceKind::ValueMismatch:
+    return os << "value mismatch";
+  case DivergenceKind::VersionMismatch:
+    return os << "version mismatch";
+  }
+  return os;
+}
+
+namespace crdt_format {
+
+/// Writes a record id or value: strings quoted, variants as their alternative, other types as streamed, and types that
+/// cannot be streamed as a placeholder.
+template <typename T> void write_value(std::ostream &os, const T &value) {
+  if constexpr (std::is_same_v<T, CrdtString>) {
+    os << std::quoted(value);
+  } else if constexpr (CrdtIsVariant<T>::value) {
+    std::visit([&](const auto &alternative) { write_value(os, alternative); }, value);
+  } else if constexpr (std::is_same_v<T, CrdtVector<uint8_t>>) {
+    os << "<" << value.size() << " bytes>";
+  } else if constexpr (requires { os << value; }) {
+    os << value;
+  } else {
+    os << "<value>";
+  }
+}
+
+template <typename T> CrdtString to_text(const T &value) {
+  std::ostringstream os;
+  write_value(os, value);
+  return os.str();
+}
+
+template <typename T> CrdtString to_text(const std::optional<T> &value) { return value ? to_text(*value) : "-"; }
+
+// A version in a table cell, as col_version/db_version/node
+inline CrdtString version_cell(const std::optional<ColumnVersion> &version) {
+  if (!version) {
+    return "-";
+  }
+  std::ostringstream os;
+  os << version->col_version << "/" << version->db_version << "/" << version->node_id;
+  return os.str();
+}
+
+/// Writes rows as a table, the first row being the header, with columns padded to their widest cell.
+inline void write_table(std::ostream &os, const CrdtVector<CrdtVector<CrdtString>> &rows) {
+  CrdtVector<size_t> widths;
+  for (const auto &row : rows) {
+    widths.resize(std::max(widths.size(), row.size()));
+    for (size_t i = 0; i < row.size(); ++i) {
+      widths[i] = std::max(widths[i], row[i].size());
+    }
+  }
+  auto write_row = [&](const CrdtVector<CrdtString> &row) {
+    for (size_t i = 0; i < row.size(); ++i) {
+      os << (i == 0 ? "" : " | ") << row[i];
+      if (i + 1 < row.size()) {
+        os << CrdtString(widths[i] - row[i].size(), ' ');
+      }
+    }
+    os << "\n";
+  };
+  for (size_t r = 0; r < rows.size(); ++r) {
+    write_row(rows[r]);
+    if (r == 0) {
+      for (size_t i = 0; i < widths.size(); ++i) {
+        os << (i == 0 ? "" : "-+-") << CrdtString(widths[i], '-');
+      }
+      os << "\n";
+    }
+  }
+}
+
+} // namespace crdt_format
+
+template <typename K, typename V> std::ostream &operator<<(std::ostream &os, const Divergence<K, V> &divergence) {
+  crdt_format::write_value(os, divergence.record_id);
+  if (divergence.col_name) {
+    os << " " << *divergence.col_name;
+  }
+  os << ": " << divergence.kind;
+  if (divergence.kind == DivergenceKind::ValueMismatch || divergence.kind == DivergenceKind::VersionMismatch) {
+    os << "\n  local:  " << crdt_format::to_text(divergence.local_value);
+    if (divergence.local_version) {
+      os << " (" << *divergence.local_version << ")";
+    }
+    os << "\n  remote: " << crdt_format::to_text(divergence.remote_value);
+    if (divergence.remote_version) {
+      os << " (" << *divergence.remote_version << ")";
+    }
+  }
+  return os;
+}
+
+/// An internal invariant of the stored state, as checked by CRDT::check_invariants.
+enum class CrdtInvariantKind {
+  TombstoneNotDeletion, // a tombstone's version is not a deletion (an odd incarnation)
+  LiveRecordDeleted,    // a live record's revival version is a deletion
+  LiveAndTombstoned,    // a record is both live and tombstoned
+  FieldWithoutVersion,  // a field of a replicated column has no column version
+  ColumnAfterRecord,    // a column was written in a later incarnation than its record's
+  VersionAheadOfClock,  // a db_version or local_db_version is ahead of the clock
+};
+
+inline std::ostream &operator<<(std::ostream &os, CrdtInvariantKind kind) {
+  switch (kind) {
+  case CrdtInvariantKind::TombstoneNotDeletion:
+    return os << "tombstone without a deletion version";
+  case CrdtInvariantKind::LiveRecordDeleted:
+    return os << "live record with a deletion version";
+  case CrdtInvariantKind::LiveAndTombstoned:
+    return os << "record both live and tombstoned";
+  case CrdtInvariantKind::FieldWithoutVersion:
+    return os << "field without a column version";
+  case CrdtInvariantKind::ColumnAfterRecord:
+    return os << "column from a later incarnation than its record";
+  case CrdtInvariantKind::VersionAheadOfClock:
+    return os << "version ahead of the clock";
+  }
+  return os << "unknown invariant";
+}
+
+/// A violated invariant: where it was found, and the version and clock involved.
+template <typename K> struct CrdtInvariantViolation {
+  CrdtInvariantKind kind;
+  K record_id;
+  std::optional<CrdtString> col_name; // std::nullopt for the record's tombstone or the record as a whole
+  std::optional<ColumnVersion> version;
+  uint64_t clock = 0;
+};
+
+template <typename K> std::ostream &operator<<(std::ostream &os, const CrdtInvariantViolation<K> &violation) {
+  crdt_format::write_value(os, violation.record_id);
+  if (violation.col_name) {
+    os << " " << *violation.col_name;
+  }
+  os << ": " << violation.kind;
+  if (violation.version) {
+    os << " (" << *violation.version << ")";
+  }
+  return os << ", clock " << violation.clock;
+}
+
+/// Thrown after a merge that left the state inconsistent when CRDT_CHECK_INVARIANTS is defined. The message lists every
+/// violation.
+class CrdtInvariantError : public std::logic_error {
+public:
+  using std::logic_error::logic_error;
+};
+
+/// A column type check for CrdtSchema: whether a value of type `V` holds a `T`. Variants must hold the alternative `T`,
+/// and strings must parse entirely as `T` when `T` is arithmetic (so "12" is an int64_t but "12a" is not).
+template <typename T, typename V> std::function<bool(const V &)> crdt_column_type() {
+  if constexpr (std::is_same_v<T, V>) {
+    return [](const V &) { return true; };
+  } else if constexpr (CrdtIsVariant<V>::value) {
+    return [](const V &value) { return std::holds_alternative<T>(value); };
+  } else if constexpr (std::is_same_v<V, CrdtString> && std::is_same_v<T, bool>) {
+    return [](const V &value) { return value == "true" || value == "false"; };
+  } else if constexpr (std::is_same_v<V, CrdtString> && std::is_arithmetic_v<T>) {
+    return [](const V &value) {
+      T parsed;
+      auto [end, error] = std::from_chars(value.data(), value.data() + value.size(), parsed);
+      return error == std::errc() && end == value.data() + value.size();
+    };
+  } else {
+    static_assert(std::is_same_v<T, V>, "no column type check for this value type; pass a predicate instead");
+  }
+}
+
+/// What `merge_changes` does with incoming changes that violate the schema.
+enum class CrdtInvalidChangePolicy {
+  Reject,     // drop them
+  Quarantine, // drop them, keeping a copy in CRDT::quarantined() for inspection or repair
+};
+
+/// What `merge_changes` does with changes made in an epoch before the replica's (see CRDT::start_epoch).
+enum class CrdtEpochPolicy {
+  Reject,    // drop them
+  Translate, // rebase them to the start of the current epoch, like the versions kept through the compaction
+};
+
+/// A clock safeguard that was triggered, as reported to CRDT::set_clock_anomaly_handler.
+enum class CrdtClockAnomalyKind {
+  FarAhead,  // a received db_version is further ahead of the local clock than the clock guard allows
+  Exhausted, // the clock reached the last time of its epoch, so it cannot advance to the received db_version or tick
+};
+
+struct CrdtClockAnomaly {
+  CrdtClockAnomalyKind kind;
+  std::optional<CrdtNodeId> node_id; // the node that sent the db_version, std::nullopt for a local operation
+  uint64_t local_time = 0;           // the local clock when the anomaly was detected
+  uint64_t received_time = 0;        // the received db_version, 0 for a local operation
+  bool rejected = false;             // whether the change was dropped (local operations throw std::overflow_error)
+};
+
+/// What `merge_changes` does with changes whose db_version is too far ahead, installed with CRDT::set_clock_guard.
+enum class CrdtClockAnomalyAction {
+  Reject, // drop them
+  Report, // merge them, only reporting them
+};
+
+struct CrdtClockGuard {
+  uint64_t max_jump = 0; // how far ahead of the local clock a received db_version may be, 0 for no limit
+  CrdtClockAnomalyAction action = CrdtClockAnomalyAction::Reject;
+};
+
+/// Expected columns of the records of a CRDT, installed with CRDT::set_schema.
+///
+/// Local operations that violate the schema throw std::invalid_argument; incoming changes that violate it are handled
+/// by `invalid_policy`. Every replica must install the same schema so that all of them drop the same changes.
+template <typename V> class CrdtSchema {
+public:
+  struct Column {
+    std::function<bool(const V &)> type; // accepts every value when empty; see crdt_column_type
+    bool nullable = true;                // whether the column may be deleted or left out
+    std::optional<V> default_value;      // written when a local insert creates a record without the column
+  };
+
+  CrdtSchema &column(const CrdtString &col_name, Column column) {
+    columns_[col_name] = std::move(column);
+    return *this;
+  }
+
+  /// Rejects columns the schema does not declare. By default they are allowed and unchecked.
+  CrdtSchema &strict(bool strict = true) {
+    strict_ = strict;
+    return *this;
+  }
+
+  /// Keeps incoming changes to undeclared columns even when strict, e.g. columns added by a newer app version, so this
+  /// replica stores them and passes them on instead of dropping them. Local writes to them are still rejected.
+  CrdtSchema &preserve_unknown(bool preserve = true) {
+    preserve_unknown_ = preserve;
+    return *this;
+  }
+
+  CrdtSchema &invalid_policy(CrdtInvalidChangePolicy policy) {
+    invalid_policy_ = policy;
+    return *this;
+  }
+
+  CrdtInvalidChangePolicy invalid_policy() const { return invalid_policy_; }
+
+  const CrdtMap<CrdtString, Column> &columns() const { return columns_; }
+
+  /// Why writing `value` to `col_name` (or deleting the column, when `value` is std::nullopt) violates the schema, or
+  /// std::nullopt if it does not.
+  std::optional<CrdtString> violation(const CrdtString &col_name, const std::optional<V> &value) const {
+    auto it = columns_.find(col_name);
+    if (it == columns_.end()) {
+      return strict_ ? std::optional<CrdtString>("unknown column '" + col_name + "'") : std::nullopt;
+    }
+    if (!value) {
+      return it->second.nullable ? std::nullopt : std::optional<CrdtString>("column '" + col_name + "' is not nullable");
+    }
+    if (it->second.type && !it->second.type(*value)) {
+      return "invalid value for column '" + col_name + "'";
+    }
+    return std::nullopt;
+  }
+
+  /// Same as `violation`, for a change received from a peer.
+  std::optional<CrdtString> incoming_violation(const CrdtString &col_name, const std::optional<V> &value) const {
+    if (preserve_unknown_ && !columns_.contains(col_name)) {
+      return std::nullopt;
+    }
+    return violation(col_name, value);
+  }
+
+private:
+  CrdtMap<CrdtString, Column> columns_;
+  bool strict_ = false;
+  bool preserve_unknown_ = false;
+  CrdtInvalidChangePolicy invalid_policy_ = CrdtInvalidChangePolicy::Reject;
+};
+
+/// A schema change replicated through CRDT::migrate. Migrations are numbered from 1 and applied in order.
+template <typename V> struct CrdtMigration {
+  enum class Kind : uint8_t {
+    AddColumn,    // every record without `column` gets `default_value`
+    RenameColumn, // `column` becomes `new_name`, keeping its versions
+    DropColumn,   // `column` is removed and later writes to it are ignored
+  };
+
+  uint32_t version = 0;
+  Kind kind = Kind::AddColumn;
+  CrdtString column;
+  CrdtString new_name;
+  std::optional<V> default_value;
+
+  static CrdtMigration add_column(uint32_t version, CrdtString column, V default_value) {
+    return {version, Kind::AddColumn, std::move(column), {}, std::move(default_value)};
+  }
+  static CrdtMigration rename_column(uint32_t version, CrdtString column, CrdtString new_name) {
+    return {version, Kind::RenameColumn, std::move(column), std::move(new_name), std::nullopt};
+  }
+  static CrdtMigration drop_column(uint32_t version, CrdtString column) {
+    return {version, Kind::DropColumn, std::move(column), {}, std::nullopt};
+  }
+
+  // Migrations are stored as columns of the migration record, named "<version>\x1f<kind>\x1f<column>\x1f<new_name>"
+  // with the default value as the column's value.
+  static constexpr char SEPARATOR = '\x1f';
+
+  CrdtString column_key() const {
+    return std::to_string(version) + SEPARATOR + std::to_string(static_cast<int>(kind)) + SEPARATOR + column +
+           SEPARATOR + new_name;
+  }
+
+  static std::optional<CrdtMigration> parse(const CrdtString &key, const std::optional<V> &value) {
+    CrdtVector<CrdtString> parts;
+    size_t start = 0;
+    for (size_t end; (end = key.find(SEPARATOR, start)) != CrdtString::npos; start = end + 1) {
+      parts.push_back(key.substr(start, end - start));
+    }
+    parts.push_back(key.substr(start));
+    uint32_t version = 0;
+    int kind = -1;
+    if (parts.size() != 4 ||
+        std::from_chars(parts[0].data(), parts[0].data() + parts[0].size(), version).ec != std::errc() ||
+        std::from_chars(parts[1].data(), parts[1].data() + parts[1].size(), kind).ec != std::errc() || kind < 0 ||
+        kind > static_cast<int>(Kind::DropColumn) || (kind == static_cast<int>(Kind::AddColumn) && !value)) {
+      return std::nullopt;
+    }
+    return CrdtMigration{version, static_cast<Kind>(kind), parts[2], parts[3], value};
+  }
+};
+
+/// A site's membership, as recorded in the site registry (see CRDT::enable_site_registry).
+enum class CrdtSiteStatus {
+  Unknown, // never joined
+  Active,  // joined and not retired
+  Retired, // left for good; it must not write again, and rejoins under a new node id
+};
+
+struct CrdtSiteInfo {
+  CrdtNodeId node_id{};
+  CrdtSiteStatus status = CrdtSiteStatus::Unknown;
+  uint64_t final_version = 0; // for a retired site, the last db_version it wrote
+};
+
+// A join or retirement in the site registry, stored as a column without a value named "<kind>\x1f<node_id>" for a
+// join and "<kind>\x1f<node_id>\x1f<final_version>" for a retirement
+struct CrdtSiteMarker {
+  bool retired = false;
+  CrdtNodeId node_id{};
+  uint64_t final_version = 0;
+
+  static constexpr char SEPARATOR = '\x1f';
+
+  CrdtString column_key() const {
+#ifdef CRDT_UUID_NODE_IDS
+    CrdtString node = to_string(node_id);
+#else
+    CrdtString node = std::to_string(node_id);
+#endif
+    return retired ? "retired" + CrdtString(1, SEPARATOR) + node + SEPARATOR + std::to_string(final_version)
+                   : "joined" + CrdtString(1, SEPARATOR) + node;
+  }
+
+  static std::optional<CrdtSiteMarker> parse(const CrdtString &key) {
+    size_t first = key.find(SEPARATOR);
+    if (first == CrdtString::npos) {
+      return std::nullopt;
+    }
+    CrdtSiteMarker marker;
+    marker.retired = key.compare(0, first, "retired") == 0;
+    if (!marker.retired && key.compare(0, first, "joined") != 0) {
+      return std::nullopt;
+    }
+    size_t second = marker.retired ? key.find(SEPARATOR, first + 1) : key.size();
+    if (second == CrdtString::npos) {
+      return std::nullopt;
+    }
+    std::string_view node(key.data() + first + 1, second - first - 1);
+#ifdef CRDT_UUID_NODE_IDS
+    std::optional<CrdtNodeId> parsed = parse_uuid(node);
+    if (!parsed) {
+      return std::nullopt;
+    }
+    marker.node_id = *parsed;
+#else
+    if (std::from_chars(node.data(), node.data() + node.size(), marker.node_id).ptr != node.data() + node.size()) {
+      return std::nullopt;
+    }
+#endif
+    if (marker.retired && std::from_chars(key.data() + second + 1, key.data() + key.size(), marker.final_version).ptr !=
+                              key.data() + key.size()) {
+      return std::nullopt;
+    }
+    return marker;
+  }
+};
+
+/// Column-level write permissions, installed with CRDT::set_write_policy and checked against the node id of every
+/// local operation and every incoming change.
+///
+/// A protected column may only be written by its owners. A restricted node may only write the columns it is granted,
+/// and delete records only if allowed to. Everything else is permitted. Every replica must install the same policy so
+/// that all of them reject the same changes and still converge.
+class CrdtWritePolicy {
+public:
+  /// Only `owners` may write `col_name`.
+  CrdtWritePolicy &protect(const CrdtString &col_name, CrdtSet<CrdtNodeId> owners) {
+    protected_[col_name] = std::move(owners);
+    return *this;
+  }
+
+  /// `node_id` may only write `columns`, and delete records if `can_delete` is set.
+  CrdtWritePolicy &restrict(const CrdtNodeId &node_id, CrdtSet<CrdtString> columns, bool can_delete = false) {
+    grants_[node_id] = Grant{std::move(columns), can_delete};
+    return *this;
+  }
+
+  /// Whether `node_id` may write `col_name`, or delete a record if `col_name` is std::nullopt.
+  bool allows(const CrdtNodeId &node_id, const std::optional<CrdtString> &col_name) const {
+    if (auto grant = grants_.find(node_id); grant != grants_.end()) {
+      if (col_name ? !grant->second.columns.contains(*col_name) : !grant->second.can_delete) {
+        return false;
+      }
+    }
+    if (col_name) {
+      if (auto owners = protected_.find(*col_name); owners != protected_.end()) {
+        return owners->second.contains(node_id);
+      }
+    }
+    return true;
+  }
+
+private:
+  struct Grant {
+    CrdtSet<CrdtString> columns;
+    bool can_delete = false;
+  };
+
+  CrdtMap<CrdtString, CrdtSet<CrdtNodeId>> protected_;
+  CrdtMap<CrdtNodeId, Grant> grants_;
+};
+
+/// Deterministic 64-bit FNV-1a hasher, used for digests that must match across nodes and platforms
+/// (unlike std::hash, and independent of map iteration order).
+class CrdtHasher {
+public:
+  constexpr void update(const void *data, size_t size) {
+    const uint8_t *bytes = static_cast<const uint8_t *>(data);
+    for (size_t i = 0; i < size; ++i) {
+      state_ ^= bytes[i];
+      state_ *= 1099511628211ULL;
+    }
+  }
+
+  constexpr void update_u64(uint64_t value) {
+    uint8_t bytes[8];
+    for (int i = 0; i < 8; ++i) {
+      bytes[i] = static_cast<uint8_t>(value >> (8 * i));
+    }
+    update(bytes, sizeof(bytes));
+  }
+
+  constexpr uint64_t finish() const { return state_; }
+
+  /// Finalizer that spreads the bits of a hash, so digests can be combined with addition.
+  static constexpr uint64_t mix(uint64_t value) {
+    value ^= value >> 33;
+    value *= 0xff51afd7ed558ccdULL;
+    value ^= value >> 33;
+    value *= 0xc4ceb9fe1a85ec53ULL;
+    value ^= value >> 33;
+    return value;
+  }
+
+private:
+  uint64_t state_ = 14695981039346656037ULL;
+};
+
+/// Feeds a value into a CrdtHasher. Specialize this for custom key or value types.
+template <typename T, typename Enable = void> struct CrdtDigest;
+
+template <> struct CrdtDigest<CrdtString> {
+  static void add(CrdtHasher &hasher, const CrdtString &value) {
+    hasher.update_u64(value.size());
+    hasher.update(value.data(), value.size());
+  }
+};
+
+template <typename T> struct CrdtDigest<T, std::enable_if_t<std::is_integral_v<T>>> {
+  static void add(CrdtHasher &hasher, T value) { hasher.update_u64(static_cast<uint64_t>(value)); }
+};
+
+template <typename T> struct CrdtDigest<T, std::enable_if_t<std::is_floating_point_v<T>>> {
+  static void add(CrdtHasher &hasher, T value) {
+    double wide = value;
+    uint64_t bits;
+    std::memcpy(&bits, &wide, sizeof(bits));
+    hasher.update_u64(bits);
+  }
+};
+
+template <> struct CrdtDigest<CrdtUuid> {
+  static void add(CrdtHasher &hasher, const CrdtUuid &value) {
+    hasher.update_u64(value.high);
+    hasher.update_u64(value.low);
+  }
+};
+
+/// Reports the heap memory owned by a value, beyond `sizeof(T)`, for CRDT::estimated_memory_bytes. Specialize this for
+/// custom key or value types that allocate.
+template <typename T, typename Enable = void> struct CrdtMemoryUsage {
+  static size_t heap_bytes(const T &) { return 0; }
+};
+
+template <> struct CrdtMemoryUsage<CrdtString> {
+  static size_t heap_bytes(const CrdtString &value) {
+    // Strings within the small-string buffer don't allocate
+    return value.capacity() > CrdtString().capacity() ? value.capacity() + 1 : 0;
+  }
+};
+
+template <typename T>
+concept CrdtDigestible = requires(CrdtHasher &hasher, const T &value) { CrdtDigest<T>::add(hasher, value); };
+
+/// Converts between a struct member type (`M`) and the CRDT value type (`V`).
+/// Specialize this for domain types that are not directly convertible.
+template <typename M, typename V> struct CrdtValueConverter {
+  static V to_value(const M &member)
+    requires std::convertible_to<const M &, V>
+  {
+    return V(member);
+  }
+
+  static std::optional<M> from_value(const V &value)
+    requires std::convertible_to<const V &, M>
+  {
+    return M(value);
+  }
+};
+
+// Numbers stored in string-valued CRDTs
+template <typename M>
+  requires(std::is_arithmetic_v<M> && !std::is_same_v<M, bool>)
+struct CrdtValueConverter<M, CrdtString> {
+  static CrdtString to_value(const M &member) {
+    char buffer[64];
+    auto result = std::to_chars(buffer, buffer + sizeof(buffer), member);
+    return CrdtString(buffer, result.ptr);
+  }
+
+  static std::optional<M> from_value(const CrdtString &value) {
+    M member{};
+    auto result = std::from_chars(value.data(), value.data() + value.size(), member);
+    if (result.ec != std::errc() || result.ptr != value.data() + value.size()) {
+      return std::nullopt;
+    }
+    return member;
+  }
+};
+
+/// Describes one struct member mapped to a CRDT column.
+template <typename T, typename M> struct CrdtField {
+  const char *name;
+  M T::*member;
+};
+
+template <typename T, typename M> constexpr CrdtField<T, M> crdt_field(const char *name, M T::*member) { return {name, member}; }
+
+/// Maps a struct member to a column with the same name, for use in CrdtRecordTraits.
+#define CRDT_FIELD(Type, member) crdt_field(#member, &Type::member)
+
+/// Specialize this to map a struct to CRDT columns, e.g.
+///
+/// template <> struct CrdtRecordTraits<User> {
+///   static constexpr auto fields = std::make_tuple(CRDT_FIELD(User, name), CRDT_FIELD(User, age));
+/// };
+template <typename T> struct CrdtRecordTraits;
+
+template <typename T>
+concept CrdtMappedRecord = requires { CrdtRecordTraits<T>::fields; };
+
+/// Time-to-live rules, installed with CRDT::set_ttl_policy and applied by CRDT::expire.
+///
+/// Expiry is computed from replicated data only: a record or column expires `ttl_ms` milliseconds after the
+/// wall-clock time (in milliseconds since the Unix epoch) stored in its timestamp column, so every replica expires the
+/// same data once its clock passes that time. A per-record TTL is a timestamp column holding the expiry time itself,
+/// with a `ttl_ms` of 0. Records and columns whose timestamp column is missing or not a number never expire.
+class CrdtTtlPolicy {
+public:
+  /// Records expire `ttl_ms` after the time in `timestamp_column`.
+  CrdtTtlPolicy &expire_records(CrdtString timestamp_column, uint64_t ttl_ms) {
+    records_ = Rule{std::move(timestamp_column), ttl_ms};
+    return *this;
+  }
+
+  /// `col_name` expires `ttl_ms` after the time in `timestamp_column` of its record.
+  CrdtTtlPolicy &expire_column(const CrdtString &col_name, CrdtString timestamp_column, uint64_t ttl_ms) {
+    columns_[col_name] = Rule{std::move(timestamp_column), ttl_ms};
+    return *this;
+  }
+
+  /// Whether `record` has expired at `now_ms`.
+  template <typename V> bool record_expired(const Record<V> &record, uint64_t now_ms) const {
+    return records_ && records_->expired(record, now_ms);
+  }
+
+  /// The columns of `record` that have expired at `now_ms` and still hold a value.
+  template <typename V> CrdtVector<CrdtString> expired_columns(const Record<V> &record, uint64_t now_ms) const {
+    CrdtVector<CrdtString> expired;
+    for (const auto &[col_name, rule] : columns_) {
+      if (record.fields.contains(col_name) && rule.expired(record, now_ms)) {
+        expired.push_back(col_name);
+      }
+    }
+    return expired;
+  }
+
+private:
+  struct Rule {
+    CrdtString timestamp_column;
+    uint64_t ttl_ms = 0;
+
+    template <typename V> bool expired(const Record<V> &record, uint64_t now_ms) const {
+      auto it = record.fields.find(timestamp_column);
+      if (it == record.fields.end()) {
+        return false;
+      }
+      std::optional<uint64_t> timestamp = CrdtValueConverter<uint64_t, V>::from_value(it->second);
+      return timestamp && *timestamp <= UINT64_MAX - ttl_ms && *timestamp + ttl_ms <= now_ms;
+    }
+  };
+
+  std::optional<Rule> records_;
+  CrdtMap<CrdtString, Rule> columns_;
+};
+
+/// Semantic merge functions for domain values (durations, money, bitsets, ...), installed with
+/// CRDT::set_merge_registry.
+///
+/// Each merge function is registered under a type tag. A column is given a tag with `assign_column`, or the tag is read
+/// from the values themselves by the tagger, e.g. from a "money:" prefix. When an incoming value meets a local value of
+/// the same column and both have the tag, the column holds `merge(local, remote, local_version, remote_version)` with the
+/// version the merge rule picks, instead of just the winning value. Deletions and writes to columns without a value
+/// merge as usual.
+///
+/// Replicas only converge if every merge function is a join: commutative (`merge(a, b) == merge(b, a)`), associative
+/// and idempotent (`merge(a, a) == a`), e.g. a maximum, a set union or per-node counters. Every replica must install the
+/// same registry.
+template <typename V> class CrdtMergeRegistry {
+public:
+  using MergeFunction =
+      std::function<V(const V &local, const V &remote, const ColumnVersion &local_version, const ColumnVersion &remote_version)>;
+  using Tagger = std::function<std::optional<CrdtString>(const V &value)>;
+
+  CrdtMergeRegistry &register_type(const CrdtString &tag, MergeFunction merge) {
+    functions_[tag] = std::move(merge);
+    return *this;
+  }
+
+  /// Merges the values of `col_name` with the function registered under `tag`.
+  CrdtMergeRegistry &assign_column(const CrdtString &col_name, CrdtString tag) {
+    columns_[col_name] = std::move(tag);
+    return *this;
+  }
+
+  /// Reads the tag of a value, for columns without an assigned tag. Values for which it returns std::nullopt, or two
+  /// values with different tags, merge as usual.
+  CrdtMergeRegistry &set_tagger(Tagger tagger) {
+    tagger_ = std::move(tagger);
+    return *this;
+  }
+
+  /// The merge function for a local and a remote value of `col_name`, or nullptr if they merge as usual.
+  const MergeFunction *find(const CrdtString &col_name, const V &local, const V &remote) const {
+    std::optional<CrdtString> tag;
+    if (auto column = columns_.find(col_name); column != columns_.end()) {
+      tag = column->second;
+    } else if (tagger_) {
+      tag = tagger_(local);
+      if (!tag || tagger_(remote) != tag) {
+        return nullptr;
+      }
+    }
+    if (!tag) {
+      return nullptr;
+    }
+    auto function = functions_.find(*tag);
+    return function != functions_.end() ? &function->second : nullptr;
+  }
+
+private:
+  CrdtMap<CrdtString, MergeFunction> functions_;
+  CrdtMap<CrdtString, CrdtString> columns_;
+  Tagger tagger_;
+};
+
+/// Represents the CRDT structure, generic over key (`K`) and value (`V`) types.
+template <typename K, typename V, MergeRule<K, V> MergeRuleType = DefaultMergeRule<K, V>,
+          ChangeComparator<K, V> ChangeComparatorType = DefaultChangeComparator<K, V>, typename SortFunctionType = DefaultSort>
+class CRDT : public std::enable_shared_from_this<CRDT<K, V, MergeRuleType, ChangeComparatorType, SortFunctionType>> {
+public:
+  using KeyType = K;
+  using ValueType = V;
+
+  // Create a new empty CRDT
+  // Complexity: O(1)
+  CRDT(CrdtNodeId node_id, std::shared_ptr<CRDT<K, V, MergeRuleType, ChangeComparatorType, SortFunctionType>> parent = nullptr,
+       MergeRuleType merge_rule = MergeRuleType(), ChangeComparatorType change_comparator = ChangeComparatorType(),
+       SortFunctionType sort_func = SortFunctionType())
+      : node_id_(node_id), clock_(), data_(), tombstones_(), parent_(parent), merge_rule_(std::move(merge_rule)),
+        change_comparator_(std::move(change_comparator)), sort_func_(std::move(sort_func)) {
+    if (parent_) {
+      copy_config(*parent_);
+      // Set clock to parent's clock
+      clock_ = parent_->clock_;
+      seq_ = parent_->seq_;
+      // Capture the base version from the parent
+      base_version_ = parent_->clock_.current_time();
+    } else {
+      base_version_ = 0; // No parent, base_version_ is 0
+    }
+  }
+
+  /// Create a CRDT from a list of changes (e.g., loaded from disk).
+  ///
+  /// # Arguments
+  ///
+  /// * `node_id` - The unique identifier for this CRDT node.
+  /// * `changes` - A list of changes to apply to reconstruct the CRDT state.
+  ///
+  /// Complexity: O(n), where n is the number of changes
+  constexpr CRDT(CrdtNodeId node_id, CrdtVector<Change<K, V>> &&changes) : node_id_(node_id), clock_(), data_(), tombstones_() {
+    apply_changes(std::move(changes));
+  }
+
+  /// Creates a CRDT with room for `records` records, avoiding rehashing while bulk-loading.
+  ///
+  /// Complexity: O(records)
+  static CRDT with_capacity(CrdtNodeId node_id, size_t records) {
+    CRDT crdt(node_id);
+    crdt.reserve(records);
+    return crdt;
+  }
+
+  /// Reserves room for at least `records` records in total. A no-op with ordered storage.
+  ///
+  /// # Arguments
+  ///
+  /// * `records` - Expected number of live records.
+  /// * `tombstones` - Expected number of tombstones.
+  ///
+  /// Complexity: O(n), where n is the number of records
+  void reserve(size_t records, size_t tombstones = 0) {
+    if constexpr (requires { data_.reserve(records); }) {
+      data_.reserve(records);
+      tombstones_.reserve(tombstones);
+    }
+  }
+
+  /// Releases memory left over after large deletions or purges by shrinking the record and tombstone tables to fit
+  /// their contents. A no-op with ordered storage.
+  ///
+  /// Complexity: O(n), where n is the number of records
+  void shrink_to_fit() {
+    if constexpr (requires { data_.rehash(0); }) {
+      data_.rehash(0);
+      tombstones_.rehash(0);
+    }
+  }
+
+  /// Drops the entries of the index behind `get_changes_since` that refer to records since written again or garbage
+  /// collected. The index also compacts itself once such entries outnumber the records, so this is only needed to
+  /// release that memory early, e.g. after collecting tombstones.
+  ///
+  /// Complexity: O(n * m), where n is the number of records and m is the average number of columns per record
+  void compact_change_index() { rebuild_change_index(); }
+
+  /// The epoch of this replica's versions, 0 until `start_epoch` is called.
+  uint64_t epoch() const { return crdt_epoch(clock_.current_time()); }
+
+  /// Starts a new epoch after a fleet-wide compaction, restarting the db_version counters.
+  ///
+  /// Every replica must call this with the same state, e.g. once all of them have converged on (or been restored from)
+  /// the same snapshot and their change logs have been truncated. The db_version of every column and tombstone is
+  /// rebased to `crdt_epoch_start(epoch)`, keeping col_versions and node ids, so the replicas still agree; the clock
+  /// continues from there, and `max_seen_versions` and the column history are cleared.
+  ///
+  /// Afterwards `merge_changes` drops changes from later epochs, which this replica cannot compare until it starts
+  /// them too, and handles changes from earlier epochs (e.g. from a replica that missed the compaction) according to
+  /// `set_epoch_policy`. Dropped changes are counted in `stats().changes_other_epoch`.
+  ///
+  /// Throws std::invalid_argument if `epoch` is not above the current one or does not fit in a db_version, and
+  /// std::logic_error on a child CRDT.
+  ///
+  /// Complexity: O(n * m), where n is the number of records and m is the average number of columns per record
+  void start_epoch(uint64_t epoch) {
+    if (epoch <= this->epoch() || epoch > crdt_epoch(UINT64_MAX)) {
+      throw std::invalid_argument("epochs must increase and fit in a db_version");
+    }
+    if (parent_) {
+      throw std::logic_error("epochs are not supported on child CRDTs");
+    }
+    uint64_t start = crdt_epoch_start(epoch);
+    for (auto &[record_id, record] : data_) {
+      for (auto &[col_name, version] : record.column_versions) {
+        version.db_version = start;
+        version.local_db_version = start;
+      }
+      if (record.revival) {
+        if (!is_placeholder(*record.revival)) {
+          record.revival->db_version = start;
+        }
+        record.revival->local_db_version = start;
+      }
+    }
+    for (auto &[record_id, tombstone] : tombstones_) {
+      tombstone.version.db_version = start;
+      tombstone.version.local_db_version = start;
+    }
+    clock_.set_time(start);
+    max_seen_versions_.clear();
+    history_.clear();
+    rebuild_change_index();
+  }
+
+  /// Sets what `merge_changes` does with changes from an epoch before this replica's: drop them (the default), or
+  /// translate them to the start of the current epoch, where they win over the compacted state only with a higher
+  /// col_version, as they would have before the compaction. Every replica should use the same policy. Child CRDTs
+  /// inherit the parent's policy when created.
+  void set_epoch_policy(CrdtEpochPolicy policy) { epoch_policy_ = policy; }
+
+  CrdtEpochPolicy epoch_policy() const { return epoch_policy_; }
+
+  /// Rebuilds the converged state by replaying an append-only change log, for event-sourced persistence where only
+  /// changes are stored durably.
+  ///
+  /// Unlike the constructor taking a change vector, changes go through the merge rule, so the log may contain
+  /// superseded and duplicated changes in any order. It is consumed in batches, so it need not fit in memory at once.
+  ///
+  /// # Arguments
+  ///
+  /// * `node_id` - The unique identifier for this CRDT node.
+  /// * `log` - Any input range of `Change<K, V>`, e.g. a vector or a view reading from disk.
+  ///
+  /// Complexity: O(c), where c is the number of changes in the log
+  template <std::ranges::input_range Range>
+    requires std::convertible_to<std::ranges::range_reference_t<Range>, Change<K, V>>
+  static CRDT from_changes(CrdtNodeId node_id, Range &&log) {
+    constexpr size_t batch_size = 1024;
+    CRDT crdt(node_id);
+    CrdtVector<Change<K, V>> batch;
+    batch.reserve(batch_size);
+    for (auto &&change : log) {
+      batch.emplace_back(std::forward<decltype(change)>(change));
+      if (batch.size() == batch_size) {
+        crdt.merge_changes(std::move(batch));
+        batch.clear();
+      }
+    }
+    crdt.merge_changes(std::move(batch));
+    return crdt;
+  }
+
+  /// Restores a CRDT from state persisted with `get_data()`, `get_tombstones()` and `get_clock().current_time()`.
+  ///
+  /// Every column version is kept as-is, and the clock resumes at `clock_time` (or the highest stored version, if that
+  /// is larger) so new local changes never reuse a db_version.
+  ///
+  /// # Arguments
+  ///
+  /// * `node_id` - The unique identifier for this CRDT node.
+  /// * `clock_time` - The persisted logical clock value.
+  /// * `data` - The persisted live records.
+  /// * `tombstones` - The persisted tombstones.
+  ///
+  /// Throws std::invalid_argument if a record is both live and tombstoned.
+  ///
+  /// Complexity: O(n * m), where n is the number of records and m is the average number of columns per record
+  static CRDT from_parts(CrdtNodeId node_id, uint64_t clock_time, CrdtMap<K, Record<V>> data,
+                         CrdtMap<K, CrdtTombstone> tombstones) {
+    CRDT crdt(node_id);
+    uint64_t max_version = clock_time;
+    for (const auto &[record_id, record] : data) {
+      for_each_version(record, [&](const ColumnVersion &version) {
+        max_version = std::max({max_version, version.db_version, version.local_db_version});
+        crdt.note_seen(version.node_id, version.db_version);
+      });
+    }
+    for (const auto &[record_id, tombstone] : tombstones) {
+      if (data.contains(record_id)) {
+        throw std::invalid_argument("record is both live and tombstoned");
+      }
+      max_version = std::max({max_version, tombstone.version.db_version, tombstone.version.local_db_version});
+      crdt.note_seen(tombstone.version.node_id, tombstone.version.db_version);
+    }
+    crdt.clock_.set_time(max_version);
+    crdt.seq_ = max_version; // every local operation ticked the clock, so no earlier one had a higher sequence number
+    crdt.live_count_ = data.size();
+    crdt.tombstone_count_ = tombstones.size();
+    crdt.data_ = std::move(data);
+    crdt.tombstones_ = std::move(tombstones);
+    crdt.rebuild_change_index();
+    return crdt;
+  }
+
+  /// Resets the CRDT to a state as if it was constructed with the given changes.
+  ///
+  /// # Arguments
+  ///
+  /// * `changes` - A list of changes to apply to reconstruct the CRDT state.
+  ///
+  /// Complexity: O(n), where n is the number of changes
+  constexpr void reset(CrdtVector<Change<K, V>> &&changes) {
+    // Clear existing data
+    data_.clear();
+    tombstones_.clear();
+    change_index_.clear();
+    change_index_entries_ = 0;
+    live_count_ = 0;
+    tombstone_count_ = 0;
+
+    // Reset the logical clock
+    clock_ = LogicalClock();
+
+    apply_changes(std::move(changes));
+    rebuild_unique_index();
+  }
+
+  /// Applies changes as the constructor taking a change vector does, keeping their local db_versions, so a state too
+  /// large for one vector can be loaded batch by batch, e.g. while streaming it from disk. Meant for a CRDT that is being
+  /// loaded: the merge rule is bypassed (a later change of a column replaces an earlier one) and no observer is notified.
+  ///
+  /// # Arguments
+  ///
+  /// * `changes` - A batch of changes to apply, after the batches loaded before it.
+  ///
+  /// Complexity: O(n), where n is the number of changes
+  void load_changes(CrdtVector<Change<K, V>> &&changes) { apply_changes(std::move(changes)); }
+
+  /// Generates inverse changes for a given set of changes based on the parent state.
+  ///
+  /// # Arguments
+  ///
+  /// * `changes` - A vector of changes to invert.
+  ///
+  /// # Returns
+  ///
+  /// A vector of inverse `Change` objects.
+  /// Generates inverse changes for a given set of changes based on the specified source (parent or top-level CRDT).
+  ///
+  /// # Arguments
+  ///
+  /// * `changes` - A vector of changes to invert.
+  ///
+  /// # Returns
+  ///
+  /// A vector of inverse `Change` objects.
+  CrdtVector<Change<K, V>> invert_changes(const CrdtVector<Change<K, V>> &changes) {
+    CrdtVector<Change<K, V>> inverse_changes;
+
+    for (const auto &change : changes) {
+      const K &record_id = change.record_id;
+      const std::optional<CrdtString> &col_name = change.col_name;
+      const std::optional<V> &value = change.value;
+
+      if (!col_name.has_value()) {
+        // The change was a record deletion (tombstone)
+        // To revert, restore the record's state from the specified source
+        auto record_ptr = parent_ ? parent_->get_record_ptr(record_id) : get_record_ptr(record_id);
+        if (record_ptr) {
+          // Restore all fields from the record
+          for (const auto &[parent_col, parent_val] : record_ptr->fields) {
+            if (local_columns_.contains(parent_col)) {
+              continue;
+            }
+            inverse_changes.emplace_back(Change<K, V>(record_id, parent_col, parent_val,
+                                                      record_ptr->column_versions.at(parent_col).col_version,
+                                                      record_ptr->column_versions.at(parent_col).db_version, node_id_,
+                                                      record_ptr->column_versions.at(parent_col).local_db_version));
+          }
+          // Remove the tombstone
+          inverse_changes.emplace_back(Change<K, V>(record_id, std::nullopt, std::nullopt,
+                                                    0, // Column version 0 signifies removal of tombstone
+                                                    clock_.current_time(), node_id_));
+        }
+      } else {
+        // The change was an insertion or update of a column
+        CrdtString col = *col_name;
+        auto record_ptr = parent_ ? parent_->get_record_ptr(record_id) : get_record_ptr(record_id);
+        if (record_ptr) {
+          auto field_it = record_ptr->fields.find(col);
+          if (field_it != record_ptr->fields.end()) {
+            // The record has a value for this column; set it back to the record's value
+            inverse_changes.emplace_back(Change<K, V>(
+                record_id, col, field_it->second, record_ptr->column_versions.at(col).col_version,
+                record_ptr->column_versions.at(col).db_version, node_id_, record_ptr->column_versions.at(col).local_db_version));
+          } else {
+            // The record does not have this column; delete it to revert
+            inverse_changes.emplace_back(Change<K, V>(record_id, col,
+                                                      std::nullopt, // Indicates deletion
+                                                      0,            // Column version 0 signifies deletion
+                                                      clock_.current_time(), node_id_));
+          }
+        } else {
+          // The record does not have the record; remove the entire record to revert
+          inverse_changes.emplace_back(Change<K, V>(record_id, std::nullopt, std::nullopt,
+                                                    0, // Column version 0 signifies a tombstone
+                                                    clock_.current_time(), node_id_));
+        }
+      }
+    }
+
+    return inverse_changes;
+  }
+
+  /// Reverts all changes made by this CRDT since it was created from the parent.
+  ///
+  /// # Returns
+  ///
+  /// A vector of `Change` objects representing the inverse changes needed to undo the child’s changes.
+  ///
+  /// # Complexity
+  ///
+  /// O(c), where c is the number of changes since `base_version_`
+  constexpr CrdtVector<Change<K, V>> revert() {
+    if (!parent_ || parent_->snapshot_layer_) {
+      throw std::runtime_error("Cannot revert without a parent CRDT.");
+    }
+
+    // Step 1: Retrieve all changes made by the child since base_version_
+    CrdtVector<Change<K, V>> child_changes = this->get_changes_since(base_version_);
+
+    // Step 2: Generate inverse changes using the generalized function
+    CrdtVector<Change<K, V>> inverse_changes = invert_changes(child_changes);
+
+    return inverse_changes;
+  }
 
   /// Inserts a new record or updates an existing record in the CRDT.
   ///
   /// # Arguments
   ///
   /// * `record_id` - The unique identifier for the record.
-  /// * `fields` - A hashmap of field names to their values.
+  /// * `fields` - A hashmap of field names to their values.
+  ///
+  /// # Returns
+  ///
+  /// A vector of `Change` objects representing the changes made, or void if ReturnChanges is false.
+  ///
+  /// Complexity: O(m), where m is the number of fields in the input
+  template <bool ReturnChanges = true>
+  constexpr std::conditional_t<ReturnChanges, CrdtVector<Change<K, V>>, void> insert_or_update(const K &record_id,
+                                                                                               CrdtMap<CrdtString, V> &&fields) {
+    check_writable();
+    CrdtVector<Change<K, V>> changes;
+
+    // Check if the record is tombstoned
+    if (is_record_tombstoned(record_id)) {
+      if constexpr (ReturnChanges) {
+        return changes;
+      } else {
+        return;
+      }
+    }
+
+    check_fields(record_id, fields);
+
+    // One tick per operation, so every column written here shares the sequence number
+    uint64_t db_version = local_tick();
+    note_seen(node_id_, db_version);
+
+    CrdtVector<ChangeEvent<K, V>> events;
+    write_fields(record_id, std::move(fields), db_version, events, changes, ReturnChanges || !batch_observers_.empty());
+
+    notify(events);
+    notify_batch(changes);
+
+    if constexpr (ReturnChanges) {
+      return changes;
+    }
+  }
+
+  /// Imports many records in one operation, e.g. when migrating a legacy dataset.
+  ///
+  /// Every column written shares a single db_version, so the import costs one clock tick rather than one per record.
+  /// The record table is pre-sized when the number of records is known up front, and unique constraints are indexed
+  /// once at the end. Records are written as with `insert_or_update`: tombstoned ones are skipped and existing ones
+  /// updated. Observers receive the whole import as one batch; peers pick it up with `get_changes_since` as usual.
+  ///
+  /// Throws like `insert_or_update` when a record is rejected; the records before it stay written.
+  ///
+  /// # Arguments
+  ///
+  /// * `records` - Any input range of `(record_id, fields)` pairs, e.g. a map or vector, or a view reading from disk.
+  ///
+  /// # Returns
+  ///
+  /// The number of records written.
+  ///
+  /// Complexity: O(r * m), where r is the number of records and m is the average number of fields per record
+  template <std::ranges::input_range Range>
+    requires std::constructible_from<std::pair<K, CrdtMap<CrdtString, V>>, std::ranges::range_reference_t<Range>>
+  size_t bulk_load(Range &&records) {
+    check_writable();
+    if constexpr (std::ranges::sized_range<Range>) {
+      reserve(data_.size() + std::ranges::size(records));
+    }
+
+    std::optional<uint64_t> db_version;
+    CrdtVector<ChangeEvent<K, V>> events;
+    CrdtVector<Change<K, V>> changes;
+    size_t written = 0;
+    try {
+      for (auto &&entry : records) {
+        std::pair<K, CrdtMap<CrdtString, V>> record(std::forward<decltype(entry)>(entry));
+        if (is_record_tombstoned(record.first)) {
+          continue;
+        }
+        check_fields(record.first, record.second);
+        if (!db_version) {
+          db_version = local_tick();
+          note_seen(node_id_, *db_version);
+        }
+        write_fields(record.first, std::move(record.second), *db_version, events, changes, !batch_observers_.empty(),
+                     false);
+        ++written;
+      }
+    } catch (...) {
+      rebuild_unique_index();
+      throw;
+    }
+
+    rebuild_unique_index();
+    notify(events);
+    notify_batch(changes);
+    return written;
+  }
+
+  /// Inserts or updates a record from a struct mapped via `CrdtRecordTraits`, writing every mapped field.
+  ///
+  /// # Arguments
+  ///
+  /// * `record_id` - The unique identifier for the record.
+  /// * `object` - The struct whose mapped fields become columns.
+  ///
+  /// # Returns
+  ///
+  /// A vector of `Change` objects representing the changes made, or void if ReturnChanges is false.
+  ///
+  /// Complexity: O(m), where m is the number of mapped fields
+  template <bool ReturnChanges = true, CrdtMappedRecord T>
+  std::conditional_t<ReturnChanges, CrdtVector<Change<K, V>>, void> insert_struct(const K &record_id, const T &object) {
+    CrdtMap<CrdtString, V> fields;
+    std::apply(
+        [&](const auto &...field) {
+          ((fields.emplace(field.name, CrdtValueConverter<std::remove_cvref_t<decltype(object.*(field.member))>, V>::to_value(
+                                           object.*(field.member)))),
+           ...);
+        },
+        CrdtRecordTraits<T>::fields);
+    return insert_or_update<ReturnChanges>(record_id, std::move(fields));
+  }
+
+  /// Updates a record from a mapped struct, writing only the fields whose value differs from the stored one.
+  ///
+  /// # Arguments
+  ///
+  /// * `record_id` - The unique identifier for the record.
+  /// * `object` - The struct whose mapped fields become columns.
+  ///
+  /// # Returns
+  ///
+  /// A vector of `Change` objects representing the changes made, or void if ReturnChanges is false.
+  ///
+  /// Complexity: O(m), where m is the number of mapped fields
+  template <bool ReturnChanges = true, CrdtMappedRecord T>
+  std::conditional_t<ReturnChanges, CrdtVector<Change<K, V>>, void> update_struct(const K &record_id, const T &object) {
+    const Record<V> *existing = get_record(record_id);
+    CrdtMap<CrdtString, V> fields;
+    std::apply(
+        [&](const auto &...field) {
+          auto add_if_changed = [&](const auto &f) {
+            V value = CrdtValueConverter<std::remove_cvref_t<decltype(object.*(f.member))>, V>::to_value(object.*(f.member));
+            if (existing) {
+              auto it = existing->fields.find(f.name);
+              if (it != existing->fields.end() && it->second == value) {
+                return;
+              }
+            }
+            fields.emplace(f.name, std::move(value));
+          };
+          (add_if_changed(field), ...);
+        },
+        CrdtRecordTraits<T>::fields);
+    return insert_or_update<ReturnChanges>(record_id, std::move(fields));
+  }
+
+  /// Reads a live record into a struct mapped via `CrdtRecordTraits`.
+  ///
+  /// Columns missing from the record leave the corresponding member default-initialized.
+  ///
+  /// # Returns
+  ///
+  /// The populated struct, or std::nullopt if the record is missing, tombstoned, or a value fails to convert.
+  ///
+  /// Complexity: O(m), where m is the number of mapped fields
+  template <CrdtMappedRecord T>
+    requires std::default_initializable<T>
+  std::optional<T> get_struct(const K &record_id) const {
+    const Record<V> *record = get_record(record_id);
+    if (!record) {
+      return std::nullopt;
+    }
+
+    T object{};
+    bool ok = true;
+    std::apply(
+        [&](const auto &...field) {
+          auto read = [&](const auto &f) {
+            auto it = record->fields.find(f.name);
+            if (it == record->fields.end()) {
+              return;
+            }
+            auto converted = CrdtValueConverter<std::remove_cvref_t<decltype(object.*(f.member))>, V>::from_value(it->second);
+            if (converted) {
+              object.*(f.member) = std::move(*converted);
+            } else {
+              ok = false;
+            }
+          };
+          (read(field), ...);
+        },
+        CrdtRecordTraits<T>::fields);
+
+    if (!ok) {
+      return std::nullopt;
+    }
+    return object;
+  }
+
+  /// Deletes a record by marking it as tombstoned.
+  ///
+  /// # Arguments
+  ///
+  /// * `record_id` - The unique identifier for the record.
+  ///
+  /// # Returns
+  ///
+  /// A vector of `Change` objects representing the deletion, or void if ReturnChanges is false.
+  ///
+  /// Complexity: O(1)
+  template <bool ReturnChanges = true>
+  std::conditional_t<ReturnChanges, CrdtVector<Change<K, V>>, void> delete_record(const K &record_id) {
+    check_writable();
+    CrdtVector<Change<K, V>> changes;
+    if (is_record_tombstoned(record_id)) {
+      if constexpr (ReturnChanges) {
+        return changes;
+      } else {
+        return;
+      }
+    }
+
+    check_write_permitted(std::nullopt);
+    validate_deletion(record_id, std::nullopt);
+
+    uint64_t db_version = local_tick();
+    note_seen(node_id_, db_version);
+    CrdtVector<ChangeEvent<K, V>> events;
+    changes.push_back(tombstone_record(record_id, db_version, events));
+    notify(events);
+    notify_batch(changes);
+
+    if constexpr (ReturnChanges) {
+      return changes;
+    }
+  }
+
+  /// Deletes every live record matching a predicate, e.g. for retention jobs.
+  ///
+  /// Matching records are collected before any is deleted, so the predicate sees a consistent state, and all deletions
+  /// share a single db_version. The write policy and validator are consulted for every match before anything is
+  /// deleted, so a rejected deletion leaves the CRDT unchanged.
+  ///
+  /// # Arguments
+  ///
+  /// * `predicate` - Called with `(record_id, record)` for every live record, including records inherited from the parent.
+  ///
+  /// # Returns
+  ///
+  /// The deletions to replicate, one per deleted record.
+  ///
+  /// Complexity: O(n), where n is the number of records
+  template <typename Predicate>
+    requires std::predicate<Predicate &, const K &, const Record<V> &>
+  CrdtVector<Change<K, V>> delete_where(Predicate &&predicate) {
+    check_writable();
+    CrdtVector<Change<K, V>> changes;
+    CrdtVector<K> deleted;
+    for_each_live_record([&](const K &record_id, const Record<V> &record) {
+      if (predicate(record_id, record)) {
+        deleted.push_back(record_id);
+      }
+    });
+    if (deleted.empty()) {
+      return changes;
+    }
+
+    check_write_permitted(std::nullopt);
+    for (const K &record_id : deleted) {
+      validate_deletion(record_id, std::nullopt);
+    }
+
+    uint64_t db_version = local_tick();
+    note_seen(node_id_, db_version);
+    CrdtVector<ChangeEvent<K, V>> events;
+    for (const K &record_id : deleted) {
+      changes.push_back(tombstone_record(record_id, db_version, events));
+    }
+    notify(events);
+    notify_batch(changes);
+    return changes;
+  }
+
+  /// Irreversibly erases a record and replicates the erasure, e.g. to satisfy a data-erasure request.
+  ///
+  /// Unlike `delete_record`, whose deletion a later revival can undo, a purge leaves only a minimal marker: a tombstone
+  /// of the final incarnation. It merges like any deletion, but every later write or revival of the record is rejected
+  /// against it, and `collect_tombstones` keeps it so a stale replica can never bring the data back. The record's
+  /// fields, column history, parked conflicts and quarantined changes are dropped here and on every replica that merges
+  /// the purge. Copies outside this CRDT (a parent, snapshots, logs, undo stacks) are not erased; a CrdtChangeLog
+  /// compacts itself when it sees a purge.
+  ///
+  /// # Arguments
+  ///
+  /// * `record_id` - The record to erase; it may be live, deleted or unknown.
+  ///
+  /// # Returns
+  ///
+  /// The purge, or an empty vector if the record was already purged.
+  ///
+  /// Complexity: O(m + h + q), where m is the number of fields and h, q the history and quarantine sizes
+  CrdtVector<Change<K, V>> purge(const K &record_id) {
+    check_writable();
+    CrdtVector<Change<K, V>> changes;
+    if (is_purged(record_id)) {
+      return changes;
+    }
+    check_write_permitted(std::nullopt);
+
+    uint64_t db_version = local_tick();
+    note_seen(node_id_, db_version);
+    CrdtVector<ChangeEvent<K, V>> events;
+    if (!observers_.empty() && !is_record_tombstoned(record_id)) {
+      collect_deletion_events(record_id, node_id_, db_version, false, events);
+    }
+    unindex_record(record_id);
+    CrdtTombstone tombstone{local_version(PURGED_INCARNATION, db_version),
+                            wall_clock_ ? wall_clock_() : 0};
+    changes.push_back(tombstone_change(record_id, tombstone));
+    store_tombstone(record_id, std::move(tombstone));
+    erase_traces(record_id);
+    note_change(record_id, db_version);
+    notify(events);
+    notify_batch(changes);
+    return changes;
+  }
+
+  /// Whether `record_id` was erased with `purge`, here or on a replica whose purge was merged.
+  bool is_purged(const K &record_id) const {
+    const CrdtTombstone *tombstone = find_tombstone(record_id);
+    return tombstone && tombstone->version.col_version == PURGED_INCARNATION;
+  }
+
+  /// Whether `change` is the purge of its record.
+  static bool is_purge(const Change<K, V> &change) {
+    return !change.col_name && change.col_version == PURGED_INCARNATION;
+  }
+
+  /// Re-creates a deleted record as a new incarnation, optionally writing its first fields.
+  ///
+  /// Every record has an incarnation counter, replicated as the version of its deletion: odd while the record is
+  /// deleted and even once it is revived. Column versions carry the incarnation they were written in (in their upper
+  /// 32 bits), so merging converges however deletes, revivals and writes are ordered: a deletion or write of an older
+  /// incarnation is ignored, and a write of a newer one revives the record even before the revival itself arrives.
+  /// Concurrent revivals of the same deletion share the incarnation, so their writes are merged column by column.
+  ///
+  /// # Arguments
+  ///
+  /// * `record_id` - The unique identifier for the record.
+  /// * `fields` - The fields of the new incarnation.
+  ///
+  /// # Returns
+  ///
+  /// The revival followed by the field changes, or just the field changes if the record was not deleted (in which
+  /// case this behaves like `insert_or_update`).
+  ///
+  /// Complexity: O(m), where m is the number of fields in the input
+  CrdtVector<Change<K, V>> revive_record(const K &record_id, CrdtMap<CrdtString, V> &&fields = {}) {
+    check_writable();
+    if (!is_record_tombstoned(record_id)) {
+      return insert_or_update(record_id, std::move(fields));
+    }
+    if (is_purged(record_id)) {
+      throw std::invalid_argument("a purged record cannot be revived");
+    }
+    check_write_permitted(std::nullopt);
+
+    uint64_t incarnation = find_tombstone(record_id)->version.col_version + 1;
+    uint64_t db_version = local_tick();
+    note_seen(node_id_, db_version);
+    CrdtVector<ChangeEvent<K, V>> events;
+    ColumnVersion revival = local_version(incarnation, db_version);
+    reincarnate(record_id, revival, false, events);
+    CrdtVector<Change<K, V>> changes;
+    changes.push_back(revival_change(record_id, revival));
+    notify(events);
+    notify_batch(changes);
+
+    if (!fields.empty()) {
+      auto written = insert_or_update(record_id, std::move(fields));
+      changes.insert(changes.end(), std::make_move_iterator(written.begin()), std::make_move_iterator(written.end()));
+    }
+    return changes;
+  }
+
+  /// Moves a live record to a new key: the old id is tombstoned and its fields are written under the new id, all at a
+  /// single db_version and in one batch, so peers never see the record under both keys or neither.
+  ///
+  /// The fields start with fresh column versions under the new id. Edits to the old id that a peer makes concurrently
+  /// are lost to its tombstone, like with `delete_record`. A tombstoned `new_id` is revived as a new incarnation.
+  ///
+  /// # Arguments
+  ///
+  /// * `old_id` - The record to move.
+  /// * `new_id` - The key to move it to.
+  ///
+  /// # Returns
+  ///
+  /// The deletion of `old_id` followed by the changes writing `new_id`, or an empty vector if `old_id` is not live.
+  /// Throws std::invalid_argument if `new_id` is live.
+  ///
+  /// Complexity: O(m), where m is the number of fields in the record
+  CrdtVector<Change<K, V>> move_record(const K &old_id, const K &new_id) {
+    check_writable();
+    CrdtVector<Change<K, V>> changes;
+    const Record<V> *existing = get_record_ptr(old_id);
+    if (existing == nullptr || old_id == new_id) {
+      return changes;
+    }
+    if (get_record_ptr(new_id) != nullptr) {
+      throw std::invalid_argument("move target is a live record");
+    }
+    if (is_purged(new_id)) {
+      throw std::invalid_argument("move target was purged");
+    }
+
+    CrdtMap<CrdtString, V> fields = existing->fields;
+    check_write_permitted(std::nullopt);
+    validate_deletion(old_id, std::nullopt);
+    check_fields(new_id, fields);
+
+    uint64_t db_version = local_tick();
+    note_seen(node_id_, db_version);
+    CrdtVector<ChangeEvent<K, V>> events;
+    changes.push_back(tombstone_record(old_id, db_version, events));
+    if (const CrdtTombstone *tombstone = find_tombstone(new_id)) {
+      uint64_t incarnation = tombstone->version.col_version + 1;
+      ColumnVersion revival = local_version(incarnation, db_version);
+      reincarnate(new_id, revival, false, events);
+      changes.push_back(revival_change(new_id, revival));
+    }
+    write_fields(new_id, std::move(fields), db_version, events, changes, true);
+    notify(events);
+    notify_batch(changes);
+    return changes;
+  }
+
+  /// Removes a single column from a live record, replicating as a column deletion.
+  ///
+  /// # Arguments
+  ///
+  /// * `record_id` - The unique identifier for the record.
+  /// * `col_name` - The column to remove.
+  ///
+  /// # Returns
+  ///
+  /// A vector containing the column deletion, or void if ReturnChanges is false. Nothing is changed if the record is
+  /// tombstoned or the column was never written.
+  ///
+  /// Complexity: O(1)
+  template <bool ReturnChanges = true>
+  std::conditional_t<ReturnChanges, CrdtVector<Change<K, V>>, void> delete_field(const K &record_id, const CrdtString &col_name) {
+    check_writable();
+    CrdtVector<Change<K, V>> changes;
+    const Record<V> *existing = get_record_ptr(record_id);
+    if (!local_columns_.empty() && local_columns_.contains(col_name)) {
+      // Unversioned, so the field is just dropped without a change
+      if (!is_record_tombstoned(record_id) && existing && existing->fields.contains(col_name)) {
+        Record<V> &record = get_or_create_record_unchecked(record_id);
+        CrdtVector<ChangeEvent<K, V>> events;
+        if (!observers_.empty()) {
+          events.push_back({record_id, col_name, field_value(record, col_name), std::nullopt, node_id_,
+                            clock_.current_time(), false});
+        }
+        record.fields.erase(col_name);
+        notify(events);
+      }
+      if constexpr (ReturnChanges) {
+        return changes;
+      } else {
+        return;
+      }
+    }
+    if (is_record_tombstoned(record_id) || existing == nullptr || !existing->column_versions.contains(col_name)) {
+      if constexpr (ReturnChanges) {
+        return changes;
+      } else {
+        return;
+      }
+    }
+
+    check_write_permitted(col_name);
+    validate_deletion(record_id, col_name);
+    if (schema_) {
+      if (auto violation = schema_->violation(col_name, std::nullopt)) {
+        throw std::invalid_argument(*violation);
+      }
+    }
+
+    uint64_t db_version = local_tick();
+    note_seen(node_id_, db_version);
+    Record<V> &record = get_or_create_record_unchecked(record_id);
+    CrdtVector<ChangeEvent<K, V>> events;
+    if (!observers_.empty()) {
+      events.push_back({record_id, col_name, field_value(record, col_name), std::nullopt, node_id_, db_version, false});
+    }
+
+    ColumnVersion &version = record.column_versions.at(col_name);
+    const ColumnVersion *group = column_groups_.empty() ? nullptr : group_version(record_id, record, col_name);
+    version.col_version = next_column_version(group ? group->col_version : version.col_version);
+    version.db_version = db_version;
+    version.node_id = node_id_;
+    version.local_db_version = db_version;
+    version.seq = seq_;
+    index_field(record_id, col_name, field_ptr(record, col_name), nullptr);
+    record.fields.erase(col_name);
+    if (history_depth_ > 0) {
+      record_history(record_id, col_name, std::nullopt, version);
+    }
+    changes.push_back(version_change(record_id, col_name, std::nullopt, version));
+    if (group) {
+      stamp_group(record_id, record, column_group_.at(col_name), version.col_version, db_version, changes, true);
+    }
+    note_change(record_id, db_version);
+    notify(events);
+    notify_batch(changes);
+
+    if constexpr (ReturnChanges) {
+      return changes;
+    }
+  }
+
+  /// Retrieves all changes since a given `last_db_version`.
+  ///
+  /// Changes are read from the current state rather than a log, so a burst of edits to one column yields a single
+  /// change carrying the latest value, and a deleted record yields only its deletion. Batches accumulated from the
+  /// return values of local operations can be coalesced the same way with `compress_changes`.
+  ///
+  /// Without a parent, the records to read are looked up in an index of the versions they were written at, so frequent
+  /// incremental syncs only visit what changed. Changes are then returned in the order they were made (oldest first);
+  /// a record written at several versions appears at its first one after `last_db_version`.
+  ///
+  /// # Arguments
+  ///
+  /// * `last_db_version` - The database version to retrieve changes since.
+  ///
+  /// # Returns
+  ///
+  /// A vector of changes.
+  ///
+  /// Complexity: O(c * m), where c is the number of records changed since `last_db_version` and m is the average number
+  /// of columns per record; O(n * m) over all n records with a parent
+  CrdtVector<Change<K, V>> get_changes_since(uint64_t last_db_version) const {
+    CrdtTraceSpan span("get_changes_since");
+    span.event.version = last_db_version;
+    CrdtVector<Change<K, V>> changes;
+
+    // Get changes from parent
+    if (parent_) {
+      auto parent_changes = parent_->get_changes_since(last_db_version);
+      changes.insert(changes.end(), parent_changes.begin(), parent_changes.end());
+    }
+
+    auto append_record = [&](const K &record_id, const Record<V> &record) {
+      if (record.revival && record.revival->local_db_version > last_db_version && !is_placeholder(*record.revival)) {
+        changes.push_back(revival_change(record_id, *record.revival));
+      }
+      for (const auto &[col_name, clock_info] : record.column_versions) {
+        if (clock_info.local_db_version > last_db_version) {
+          auto field_it = record.fields.find(col_name);
+          std::optional<V> value = field_it != record.fields.end() ? std::optional<V>(field_it->second) : std::nullopt;
+          changes.push_back(version_change(record_id, col_name, std::move(value), clock_info));
+        }
+      }
+    };
+    auto append_tombstone = [&](const K &record_id, const CrdtTombstone &tombstone) {
+      if (tombstone.version.local_db_version > last_db_version) {
+        changes.push_back(tombstone_change(record_id, tombstone));
+      }
+    };
+
+    if (!parent_) {
+      CrdtSet<K> visited;
+      for (auto it = change_index_.upper_bound(last_db_version); it != change_index_.end(); ++it) {
+        for (const K &record_id : it->second) {
+          if (!visited.insert(record_id).second) {
+            continue;
+          }
+          if (auto record = data_.find(record_id); record != data_.end()) {
+            append_record(record_id, record->second);
+          } else if (auto tombstone = tombstones_.find(record_id); tombstone != tombstones_.end()) {
+            append_tombstone(record_id, tombstone->second);
+          }
+        }
+      }
+      span.event.changes = changes.size();
+      return changes;
+    }
+
+    for (const auto &[record_id, record] : data_) {
+      append_record(record_id, record);
+    }
+    for (const auto &[record_id, tombstone] : tombstones_) {
+      append_tombstone(record_id, tombstone);
+    }
+
+    if (parent_) {
+      // Since we merge from the parent, we need to also run a compression pass
+      // to remove changes that have been overwritten by top level changes
+      // since we compare at first by col_version, it's fine even if our db_version is lower
+      // since we merge from the parent, we know that the changes are applied in order and col_version should always be increasing
+      compress_changes(changes);
+    }
+
+    span.event.changes = changes.size();
+    return changes;
+  }
+
+  /// Same as `get_changes_since`, but borrows record ids, column names and values instead of copying them, so large
+  /// values can be encoded straight from the CRDT. The result is invalidated by any modification of the CRDT (or its
+  /// parent).
+  ///
+  /// Records present in this CRDT shadow the parent's version, which makes a compression pass unnecessary.
+  ///
+  /// Complexity: O(c * m), where c is the number of records changed since `last_db_version` and m is the average number
+  /// of columns per record; O(n * m) over all n records with a parent
+  CrdtVector<ChangeRef<K, V>> get_change_refs_since(uint64_t last_db_version) const {
+    CrdtTraceSpan span("get_changes_since");
+    span.event.version = last_db_version;
+    CrdtVector<ChangeRef<K, V>> changes;
+    for_each_change_since(last_db_version, [&](const ChangeRef<K, V> &change) { changes.push_back(change); });
+    span.event.changes = changes.size();
+    return changes;
+  }
+
+  /// Calls `fn(change)` with each change `get_change_refs_since` would return, one at a time instead of collecting
+  /// them, so the state can be streamed to storage or a peer with bounded memory. The CRDT must not be modified during
+  /// the call.
+  ///
+  /// Like `get_changes_since`, the records to visit come from the index of the versions they were written at when
+  /// there is no parent, in the order they were made.
+  ///
+  /// Complexity: O(c * m), where c is the number of records changed since `last_db_version` and m is the average number
+  /// of columns per record; O(n * m) over all n records with a parent
+  template <typename Fn>
+    requires std::invocable<Fn &, const ChangeRef<K, V> &>
+  void for_each_change_since(uint64_t last_db_version, Fn &&fn) const {
+    auto visit_record = [&](const K &record_id, const Record<V> &record) {
+      if (const std::optional<ColumnVersion> &revival = record.revival;
+          revival && revival->local_db_version > last_db_version && !is_placeholder(*revival)) {
+        fn(ChangeRef<K, V>{std::cref(record_id), nullptr, nullptr, revival->col_version, revival->db_version,
+                           revival->node_id, revival->local_db_version, 0, revival->seq});
+      }
+      for (const auto &[col_name, clock_info] : record.column_versions) {
+        if (clock_info.local_db_version <= last_db_version) {
+          continue;
+        }
+        auto field_it = record.fields.find(col_name);
+        const V *value = field_it != record.fields.end() ? &field_it->second : nullptr;
+        fn(ChangeRef<K, V>{std::cref(record_id), &col_name, value, clock_info.col_version, clock_info.db_version,
+                           clock_info.node_id, clock_info.local_db_version, 0, clock_info.seq});
+      }
+    };
+    auto visit_tombstone = [&](const K &record_id, const CrdtTombstone &tombstone) {
+      const ColumnVersion &version = tombstone.version;
+      if (version.local_db_version > last_db_version) {
+        fn(ChangeRef<K, V>{std::cref(record_id), nullptr, nullptr, version.col_version, version.db_version, version.node_id,
+                           version.local_db_version, tombstone.wall_time, version.seq});
+      }
+    };
+
+    if (parent_) {
+      for_each_record(visit_record);
+      for_each_tombstone(visit_tombstone);
+      return;
+    }
+    CrdtSet<K> visited;
+    for (auto it = change_index_.upper_bound(last_db_version); it != change_index_.end(); ++it) {
+      for (const K &record_id : it->second) {
+        if (!visited.insert(record_id).second) {
+          continue;
+        }
+        if (auto record = data_.find(record_id); record != data_.end()) {
+          visit_record(record->first, record->second);
+        } else if (auto tombstone = tombstones_.find(record_id); tombstone != tombstones_.end()) {
+          visit_tombstone(tombstone->first, tombstone->second);
+        }
+      }
+    }
+  }
+
+  /// Returns the digest of every record and tombstone, including records inherited from the parent, for a peer to pass
+  /// to `changes_missing_for`.
+  ///
+  /// Complexity: O(n * m log m), where n is the number of records and m is the average number of columns per record
+  CrdtMap<K, uint64_t> record_digests() const {
+    CrdtMap<K, uint64_t> digests;
+    for_each_record([&](const K &record_id, const Record<V> &record) { digests.emplace(record_id, record_digest(record)); });
+    for_each_tombstone(
+        [&](const K &record_id, const CrdtTombstone &tombstone) { digests.emplace(record_id, tombstone_digest(tombstone)); });
+    return digests;
+  }
+
+  /// Returns the changes of the records a peer lacks or holds at another version, given its `record_digests()`, instead
+  /// of everything written locally after a single db_version like `get_changes_since`. Records that reached the peer
+  /// along another path, e.g. in a mesh or a multi-hop topology, are not sent again.
+  ///
+  /// The digests describe the peer's whole state, so whatever a partial delivery (a `get_changes_for` batch, a
+  /// namespace stream, an interrupted merge) left out is found. A record the peer holds at a newer version is sent too,
+  /// and its merge rejects it. This costs a digest per record; a MerkleTree or an Iblt finds a few differences cheaper.
+  ///
+  /// # Arguments
+  ///
+  /// * `peer_digests` - The peer's `record_digests()`.
+  ///
+  /// Complexity: O(n * m log m), where n is the number of records and m is the average number of columns per record
+  CrdtVector<Change<K, V>> changes_missing_for(const CrdtMap<K, uint64_t> &peer_digests) const {
+    CrdtTraceSpan span("get_changes_since");
+    CrdtVector<K> differing;
+    auto compare = [&](const K &record_id, uint64_t digest) {
+      auto peer = peer_digests.find(record_id);
+      if (peer == peer_digests.end() || peer->second != digest) {
+        differing.push_back(record_id);
+      }
+    };
+    for_each_record([&](const K &record_id, const Record<V> &record) { compare(record_id, record_digest(record)); });
+    for_each_tombstone(
+        [&](const K &record_id, const CrdtTombstone &tombstone) { compare(record_id, tombstone_digest(tombstone)); });
+    CrdtVector<Change<K, V>> changes = get_changes_for(differing);
+    span.event.changes = changes.size();
+    return changes;
+  }
+
+  /// Merges a set of incoming changes into the CRDT.
+  ///
+  /// Merging is idempotent and order-independent: a change whose write is already stored (same node and sequence
+  /// number) is skipped as a duplicate, whatever the merge rule, and a reordered or older one is rejected by the merge
+  /// rule, so unreliable transports can retransmit freely. See `is_applied` for deciding what to retransmit.
+  ///
+  /// # Arguments
+  ///
+  /// * `changes` - A vector of changes to merge.
+  ///
+  /// # Returns
+  ///
+  /// If `ReturnAcceptedChanges` is `true`, returns a vector of accepted changes.
+  /// Otherwise, returns `void`.
+  ///
+  /// Complexity: O(c), where c is the number of changes to merge
+  template <bool ReturnAcceptedChanges = false>
+  std::conditional_t<ReturnAcceptedChanges, CrdtVector<Change<K, V>>, void> merge_changes(CrdtVector<Change<K, V>> &&changes,
+                                                                                          bool ignore_parent = false) {
+    return merge_range<ReturnAcceptedChanges>(changes, ignore_parent);
+  }
+
+  /// Same as `merge_changes`, but consumes any input range of changes, e.g. a deque, a decoding view or a generator,
+  /// without collecting it into a vector first. Record ids, column names and values are moved into the store when the
+  /// range yields non-const lvalues or rvalues; const elements are copied.
+  ///
+  /// # Arguments
+  ///
+  /// * `changes` - Any input range of `Change<K, V>`.
+  /// * `ignore_parent` - If true, merges against this instance only, ignoring the parent.
+  ///
+  /// Complexity: O(c), where c is the number of changes to merge
+  template <bool ReturnAcceptedChanges = false, std::ranges::input_range Range>
+    requires std::convertible_to<std::ranges::range_reference_t<Range>, const Change<K, V> &>
+  std::conditional_t<ReturnAcceptedChanges, CrdtVector<Change<K, V>>, void> merge_changes_owned(Range &&changes,
+                                                                                                bool ignore_parent = false) {
+    return merge_range<ReturnAcceptedChanges>(changes, ignore_parent);
+  }
+
+  /// Same as `merge_changes`, but partitions the batch by record id and merges the partitions on separate threads, for
+  /// large batches such as an initial sync. The clock is updated once, so every accepted change gets the same local
+  /// db_version, and accepted changes are returned (and reported to batch observers) grouped by partition rather than in
+  /// batch order. The validator and trace sink, if any, are called from several threads at once.
+  ///
+  /// Falls back to `merge_changes` for small batches and for features whose merge effects are not confined to one
+  /// record: a parent, change observers (batch observers are fine), history, unique columns, manual conflict resolution
+  /// and schema migrations.
+  ///
+  /// # Arguments
+  ///
+  /// * `changes` - A vector of changes to merge.
+  /// * `threads` - Number of partitions merged in parallel, by default one per hardware thread.
+  ///
+  /// Complexity: O(c / t + r), where c is the number of changes, t the number of threads and r the number of records
+  /// they touch
+  template <bool ReturnAcceptedChanges = false>
+  std::conditional_t<ReturnAcceptedChanges, CrdtVector<Change<K, V>>, void>
+  merge_changes_parallel(CrdtVector<Change<K, V>> &&changes, size_t threads = std::thread::hardware_concurrency()) {
+    threads = std::min(threads, changes.size() / PARALLEL_MERGE_MIN_PARTITION);
+    if (threads <= 1 || parent_ || !observers_.empty() || history_depth_ > 0 || !unique_index_.empty() ||
+        !manual_columns_.empty() || migration_record_ || !column_renames_.empty() || !migration_defaults_.empty() ||
+        (strict_sites_ && site_registry_)) {
+      return merge_changes<ReturnAcceptedChanges>(std::move(changes));
+    }
+
+    CrdtTraceSpan span("merge_changes_parallel");
+    span.event.changes = changes.size();
+    CrdtVector<CrdtVector<Change<K, V>>> partitions(threads);
+    for (auto &change : changes) {
+      partitions[partition_of(change.record_id, threads)].push_back(std::move(change));
+    }
+
+    const bool collect_accepted = ReturnAcceptedChanges || !batch_observers_.empty();
+    const uint64_t base_time = clock_.current_time();
+    CrdtVector<std::optional<CRDT>> shards(threads);
+    CrdtVector<CrdtVector<Change<K, V>>> accepted(threads);
+    CrdtVector<std::exception_ptr> errors(threads);
+    {
+      CrdtVector<std::thread> workers;
+      workers.reserve(threads);
+      for (size_t i = 0; i < threads; ++i) {
+        workers.emplace_back([&, i] {
+          try {
+            shards[i].emplace(node_id_, nullptr, merge_rule_, change_comparator_, sort_func_);
+            seed_shard(*shards[i], partitions[i], base_time);
+            if (collect_accepted) {
+              accepted[i] = shards[i]->template merge_changes<true>(std::move(partitions[i]));
+            } else {
+              shards[i]->merge_changes(std::move(partitions[i]));
+            }
+          } catch (...) {
+            errors[i] = std::current_exception();
+          }
+        });
+      }
+      for (auto &worker : workers) {
+        worker.join();
+      }
+    }
+    for (const auto &error : errors) {
+      if (error) {
+        std::rethrow_exception(error);
+      }
+    }
+
+    // Nothing is modified until every partition merged, so a failed partition leaves the state untouched. The clock
+    // follows the changes the shards did not drop for their epoch or clock anomalies
+    uint64_t max_db_version = 0;
+    for (const auto &shard : shards) {
+      for (const auto &[node_id, version] : shard->max_seen_versions_) {
+        max_db_version = std::max(max_db_version, version);
+      }
+    }
+    const uint64_t local_db_version = clock_.update(max_db_version);
+    CrdtVector<Change<K, V>> accepted_changes;
+    ++stats_.merges;
+    for (size_t i = 0; i < threads; ++i) {
+      CRDT &shard = *shards[i];
+      // Returns whether the shard wrote `version`; records it only seeded are left as they are
+      auto restamp = [&](ColumnVersion &version) {
+        if (version.local_db_version <= base_time) {
+          return false;
+        }
+        version.local_db_version = local_db_version;
+        return true;
+      };
+      for (auto &[record_id, record] : shard.data_) {
+        bool changed = false;
+        for (auto &[col_name, version] : record.column_versions) {
+          changed |= restamp(version);
+        }
+        if (record.revival) {
+          changed |= restamp(*record.revival);
+        }
+        if (!changed) {
+          continue;
+        }
+        count_transition(status(record_id), RecordStatus::Live);
+        tombstones_.erase(record_id);
+        data_.insert_or_assign(record_id, std::move(record));
+        note_change(record_id, local_db_version);
+      }
+      for (auto &[record_id, tombstone] : shard.tombstones_) {
+        if (!restamp(tombstone.version)) {
+          continue;
+        }
+        store_tombstone(record_id, std::move(tombstone));
+        note_change(record_id, local_db_version);
+      }
+      for (const auto &[node_id, version] : shard.max_seen_versions_) {
+        note_seen(node_id, version);
+      }
+      std::move(shard.quarantine_.begin(), shard.quarantine_.end(), std::back_inserter(quarantine_));
+      stats_.changes_accepted += shard.stats_.changes_accepted;
+      stats_.changes_rejected += shard.stats_.changes_rejected;
+      stats_.conflicts_resolved += shard.stats_.conflicts_resolved;
+      stats_.changes_denied += shard.stats_.changes_denied;
+      stats_.changes_invalid += shard.stats_.changes_invalid;
+      stats_.changes_other_epoch += shard.stats_.changes_other_epoch;
+      stats_.clock_anomalies += shard.stats_.clock_anomalies;
+      for (auto &change : accepted[i]) {
+        change.local_db_version = local_db_version;
+        accepted_changes.push_back(std::move(change));
+      }
+    }
+    span.event.version = local_db_version;
+    span.event.accepted = accepted_changes.size();
+    assert_invariants("merge_changes_parallel");
+    notify_batch(accepted_changes);
+
+    if constexpr (ReturnAcceptedChanges) {
+      return accepted_changes;
+    }
+  }
+
+  /// Compresses a vector of changes in-place by removing redundant changes that overwrite each other.
+  ///
+  /// # Arguments
+  ///
+  /// * `changes` - A vector of changes to compress (will be modified in-place).
+  ///
+  /// Complexity: O(n log n), where n is the number of changes
+  template <bool Sorted = false> static void compress_changes(CrdtVector<Change<K, V>> &changes) {
+    if (changes.empty())
+      return;
+
+    auto new_end = compress_changes<Sorted>(changes.begin(), changes.end());
+    changes.erase(new_end, changes.end());
+  }
+
+  /// Compresses a range of changes by removing redundant changes that overwrite each other.
+  ///
+  /// # Arguments
+  ///
+  /// * `begin` - Iterator to the beginning of the range.
+  /// * `end` - Iterator to the end of the range.
+  ///
+  /// # Returns
+  ///
+  /// Iterator to the new end of the range after compression.
+  ///
+  /// Complexity: O(n log n), where n is the number of changes
+  template <bool Sorted = false, typename Iterator> static Iterator compress_changes(Iterator begin, Iterator end) {
+    if (begin == end)
+      return end;
+
+    if constexpr (!Sorted) {
+      // Sort changes using the custom ChangeComparator
+      SortFunctionType()(begin, end, ChangeComparatorType());
+    }
+
+    // Use two-pointer technique to compress in-place
+    Iterator write = begin;
+    Iterator record_begin = begin;
+    for (Iterator read = std::next(begin); read != end; ++read) {
+      if (read->record_id != write->record_id) {
+        // New record, always keep it
+        ++write;
+        if (write != read) {
+          *write = std::move(*read);
+        }
+        record_begin = write;
+      } else if (!read->col_name.has_value() && write->col_name.has_value()) {
+        // Current read is the latest deletion or revival, keep it and skip the changes of earlier incarnations
+        uint64_t incarnation = read->col_version;
+        Iterator kept = std::remove_if(record_begin, std::next(write), [&](const Change<K, V> &change) {
+          return column_incarnation(change.col_version) < incarnation;
+        });
+        write = kept;
+        if (write != read) {
+          *write = std::move(*read);
+        }
+      } else if (read->col_name != write->col_name) {
+        // New column for the same record
+        ++write;
+        if (write != read) {
+          *write = std::move(*read);
+        }
+      }
+      // Else: same record and column, keep the existing one (which is the most recent due to sorting)
+    }
+
+    return std::next(write);
+  }
+
+  /// Renders a record for debugging: its columns with their values and versions, in column name order, or its
+  /// tombstone.
+  ///
+  /// # Arguments
+  ///
+  /// * `record_id` - The record to render; unknown records are reported as such.
+  /// * `style` - Indented lines, or a table with a row per column.
+  ///
+  /// Complexity: O(m log m), where m is the number of columns in the record
+  CrdtString format_record(const K &record_id, CrdtFormatStyle style = CrdtFormatStyle::Text) const {
+    std::ostringstream os;
+    write_records(os, {record_id}, style);
+    return os.str();
+  }
+
+  /// Renders the records and columns where this instance and `other` disagree (see `diff`), in record id order.
+  ///
+  /// Complexity: O(n * m + d log d), where n is the number of records, m is the average number of columns per record
+  /// and d is the number of differences
+  CrdtString format_diff(const CRDT &other, CrdtFormatStyle style = CrdtFormatStyle::Text) const {
+    CrdtVector<Divergence<K, V>> divergences = diff(other);
+    std::sort(divergences.begin(), divergences.end(), [](const Divergence<K, V> &a, const Divergence<K, V> &b) {
+      if (record_less(a.record_id, b.record_id) || record_less(b.record_id, a.record_id)) {
+        return record_less(a.record_id, b.record_id);
+      }
+      return a.col_name < b.col_name;
+    });
+
+    std::ostringstream os;
+    if (style == CrdtFormatStyle::Text) {
+      for (const Divergence<K, V> &divergence : divergences) {
+        os << divergence << "\n";
+      }
+      os << divergences.size() << (divergences.size() == 1 ? " difference" : " differences") << "\n";
+      return os.str();
+    }
+    CrdtVector<CrdtVector<CrdtString>> rows = {{"record", "column", "kind", "local", "remote", "local version",
+                                                "remote version"}};
+    for (const Divergence<K, V> &divergence : divergences) {
+      std::ostringstream kind;
+      kind << divergence.kind;
+      rows.push_back({crdt_format::to_text(divergence.record_id), divergence.col_name.value_or("-"), kind.str(),
+                      crdt_format::to_text(divergence.local_value), crdt_format::to_text(divergence.remote_value),
+                      crdt_format::version_cell(divergence.local_version),
+                      crdt_format::version_cell(divergence.remote_version)});
+    }
+    crdt_format::write_table(os, rows);
+    return os.str();
+  }
+
+  /// Writes the node id, the clock, and every record and tombstone in record id order, for debugging.
+  ///
+  /// Complexity: O(n * m + n log n), where n is the number of records and m is the average number of columns per record
+  friend std::ostream &operator<<(std::ostream &os, const CRDT &crdt) {
+    CrdtVector<K> record_ids;
+    crdt.for_each_record([&](const K &record_id, const Record<V> &) { record_ids.push_back(record_id); });
+    crdt.for_each_tombstone([&](const K &record_id, const CrdtTombstone &) { record_ids.push_back(record_id); });
+    std::sort(record_ids.begin(), record_ids.end(), record_less);
+    os << "Node " << crdt.node_id_ << " (clock " << crdt.clock_.current_time() << ", live " << crdt.len_live()
+       << ", tombstones " << crdt.len_tombstones() << ")\n";
+    crdt.write_records(os, record_ids, CrdtFormatStyle::Text);
+    return os;
+  }
+
+/// Prints the current data and tombstones for debugging purposes (see `operator<<`).
+///
+/// Complexity: O(n * m + n log n), where n is the number of records and m is the average number of fields per record
+#ifndef NDEBUG
+  constexpr void print_data() const { std::cout << *this << std::endl; }
+#else
+  constexpr void print_data() const {}
+#endif
+
+  // Accessors for testing
+  // Complexity: O(1)
+  constexpr const LogicalClock &get_clock() const { return clock_; }
+
+  /// The tombstones of this instance, excluding ones inherited from the parent (see `for_each_tombstone`).
+  ///
+  /// Complexity: O(1)
+  constexpr const CrdtMap<K, CrdtTombstone> &get_tombstones() const { return tombstones_; }
+
+  /// The tombstone of a deleted record, including one inherited from the parent, or nullptr if the record is not
+  /// deleted.
+  ///
+  /// Complexity: O(1) average case for hash table lookup
+  const CrdtTombstone *get_tombstone(const K &record_id) const { return find_tombstone(record_id); }
+
+  /// Lists every tombstone, including ones inherited from the parent, oldest first, for monitoring tombstone growth.
+  ///
+  /// # Returns
+  ///
+  /// The tombstones with their deletion versions and ages. `age` is measured in local db_versions, and `wall_age` in
+  /// milliseconds when the deleting node and this node both have a wall clock set (see `set_wall_clock`).
+  ///
+  /// Complexity: O(t log t), where t is the number of tombstones
+  CrdtVector<CrdtTombstoneInfo<K>> tombstones() const {
+    uint64_t now = clock_.current_time();
+    std::optional<uint64_t> wall_now;
+    if (wall_clock_) {
+      wall_now = wall_clock_();
+    }
+    CrdtVector<CrdtTombstoneInfo<K>> result;
+    for_each_tombstone([&](const K &record_id, const CrdtTombstone &tombstone) {
+      CrdtTombstoneInfo<K> info{record_id, tombstone.version, now - std::min(now, tombstone.version.local_db_version), {}, {}};
+      if (tombstone.wall_time != 0) {
+        info.wall_time = tombstone.wall_time;
+        if (wall_now) {
+          info.wall_age = *wall_now - std::min(*wall_now, tombstone.wall_time);
+        }
+      }
+      result.push_back(std::move(info));
+    });
+    std::sort(result.begin(), result.end(), [](const CrdtTombstoneInfo<K> &a, const CrdtTombstoneInfo<K> &b) {
+      return a.version.local_db_version < b.version.local_db_version;
+    });
+    return result;
+  }
+
+  /// Counts the tombstones, including ones inherited from the parent, and how many of them could be garbage collected.
+  ///
+  /// # Arguments
+  ///
+  /// * `acknowledged_version` - The highest local db_version every peer has received (e.g. the minimum of the
+  ///   versions peers last synced from). Tombstones stored at or before it are reported as eligible for GC.
+  ///
+  /// Complexity: O(t), where t is the number of tombstones
+  CrdtTombstoneCounts tombstone_counts(uint64_t acknowledged_version) const {
+    CrdtTombstoneCounts counts;
+    for_each_tombstone([&](const K &, const CrdtTombstone &tombstone) {
+      ++counts.total;
+      if (tombstone.version.local_db_version <= acknowledged_version) {
+        ++counts.gc_eligible;
+      }
+    });
+    return counts;
+  }
+
+  /// Garbage collects the tombstones every peer has received, i.e. the ones `tombstone_counts` reports as eligible.
+  ///
+  /// A collected record is forgotten: a peer that had not seen its deletion could bring it back by sending older
+  /// writes, so `acknowledged_version` must really cover every peer. Tombstones inherited from the parent, and ones that
+  /// hide a record of the parent, are kept, as are the markers of purged records.
+  ///
+  /// # Arguments
+  ///
+  /// * `acknowledged_version` - The highest local db_version every peer has received.
+  ///
+  /// # Returns
+  ///
+  /// The number of tombstones removed.
+  ///
+  /// Complexity: O(t), where t is the number of tombstones
+  size_t collect_tombstones(uint64_t acknowledged_version) {
+    return std::erase_if(tombstones_, [&](const auto &entry) {
+      const auto &[record_id, tombstone] = entry;
+      bool collect = tombstone.version.local_db_version <= acknowledged_version &&
+                     tombstone.version.col_version != PURGED_INCARNATION &&
+                     (!parent_ || parent_->get_record_ptr(record_id) == nullptr);
+      if (collect) {
+        count_transition(RecordStatus::Tombstoned, parent_ ? parent_->status(record_id) : RecordStatus::Unknown);
+      }
+      return collect;
+    });
+  }
+
+  /// The live records, including records inherited from the parent. Tombstones are kept apart, in `get_tombstones`.
+  ///
+  /// Complexity: O(n), where n is the number of records
+  constexpr CrdtMap<K, Record<V>> get_data() const {
+    if (!parent_) {
+      return data_;
+    }
+
+    CrdtMap<K, Record<V>> combined_data = parent_->get_data();
+    for (const auto &[key, record] : data_) {
+      combined_data[key] = record;
+    }
+    for (const auto &[key, tombstone] : tombstones_) {
+      combined_data.erase(key);
+    }
+    return combined_data;
+  }
+
+  /// Retrieves a pointer to a live record if it exists, or nullptr if it doesn't.
+  ///
+  /// # Arguments
+  ///
+  /// * `record_id` - The unique identifier for the record.
+  /// * `ignore_parent` - If true, only checks the current CRDT instance, ignoring the parent.
+  ///
+  /// # Returns
+  ///
+  /// A pointer to the Record<V> if found, or nullptr if not found or tombstoned.
+  ///
+  /// Complexity: O(1) average case for hash table lookup
+  Record<V> *get_record(const K &record_id, bool ignore_parent = false) {
+    if (is_record_tombstoned(record_id, ignore_parent)) {
+      return nullptr;
+    }
+    return get_record_ptr(record_id, ignore_parent);
+  }
+
+  const Record<V> *get_record(const K &record_id, bool ignore_parent = false) const {
+    if (is_record_tombstoned(record_id, ignore_parent)) {
+      return nullptr;
+    }
+    return get_record_ptr(record_id, ignore_parent);
+  }
+
+  /// Retrieves a pointer to a field value of a live record.
+  ///
+  /// # Arguments
+  ///
+  /// * `record_id` - The unique identifier for the record.
+  /// * `col_name` - The column to read.
+  ///
+  /// # Returns
+  ///
+  /// A pointer to the value, or nullptr if the record is missing, tombstoned, or has no such column.
+  ///
+  /// Complexity: O(1) average case for hash table lookup
+  const V *get_value(const K &record_id, const CrdtString &col_name) const {
+    const Record<V> *record = get_record(record_id);
+    if (!record) {
+      return nullptr;
+    }
+    auto it = record->fields.find(col_name);
+    return it != record->fields.end() ? &it->second : nullptr;
+  }
+
+  /// Checks whether a live (non-tombstoned) record exists.
+  ///
+  /// Complexity: O(1) average case for hash table lookup
+  bool contains_record(const K &record_id) const { return get_record(record_id) != nullptr; }
+
+  /// Reports whether a record is live, tombstoned, or unknown, including records inherited from the parent.
+  ///
+  /// Complexity: O(1) average case for hash table lookup
+  RecordStatus status(const K &record_id) const {
+    if (get_record(record_id) != nullptr) {
+      return RecordStatus::Live;
+    }
+    return is_record_tombstoned(record_id) ? RecordStatus::Tombstoned : RecordStatus::Unknown;
+  }
+
+  /// Checks whether a record was deleted (and not revived since), including records inherited from the parent.
+  ///
+  /// Complexity: O(1) average case for hash table lookup
+  bool is_deleted(const K &record_id) const { return status(record_id) == RecordStatus::Tombstoned; }
+
+  /// Returns the number of live records, including records inherited from the parent.
+  ///
+  /// Complexity: O(1); O(n) on a CRDT created with a parent, where n is the number of records
+  size_t len_live() const {
+    if (counts_exact()) {
+      return live_count_;
+    }
+    size_t count = 0;
+    for_each_live_record([&](const K &, const Record<V> &) { ++count; });
+    return count;
+  }
+
+  /// Checks whether there are no live records, including records inherited from the parent.
+  ///
+  /// Complexity: O(1); O(n) on a CRDT created with a parent, where n is the number of records
+  bool is_empty_live() const {
+    return len_live() == 0;
+  }
+
+  /// Returns the number of tombstones, including ones inherited from the parent.
+  ///
+  /// Complexity: O(1); O(t) on a CRDT created with a parent, where t is the number of tombstones
+  size_t len_tombstones() const {
+    if (counts_exact()) {
+      return tombstone_count_;
+    }
+    size_t count = 0;
+    for_each_tombstone([&](const K &, const CrdtTombstone &) { ++count; });
+    return count;
+  }
+
+  /// Returns sizes of the current state and counters accumulated since this instance was constructed. Counters are not
+  /// copied along with the CRDT.
+  ///
+  /// Complexity: O(n * m), where n is the number of records and m is the average number of columns per record
+  CrdtStats stats() const {
+    CrdtStats result = stats_;
+    result.clock = clock_.current_time();
+    result.live_records = len_live();
+    result.tombstones = len_tombstones();
+    for_each_record([&](const K &, const Record<V> &record) { result.columns += record.column_versions.size(); });
+    result.bytes_estimate = estimated_memory_bytes();
+    return result;
+  }
+
+  /// Estimates the memory held by this instance: keys, field values, column-name strings, version metadata, tombstones,
+  /// column history, and container overhead. Records inherited from a parent are not included.
+  ///
+  /// The figure is an approximation for eviction and compaction policies; allocator overhead and fragmentation are not
+  /// counted. Specialize `CrdtMemoryUsage` for key or value types that allocate.
+  ///
+  /// Complexity: O(n * m), where n is the number of records and m is the average number of columns per record
+  size_t estimated_memory_bytes() const {
+    size_t bytes = sizeof(*this) + container_overhead(data_) + container_overhead(tombstones_);
+    for (const auto &[record_id, record] : data_) {
+      bytes += CrdtMemoryUsage<K>::heap_bytes(record_id) + container_overhead(record.fields) +
+               container_overhead(record.column_versions);
+      for (const auto &[col_name, value] : record.fields) {
+        bytes += CrdtMemoryUsage<CrdtString>::heap_bytes(col_name) + CrdtMemoryUsage<V>::heap_bytes(value);
+      }
+      for (const auto &[col_name, version] : record.column_versions) {
+        bytes += CrdtMemoryUsage<CrdtString>::heap_bytes(col_name);
+      }
+    }
+    for (const auto &[record_id, tombstone] : tombstones_) {
+      bytes += CrdtMemoryUsage<K>::heap_bytes(record_id);
+    }
+    bytes += container_overhead(history_);
+    for (const auto &[record_id, columns] : history_) {
+      bytes += CrdtMemoryUsage<K>::heap_bytes(record_id) + container_overhead(columns);
+      for (const auto &[col_name, entries] : columns) {
+        bytes += CrdtMemoryUsage<CrdtString>::heap_bytes(col_name) + entries.size() * sizeof(ColumnHistoryEntry<V>);
+        for (const auto &entry : entries) {
+          bytes += entry.value ? CrdtMemoryUsage<V>::heap_bytes(*entry.value) : 0;
+        }
+      }
+    }
+    bytes += container_overhead(max_seen_versions_);
+    return bytes;
+  }
+
+  /// Calls `fn(record_id, record)` for every live record, including records inherited from the parent.
+  /// Records present in this CRDT shadow the parent's version, and tombstoned records are skipped.
+  ///
+  /// Complexity: O(n), where n is the number of records
+  template <typename Fn>
+    requires std::invocable<Fn &, const K &, const Record<V> &>
+  void iter_live(Fn &&fn) const {
+    for_each_live_record(fn);
+  }
+
+  /// Calls `fn(record_id, record)` for every live record, including records inherited from the parent. Records and
+  /// tombstones present in this CRDT shadow the parent's version. Unlike `iter_live`, the order is unspecified.
+  ///
+  /// Complexity: O(n), where n is the number of records
+  template <typename Fn>
+    requires std::invocable<Fn &, const K &, const Record<V> &>
+  void for_each_record(Fn &&fn) const {
+    for (const CRDT *level = this; level != nullptr; level = level->parent_.get()) {
+      for (const auto &[record_id, record] : level->data_) {
+        if (!shadowed_below(level, record_id)) {
+          fn(record_id, record);
+        }
+      }
+    }
+  }
+
+  /// Calls `fn(record_id, tombstone)` for every tombstone, including ones inherited from the parent. Records and
+  /// tombstones present in this CRDT shadow the parent's version.
+  ///
+  /// Complexity: O(t), where t is the number of tombstones
+  template <typename Fn>
+    requires std::invocable<Fn &, const K &, const CrdtTombstone &>
+  void for_each_tombstone(Fn &&fn) const {
+    for (const CRDT *level = this; level != nullptr; level = level->parent_.get()) {
+      for (const auto &[record_id, tombstone] : level->tombstones_) {
+        if (!shadowed_below(level, record_id)) {
+          fn(record_id, tombstone);
+        }
+      }
+    }
+  }
+
+  /// Computes a deterministic digest of a record's column versions, and of its values when `V` is CrdtDigestible.
+  /// Node-local bookkeeping (`local_db_version`) is excluded, so converged replicas produce the same digest.
+  ///
+  /// Complexity: O(m log m), where m is the number of columns in the record
+  static uint64_t record_digest(const Record<V> &record) {
+    CrdtVector<const CrdtString *> columns;
+    columns.reserve(record.column_versions.size());
+    for (const auto &[col_name, _] : record.column_versions) {
+      columns.push_back(&col_name);
+    }
+    std::sort(columns.begin(), columns.end(), [](const CrdtString *a, const CrdtString *b) { return *a < *b; });
+
+    CrdtHasher hasher;
+    add_lifecycle_digest(hasher, record.revival ? &*record.revival : nullptr);
+    for (const CrdtString *col_name : columns) {
+      const ColumnVersion &version = record.column_versions.at(*col_name);
+      CrdtDigest<CrdtString>::add(hasher, *col_name);
+      hasher.update_u64(version.col_version);
+      hasher.update_u64(version.db_version);
+      CrdtDigest<CrdtNodeId>::add(hasher, version.node_id);
+      if constexpr (CrdtDigestible<V>) {
+        auto field_it = record.fields.find(*col_name);
+        hasher.update_u64(field_it != record.fields.end() ? 1 : 0);
+        if (field_it != record.fields.end()) {
+          CrdtDigest<V>::add(hasher, field_it->second);
+        }
+      }
+    }
+    return hasher.finish();
+  }
+
+  /// Computes a deterministic digest of a tombstone's deletion version, equal to the digest of a record without columns
+  /// whose revival version is the deletion version.
+  ///
+  /// Complexity: O(1)
+  static uint64_t tombstone_digest(const CrdtTombstone &tombstone) {
+    CrdtHasher hasher;
+    add_lifecycle_digest(hasher, &tombstone.version);
+    return hasher.finish();
+  }
+
+  /// Computes a deterministic fingerprint of the whole state: every record's id, column versions and values, including
+  /// tombstones. Independent of map iteration order and node-local bookkeeping, so two replicas that have converged
+  /// return the same hash.
+  ///
+  /// Complexity: O(n * m log m), where n is the number of records and m is the average number of columns per record
+  uint64_t state_hash() const
+    requires CrdtDigestible<K>
+  {
+    uint64_t hash = 0;
+    for_each_record([&](const K &record_id, const Record<V> &record) {
+      CrdtHasher key_hasher;
+      CrdtDigest<K>::add(key_hasher, record_id);
+      // Summing mixed per-record hashes keeps the result independent of visiting order
+      hash += CrdtHasher::mix(key_hasher.finish() ^ record_digest(record));
+    });
+    for_each_tombstone([&](const K &record_id, const CrdtTombstone &tombstone) {
+      CrdtHasher key_hasher;
+      CrdtDigest<K>::add(key_hasher, record_id);
+      hash += CrdtHasher::mix(key_hasher.finish() ^ tombstone_digest(tombstone));
+    });
+    return hash;
+  }
+
+  /// Checks the internal invariants of this instance's records and tombstones (not those inherited from a parent), for
+  /// tracking down silent inconsistencies: every tombstone holds a deletion version, no live record holds one or is
+  /// also tombstoned, every field has a column version written in the record's incarnation, and the clock is at or
+  /// above every version. Define CRDT_CHECK_INVARIANTS to run this after every merge, throwing CrdtInvariantError.
+  ///
+  /// # Returns
+  ///
+  /// The violations found, empty if the state is consistent.
+  ///
+  /// Complexity: O(n * m), where n is the number of records and m is the average number of columns per record
+  CrdtVector<CrdtInvariantViolation<K>> check_invariants() const {
+    CrdtVector<CrdtInvariantViolation<K>> violations;
+    uint64_t clock = clock_.current_time();
+    auto check_clock = [&](const K &record_id, const std::optional<CrdtString> &col_name, const ColumnVersion &version) {
+      if (version.db_version > clock || version.local_db_version > clock) {
+        violations.push_back({CrdtInvariantKind::VersionAheadOfClock, record_id, col_name, version, clock});
+      }
+    };
+    for (const auto &[record_id, record] : data_) {
+      if (tombstones_.find(record_id) != tombstones_.end()) {
+        violations.push_back({CrdtInvariantKind::LiveAndTombstoned, record_id, std::nullopt, std::nullopt, clock});
+      }
+      uint64_t incarnation = incarnation_of(record);
+      if (record.revival) {
+        if (!is_revival(record.revival->col_version)) {
+          violations.push_back({CrdtInvariantKind::LiveRecordDeleted, record_id, std::nullopt, record.revival, clock});
+        }
+        check_clock(record_id, std::nullopt, *record.revival);
+      }
+      for (const auto &[col_name, version] : record.column_versions) {
+        if (column_incarnation(version.col_version) > incarnation) {
+          violations.push_back({CrdtInvariantKind::ColumnAfterRecord, record_id, col_name, version, clock});
+        }
+        check_clock(record_id, col_name, version);
+      }
+      for (const auto &[col_name, value] : record.fields) {
+        if (record.column_versions.find(col_name) == record.column_versions.end() && !local_columns_.contains(col_name)) {
+          violations.push_back({CrdtInvariantKind::FieldWithoutVersion, record_id, col_name, std::nullopt, clock});
+        }
+      }
+    }
+    for (const auto &[record_id, tombstone] : tombstones_) {
+      if (tombstone.version.col_version % 2 == 0) {
+        violations.push_back({CrdtInvariantKind::TombstoneNotDeletion, record_id, std::nullopt, tombstone.version, clock});
+      }
+      check_clock(record_id, std::nullopt, tombstone.version);
+    }
+    return violations;
+  }
+
+  /// Lists the records and columns where this instance and `other` disagree, for debugging replication issues.
+  ///
+  /// Versions are compared by `col_version`, `db_version` and `node_id`; node-local bookkeeping is ignored.
+  ///
+  /// Complexity: O(n * m), where n is the number of records and m is the average number of columns per record
+  CrdtVector<Divergence<K, V>> diff(const CRDT &other) const {
+    CrdtVector<Divergence<K, V>> divergences;
+    CrdtMap<K, const Record<V> *> theirs;
+    other.for_each_record([&](const K &record_id, const Record<V> &record) { theirs.emplace(record_id, &record); });
+
+    auto same_version = [](const ColumnVersion &a, const ColumnVersion &b) {
+      return a.col_version == b.col_version && a.db_version == b.db_version && a.node_id == b.node_id;
+    };
+    auto version_of = [](const Record<V> &record, const CrdtString &col_name) -> std::optional<ColumnVersion> {
+      auto it = record.column_versions.find(col_name);
+      return it != record.column_versions.end() ? std::optional<ColumnVersion>(it->second) : std::nullopt;
+    };
+    auto value_of = [](const Record<V> &record, const CrdtString &col_name) -> std::optional<V> {
+      auto it = record.fields.find(col_name);
+      return it != record.fields.end() ? std::optional<V>(it->second) : std::nullopt;
+    };
+
+    for_each_tombstone([&](const K &record_id, const CrdtTombstone &ours) {
+      if (const CrdtTombstone *their_tombstone = other.find_tombstone(record_id)) {
+        if (!same_version(ours.version, their_tombstone->version)) {
+          divergences.push_back({record_id, std::nullopt, DivergenceKind::VersionMismatch, {}, {}, ours.version,
+                                 their_tombstone->version});
+        }
+      } else if (theirs.erase(record_id) > 0) {
+        divergences.push_back({record_id, std::nullopt, DivergenceKind::TombstoneMismatch, {}, {}, {}, {}});
+      } else {
+        divergences.push_back({record_id, std::nullopt, DivergenceKind::MissingRemotely, {}, {}, {}, {}});
+      }
+    });
+    other.for_each_tombstone([&](const K &record_id, const CrdtTombstone &) {
+      if (find_tombstone(record_id) == nullptr && get_record_ptr(record_id) == nullptr) {
+        divergences.push_back({record_id, std::nullopt, DivergenceKind::MissingLocally, {}, {}, {}, {}});
+      }
+    });
+
+    for_each_record([&](const K &record_id, const Record<V> &ours) {
+      auto their_it = theirs.find(record_id);
+      if (their_it == theirs.end()) {
+        DivergenceKind kind = other.find_tombstone(record_id) ? DivergenceKind::TombstoneMismatch
+                                                              : DivergenceKind::MissingRemotely;
+        divergences.push_back({record_id, std::nullopt, kind, {}, {}, {}, {}});
+        return;
+      }
+      const Record<V> &their_record = *their_it->second;
+      theirs.erase(their_it);
+
+      auto compare_column = [&](const CrdtString &col_name) {
+        auto local_version = version_of(ours, col_name);
+        auto remote_version = version_of(their_record, col_name);
+        auto local_value = value_of(ours, col_name);
+        auto remote_value = value_of(their_record, col_name);
+        if (local_value != remote_value) {
+          divergences.push_back({record_id, col_name, DivergenceKind::ValueMismatch, std::move(local_value),
+                                 std::move(remote_value), local_version, remote_version});
+        } else if (local_version.has_value() != remote_version.has_value() ||
+                   (local_version && !same_version(*local_version, *remote_version))) {
+          divergences.push_back({record_id, col_name, DivergenceKind::VersionMismatch, std::move(local_value),
+                                 std::move(remote_value), local_version, remote_version});
+        }
+      };
+      if (ours.revival.has_value() != their_record.revival.has_value() ||
+          (ours.revival && !same_version(*ours.revival, *their_record.revival))) {
+        divergences.push_back(
+            {record_id, std::nullopt, DivergenceKind::VersionMismatch, {}, {}, ours.revival, their_record.revival});
+      }
+      for (const auto &[col_name, _] : ours.column_versions) {
+        compare_column(col_name);
+      }
+      for (const auto &[col_name, _] : their_record.column_versions) {
+        if (ours.column_versions.find(col_name) == ours.column_versions.end()) {
+          compare_column(col_name);
+        }
+      }
+    });
+
+    for (const auto &[record_id, _] : theirs) {
+      divergences.push_back({record_id, std::nullopt, DivergenceKind::MissingLocally, {}, {}, {}, {}});
+    }
+    return divergences;
+  }
+
+  /// Returns the full-state changes (every column version, or the tombstone) for the given records, e.g. after a digest
+  /// comparison found them to differ. Unknown records are skipped.
+  ///
+  /// Complexity: O(r * m), where r is the number of requested records and m is the average number of columns per record
+  CrdtVector<Change<K, V>> get_changes_for(const CrdtVector<K> &record_ids) const {
+    CrdtVector<Change<K, V>> changes;
+    for (const K &record_id : record_ids) {
+      if (const CrdtTombstone *tombstone = find_tombstone(record_id)) {
+        changes.push_back(tombstone_change(record_id, *tombstone));
+        continue;
+      }
+      const Record<V> *record = get_record_ptr(record_id);
+      if (!record) {
+        continue;
+      }
+      if (record->revival && !is_placeholder(*record->revival)) {
+        changes.push_back(revival_change(record_id, *record->revival));
+      }
+      for (const auto &[col_name, clock_info] : record->column_versions) {
+        auto field_it = record->fields.find(col_name);
+        std::optional<V> value = field_it != record->fields.end() ? std::optional<V>(field_it->second) : std::nullopt;
+        changes.push_back(version_change(record_id, col_name, std::move(value), clock_info));
+      }
+    }
+    return changes;
+  }
+
+  /// Returns all live (non-tombstoned) records matching a predicate.
+  ///
+  /// # Arguments
+  ///
+  /// * `predicate` - Called with `(record_id, record)` for every live record, including records inherited from the parent.
+  ///
+  /// # Returns
+  ///
+  /// A vector of `(record_id, record pointer)` pairs. The pointers are invalidated by any subsequent mutation.
+  ///
+  /// Complexity: O(n), where n is the number of records
+  template <typename Predicate>
+    requires std::predicate<Predicate &, const K &, const Record<V> &>
+  CrdtVector<std::pair<K, const Record<V> *>> query(Predicate &&predicate) const {
+    CrdtVector<std::pair<K, const Record<V> *>> results;
+    for_each_live_record([&](const K &record_id, const Record<V> &record) {
+      if (predicate(record_id, record)) {
+        results.emplace_back(record_id, &record);
+      }
+    });
+    return results;
+  }
+
+  /// Returns the live records with keys in the half-open range `[start, end)`, sorted by key.
+  ///
+  /// # Arguments
+  ///
+  /// * `start` - The inclusive lower bound.
+  /// * `end` - The exclusive upper bound.
+  ///
+  /// # Returns
+  ///
+  /// A vector of `(record_id, record pointer)` pairs in ascending key order.
+  ///
+  /// Complexity: O(log n + r) with CRDT_ORDERED_COLLECTIONS, O(n + r log r) otherwise, where r is the number of results
+  CrdtVector<std::pair<K, const Record<V> *>> range(const K &start, const K &end) const {
+    return collect_live_from(start, [&](const K &record_id) { return record_id < end; });
+  }
+
+  /// Returns the live records whose keys start with `key_prefix`, sorted by key.
+  ///
+  /// Only available for string-like keys.
+  ///
+  /// Complexity: O(log n + r) with CRDT_ORDERED_COLLECTIONS, O(n + r log r) otherwise, where r is the number of results
+  CrdtVector<std::pair<K, const Record<V> *>> prefix(const K &key_prefix) const
+    requires requires(const K &k) { k.starts_with(k); }
+  {
+    return collect_live_from(key_prefix, [&](const K &record_id) { return record_id.starts_with(key_prefix); });
+  }
+
+  /// Returns up to `count` live records with the greatest keys, in descending key order.
+  ///
+  /// Useful with lexicographically sortable ids (e.g. KSUIDs) to fetch the most recently created records.
+  ///
+  /// Complexity: O(log n + count) with CRDT_ORDERED_COLLECTIONS and no parent, O(n log count) otherwise
+  CrdtVector<std::pair<K, const Record<V> *>> latest(size_t count) const {
+    CrdtVector<std::pair<K, const Record<V> *>> results;
+    if (count == 0) {
+      return results;
+    }
+
+    if constexpr (has_ordered_storage) {
+      if (!parent_) {
+        for (auto it = data_.rbegin(); it != data_.rend() && results.size() < count; ++it) {
+          results.emplace_back(it->first, &it->second);
+        }
+        return results;
+      }
+    }
+
+    for_each_live_record([&](const K &record_id, const Record<V> &record) { results.emplace_back(record_id, &record); });
+    auto by_key_desc = [](const auto &a, const auto &b) { return b.first < a.first; };
+    if (results.size() > count) {
+      std::partial_sort(results.begin(), results.begin() + count, results.end(), by_key_desc);
+      results.resize(count);
+    } else {
+      std::sort(results.begin(), results.end(), by_key_desc);
+    }
+    return results;
+  }
+
+  // Add this public method to the CRDT class
+  /// Checks if a record is tombstoned.
+  ///
+  /// # Arguments
+  ///
+  /// * `record_id` - The unique identifier for the record.
+  /// * `ignore_parent` - If true, only checks the current CRDT instance, ignoring the parent.
+  ///
+  /// # Returns
+  ///
+  /// True if the record is tombstoned, false otherwise.
+  ///
+  /// Complexity: O(1) average case for hash table lookup
+  constexpr bool is_tombstoned(const K &record_id, bool ignore_parent = false) const {
+    return is_record_tombstoned(record_id, ignore_parent);
+  }
+
+  /// Who deleted a record and when, or std::nullopt if the record is not deleted. Replicated with the deletion, so
+  /// every replica reports the same node and clock; the wall-clock time is only known if the deleting node set a wall
+  /// clock.
+  ///
+  /// Complexity: O(1) average case
+  std::optional<CrdtDeletionInfo> deletion_info(const K &record_id) const {
+    const CrdtTombstone *tombstone = find_tombstone(record_id);
+    if (tombstone == nullptr) {
+      return std::nullopt;
+    }
+    return CrdtDeletionInfo{tombstone->version.node_id, tombstone->version.db_version,
+                            tombstone->wall_time != 0 ? std::optional<uint64_t>(tombstone->wall_time) : std::nullopt};
+  }
+
+  /// The latest write to a record, for cheap "has this record changed since it was rendered?" checks and ETags.
+  ///
+  /// Covers every column of a live record (including column deletions and revivals) or the deletion of a tombstoned
+  /// one. Ties between nodes at the same db_version go to the higher node id, so converged replicas report the same
+  /// version.
+  ///
+  /// # Returns
+  ///
+  /// The highest db_version and the node that wrote it, or std::nullopt if the record is unknown.
+  ///
+  /// Complexity: O(m), where m is the number of columns in the record
+  std::optional<CrdtRecordVersion> record_version(const K &record_id) const {
+    if (const CrdtTombstone *tombstone = find_tombstone(record_id)) {
+      return CrdtRecordVersion{tombstone->version.db_version, tombstone->version.node_id};
+    }
+    const Record<V> *record = get_record_ptr(record_id);
+    if (record == nullptr) {
+      return std::nullopt;
+    }
+    std::optional<CrdtRecordVersion> latest;
+    for_each_version(*record, [&](const ColumnVersion &version) {
+      if (!latest || std::tie(version.db_version, version.node_id) > std::tie(latest->db_version, latest->node_id)) {
+        latest = CrdtRecordVersion{version.db_version, version.node_id};
+      }
+    });
+    return latest;
+  }
+
+  /// Who last wrote a column of a live record, e.g. to show "edited by device X" or attribute bad data to a client.
+  ///
+  /// # Arguments
+  ///
+  /// * `record_id` - The unique identifier for the record.
+  /// * `col_name` - The column to look up.
+  ///
+  /// # Returns
+  ///
+  /// The last writer, or std::nullopt if the record is missing or tombstoned, or the column was never written. A
+  /// column deletion counts as a write.
+  ///
+  /// Complexity: O(1) average case for hash table lookup
+  std::optional<CrdtLastWriter> last_writer(const K &record_id, const CrdtString &col_name) const {
+    const Record<V> *record = get_record_ptr(record_id);
+    if (record == nullptr) {
+      return std::nullopt;
+    }
+    auto it = record->column_versions.find(col_name);
+    if (it == record->column_versions.end()) {
+      return std::nullopt;
+    }
+    return CrdtLastWriter{it->second.node_id, it->second.db_version, it->second.col_version};
+  }
+
+  /// Returns the wall-clock time in milliseconds since the Unix epoch.
+  using WallClock = std::function<uint64_t()>;
+
+  /// Sets the wall clock stamped on local deletions, or stops stamping them with an empty function. Wall-clock times
+  /// are informational (see `deletion_info`) and never affect merging. Child CRDTs inherit the parent's wall clock.
+  void set_wall_clock(WallClock wall_clock) { wall_clock_ = std::move(wall_clock); }
+
+  /// Called with each clock anomaly, e.g. to alert an operator or disconnect a misbehaving peer.
+  using ClockAnomalyHandler = std::function<void(const CrdtClockAnomaly &)>;
+
+  /// Guards the logical clock against peers sending db_versions implausibly far ahead, which would otherwise drag every
+  /// replica's clock along and could exhaust it, or removes the guard with std::nullopt. Every replica should use the
+  /// same guard, or replicas may disagree on which changes they merged.
+  ///
+  /// Independently of the guard, the clock never wraps around or leaves its epoch: incoming changes that would take it
+  /// past the last time of its epoch are dropped, and local operations throw std::overflow_error, until a new epoch is
+  /// started. Either way the anomaly is counted in `stats().clock_anomalies` and reported to the handler. Child CRDTs
+  /// inherit the parent's guard and handler when created.
+  void set_clock_guard(std::optional<CrdtClockGuard> guard) { clock_guard_ = guard; }
+
+  const std::optional<CrdtClockGuard> &clock_guard() const { return clock_guard_; }
+
+  /// Sets the handler called with each clock anomaly, or removes it with an empty function. During
+  /// `merge_changes_parallel` it is called from several threads at once.
+  void set_clock_anomaly_handler(ClockAnomalyHandler handler) { clock_anomaly_handler_ = std::move(handler); }
+
+  /// Installs column-level write permissions, or removes them with std::nullopt. Incoming changes the policy denies
+  /// are dropped by `merge_changes`, and local operations it denies throw std::invalid_argument without changing
+  /// anything. Child CRDTs inherit the parent's policy when created.
+  void set_write_policy(std::optional<CrdtWritePolicy> policy) { write_policy_ = std::move(policy); }
+
+  const std::optional<CrdtWritePolicy> &write_policy() const { return write_policy_; }
+
+  /// Installs time-to-live rules, or removes them with std::nullopt. Nothing expires until `expire` is called, e.g.
+  /// periodically by a CrdtMaintenance. Child CRDTs inherit the parent's rules when created.
+  void set_ttl_policy(std::optional<CrdtTtlPolicy> policy) { ttl_policy_ = std::move(policy); }
+
+  const std::optional<CrdtTtlPolicy> &ttl_policy() const { return ttl_policy_; }
+
+  /// Whether the live record `record_id` has expired at `now_ms` under the TTL policy but was not deleted by `expire`
+  /// yet, e.g. to hide it from readers in the meantime.
+  bool is_expired(const K &record_id, uint64_t now_ms) const {
+    const Record<V> *record = get_record_ptr(record_id);
+    return ttl_policy_ && record && !is_record_tombstoned(record_id) && ttl_policy_->record_expired(*record, now_ms);
+  }
+
+  /// Deletes the records and columns that have expired at `now_ms` under the TTL policy, as regular local deletions
+  /// that replicate like any other. Every replica computes the same expiry from the replicated timestamp columns, so
+  /// it does not matter which ones run `expire`; concurrent expiries of the same record converge like concurrent
+  /// deletions. A record whose timestamp is refreshed before it expires lives on.
+  ///
+  /// # Arguments
+  ///
+  /// * `now_ms` - The current wall-clock time in milliseconds since the Unix epoch.
+  ///
+  /// # Returns
+  ///
+  /// The deletions, one per expired record or column.
+  ///
+  /// Complexity: O(n * t), where n is the number of records and t the number of TTL rules
+  CrdtVector<Change<K, V>> expire(uint64_t now_ms) {
+    CrdtVector<Change<K, V>> changes;
+    if (!ttl_policy_) {
+      return changes;
+    }
+    CrdtVector<std::pair<K, CrdtString>> columns;
+    changes = delete_where([&](const K &record_id, const Record<V> &record) {
+      if (ttl_policy_->record_expired(record, now_ms)) {
+        return true;
+      }
+      for (CrdtString &col_name : ttl_policy_->expired_columns(record, now_ms)) {
+        columns.emplace_back(record_id, std::move(col_name));
+      }
+      return false;
+    });
+    for (const auto &[record_id, col_name] : columns) {
+      for (auto &change : delete_field(record_id, col_name)) {
+        changes.push_back(std::move(change));
+      }
+    }
+    return changes;
+  }
+
+  /// Same as `expire(now_ms)`, at the time of the wall clock set with `set_wall_clock`, or the system clock.
+  CrdtVector<Change<K, V>> expire() {
+    if (wall_clock_) {
+      return expire(wall_clock_());
+    }
+    auto now = std::chrono::system_clock::now().time_since_epoch();
+    return expire(static_cast<uint64_t>(std::chrono::duration_cast<std::chrono::milliseconds>(now).count()));
+  }
+
+  /// Installs a schema, or removes it with std::nullopt. Child CRDTs inherit the parent's schema when created.
+  void set_schema(std::optional<CrdtSchema<V>> schema) { schema_ = std::move(schema); }
+
+  const std::optional<CrdtSchema<V>> &schema() const { return schema_; }
+
+  using ChangeValidator = std::function<bool(const K &record_id, const std::optional<CrdtString> &col_name,
+                                            std::optional<V> &value, const CrdtNodeId &node_id)>;
+
+  /// Installs a hook that checks every incoming change before the merge rule sees it, or removes it with an empty
+  /// function. The validator returns false to drop the change, and may rewrite `value` (e.g. clamp a number) but not
+  /// add or remove it; the record, column and versions cannot be changed. Local operations go through the same validator, so a rejected
+  /// local write throws std::invalid_argument and a rewritten one stores the rewritten value.
+  ///
+  /// Replicas stay convergent as long as all of them install the same validator and it is a pure function of its
+  /// arguments. `col_name` is std::nullopt for record deletions, and `value` for deletions of any kind. Child CRDTs
+  /// inherit the parent's validator when created.
+  void set_validator(ChangeValidator validator) { validator_ = std::move(validator); }
+
+  /// Sets site weights for breaking ties: when two writes of a column (or two deletions of a record) have the same
+  /// col_version, the one from the site with the higher weight wins, e.g. so a server beats mobile clients. Sites
+  /// without a weight count as 0, and ties between equal weights fall back to the merge rule. An empty map restores
+  /// plain merge rule behavior.
+  ///
+  /// Replicas only converge if all of them use the same weights. Child CRDTs inherit the parent's weights when created.
+  void set_site_weights(CrdtMap<CrdtNodeId, int64_t> weights) { site_weights_ = std::move(weights); }
+
+  /// Sets site weights from a priority list, highest priority first; sites not listed lose to every listed site.
+  void set_site_priority(const CrdtVector<CrdtNodeId> &order) {
+    CrdtMap<CrdtNodeId, int64_t> weights;
+    for (size_t i = 0; i < order.size(); ++i) {
+      weights.emplace(order[i], static_cast<int64_t>(order.size() - i));
+    }
+    set_site_weights(std::move(weights));
+  }
+
+  /// Incoming changes dropped for violating a schema with the Quarantine policy, or from unregistered sites in strict
+  /// mode (see `set_strict_sites`), in arrival order.
+  const CrdtVector<Change<K, V>> &quarantined() const { return quarantine_; }
+
+  /// Removes and returns the quarantined changes.
+  CrdtVector<Change<K, V>> take_quarantined() { return std::exchange(quarantine_, {}); }
+
+  /// Requires conflicting writes to `col_name` to be resolved by hand. When an incoming write meets a concurrent local
+  /// write of a different value (one with the same column version, made without seeing it), the incoming write is
+  /// parked in `pending_conflicts` instead of going through the merge rule, and the local value is kept until
+  /// `resolve_conflict` writes a decision. Replicas may disagree on the column until then.
+  ///
+  /// Every replica should protect the same columns. Child CRDTs inherit the parent's protected columns when created.
+  void require_manual_resolution(const CrdtString &col_name) { manual_columns_.insert(col_name); }
+
+  /// Marks `col_name` as local-only: it is stored in the records' fields next to the replicated columns, but has no
+  /// version, is left out of returned changes, `get_changes_since`, batch observers and digests, and incoming changes
+  /// to it are dropped. Useful for per-device UI state, sync flags and cached derived values. Field observers still see
+  /// its writes. Local-only values are lost with their record when it is deleted, and are not kept by change logs, so
+  /// persist them from `get_data` if needed. Every replica should mark the same columns. Child CRDTs inherit the
+  /// parent's local-only columns when created.
+  void add_local_column(const CrdtString &col_name) { local_columns_.insert(col_name); }
+
+  bool is_local_column(const CrdtString &col_name) const { return local_columns_.contains(col_name); }
+
+  /// Installs semantic merge functions for values, or removes them with std::nullopt. Child CRDTs inherit the parent's
+  /// registry when created.
+  void set_merge_registry(std::optional<CrdtMergeRegistry<V>> registry)
+    requires std::equality_comparable<V>
+  {
+    merge_registry_ = std::move(registry);
+  }
+
+  const std::optional<CrdtMergeRegistry<V>> &merge_registry() const { return merge_registry_; }
+
+  /// The parked conflicts that are still unresolved, in arrival order. A conflict is settled once its column is
+  /// written again at a newer version, here or on another replica, or its record is deleted.
+  ///
+  /// Complexity: O(p), where p is the number of parked conflicts
+  CrdtVector<CrdtConflict<K, V>> pending_conflicts() const {
+    CrdtVector<CrdtConflict<K, V>> pending;
+    for (const CrdtConflict<K, V> &conflict : conflicts_) {
+      if (!is_conflict_settled(conflict)) {
+        pending.push_back(conflict);
+      }
+    }
+    return pending;
+  }
+
+  /// Applies a human decision to a conflicted column as a new versioned write, which supersedes the local write and
+  /// every parked one on all replicas, and settles the column's pending conflicts.
+  ///
+  /// # Arguments
+  ///
+  /// * `record_id` - The unique identifier for the record.
+  /// * `col_name` - The conflicted column.
+  /// * `value` - The decided value, or std::nullopt to delete the column.
+  ///
+  /// # Returns
+  ///
+  /// The change writing the decision, or an empty vector if the record is no longer live.
+  ///
+  /// Complexity: O(p), where p is the number of parked conflicts
+  CrdtVector<Change<K, V>> resolve_conflict(const K &record_id, const CrdtString &col_name, std::optional<V> value) {
+    check_writable();
+    CrdtVector<Change<K, V>> changes;
+    if (get_record_ptr(record_id) == nullptr) {
+      prune_conflicts();
+      return changes;
+    }
+
+    // Check the write before raising the local version, so a rejected decision leaves the CRDT unchanged
+    CrdtMap<CrdtString, V> fields;
+    if (value) {
+      fields.emplace(col_name, std::move(*value));
+      check_fields(record_id, fields);
+    } else {
+      check_write_permitted(col_name);
+      validate_deletion(record_id, col_name);
+      if (schema_) {
+        if (auto violation = schema_->violation(col_name, std::nullopt)) {
+          throw std::invalid_argument(*violation);
+        }
+      }
+    }
+
+    // Written one version above every side of the conflict
+    Record<V> &record = get_or_create_record_unchecked(record_id);
+    auto version_it = record.column_versions.find(col_name);
+    if (version_it != record.column_versions.end()) {
+      for (const CrdtConflict<K, V> &conflict : conflicts_) {
+        if (conflict.remote.record_id == record_id && *conflict.remote.col_name == col_name &&
+            !is_conflict_settled(conflict)) {
+          version_it->second.col_version = std::max(version_it->second.col_version, conflict.remote.col_version);
+        }
+      }
+    }
+
+    if (value) {
+      uint64_t db_version = local_tick();
+      note_seen(node_id_, db_version);
+      CrdtVector<ChangeEvent<K, V>> events;
+      write_fields(record_id, std::move(fields), db_version, events, changes, true);
+      notify(events);
+      notify_batch(changes);
+    } else if (version_it != record.column_versions.end()) {
+      changes = delete_field(record_id, col_name);
+    }
+    prune_conflicts();
+    return changes;
+  }
+
+  /// Enables replicated schema migrations, stored as columns of the record `record_id`. Every replica must use the
+  /// same record id, which is reserved: migrations never modify it and it must not be deleted.
+  void enable_migrations(K record_id) {
+    migration_record_ = std::move(record_id);
+    apply_migrations();
+  }
+
+  /// The version of the last applied migration, 0 if none.
+  uint32_t schema_version() const { return schema_version_; }
+
+  /// Records a schema migration and applies it. It replicates like any other change; every replica applies
+  /// migrations in version order as soon as it has received them, and keeps merging changes made under older schema
+  /// versions (writes to renamed columns are moved to the new name, writes to dropped columns are ignored).
+  ///
+  /// Rewriting existing data creates no new versions: renamed columns keep their versions (if both names were written,
+  /// the winner per the merge rule is kept), and added defaults carry version 0 so any real write wins over them.
+  /// Each version must be authored by one node only; if two differ, the one with the smaller column key is applied.
+  ///
+  /// # Returns
+  ///
+  /// The change to replicate.
+  ///
+  /// Throws std::logic_error if migrations are not enabled, and std::invalid_argument if `migration.version` is not
+  /// `schema_version() + 1` or a column name contains the separator character.
+  CrdtVector<Change<K, V>> migrate(const CrdtMigration<V> &migration) {
+    check_writable();
+    if (!migration_record_) {
+      throw std::logic_error("migrations are not enabled");
+    }
+    if (migration.version != schema_version_ + 1) {
+      throw std::invalid_argument("migration version must be " + std::to_string(schema_version_ + 1));
+    }
+    if (migration.column.find(CrdtMigration<V>::SEPARATOR) != CrdtString::npos ||
+        migration.new_name.find(CrdtMigration<V>::SEPARATOR) != CrdtString::npos) {
+      throw std::invalid_argument("column names must not contain the migration separator");
+    }
+
+    CrdtString key = migration.column_key();
+    uint64_t db_version = local_tick();
+    note_seen(node_id_, db_version);
+    Record<V> &record = get_or_create_record_unchecked(*migration_record_);
+    ColumnVersion version = local_version(1, db_version);
+    record.column_versions.insert_or_assign(key, version);
+    if (migration.default_value) {
+      record.fields.insert_or_assign(key, *migration.default_value);
+    }
+    note_change(*migration_record_, db_version);
+    CrdtVector<Change<K, V>> changes;
+    changes.push_back(version_change(*migration_record_, key, migration.default_value, version));
+    apply_migrations();
+    notify_batch(changes);
+    return changes;
+  }
+
+  /// Enables the replicated site registry, stored as columns of the record `record_id`. It lists the sites (nodes) of
+  /// the fleet, so that features working from version vectors know the peer set, and lets departed sites be retired so
+  /// they are no longer waited for. Every replica must use the same record id, which is reserved: it must not be
+  /// written or deleted otherwise.
+  void enable_site_registry(K record_id) { site_registry_ = std::move(record_id); }
+
+  /// Records that `node_id` (this node by default) joined the fleet. Joining again is a no-op.
+  ///
+  /// # Returns
+  ///
+  /// The change to replicate, if any.
+  ///
+  /// Throws std::logic_error if the registry is not enabled, and std::invalid_argument if the site was retired.
+  CrdtVector<Change<K, V>> join_site(std::optional<CrdtNodeId> node_id = std::nullopt) {
+    CrdtSiteInfo info = site(node_id.value_or(node_id_));
+    if (info.status == CrdtSiteStatus::Retired) {
+      throw std::invalid_argument("a retired site cannot rejoin; use a new node id");
+    }
+    if (info.status == CrdtSiteStatus::Active) {
+      return {};
+    }
+    return write_site_marker(CrdtSiteMarker{false, info.node_id, 0});
+  }
+
+  /// Retires `node_id` for good, e.g. when decommissioning a device; it must not write again. Once every write it made
+  /// up to `final_version` has been seen here, `acknowledged_version` stops waiting for it and
+  /// `forget_retired_sites` drops it from `max_seen_versions`.
+  ///
+  /// A site retiring itself should stop writing first; its final version is then its clock. For a site that is gone,
+  /// the final version defaults to the highest of its db_versions this node has seen; writes beyond it that surface
+  /// later still merge, but may revive records whose tombstones were collected in the meantime. Retiring again with a
+  /// higher final version raises it.
+  ///
+  /// # Returns
+  ///
+  /// The change to replicate, if any.
+  ///
+  /// Throws std::logic_error if the registry is not enabled.
+  CrdtVector<Change<K, V>> retire_site(CrdtNodeId node_id, std::optional<uint64_t> final_version = std::nullopt) {
+    if (!final_version) {
+      auto seen = max_seen_versions_.find(node_id);
+      final_version = node_id == node_id_                ? clock_.current_time()
+                      : seen != max_seen_versions_.end() ? seen->second
+                                                         : 0;
+    }
+    CrdtSiteInfo info = site(node_id);
+    if (info.status == CrdtSiteStatus::Retired && info.final_version >= *final_version) {
+      return {};
+    }
+    return write_site_marker(CrdtSiteMarker{true, node_id, *final_version});
+  }
+
+  /// The registry's entry for `node_id`; a retirement outranks any join.
+  ///
+  /// Complexity: O(s), where s is the number of registry entries
+  CrdtSiteInfo site(CrdtNodeId node_id) const {
+    for (const CrdtSiteInfo &info : sites()) {
+      if (info.node_id == node_id) {
+        return info;
+      }
+    }
+    return CrdtSiteInfo{node_id};
+  }
+
+  /// Every site in the registry, sorted by node id.
+  ///
+  /// Complexity: O(s log s), where s is the number of registry entries
+  CrdtVector<CrdtSiteInfo> sites() const {
+    if (!site_registry_) {
+      throw std::logic_error("the site registry is not enabled");
+    }
+    std::map<CrdtNodeId, CrdtSiteInfo> sites;
+    if (const Record<V> *registry = get_record_ptr(*site_registry_)) {
+      for (const auto &[key, version] : registry->column_versions) {
+        if (std::optional<CrdtSiteMarker> marker = CrdtSiteMarker::parse(key)) {
+          CrdtSiteInfo &info = sites.try_emplace(marker->node_id, CrdtSiteInfo{marker->node_id}).first->second;
+          if (marker->retired) {
+            info.status = CrdtSiteStatus::Retired;
+            info.final_version = std::max(info.final_version, marker->final_version);
+          } else if (info.status == CrdtSiteStatus::Unknown) {
+            info.status = CrdtSiteStatus::Active;
+          }
+        }
+      }
+    }
+    CrdtVector<CrdtSiteInfo> result;
+    for (auto &[node_id, info] : sites) {
+      result.push_back(info);
+    }
+    return result;
+  }
+
+  /// The node ids of the active sites, sorted.
+  ///
+  /// Complexity: O(s log s), where s is the number of registry entries
+  CrdtVector<CrdtNodeId> active_sites() const {
+    CrdtVector<CrdtNodeId> active;
+    for (const CrdtSiteInfo &info : sites()) {
+      if (info.status == CrdtSiteStatus::Active) {
+        active.push_back(info.node_id);
+      }
+    }
+    return active;
+  }
+
+  /// The highest local db_version every site has received, for `collect_tombstones` (e.g. from
+  /// CrdtMaintenancePolicy::acknowledged_version): the lowest version the other active sites acknowledged. Retired
+  /// sites are waited for until this node has seen their writes up to their final version, then skipped.
+  ///
+  /// # Arguments
+  ///
+  /// * `acknowledged` - The local db_version each peer acknowledged, e.g. from its sync cursor.
+  ///
+  /// # Returns
+  ///
+  /// The version, or std::nullopt while an active site has acknowledged nothing or a retired site is not drained.
+  ///
+  /// Complexity: O(s log s), where s is the number of registry entries
+  std::optional<uint64_t> acknowledged_version(const CrdtMap<CrdtNodeId, uint64_t> &acknowledged) const {
+    uint64_t version = clock_.current_time();
+    for (const CrdtSiteInfo &info : sites()) {
+      if (info.node_id == node_id_) {
+        continue;
+      }
+      if (info.status == CrdtSiteStatus::Retired) {
+        if (!is_site_drained(info)) {
+          return std::nullopt;
+        }
+        continue;
+      }
+      auto it = acknowledged.find(info.node_id);
+      if (it == acknowledged.end()) {
+        return std::nullopt;
+      }
+      version = std::min(version, it->second);
+    }
+    return version;
+  }
+
+  /// Drops the retired sites whose writes up to their final version have all been seen here from
+  /// `max_seen_versions`, so version vectors stop growing with departed sites.
+  ///
+  /// # Returns
+  ///
+  /// The number of sites forgotten.
+  ///
+  /// Complexity: O(s log s), where s is the number of registry entries
+  size_t forget_retired_sites() {
+    size_t forgotten = 0;
+    for (const CrdtSiteInfo &info : sites()) {
+      if (info.status == CrdtSiteStatus::Retired && max_seen_versions_.find(info.node_id) != max_seen_versions_.end() &&
+          is_site_drained(info)) {
+        forgotten_sites_.insert(info.node_id);
+        max_seen_versions_.erase(info.node_id);
+        ++forgotten;
+      }
+    }
+    return forgotten;
+  }
+
+  /// Enables or disables strict mode, protecting the replica from rogue or misconfigured clients. With the site
+  /// registry enabled, `merge_changes` then quarantines (see `quarantined`) the changes of sites that are not
+  /// registered, and those a retired site made after its final version, counting them in
+  /// `stats().changes_unregistered`; they do not move the clock. Sites must therefore be registered by a registered
+  /// site, except the first one, before their changes are merged; quarantined changes can be merged again afterwards.
+  /// Child CRDTs inherit the parent's mode when created.
+  void set_strict_sites(bool strict) { strict_sites_ = strict; }
+
+  bool strict_sites() const { return strict_sites_; }
+
+  /// Declares columns that must be merged as a group, e.g. `lat` and `lon`, so no record ends up with a mix of values
+  /// from different writes. Every local write to a column of the group re-stamps the group's other columns at the same
+  /// version, and incoming writes are compared against the group's latest write rather than their own column, so a
+  /// group is accepted or rejected as a whole.
+  ///
+  /// Every replica should declare the same groups, before the grouped columns are first written. Child CRDTs inherit
+  /// the parent's groups when created. Throws std::invalid_argument if a column already belongs to another group.
+  void add_column_group(const CrdtVector<CrdtString> &columns) {
+    for (const CrdtString &col_name : columns) {
+      if (column_group_.contains(col_name)) {
+        throw std::invalid_argument("column '" + col_name + "' already belongs to a group");
+      }
+    }
+    for (const CrdtString &col_name : columns) {
+      column_group_.emplace(col_name, column_groups_.size());
+    }
+    column_groups_.push_back(columns);
+  }
+
+  /// Declares `col_name` unique: no two live records should hold the same value in it. Concurrent claims cannot be
+  /// prevented, so they are resolved deterministically from the merged state instead: the earliest claim keeps the
+  /// value, comparing the column's (db_version, node id), then the record id. The others are reported by
+  /// `unique_conflicts` until they are changed, e.g. with `resolve_unique_conflicts`.
+  ///
+  /// Requires std::hash<V>, and `operator<` on K for the final tie-break.
+  ///
+  /// Complexity: O(n), where n is the number of records
+  void add_unique_constraint(const CrdtString &col_name) {
+    static_assert(requires(const V &value) { std::hash<V>{}(value); }, "unique constraints need std::hash<V>");
+    unique_index_[col_name];
+    rebuild_unique_index();
+  }
+
+  /// The record holding `value` in the unique column `col_name`: the winning claim if several records hold it.
+  ///
+  /// Complexity: O(k), where k is the number of records holding the value
+  std::optional<K> unique_owner(const CrdtString &col_name, const V &value) const {
+    CrdtVector<const K *> claims = unique_claims(col_name, value);
+    return claims.empty() ? std::nullopt : std::optional<K>(*claims.front());
+  }
+
+  /// Records that lost a claim on the unique column `col_name`.
+  ///
+  /// Complexity: O(n), where n is the number of records holding a value of the column
+  CrdtVector<K> unique_conflicts(const CrdtString &col_name) const {
+    CrdtVector<K> losers;
+    auto index = unique_index_.find(col_name);
+    if (index == unique_index_.end()) {
+      return losers;
+    }
+    for (const auto &[hash, records] : index->second) {
+      if (records.size() < 2) {
+        continue;
+      }
+      CrdtSet<K> seen;
+      for (const K &record_id : records) {
+        if (seen.contains(record_id)) {
+          continue;
+        }
+        CrdtVector<const K *> claims = unique_claims(col_name, *field_ptr(*get_record_ptr(record_id), col_name));
+        for (size_t i = 0; i < claims.size(); ++i) {
+          seen.insert(*claims[i]);
+          if (i > 0) {
+            losers.push_back(*claims[i]);
+          }
+        }
+      }
+    }
+    return losers;
+  }
+
+  /// Changes the value of every losing claim on `col_name` that this node made, to `rename(value, record_id)` (e.g.
+  /// "alice" -> "alice-2"). Only the node whose write lost changes it, so replicas calling this after each merge never
+  /// race on the fix.
+  ///
+  /// # Returns
+  ///
+  /// The changes to replicate.
+  template <typename Rename>
+    requires std::invocable<Rename &, const V &, const K &>
+  CrdtVector<Change<K, V>> resolve_unique_conflicts(const CrdtString &col_name, Rename &&rename) {
+    CrdtVector<Change<K, V>> changes;
+    for (const K &record_id : unique_conflicts(col_name)) {
+      const Record<V> *record = get_record_ptr(record_id);
+      if (record->column_versions.at(col_name).node_id != node_id_) {
+        continue;
+      }
+      V renamed = rename(*field_ptr(*record, col_name), record_id);
+      CrdtMap<CrdtString, V> fields;
+      fields.emplace(col_name, std::move(renamed));
+      auto written = insert_or_update(record_id, std::move(fields));
+      changes.insert(changes.end(), std::make_move_iterator(written.begin()), std::make_move_iterator(written.end()));
+    }
+    return changes;
+  }
+
+  /// Makes this CRDT a read-only replica, for caches and followers that must never create versions under their own
+  /// node id. A read-only CRDT still merges changes, but local operations throw std::logic_error.
+  void set_read_only(bool read_only) { read_only_ = read_only; }
+
+  bool is_read_only() const { return read_only_; }
+
+  using ChangeObserver = std::function<void(const ChangeEvent<K, V> &)>;
+
+  /// Registers a callback invoked whenever a local operation or a merge modifies a record.
+  ///
+  /// Callbacks run synchronously once the operation has been applied, in registration order. They may read the CRDT
+  /// but must not modify it. Observers are not copied along with the CRDT, and loading changes through the constructor
+  /// does not notify.
+  ///
+  /// # Returns
+  ///
+  /// An id to pass to `remove_on_change`.
+  size_t on_change(ChangeObserver observer) {
+    size_t id = next_observer_id_++;
+    observers_.emplace_back(id, std::move(observer));
+    return id;
+  }
+
+  using ChangeBatchObserver = std::function<void(const CrdtVector<Change<K, V>> &)>;
+
+  /// Registers a callback invoked once per local operation or merge with the changes it applied, in the form
+  /// `get_changes_since` would return them. Merges that apply nothing do not invoke it. The same rules as `on_change`
+  /// apply.
+  ///
+  /// # Returns
+  ///
+  /// An id to pass to `remove_on_change`.
+  size_t on_change_batch(ChangeBatchObserver observer) {
+    size_t id = next_observer_id_++;
+    batch_observers_.emplace_back(id, std::move(observer));
+    return id;
+  }
+
+  /// Unregisters a callback added with `on_change` or `on_change_batch`. Returns false if the id is unknown.
+  bool remove_on_change(size_t id) {
+    auto matches = [&](const auto &entry) { return entry.first == id; };
+    return std::erase_if(observers_, matches) + std::erase_if(batch_observers_, matches) > 0;
+  }
+
+  /// Keeps the last `depth` values of every column, including the current one, for `history`. 0 (the default) disables
+  /// history; lowering the depth trims what is already kept.
+  ///
+  /// Only writes made after enabling are recorded. Values loaded through the constructor are not.
+  void set_history_depth(size_t depth) {
+    history_depth_ = depth;
+    if (depth == 0) {
+      history_.clear();
+      return;
+    }
+    for (auto &[record_id, columns] : history_) {
+      for (auto &[col_name, entries] : columns) {
+        while (entries.size() > depth) {
+          entries.pop_front();
+        }
+      }
+    }
+  }
+
+  size_t history_depth() const { return history_depth_; }
+
+  /// Returns the kept values of a column, oldest first; the last entry is the current value.
+  ///
+  /// # Arguments
+  ///
+  /// * `record_id` - The unique identifier for the record.
+  /// * `col_name` - The column name.
+  ///
+  /// Complexity: O(h), where h is the history depth
+  CrdtVector<ColumnHistoryEntry<V>> history(const K &record_id, const CrdtString &col_name) const {
+    auto record_it = history_.find(record_id);
+    if (record_it == history_.end()) {
+      return {};
+    }
+    auto col_it = record_it->second.find(col_name);
+    if (col_it == record_it->second.end()) {
+      return {};
+    }
+    return CrdtVector<ColumnHistoryEntry<V>>(col_it->second.begin(), col_it->second.end());
+  }
+
+  /// Reconstructs a record as it was when this node's clock was at `db_version`, using the current state and the
+  /// column history (see `set_history_depth`).
+  ///
+  /// Columns whose value at that version is no longer kept in the history are omitted, so keep the history deep enough
+  /// for the window you want to query.
   ///
   /// # Returns
   ///
-  /// A vector of `Change` objects representing the changes made, or void if ReturnChanges is false.
+  /// The record, or std::nullopt if it did not exist or was already deleted at that version.
   ///
-  /// Complexity: O(m), where m is the number of fields in the input
-  template <bool ReturnChanges = true>
-  constexpr std::conditional_t<ReturnChanges, CrdtVector<Change<K, V>>, void> insert_or_update(const K &record_id,
-                                                                                               CrdtMap<CrdtString, V> &&fields) {
+  /// Complexity: O(c * h), where c is the number of columns and h is the history depth
+  std::optional<Record<V>> as_of(const K &record_id, uint64_t db_version) const {
+    if (const CrdtTombstone *tombstone = find_tombstone(record_id)) {
+      return tombstone_as_of(record_id, *tombstone, db_version);
+    }
+    const Record<V> *record = get_record_ptr(record_id);
+    if (record == nullptr) {
+      return std::nullopt;
+    }
+    return record_as_of(record_id, *record, db_version);
+  }
+
+  /// Reconstructs every record that was live when this node's clock was at `db_version`. See `as_of(record_id, ...)`.
+  ///
+  /// Complexity: O(n * c * h), where n is the number of records
+  CrdtMap<K, Record<V>> as_of(uint64_t db_version) const {
+    CrdtMap<K, Record<V>> result;
+    for_each_record([&](const K &record_id, const Record<V> &record) {
+      if (auto past = record_as_of(record_id, record, db_version)) {
+        result.emplace(record_id, std::move(*past));
+      }
+    });
+    for_each_tombstone([&](const K &record_id, const CrdtTombstone &tombstone) {
+      if (auto past = tombstone_as_of(record_id, tombstone, db_version)) {
+        result.emplace(record_id, std::move(*past));
+      }
+    });
+    return result;
+  }
+
+  /// The highest db_version seen from each node, through local operations, merges, or loading.
+  ///
+  /// This is a hint, e.g. for causal ordering, and not a sync watermark: db_versions skip values, and a node's lower
+  /// changes may still be missing after a partial or out-of-order delivery (a `get_changes_for` batch, an interrupted
+  /// merge), with nothing to tell them apart from values that were never used. The per-node sequence numbers
+  /// (ColumnVersion::seq) make no watermark either: sync sends the current state, so a write overwritten before it was
+  /// sent never arrives, and a receiver could never close the gap it leaves. Use `is_applied` to check a change, and
+  /// `changes_missing_for` or a MerkleTree to find what a peer lacks.
+  const CrdtMap<CrdtNodeId, uint64_t> &max_seen_versions() const { return max_seen_versions_; }
+
+  /// True if the state already reflects `change` or something that supersedes it, so merging it would be a no-op: the
+  /// stored version of its column (or record) is the same write, by node and sequence number, or wins over it.
+  ///
+  /// This is the exact per-change answer a sender needs to retransmit safely; there is no per-node watermark of applied
+  /// changes (see `max_seen_versions`).
+  ///
+  /// Complexity: O(1) average case
+  bool is_applied(const Change<K, V> &change) const {
+    const Record<V> *record = get_record_ptr(change.record_id);
+    const ColumnVersion *lifecycle = lifecycle_version(change.record_id, record);
+    uint64_t incarnation = lifecycle ? lifecycle->col_version : 0;
+    if (change.col_name && column_incarnation(change.col_version) != incarnation) {
+      return column_incarnation(change.col_version) < incarnation;
+    }
+    const ColumnVersion *local_version = lifecycle;
+    if (change.col_name) {
+      local_version = nullptr;
+      if (record != nullptr) {
+        auto col_it = record->column_versions.find(*change.col_name);
+        if (col_it != record->column_versions.end()) {
+          local_version = &col_it->second;
+        }
+        local_version = compared_version(change.record_id, *record, *change.col_name, local_version, change);
+      }
+    }
+    if (local_version == nullptr) {
+      return false;
+    }
+    if (is_same_write(*local_version, change.node_id, change.seq)) {
+      return true;
+    }
+    return !remote_wins(version_change(change.record_id, change.col_name, std::nullopt, *local_version), change);
+  }
+
+  /// Takes a consistent, immutable snapshot that readers can use from other threads while this CRDT keeps changing.
+  ///
+  /// The current records are moved into a shared, frozen layer and this CRDT continues as a child on top of it, so the
+  /// snapshot costs O(1) and a record is only copied when it is next written (copy-on-write). Taking a snapshot with
+  /// no writes since the previous one returns the same layer. Every few snapshots the layers are folded back into one,
+  /// which costs O(n) but keeps lookups from walking a long chain.
+  ///
+  /// Not supported on a CRDT created with a parent (throws std::logic_error), since that parent is used by `revert`.
+  /// Must not run concurrently with other calls on this CRDT; the returned snapshot is safe to read concurrently.
+  std::shared_ptr<const CRDT> snapshot() {
+    if (parent_ && !parent_->snapshot_layer_) {
+      throw std::logic_error("snapshots are not supported on child CRDTs");
+    }
+    if (data_.empty() && tombstones_.empty() && parent_) {
+      return parent_;
+    }
+
+    auto layer = std::make_shared<CRDT>(node_id_);
+    layer->snapshot_layer_ = true;
+    layer->clock_ = clock_;
+    layer->data_ = std::move(data_);
+    layer->tombstones_ = std::move(tombstones_);
+    layer->change_index_ = std::move(change_index_);
+    layer->change_index_entries_ = change_index_entries_;
+    layer->live_count_ = live_count_;
+    layer->tombstone_count_ = tombstone_count_;
+    layer->parent_ = std::move(parent_);
+    data_ = CrdtMap<K, Record<V>>();
+    tombstones_ = CrdtMap<K, CrdtTombstone>();
+    change_index_ = std::map<uint64_t, CrdtVector<K>>();
+    change_index_entries_ = 0;
+
+    if (++snapshot_depth_ > MAX_SNAPSHOT_LAYERS) {
+      auto folded = std::make_shared<CRDT>(node_id_);
+      folded->snapshot_layer_ = true;
+      folded->clock_ = clock_;
+      folded->live_count_ = live_count_;
+      folded->tombstone_count_ = tombstone_count_;
+      layer->for_each_record([&](const K &record_id, const Record<V> &record) { folded->data_.emplace(record_id, record); });
+      layer->for_each_tombstone(
+          [&](const K &record_id, const CrdtTombstone &tombstone) { folded->tombstones_.emplace(record_id, tombstone); });
+      folded->rebuild_change_index();
+      layer = std::move(folded);
+      snapshot_depth_ = 1;
+    }
+
+    parent_ = layer;
+    return layer;
+  }
+
+  // Add this constructor to the CRDT class
+  CRDT(const CRDT &other)
+      : std::enable_shared_from_this<CRDT>(), node_id_(other.node_id_), clock_(other.clock_), seq_(other.seq_),
+        data_(other.data_),
+        tombstones_(other.tombstones_), parent_(other.parent_), base_version_(other.base_version_),
+        merge_rule_(other.merge_rule_), change_comparator_(other.change_comparator_), sort_func_(other.sort_func_),
+        history_(other.history_), history_depth_(other.history_depth_), max_seen_versions_(other.max_seen_versions_),
+        read_only_(other.read_only_), quarantine_(other.quarantine_), conflicts_(other.conflicts_),
+        forgotten_sites_(other.forgotten_sites_), snapshot_depth_(other.snapshot_depth_),
+        change_index_(other.change_index_), change_index_entries_(other.change_index_entries_),
+        live_count_(other.live_count_), tombstone_count_(other.tombstone_count_) {
+    // Note: This creates a shallow copy of the parent pointer
+    copy_config(other);
+  }
+
+  CRDT &operator=(const CRDT &other) {
+    if (this != &other) {
+      node_id_ = other.node_id_;
+      clock_ = other.clock_;
+      seq_ = other.seq_;
+      data_ = other.data_;
+      tombstones_ = other.tombstones_;
+      parent_ = other.parent_;
+      base_version_ = other.base_version_;
+      merge_rule_ = other.merge_rule_;
+      change_comparator_ = other.change_comparator_;
+      sort_func_ = other.sort_func_;
+      history_ = other.history_;
+      history_depth_ = other.history_depth_;
+      max_seen_versions_ = other.max_seen_versions_;
+      read_only_ = other.read_only_;
+      quarantine_ = other.quarantine_;
+      conflicts_ = other.conflicts_;
+      forgotten_sites_ = other.forgotten_sites_;
+      snapshot_depth_ = other.snapshot_depth_;
+      change_index_ = other.change_index_;
+      change_index_entries_ = other.change_index_entries_;
+      live_count_ = other.live_count_;
+      tombstone_count_ = other.tombstone_count_;
+      copy_config(other);
+    }
+    return *this;
+  }
+
+private:
+  CrdtNodeId node_id_;
+  LogicalClock clock_;
+  uint64_t seq_ = 0; // sequence number of the last local operation, see ColumnVersion::seq
+  CrdtMap<K, Record<V>> data_;
+  CrdtMap<K, CrdtTombstone> tombstones_;
+
+  // our clock won't be shared with the parent
+  // we optionally allow to merge from the parent or push to the parent
+  std::shared_ptr<CRDT<K, V, MergeRuleType, ChangeComparatorType, SortFunctionType>> parent_;
+  uint64_t base_version_; // Tracks the parent’s db_version at the time of child creation
+  MergeRuleType merge_rule_;
+  ChangeComparatorType change_comparator_;
+  SortFunctionType sort_func_;
+  CrdtVector<std::pair<size_t, ChangeObserver>> observers_;
+  CrdtVector<std::pair<size_t, ChangeBatchObserver>> batch_observers_;
+  size_t next_observer_id_ = 0;
+  CrdtMap<K, CrdtMap<CrdtString, std::deque<ColumnHistoryEntry<V>>>> history_;
+  size_t history_depth_ = 0;
+  CrdtMap<CrdtNodeId, uint64_t> max_seen_versions_;
+  std::optional<CrdtWritePolicy> write_policy_;
+  std::optional<CrdtTtlPolicy> ttl_policy_;
+  CrdtEpochPolicy epoch_policy_ = CrdtEpochPolicy::Reject;
+  bool read_only_ = false;
+  std::optional<CrdtSchema<V>> schema_;
+  ChangeValidator validator_;
+  CrdtVector<Change<K, V>> quarantine_;
+  CrdtSet<CrdtString> manual_columns_;
+  CrdtSet<CrdtString> local_columns_; // stored in record fields only, without a version
+  std::optional<CrdtMergeRegistry<V>> merge_registry_;
+  CrdtVector<CrdtConflict<K, V>> conflicts_;
+  CrdtMap<CrdtNodeId, int64_t> site_weights_;
+  CrdtVector<CrdtVector<CrdtString>> column_groups_;
+  CrdtMap<CrdtString, size_t> column_group_; // column -> index into column_groups_
+  std::optional<K> migration_record_;
+  std::optional<K> site_registry_;
+  CrdtSet<CrdtNodeId> forgotten_sites_; // retired sites dropped from max_seen_versions_ by forget_retired_sites
+  bool strict_sites_ = false;
+  uint32_t schema_version_ = 0;
+  CrdtMap<CrdtString, std::optional<CrdtString>> column_renames_; // old name -> current name, std::nullopt if dropped
+  CrdtMap<CrdtString, V> migration_defaults_;                     // added columns and their defaults
+  CrdtMap<CrdtString, CrdtMap<size_t, CrdtSet<K>>> unique_index_;  // unique column -> value hash -> records
+  WallClock wall_clock_;
+  std::optional<CrdtClockGuard> clock_guard_;
+  ClockAnomalyHandler clock_anomaly_handler_;
+  CrdtStats stats_;
+  static constexpr size_t MAX_SNAPSHOT_LAYERS = 8;
+  static constexpr size_t PARALLEL_MERGE_MIN_PARTITION = 4096; // smaller partitions are not worth a thread
+  bool snapshot_layer_ = false; // this is a frozen layer created by snapshot()
+  size_t snapshot_depth_ = 0;   // number of snapshot layers below this CRDT
+  std::map<uint64_t, CrdtVector<K>> change_index_; // local db_version -> records written at it, possibly since rewritten
+  size_t change_index_entries_ = 0;
+  // Live records and tombstones, including inherited ones. Exact unless the parent can change underneath this CRDT
+  size_t live_count_ = 0;
+  size_t tombstone_count_ = 0;
+
+  // Node and bucket memory of a map or set, excluding heap memory owned by its elements
+  template <typename Container> static size_t container_overhead(const Container &container) {
+    size_t per_node = sizeof(typename Container::value_type) + 2 * sizeof(void *);
+    if constexpr (requires { container.bucket_count(); }) {
+      return container.size() * per_node + container.bucket_count() * sizeof(void *);
+    } else {
+      // Tree nodes also hold a parent pointer and a color
+      return container.size() * (per_node + 2 * sizeof(void *));
+    }
+  }
+
+  CrdtVector<Change<K, V>> write_site_marker(const CrdtSiteMarker &marker) {
+    check_writable();
+    if (!site_registry_) {
+      throw std::logic_error("the site registry is not enabled");
+    }
+    CrdtString key = marker.column_key();
+    uint64_t db_version = local_tick();
+    note_seen(node_id_, db_version);
+    Record<V> &record = get_or_create_record_unchecked(*site_registry_);
+    ColumnVersion version = local_version(1, db_version);
+    record.column_versions.insert_or_assign(key, version);
+    note_change(*site_registry_, db_version);
     CrdtVector<Change<K, V>> changes;
-    uint64_t db_version = clock_.tick();
+    changes.push_back(version_change(*site_registry_, key, std::nullopt, version));
+    notify_batch(changes);
+    return changes;
+  }
+
+  // Whether this node has seen the writes of a retired site up to its final version
+  bool is_site_drained(const CrdtSiteInfo &info) const {
+    if (forgotten_sites_.contains(info.node_id)) {
+      return true;
+    }
+    auto seen = max_seen_versions_.find(info.node_id);
+    return info.final_version == 0 || (seen != max_seen_versions_.end() && seen->second >= info.final_version);
+  }
+
+  // Throws CrdtInvariantError listing the violated invariants, when CRDT_CHECK_INVARIANTS is defined
+  void assert_invariants([[maybe_unused]] const char *operation) const {
+#ifdef CRDT_CHECK_INVARIANTS
+    CrdtVector<CrdtInvariantViolation<K>> violations = check_invariants();
+    if (!violations.empty()) {
+      std::ostringstream message;
+      message << "invariants violated after " << operation << " on node " << node_id_ << ":";
+      for (const auto &violation : violations) {
+        message << "\n  " << violation;
+      }
+      throw CrdtInvariantError(message.str());
+    }
+#endif
+  }
+
+  // Ticks the clock for a local operation and assigns the operation its sequence number, reporting an exhausted clock
+  // before the tick throws
+  uint64_t local_tick() {
+    if (!LogicalClock::can_advance(clock_.current_time())) {
+      report_clock_anomaly({CrdtClockAnomalyKind::Exhausted, std::nullopt, clock_.current_time(), 0, false});
+    }
+    uint64_t time = clock_.tick();
+    ++seq_;
+    return time;
+  }
+
+  // The version of a write by the local operation that ticked the clock to `db_version`
+  ColumnVersion local_version(uint64_t col_version, uint64_t db_version) const {
+    return ColumnVersion(col_version, db_version, node_id_, db_version, seq_);
+  }
+
+  // The change replicating `version`, of the column `col_name` or, without it, of the record's lifecycle
+  static Change<K, V> version_change(const K &record_id, std::optional<CrdtString> col_name, std::optional<V> value,
+                                     const ColumnVersion &version) {
+    Change<K, V> change(record_id, std::move(col_name), std::move(value), version.col_version, version.db_version,
+                        version.node_id, version.local_db_version);
+    change.seq = version.seq;
+    return change;
+  }
+
+  // Checks a received db_version against the end of the clock's epoch and the clock guard, reporting an anomaly.
+  // Returns whether the change must be dropped
+  bool clock_anomaly(CrdtNodeId node_id, uint64_t received_time) {
+    uint64_t local_time = clock_.current_time();
+    if (!LogicalClock::can_advance(std::max(local_time, received_time))) {
+      report_clock_anomaly({CrdtClockAnomalyKind::Exhausted, node_id, local_time, received_time, true});
+      return true;
+    }
+    if (clock_guard_ && clock_guard_->max_jump > 0 && received_time > local_time &&
+        received_time - local_time > clock_guard_->max_jump) {
+      bool reject = clock_guard_->action == CrdtClockAnomalyAction::Reject;
+      report_clock_anomaly({CrdtClockAnomalyKind::FarAhead, node_id, local_time, received_time, reject});
+      return reject;
+    }
+    return false;
+  }
+
+  void report_clock_anomaly(const CrdtClockAnomaly &anomaly) {
+    ++stats_.clock_anomalies;
+    if (clock_anomaly_handler_) {
+      clock_anomaly_handler_(anomaly);
+    }
+  }
+
+  void note_seen(CrdtNodeId node_id, uint64_t db_version) {
+    if (db_version == 0) {
+      return; // a placeholder, which no node wrote
+    }
+    uint64_t &seen = max_seen_versions_[node_id];
+    seen = std::max(seen, db_version);
+  }
+
+  // Records that `record_id` was written at `local_db_version`, for get_changes_since. Call after the write, since the
+  // index may be rebuilt from the current state once stale entries (of records written again since) pile up.
+  void note_change(const K &record_id, uint64_t local_db_version) {
+    if (local_db_version == 0) {
+      return;
+    }
+    CrdtVector<K> &records = change_index_[local_db_version];
+    if (!records.empty() && records.back() == record_id) {
+      return;
+    }
+    records.push_back(record_id);
+    if (++change_index_entries_ > 2 * (data_.size() + tombstones_.size()) + 1024) {
+      rebuild_change_index();
+    }
+  }
+
+  void rebuild_change_index() {
+    change_index_.clear();
+    change_index_entries_ = 0;
+    auto add = [&](const K &record_id, uint64_t local_db_version) {
+      CrdtVector<K> &records = change_index_[local_db_version];
+      if (records.empty() || records.back() != record_id) {
+        records.push_back(record_id);
+        ++change_index_entries_;
+      }
+    };
+    for (const auto &[record_id, record] : data_) {
+      for_each_version(record, [&](const ColumnVersion &version) {
+        if (version.local_db_version > 0) {
+          add(record_id, version.local_db_version);
+        }
+      });
+    }
+    for (const auto &[record_id, tombstone] : tombstones_) {
+      add(record_id, tombstone.version.local_db_version);
+    }
+  }
+
+  // A deleted record's columns are only known from the history
+  std::optional<Record<V>> tombstone_as_of(const K &record_id, const CrdtTombstone &tombstone, uint64_t db_version) const {
+    if (tombstone.version.local_db_version <= db_version) {
+      return std::nullopt;
+    }
+    return record_as_of(record_id, Record<V>(), db_version);
+  }
+
+  std::optional<Record<V>> record_as_of(const K &record_id, const Record<V> &record, uint64_t db_version) const {
+    auto history_it = history_.find(record_id);
+    Record<V> past;
+    auto restore_column = [&](const CrdtString &col_name) {
+      auto version_it = record.column_versions.find(col_name);
+      if (version_it != record.column_versions.end() && version_it->second.local_db_version <= db_version) {
+        // Unchanged since then
+        past.column_versions.emplace(col_name, version_it->second);
+        if (auto value = field_value(record, col_name)) {
+          past.fields.emplace(col_name, std::move(*value));
+        }
+        return;
+      }
+      if (history_it == history_.end()) {
+        return;
+      }
+      auto entries_it = history_it->second.find(col_name);
+      if (entries_it == history_it->second.end()) {
+        return;
+      }
+      const auto &entries = entries_it->second;
+      for (auto it = entries.rbegin(); it != entries.rend(); ++it) {
+        if (it->version.local_db_version <= db_version) {
+          past.column_versions.emplace(col_name, it->version);
+          if (it->value) {
+            past.fields.emplace(col_name, *it->value);
+          }
+          return;
+        }
+      }
+    };
+
+    for (const auto &[col_name, version] : record.column_versions) {
+      restore_column(col_name);
+    }
+    if (history_it != history_.end()) {
+      // Columns removed since then are only known from the history
+      for (const auto &[col_name, entries] : history_it->second) {
+        if (!record.column_versions.contains(col_name)) {
+          restore_column(col_name);
+        }
+      }
+    }
+
+    if (past.column_versions.empty()) {
+      return std::nullopt;
+    }
+    return past;
+  }
+
+  void record_history(const K &record_id, const CrdtString &col_name, const std::optional<V> &value,
+                      const ColumnVersion &version) {
+    auto &entries = history_[record_id][col_name];
+    entries.push_back({value, version});
+    if (entries.size() > history_depth_) {
+      entries.pop_front();
+    }
+  }
+
+  static std::optional<V> field_value(const Record<V> &record, const CrdtString &col_name) {
+    auto it = record.fields.find(col_name);
+    return it != record.fields.end() ? std::optional<V>(it->second) : std::nullopt;
+  }
+
+  // Must be called before the record is replaced by its tombstone
+  void collect_deletion_events(const K &record_id, CrdtNodeId node_id, uint64_t db_version, bool is_remote,
+                               CrdtVector<ChangeEvent<K, V>> &events) const {
+    if (const Record<V> *record = get_record_ptr(record_id)) {
+      for (const auto &[col_name, value] : record->fields) {
+        events.push_back({record_id, col_name, value, std::nullopt, node_id, db_version, is_remote});
+      }
+    }
+    events.push_back({record_id, std::nullopt, std::nullopt, std::nullopt, node_id, db_version, is_remote});
+  }
 
-    // Check if the record is tombstoned
-    if (is_record_tombstoned(record_id)) {
-      if constexpr (ReturnChanges) {
-        return changes;
-      } else {
-        return;
+  void notify(const CrdtVector<ChangeEvent<K, V>> &events) const {
+    for (const auto &event : events) {
+      for (const auto &[id, observer] : observers_) {
+        observer(event);
       }
     }
+  }
 
-    Record<V> &record = get_or_create_record_unchecked(record_id);
+  // Validates a local write, and fills in defaults when it creates a record
+  void apply_schema(const K &record_id, CrdtMap<CrdtString, V> &fields) const {
+    for (const auto &[col_name, value] : fields) {
+      if (auto violation = schema_->violation(col_name, value)) {
+        throw std::invalid_argument(*violation);
+      }
+    }
+    if (const Record<V> *record = get_record_ptr(record_id); record && has_columns(*record)) {
+      return;
+    }
+    for (const auto &[col_name, column] : schema_->columns()) {
+      if (fields.find(col_name) != fields.end()) {
+        continue;
+      }
+      if (column.default_value) {
+        fields.emplace(col_name, *column.default_value);
+      } else if (!column.nullable) {
+        throw std::invalid_argument("missing value for column '" + col_name + "'");
+      }
+    }
+  }
 
-    for (auto &[col_name, value] : fields) {
-      uint64_t col_version;
-      auto col_it = record.column_versions.find(col_name);
-      if (col_it != record.column_versions.end()) {
-        col_version = ++col_it->second.col_version;
-        col_it->second.db_version = db_version;
-        col_it->second.node_id = node_id_;
-      } else {
-        col_version = 1;
-        record.column_versions.emplace(col_name, ColumnVersion(col_version, db_version, node_id_, db_version));
+  // Applies every received migration following schema_version_, in order
+  void apply_migrations() {
+    if (!migration_record_) {
+      return;
+    }
+    const Record<V> *log = get_record_ptr(*migration_record_);
+    while (log != nullptr) {
+      std::optional<CrdtMigration<V>> next;
+      const CrdtString *next_key = nullptr;
+      for (const auto &[key, version] : log->column_versions) {
+        auto migration = CrdtMigration<V>::parse(key, field_value(*log, key));
+        if (migration && migration->version == schema_version_ + 1 && (next_key == nullptr || key < *next_key)) {
+          next = std::move(migration);
+          next_key = &key;
+        }
+      }
+      if (!next) {
+        return;
       }
+      apply_migration(*next);
+      rebuild_unique_index();
+      schema_version_ = next->version;
+      log = get_record_ptr(*migration_record_);
+    }
+  }
 
-      if constexpr (ReturnChanges) {
-        record.fields[col_name] = value;
-        changes.emplace_back(
-            Change<K, V>(record_id, std::move(col_name), std::move(value), col_version, db_version, node_id_, db_version));
+  void apply_migration(const CrdtMigration<V> &migration) {
+    using Kind = typename CrdtMigration<V>::Kind;
+    CrdtVector<K> affected;
+    for_each_record([&](const K &record_id, const Record<V> &record) {
+      if (record_id == *migration_record_ || is_record_tombstoned(record_id)) {
+        return;
+      }
+      bool has_column = record.column_versions.find(migration.column) != record.column_versions.end();
+      if (has_column != (migration.kind == Kind::AddColumn)) {
+        affected.push_back(record_id);
+      }
+    });
+
+    for (const K &record_id : affected) {
+      Record<V> &record = get_or_create_record_unchecked(record_id);
+      if (migration.kind == Kind::AddColumn) {
+        record.column_versions.emplace(migration.column, ColumnVersion(0, 0, CrdtNodeId{}, 0));
+        record.fields.emplace(migration.column, *migration.default_value);
+        continue;
+      }
+      auto old_version = record.column_versions.find(migration.column);
+      std::optional<V> old_value = field_value(record, migration.column);
+      ColumnVersion version = old_version->second;
+      record.column_versions.erase(old_version);
+      record.fields.erase(migration.column);
+      if (migration.kind == Kind::DropColumn) {
+        continue;
+      }
+      auto existing = record.column_versions.find(migration.new_name);
+      if (existing != record.column_versions.end()) {
+        Change<K, V> current(record_id, migration.new_name, std::nullopt, existing->second.col_version,
+                             existing->second.db_version, existing->second.node_id);
+        Change<K, V> renamed(record_id, migration.new_name, std::nullopt, version.col_version, version.db_version,
+                             version.node_id);
+        if (!remote_wins(current, renamed)) {
+          continue;
+        }
+      }
+      record.column_versions.insert_or_assign(migration.new_name, version);
+      if (old_value) {
+        record.fields.insert_or_assign(migration.new_name, std::move(*old_value));
       } else {
-        record.fields[std::move(col_name)] = std::move(value);
+        record.fields.erase(migration.new_name);
       }
     }
 
-    if constexpr (ReturnChanges) {
-      return changes;
+    // Route writes made under older names, and keep defaults applying to the column's current name
+    std::optional<CrdtString> target;
+    if (migration.kind == Kind::RenameColumn) {
+      target = migration.new_name;
+    }
+    if (migration.kind == Kind::AddColumn) {
+      column_renames_.erase(migration.column);
+      migration_defaults_.insert_or_assign(migration.column, *migration.default_value);
+      return;
+    }
+    for (auto &[old_name, current] : column_renames_) {
+      if (current == migration.column) {
+        current = target;
+      }
+    }
+    column_renames_.insert_or_assign(migration.column, target);
+    auto default_value = migration_defaults_.find(migration.column);
+    if (default_value != migration_defaults_.end()) {
+      if (target) {
+        migration_defaults_.insert_or_assign(*target, std::move(default_value->second));
+      }
+      migration_defaults_.erase(migration.column);
     }
   }
 
-  /// Deletes a record by marking it as tombstoned.
-  ///
-  /// # Arguments
-  ///
-  /// * `record_id` - The unique identifier for the record.
-  ///
-  /// # Returns
-  ///
-  /// A vector of `Change` objects representing the deletion, or void if ReturnChanges is false.
-  ///
-  /// Complexity: O(1)
-  template <bool ReturnChanges = true>
-  std::conditional_t<ReturnChanges, CrdtVector<Change<K, V>>, void> delete_record(const K &record_id) {
-    CrdtVector<Change<K, V>> changes;
-    if (is_record_tombstoned(record_id)) {
-      if constexpr (ReturnChanges) {
-        return changes;
-      } else {
-        return;
+  // New records get the defaults of added columns, so replicas agree whichever applied the migration first
+  void fill_migration_defaults(const K &record_id, Record<V> &record) {
+    if (record_id == migration_record_) {
+      return;
+    }
+    for (const auto &[col_name, value] : migration_defaults_) {
+      if (record.column_versions.emplace(col_name, ColumnVersion(0, 0, CrdtNodeId{}, 0)).second) {
+        index_field(record_id, col_name, nullptr, &value);
+        record.fields.emplace(col_name, value);
       }
     }
+  }
 
-    uint64_t db_version = clock_.tick();
+  void validate_deletion(const K &record_id, const std::optional<CrdtString> &col_name) const {
+    if (!validator_) {
+      return;
+    }
+    std::optional<V> value;
+    if (!validator_(record_id, col_name, value, node_id_) || value) {
+      throw std::invalid_argument(col_name ? "deletion of column '" + *col_name + "' rejected by the validator"
+                                           : CrdtString("record deletion rejected by the validator"));
+    }
+  }
 
-    // Mark as tombstone and remove data
-    tombstones_.emplace(record_id);
-    data_.erase(record_id);
+  // A record's incarnation is the version of its last deletion or revival: 0 for a record never deleted, odd while
+  // deleted, even once revived. A revived record keeps it as its revival version. Column versions hold the
+  // incarnation they were written in above the lower 32 bits.
+  static uint64_t incarnation_of(const Record<V> &record) { return record.revival ? record.revival->col_version : 0; }
 
-    // Insert deletion clock info
-    CrdtMap<CrdtString, ColumnVersion> deletion_clock;
-    deletion_clock.emplace("__deleted__", ColumnVersion(1, db_version, node_id_, db_version));
+  static constexpr uint64_t column_incarnation(uint64_t col_version) { return col_version >> 32; }
 
-    // Store deletion info in the data map
-    data_.emplace(record_id, Record<V>(CrdtMap<CrdtString, V>(), std::move(deletion_clock)));
+  static constexpr uint64_t first_column_version(uint64_t incarnation) { return (incarnation << 32) + 1; }
 
-    if constexpr (ReturnChanges) {
-      changes.emplace_back(Change<K, V>(record_id, std::nullopt, std::nullopt, 1, db_version, node_id_, db_version));
-      return changes;
+  // The version of the next write to a column written at `col_version`. Throws std::overflow_error once the write count
+  // in the lower 32 bits is exhausted, since carrying into the incarnation would make peers revive the record
+  static uint64_t next_column_version(uint64_t col_version) {
+    if ((col_version & 0xFFFFFFFFULL) == 0xFFFFFFFFULL) {
+      throw std::overflow_error("column version exhausted; delete and revive the record to write the column again");
     }
+    return col_version + 1;
   }
 
-  /// Retrieves all changes since a given `last_db_version`.
-  ///
-  /// # Arguments
-  ///
-  /// * `last_db_version` - The database version to retrieve changes since.
-  ///
-  /// # Returns
-  ///
-  /// A vector of changes.
-  ///
-  /// Complexity: O(n * m), where n is the number of records and m is the average number of columns per record
-  CrdtVector<Change<K, V>> get_changes_since(uint64_t last_db_version) const {
-    CrdtVector<Change<K, V>> changes;
+  // The incarnation of a purge: odd like any deletion, and above every other, so nothing can follow it
+  static constexpr uint64_t PURGED_INCARNATION = UINT64_MAX;
 
-    // Get changes from parent
-    if (parent_) {
-      auto parent_changes = parent_->get_changes_since(last_db_version);
-      changes.insert(changes.end(), parent_changes.begin(), parent_changes.end());
+  // Drops what is kept about a record besides its data: column history, parked conflicts and quarantined changes
+  void erase_traces(const K &record_id) {
+    history_.erase(record_id);
+    std::erase_if(conflicts_, [&](const CrdtConflict<K, V> &conflict) { return conflict.remote.record_id == record_id; });
+    std::erase_if(quarantine_, [&](const Change<K, V> &change) { return change.record_id == record_id; });
+  }
+
+  static constexpr bool is_revival(uint64_t incarnation) { return incarnation != 0 && incarnation % 2 == 0; }
+
+  static bool has_columns(const Record<V> &record) { return !record.column_versions.empty(); }
+
+  // Calls `fn(version)` with the version of each column of `record` and its revival version
+  template <typename Fn> static void for_each_version(const Record<V> &record, Fn &&fn) {
+    for (const auto &[col_name, version] : record.column_versions) {
+      fn(version);
     }
+    if (record.revival) {
+      fn(*record.revival);
+    }
+  }
 
-    for (const auto &[record_id, record] : data_) {
-      for (const auto &[col_name, clock_info] : record.column_versions) {
-        if (clock_info.local_db_version > last_db_version) {
-          std::optional<V> value = std::nullopt;
-          std::optional<CrdtString> name = std::nullopt;
-          if (col_name != "__deleted__") {
-            auto field_it = record.fields.find(col_name);
-            if (field_it != record.fields.end()) {
-              value = field_it->second;
-            }
-            name = col_name;
-          }
-          changes.emplace_back(Change<K, V>(record_id, std::move(name), std::move(value), clock_info.col_version,
-                                            clock_info.db_version, clock_info.node_id, clock_info.local_db_version));
+  // The version of a record's last deletion or revival, or nullptr if it never had one. `record` is its live record
+  const ColumnVersion *lifecycle_version(const K &record_id, const Record<V> *record, bool ignore_parent = false) const {
+    if (record != nullptr) {
+      return record->revival ? &*record->revival : nullptr;
+    }
+    const CrdtTombstone *tombstone = find_tombstone(record_id, ignore_parent);
+    return tombstone ? &tombstone->version : nullptr;
+  }
+
+  // Whether a revival version was made up locally, standing in for a revival that has not arrived yet because a write
+  // of its incarnation came first. Placeholders are not sent on; the real revival replaces one once it arrives
+  static bool is_placeholder(const ColumnVersion &version) { return version.db_version == 0; }
+
+  // Whether `version` is the write of operation `seq` of node `node_id`; writes without a sequence number never match
+  static bool is_same_write(const ColumnVersion &version, CrdtNodeId node_id, uint64_t seq) {
+    return seq != 0 && version.seq == seq && version.node_id == node_id;
+  }
+
+  static Change<K, V> revival_change(const K &record_id, const ColumnVersion &version) {
+    return version_change(record_id, std::nullopt, std::nullopt, version);
+  }
+
+  // Feeds a record's deletion or revival version into a digest, ahead of its columns
+  static void add_lifecycle_digest(CrdtHasher &hasher, const ColumnVersion *version) {
+    hasher.update_u64(version ? 1 : 0);
+    if (version) {
+      hasher.update_u64(version->col_version);
+      hasher.update_u64(version->db_version);
+      CrdtDigest<CrdtNodeId>::add(hasher, version->node_id);
+    }
+  }
+
+  static Change<K, V> tombstone_change(const K &record_id, const CrdtTombstone &tombstone) {
+    Change<K, V> change = version_change(record_id, std::nullopt, std::nullopt, tombstone.version);
+    change.wall_time = tombstone.wall_time;
+    return change;
+  }
+
+  // True if a layer above `level` (up to this one) holds its own record or tombstone for `record_id`
+  bool shadowed_below(const CRDT *level, const K &record_id) const {
+    for (const CRDT *child = this; child != level; child = child->parent_.get()) {
+      if (child->data_.find(record_id) != child->data_.end() ||
+          child->tombstones_.find(record_id) != child->tombstones_.end()) {
+        return true;
+      }
+    }
+    return false;
+  }
+
+  // Moves a live record to the tombstone store as a local deletion at `db_version`
+  Change<K, V> tombstone_record(const K &record_id, uint64_t db_version, CrdtVector<ChangeEvent<K, V>> &events) {
+    const Record<V> *existing = get_record_ptr(record_id);
+    uint64_t incarnation = (existing ? incarnation_of(*existing) : 0) + 1;
+    if (!observers_.empty()) {
+      collect_deletion_events(record_id, node_id_, db_version, false, events);
+    }
+    unindex_record(record_id);
+
+    CrdtTombstone tombstone{local_version(incarnation, db_version), wall_clock_ ? wall_clock_() : 0};
+    Change<K, V> change = tombstone_change(record_id, tombstone);
+    store_tombstone(record_id, std::move(tombstone));
+    note_change(record_id, db_version);
+    return change;
+  }
+
+  // Starts a new incarnation of a record, dropping the columns of earlier ones
+  void reincarnate(const K &record_id, const ColumnVersion &version, bool ignore_parent,
+                   CrdtVector<ChangeEvent<K, V>> &events, CrdtNodeId node_id = {}, uint64_t db_version = 0) {
+    bool was_live = !is_record_tombstoned(record_id, ignore_parent);
+    if (was_live && !observers_.empty()) {
+      collect_deletion_events(record_id, node_id, db_version, true, events);
+    }
+    if (was_live) {
+      unindex_record(record_id);
+    }
+    count_transition(status(record_id), RecordStatus::Live);
+    tombstones_.erase(record_id);
+    data_.erase(record_id);
+    Record<V> &record = data_[record_id];
+    record.revival = version;
+    fill_migration_defaults(record_id, record);
+    note_change(record_id, version.local_db_version);
+  }
+
+  static const V *field_ptr(const Record<V> &record, const CrdtString &col_name) {
+    auto it = record.fields.find(col_name);
+    return it != record.fields.end() ? &it->second : nullptr;
+  }
+
+  static size_t unique_hash(const V &value) {
+    if constexpr (requires { std::hash<V>{}(value); }) {
+      return std::hash<V>{}(value);
+    } else {
+      return 0; // unreachable: add_unique_constraint requires std::hash<V>
+    }
+  }
+
+  void index_field(const K &record_id, const CrdtString &col_name, const V *old_value, const V *new_value) {
+    if (unique_index_.empty()) {
+      return;
+    }
+    auto index = unique_index_.find(col_name);
+    if (index == unique_index_.end()) {
+      return;
+    }
+    if (old_value != nullptr) {
+      auto bucket = index->second.find(unique_hash(*old_value));
+      if (bucket != index->second.end()) {
+        bucket->second.erase(record_id);
+        if (bucket->second.empty()) {
+          index->second.erase(bucket);
         }
       }
     }
+    if (new_value != nullptr) {
+      index->second[unique_hash(*new_value)].insert(record_id);
+    }
+  }
 
-    if (parent_) {
-      // Since we merge from the parent, we need to also run a compression pass
-      // to remove changes that have been overwritten by top level changes
-      // since we compare at first by col_version, it's fine even if our db_version is lower
-      // since we merge from the parent, we know that the changes are applied in order and col_version should always be increasing
-      compress_changes(changes);
+  void unindex_record(const K &record_id) {
+    if (unique_index_.empty()) {
+      return;
+    }
+    if (const Record<V> *record = get_record_ptr(record_id)) {
+      for (const auto &[col_name, value] : record->fields) {
+        index_field(record_id, col_name, &value, nullptr);
+      }
     }
+  }
 
-    return changes;
+  void rebuild_unique_index() {
+    for (auto &[col_name, index] : unique_index_) {
+      index.clear();
+    }
+    if (unique_index_.empty()) {
+      return;
+    }
+    for_each_live_record([&](const K &record_id, const Record<V> &record) {
+      for (const auto &[col_name, value] : record.fields) {
+        index_field(record_id, col_name, nullptr, &value);
+      }
+    });
   }
 
-  /// Merges a set of incoming changes into the CRDT.
-  ///
-  /// # Arguments
-  ///
-  /// * `changes` - A vector of changes to merge.
-  ///
-  /// # Returns
-  ///
-  /// If `ReturnAcceptedChanges` is `true`, returns a vector of accepted changes.
-  /// Otherwise, returns `void`.
-  ///
-  /// Complexity: O(c), where c is the number of changes to merge
-  template <bool ReturnAcceptedChanges = false>
-  std::conditional_t<ReturnAcceptedChanges, CrdtVector<Change<K, V>>, void> merge_changes(CrdtVector<Change<K, V>> &&changes,
-                                                                                          bool ignore_parent = false) {
+  // Live records holding `value` in `col_name`, winning claim first
+  CrdtVector<const K *> unique_claims(const CrdtString &col_name, const V &value) const {
+    CrdtVector<const K *> claims;
+    auto index = unique_index_.find(col_name);
+    if (index == unique_index_.end()) {
+      return claims;
+    }
+    auto bucket = index->second.find(unique_hash(value));
+    if (bucket == index->second.end()) {
+      return claims;
+    }
+    for (const K &record_id : bucket->second) {
+      const V *held = field_ptr(*get_record_ptr(record_id), col_name);
+      if (held != nullptr && *held == value) {
+        claims.push_back(&record_id);
+      }
+    }
+    std::sort(claims.begin(), claims.end(), [&](const K *a, const K *b) {
+      const ColumnVersion &va = get_record_ptr(*a)->column_versions.at(col_name);
+      const ColumnVersion &vb = get_record_ptr(*b)->column_versions.at(col_name);
+      return std::tie(va.db_version, va.node_id, *a) < std::tie(vb.db_version, vb.node_id, *b);
+    });
+    return claims;
+  }
+
+  // The merge loop behind merge_changes and merge_changes_owned
+  template <bool ReturnAcceptedChanges, typename Range>
+  std::conditional_t<ReturnAcceptedChanges, CrdtVector<Change<K, V>>, void> merge_range(Range &changes, bool ignore_parent) {
     CrdtVector<Change<K, V>> accepted_changes;
+    CrdtVector<ChangeEvent<K, V>> events;
 
-    if (changes.empty()) {
+    auto it = std::ranges::begin(changes);
+    auto end = std::ranges::end(changes);
+    if (it == end) {
       if constexpr (ReturnAcceptedChanges) {
         return accepted_changes;
       } else {
@@ -491,28 +4939,141 @@ public:
       }
     }
 
-    for (auto &&change : changes) {
+    CrdtTraceSpan span("merge_changes");
+    span.event.version = clock_.current_time();
+    uint64_t accepted_before = stats_.changes_accepted;
+    uint64_t rejected_before = stats_.changes_rejected;
+
+    ++stats_.merges;
+    const bool collect_accepted = ReturnAcceptedChanges || !batch_observers_.empty();
+    size_t merged = 0;
+    std::optional<CrdtMap<CrdtNodeId, CrdtSiteInfo>> registered_sites; // for strict mode, rebuilt after registry changes
+    for (; it != end; ++it) {
+      crdt_fault_check(CrdtFaultPoint::MergeChange, merged, "injected merge interruption");
+      auto &&change = *it;
+      ++merged;
       const K &record_id = change.record_id;
       std::optional<CrdtString> col_name = std::move(change.col_name);
       uint64_t remote_col_version = change.col_version;
       uint64_t remote_db_version = change.db_version;
       CrdtNodeId remote_node_id = change.node_id;
+      uint64_t remote_seq = change.seq;
       std::optional<V> remote_value = std::move(change.value);
 
+      // Changes of a later epoch cannot be compared with this state; changes of an earlier one are translated to the
+      // start of this epoch, where the versions that survived the compaction were moved, or dropped
+      std::optional<Change<K, V>> translated;
+      if (crdt_epoch(remote_db_version) != epoch()) {
+        if (crdt_epoch(remote_db_version) > epoch() || epoch_policy_ == CrdtEpochPolicy::Reject) {
+          ++stats_.changes_other_epoch;
+          continue;
+        }
+        remote_db_version = crdt_epoch_start(epoch());
+        translated = change;
+        translated->db_version = remote_db_version;
+      }
+      const Change<K, V> &remote_change = translated ? *translated : change;
+      if (strict_sites_ && site_registry_) {
+        if (!registered_sites) {
+          registered_sites.emplace();
+          for (const CrdtSiteInfo &info : sites()) {
+            registered_sites->insert_or_assign(info.node_id, info);
+          }
+        }
+        auto site = registered_sites->find(remote_node_id);
+        bool registered = site != registered_sites->end() &&
+                          (site->second.status == CrdtSiteStatus::Active || remote_db_version <= site->second.final_version);
+        // An empty registry accepts its first site
+        if (!registered && !registered_sites->empty()) {
+          ++stats_.changes_unregistered;
+          quarantine_.emplace_back(record_id, std::move(col_name), std::move(remote_value), remote_col_version,
+                                   remote_db_version, remote_node_id);
+          continue;
+        }
+        if (record_id == *site_registry_) {
+          registered_sites.reset();
+        }
+      }
+      if (clock_anomaly(remote_node_id, remote_db_version)) {
+        continue;
+      }
+
       // Always update the logical clock to maintain causal consistency,
       // prevent clock drift, and ensure accurate conflict resolution.
       // This reflects the node's knowledge of global progress, even for
       // non-accepted changes.
       uint64_t new_local_db_version = clock_.update(remote_db_version);
+      note_seen(remote_node_id, remote_db_version);
 
-      // Retrieve local column version information
+      // Changes made under an older schema are moved to the column's current name
+      if (col_name && !column_renames_.empty() && record_id != migration_record_) {
+        auto renamed = column_renames_.find(*col_name);
+        if (renamed != column_renames_.end()) {
+          if (!renamed->second) {
+            ++stats_.changes_rejected;
+            continue;
+          }
+          col_name = renamed->second;
+        }
+      }
+
+      if (col_name && !local_columns_.empty() && local_columns_.contains(*col_name)) {
+        ++stats_.changes_denied;
+        continue;
+      }
+      if (write_policy_ && !write_policy_->allows(remote_node_id, col_name)) {
+        ++stats_.changes_denied;
+        continue;
+      }
+      if (validator_) {
+        bool had_value = remote_value.has_value();
+        if (!validator_(record_id, col_name, remote_value, remote_node_id) || remote_value.has_value() != had_value) {
+          ++stats_.changes_invalid;
+          continue;
+        }
+      }
+      if (schema_ && col_name && schema_->incoming_violation(*col_name, remote_value)) {
+        ++stats_.changes_invalid;
+        if (schema_->invalid_policy() == CrdtInvalidChangePolicy::Quarantine) {
+          quarantine_.emplace_back(record_id, std::move(col_name), std::move(remote_value), remote_col_version,
+                                   remote_db_version, remote_node_id);
+        }
+        continue;
+      }
+
+      // Writes of an older incarnation are stale; writes of a newer one revive the record
       const Record<V> *record_ptr = get_record_ptr(record_id, ignore_parent);
+      if (col_name) {
+        uint64_t incarnation = column_incarnation(remote_col_version);
+        const ColumnVersion *lifecycle = lifecycle_version(record_id, record_ptr, ignore_parent);
+        uint64_t local_incarnation = lifecycle ? lifecycle->col_version : 0;
+        if (incarnation < local_incarnation) {
+          ++stats_.changes_rejected;
+          continue;
+        }
+        if (incarnation > local_incarnation) {
+          reincarnate(record_id, ColumnVersion(incarnation, 0, CrdtNodeId{}, new_local_db_version), ignore_parent, events,
+                      remote_node_id, remote_db_version);
+          record_ptr = get_record_ptr(record_id, ignore_parent);
+        }
+      }
+
+      // Retrieve local column version information
       const ColumnVersion *local_col_info = nullptr;
-      if (record_ptr != nullptr) {
-        auto col_it = record_ptr->column_versions.find(col_name ? *col_name : "__deleted__");
+      if (!col_name) {
+        local_col_info = lifecycle_version(record_id, record_ptr, ignore_parent);
+      } else if (record_ptr != nullptr) {
+        auto col_it = record_ptr->column_versions.find(*col_name);
         if (col_it != record_ptr->column_versions.end()) {
           local_col_info = &col_it->second;
         }
+        local_col_info = compared_version(record_id, *record_ptr, *col_name, local_col_info, remote_change);
+      }
+
+      // A write already held, identified by its node and sequence number, is a duplicate (a redelivery or an echo)
+      if (local_col_info != nullptr && is_same_write(*local_col_info, remote_node_id, remote_seq)) {
+        ++stats_.changes_rejected;
+        continue;
       }
 
       // Determine whether to accept the remote change
@@ -522,36 +5083,87 @@ public:
         // No local version exists; accept the remote change
         should_accept = true;
       } else {
-        Change<K, V> local_change(record_id, col_name ? *col_name : "__deleted__", std::nullopt, local_col_info->col_version,
-                                  local_col_info->db_version, local_col_info->node_id);
-        should_accept = merge_rule_(local_change, change);
+        if (col_name && manual_columns_.contains(*col_name) && is_concurrent_write(*local_col_info, remote_change) &&
+            !same_value(field_value(*record_ptr, *col_name), remote_value)) {
+          park_conflict(record_id, *col_name, std::move(remote_value), remote_change, *local_col_info);
+          continue;
+        }
+        should_accept = remote_wins(version_change(record_id, col_name, std::nullopt, *local_col_info), remote_change);
+        if (local_col_info->col_version != remote_col_version || local_col_info->db_version != remote_db_version ||
+            local_col_info->node_id != remote_node_id) {
+          ++stats_.conflicts_resolved;
+        }
+        if (const V *local_value = col_name && merge_registry_ && remote_value ? field_ptr(*record_ptr, *col_name) : nullptr) {
+          if (const auto *merge = merge_registry_->find(*col_name, *local_value, *remote_value)) {
+            // The merged value is written with the winning version, or kept if it is the local value at its version
+            ColumnVersion remote_version(remote_col_version, remote_db_version, remote_node_id, new_local_db_version,
+                                         remote_seq);
+            V merged = (*merge)(*local_value, *remote_value, *local_col_info, remote_version);
+            if (!should_accept) {
+              remote_col_version = local_col_info->col_version;
+              remote_db_version = local_col_info->db_version;
+              remote_node_id = local_col_info->node_id;
+              remote_seq = local_col_info->seq;
+              should_accept = !same_value(*local_value, merged);
+            }
+            remote_value = std::move(merged);
+          }
+        }
       }
+      ++(should_accept ? stats_.changes_accepted : stats_.changes_rejected);
 
       if (should_accept) {
-        if (!col_name) {
+        ChangeKind kind = crdt_change_kind(col_name.has_value(), remote_col_version);
+        if (kind == ChangeKind::Revive) {
+          // Handle revival; a revival of the current incarnation only updates its version
+          ColumnVersion version(remote_col_version, remote_db_version, remote_node_id, new_local_db_version, remote_seq);
+          if (record_ptr == nullptr || remote_col_version > incarnation_of(*record_ptr)) {
+            reincarnate(record_id, version, ignore_parent, events, remote_node_id, remote_db_version);
+          } else {
+            get_or_create_record_unchecked(record_id, ignore_parent).revival = version;
+            note_change(record_id, new_local_db_version);
+          }
+          if (collect_accepted) {
+            accepted_changes.push_back(revival_change(record_id, version));
+          }
+        } else if (kind == ChangeKind::Delete) {
           // Handle deletion
-          tombstones_.emplace(record_id);
-          data_.erase(record_id);
-
-          // Update deletion clock info
-          CrdtMap<CrdtString, ColumnVersion> deletion_clock;
-          deletion_clock.emplace("__deleted__",
-                                 ColumnVersion(remote_col_version, remote_db_version, remote_node_id, new_local_db_version));
-
-          // Store deletion info in the data map
-          data_.emplace(record_id, Record<V>(CrdtMap<CrdtString, V>(), std::move(deletion_clock)));
-
-          if constexpr (ReturnAcceptedChanges) {
-            accepted_changes.emplace_back(Change<K, V>(record_id, std::nullopt, std::nullopt, remote_col_version,
-                                                       remote_db_version, remote_node_id, new_local_db_version));
+          if (!observers_.empty() && !is_record_tombstoned(record_id, ignore_parent)) {
+            collect_deletion_events(record_id, remote_node_id, remote_db_version, true, events);
+          }
+          unindex_record(record_id);
+          CrdtTombstone tombstone{
+              ColumnVersion(remote_col_version, remote_db_version, remote_node_id, new_local_db_version, remote_seq),
+              change.wall_time};
+          if (collect_accepted) {
+            accepted_changes.push_back(tombstone_change(record_id, tombstone));
           }
+          store_tombstone(record_id, std::move(tombstone));
+          if (remote_col_version == PURGED_INCARNATION) {
+            erase_traces(record_id);
+          }
+          note_change(record_id, new_local_db_version);
         } else if (!is_record_tombstoned(record_id, ignore_parent)) {
           // Handle insertion or update
           Record<V> &record = get_or_create_record_unchecked(record_id, ignore_parent);
+          if (record_ptr == nullptr) {
+            fill_migration_defaults(record_id, record);
+          }
+          ColumnVersion version(remote_col_version, remote_db_version, remote_node_id, new_local_db_version, remote_seq);
+
+          if (!observers_.empty()) {
+            events.push_back({record_id, *col_name, field_value(record, *col_name), remote_value, remote_node_id,
+                              remote_db_version, true});
+          }
+          if (history_depth_ > 0) {
+            record_history(record_id, *col_name, remote_value, version);
+          }
+
+          index_field(record_id, *col_name, field_ptr(record, *col_name), remote_value ? &*remote_value : nullptr);
 
           // Update field value
           if (remote_value.has_value()) {
-            if constexpr (ReturnAcceptedChanges) {
+            if (collect_accepted) {
               record.fields[*col_name] = *remote_value;
             } else {
               record.fields[*col_name] = std::move(*remote_value);
@@ -561,199 +5173,336 @@ public:
             record.fields.erase(*col_name);
           }
 
-          // Update the column version info
-          if constexpr (ReturnAcceptedChanges) {
-            record.column_versions.insert_or_assign(
-                *col_name, ColumnVersion(remote_col_version, remote_db_version, remote_node_id, new_local_db_version));
-            accepted_changes.emplace_back(Change<K, V>(record_id, std::move(col_name), std::move(remote_value),
-                                                       remote_col_version, remote_db_version, remote_node_id,
-                                                       new_local_db_version));
-          } else {
-            record.column_versions.insert_or_assign(
-                std::move(*col_name), ColumnVersion(remote_col_version, remote_db_version, remote_node_id, new_local_db_version));
-          }
-        }
+          // Update the column version info
+          if (collect_accepted) {
+            record.column_versions.insert_or_assign(*col_name, version);
+            accepted_changes.push_back(version_change(record_id, std::move(col_name), std::move(remote_value), version));
+          } else {
+            record.column_versions.insert_or_assign(std::move(*col_name), version);
+          }
+          note_change(record_id, new_local_db_version);
+        }
+      }
+    }
+
+    span.event.changes = merged;
+    span.event.accepted = static_cast<size_t>(stats_.changes_accepted - accepted_before);
+    span.event.rejected = static_cast<size_t>(stats_.changes_rejected - rejected_before);
+    prune_conflicts();
+    apply_migrations();
+    assert_invariants("merge_changes");
+    notify(events);
+    notify_batch(accepted_changes);
+
+    if constexpr (ReturnAcceptedChanges) {
+      return accepted_changes;
+    }
+  }
+
+  // Copies the policies, schema, hooks and replicated configuration (migrations, site registry, unique columns) that
+  // decide how changes are written and merged, for copies, child CRDTs and parallel merge shards
+  void copy_config(const CRDT &other) {
+    write_policy_ = other.write_policy_;
+    ttl_policy_ = other.ttl_policy_;
+    epoch_policy_ = other.epoch_policy_;
+    schema_ = other.schema_;
+    validator_ = other.validator_;
+    manual_columns_ = other.manual_columns_;
+    local_columns_ = other.local_columns_;
+    merge_registry_ = other.merge_registry_;
+    site_weights_ = other.site_weights_;
+    column_groups_ = other.column_groups_;
+    column_group_ = other.column_group_;
+    migration_record_ = other.migration_record_;
+    site_registry_ = other.site_registry_;
+    strict_sites_ = other.strict_sites_;
+    schema_version_ = other.schema_version_;
+    column_renames_ = other.column_renames_;
+    migration_defaults_ = other.migration_defaults_;
+    unique_index_ = other.unique_index_;
+    wall_clock_ = other.wall_clock_;
+    clock_guard_ = other.clock_guard_;
+    clock_anomaly_handler_ = other.clock_anomaly_handler_;
+  }
+
+  // Gives `shard` this CRDT's merge settings and the current state of the records `changes` touch, so a partition of a
+  // parallel merge can be merged into it. Only reads this CRDT, so several shards can be seeded at once.
+  void seed_shard(CRDT &shard, const CrdtVector<Change<K, V>> &changes, uint64_t base_time) const {
+    shard.clock_.set_time(base_time);
+    shard.copy_config(*this);
+    for (const auto &change : changes) {
+      if (shard.data_.contains(change.record_id) || shard.tombstones_.contains(change.record_id)) {
+        continue;
+      }
+      if (auto it = data_.find(change.record_id); it != data_.end()) {
+        shard.data_.emplace(it->first, it->second);
+      } else if (auto tombstone = tombstones_.find(change.record_id); tombstone != tombstones_.end()) {
+        shard.tombstones_.emplace(tombstone->first, tombstone->second);
+      }
+    }
+  }
+
+  // Spreads record ids over `partitions`, keeping all changes of a record in one partition
+  static size_t partition_of(const K &record_id, size_t partitions) {
+    if constexpr (requires { std::hash<K>{}(record_id); }) {
+      return std::hash<K>{}(record_id) % partitions;
+    } else {
+      std::ostringstream key;
+      key << record_id;
+      return std::hash<std::string>{}(key.str()) % partitions;
+    }
+  }
+
+  // The merge rule, with site weights deciding ties in col_version first
+  bool remote_wins(const Change<K, V> &local, const Change<K, V> &remote) const {
+    if (!site_weights_.empty() && local.col_version == remote.col_version) {
+      auto weight = [&](const CrdtNodeId &node_id) {
+        auto it = site_weights_.find(node_id);
+        return it != site_weights_.end() ? it->second : 0;
+      };
+      int64_t local_weight = weight(local.node_id);
+      int64_t remote_weight = weight(remote.node_id);
+      if (local_weight != remote_weight) {
+        return remote_weight > local_weight;
       }
     }
+    return merge_rule_(local, remote);
+  }
 
-    if constexpr (ReturnAcceptedChanges) {
-      return accepted_changes;
+  // The latest write among the columns of `col_name`'s group, or nullptr if it is not grouped or none was written
+  const ColumnVersion *group_version(const K &record_id, const Record<V> &record, const CrdtString &col_name) const {
+    auto group = column_group_.find(col_name);
+    if (group == column_group_.end()) {
+      return nullptr;
+    }
+    const ColumnVersion *latest = nullptr;
+    for (const CrdtString &member : column_groups_[group->second]) {
+      auto it = record.column_versions.find(member);
+      if (it == record.column_versions.end()) {
+        continue;
+      }
+      if (latest == nullptr ||
+          remote_wins(Change<K, V>(record_id, member, std::nullopt, latest->col_version, latest->db_version, latest->node_id),
+                      Change<K, V>(record_id, member, std::nullopt, it->second.col_version, it->second.db_version,
+                                   it->second.node_id))) {
+        latest = &it->second;
+      }
     }
+    return latest;
   }
 
-  /// Compresses a vector of changes in-place by removing redundant changes that overwrite each other.
-  ///
-  /// # Arguments
-  ///
-  /// * `changes` - A vector of changes to compress (will be modified in-place).
-  ///
-  /// Complexity: O(n log n), where n is the number of changes
-  template <bool Sorted = false> static void compress_changes(CrdtVector<Change<K, V>> &changes) {
-    if (changes.empty())
-      return;
+  // The version an incoming write of a column must beat: for a grouped column, the group's latest write, unless the
+  // incoming write is that write (then only a duplicate of the column's own version is rejected)
+  const ColumnVersion *compared_version(const K &record_id, const Record<V> &record, const CrdtString &col_name,
+                                        const ColumnVersion *own, const Change<K, V> &remote) const {
+    if (column_groups_.empty()) {
+      return own;
+    }
+    const ColumnVersion *latest = group_version(record_id, record, col_name);
+    if (latest == nullptr) {
+      return own;
+    }
+    auto is_remote = [&](const ColumnVersion &version) {
+      return version.col_version == remote.col_version && version.db_version == remote.db_version &&
+             version.node_id == remote.node_id;
+    };
+    if (!is_remote(*latest)) {
+      return latest;
+    }
+    return own != nullptr && is_remote(*own) ? own : nullptr;
+  }
 
-    auto new_end = compress_changes<Sorted>(changes.begin(), changes.end());
-    changes.erase(new_end, changes.end());
+  // Re-stamps the other written columns of a group at `col_version`, so the group replicates as one write
+  void stamp_group(const K &record_id, Record<V> &record, size_t group, uint64_t col_version, uint64_t db_version,
+                   CrdtVector<Change<K, V>> &changes, bool collect_changes) {
+    ColumnVersion stamped = local_version(col_version, db_version);
+    for (const CrdtString &col_name : column_groups_[group]) {
+      auto it = record.column_versions.find(col_name);
+      if (it == record.column_versions.end() ||
+          (it->second.col_version == col_version && it->second.db_version == db_version && it->second.node_id == node_id_)) {
+        continue;
+      }
+      it->second = stamped;
+      std::optional<V> value = field_value(record, col_name);
+      if (history_depth_ > 0) {
+        record_history(record_id, col_name, value, stamped);
+      }
+      if (collect_changes) {
+        changes.push_back(version_change(record_id, col_name, std::move(value), stamped));
+      }
+    }
   }
 
-  /// Compresses a range of changes by removing redundant changes that overwrite each other.
-  ///
-  /// # Arguments
-  ///
-  /// * `begin` - Iterator to the beginning of the range.
-  /// * `end` - Iterator to the end of the range.
-  ///
-  /// # Returns
-  ///
-  /// Iterator to the new end of the range after compression.
-  ///
-  /// Complexity: O(n log n), where n is the number of changes
-  template <bool Sorted = false, typename Iterator> static Iterator compress_changes(Iterator begin, Iterator end) {
-    if (begin == end)
-      return end;
+  // Whether a remote write to a column was made without seeing the local one: both are the same write of the column
+  static bool is_concurrent_write(const ColumnVersion &local, const Change<K, V> &remote) {
+    return crdt_column_order(local, ColumnVersion(remote.col_version, remote.db_version, remote.node_id)) ==
+           CrdtCausality::Concurrent;
+  }
 
-    if constexpr (!Sorted) {
-      // Sort changes using the custom ChangeComparator
-      SortFunctionType()(begin, end, ChangeComparatorType());
+  static bool same_value(const std::optional<V> &a, const std::optional<V> &b) {
+    if constexpr (std::equality_comparable<V>) {
+      return a == b;
+    } else {
+      return false;
     }
+  }
 
-    // Use two-pointer technique to compress in-place
-    Iterator write = begin;
-    for (Iterator read = std::next(begin); read != end; ++read) {
-      if (read->record_id != write->record_id) {
-        // New record, always keep it
-        ++write;
-        if (write != read) {
-          *write = std::move(*read);
-        }
-      } else if (!read->col_name.has_value() && write->col_name.has_value()) {
-        // Current read is a deletion, keep it and skip all previous changes for this record
-        *write = std::move(*read);
-      } else if (read->col_name != write->col_name) {
-        // New column for the same record
-        ++write;
-        if (write != read) {
-          *write = std::move(*read);
-        }
+  void park_conflict(const K &record_id, const CrdtString &col_name, std::optional<V> &&remote_value,
+                     const Change<K, V> &change, const ColumnVersion &local) {
+    for (const CrdtConflict<K, V> &conflict : conflicts_) {
+      const Change<K, V> &parked = conflict.remote;
+      if (parked.record_id == record_id && *parked.col_name == col_name && parked.col_version == change.col_version &&
+          parked.db_version == change.db_version && parked.node_id == change.node_id) {
+        return;
       }
-      // Else: same record and column, keep the existing one (which is the most recent due to sorting)
     }
+    ++stats_.conflicts_parked;
+    conflicts_.push_back({Change<K, V>(record_id, col_name, std::move(remote_value), change.col_version, change.db_version,
+                                       change.node_id),
+                          local, field_value(*get_record_ptr(record_id), col_name)});
+  }
 
-    return std::next(write);
+  bool is_conflict_settled(const CrdtConflict<K, V> &conflict) const {
+    const Record<V> *record = get_record_ptr(conflict.remote.record_id);
+    if (record == nullptr) {
+      return true;
+    }
+    auto it = record->column_versions.find(*conflict.remote.col_name);
+    return it == record->column_versions.end() || it->second.col_version > conflict.remote.col_version;
   }
 
-/// Prints the current data and tombstones for debugging purposes.
-///
-/// Complexity: O(n * m), where n is the number of records and m is the average number of fields per record
-#ifndef NDEBUG
-  constexpr void print_data() const {
-    std::cout << "Node " << node_id_ << " Data:" << std::endl;
-    for (const auto &[record_id, record] : data_) {
-      if (tombstones_.find(record_id) != tombstones_.end()) {
-        continue; // Skip tombstoned records
+  void prune_conflicts() {
+    if (!conflicts_.empty()) {
+      std::erase_if(conflicts_, [&](const CrdtConflict<K, V> &conflict) { return is_conflict_settled(conflict); });
+    }
+  }
+
+  void check_writable() const {
+    if (read_only_) {
+      throw std::logic_error("local write to a read-only CRDT");
+    }
+  }
+
+  // Runs the write policy, validator and schema over the fields of a local write
+  void check_fields(const K &record_id, CrdtMap<CrdtString, V> &fields) {
+    if (write_policy_) {
+      for (const auto &[col_name, value] : fields) {
+        check_write_permitted(col_name);
       }
-      std::cout << "ID: " << record_id << std::endl;
-      for (const auto &[key, value] : record.fields) {
-        std::cout << "  " << key << ": " << value << std::endl;
+    }
+    if (validator_) {
+      for (auto &[col_name, value] : fields) {
+        std::optional<V> validated(std::move(value));
+        if (!validator_(record_id, col_name, validated, node_id_) || !validated) {
+          throw std::invalid_argument("write to column '" + col_name + "' rejected by the validator");
+        }
+        value = std::move(*validated);
       }
     }
-    std::cout << "Tombstones: ";
-    for (const auto &tid : tombstones_) {
-      std::cout << tid << " ";
+    if (schema_) {
+      apply_schema(record_id, fields);
     }
-    std::cout << std::endl << std::endl;
   }
-#else
-  constexpr void print_data() const {}
-#endif
-
-  // Accessors for testing
-  // Complexity: O(1)
-  constexpr const LogicalClock &get_clock() const { return clock_; }
 
-  // Updated get_data() method
-  constexpr CrdtMap<K, Record<V>> get_data() const {
-    if (!parent_) {
-      return data_;
+  // Writes checked fields of a live record at `db_version`, creating the record if needed
+  void write_fields(const K &record_id, CrdtMap<CrdtString, V> &&fields, uint64_t db_version,
+                    CrdtVector<ChangeEvent<K, V>> &events, CrdtVector<Change<K, V>> &changes, bool collect_changes,
+                    bool index = true) {
+    // Fail before writing anything if a column cannot be written again
+    if (const Record<V> *existing = get_record_ptr(record_id)) {
+      for (const auto &[col_name, value] : fields) {
+        auto col_it = existing->column_versions.find(col_name);
+        if (col_it != existing->column_versions.end()) {
+          next_column_version(col_it->second.col_version);
+        }
+      }
+    }
+    bool created = get_record_ptr(record_id) == nullptr;
+    Record<V> &record = get_or_create_record_unchecked(record_id);
+    if (created) {
+      fill_migration_defaults(record_id, record);
     }
 
-    CrdtMap<K, Record<V>> combined_data = parent_->get_data();
-    for (const auto &[key, record] : data_) {
-      combined_data[key] = record;
+    // The columns of a group are written together, one version above the group's latest write
+    CrdtMap<size_t, uint64_t> group_versions;
+    if (!column_groups_.empty()) {
+      for (const auto &[col_name, value] : fields) {
+        auto group = column_group_.find(col_name);
+        if (group != column_group_.end() && !group_versions.contains(group->second)) {
+          const ColumnVersion *latest = group_version(record_id, record, col_name);
+          group_versions.emplace(group->second, latest ? next_column_version(latest->col_version)
+                                                       : first_column_version(incarnation_of(record)));
+        }
+      }
     }
-    return combined_data;
-  }
 
-  /// Retrieves a pointer to a record if it exists, or nullptr if it doesn't.
-  ///
-  /// # Arguments
-  ///
-  /// * `record_id` - The unique identifier for the record.
-  /// * `ignore_parent` - If true, only checks the current CRDT instance, ignoring the parent.
-  ///
-  /// # Returns
-  ///
-  /// A pointer to the Record<V> if found, or nullptr if not found.
-  ///
-  /// Complexity: O(1) average case for hash table lookup
-  Record<V> *get_record(const K &record_id, bool ignore_parent = false) { return get_record_ptr(record_id, ignore_parent); }
+    for (auto &[col_name, value] : fields) {
+      if (!local_columns_.empty() && local_columns_.contains(col_name)) {
+        if (!observers_.empty()) {
+          events.push_back({record_id, col_name, field_value(record, col_name), value, node_id_, db_version, false});
+        }
+        record.fields[col_name] = std::move(value);
+        continue;
+      }
+      uint64_t col_version;
+      auto group = group_versions.empty() ? column_group_.end() : column_group_.find(col_name);
+      auto col_it = record.column_versions.find(col_name);
+      if (col_it != record.column_versions.end()) {
+        col_version =
+            group != column_group_.end() ? group_versions.at(group->second) : next_column_version(col_it->second.col_version);
+        col_it->second = local_version(col_version, db_version);
+      } else {
+        col_version =
+            group != column_group_.end() ? group_versions.at(group->second) : first_column_version(incarnation_of(record));
+        record.column_versions.emplace(col_name, local_version(col_version, db_version));
+      }
 
-  // Add this public method to the CRDT class
-  /// Checks if a record is tombstoned.
-  ///
-  /// # Arguments
-  ///
-  /// * `record_id` - The unique identifier for the record.
-  /// * `ignore_parent` - If true, only checks the current CRDT instance, ignoring the parent.
-  ///
-  /// # Returns
-  ///
-  /// True if the record is tombstoned, false otherwise.
-  ///
-  /// Complexity: O(1) average case for hash table lookup
-  constexpr bool is_tombstoned(const K &record_id, bool ignore_parent = false) const {
-    return is_record_tombstoned(record_id, ignore_parent);
-  }
+      if (!observers_.empty()) {
+        events.push_back({record_id, col_name, field_value(record, col_name), value, node_id_, db_version, false});
+      }
+      if (history_depth_ > 0) {
+        record_history(record_id, col_name, value, record.column_versions.at(col_name));
+      }
+      if (index) {
+        index_field(record_id, col_name, field_ptr(record, col_name), &value);
+      }
 
-  // Add this constructor to the CRDT class
-  CRDT(const CRDT &other)
-      : node_id_(other.node_id_), clock_(other.clock_), data_(other.data_), tombstones_(other.tombstones_),
-        parent_(other.parent_), base_version_(other.base_version_), merge_rule_(other.merge_rule_),
-        change_comparator_(other.change_comparator_), sort_func_(other.sort_func_) {
-    // Note: This creates a shallow copy of the parent pointer
+      if (collect_changes) {
+        record.fields[col_name] = value;
+        Change<K, V> &change = changes.emplace_back(
+            Change<K, V>(record_id, std::move(col_name), std::move(value), col_version, db_version, node_id_, db_version));
+        change.seq = seq_;
+      } else {
+        record.fields[std::move(col_name)] = std::move(value);
+      }
+    }
+    for (const auto &[group, col_version] : group_versions) {
+      stamp_group(record_id, record, group, col_version, db_version, changes, collect_changes);
+    }
+    note_change(record_id, db_version);
   }
 
-  CRDT &operator=(const CRDT &other) {
-    if (this != &other) {
-      node_id_ = other.node_id_;
-      clock_ = other.clock_;
-      data_ = other.data_;
-      tombstones_ = other.tombstones_;
-      parent_ = other.parent_;
-      base_version_ = other.base_version_;
-      merge_rule_ = other.merge_rule_;
-      change_comparator_ = other.change_comparator_;
-      sort_func_ = other.sort_func_;
+  void check_write_permitted(const std::optional<CrdtString> &col_name) const {
+    if (write_policy_ && !write_policy_->allows(node_id_, col_name)) {
+      throw std::invalid_argument(col_name ? "write to column '" + *col_name + "' denied by the write policy"
+                                           : CrdtString("record deletion denied by the write policy"));
     }
-    return *this;
   }
 
-private:
-  CrdtNodeId node_id_;
-  LogicalClock clock_;
-  CrdtMap<K, Record<V>> data_;
-  CrdtSet<K> tombstones_;
-
-  // our clock won't be shared with the parent
-  // we optionally allow to merge from the parent or push to the parent
-  std::shared_ptr<CRDT<K, V, MergeRuleType, ChangeComparatorType, SortFunctionType>> parent_;
-  uint64_t base_version_; // Tracks the parent’s db_version at the time of child creation
-  MergeRuleType merge_rule_;
-  ChangeComparatorType change_comparator_;
-  SortFunctionType sort_func_;
+  void notify_batch(const CrdtVector<Change<K, V>> &changes) const {
+    if (changes.empty()) {
+      return;
+    }
+    for (const auto &[id, observer] : batch_observers_) {
+      observer(changes);
+    }
+  }
 
   /// Applies a list of changes to reconstruct the CRDT state.
   ///
+  /// The changes are applied in order without the merge rule, keeping their local db_versions, so they must be this
+  /// node's state (from `get_changes_since`) or the changes it applied, in the order it applied them.
+  ///
   /// # Arguments
   ///
   /// * `changes` - A list of changes to apply.
@@ -763,15 +5512,16 @@ private:
     // Determine the maximum db_version from the changes
     uint64_t max_db_version = 0;
     for (const auto &change : changes) {
-      if (change.db_version > max_db_version) {
-        max_db_version = change.db_version;
-      }
+      max_db_version = std::max({max_db_version, change.db_version, change.local_db_version});
     }
 
-    // Set the logical clock to the maximum db_version
-    clock_.set_time(max_db_version);
+    // Advance the logical clock to the maximum db_version, so new local changes sort after the applied ones, and the
+    // sequence number with it, since every local operation ticked the clock
+    clock_.set_time(std::max(clock_.current_time(), max_db_version));
+    seq_ = std::max(seq_, clock_.current_time());
 
     // Apply each change to reconstruct the CRDT state
+    CrdtVector<ChangeEvent<K, V>> events;
     for (auto &&change : changes) {
       const K &record_id = change.record_id;
       std::optional<CrdtString> col_name = std::move(change.col_name);
@@ -779,21 +5529,33 @@ private:
       uint64_t remote_db_version = change.db_version;
       CrdtNodeId remote_node_id = change.node_id;
       uint64_t remote_local_db_version = change.local_db_version;
+      uint64_t remote_seq = change.seq;
       std::optional<V> remote_value = std::move(change.value);
+      note_seen(remote_node_id, remote_db_version);
 
-      if (!col_name.has_value()) {
+      ChangeKind kind = crdt_change_kind(col_name.has_value(), remote_col_version);
+      if (kind == ChangeKind::Revive) {
+        // A revived record keeps its incarnation next to its columns
+        ColumnVersion version(remote_col_version, remote_db_version, remote_node_id, remote_local_db_version, remote_seq);
+        const Record<V> *record = get_record_ptr(record_id);
+        if (record == nullptr || remote_col_version > incarnation_of(*record)) {
+          reincarnate(record_id, version, false, events);
+        } else {
+          get_or_create_record_unchecked(record_id).revival = version;
+          note_change(record_id, remote_local_db_version);
+        }
+      } else if (kind == ChangeKind::Delete) {
         // Handle deletion
-        tombstones_.emplace(record_id);
-        data_.erase(record_id);
-
-        // Insert deletion clock info
-        CrdtMap<CrdtString, ColumnVersion> deletion_clock;
-        deletion_clock.emplace("__deleted__",
-                               ColumnVersion(remote_col_version, remote_db_version, remote_node_id, remote_local_db_version));
-
-        // Store deletion info in the data map
-        data_.emplace(record_id, Record<V>(CrdtMap<CrdtString, V>(), std::move(deletion_clock)));
+        ColumnVersion version(remote_col_version, remote_db_version, remote_node_id, remote_local_db_version, remote_seq);
+        store_tombstone(record_id, CrdtTombstone{version, change.wall_time});
+        note_change(record_id, remote_local_db_version);
       } else {
+        // A write of a newer incarnation revives the record, as it did when it was merged
+        uint64_t incarnation = column_incarnation(remote_col_version);
+        const ColumnVersion *lifecycle = lifecycle_version(record_id, get_record_ptr(record_id));
+        if (incarnation > (lifecycle ? lifecycle->col_version : 0)) {
+          reincarnate(record_id, ColumnVersion(incarnation, 0, CrdtNodeId{}, remote_local_db_version), false, events);
+        }
         if (!is_record_tombstoned(record_id)) {
           // Handle insertion or update
           Record<V> &record = get_or_create_record_unchecked(record_id);
@@ -801,29 +5563,182 @@ private:
           // Insert or update the field value
           if (remote_value.has_value()) {
             record.fields[*col_name] = std::move(remote_value.value());
+          } else {
+            record.fields.erase(*col_name);
           }
 
           // Update the column version info
-          record.column_versions.insert_or_assign(std::move(*col_name), ColumnVersion(remote_col_version, remote_db_version,
-                                                                                      remote_node_id, remote_local_db_version));
+          record.column_versions.insert_or_assign(
+              std::move(*col_name),
+              ColumnVersion(remote_col_version, remote_db_version, remote_node_id, remote_local_db_version, remote_seq));
+          note_change(record_id, remote_local_db_version);
+        }
+      }
+    }
+  }
+
+  // Visits every live record once; records present in this CRDT shadow the parent's version
+  template <typename Fn> void for_each_live_record(Fn &&fn) const { for_each_record(fn); }
+
+  static constexpr bool has_ordered_storage = requires(const CrdtMap<K, Record<V>> &map, const K &key) {
+    map.lower_bound(key);
+  };
+
+  // Collects live records with keys >= start for which `in_range` holds, sorted by key.
+  // `in_range` must hold for a contiguous run of keys starting at `start`.
+  template <typename InRange>
+  CrdtVector<std::pair<K, const Record<V> *>> collect_live_from(const K &start, InRange &&in_range) const {
+    CrdtVector<std::pair<K, const Record<V> *>> results;
+    for (const CRDT *level = this; level != nullptr; level = level->parent_.get()) {
+      auto visit = [&](const K &record_id, const Record<V> &record) {
+        for (const CRDT *child = this; child != level; child = child->parent_.get()) {
+          if (child->data_.find(record_id) != child->data_.end()) {
+            return;
+          }
+        }
+        if (!is_record_tombstoned(record_id)) {
+          results.emplace_back(record_id, &record);
+        }
+      };
+
+      if constexpr (has_ordered_storage) {
+        for (auto it = level->data_.lower_bound(start); it != level->data_.end() && in_range(it->first); ++it) {
+          visit(it->first, it->second);
+        }
+      } else {
+        for (const auto &[record_id, record] : level->data_) {
+          if (!(record_id < start) && in_range(record_id)) {
+            visit(record_id, record);
+          }
         }
       }
     }
+
+    if (!has_ordered_storage || parent_) {
+      std::sort(results.begin(), results.end(), [](const auto &a, const auto &b) { return a.first < b.first; });
+    }
+    return results;
   }
 
   constexpr bool is_record_tombstoned(const K &record_id, bool ignore_parent = false) const {
-    if (tombstones_.find(record_id) != tombstones_.end()) {
-      return true;
+    return find_tombstone(record_id, ignore_parent) != nullptr;
+  }
+
+  // Records and tombstones present in this CRDT shadow the parent's version
+  // Orders record ids for display: by value where K is ordered, otherwise by their rendering
+  static bool record_less(const K &a, const K &b) {
+    if constexpr (std::totally_ordered<K>) {
+      return a < b;
+    } else {
+      return crdt_format::to_text(a) < crdt_format::to_text(b);
+    }
+  }
+
+  // Writes records for format_record and operator<<, live ones column by column in column name order
+  void write_records(std::ostream &os, const CrdtVector<K> &record_ids, CrdtFormatStyle style) const {
+    CrdtVector<CrdtVector<CrdtString>> rows = {{"record", "column", "value", "col_version", "db_version", "node",
+                                                "local_db_version"}};
+    auto version_cells = [](const ColumnVersion &version) {
+      return CrdtVector<CrdtString>{std::to_string(version.col_version), std::to_string(version.db_version),
+                                    crdt_format::to_text(version.node_id), std::to_string(version.local_db_version)};
+    };
+    for (const K &record_id : record_ids) {
+      CrdtString id = crdt_format::to_text(record_id);
+      if (const CrdtTombstone *tombstone = find_tombstone(record_id)) {
+        if (style == CrdtFormatStyle::Text) {
+          os << id << ": " << *tombstone << "\n";
+        } else {
+          rows.push_back({id, "-", "(deleted)"});
+          std::ranges::copy(version_cells(tombstone->version), std::back_inserter(rows.back()));
+        }
+        continue;
+      }
+      const Record<V> *record = get_record_ptr(record_id);
+      if (record == nullptr) {
+        if (style == CrdtFormatStyle::Text) {
+          os << id << ": unknown\n";
+        } else {
+          rows.push_back({id, "-", "(unknown)", "-", "-", "-", "-"});
+        }
+        continue;
+      }
+      std::map<CrdtString, const ColumnVersion *> columns;
+      for (const auto &[col_name, version] : record->column_versions) {
+        columns.emplace(col_name, &version);
+      }
+      if (style == CrdtFormatStyle::Text) {
+        os << id << "\n";
+        if (record->revival) {
+          os << "  (revived " << *record->revival << ")\n";
+        }
+      } else if (record->revival) {
+        rows.push_back({id, "-", "(revived)"});
+        std::ranges::copy(version_cells(*record->revival), std::back_inserter(rows.back()));
+      }
+      for (const auto &[col_name, version] : columns) {
+        auto field = record->fields.find(col_name);
+        CrdtString value = field != record->fields.end() ? crdt_format::to_text(field->second) : "-";
+        if (style == CrdtFormatStyle::Text) {
+          os << "  " << col_name << ": " << value << " (" << *version << ")\n";
+        } else {
+          rows.push_back({id, col_name, value});
+          std::ranges::copy(version_cells(*version), std::back_inserter(rows.back()));
+        }
+      }
     }
-    if (parent_ && !ignore_parent) {
-      return parent_->is_record_tombstoned(record_id);
+    if (style == CrdtFormatStyle::Table) {
+      crdt_format::write_table(os, rows);
+    }
+  }
+
+  const CrdtTombstone *find_tombstone(const K &record_id, bool ignore_parent = false) const {
+    auto it = tombstones_.find(record_id);
+    if (it != tombstones_.end()) {
+      return &it->second;
+    }
+    if (parent_ && !ignore_parent && data_.find(record_id) == data_.end()) {
+      return parent_->find_tombstone(record_id);
+    }
+    return nullptr;
+  }
+
+  // Replaces whatever is stored for a record with a tombstone
+  void store_tombstone(const K &record_id, CrdtTombstone tombstone) {
+    count_transition(status(record_id), RecordStatus::Tombstoned);
+    data_.erase(record_id);
+    tombstones_.insert_or_assign(record_id, std::move(tombstone));
+  }
+
+  // Keeps `live_count_` and `tombstone_count_` in step with a record whose status changes from `before` to `after`
+  void count_transition(RecordStatus before, RecordStatus after) {
+    if (before == after) {
+      return;
+    }
+    if (before == RecordStatus::Live) {
+      --live_count_;
+    } else if (before == RecordStatus::Tombstoned) {
+      --tombstone_count_;
+    }
+    if (after == RecordStatus::Live) {
+      ++live_count_;
+    } else if (after == RecordStatus::Tombstoned) {
+      ++tombstone_count_;
     }
-    return false;
   }
 
+  // True if the counts are exact: nothing below this CRDT can change but through it
+  bool counts_exact() const { return !parent_ || parent_->snapshot_layer_; }
+
   // Notice that this will not check if the record is tombstoned! Such check should be done by the caller
   constexpr Record<V> &get_or_create_record_unchecked(const K &record_id, bool ignore_parent = false) {
     auto [it, inserted] = data_.try_emplace(record_id);
+    if (inserted) {
+      // It was not stored here, so it was tombstoned here or had the parent's status
+      RecordStatus before = tombstones_.contains(record_id) ? RecordStatus::Tombstoned
+                            : parent_                       ? parent_->status(record_id)
+                                                            : RecordStatus::Unknown;
+      count_transition(before, RecordStatus::Live);
+    }
     if (inserted && parent_ && !ignore_parent) {
       if (auto parent_record = parent_->get_record_ptr(record_id)) {
         it->second = *parent_record;
@@ -837,12 +5752,24 @@ private:
     if (it != data_.end()) {
       return &(it->second);
     }
-    if (ignore_parent) {
+    if (ignore_parent || tombstones_.find(record_id) != tombstones_.end()) {
       return nullptr;
     } else {
       return parent_ ? parent_->get_record_ptr(record_id) : nullptr;
     }
   }
+
+  constexpr const Record<V> *get_record_ptr(const K &record_id, bool ignore_parent = false) const {
+    auto it = data_.find(record_id);
+    if (it != data_.end()) {
+      return &(it->second);
+    }
+    if (ignore_parent || tombstones_.find(record_id) != tombstones_.end()) {
+      return nullptr;
+    } else {
+      return parent_ ? static_cast<const CRDT &>(*parent_).get_record_ptr(record_id) : nullptr;
+    }
+  }
 };
 
 /// Synchronizes two CRDT nodes.
@@ -0,0 +1,507 @@
+// crdt_archive.hpp
+#ifndef CRDT_ARCHIVE_HPP
+#define CRDT_ARCHIVE_HPP
+
+// Archived snapshots that are queried in place, for fast cold starts of large read-mostly replicas.
+//
+// `crdt_archive::save` writes a CRDT's state to a file laid out for reading without deserializing it: every record and
+// tombstone is encoded on its own, followed by an index of fixed-size entries sorted by the hash of the record id. A
+// CrdtArchive maps the file into memory (or reads it whole where mmap is unavailable) and only checks its header, so
+// opening one costs O(1) whatever its size. A lookup binary searches the index and decodes just the record asked for,
+// and `get_value` just the column asked for; the operating system pages in the parts of the file that are touched.
+// When the replica needs to write again, `to_crdt` decodes the archive into a regular CRDT.
+//
+// Every entry carries a checksum over its record id, values and versions. `to_crdt` and `verify` check all of them,
+// and lookups check the entries they decode when the archive is opened with `verify_reads`, so bit rot or a faulty
+// storage backend is detected before corrupted records are loaded and replicated to other nodes.
+//
+// File format (integers big-endian): the magic "CRDTARC1", a u32 format version, a u32 of flags, and the u64 clock,
+// live record count, tombstone count and index offset. Then the entries: each the record id's CrdtCodec bytes as a blob
+// and a kind byte, followed for a live record by a varint field count and per field its name and value (CrdtCodec, as a
+// blob so other columns can be skipped), then a varint version count and per version its column name and version; or
+// for a tombstone by its version and wall time. A version is the varints col_version, db_version and local_db_version
+// and the node id, followed with the SEQS flag by the varint sequence number. With the REVIVALS flag a live record ends
+// with a byte telling whether it was revived, followed by its revival version if so; archives without it hold the
+// revival as a "__deleted__" column. With the CHECKSUMS flag, which archives written before checksums lack, each entry
+// is written as a blob followed by the u64 checksum of its bytes. Last the index: per entry a u64 hash of the encoded
+// record id and the u64 offset of the entry, sorted by hash.
+
+#include "crdt_codec.hpp"
+
+#include <filesystem>
+#include <fstream>
+
+#if __has_include(<sys/mman.h>)
+#include <fcntl.h>
+#include <sys/mman.h>
+#include <sys/stat.h>
+#include <unistd.h>
+#define CRDT_ARCHIVE_MMAP 1
+#endif
+
+namespace crdt_archive {
+
+constexpr char MAGIC[8] = {'C', 'R', 'D', 'T', 'A', 'R', 'C', '1'};
+constexpr uint32_t FORMAT_VERSION = 1;
+constexpr size_t HEADER_SIZE = 48;
+constexpr size_t INDEX_ENTRY_SIZE = 16;
+constexpr uint32_t FLAG_CHECKSUMS = 1; // entries are framed and checksummed; archives written before lack it
+constexpr uint32_t FLAG_REVIVALS = 2;  // revival versions are stored apart from the columns
+constexpr uint32_t FLAG_SEQS = 4;      // versions end with the writer's sequence number
+
+enum class EntryKind : uint8_t {
+  Live = 1,
+  Tombstone = 2,
+};
+
+template <typename K> CrdtBytes encode_key(const K &record_id) {
+  CrdtBytes bytes;
+  ByteWriter writer(bytes);
+  CrdtCodec<K>::encode(writer, record_id);
+  return bytes;
+}
+
+inline uint64_t key_hash(const CrdtBytes &key) {
+  CrdtHasher hasher;
+  hasher.update(key.data(), key.size());
+  return hasher.finish();
+}
+
+inline uint64_t entry_checksum(const uint8_t *entry, size_t size) {
+  CrdtHasher hasher;
+  hasher.update(entry, size);
+  return CrdtHasher::mix(hasher.finish());
+}
+
+inline void write_version(ByteWriter &writer, const ColumnVersion &version) {
+  writer.write_varint(version.col_version);
+  writer.write_varint(version.db_version);
+  writer.write_varint(version.local_db_version);
+  CrdtCodec<CrdtNodeId>::encode(writer, version.node_id);
+  writer.write_varint(version.seq);
+}
+
+// `seqs` tells whether the archive has FLAG_SEQS
+inline ColumnVersion read_version(ByteReader &reader, bool seqs) {
+  uint64_t col_version = reader.read_varint();
+  uint64_t db_version = reader.read_varint();
+  uint64_t local_db_version = reader.read_varint();
+  CrdtNodeId node_id = CrdtCodec<CrdtNodeId>::decode(reader);
+  return ColumnVersion(col_version, db_version, node_id, local_db_version, seqs ? reader.read_varint() : 0);
+}
+
+/// Writes the state of `crdt` as an archive at `path`, replacing the file only once it is complete. Entries are
+/// written as the records are visited, so besides the index (16 bytes per record) no copy of the state is held.
+///
+/// Throws std::runtime_error if the file cannot be written.
+///
+/// Complexity: O(n * m + n log n), where n is the number of records and m is the average number of columns per record
+template <typename CrdtType> void save(const CrdtType &crdt, const std::filesystem::path &path) {
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+  std::filesystem::path temporary = path;
+  temporary += ".tmp";
+  std::ofstream out(temporary, std::ios::binary | std::ios::trunc);
+  out.write(MAGIC, sizeof(MAGIC)); // the rest of the header follows once the index offset is known
+  out.write(CrdtString(HEADER_SIZE - sizeof(MAGIC), '\0').data(), HEADER_SIZE - sizeof(MAGIC));
+
+  CrdtVector<std::pair<uint64_t, uint64_t>> index; // hash and offset
+  index.reserve(crdt.len_live() + crdt.len_tombstones());
+  uint64_t offset = HEADER_SIZE;
+  CrdtBytes entry;
+  CrdtBytes frame;
+  auto write_entry = [&](const K &record_id, EntryKind kind, auto &&body) {
+    entry.clear();
+    ByteWriter writer(entry);
+    CrdtBytes key = encode_key(record_id);
+    writer.write_blob(key.data(), key.size());
+    writer.write_u8(static_cast<uint8_t>(kind));
+    body(writer);
+    frame.clear();
+    ByteWriter frame_writer(frame);
+    frame_writer.write_blob(entry.data(), entry.size());
+    frame_writer.write_u64_be(entry_checksum(entry.data(), entry.size()));
+    out.write(reinterpret_cast<const char *>(frame.data()), static_cast<std::streamsize>(frame.size()));
+    index.emplace_back(key_hash(key), offset);
+    offset += frame.size();
+  };
+  size_t live = 0;
+  crdt.for_each_record([&](const K &record_id, const Record<V> &record) {
+    ++live;
+    write_entry(record_id, EntryKind::Live, [&](ByteWriter &writer) {
+      writer.write_varint(record.fields.size());
+      for (const auto &[col_name, value] : record.fields) {
+        CrdtCodec<CrdtString>::encode(writer, col_name);
+        crdt_codec::encode_value<V>(writer, value, true);
+      }
+      writer.write_varint(record.column_versions.size());
+      for (const auto &[col_name, version] : record.column_versions) {
+        CrdtCodec<CrdtString>::encode(writer, col_name);
+        write_version(writer, version);
+      }
+      writer.write_u8(record.revival ? 1 : 0);
+      if (record.revival) {
+        write_version(writer, *record.revival);
+      }
+    });
+  });
+  crdt.for_each_tombstone([&](const K &record_id, const CrdtTombstone &tombstone) {
+    write_entry(record_id, EntryKind::Tombstone, [&](ByteWriter &writer) {
+      write_version(writer, tombstone.version);
+      writer.write_varint(tombstone.wall_time);
+    });
+  });
+
+  std::sort(index.begin(), index.end());
+  CrdtBytes tail;
+  ByteWriter tail_writer(tail);
+  for (const auto &[hash, entry_offset] : index) {
+    tail_writer.write_u64_be(hash);
+    tail_writer.write_u64_be(entry_offset);
+  }
+  out.write(reinterpret_cast<const char *>(tail.data()), static_cast<std::streamsize>(tail.size()));
+
+  CrdtBytes header;
+  ByteWriter header_writer(header);
+  header_writer.write_u32_be(FORMAT_VERSION);
+  header_writer.write_u32_be(FLAG_CHECKSUMS | FLAG_REVIVALS | FLAG_SEQS);
+  header_writer.write_u64_be(crdt.get_clock().current_time());
+  header_writer.write_u64_be(live);
+  header_writer.write_u64_be(index.size() - live);
+  header_writer.write_u64_be(offset);
+  out.seekp(sizeof(MAGIC));
+  out.write(reinterpret_cast<const char *>(header.data()), static_cast<std::streamsize>(header.size()));
+  out.flush();
+  if (!out) {
+    throw std::runtime_error("failed to write archive");
+  }
+  out.close();
+  std::filesystem::rename(temporary, path);
+}
+
+} // namespace crdt_archive
+
+/// Thrown when an archive entry does not match its checksum.
+class CrdtChecksumError : public CrdtDecodeError {
+public:
+  explicit CrdtChecksumError(uint64_t offset)
+      : CrdtDecodeError("archive entry checksum mismatch"), offset_(offset) {}
+
+  /// The offset of the corrupted entry in the file.
+  uint64_t offset() const { return offset_; }
+
+private:
+  uint64_t offset_;
+};
+
+/// A read-only view of an archive written by `crdt_archive::save`. Lookups decode only the entry they need, and
+/// concurrent reads are safe since nothing is modified after opening.
+template <typename CrdtType> class CrdtArchive {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+
+  /// Opens the archive at `path`, checking its header and size but none of its entries.
+  ///
+  /// # Arguments
+  ///
+  /// * `verify_reads` - Whether lookups check the checksum of every entry they decode, throwing CrdtChecksumError on
+  ///   a mismatch. This costs a pass over each entry read, e.g. all of a record's columns for `get_value`.
+  ///
+  /// Throws std::runtime_error if the file cannot be read and CrdtDecodeError if it is not a valid archive.
+  explicit CrdtArchive(const std::filesystem::path &path, bool verify_reads = false) : verify_reads_(verify_reads) {
+    map(path);
+    try {
+      read_header();
+    } catch (...) {
+      unmap();
+      throw;
+    }
+  }
+
+  ~CrdtArchive() { unmap(); }
+
+  CrdtArchive(const CrdtArchive &) = delete;
+  CrdtArchive &operator=(const CrdtArchive &) = delete;
+
+  /// The clock of the archived CRDT.
+  uint64_t clock_time() const { return clock_time_; }
+
+  size_t len_live() const { return static_cast<size_t>(live_); }
+  size_t len_tombstones() const { return static_cast<size_t>(tombstones_); }
+
+  /// Reports whether a record is live, tombstoned, or unknown.
+  ///
+  /// Throws CrdtDecodeError if the entries looked at are malformed.
+  ///
+  /// Complexity: O(log n), where n is the number of entries
+  RecordStatus status(const K &record_id) const {
+    std::optional<ByteReader> entry = find(record_id);
+    if (!entry) {
+      return RecordStatus::Unknown;
+    }
+    return read_kind(*entry) == crdt_archive::EntryKind::Live ? RecordStatus::Live : RecordStatus::Tombstoned;
+  }
+
+  bool contains_record(const K &record_id) const { return status(record_id) == RecordStatus::Live; }
+
+  /// Decodes a live record.
+  ///
+  /// # Returns
+  ///
+  /// The record, or std::nullopt if it is tombstoned or unknown.
+  ///
+  /// Complexity: O(log n + m), where n is the number of entries and m is the number of columns in the record
+  std::optional<Record<V>> get_record(const K &record_id) const {
+    std::optional<ByteReader> entry = find(record_id);
+    if (!entry || read_kind(*entry) != crdt_archive::EntryKind::Live) {
+      return std::nullopt;
+    }
+    return read_record(*entry);
+  }
+
+  /// Decodes one field of a live record, skipping the others without decoding them.
+  ///
+  /// Complexity: O(log n + m), where n is the number of entries and m is the number of columns in the record
+  std::optional<V> get_value(const K &record_id, const CrdtString &col_name) const {
+    std::optional<ByteReader> entry = find(record_id);
+    if (!entry || read_kind(*entry) != crdt_archive::EntryKind::Live) {
+      return std::nullopt;
+    }
+    uint64_t fields = entry->read_varint();
+    for (uint64_t i = 0; i < fields; ++i) {
+      auto [name, name_size] = entry->read_blob();
+      auto [value, value_size] = entry->read_blob();
+      if (name_size == col_name.size() && std::memcmp(name, col_name.data(), name_size) == 0) {
+        ByteReader value_reader(value, value + value_size);
+        return CrdtCodec<V>::decode(value_reader);
+      }
+    }
+    return std::nullopt;
+  }
+
+  /// Whether the entries carry checksums, i.e. the archive was not written by a version without them.
+  bool has_checksums() const { return checksums_; }
+
+  /// Checks the checksum of every entry, e.g. before serving an archive that was copied from elsewhere.
+  ///
+  /// Throws CrdtChecksumError for the first corrupted entry and CrdtDecodeError if the framing is malformed.
+  ///
+  /// Complexity: O(s), where s is the size of the archive
+  void verify() const {
+    for (uint64_t i = 0; i < live_ + tombstones_; ++i) {
+      entry_at(index_entry(i).second, true);
+    }
+  }
+
+  /// Calls `fn(record_id, record)` for every live record, decoding one at a time, in no particular order.
+  ///
+  /// Complexity: O(n * m), where n is the number of entries and m is the average number of columns per record
+  template <typename Fn>
+    requires std::invocable<Fn &, const K &, const Record<V> &>
+  void for_each_record(Fn &&fn) const {
+    for (uint64_t i = 0; i < live_ + tombstones_; ++i) {
+      ByteReader entry = entry_at(index_entry(i).second, verify_reads_);
+      K record_id = read_key(entry);
+      if (read_kind(entry) == crdt_archive::EntryKind::Live) {
+        fn(record_id, read_record(entry));
+      }
+    }
+  }
+
+  /// Decodes the whole archive into a CRDT for node `node_id`, e.g. to resume writing. Every entry's checksum is
+  /// checked, so a corrupted archive is never loaded.
+  ///
+  /// Throws CrdtChecksumError if an entry is corrupted and CrdtDecodeError if one is malformed.
+  ///
+  /// Complexity: O(n * m), where n is the number of entries and m is the average number of columns per record
+  CrdtType to_crdt(CrdtNodeId node_id) const {
+    CrdtMap<K, Record<V>> data;
+    CrdtMap<K, CrdtTombstone> tombstones;
+    for (uint64_t i = 0; i < live_ + tombstones_; ++i) {
+      ByteReader entry = entry_at(index_entry(i).second, true);
+      K record_id = read_key(entry);
+      if (read_kind(entry) == crdt_archive::EntryKind::Live) {
+        data.emplace(std::move(record_id), read_record(entry));
+      } else {
+        ColumnVersion version = crdt_archive::read_version(entry, seqs_);
+        tombstones.emplace(std::move(record_id), CrdtTombstone{version, entry.read_varint()});
+      }
+    }
+    return CrdtType::from_parts(node_id, clock_time_, std::move(data), std::move(tombstones));
+  }
+
+private:
+  const uint8_t *data_ = nullptr;
+  size_t size_ = 0;
+  CrdtBytes buffer_; // the file's contents where it is read instead of mapped
+  bool mapped_ = false;
+  uint64_t clock_time_ = 0;
+  uint64_t live_ = 0;
+  uint64_t tombstones_ = 0;
+  uint64_t index_offset_ = 0;
+  bool checksums_ = false;
+  bool revivals_ = false;
+  bool seqs_ = false;
+  bool verify_reads_ = false;
+
+  void map(const std::filesystem::path &path) {
+#ifdef CRDT_ARCHIVE_MMAP
+    int fd = ::open(path.c_str(), O_RDONLY);
+    if (fd < 0) {
+      throw std::runtime_error("failed to open archive");
+    }
+    struct stat info;
+    if (::fstat(fd, &info) != 0) {
+      ::close(fd);
+      throw std::runtime_error("failed to open archive");
+    }
+    size_ = static_cast<size_t>(info.st_size);
+    if (size_ > 0) {
+      void *mapping = ::mmap(nullptr, size_, PROT_READ, MAP_PRIVATE, fd, 0);
+      if (mapping != MAP_FAILED) {
+        data_ = static_cast<const uint8_t *>(mapping);
+        mapped_ = true;
+      }
+    }
+    ::close(fd);
+    if (mapped_ || size_ == 0) {
+      return;
+    }
+#endif
+    std::ifstream in(path, std::ios::binary);
+    if (!in) {
+      throw std::runtime_error("failed to open archive");
+    }
+    buffer_.assign(std::istreambuf_iterator<char>(in), std::istreambuf_iterator<char>());
+    data_ = buffer_.data();
+    size_ = buffer_.size();
+  }
+
+  void read_header() {
+    if (size_ < crdt_archive::HEADER_SIZE || std::memcmp(data_, crdt_archive::MAGIC, sizeof(crdt_archive::MAGIC)) != 0) {
+      throw CrdtDecodeError("not a CRDT archive");
+    }
+    ByteReader header(data_ + sizeof(crdt_archive::MAGIC), data_ + crdt_archive::HEADER_SIZE);
+    if (header.read_u32_be() != crdt_archive::FORMAT_VERSION) {
+      throw CrdtDecodeError("unsupported archive version");
+    }
+    uint32_t flags = header.read_u32_be();
+    if ((flags & ~(crdt_archive::FLAG_CHECKSUMS | crdt_archive::FLAG_REVIVALS | crdt_archive::FLAG_SEQS)) != 0) {
+      throw CrdtDecodeError("unsupported archive flags");
+    }
+    checksums_ = (flags & crdt_archive::FLAG_CHECKSUMS) != 0;
+    revivals_ = (flags & crdt_archive::FLAG_REVIVALS) != 0;
+    seqs_ = (flags & crdt_archive::FLAG_SEQS) != 0;
+    clock_time_ = header.read_u64_be();
+    live_ = header.read_u64_be();
+    tombstones_ = header.read_u64_be();
+    index_offset_ = header.read_u64_be();
+    uint64_t entries = live_ + tombstones_;
+    if (entries < live_ || index_offset_ < crdt_archive::HEADER_SIZE || index_offset_ > size_ ||
+        (size_ - index_offset_) / crdt_archive::INDEX_ENTRY_SIZE != entries ||
+        (size_ - index_offset_) % crdt_archive::INDEX_ENTRY_SIZE != 0) {
+      throw CrdtDecodeError("archive index does not match its header");
+    }
+  }
+
+  void unmap() {
+#ifdef CRDT_ARCHIVE_MMAP
+    if (mapped_) {
+      ::munmap(const_cast<uint8_t *>(data_), size_);
+    }
+#endif
+  }
+
+  // The hash and entry offset of the i-th index entry
+  std::pair<uint64_t, uint64_t> index_entry(uint64_t i) const {
+    const uint8_t *position = data_ + index_offset_ + i * crdt_archive::INDEX_ENTRY_SIZE;
+    ByteReader reader(position, position + crdt_archive::INDEX_ENTRY_SIZE);
+    uint64_t hash = reader.read_u64_be();
+    return {hash, reader.read_u64_be()};
+  }
+
+  // A reader over the entry at `offset`, bounded by its frame or, in archives without checksums, by the index
+  ByteReader entry_at(uint64_t offset, bool verify) const {
+    if (offset < crdt_archive::HEADER_SIZE || offset >= index_offset_) {
+      throw CrdtDecodeError("archive entry offset out of range");
+    }
+    ByteReader reader(data_ + offset, data_ + index_offset_);
+    if (!checksums_) {
+      return reader;
+    }
+    auto [entry, entry_size] = reader.read_blob();
+    uint64_t checksum = reader.read_u64_be();
+    if (verify && checksum != crdt_archive::entry_checksum(entry, entry_size)) {
+      throw CrdtChecksumError(offset);
+    }
+    return ByteReader(entry, entry + entry_size);
+  }
+
+  // A reader positioned after the record id of the entry for `record_id`, if there is one
+  std::optional<ByteReader> find(const K &record_id) const {
+    CrdtBytes key = crdt_archive::encode_key(record_id);
+    uint64_t hash = crdt_archive::key_hash(key);
+    uint64_t low = 0;
+    uint64_t high = live_ + tombstones_;
+    while (low < high) {
+      uint64_t middle = low + (high - low) / 2;
+      if (index_entry(middle).first < hash) {
+        low = middle + 1;
+      } else {
+        high = middle;
+      }
+    }
+    // Entries whose record ids collide on the hash are adjacent
+    for (uint64_t i = low; i < live_ + tombstones_ && index_entry(i).first == hash; ++i) {
+      ByteReader entry = entry_at(index_entry(i).second, verify_reads_);
+      auto [stored, stored_size] = entry.read_blob();
+      if (stored_size == key.size() && std::memcmp(stored, key.data(), key.size()) == 0) {
+        return entry;
+      }
+    }
+    return std::nullopt;
+  }
+
+  static K read_key(ByteReader &entry) {
+    auto [key, key_size] = entry.read_blob();
+    ByteReader key_reader(key, key + key_size);
+    return CrdtCodec<K>::decode(key_reader);
+  }
+
+  static crdt_archive::EntryKind read_kind(ByteReader &entry) {
+    uint8_t kind = entry.read_u8();
+    if (kind != static_cast<uint8_t>(crdt_archive::EntryKind::Live) &&
+        kind != static_cast<uint8_t>(crdt_archive::EntryKind::Tombstone)) {
+      throw CrdtDecodeError("unknown archive entry kind");
+    }
+    return static_cast<crdt_archive::EntryKind>(kind);
+  }
+
+  Record<V> read_record(ByteReader &entry) const {
+    Record<V> record;
+    uint64_t fields = entry.read_varint();
+    for (uint64_t i = 0; i < fields; ++i) {
+      CrdtString col_name = CrdtCodec<CrdtString>::decode(entry);
+      auto [value, value_size] = entry.read_blob();
+      ByteReader value_reader(value, value + value_size);
+      record.fields.emplace(std::move(col_name), CrdtCodec<V>::decode(value_reader));
+    }
+    uint64_t versions = entry.read_varint();
+    for (uint64_t i = 0; i < versions; ++i) {
+      CrdtString col_name = CrdtCodec<CrdtString>::decode(entry);
+      record.column_versions.emplace(std::move(col_name), crdt_archive::read_version(entry, seqs_));
+    }
+    if (revivals_) {
+      if (entry.read_u8() != 0) {
+        record.revival = crdt_archive::read_version(entry, seqs_);
+      }
+    } else if (auto legacy = record.column_versions.find("__deleted__"); legacy != record.column_versions.end()) {
+      record.revival = legacy->second;
+      record.column_versions.erase(legacy);
+    }
+    return record;
+  }
+};
+
+#endif // CRDT_ARCHIVE_HPP
@@ -0,0 +1,387 @@
+// crdt_arrow.hpp
+#ifndef CRDT_ARROW_HPP
+#define CRDT_ARROW_HPP
+
+// Arrow export of the live records, so replica state can be queried with DuckDB, Polars or Spark without an ETL step.
+//
+// `crdt_arrow::export_batches` converts the live records into Arrow record batches through the Arrow C data interface,
+// a stable ABI that every Arrow implementation imports without copying (`arrow::ImportRecordBatch` in C++,
+// `pyarrow.RecordBatch._import_from_c`, DuckDB's Arrow scans, ...), so no Arrow library is needed to produce them. A
+// batch has a `record_id` column followed by the columns of the given schema; a field that is missing, or does not
+// convert to its column's type, is null. Values convert from strings (parsed in full, like CrdtSchema's type checks),
+// arithmetic types and variants holding either.
+//
+// Writing Parquet files is opt-in: define CRDT_WITH_PARQUET and link Arrow C++ and Parquet to use `write_parquet`.
+
+#include "crdt_codec.hpp"
+
+#include <charconv>
+#include <cstring>
+#include <memory>
+
+#ifdef CRDT_WITH_PARQUET
+#include <arrow/c/bridge.h>
+#include <arrow/io/file.h>
+#include <parquet/arrow/writer.h>
+#endif
+
+#ifndef ARROW_C_DATA_INTERFACE
+#define ARROW_C_DATA_INTERFACE
+
+#define ARROW_FLAG_DICTIONARY_ORDERED 1
+#define ARROW_FLAG_NULLABLE 2
+#define ARROW_FLAG_MAP_KEYS_SORTED 4
+
+struct ArrowSchema {
+  const char *format;
+  const char *name;
+  const char *metadata;
+  int64_t flags;
+  int64_t n_children;
+  struct ArrowSchema **children;
+  struct ArrowSchema *dictionary;
+  void (*release)(struct ArrowSchema *);
+  void *private_data;
+};
+
+struct ArrowArray {
+  int64_t length;
+  int64_t null_count;
+  int64_t offset;
+  int64_t n_buffers;
+  int64_t n_children;
+  const void **buffers;
+  struct ArrowArray **children;
+  struct ArrowArray *dictionary;
+  void (*release)(struct ArrowArray *);
+  void *private_data;
+};
+
+#endif // ARROW_C_DATA_INTERFACE
+
+enum class CrdtArrowType {
+  Utf8,
+  Int64,
+  Float64,
+  Boolean,
+};
+
+/// A column of the exported batches: the record field `name`, converted to `type`.
+struct CrdtArrowColumn {
+  CrdtString name;
+  CrdtArrowType type;
+};
+
+namespace crdt_arrow {
+
+/// Converts a value to `T` (CrdtString, int64_t, double or bool), or returns std::nullopt if it does not convert.
+template <typename T, typename V> std::optional<T> convert(const V &value) {
+  if constexpr (CrdtIsVariant<V>::value) {
+    return std::visit([](const auto &alternative) { return convert<T>(alternative); }, value);
+  } else if constexpr (std::is_same_v<V, T>) {
+    return value;
+  } else if constexpr (std::is_same_v<T, CrdtString>) {
+    if constexpr (std::is_same_v<V, bool>) {
+      return CrdtString(value ? "true" : "false");
+    } else if constexpr (std::is_arithmetic_v<V>) {
+      return std::to_string(value);
+    } else {
+      return std::nullopt;
+    }
+  } else if constexpr (std::is_same_v<V, CrdtString>) {
+    if constexpr (std::is_same_v<T, bool>) {
+      if (value == "true" || value == "1") {
+        return true;
+      }
+      return value == "false" || value == "0" ? std::optional<bool>(false) : std::nullopt;
+    } else {
+      T parsed{};
+      auto [end, error] = std::from_chars(value.data(), value.data() + value.size(), parsed);
+      return error == std::errc() && end == value.data() + value.size() ? std::optional<T>(parsed) : std::nullopt;
+    }
+  } else if constexpr (std::is_same_v<T, double> && std::is_arithmetic_v<V> && !std::is_same_v<V, bool>) {
+    return static_cast<double>(value);
+  } else if constexpr (std::is_same_v<T, int64_t> && std::is_integral_v<V> && !std::is_same_v<V, bool>) {
+    return static_cast<int64_t>(value);
+  } else {
+    return std::nullopt;
+  }
+}
+
+/// The type of the `record_id` column for record ids of type `K`: Int64 for integers, Utf8 otherwise.
+template <typename K> constexpr CrdtArrowType record_id_type() {
+  return std::is_integral_v<K> && !std::is_same_v<K, bool> ? CrdtArrowType::Int64 : CrdtArrowType::Utf8;
+}
+
+inline const char *format_of(CrdtArrowType type) {
+  switch (type) {
+  case CrdtArrowType::Utf8:
+    return "u";
+  case CrdtArrowType::Int64:
+    return "l";
+  case CrdtArrowType::Float64:
+    return "g";
+  case CrdtArrowType::Boolean:
+    return "b";
+  }
+  return "n";
+}
+
+// Owned by an exported ArrowSchema: its name and children
+struct SchemaData {
+  CrdtString name;
+  CrdtVector<ArrowSchema> children;
+  CrdtVector<ArrowSchema *> child_pointers;
+};
+
+inline void release_schema(ArrowSchema *schema) {
+  auto *data = static_cast<SchemaData *>(schema->private_data);
+  for (ArrowSchema *child : data->child_pointers) {
+    if (child->release) {
+      child->release(child);
+    }
+  }
+  delete data;
+  schema->release = nullptr;
+}
+
+inline ArrowSchema make_schema(const char *format, CrdtString name, int64_t flags) {
+  auto *data = new SchemaData{std::move(name), {}, {}};
+  return ArrowSchema{format, data->name.c_str(), nullptr, flags, 0, nullptr, nullptr, release_schema, data};
+}
+
+// Owned by an exported ArrowArray: its buffers and children
+struct ArrayData {
+  CrdtVector<CrdtBytes> buffers;
+  CrdtVector<const void *> buffer_pointers;
+  CrdtVector<ArrowArray> children;
+  CrdtVector<ArrowArray *> child_pointers;
+};
+
+inline void release_array(ArrowArray *array) {
+  auto *data = static_cast<ArrayData *>(array->private_data);
+  for (ArrowArray *child : data->child_pointers) {
+    if (child->release) {
+      child->release(child);
+    }
+  }
+  delete data;
+  array->release = nullptr;
+}
+
+// Collects the values of one column for a batch
+class ColumnBuilder {
+public:
+  explicit ColumnBuilder(CrdtArrowType type) : type_(type) {}
+
+  template <typename V> void append(const V *value) {
+    bool valid = false;
+    if (value != nullptr) {
+      switch (type_) {
+      case CrdtArrowType::Utf8:
+        valid = push(convert<CrdtString>(*value));
+        break;
+      case CrdtArrowType::Int64:
+        valid = push(convert<int64_t>(*value));
+        break;
+      case CrdtArrowType::Float64:
+        valid = push(convert<double>(*value));
+        break;
+      case CrdtArrowType::Boolean:
+        valid = push(convert<bool>(*value));
+        break;
+      }
+    }
+    if (!valid) {
+      push_null();
+    }
+    set_bit(validity_, length_, valid);
+    ++length_;
+  }
+
+  // Moves the collected values into an array and starts a new one
+  ArrowArray finish() {
+    auto *data = new ArrayData;
+    data->buffers.push_back(std::move(validity_));
+    if (type_ == CrdtArrowType::Utf8) {
+      CrdtBytes offsets(offsets_.size() * sizeof(int32_t));
+      std::memcpy(offsets.data(), offsets_.data(), offsets.size());
+      data->buffers.push_back(std::move(offsets));
+    }
+    data->buffers.push_back(std::move(values_));
+    for (CrdtBytes &buffer : data->buffers) {
+      buffer.reserve(1); // importers expect a valid pointer even for an empty buffer
+      data->buffer_pointers.push_back(buffer.data());
+    }
+    ArrowArray array{length_, nulls_, 0, static_cast<int64_t>(data->buffers.size()), 0, data->buffer_pointers.data(),
+                     nullptr, nullptr, release_array, data};
+    *this = ColumnBuilder(type_);
+    return array;
+  }
+
+private:
+  CrdtArrowType type_;
+  int64_t length_ = 0;
+  int64_t nulls_ = 0;
+  CrdtBytes validity_;
+  CrdtBytes values_;
+  CrdtVector<int32_t> offsets_{0}; // Utf8 only
+
+  static void set_bit(CrdtBytes &bits, int64_t index, bool value) {
+    if (index % 8 == 0) {
+      bits.push_back(0);
+    }
+    if (value) {
+      bits.back() |= static_cast<uint8_t>(1 << (index % 8));
+    }
+  }
+
+  template <typename T> bool push(const std::optional<T> &value) {
+    if (!value) {
+      return false;
+    }
+    if constexpr (std::is_same_v<T, CrdtString>) {
+      if (values_.size() + value->size() > static_cast<size_t>(INT32_MAX)) {
+        throw std::length_error("Arrow string column exceeds 2 GiB; export smaller batches");
+      }
+      values_.insert(values_.end(), value->begin(), value->end());
+      offsets_.push_back(static_cast<int32_t>(values_.size()));
+    } else if constexpr (std::is_same_v<T, bool>) {
+      set_bit(values_, length_, *value);
+    } else {
+      uint8_t bytes[sizeof(T)];
+      std::memcpy(bytes, &*value, sizeof(T));
+      values_.insert(values_.end(), bytes, bytes + sizeof(T));
+    }
+    return true;
+  }
+
+  void push_null() {
+    ++nulls_;
+    if (type_ == CrdtArrowType::Utf8) {
+      offsets_.push_back(offsets_.back());
+    } else if (type_ == CrdtArrowType::Boolean) {
+      set_bit(values_, length_, false);
+    } else {
+      values_.insert(values_.end(), 8, 0);
+    }
+  }
+};
+
+/// Exports the schema of the batches `export_batches` produces for record ids of type `K`: a struct with a non-null
+/// `record_id` column and a nullable column per entry of `columns`. The caller owns `*out` and must release it.
+template <typename K> void export_schema(const CrdtVector<CrdtArrowColumn> &columns, ArrowSchema *out) {
+  *out = make_schema("+s", "", 0);
+  auto *data = static_cast<SchemaData *>(out->private_data);
+  data->children.push_back(make_schema(format_of(record_id_type<K>()), "record_id", 0));
+  for (const CrdtArrowColumn &column : columns) {
+    data->children.push_back(make_schema(format_of(column.type), column.name, ARROW_FLAG_NULLABLE));
+  }
+  for (ArrowSchema &child : data->children) {
+    data->child_pointers.push_back(&child);
+  }
+  out->n_children = static_cast<int64_t>(data->child_pointers.size());
+  out->children = data->child_pointers.data();
+}
+
+/// Converts the live records of `crdt` into record batches of up to `batch_rows` rows, matching `export_schema`, and
+/// calls `fn(ArrowArray &batch)` with each. `fn` may import the batch, which moves it out; a batch still owned when
+/// `fn` returns is released, so only one batch is held at a time.
+///
+/// # Returns
+///
+/// The number of rows exported.
+///
+/// Complexity: O(n * c), where n is the number of records and c is the number of columns
+template <typename CrdtType, typename Fn>
+  requires std::invocable<Fn &, ArrowArray &>
+size_t export_batches(const CrdtType &crdt, const CrdtVector<CrdtArrowColumn> &columns, size_t batch_rows, Fn &&fn) {
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+  ColumnBuilder record_ids(record_id_type<K>());
+  CrdtVector<ColumnBuilder> builders;
+  for (const CrdtArrowColumn &column : columns) {
+    builders.emplace_back(column.type);
+  }
+  size_t rows = 0;
+  size_t pending = 0;
+  auto flush = [&] {
+    auto *data = new ArrayData;
+    data->buffer_pointers.push_back(nullptr); // a struct array has only a validity buffer, and no nulls
+    data->children.push_back(record_ids.finish());
+    for (ColumnBuilder &builder : builders) {
+      data->children.push_back(builder.finish());
+    }
+    for (ArrowArray &child : data->children) {
+      data->child_pointers.push_back(&child);
+    }
+    ArrowArray batch{static_cast<int64_t>(pending), 0, 0, 1, static_cast<int64_t>(data->child_pointers.size()),
+                     data->buffer_pointers.data(), data->child_pointers.data(), nullptr, release_array, data};
+    pending = 0;
+    try {
+      fn(batch);
+    } catch (...) {
+      if (batch.release) {
+        batch.release(&batch);
+      }
+      throw;
+    }
+    if (batch.release) {
+      batch.release(&batch);
+    }
+  };
+  crdt.iter_live([&](const K &record_id, const Record<V> &record) {
+    if constexpr (record_id_type<K>() == CrdtArrowType::Int64 || std::is_same_v<K, CrdtString>) {
+      record_ids.append(&record_id);
+    } else {
+      using std::to_string;
+      CrdtString text = to_string(record_id);
+      record_ids.append(&text);
+    }
+    for (size_t i = 0; i < columns.size(); ++i) {
+      auto it = record.fields.find(columns[i].name);
+      builders[i].append(it != record.fields.end() ? &it->second : static_cast<const V *>(nullptr));
+    }
+    ++rows;
+    if (++pending == std::max<size_t>(batch_rows, 1)) {
+      flush();
+    }
+  });
+  if (pending > 0) {
+    flush();
+  }
+  return rows;
+}
+
+#ifdef CRDT_WITH_PARQUET
+/// Writes the live records of `crdt` to a Parquet file at `path`, one row group per batch of `batch_rows` rows.
+///
+/// Throws std::runtime_error if Arrow or Parquet report an error.
+template <typename CrdtType>
+void write_parquet(const CrdtType &crdt, const CrdtVector<CrdtArrowColumn> &columns, const std::string &path,
+                   size_t batch_rows = 65536) {
+  auto check = [](const arrow::Status &status) {
+    if (!status.ok()) {
+      throw std::runtime_error(status.ToString());
+    }
+  };
+  auto unwrap = [&](auto &&result) {
+    check(result.status());
+    return std::move(result).ValueUnsafe();
+  };
+  ArrowSchema c_schema;
+  export_schema<typename CrdtType::KeyType>(columns, &c_schema);
+  std::shared_ptr<arrow::Schema> schema = unwrap(arrow::ImportSchema(&c_schema));
+  std::shared_ptr<arrow::io::FileOutputStream> file = unwrap(arrow::io::FileOutputStream::Open(path));
+  std::unique_ptr<parquet::arrow::FileWriter> writer =
+      unwrap(parquet::arrow::FileWriter::Open(*schema, arrow::default_memory_pool(), file));
+  export_batches(crdt, columns, batch_rows, [&](ArrowArray &batch) {
+    check(writer->WriteRecordBatch(*unwrap(arrow::ImportRecordBatch(&batch, schema))));
+  });
+  check(writer->Close());
+}
+#endif
+
+} // namespace crdt_arrow
+
+#endif // CRDT_ARROW_HPP
@@ -0,0 +1,164 @@
+// crdt_backpressure.hpp
+#ifndef CRDT_BACKPRESSURE_HPP
+#define CRDT_BACKPRESSURE_HPP
+
+// Rate limiting and backpressure for incoming merges.
+//
+// Merging is done on the thread that owns the CRDT, usually the one serving the user. A peer replaying a long history
+// in one go (a reinstalled client, a misbehaving or malicious node) would keep that thread merging for seconds on a
+// small device. A CrdtMergeQueue sits between the transport and the CRDT: the transport offers the change batches it
+// receives, and the owning thread calls `process` regularly (e.g. once per frame or event loop turn), which merges at
+// most `changes_per_second` changes over time, so interactive operations stay responsive.
+//
+// Oversized batches are rejected outright, and so is any batch that would queue more than `max_pending_changes`
+// changes. Before that, once `pause_above` changes are queued, the backpressure handler is told to pause: the transport
+// should stop reading from its peers (TCP then slows the senders down) until the handler is told to resume, once the
+// queue has drained to half that.
+//
+// Queued changes are not merged yet: a transport keeping sync cursors should persist them only once the queue is
+// empty, or the changes still queued at a crash would be skipped by the next session.
+
+#include "crdt.hpp"
+
+#include <chrono>
+
+struct CrdtMergeLimits {
+  uint64_t changes_per_second = 10000;      // merged at most, in bursts of up to a second's worth; 0 for no limit
+  size_t max_batch_bytes = 4 * 1024 * 1024; // larger batches are rejected
+  size_t max_pending_changes = 100000;      // batches that would queue more changes are rejected
+  size_t pause_above = 50000;               // the transport is paused once more changes are queued
+};
+
+template <typename CrdtType> class CrdtMergeQueue {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+  using Clock = std::chrono::steady_clock;
+  /// Called with true when the transport should stop reading incoming data, and with false when it can resume.
+  using BackpressureHandler = std::function<void(bool paused)>;
+
+  enum class Admission {
+    Queued,   // accepted
+    Paused,   // accepted, and the backpressure handler was told to pause
+    Rejected, // dropped: the batch is too large or the queue is full; the peer should resend it later
+  };
+
+  /// `crdt` must outlive the queue.
+  explicit CrdtMergeQueue(CrdtType &crdt, CrdtMergeLimits limits = {}, BackpressureHandler on_backpressure = {})
+      : crdt_(crdt), limits_(limits), on_backpressure_(std::move(on_backpressure)) {}
+
+  /// Queues a batch received from a peer.
+  ///
+  /// # Arguments
+  ///
+  /// * `changes` - The decoded changes.
+  /// * `bytes` - The size of the batch as received, checked against `max_batch_bytes`.
+  Admission offer(CrdtVector<Change<K, V>> changes, size_t bytes) {
+    if (bytes > limits_.max_batch_bytes || pending_changes_ + changes.size() > limits_.max_pending_changes) {
+      ++rejected_batches_;
+      return Admission::Rejected;
+    }
+    pending_changes_ += changes.size();
+    pending_.push_back(std::move(changes));
+    if (!paused_ && pending_changes_ > limits_.pause_above) {
+      set_paused(true);
+      return Admission::Paused;
+    }
+    return Admission::Queued;
+  }
+
+  /// Merges the queued changes the rate allows at `now`, in the order they arrived.
+  ///
+  /// # Returns
+  ///
+  /// The number of changes merged.
+  size_t process(Clock::time_point now = Clock::now()) {
+    refill(now);
+    size_t merged = 0;
+    CrdtVector<Change<K, V>> batch;
+    while (!pending_.empty() && (unlimited() || tokens_ >= 1)) {
+      CrdtVector<Change<K, V>> &front = pending_.front();
+      size_t take = front.size() - front_offset_;
+      if (!unlimited()) {
+        take = std::min(take, static_cast<size_t>(tokens_));
+        tokens_ -= static_cast<double>(take);
+      }
+      auto begin = front.begin() + static_cast<std::ptrdiff_t>(front_offset_);
+      batch.insert(batch.end(), std::make_move_iterator(begin),
+                   std::make_move_iterator(begin + static_cast<std::ptrdiff_t>(take)));
+      front_offset_ += take;
+      merged += take;
+      if (front_offset_ == front.size()) {
+        pending_.pop_front();
+        front_offset_ = 0;
+      }
+    }
+    if (!batch.empty()) {
+      crdt_.merge_changes(std::move(batch));
+    }
+    pending_changes_ -= merged;
+    merged_changes_ += merged;
+    if (paused_ && pending_changes_ <= limits_.pause_above / 2) {
+      set_paused(false);
+    }
+    return merged;
+  }
+
+  /// How long until `process` can merge again, zero if it can now or nothing is queued.
+  Clock::duration time_until_ready(Clock::time_point now = Clock::now()) {
+    refill(now);
+    if (pending_.empty() || unlimited() || tokens_ >= 1) {
+      return Clock::duration::zero();
+    }
+    auto missing = std::chrono::duration<double>((1 - tokens_) / static_cast<double>(limits_.changes_per_second));
+    return std::chrono::ceil<Clock::duration>(missing);
+  }
+
+  /// Number of changes queued and not merged yet.
+  size_t pending_changes() const { return pending_changes_; }
+
+  /// True while the transport is asked to pause.
+  bool paused() const { return paused_; }
+
+  uint64_t merged_changes() const { return merged_changes_; }
+
+  uint64_t rejected_batches() const { return rejected_batches_; }
+
+private:
+  CrdtType &crdt_;
+  CrdtMergeLimits limits_;
+  BackpressureHandler on_backpressure_;
+  std::deque<CrdtVector<Change<K, V>>> pending_;
+  size_t front_offset_ = 0; // changes of the first pending batch already merged
+  size_t pending_changes_ = 0;
+  bool paused_ = false;
+  double tokens_ = 0; // changes that can be merged now
+  std::optional<Clock::time_point> last_refill_;
+  uint64_t merged_changes_ = 0;
+  uint64_t rejected_batches_ = 0;
+
+  bool unlimited() const { return limits_.changes_per_second == 0; }
+
+  // Adds the changes allowed since the last refill, up to a second's worth
+  void refill(Clock::time_point now) {
+    double burst = static_cast<double>(limits_.changes_per_second);
+    if (!last_refill_) {
+      tokens_ = burst;
+    } else if (now > *last_refill_) {
+      double elapsed = std::chrono::duration<double>(now - *last_refill_).count();
+      tokens_ = std::min(burst, tokens_ + elapsed * burst);
+    }
+    if (!last_refill_ || now > *last_refill_) {
+      last_refill_ = now;
+    }
+  }
+
+  void set_paused(bool paused) {
+    paused_ = paused;
+    if (on_backpressure_) {
+      on_backpressure_(paused);
+    }
+  }
+};
+
+#endif // CRDT_BACKPRESSURE_HPP
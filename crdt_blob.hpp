@@ -0,0 +1,249 @@
+// crdt_blob.hpp
+#ifndef CRDT_BLOB_HPP
+#define CRDT_BLOB_HPP
+
+// Large binary values (images, attachments) stored as content-addressed chunks outside the record map.
+//
+// `CrdtBlobStore::put` splits a blob into chunks, stores each under the hash of its content and returns a small
+// manifest string listing the chunk hashes. The manifest is what gets written to the column, so changes, merges and
+// `get_changes_since` only ever copy the manifest, never the blob. A node that merged a manifest fetches the chunks it
+// lacks separately, with BlobRequest/BlobChunks frames that share the sync transport (a SyncSession ignores them):
+// `request_missing` builds the request and `on_frame` answers requests and stores the chunks received, after checking
+// their hashes. Identical chunks are stored once, however many records or versions refer to them.
+//
+// The default chunk hash is a non-cryptographic 128-bit FNV-1a; pass a cryptographic hash (e.g. SHA-256 from OpenSSL)
+// when peers are not trusted not to forge chunks.
+
+#include "crdt_sync.hpp"
+
+#include <functional>
+
+/// True if `frame` is a blob request or response rather than a sync message.
+inline bool is_blob_frame(const CrdtBytes &frame) {
+  return !frame.empty() && (frame[0] == static_cast<uint8_t>(SyncMessageType::BlobRequest) ||
+                            frame[0] == static_cast<uint8_t>(SyncMessageType::BlobChunks));
+}
+
+class CrdtBlobStore {
+public:
+  /// Returns the content hash of a chunk as a string without ':' or ','.
+  using ChunkHasher = std::function<CrdtString(const uint8_t *data, size_t size)>;
+
+  static constexpr const char *MANIFEST_PREFIX = "crdt-blob:";
+
+  /// # Arguments
+  ///
+  /// * `chunk_size` - The size of the chunks blobs are split into; every node should use the same size so identical
+  ///   blobs share their chunks.
+  /// * `hasher` - The chunk hash; every node must use the same one.
+  explicit CrdtBlobStore(size_t chunk_size = 256 * 1024, ChunkHasher hasher = fnv_hash)
+      : chunk_size_(chunk_size), hasher_(std::move(hasher)) {}
+
+  /// Stores `blob` and returns its manifest, to be written as the column value.
+  ///
+  /// Complexity: O(n), where n is the size of the blob
+  CrdtString put(const CrdtBytes &blob) {
+    CrdtString manifest = MANIFEST_PREFIX + std::to_string(blob.size()) + ":";
+    for (size_t offset = 0; offset < blob.size(); offset += chunk_size_) {
+      size_t size = std::min(chunk_size_, blob.size() - offset);
+      CrdtString hash = hasher_(blob.data() + offset, size);
+      chunks_.try_emplace(hash, blob.begin() + offset, blob.begin() + offset + size);
+      manifest += (offset > 0 ? "," : "") + hash;
+    }
+    return manifest;
+  }
+
+  /// Reassembles the blob of `manifest`, or returns std::nullopt if a chunk is missing or `manifest` is not one.
+  std::optional<CrdtBytes> get(const CrdtString &manifest) const {
+    std::optional<Manifest> parsed = parse(manifest);
+    if (!parsed) {
+      return std::nullopt;
+    }
+    CrdtBytes blob;
+    blob.reserve(parsed->size);
+    for (const CrdtString &hash : parsed->chunks) {
+      auto chunk = chunks_.find(hash);
+      if (chunk == chunks_.end()) {
+        return std::nullopt;
+      }
+      blob.insert(blob.end(), chunk->second.begin(), chunk->second.end());
+    }
+    if (blob.size() != parsed->size) {
+      return std::nullopt;
+    }
+    return blob;
+  }
+
+  static bool is_manifest(const CrdtString &value) { return parse(value).has_value(); }
+
+  /// The chunks of `manifest` not stored yet.
+  CrdtVector<CrdtString> missing(const CrdtString &manifest) const {
+    CrdtVector<CrdtString> missing;
+    if (std::optional<Manifest> parsed = parse(manifest)) {
+      for (CrdtString &hash : parsed->chunks) {
+        if (!chunks_.contains(hash)) {
+          missing.push_back(std::move(hash));
+        }
+      }
+    }
+    return missing;
+  }
+
+  /// Returns a BlobRequest frame for the chunks missing from the live blob columns of `crdt`, or std::nullopt if
+  /// nothing is missing.
+  ///
+  /// Complexity: O(n * m), where n is the number of records and m the average number of columns per record
+  template <typename CrdtType> std::optional<CrdtBytes> request_missing(const CrdtType &crdt) const {
+    CrdtSet<CrdtString> wanted;
+    crdt.iter_live([&](const auto &, const auto &record) {
+      for (const auto &[col_name, value] : record.fields) {
+        for (CrdtString &hash : missing(value)) {
+          wanted.insert(std::move(hash));
+        }
+      }
+    });
+    if (wanted.empty()) {
+      return std::nullopt;
+    }
+    CrdtBytes frame;
+    ByteWriter writer(frame);
+    writer.write_u8(static_cast<uint8_t>(SyncMessageType::BlobRequest));
+    writer.write_varint(wanted.size());
+    for (const CrdtString &hash : wanted) {
+      CrdtCodec<CrdtString>::encode(writer, hash);
+    }
+    return frame;
+  }
+
+  /// Handles a blob frame from a peer: answers a BlobRequest with the chunks we have, and stores the chunks of a
+  /// BlobChunks frame whose hash matches their content (others are dropped).
+  ///
+  /// Throws CrdtDecodeError on malformed frames.
+  ///
+  /// # Returns
+  ///
+  /// The frames to send back.
+  CrdtVector<CrdtBytes> on_frame(const CrdtBytes &frame) {
+    ByteReader reader(frame);
+    auto type = static_cast<SyncMessageType>(reader.read_u8());
+    if (type != SyncMessageType::BlobRequest && type != SyncMessageType::BlobChunks) {
+      throw CrdtDecodeError("not a blob frame");
+    }
+    uint64_t count = reader.read_varint();
+    if (count > reader.remaining()) {
+      throw CrdtDecodeError("chunk count exceeds input");
+    }
+
+    if (type == SyncMessageType::BlobChunks) {
+      for (uint64_t i = 0; i < count; ++i) {
+        auto [data, size] = reader.read_blob();
+        CrdtString hash = hasher_(data, size);
+        chunks_.try_emplace(std::move(hash), data, data + size);
+      }
+      if (!reader.at_end()) {
+        throw CrdtDecodeError("trailing bytes after chunks");
+      }
+      return {};
+    }
+
+    CrdtVector<const CrdtBytes *> found;
+    for (uint64_t i = 0; i < count; ++i) {
+      auto chunk = chunks_.find(CrdtCodec<CrdtString>::decode(reader));
+      if (chunk != chunks_.end()) {
+        found.push_back(&chunk->second);
+      }
+    }
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after chunk request");
+    }
+    CrdtVector<CrdtBytes> replies;
+    if (!found.empty()) {
+      CrdtBytes reply;
+      ByteWriter writer(reply);
+      writer.write_u8(static_cast<uint8_t>(SyncMessageType::BlobChunks));
+      writer.write_varint(found.size());
+      for (const CrdtBytes *chunk : found) {
+        writer.write_blob(chunk->data(), chunk->size());
+      }
+      replies.push_back(std::move(reply));
+    }
+    return replies;
+  }
+
+  /// Removes the chunks no live value of `crdt` refers to, e.g. after blobs were replaced or their records deleted.
+  ///
+  /// # Returns
+  ///
+  /// The number of chunks removed.
+  template <typename CrdtType> size_t collect_garbage(const CrdtType &crdt) {
+    CrdtSet<CrdtString> referenced;
+    crdt.iter_live([&](const auto &, const auto &record) {
+      for (const auto &[col_name, value] : record.fields) {
+        if (std::optional<Manifest> parsed = parse(value)) {
+          referenced.insert(parsed->chunks.begin(), parsed->chunks.end());
+        }
+      }
+    });
+    return std::erase_if(chunks_, [&](const auto &chunk) { return !referenced.contains(chunk.first); });
+  }
+
+  size_t chunk_count() const { return chunks_.size(); }
+
+  /// Total size of the stored chunks in bytes.
+  size_t stored_bytes() const {
+    size_t bytes = 0;
+    for (const auto &[hash, chunk] : chunks_) {
+      bytes += chunk.size();
+    }
+    return bytes;
+  }
+
+  /// The default chunk hash: two FNV-1a passes with different seeds, as 32 hex digits.
+  static CrdtString fnv_hash(const uint8_t *data, size_t size) {
+    CrdtString hex;
+    for (uint8_t seed : {uint8_t(0), uint8_t(1)}) {
+      CrdtHasher hasher;
+      hasher.update(&seed, 1);
+      hasher.update(data, size);
+      uint64_t value = CrdtHasher::mix(hasher.finish());
+      for (int shift = 60; shift >= 0; shift -= 4) {
+        hex += "0123456789abcdef"[(value >> shift) & 0xf];
+      }
+    }
+    return hex;
+  }
+
+private:
+  struct Manifest {
+    size_t size = 0;
+    CrdtVector<CrdtString> chunks;
+  };
+
+  size_t chunk_size_;
+  ChunkHasher hasher_;
+  CrdtMap<CrdtString, CrdtBytes> chunks_;
+
+  // Manifests are "crdt-blob:<size>:<hash>,<hash>,..."
+  static std::optional<Manifest> parse(const CrdtString &value) {
+    std::string_view rest(value);
+    if (!rest.starts_with(MANIFEST_PREFIX)) {
+      return std::nullopt;
+    }
+    rest.remove_prefix(std::string_view(MANIFEST_PREFIX).size());
+    size_t colon = rest.find(':');
+    Manifest manifest;
+    if (colon == std::string_view::npos ||
+        std::from_chars(rest.data(), rest.data() + colon, manifest.size).ptr != rest.data() + colon) {
+      return std::nullopt;
+    }
+    rest.remove_prefix(colon + 1);
+    while (!rest.empty()) {
+      size_t comma = rest.find(',');
+      manifest.chunks.emplace_back(rest.substr(0, comma));
+      rest.remove_prefix(comma == std::string_view::npos ? rest.size() : comma + 1);
+    }
+    return manifest;
+  }
+};
+
+#endif // CRDT_BLOB_HPP
@@ -0,0 +1,311 @@
+// crdt_bootstrap.hpp
+#ifndef CRDT_BOOTSTRAP_HPP
+#define CRDT_BOOTSTRAP_HPP
+
+// Resumable snapshot transfer, for bootstrapping a new replica from a large dataset over an unreliable connection.
+//
+// A SyncSession from version 0 sends a peer's whole state as one batch, which has to be resent in full if the
+// connection drops before it completes. A SnapshotReceiver instead pulls the state from a SnapshotServer in chunks:
+//
+// 1. The server takes an immutable snapshot of its CRDT (see CRDT::snapshot) and splits its changes into chunks of
+//    `changes_per_chunk` changes. Each chunk carries the snapshot id, the snapshot's version and a checksum.
+// 2. The receiver requests the chunks one at a time and merges each one as it arrives: a partial state is a valid
+//    CRDT state, so nothing is staged. A chunk failing its checksum is requested again. After a drop, the receiver
+//    resumes at the next chunk from its saved progress; if the server no longer has the snapshot (it was restarted
+//    or evicted), it answers with the first chunk of a new one and the receiver starts over, which is harmless since
+//    merging a change twice has no effect.
+// 3. Once the last chunk is merged, the receiver requests the tail: the changes the server applied since the
+//    snapshot's version. The server's version it covers is the cursor to continue with regular SyncSessions.
+//
+// Like SyncSession, both sides do no I/O: send the receiver's request frames to the server over any transport and
+// pass the replies back. The receiver's progress is small; persist it after every reply to resume after a restart.
+
+#include "crdt_blob.hpp"
+#include "crdt_codec.hpp"
+
+#include <memory>
+#include <random>
+
+enum class SnapshotMessageType : uint8_t {
+  ChunkRequest = 1, // snapshot id (empty for a new snapshot) and chunk index
+  Chunk = 2,        // snapshot id, snapshot version, chunk index, chunk count, checksum and the encoded changes
+  TailRequest = 3,  // the snapshot version and the compression codecs supported
+  Tail = 4,         // the server version covered and the compressed changes since the snapshot
+};
+
+/// How far a receiver got; persist it to resume a transfer.
+struct CrdtSnapshotProgress {
+  CrdtString snapshot_id;    // empty before the first chunk
+  uint64_t version = 0;      // the server version the snapshot was taken at
+  uint64_t next_chunk = 0;   // index of the next chunk to request
+  uint64_t total_chunks = 0; // number of chunks in the snapshot, known after the first chunk
+  bool complete = false;     // the tail was merged
+  uint64_t tail_version = 0; // the server version covered by the tail
+  uint64_t bytes_received = 0;
+
+  CrdtBytes save() const {
+    CrdtBytes bytes;
+    ByteWriter writer(bytes);
+    CrdtCodec<CrdtString>::encode(writer, snapshot_id);
+    CrdtCodec<bool>::encode(writer, complete);
+    for (uint64_t value : {version, next_chunk, total_chunks, tail_version, bytes_received}) {
+      writer.write_varint(value);
+    }
+    return bytes;
+  }
+
+  /// Throws CrdtDecodeError on malformed input.
+  static CrdtSnapshotProgress load(const CrdtBytes &bytes) {
+    ByteReader reader(bytes);
+    CrdtSnapshotProgress progress;
+    progress.snapshot_id = CrdtCodec<CrdtString>::decode(reader);
+    progress.complete = CrdtCodec<bool>::decode(reader);
+    for (uint64_t *value : {&progress.version, &progress.next_chunk, &progress.total_chunks, &progress.tail_version,
+                            &progress.bytes_received}) {
+      *value = reader.read_varint();
+    }
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after snapshot progress");
+    }
+    return progress;
+  }
+};
+
+/// Serves snapshot chunks and tails of a CRDT to receivers.
+template <typename CrdtType> class SnapshotServer {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+
+  /// # Arguments
+  ///
+  /// * `crdt` - The CRDT to serve; it must outlive the server.
+  /// * `changes_per_chunk` - The number of changes per chunk; chunks with large values are larger.
+  /// * `max_snapshots` - The number of snapshots kept for resuming transfers; the oldest one is dropped beyond that.
+  explicit SnapshotServer(CrdtType &crdt, size_t changes_per_chunk = 4096, size_t max_snapshots = 4)
+      : crdt_(crdt), changes_per_chunk_(std::max<size_t>(changes_per_chunk, 1)), max_snapshots_(max_snapshots) {}
+
+  /// Handles a request frame from a receiver and returns the reply.
+  ///
+  /// Throws CrdtDecodeError on malformed frames.
+  CrdtBytes on_frame(const CrdtBytes &request) {
+    ByteReader reader(request);
+    auto type = static_cast<SnapshotMessageType>(reader.read_u8());
+    if (type == SnapshotMessageType::TailRequest) {
+      uint64_t since = reader.read_varint();
+      CrdtCompression codec = crdt_codec::choose_compression(reader.read_varint() & crdt_codec::supported_compression());
+      require_end(reader);
+      CrdtBytes reply;
+      ByteWriter writer(reply);
+      writer.write_u8(static_cast<uint8_t>(SnapshotMessageType::Tail));
+      writer.write_varint(crdt_.get_clock().current_time());
+      crdt_codec::encode_changes_compressed(writer, crdt_.get_change_refs_since(since), codec);
+      return reply;
+    }
+    if (type != SnapshotMessageType::ChunkRequest) {
+      throw CrdtDecodeError("unexpected snapshot message type");
+    }
+    CrdtString id = CrdtCodec<CrdtString>::decode(reader);
+    uint64_t index = reader.read_varint();
+    require_end(reader);
+
+    Snapshot *snapshot = find(id);
+    if (!snapshot || index >= snapshot->chunk_count()) {
+      // Unknown or evicted: the receiver starts over on a fresh snapshot
+      snapshot = &take_snapshot();
+      index = 0;
+    }
+    ++chunks_served_;
+    return encode_chunk(*snapshot, index);
+  }
+
+  /// Number of snapshots kept for resuming transfers.
+  size_t snapshot_count() const { return snapshots_.size(); }
+
+  uint64_t chunks_served() const { return chunks_served_; }
+
+private:
+  struct Snapshot {
+    CrdtString id;
+    uint64_t version = 0;
+    std::shared_ptr<const CrdtType> state;
+    CrdtVector<ChangeRef<K, V>> changes; // borrowed from `state`, which never changes
+    size_t changes_per_chunk = 1;
+
+    uint64_t chunk_count() const {
+      return std::max<uint64_t>(1, (changes.size() + changes_per_chunk - 1) / changes_per_chunk);
+    }
+  };
+
+  CrdtType &crdt_;
+  size_t changes_per_chunk_;
+  size_t max_snapshots_;
+  std::deque<Snapshot> snapshots_; // oldest first
+  std::mt19937_64 random_{std::random_device{}()};
+  uint64_t chunks_served_ = 0;
+
+  static void require_end(const ByteReader &reader) {
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after snapshot message");
+    }
+  }
+
+  Snapshot *find(const CrdtString &id) {
+    for (Snapshot &snapshot : snapshots_) {
+      if (!id.empty() && snapshot.id == id) {
+        return &snapshot;
+      }
+    }
+    return nullptr;
+  }
+
+  // Returns the newest snapshot if nothing changed since, or takes a new one
+  Snapshot &take_snapshot() {
+    uint64_t version = crdt_.get_clock().current_time();
+    if (!snapshots_.empty() && snapshots_.back().version == version) {
+      return snapshots_.back();
+    }
+    Snapshot snapshot;
+    std::ostringstream id;
+    id << std::hex << random_();
+    snapshot.id = id.str();
+    snapshot.version = version;
+    snapshot.state = crdt_.snapshot();
+    snapshot.changes = snapshot.state->get_change_refs_since(0);
+    snapshot.changes_per_chunk = changes_per_chunk_;
+    snapshots_.push_back(std::move(snapshot));
+    while (snapshots_.size() > std::max<size_t>(max_snapshots_, 1)) {
+      snapshots_.pop_front();
+    }
+    return snapshots_.back();
+  }
+
+  CrdtBytes encode_chunk(const Snapshot &snapshot, uint64_t index) const {
+    size_t begin = static_cast<size_t>(index) * snapshot.changes_per_chunk;
+    size_t end = std::min(snapshot.changes.size(), begin + snapshot.changes_per_chunk);
+    CrdtVector<ChangeRef<K, V>> changes(snapshot.changes.begin() + static_cast<std::ptrdiff_t>(begin),
+                                        snapshot.changes.begin() + static_cast<std::ptrdiff_t>(end));
+    CrdtBytes payload = crdt_codec::encode_changes(changes);
+
+    CrdtBytes reply;
+    ByteWriter writer(reply);
+    writer.write_u8(static_cast<uint8_t>(SnapshotMessageType::Chunk));
+    CrdtCodec<CrdtString>::encode(writer, snapshot.id);
+    writer.write_varint(snapshot.version);
+    writer.write_varint(index);
+    writer.write_varint(snapshot.chunk_count());
+    CrdtCodec<CrdtString>::encode(writer, CrdtBlobStore::fnv_hash(payload.data(), payload.size()));
+    CrdtCodec<CrdtBytes>::encode(writer, payload);
+    return reply;
+  }
+};
+
+/// Bootstraps a CRDT from a SnapshotServer, chunk by chunk.
+template <typename CrdtType> class SnapshotReceiver {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+
+  enum class Status {
+    Merged,   // a chunk or the tail was merged
+    Corrupt,  // the chunk failed its checksum and was dropped; it is requested again
+    Complete, // the tail was merged: the transfer is done
+  };
+
+  /// `crdt` must outlive the receiver. Pass the progress saved before a restart to resume the transfer.
+  explicit SnapshotReceiver(CrdtType &crdt, CrdtSnapshotProgress progress = {})
+      : crdt_(crdt), progress_(std::move(progress)) {}
+
+  /// The next request to send, or std::nullopt once the transfer is complete. After a dropped connection, send it
+  /// again on the new one.
+  std::optional<CrdtBytes> request_frame() const {
+    if (is_complete()) {
+      return std::nullopt;
+    }
+    CrdtBytes frame;
+    ByteWriter writer(frame);
+    if (chunks_done()) {
+      writer.write_u8(static_cast<uint8_t>(SnapshotMessageType::TailRequest));
+      writer.write_varint(progress_.version);
+      writer.write_varint(crdt_codec::supported_compression());
+    } else {
+      writer.write_u8(static_cast<uint8_t>(SnapshotMessageType::ChunkRequest));
+      CrdtCodec<CrdtString>::encode(writer, progress_.snapshot_id);
+      writer.write_varint(progress_.next_chunk);
+    }
+    return frame;
+  }
+
+  /// Handles a reply from the server, merging its changes.
+  ///
+  /// Throws CrdtDecodeError on malformed frames.
+  Status on_frame(const CrdtBytes &frame) {
+    ByteReader reader(frame);
+    auto type = static_cast<SnapshotMessageType>(reader.read_u8());
+    if (type == SnapshotMessageType::Tail) {
+      uint64_t up_to = reader.read_varint();
+      auto changes = crdt_codec::decode_changes_compressed<K, V>(reader);
+      require_end(reader);
+      crdt_.merge_changes(std::move(changes));
+      progress_.bytes_received += frame.size();
+      progress_.tail_version = up_to;
+      progress_.complete = true;
+      return Status::Complete;
+    }
+    if (type != SnapshotMessageType::Chunk) {
+      throw CrdtDecodeError("unexpected snapshot message type");
+    }
+    CrdtString id = CrdtCodec<CrdtString>::decode(reader);
+    uint64_t version = reader.read_varint();
+    uint64_t index = reader.read_varint();
+    uint64_t total = reader.read_varint();
+    CrdtString checksum = CrdtCodec<CrdtString>::decode(reader);
+    CrdtBytes payload = CrdtCodec<CrdtBytes>::decode(reader);
+    require_end(reader);
+
+    if (id != progress_.snapshot_id) {
+      // A new snapshot: start over at its first chunk
+      progress_.snapshot_id = std::move(id);
+      progress_.version = version;
+      progress_.total_chunks = total;
+      progress_.next_chunk = 0;
+    }
+    if (CrdtBlobStore::fnv_hash(payload.data(), payload.size()) != checksum) {
+      ++corrupt_chunks_;
+      return Status::Corrupt;
+    }
+    if (index == progress_.next_chunk) {
+      crdt_.merge_changes(crdt_codec::decode_changes<K, V>(payload));
+      progress_.bytes_received += frame.size();
+      ++progress_.next_chunk;
+    }
+    return Status::Merged;
+  }
+
+  /// True once the snapshot and its tail were merged.
+  bool is_complete() const { return progress_.complete; }
+
+  /// The server's version covered once complete: the cursor for the next SyncSession with the server.
+  uint64_t last_received_version() const { return progress_.tail_version; }
+
+  /// The progress to persist after every reply.
+  const CrdtSnapshotProgress &progress() const { return progress_; }
+
+  /// Number of chunks that failed their checksum.
+  uint64_t corrupt_chunks() const { return corrupt_chunks_; }
+
+private:
+  CrdtType &crdt_;
+  CrdtSnapshotProgress progress_;
+  uint64_t corrupt_chunks_ = 0;
+
+  bool chunks_done() const { return !progress_.snapshot_id.empty() && progress_.next_chunk >= progress_.total_chunks; }
+
+  static void require_end(const ByteReader &reader) {
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after snapshot message");
+    }
+  }
+};
+
+#endif // CRDT_BOOTSTRAP_HPP
@@ -0,0 +1,122 @@
+// crdt_broadcast.hpp
+#ifndef CRDT_BROADCAST_HPP
+#define CRDT_BROADCAST_HPP
+
+// Multi-consumer feed of applied changes.
+//
+// A ChangeBroadcast attached to a CRDT publishes every ChangeEvent into a bounded ring buffer. Any number of receivers
+// (UI, persistence, metrics) read the feed independently from their own thread. Like a broadcast channel, a receiver that
+// falls more than `capacity` events behind skips the oldest ones and is told how many it missed.
+
+#include "crdt.hpp"
+
+#include <condition_variable>
+#include <deque>
+#include <mutex>
+#include <utility>
+
+template <typename K, typename V> class ChangeBroadcast {
+  struct State {
+    std::mutex mutex;
+    std::condition_variable available;
+    std::deque<ChangeEvent<K, V>> buffer;
+    uint64_t first_seq = 0; // sequence number of buffer.front()
+    size_t capacity;
+    bool closed = false;
+
+    explicit State(size_t capacity) : capacity(capacity) {}
+    uint64_t end_seq() const { return first_seq + buffer.size(); }
+  };
+
+public:
+  /// Reads the feed from the point it subscribed. Receivers are independent; each event is delivered to all of them.
+  class Receiver {
+  public:
+    /// Returns the next event without blocking, or std::nullopt if none is pending.
+    std::optional<ChangeEvent<K, V>> try_recv() {
+      std::unique_lock lock(state_->mutex);
+      return take(lock);
+    }
+
+    /// Blocks until an event is available. Returns std::nullopt once the broadcast is closed and drained.
+    std::optional<ChangeEvent<K, V>> recv() {
+      std::unique_lock lock(state_->mutex);
+      state_->available.wait(lock, [&] { return next_seq_ < state_->end_seq() || state_->closed; });
+      return take(lock);
+    }
+
+    /// Number of events skipped because this receiver fell behind, since the last call.
+    uint64_t take_lagged() { return std::exchange(lagged_, 0); }
+
+  private:
+    friend class ChangeBroadcast;
+
+    std::shared_ptr<State> state_;
+    uint64_t next_seq_;
+    uint64_t lagged_ = 0;
+
+    Receiver(std::shared_ptr<State> state, uint64_t next_seq) : state_(std::move(state)), next_seq_(next_seq) {}
+
+    std::optional<ChangeEvent<K, V>> take(std::unique_lock<std::mutex> &) {
+      if (next_seq_ < state_->first_seq) {
+        lagged_ += state_->first_seq - next_seq_;
+        next_seq_ = state_->first_seq;
+      }
+      if (next_seq_ >= state_->end_seq()) {
+        return std::nullopt;
+      }
+      return state_->buffer[next_seq_++ - state_->first_seq];
+    }
+  };
+
+  /// # Arguments
+  ///
+  /// * `capacity` - Number of events retained for slow receivers.
+  explicit ChangeBroadcast(size_t capacity = 1024) : state_(std::make_shared<State>(capacity == 0 ? 1 : capacity)) {}
+
+  ChangeBroadcast(const ChangeBroadcast &) = delete;
+  ChangeBroadcast &operator=(const ChangeBroadcast &) = delete;
+  ~ChangeBroadcast() { close(); }
+
+  /// Subscribes to the feed; the receiver sees events published from now on.
+  Receiver subscribe() {
+    std::lock_guard lock(state_->mutex);
+    return Receiver(state_, state_->end_seq());
+  }
+
+  /// Publishes an event to all receivers.
+  void publish(const ChangeEvent<K, V> &event) {
+    {
+      std::lock_guard lock(state_->mutex);
+      if (state_->closed) {
+        return;
+      }
+      if (state_->buffer.size() == state_->capacity) {
+        state_->buffer.pop_front();
+        ++state_->first_seq;
+      }
+      state_->buffer.push_back(event);
+    }
+    state_->available.notify_all();
+  }
+
+  /// Publishes every change applied to `crdt`. The broadcast must outlive the registration; the returned id can be
+  /// passed to `crdt.remove_on_change`.
+  template <typename CrdtType> size_t attach(CrdtType &crdt) {
+    return crdt.on_change([this](const ChangeEvent<K, V> &event) { publish(event); });
+  }
+
+  /// Stops publishing and wakes blocked receivers; they drain the remaining events and then receive std::nullopt.
+  void close() {
+    {
+      std::lock_guard lock(state_->mutex);
+      state_->closed = true;
+    }
+    state_->available.notify_all();
+  }
+
+private:
+  std::shared_ptr<State> state_;
+};
+
+#endif // CRDT_BROADCAST_HPP
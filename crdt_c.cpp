@@ -0,0 +1,131 @@
+// crdt_c.cpp
+#include "crdt_c.h"
+
+#include "crdt_codec.hpp"
+#include "shared_crdt.hpp"
+
+#include <cstdlib>
+#include <new>
+
+struct crdt_handle {
+  explicit crdt_handle(uint64_t node_id) : crdt(node_id) {}
+
+  SharedCrdt<CrdtString, CrdtString> crdt;
+};
+
+namespace {
+
+crdt_status fill_buffer(const uint8_t *data, size_t len, crdt_buffer *out) {
+  out->data = static_cast<uint8_t *>(std::malloc(len + 1));
+  if (out->data == nullptr) {
+    out->len = 0;
+    return CRDT_ERR_INTERNAL;
+  }
+  if (len > 0) {
+    std::memcpy(out->data, data, len);
+  }
+  out->data[len] = 0;
+  out->len = len;
+  return CRDT_OK;
+}
+
+// Exceptions must not cross the C boundary
+template <typename Fn> crdt_status guarded(Fn &&fn) {
+  try {
+    return fn();
+  } catch (const CrdtDecodeError &) {
+    return CRDT_ERR_DECODE;
+  } catch (...) {
+    return CRDT_ERR_INTERNAL;
+  }
+}
+
+} // namespace
+
+extern "C" {
+
+crdt_handle *crdt_new(uint64_t node_id) { return new (std::nothrow) crdt_handle(node_id); }
+
+void crdt_free(crdt_handle *crdt) { delete crdt; }
+
+crdt_status crdt_insert_or_update(crdt_handle *crdt, const char *record_id, const char *const *columns,
+                                  const char *const *values, size_t count) {
+  if (crdt == nullptr || record_id == nullptr || (count > 0 && (columns == nullptr || values == nullptr))) {
+    return CRDT_ERR_INVALID_ARGUMENT;
+  }
+  for (size_t i = 0; i < count; ++i) {
+    if (columns[i] == nullptr || values[i] == nullptr) {
+      return CRDT_ERR_INVALID_ARGUMENT;
+    }
+  }
+  return guarded([&] {
+    CrdtMap<CrdtString, CrdtString> fields;
+    for (size_t i = 0; i < count; ++i) {
+      fields.insert_or_assign(columns[i], values[i]);
+    }
+    crdt->crdt.insert_or_update<false>(record_id, std::move(fields));
+    return CRDT_OK;
+  });
+}
+
+crdt_status crdt_delete_record(crdt_handle *crdt, const char *record_id) {
+  if (crdt == nullptr || record_id == nullptr) {
+    return CRDT_ERR_INVALID_ARGUMENT;
+  }
+  return guarded([&] {
+    crdt->crdt.delete_record<false>(record_id);
+    return CRDT_OK;
+  });
+}
+
+crdt_status crdt_get_value(const crdt_handle *crdt, const char *record_id, const char *column, crdt_buffer *out) {
+  if (out != nullptr) {
+    *out = {nullptr, 0};
+  }
+  if (crdt == nullptr || record_id == nullptr || column == nullptr || out == nullptr) {
+    return CRDT_ERR_INVALID_ARGUMENT;
+  }
+  return guarded([&] {
+    std::optional<CrdtString> value = crdt->crdt.get_value(record_id, column);
+    if (!value) {
+      return CRDT_ERR_NOT_FOUND;
+    }
+    return fill_buffer(reinterpret_cast<const uint8_t *>(value->data()), value->size(), out);
+  });
+}
+
+crdt_status crdt_changes_since(const crdt_handle *crdt, uint64_t version, crdt_buffer *out) {
+  if (out != nullptr) {
+    *out = {nullptr, 0};
+  }
+  if (crdt == nullptr || out == nullptr) {
+    return CRDT_ERR_INVALID_ARGUMENT;
+  }
+  return guarded([&] {
+    CrdtBytes bytes = crdt_codec::encode_changes(crdt->crdt.get_changes_since(version));
+    return fill_buffer(bytes.data(), bytes.size(), out);
+  });
+}
+
+crdt_status crdt_merge(crdt_handle *crdt, const uint8_t *data, size_t len) {
+  if (crdt == nullptr || (data == nullptr && len > 0)) {
+    return CRDT_ERR_INVALID_ARGUMENT;
+  }
+  return guarded([&] {
+    CrdtBytes bytes(data, data + len);
+    crdt->crdt.merge_changes(crdt_codec::decode_changes<CrdtString, CrdtString>(bytes));
+    return CRDT_OK;
+  });
+}
+
+uint64_t crdt_current_version(const crdt_handle *crdt) { return crdt != nullptr ? crdt->crdt.current_time() : 0; }
+
+void crdt_buffer_free(crdt_buffer *buffer) {
+  if (buffer != nullptr) {
+    std::free(buffer->data);
+    buffer->data = nullptr;
+    buffer->len = 0;
+  }
+}
+
+} // extern "C"
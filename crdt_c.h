@@ -0,0 +1,67 @@
+/* crdt_c.h */
+#ifndef CRDT_C_H
+#define CRDT_C_H
+
+/*
+ * C ABI over CRDT<string, string>, for use from languages that can call C: Swift imports this header directly (via a
+ * module map or bridging header), Kotlin/Java through JNI or JNA, and most other languages through their FFI.
+ *
+ * Build crdt_c.cpp into a static or shared library for the target platform. Strings are UTF-8 and NUL-terminated.
+ * Changes cross the boundary encoded with crdt_codec.hpp, so they can be stored or sent as opaque bytes and merged on
+ * any other node. A handle may be used from several threads at once.
+ */
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct crdt_handle crdt_handle;
+
+/* Bytes owned by the library; release with crdt_buffer_free. `data` is NUL-terminated for convenience. Functions filling
+ * a buffer empty it first, so it is safe to free whatever they return. */
+typedef struct crdt_buffer {
+  uint8_t *data;
+  size_t len;
+} crdt_buffer;
+
+typedef enum crdt_status {
+  CRDT_OK = 0,
+  CRDT_ERR_INVALID_ARGUMENT = 1,
+  CRDT_ERR_DECODE = 2,
+  CRDT_ERR_NOT_FOUND = 3,
+  CRDT_ERR_INTERNAL = 4,
+} crdt_status;
+
+/* Creates a CRDT for the node `node_id`, or returns NULL if allocation fails. */
+crdt_handle *crdt_new(uint64_t node_id);
+
+void crdt_free(crdt_handle *crdt);
+
+/* Inserts or updates `count` columns of a record. `columns` and `values` are parallel arrays. */
+crdt_status crdt_insert_or_update(crdt_handle *crdt, const char *record_id, const char *const *columns,
+                                  const char *const *values, size_t count);
+
+crdt_status crdt_delete_record(crdt_handle *crdt, const char *record_id);
+
+/* Copies a column of a live record into `out`. Returns CRDT_ERR_NOT_FOUND if the record or column is missing. */
+crdt_status crdt_get_value(const crdt_handle *crdt, const char *record_id, const char *column, crdt_buffer *out);
+
+/* Encodes all changes with a local version above `version` into `out`. Pass 0 for the full state. */
+crdt_status crdt_changes_since(const crdt_handle *crdt, uint64_t version, crdt_buffer *out);
+
+/* Merges changes encoded by crdt_changes_since on another node. Returns CRDT_ERR_DECODE on malformed input. */
+crdt_status crdt_merge(crdt_handle *crdt, const uint8_t *data, size_t len);
+
+/* The node's current logical clock; remember it to request only newer changes with crdt_changes_since. */
+uint64_t crdt_current_version(const crdt_handle *crdt);
+
+void crdt_buffer_free(crdt_buffer *buffer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CRDT_C_H */
@@ -0,0 +1,248 @@
+// crdt_chain.hpp
+#ifndef CRDT_CHAIN_HPP
+#define CRDT_CHAIN_HPP
+
+// Hash-chained operations, for tamper evidence when operations travel through intermediaries (relays, brokers).
+//
+// A ChainPublisher publishes a site's operations like an OpPublisher, and links each one to the previous one: its
+// frame carries the hash of the previous operation and its own hash, computed over that previous hash and the encoded
+// operation. A CrdtChainVerifier checks every frame before merging it. An operation whose content does not match its
+// hash, or which does not extend the chain of its origin, is rejected and recorded as a violation; an operation that
+// arrives ahead of its predecessor waits for it, and stays pending for good if the predecessor was dropped. An auditor
+// that obtains a site's head (its last seq and hash) from the site itself, e.g. signed or out of band, can then prove
+// with `verify_head` that every operation of the site was received unmodified: a single dropped, altered or forged
+// operation changes every later hash.
+//
+// The chain covers the operation stream only: changes received through a regular sync are not verified. The default
+// hash is the 128-bit FNV-1a of the blob store, which catches corruption and careless rewriting; pass a cryptographic
+// hash (e.g. SHA-256 from OpenSSL) against an intermediary that would forge a whole chain.
+
+#include "crdt_blob.hpp"
+#include "crdt_ops.hpp"
+
+/// The last link of a site's chain.
+struct CrdtChainHead {
+  uint64_t seq = 0; // seq of the last operation, 0 before the first
+  CrdtString hash;  // hash of the last operation, empty before the first
+
+  bool operator==(const CrdtChainHead &) const = default;
+};
+
+namespace crdt_chain {
+
+/// Returns the hash of a byte string; every site and verifier must use the same one.
+using Hasher = std::function<CrdtString(const uint8_t *data, size_t size)>;
+
+/// A decoded chained operation frame.
+struct Link {
+  CrdtString prev_hash;
+  CrdtString hash;
+  CrdtBytes op; // the operation, encoded with `crdt_ops::encode_op`
+};
+
+/// The hash of an operation linked to `prev_hash`.
+inline CrdtString link_hash(const Hasher &hasher, const CrdtString &prev_hash, const CrdtBytes &op) {
+  CrdtBytes bytes;
+  ByteWriter writer(bytes);
+  CrdtCodec<CrdtString>::encode(writer, prev_hash);
+  bytes.insert(bytes.end(), op.begin(), op.end());
+  return hasher(bytes.data(), bytes.size());
+}
+
+inline CrdtBytes encode_link(const Link &link) {
+  CrdtBytes frame;
+  ByteWriter writer(frame);
+  CrdtCodec<CrdtString>::encode(writer, link.prev_hash);
+  CrdtCodec<CrdtString>::encode(writer, link.hash);
+  writer.write_blob(link.op.data(), link.op.size());
+  return frame;
+}
+
+/// Throws CrdtDecodeError on malformed input.
+inline Link decode_link(const CrdtBytes &frame) {
+  ByteReader reader(frame);
+  Link link;
+  link.prev_hash = CrdtCodec<CrdtString>::decode(reader);
+  link.hash = CrdtCodec<CrdtString>::decode(reader);
+  auto [data, size] = reader.read_blob();
+  link.op.assign(data, data + size);
+  if (!reader.at_end()) {
+    throw CrdtDecodeError("trailing bytes after chained operation");
+  }
+  return link;
+}
+
+} // namespace crdt_chain
+
+/// Publishes the local operations of a CRDT as hash-chained frames.
+template <typename K, typename V> class ChainPublisher {
+public:
+  using Send = std::function<void(const CrdtBytes &frame)>;
+
+  /// # Arguments
+  ///
+  /// * `node_id` - The node id of the CRDT the publisher is attached to.
+  /// * `send` - Called synchronously inside the CRDT operation with each frame; it must not modify the CRDT.
+  /// * `head` - The head saved before a restart, so the chain continues instead of starting over.
+  /// * `hasher` - The link hash.
+  ChainPublisher(CrdtNodeId node_id, Send send, CrdtChainHead head = {},
+                 crdt_chain::Hasher hasher = CrdtBlobStore::fnv_hash)
+      : send_(std::move(send)), hasher_(std::move(hasher)), head_(std::move(head)),
+        publisher_(node_id, [this](const CrdtOp<K, V> &op) { publish(op); }, head_.seq) {}
+
+  ChainPublisher(const ChainPublisher &) = delete;
+  ChainPublisher &operator=(const ChainPublisher &) = delete;
+
+  /// See `OpPublisher::attach`.
+  template <typename CrdtType> size_t attach(CrdtType &crdt) { return publisher_.attach(crdt); }
+
+  /// The current head of the chain, to persist and to hand to auditors.
+  const CrdtChainHead &head() const { return head_; }
+
+private:
+  Send send_;
+  crdt_chain::Hasher hasher_;
+  CrdtChainHead head_;
+  OpPublisher<K, V> publisher_;
+
+  void publish(const CrdtOp<K, V> &op) {
+    crdt_chain::Link link{head_.hash, {}, crdt_ops::encode_op(op)};
+    link.hash = crdt_chain::link_hash(hasher_, link.prev_hash, link.op);
+    head_ = {op.seq, link.hash};
+    send_(crdt_chain::encode_link(link));
+  }
+};
+
+/// A chained operation the verifier rejected.
+struct CrdtChainViolation {
+  CrdtNodeId origin;
+  uint64_t seq = 0;
+  CrdtString reason;
+};
+
+/// Verifies chained operation frames and merges those that extend their origin's chain.
+template <typename CrdtType> class CrdtChainVerifier {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+
+  enum class Status {
+    Applied,   // merged, along with any pending successors
+    Duplicate, // already applied
+    Pending,   // waiting for its predecessor
+    Rejected,  // tampered with or not extending the chain; recorded in `violations`
+  };
+
+  explicit CrdtChainVerifier(CrdtType &crdt, crdt_chain::Hasher hasher = CrdtBlobStore::fnv_hash)
+      : crdt_(crdt), hasher_(std::move(hasher)) {}
+
+  /// Starts verifying `origin`'s chain at `head` instead of at its first operation, e.g. for a verifier that joins
+  /// late, after catching up through a regular sync.
+  void trust(const CrdtNodeId &origin, CrdtChainHead head) { heads_[origin] = std::move(head); }
+
+  /// Verifies a frame and merges its operation if it extends its origin's chain.
+  ///
+  /// Throws CrdtDecodeError on malformed frames.
+  Status receive(const CrdtBytes &frame) {
+    crdt_chain::Link link = crdt_chain::decode_link(frame);
+    CrdtOp<K, V> op = crdt_ops::decode_op<K, V>(link.op);
+    if (crdt_chain::link_hash(hasher_, link.prev_hash, link.op) != link.hash) {
+      return reject(op, "content does not match its hash");
+    }
+
+    CrdtChainHead &head = heads_[op.origin];
+    if (op.seq <= head.seq) {
+      // The same seq with another hash is a forged or rewritten history
+      if (op.seq == head.seq && link.hash != head.hash) {
+        return reject(op, "conflicts with the applied operation");
+      }
+      return Status::Duplicate;
+    }
+    CrdtNodeId origin = op.origin;
+    if (op.prev_seq > head.seq) {
+      uint64_t prev_seq = op.prev_seq;
+      pending_[origin].try_emplace(prev_seq, Pending{std::move(link.prev_hash), std::move(link.hash), std::move(op)});
+      return Status::Pending;
+    }
+    Status status = apply(head, std::move(link.prev_hash), std::move(link.hash), std::move(op));
+    if (status == Status::Applied) {
+      drain(origin);
+    }
+    return status;
+  }
+
+  /// The head of `origin`'s chain as verified so far, or std::nullopt if nothing was received from it.
+  std::optional<CrdtChainHead> head(const CrdtNodeId &origin) const {
+    auto it = heads_.find(origin);
+    return it != heads_.end() ? std::optional(it->second) : std::nullopt;
+  }
+
+  /// True if every operation of `origin` up to `head`, obtained from the origin itself, was received and applied
+  /// unmodified.
+  bool verify_head(const CrdtNodeId &origin, const CrdtChainHead &head) const { return this->head(origin) == head; }
+
+  /// The origins with operations waiting for a predecessor that has not arrived, with the seq their chain stops at.
+  CrdtMap<CrdtNodeId, uint64_t> gaps() const {
+    CrdtMap<CrdtNodeId, uint64_t> gaps;
+    for (const auto &[origin, waiting] : pending_) {
+      if (!waiting.empty()) {
+        gaps[origin] = heads_.at(origin).seq;
+      }
+    }
+    return gaps;
+  }
+
+  const CrdtVector<CrdtChainViolation> &violations() const { return violations_; }
+
+  /// Number of operations merged.
+  uint64_t applied() const { return applied_; }
+
+private:
+  struct Pending {
+    CrdtString prev_hash;
+    CrdtString hash;
+    CrdtOp<K, V> op;
+  };
+
+  CrdtType &crdt_;
+  crdt_chain::Hasher hasher_;
+  CrdtMap<CrdtNodeId, CrdtChainHead> heads_;
+  CrdtMap<CrdtNodeId, CrdtMap<uint64_t, Pending>> pending_; // per origin, keyed by prev_seq
+  CrdtVector<CrdtChainViolation> violations_;
+  uint64_t applied_ = 0;
+
+  Status reject(const CrdtOp<K, V> &op, const char *reason) {
+    violations_.push_back({op.origin, op.seq, reason});
+    return Status::Rejected;
+  }
+
+  Status apply(CrdtChainHead &head, CrdtString &&prev_hash, CrdtString &&hash, CrdtOp<K, V> &&op) {
+    // An operation that skips applied ones or links to another hash means an earlier one was dropped or altered
+    if (op.prev_seq != head.seq || prev_hash != head.hash) {
+      return reject(op, "does not extend the chain");
+    }
+    head = {op.seq, std::move(hash)};
+    crdt_ops::apply_op(crdt_, std::move(op));
+    ++applied_;
+    return Status::Applied;
+  }
+
+  // Applies the pending operations of `origin` that now extend its chain
+  void drain(const CrdtNodeId &origin) {
+    auto waiting = pending_.find(origin);
+    if (waiting == pending_.end()) {
+      return;
+    }
+    CrdtChainHead &head = heads_[origin];
+    for (auto next = waiting->second.find(head.seq); next != waiting->second.end();
+         next = waiting->second.find(head.seq)) {
+      Pending pending = std::move(next->second);
+      waiting->second.erase(next);
+      if (apply(head, std::move(pending.prev_hash), std::move(pending.hash), std::move(pending.op)) != Status::Applied) {
+        break;
+      }
+    }
+  }
+};
+
+#endif // CRDT_CHAIN_HPP
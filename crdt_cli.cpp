@@ -0,0 +1,196 @@
+// crdt_cli.cpp
+//
+// crdt-lite: a command line tool for inspecting replicas, e.g. when debugging a customer's data.
+//
+//   g++ -std=c++20 -O2 -o crdt-lite crdt_cli.cpp
+//
+// Snapshots are archives written by `crdt_archive::save`, and change files batches written by `encode_changes`. The
+// tool reads string-keyed, string-valued CRDTs; for other types define CRDT_CLI_KEY and CRDT_CLI_VALUE (which need a
+// CrdtCodec and a CrdtScalar), e.g. -DCRDT_CLI_KEY=int64_t. Values are printed as JSON.
+//
+// Commands:
+//
+//   crdt-lite dump <snapshot>                        print the clock, the live records as JSON Lines and the tombstones
+//   crdt-lite record <snapshot> <record-id>          print a record's columns with their versions
+//   crdt-lite diff <snapshot> <snapshot>             print where two snapshots disagree; exits with 1 if they do
+//   crdt-lite changes <snapshot> <version> <file>    write the changes made after db_version <version> to a change file
+//   crdt-lite merge <snapshot> <file> [<output>]     merge a change file into a snapshot, in place unless <output>
+#include "crdt_archive.hpp"
+#include "crdt_io.hpp"
+
+#include <cstdlib>
+#include <fstream>
+#include <iostream>
+
+#ifndef CRDT_CLI_KEY
+#define CRDT_CLI_KEY CrdtString
+#endif
+#ifndef CRDT_CLI_VALUE
+#define CRDT_CLI_VALUE CrdtString
+#endif
+
+using K = CRDT_CLI_KEY;
+using V = CRDT_CLI_VALUE;
+using CliCrdt = CRDT<K, V>;
+using CliArchive = CrdtArchive<CliCrdt>;
+
+namespace {
+
+// Thrown for bad command lines; main prints the usage after the message
+class UsageError : public std::runtime_error {
+public:
+  explicit UsageError(const char *message) : std::runtime_error(message) {}
+};
+
+void print_usage(std::ostream &out) {
+  out << "usage: crdt-lite dump <snapshot>\n"
+         "       crdt-lite record <snapshot> <record-id>\n"
+         "       crdt-lite diff <snapshot> <snapshot>\n"
+         "       crdt-lite changes <snapshot> <version> <file>\n"
+         "       crdt-lite merge <snapshot> <file> [<output>]\n";
+}
+
+template <typename T> void print_json(std::ostream &out, const T &value) {
+  crdt_io::write_json_scalar(out, CrdtScalar<T>::to_scalar(value));
+}
+
+template <typename T> void print_json(std::ostream &out, const std::optional<T> &value) {
+  if (value) {
+    print_json(out, *value);
+  } else {
+    out << "(none)";
+  }
+}
+
+K parse_record_id(const char *text) {
+  std::optional<K> record_id = CrdtScalar<K>::from_scalar(CrdtString(text));
+  if (!record_id) {
+    throw UsageError("invalid record id");
+  }
+  return std::move(*record_id);
+}
+
+uint64_t parse_version(const char *text) {
+  uint64_t version = 0;
+  const char *end = text + std::strlen(text);
+  auto result = std::from_chars(text, end, version);
+  if (result.ec != std::errc() || result.ptr != end) {
+    throw UsageError("invalid version");
+  }
+  return version;
+}
+
+CrdtBytes read_file(const char *path) {
+  std::ifstream in(path, std::ios::binary);
+  if (!in) {
+    throw std::runtime_error(CrdtString("cannot read ") + path);
+  }
+  return CrdtBytes(std::istreambuf_iterator<char>(in), std::istreambuf_iterator<char>());
+}
+
+void write_file(const char *path, const CrdtBytes &bytes) {
+  std::ofstream out(path, std::ios::binary | std::ios::trunc);
+  out.write(reinterpret_cast<const char *>(bytes.data()), static_cast<std::streamsize>(bytes.size()));
+  if (!out) {
+    throw std::runtime_error(CrdtString("cannot write ") + path);
+  }
+}
+
+int dump(const char *path) {
+  CliArchive archive(path);
+  std::cout << "clock: " << archive.clock_time() << "\nlive records: " << archive.len_live()
+            << "\ntombstones: " << archive.len_tombstones() << "\n";
+  CliCrdt crdt = archive.to_crdt(CrdtNodeId{});
+  crdt_io::export_jsonl(crdt, std::cout);
+  crdt.for_each_tombstone([](const K &record_id, const CrdtTombstone &tombstone) {
+    std::cout << "tombstone ";
+    print_json(std::cout, record_id);
+    std::cout << ": " << tombstone << "\n";
+  });
+  return 0;
+}
+
+int show_record(const char *path, const char *record_id_text) {
+  CliArchive archive(path);
+  K record_id = parse_record_id(record_id_text);
+  std::optional<Record<V>> record = archive.get_record(record_id);
+  if (!record) {
+    std::cout << (archive.status(record_id) == RecordStatus::Tombstoned ? "tombstoned" : "not found") << "\n";
+    return 1;
+  }
+  std::map<CrdtString, ColumnVersion> versions(record->column_versions.begin(), record->column_versions.end());
+  for (const auto &[col_name, version] : versions) {
+    std::cout << col_name << " = ";
+    auto it = record->fields.find(col_name);
+    print_json(std::cout, it != record->fields.end() ? std::optional<V>(it->second) : std::nullopt);
+    std::cout << " (" << version << ")\n";
+  }
+  if (record->revival) {
+    std::cout << "(revived " << *record->revival << ")\n";
+  }
+  return 0;
+}
+
+int diff(const char *left_path, const char *right_path) {
+  CliCrdt left = CliArchive(left_path).to_crdt(CrdtNodeId{});
+  CliCrdt right = CliArchive(right_path).to_crdt(CrdtNodeId{});
+  std::cout << left.format_diff(right);
+  return left.diff(right).empty() ? 0 : 1;
+}
+
+int extract_changes(const char *path, const char *version_text, const char *out_path) {
+  uint64_t since = parse_version(version_text);
+  CliCrdt crdt = CliArchive(path).to_crdt(CrdtNodeId{});
+  CrdtVector<Change<K, V>> changes = crdt.get_changes_since(since);
+  write_file(out_path, crdt_codec::encode_changes(changes));
+  std::cout << changes.size() << " changes written\n";
+  return 0;
+}
+
+int merge(const char *path, const char *changes_path, const char *out_path) {
+  CliCrdt crdt = CliArchive(path).to_crdt(CrdtNodeId{});
+  CrdtVector<Change<K, V>> changes = crdt_codec::decode_changes<K, V>(read_file(changes_path));
+  size_t received = changes.size();
+  size_t accepted = crdt.merge_changes<true>(std::move(changes)).size();
+  crdt_archive::save(crdt, out_path);
+  std::cout << accepted << " of " << received << " changes accepted\n";
+  return 0;
+}
+
+int run(int argc, char **argv) {
+  if (argc < 2) {
+    throw UsageError("missing command");
+  }
+  std::string_view command = argv[1];
+  if (command == "dump" && argc == 3) {
+    return dump(argv[2]);
+  }
+  if (command == "record" && argc == 4) {
+    return show_record(argv[2], argv[3]);
+  }
+  if (command == "diff" && argc == 4) {
+    return diff(argv[2], argv[3]);
+  }
+  if (command == "changes" && argc == 5) {
+    return extract_changes(argv[2], argv[3], argv[4]);
+  }
+  if (command == "merge" && (argc == 4 || argc == 5)) {
+    return merge(argv[2], argv[3], argc == 5 ? argv[4] : argv[2]);
+  }
+  throw UsageError("unknown command or wrong number of arguments");
+}
+
+} // namespace
+
+int main(int argc, char **argv) {
+  try {
+    return run(argc, argv);
+  } catch (const UsageError &error) {
+    std::cerr << "crdt-lite: " << error.what() << "\n";
+    print_usage(std::cerr);
+    return 2;
+  } catch (const std::exception &error) {
+    std::cerr << "crdt-lite: " << error.what() << "\n";
+    return 2;
+  }
+}
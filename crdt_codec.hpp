@@ -0,0 +1,647 @@
+// crdt_codec.hpp
+#ifndef CRDT_CODEC_HPP
+#define CRDT_CODEC_HPP
+
+#include "crdt.hpp"
+
+#include <bit>
+#include <cstdint>
+#include <cstring>
+#include <istream>
+#include <optional>
+#include <stdexcept>
+
+// Compression support is opt-in: define CRDT_WITH_ZSTD and/or CRDT_WITH_LZ4 and link libzstd / liblz4.
+#ifdef CRDT_WITH_ZSTD
+#include <zstd.h>
+#endif
+#ifdef CRDT_WITH_LZ4
+#include <lz4.h>
+#endif
+
+using CrdtBytes = CrdtVector<uint8_t>;
+
+/// Thrown when decoding malformed or truncated input.
+class CrdtDecodeError : public std::runtime_error {
+public:
+  explicit CrdtDecodeError(const char *message) : std::runtime_error(message) {}
+};
+
+/// Appends primitive values to a byte buffer.
+class ByteWriter {
+public:
+  explicit ByteWriter(CrdtBytes &out) : out_(out) {}
+
+  void write_u8(uint8_t value) { out_.push_back(value); }
+
+  /// Writes an unsigned LEB128 varint.
+  void write_varint(uint64_t value) {
+    while (value >= 0x80) {
+      out_.push_back(static_cast<uint8_t>(value) | 0x80);
+      value >>= 7;
+    }
+    out_.push_back(static_cast<uint8_t>(value));
+  }
+
+  void write_u32_be(uint32_t value) {
+    for (int shift = 24; shift >= 0; shift -= 8) {
+      out_.push_back(static_cast<uint8_t>(value >> shift));
+    }
+  }
+
+  void write_u64_be(uint64_t value) {
+    for (int shift = 56; shift >= 0; shift -= 8) {
+      out_.push_back(static_cast<uint8_t>(value >> shift));
+    }
+  }
+
+  void write_bytes(const void *data, size_t size) {
+    const uint8_t *bytes = static_cast<const uint8_t *>(data);
+    out_.insert(out_.end(), bytes, bytes + size);
+  }
+
+  /// Writes a varint length followed by the bytes.
+  void write_blob(const void *data, size_t size) {
+    write_varint(size);
+    write_bytes(data, size);
+  }
+
+private:
+  CrdtBytes &out_;
+};
+
+/// Reads primitive values from a byte range, throwing CrdtDecodeError on truncated input.
+class ByteReader {
+public:
+  ByteReader(const uint8_t *begin, const uint8_t *end) : pos_(begin), end_(end) {}
+  explicit ByteReader(const CrdtBytes &bytes) : ByteReader(bytes.data(), bytes.data() + bytes.size()) {}
+
+  uint8_t read_u8() {
+    require(1);
+    return *pos_++;
+  }
+
+  uint64_t read_varint() {
+    uint64_t value = 0;
+    for (int shift = 0; shift < 64; shift += 7) {
+      uint8_t byte = read_u8();
+      value |= static_cast<uint64_t>(byte & 0x7f) << shift;
+      if ((byte & 0x80) == 0) {
+        return value;
+      }
+    }
+    throw CrdtDecodeError("varint too long");
+  }
+
+  uint32_t read_u32_be() {
+    require(4);
+    uint32_t value = 0;
+    for (int i = 0; i < 4; ++i) {
+      value = (value << 8) | *pos_++;
+    }
+    return value;
+  }
+
+  uint64_t read_u64_be() {
+    require(8);
+    uint64_t value = 0;
+    for (int i = 0; i < 8; ++i) {
+      value = (value << 8) | *pos_++;
+    }
+    return value;
+  }
+
+  const uint8_t *read_bytes(size_t size) {
+    require(size);
+    const uint8_t *start = pos_;
+    pos_ += size;
+    return start;
+  }
+
+  /// Reads a varint length followed by that many bytes.
+  std::pair<const uint8_t *, size_t> read_blob() {
+    uint64_t size = read_varint();
+    if (size > remaining()) {
+      throw CrdtDecodeError("blob length exceeds input");
+    }
+    return {read_bytes(static_cast<size_t>(size)), static_cast<size_t>(size)};
+  }
+
+  size_t remaining() const { return static_cast<size_t>(end_ - pos_); }
+  bool at_end() const { return pos_ == end_; }
+
+  /// The next byte to read.
+  const uint8_t *position() const { return pos_; }
+
+private:
+  const uint8_t *pos_;
+  const uint8_t *end_;
+
+  void require(size_t size) const {
+    if (remaining() < size) {
+      throw CrdtDecodeError("unexpected end of input");
+    }
+  }
+};
+
+/// Binary encoding for record ids and values. Specialize this for custom key or value types.
+template <typename T, typename Enable = void> struct CrdtCodec;
+
+template <> struct CrdtCodec<CrdtString> {
+  static void encode(ByteWriter &writer, const CrdtString &value) { writer.write_blob(value.data(), value.size()); }
+  static CrdtString decode(ByteReader &reader) {
+    auto [data, size] = reader.read_blob();
+    return CrdtString(reinterpret_cast<const char *>(data), size);
+  }
+};
+
+template <> struct CrdtCodec<CrdtBytes> {
+  static void encode(ByteWriter &writer, const CrdtBytes &value) { writer.write_blob(value.data(), value.size()); }
+  static CrdtBytes decode(ByteReader &reader) {
+    auto [data, size] = reader.read_blob();
+    return CrdtBytes(data, data + size);
+  }
+};
+
+template <> struct CrdtCodec<bool> {
+  static void encode(ByteWriter &writer, bool value) { writer.write_u8(value ? 1 : 0); }
+  static bool decode(ByteReader &reader) { return reader.read_u8() != 0; }
+};
+
+template <typename T> struct CrdtCodec<T, std::enable_if_t<std::is_integral_v<T> && !std::is_same_v<T, bool>>> {
+  static void encode(ByteWriter &writer, T value) {
+    if constexpr (std::is_signed_v<T>) {
+      // Zigzag so small negative numbers stay small
+      int64_t wide = value;
+      writer.write_varint((static_cast<uint64_t>(wide) << 1) ^ static_cast<uint64_t>(wide >> 63));
+    } else {
+      writer.write_varint(value);
+    }
+  }
+  static T decode(ByteReader &reader) {
+    uint64_t raw = reader.read_varint();
+    if constexpr (std::is_signed_v<T>) {
+      return static_cast<T>(static_cast<int64_t>((raw >> 1) ^ (~(raw & 1) + 1)));
+    } else {
+      return static_cast<T>(raw);
+    }
+  }
+};
+
+// Floats are written as their IEEE 754 bit patterns in little-endian order, whatever the host's byte order
+template <typename T> struct CrdtCodec<T, std::enable_if_t<std::is_floating_point_v<T>>> {
+  static_assert(sizeof(T) == 4 || sizeof(T) == 8, "only 32 and 64 bit floating point values can be encoded");
+  using Bits = std::conditional_t<sizeof(T) == 4, uint32_t, uint64_t>;
+
+  static void encode(ByteWriter &writer, T value) {
+    Bits bits = std::bit_cast<Bits>(value);
+    for (size_t i = 0; i < sizeof(T); ++i) {
+      writer.write_u8(static_cast<uint8_t>(bits >> (8 * i)));
+    }
+  }
+  static T decode(ByteReader &reader) {
+    const uint8_t *bytes = reader.read_bytes(sizeof(T));
+    Bits bits = 0;
+    for (size_t i = 0; i < sizeof(T); ++i) {
+      bits |= static_cast<Bits>(bytes[i]) << (8 * i);
+    }
+    return std::bit_cast<T>(bits);
+  }
+};
+
+template <> struct CrdtCodec<CrdtUuid> {
+  static void encode(ByteWriter &writer, const CrdtUuid &value) {
+    writer.write_u32_be(static_cast<uint32_t>(value.high >> 32));
+    writer.write_u32_be(static_cast<uint32_t>(value.high));
+    writer.write_u32_be(static_cast<uint32_t>(value.low >> 32));
+    writer.write_u32_be(static_cast<uint32_t>(value.low));
+  }
+  static CrdtUuid decode(ByteReader &reader) {
+    uint64_t words[4];
+    for (uint64_t &word : words) {
+      word = reader.read_u32_be();
+    }
+    return CrdtUuid{(words[0] << 32) | words[1], (words[2] << 32) | words[3]};
+  }
+};
+
+/// Compression codecs for change batches. The values are wire ids and bit positions in a support mask.
+enum class CrdtCompression : uint8_t {
+  None = 0,
+  Lz4 = 1,
+  Zstd = 2,
+};
+
+/// A change whose value this node could not decode, e.g. of a value type added by a newer version, kept with its
+/// original encoding so it can be passed on unchanged.
+template <typename K> struct CrdtOpaqueChange {
+  K record_id;
+  CrdtString col_name;
+  uint64_t col_version = 0;
+  uint64_t db_version = 0;
+  CrdtNodeId node_id;
+  CrdtBytes encoded; // the change as received, in the `crdt_codec::encode_change` format with a framed value
+};
+
+namespace crdt_codec {
+
+enum ChangeFlags : uint8_t {
+  HasColumn = 1 << 0,
+  HasValue = 1 << 1,
+  HasWallTime = 1 << 2,
+  FramedValue = 1 << 3,   // the value is length-prefixed, so a decoder that does not understand it can skip it
+  HasExtensions = 1 << 4, // a length-prefixed block of fields added after the first version: the varint seq, then
+                          // fields of later versions, which this one skips
+};
+
+template <typename V> void encode_value(ByteWriter &writer, const V &value, bool framed) {
+  if (!framed) {
+    CrdtCodec<V>::encode(writer, value);
+    return;
+  }
+  CrdtBytes bytes;
+  ByteWriter value_writer(bytes);
+  CrdtCodec<V>::encode(value_writer, value);
+  writer.write_blob(bytes.data(), bytes.size());
+}
+
+// Writes the fields shared by `Change` and `ChangeRef`, given the change's record id, column and value
+template <typename K, typename V, typename ChangeType>
+void write_change(ByteWriter &writer, const ChangeType &change, const K &record_id, const CrdtString *col_name,
+                  const V *value, bool framed_values) {
+  uint8_t flags = 0;
+  if (col_name) {
+    flags |= HasColumn;
+  }
+  if (value) {
+    flags |= HasValue | (framed_values ? FramedValue : 0);
+  }
+  if (change.wall_time != 0) {
+    flags |= HasWallTime;
+  }
+  if (change.seq != 0) {
+    flags |= HasExtensions;
+  }
+  writer.write_u8(flags);
+  CrdtCodec<K>::encode(writer, record_id);
+  if (col_name) {
+    CrdtCodec<CrdtString>::encode(writer, *col_name);
+  }
+  if (value) {
+    encode_value<V>(writer, *value, framed_values);
+  }
+  writer.write_varint(change.col_version);
+  writer.write_varint(change.db_version);
+  CrdtCodec<CrdtNodeId>::encode(writer, change.node_id);
+  if (change.wall_time != 0) {
+    writer.write_varint(change.wall_time);
+  }
+  if (change.seq != 0) {
+    CrdtBytes extensions;
+    ByteWriter(extensions).write_varint(change.seq);
+    writer.write_blob(extensions.data(), extensions.size());
+  }
+}
+
+/// Encodes a single change. `local_db_version` is not encoded, since it only has meaning on the originating node.
+///
+/// With `framed_values`, values are length-prefixed so a peer that cannot decode them (e.g. a value type added by a
+/// newer version) can skip the change; only use it with peers that support it (see `SyncCapability`).
+template <typename K, typename V>
+void encode_change(ByteWriter &writer, const Change<K, V> &change, bool framed_values = false) {
+  write_change<K, V>(writer, change, change.record_id, change.col_name ? &*change.col_name : nullptr,
+                     change.value ? &*change.value : nullptr, framed_values);
+}
+
+/// Decodes a single change, or returns std::nullopt for a change whose framed value this node cannot decode, which is
+/// then stored in `opaque` if given; the change is consumed either way.
+///
+/// Throws CrdtDecodeError on malformed input.
+template <typename K, typename V>
+std::optional<Change<K, V>> try_decode_change(ByteReader &reader, CrdtOpaqueChange<K> *opaque = nullptr) {
+  const uint8_t *start = reader.position();
+  uint8_t flags = reader.read_u8();
+  if (flags & ~(HasColumn | HasValue | HasWallTime | FramedValue | HasExtensions)) {
+    throw CrdtDecodeError("unknown change flags");
+  }
+  Change<K, V> change;
+  change.record_id = CrdtCodec<K>::decode(reader);
+  if (flags & HasColumn) {
+    change.col_name = CrdtCodec<CrdtString>::decode(reader);
+  }
+  bool understood = true;
+  if ((flags & HasValue) && (flags & FramedValue)) {
+    auto [data, size] = reader.read_blob();
+    try {
+      ByteReader value_reader(data, data + size);
+      change.value = CrdtCodec<V>::decode(value_reader);
+      understood = value_reader.at_end();
+    } catch (const CrdtDecodeError &) {
+      understood = false;
+    }
+  } else if (flags & HasValue) {
+    change.value = CrdtCodec<V>::decode(reader);
+  }
+  change.col_version = reader.read_varint();
+  change.db_version = reader.read_varint();
+  change.node_id = CrdtCodec<CrdtNodeId>::decode(reader);
+  change.local_db_version = 0;
+  if (flags & HasWallTime) {
+    change.wall_time = reader.read_varint();
+  }
+  if (flags & HasExtensions) {
+    auto [data, size] = reader.read_blob();
+    ByteReader extensions(data, data + size);
+    change.seq = extensions.at_end() ? 0 : extensions.read_varint();
+  }
+  if (!understood) {
+    if (opaque) {
+      *opaque = {std::move(change.record_id), change.col_name.value_or(CrdtString()), change.col_version,
+                 change.db_version, change.node_id, CrdtBytes(start, reader.position())};
+    }
+    return std::nullopt;
+  }
+  return change;
+}
+
+/// Throws CrdtDecodeError on malformed input, including a value this node cannot decode.
+template <typename K, typename V> Change<K, V> decode_change(ByteReader &reader) {
+  std::optional<Change<K, V>> change = try_decode_change<K, V>(reader);
+  if (!change) {
+    throw CrdtDecodeError("undecodable change value");
+  }
+  return std::move(*change);
+}
+
+/// Encodes a borrowed change; the wire format is the same as for `Change`.
+template <typename K, typename V>
+void encode_change(ByteWriter &writer, const ChangeRef<K, V> &change, bool framed_values = false) {
+  write_change<K, V>(writer, change, change.record_id.get(), change.col_name, change.value, framed_values);
+}
+
+/// Encodes a batch of changes (`Change` or `ChangeRef`) as a varint count followed by each change.
+template <typename ChangeType>
+void encode_changes(ByteWriter &writer, const CrdtVector<ChangeType> &changes, bool framed_values = false) {
+  writer.write_varint(changes.size());
+  for (const auto &change : changes) {
+    encode_change(writer, change, framed_values);
+  }
+}
+
+// Every encoded change takes at least this many bytes: its flags, col_version, db_version and node id
+constexpr size_t MIN_CHANGE_SIZE = 4;
+
+// Reads the count of a batch whose changes take at least `min_size` bytes each, rejecting counts the rest of the input
+// cannot hold, so hostile input cannot force a large reservation
+inline size_t read_change_count(ByteReader &reader, size_t min_size) {
+  uint64_t count = reader.read_varint();
+  if (count > reader.remaining() / min_size) {
+    throw CrdtDecodeError("change count exceeds input");
+  }
+  return static_cast<size_t>(count);
+}
+
+/// Decodes a batch. If `undecodable` is given, changes whose framed value cannot be decoded are left out and added to
+/// it; otherwise they throw CrdtDecodeError.
+template <typename K, typename V>
+CrdtVector<Change<K, V>> decode_changes(ByteReader &reader, CrdtVector<CrdtOpaqueChange<K>> *undecodable = nullptr) {
+  size_t count = read_change_count(reader, MIN_CHANGE_SIZE);
+  CrdtVector<Change<K, V>> changes;
+  changes.reserve(count);
+  for (size_t i = 0; i < count; ++i) {
+    if (!undecodable) {
+      changes.push_back(decode_change<K, V>(reader));
+      continue;
+    }
+    CrdtOpaqueChange<K> opaque;
+    if (std::optional<Change<K, V>> change = try_decode_change<K, V>(reader, &opaque)) {
+      changes.push_back(std::move(*change));
+    } else {
+      undecodable->push_back(std::move(opaque));
+    }
+  }
+  return changes;
+}
+
+/// Encodes a batch for local storage, e.g. in a change log or a recording: like `encode_changes`, with each change's
+/// `local_db_version` ahead of it.
+template <typename ChangeType> void encode_stored_changes(ByteWriter &writer, const CrdtVector<ChangeType> &changes) {
+  writer.write_varint(changes.size());
+  for (const auto &change : changes) {
+    writer.write_varint(change.local_db_version);
+    encode_change(writer, change);
+  }
+}
+
+/// Reverses `encode_stored_changes`. Throws CrdtDecodeError on malformed input.
+template <typename K, typename V> CrdtVector<Change<K, V>> decode_stored_changes(ByteReader &reader) {
+  size_t count = read_change_count(reader, MIN_CHANGE_SIZE + 1);
+  CrdtVector<Change<K, V>> changes;
+  changes.reserve(count);
+  for (size_t i = 0; i < count; ++i) {
+    uint64_t local_db_version = reader.read_varint();
+    changes.push_back(decode_change<K, V>(reader));
+    changes.back().local_db_version = local_db_version;
+  }
+  return changes;
+}
+
+constexpr size_t RECORD_HEADER_SIZE = 12;
+
+/// Frames `payload` as a storage record, as used by the change log, recordings and hub topic files: a u32 big-endian
+/// length and a u64 big-endian CrdtHasher checksum of the payload, which are followed by the payload itself.
+inline CrdtBytes record_header(const CrdtBytes &payload) {
+  CrdtHasher hasher;
+  hasher.update(payload.data(), payload.size());
+  CrdtBytes header;
+  ByteWriter writer(header);
+  writer.write_u32_be(static_cast<uint32_t>(payload.size()));
+  writer.write_u64_be(hasher.finish());
+  return header;
+}
+
+/// Reads the payload of the next storage record framed by `record_header`. Returns std::nullopt at the end of `in`,
+/// and for a final record cut short by a crash.
+///
+/// Throws CrdtDecodeError if the record is larger than `max_size` or its payload does not match the checksum.
+inline std::optional<CrdtBytes> read_record(std::istream &in, uint32_t max_size) {
+  uint8_t header[RECORD_HEADER_SIZE];
+  in.read(reinterpret_cast<char *>(header), sizeof(header));
+  if (in.gcount() != sizeof(header)) {
+    return std::nullopt;
+  }
+  ByteReader reader(header, header + sizeof(header));
+  uint32_t size = reader.read_u32_be();
+  uint64_t checksum = reader.read_u64_be();
+  if (size > max_size) {
+    throw CrdtDecodeError("storage record too large");
+  }
+  CrdtBytes payload(size);
+  in.read(reinterpret_cast<char *>(payload.data()), static_cast<std::streamsize>(size));
+  if (static_cast<uint32_t>(in.gcount()) != size) {
+    return std::nullopt;
+  }
+  CrdtHasher hasher;
+  hasher.update(payload.data(), payload.size());
+  if (hasher.finish() != checksum) {
+    throw CrdtDecodeError("storage record checksum mismatch");
+  }
+  return payload;
+}
+
+template <typename ChangeType>
+CrdtBytes encode_changes(const CrdtVector<ChangeType> &changes, bool framed_values = false) {
+  CrdtBytes out;
+  ByteWriter writer(out);
+  encode_changes(writer, changes, framed_values);
+  return out;
+}
+
+template <typename K, typename V>
+CrdtVector<Change<K, V>> decode_changes(const CrdtBytes &bytes, CrdtVector<CrdtOpaqueChange<K>> *undecodable = nullptr) {
+  ByteReader reader(bytes);
+  auto changes = decode_changes<K, V>(reader, undecodable);
+  if (!reader.at_end()) {
+    throw CrdtDecodeError("trailing bytes after changes");
+  }
+  return changes;
+}
+
+/// Batches smaller than this are sent uncompressed; the codec overhead isn't worth it.
+constexpr size_t COMPRESSION_MIN_SIZE = 256;
+
+/// Decompressed batches larger than this are rejected to bound memory use on hostile input.
+constexpr size_t MAX_DECOMPRESSED_SIZE = 256 * 1024 * 1024;
+
+constexpr uint64_t compression_bit(CrdtCompression codec) { return uint64_t{1} << static_cast<uint8_t>(codec); }
+
+/// Bitmask of the codecs compiled into this build.
+constexpr uint64_t supported_compression() {
+  uint64_t mask = compression_bit(CrdtCompression::None);
+#ifdef CRDT_WITH_LZ4
+  mask |= compression_bit(CrdtCompression::Lz4);
+#endif
+#ifdef CRDT_WITH_ZSTD
+  mask |= compression_bit(CrdtCompression::Zstd);
+#endif
+  return mask;
+}
+
+/// Picks the preferred codec present in `mask` (zstd, then lz4, then none).
+constexpr CrdtCompression choose_compression(uint64_t mask) {
+  for (CrdtCompression codec : {CrdtCompression::Zstd, CrdtCompression::Lz4}) {
+    if (mask & compression_bit(codec)) {
+      return codec;
+    }
+  }
+  return CrdtCompression::None;
+}
+
+/// Compresses `size` bytes with `codec`, which must be compiled in.
+inline CrdtBytes compress(CrdtCompression codec, const uint8_t *data, size_t size) {
+  switch (codec) {
+  case CrdtCompression::None:
+    return CrdtBytes(data, data + size);
+#ifdef CRDT_WITH_LZ4
+  case CrdtCompression::Lz4: {
+    if (size > static_cast<size_t>(LZ4_MAX_INPUT_SIZE)) {
+      throw std::length_error("batch too large for lz4");
+    }
+    CrdtBytes out(static_cast<size_t>(LZ4_compressBound(static_cast<int>(size))));
+    int written = LZ4_compress_default(reinterpret_cast<const char *>(data), reinterpret_cast<char *>(out.data()),
+                                       static_cast<int>(size), static_cast<int>(out.size()));
+    if (written <= 0) {
+      throw std::runtime_error("lz4 compression failed");
+    }
+    out.resize(static_cast<size_t>(written));
+    return out;
+  }
+#endif
+#ifdef CRDT_WITH_ZSTD
+  case CrdtCompression::Zstd: {
+    CrdtBytes out(ZSTD_compressBound(size));
+    size_t written = ZSTD_compress(out.data(), out.size(), data, size, 3);
+    if (ZSTD_isError(written)) {
+      throw std::runtime_error(ZSTD_getErrorName(written));
+    }
+    out.resize(written);
+    return out;
+  }
+#endif
+  default:
+    throw std::invalid_argument("compression codec not available");
+  }
+}
+
+/// Reverses `compress`. `original_size` is the size of the uncompressed input, transmitted alongside.
+inline CrdtBytes decompress(CrdtCompression codec, const uint8_t *data, size_t size, size_t original_size) {
+  if (original_size > MAX_DECOMPRESSED_SIZE) {
+    throw CrdtDecodeError("decompressed batch exceeds maximum size");
+  }
+  switch (codec) {
+  case CrdtCompression::None:
+    if (size != original_size) {
+      throw CrdtDecodeError("uncompressed batch size mismatch");
+    }
+    return CrdtBytes(data, data + size);
+#ifdef CRDT_WITH_LZ4
+  case CrdtCompression::Lz4: {
+    CrdtBytes out(original_size);
+    int read = LZ4_decompress_safe(reinterpret_cast<const char *>(data), reinterpret_cast<char *>(out.data()),
+                                   static_cast<int>(size), static_cast<int>(out.size()));
+    if (read < 0 || static_cast<size_t>(read) != original_size) {
+      throw CrdtDecodeError("malformed lz4 batch");
+    }
+    return out;
+  }
+#endif
+#ifdef CRDT_WITH_ZSTD
+  case CrdtCompression::Zstd: {
+    CrdtBytes out(original_size);
+    size_t read = ZSTD_decompress(out.data(), out.size(), data, size);
+    if (ZSTD_isError(read) || read != original_size) {
+      throw CrdtDecodeError("malformed zstd batch");
+    }
+    return out;
+  }
+#endif
+  default:
+    throw CrdtDecodeError("unsupported compression codec");
+  }
+}
+
+/// Encodes a batch already in the `encode_changes` format as a codec byte, the uncompressed size and the (possibly
+/// compressed) bytes. Batches below COMPRESSION_MIN_SIZE are written with CrdtCompression::None whatever `codec` is.
+inline void encode_batch_compressed(ByteWriter &writer, CrdtBytes raw, CrdtCompression codec) {
+  if (raw.size() < COMPRESSION_MIN_SIZE) {
+    codec = CrdtCompression::None;
+  }
+  CrdtBytes payload = codec == CrdtCompression::None ? std::move(raw) : compress(codec, raw.data(), raw.size());
+  writer.write_u8(static_cast<uint8_t>(codec));
+  writer.write_varint(codec == CrdtCompression::None ? payload.size() : raw.size());
+  writer.write_blob(payload.data(), payload.size());
+}
+
+/// Encodes a batch of changes with `encode_changes` and `encode_batch_compressed`.
+template <typename ChangeType>
+void encode_changes_compressed(ByteWriter &writer, const CrdtVector<ChangeType> &changes, CrdtCompression codec,
+                               bool framed_values = false) {
+  encode_batch_compressed(writer, encode_changes(changes, framed_values), codec);
+}
+
+/// See `decode_changes` for `undecodable`.
+template <typename K, typename V>
+CrdtVector<Change<K, V>> decode_changes_compressed(ByteReader &reader,
+                                                   CrdtVector<CrdtOpaqueChange<K>> *undecodable = nullptr) {
+  auto codec = static_cast<CrdtCompression>(reader.read_u8());
+  uint64_t original_size = reader.read_varint();
+  auto [data, size] = reader.read_blob();
+  if (original_size > MAX_DECOMPRESSED_SIZE) {
+    throw CrdtDecodeError("decompressed batch exceeds maximum size");
+  }
+  return decode_changes<K, V>(decompress(codec, data, size, static_cast<size_t>(original_size)), undecodable);
+}
+
+} // namespace crdt_codec
+
+#endif // CRDT_CODEC_HPP
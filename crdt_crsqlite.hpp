@@ -0,0 +1,221 @@
+// crdt_crsqlite.hpp
+#ifndef CRDT_CRSQLITE_HPP
+#define CRDT_CRSQLITE_HPP
+
+// Conversion between crdt-lite changes and cr-sqlite changeset rows (`SELECT * FROM crsql_changes`).
+//
+// Both libraries version each column with (col_version, db_version, site id), so changes map one to one:
+//
+// * A column write maps to a row with `cid` = column name and `val` = the value.
+// * A record deletion maps to the sentinel row (`cid` = "-1") with an even causal length (`cl`), and a revival to the
+//   sentinel row with an odd one. A record's incarnation is its causal length minus one.
+// * A column deletion has no cr-sqlite equivalent and maps to a NULL `val`.
+//
+// Record ids are packed into `pk` with cr-sqlite's packed-column encoding, and node ids become 16-byte site ids.
+// Rows are grouped into one table per CRDT; rows of other tables are skipped on import.
+
+#include "crdt_codec.hpp"
+
+namespace crdt_crsqlite {
+
+/// The `cid` of cr-sqlite's row-level sentinel, used for deletions and revivals.
+inline const CrdtString SENTINEL_CID = "-1";
+
+/// One row of `crsql_changes`. `val` is std::nullopt for SQL NULL.
+template <typename V> struct ChangeRow {
+  CrdtString table;
+  CrdtBytes pk;
+  CrdtString cid;
+  std::optional<V> val;
+  int64_t col_version = 0;
+  int64_t db_version = 0;
+  CrdtBytes site_id; // 16 bytes
+  int64_t cl = 1;    // causal length: odd while the row exists, even once deleted
+  int64_t seq = 0;
+};
+
+enum PackedType : uint8_t {
+  Integer = 1,
+  Float = 2,
+  Text = 3,
+  Blob = 4,
+  Null = 5,
+};
+
+inline uint8_t bytes_needed(uint64_t value) {
+  uint8_t bytes = 8;
+  while (bytes > 1 && (value >> ((bytes - 1) * 8)) == 0) {
+    --bytes;
+  }
+  return bytes;
+}
+
+inline void write_int(ByteWriter &writer, uint64_t value, uint8_t bytes) {
+  for (int shift = (bytes - 1) * 8; shift >= 0; shift -= 8) {
+    writer.write_u8(static_cast<uint8_t>(value >> shift));
+  }
+}
+
+inline uint64_t read_int(ByteReader &reader, uint8_t bytes) {
+  if (bytes > 8) {
+    throw CrdtDecodeError("packed integer too wide");
+  }
+  uint64_t value = 0;
+  for (uint8_t i = 0; i < bytes; ++i) {
+    value = (value << 8) | reader.read_u8();
+  }
+  return value;
+}
+
+/// Packs a single-column primary key. Specialize for other key types (or composite keys).
+template <typename K, typename Enable = void> struct PrimaryKey;
+
+template <> struct PrimaryKey<CrdtString> {
+  static CrdtBytes pack(const CrdtString &key) {
+    CrdtBytes out;
+    ByteWriter writer(out);
+    writer.write_u8(1); // column count
+    uint8_t len_bytes = bytes_needed(key.size());
+    writer.write_u8(static_cast<uint8_t>(len_bytes << 3 | Text));
+    write_int(writer, key.size(), len_bytes);
+    writer.write_bytes(key.data(), key.size());
+    return out;
+  }
+
+  static CrdtString unpack(const CrdtBytes &pk) {
+    ByteReader reader(pk);
+    if (reader.read_u8() != 1) {
+      throw CrdtDecodeError("expected a single-column primary key");
+    }
+    uint8_t type = reader.read_u8();
+    if ((type & 0x07) != Text) {
+      throw CrdtDecodeError("expected a text primary key");
+    }
+    uint64_t size = read_int(reader, type >> 3);
+    if (size > reader.remaining()) {
+      throw CrdtDecodeError("primary key length exceeds input");
+    }
+    const uint8_t *data = reader.read_bytes(static_cast<size_t>(size));
+    return CrdtString(reinterpret_cast<const char *>(data), static_cast<size_t>(size));
+  }
+};
+
+template <typename K> struct PrimaryKey<K, std::enable_if_t<std::is_integral_v<K>>> {
+  static CrdtBytes pack(K key) {
+    CrdtBytes out;
+    ByteWriter writer(out);
+    writer.write_u8(1); // column count
+    uint64_t bits = static_cast<uint64_t>(static_cast<int64_t>(key));
+    uint8_t bytes = bytes_needed(bits);
+    writer.write_u8(static_cast<uint8_t>(bytes << 3 | Integer));
+    write_int(writer, bits, bytes);
+    return out;
+  }
+
+  static K unpack(const CrdtBytes &pk) {
+    ByteReader reader(pk);
+    if (reader.read_u8() != 1) {
+      throw CrdtDecodeError("expected a single-column primary key");
+    }
+    uint8_t type = reader.read_u8();
+    if ((type & 0x07) != Integer) {
+      throw CrdtDecodeError("expected an integer primary key");
+    }
+    uint8_t bytes = type >> 3;
+    uint64_t bits = read_int(reader, bytes);
+    if (bytes < 8 && (bits >> (bytes * 8 - 1)) & 1) {
+      bits |= ~uint64_t{0} << (bytes * 8); // sign-extend
+    }
+    return static_cast<K>(static_cast<int64_t>(bits));
+  }
+};
+
+/// Converts a node id to a 16-byte site id: UUIDs map directly, integer ids are stored big-endian in the low 8 bytes.
+template <typename NodeId = CrdtNodeId> CrdtBytes site_id(const NodeId &node_id) {
+  CrdtBytes out;
+  ByteWriter writer(out);
+  if constexpr (std::is_same_v<NodeId, CrdtUuid>) {
+    write_int(writer, node_id.high, 8);
+    write_int(writer, node_id.low, 8);
+  } else {
+    write_int(writer, 0, 8);
+    write_int(writer, static_cast<uint64_t>(node_id), 8);
+  }
+  return out;
+}
+
+/// Reverses `site_id`. Throws CrdtDecodeError if the site id is not 16 bytes, or does not fit an integer node id.
+template <typename NodeId = CrdtNodeId> NodeId node_id(const CrdtBytes &site_id) {
+  if (site_id.size() != 16) {
+    throw CrdtDecodeError("site id must be 16 bytes");
+  }
+  ByteReader reader(site_id);
+  uint64_t high = read_int(reader, 8);
+  uint64_t low = read_int(reader, 8);
+  if constexpr (std::is_same_v<NodeId, CrdtUuid>) {
+    return CrdtUuid{high, low};
+  } else {
+    if (high != 0) {
+      throw CrdtDecodeError("site id does not fit an integer node id; define CRDT_UUID_NODE_IDS");
+    }
+    return static_cast<NodeId>(low);
+  }
+}
+
+/// Converts changes of one CRDT into `crsql_changes` rows for `table`.
+template <typename K, typename V> CrdtVector<ChangeRow<V>> to_rows(const CrdtVector<Change<K, V>> &changes, const CrdtString &table) {
+  CrdtVector<ChangeRow<V>> rows;
+  rows.reserve(changes.size());
+  for (const auto &change : changes) {
+    ChangeRow<V> row;
+    row.table = table;
+    row.pk = PrimaryKey<K>::pack(change.record_id);
+    row.db_version = static_cast<int64_t>(change.db_version);
+    row.site_id = site_id(change.node_id);
+    if (change.col_name) {
+      // The incarnation in the upper bits of the column version is the row's causal length, minus one
+      row.cid = *change.col_name;
+      row.val = change.value;
+      row.col_version = static_cast<int64_t>(change.col_version & 0xffffffff);
+      row.cl = static_cast<int64_t>(change.col_version >> 32) + 1;
+    } else {
+      row.cid = SENTINEL_CID;
+      row.cl = static_cast<int64_t>(change.col_version) + 1; // even for a deletion, odd for a revival
+      row.col_version = row.cl;
+    }
+    rows.push_back(std::move(row));
+  }
+  return rows;
+}
+
+/// Converts `crsql_changes` rows of `table` into changes to merge; rows of other tables are skipped.
+///
+/// Sentinel rows with a causal length of 1 (a row being created) carry no data and are skipped as well.
+template <typename K, typename V> CrdtVector<Change<K, V>> from_rows(const CrdtVector<ChangeRow<V>> &rows, const CrdtString &table) {
+  CrdtVector<Change<K, V>> changes;
+  for (const auto &row : rows) {
+    if (row.table != table) {
+      continue;
+    }
+    if (row.col_version < 0 || row.db_version < 0) {
+      throw CrdtDecodeError("negative version in changeset row");
+    }
+    K record_id = PrimaryKey<K>::unpack(row.pk);
+    CrdtNodeId node = node_id(row.site_id);
+    if (row.cid == SENTINEL_CID) {
+      if (row.cl > 1) {
+        changes.emplace_back(std::move(record_id), std::nullopt, std::nullopt, static_cast<uint64_t>(row.cl - 1),
+                             static_cast<uint64_t>(row.db_version), node);
+      }
+      continue;
+    }
+    uint64_t incarnation = row.cl > 1 ? static_cast<uint64_t>(row.cl - 1) : 0;
+    changes.emplace_back(std::move(record_id), row.cid, row.val, (incarnation << 32) | static_cast<uint64_t>(row.col_version),
+                         static_cast<uint64_t>(row.db_version), node);
+  }
+  return changes;
+}
+
+} // namespace crdt_crsqlite
+
+#endif // CRDT_CRSQLITE_HPP
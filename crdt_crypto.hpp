@@ -0,0 +1,312 @@
+// crdt_crypto.hpp
+#ifndef CRDT_CRYPTO_HPP
+#define CRDT_CRYPTO_HPP
+
+// End-to-end encryption of change values with ChaCha20-Poly1305. Link with -lcrypto (OpenSSL 1.1 or later).
+//
+// `encrypt_changes` replaces each value by a sealed blob and leaves the version metadata (record id, column name,
+// col_version, db_version, node id) in the clear. An untrusted relay can therefore run a plain CRDT<K, CrdtBytes>:
+// it merges, orders and forwards encrypted batches with the usual conflict resolution, without reading user data.
+// The metadata is authenticated along with the value, so the relay cannot move a value to another record, column or
+// version without `decrypt_changes` rejecting it.
+//
+// `save_encrypted` and `load_encrypted` persist a whole CRDT encrypted at rest, with a raw key or a password (scrypt).
+// The state is written and read in independently authenticated frames, so large snapshots stream through a bounded
+// buffer, and a truncated or reordered file fails to load instead of silently losing records.
+
+#include "crdt_codec.hpp"
+
+#include <openssl/evp.h>
+#include <openssl/rand.h>
+
+#include <array>
+#include <istream>
+#include <memory>
+#include <ostream>
+#include <string_view>
+
+/// A 256-bit symmetric key.
+using CrdtKey = std::array<uint8_t, 32>;
+
+namespace crdt_crypto {
+
+constexpr size_t NONCE_SIZE = 12;
+constexpr size_t TAG_SIZE = 16;
+
+inline void random_bytes(uint8_t *out, size_t size) {
+  if (RAND_bytes(out, static_cast<int>(size)) != 1) {
+    throw std::runtime_error("random number generation failed");
+  }
+}
+
+inline CrdtKey random_key() {
+  CrdtKey key;
+  random_bytes(key.data(), key.size());
+  return key;
+}
+
+using CipherContext = std::unique_ptr<EVP_CIPHER_CTX, decltype(&EVP_CIPHER_CTX_free)>;
+
+/// Encrypts and authenticates `plain` together with the unencrypted `aad`, with a random nonce.
+///
+/// # Returns
+///
+/// nonce || ciphertext || tag
+inline CrdtBytes seal(const CrdtKey &key, const uint8_t *plain, size_t size, const CrdtBytes &aad) {
+  CrdtBytes out(NONCE_SIZE + size + TAG_SIZE);
+  random_bytes(out.data(), NONCE_SIZE);
+  CipherContext ctx(EVP_CIPHER_CTX_new(), &EVP_CIPHER_CTX_free);
+  int len = 0;
+  if (!ctx || EVP_EncryptInit_ex(ctx.get(), EVP_chacha20_poly1305(), nullptr, key.data(), out.data()) != 1 ||
+      EVP_EncryptUpdate(ctx.get(), nullptr, &len, aad.data(), static_cast<int>(aad.size())) != 1 ||
+      EVP_EncryptUpdate(ctx.get(), out.data() + NONCE_SIZE, &len, plain, static_cast<int>(size)) != 1 ||
+      EVP_EncryptFinal_ex(ctx.get(), out.data() + NONCE_SIZE + len, &len) != 1 ||
+      EVP_CIPHER_CTX_ctrl(ctx.get(), EVP_CTRL_AEAD_GET_TAG, TAG_SIZE, out.data() + NONCE_SIZE + size) != 1) {
+    throw std::runtime_error("encryption failed");
+  }
+  return out;
+}
+
+/// Reverses `seal`. Throws CrdtDecodeError if the key is wrong or the data or `aad` were modified.
+inline CrdtBytes open(const CrdtKey &key, const uint8_t *sealed, size_t size, const CrdtBytes &aad) {
+  if (size < NONCE_SIZE + TAG_SIZE) {
+    throw CrdtDecodeError("sealed data too short");
+  }
+  size_t plain_size = size - NONCE_SIZE - TAG_SIZE;
+  CrdtBytes out(plain_size);
+  CipherContext ctx(EVP_CIPHER_CTX_new(), &EVP_CIPHER_CTX_free);
+  int len = 0;
+  if (!ctx || EVP_DecryptInit_ex(ctx.get(), EVP_chacha20_poly1305(), nullptr, key.data(), sealed) != 1 ||
+      EVP_DecryptUpdate(ctx.get(), nullptr, &len, aad.data(), static_cast<int>(aad.size())) != 1 ||
+      EVP_DecryptUpdate(ctx.get(), out.data(), &len, sealed + NONCE_SIZE, static_cast<int>(plain_size)) != 1 ||
+      EVP_CIPHER_CTX_ctrl(ctx.get(), EVP_CTRL_AEAD_SET_TAG, TAG_SIZE, const_cast<uint8_t *>(sealed + size - TAG_SIZE)) !=
+          1) {
+    throw std::runtime_error("decryption setup failed");
+  }
+  if (EVP_DecryptFinal_ex(ctx.get(), out.data() + len, &len) != 1) {
+    throw CrdtDecodeError("authentication failed");
+  }
+  return out;
+}
+
+// Everything of a change except its value, which must not be separable from it
+template <typename K> CrdtBytes change_aad(const K &record_id, const std::optional<CrdtString> &col_name,
+                                           uint64_t col_version, uint64_t db_version, const CrdtNodeId &node_id) {
+  CrdtBytes aad;
+  ByteWriter writer(aad);
+  CrdtCodec<K>::encode(writer, record_id);
+  CrdtCodec<CrdtString>::encode(writer, col_name ? *col_name : CrdtString());
+  writer.write_varint(col_version);
+  writer.write_varint(db_version);
+  CrdtCodec<CrdtNodeId>::encode(writer, node_id);
+  return aad;
+}
+
+/// Encrypts the values of `changes`. `key_for(record_id)` returns the key of each record, which allows per-record keys
+/// (e.g. one per shared document); deletions carry no value and are passed through.
+template <typename K, typename V, typename KeyFor>
+  requires std::invocable<KeyFor &, const K &>
+CrdtVector<Change<K, CrdtBytes>> encrypt_changes(const CrdtVector<Change<K, V>> &changes, KeyFor &&key_for) {
+  CrdtVector<Change<K, CrdtBytes>> out;
+  out.reserve(changes.size());
+  for (const auto &change : changes) {
+    std::optional<CrdtBytes> sealed;
+    if (change.value) {
+      CrdtBytes plain;
+      ByteWriter writer(plain);
+      CrdtCodec<V>::encode(writer, *change.value);
+      sealed = seal(key_for(change.record_id), plain.data(), plain.size(),
+                    change_aad(change.record_id, change.col_name, change.col_version, change.db_version, change.node_id));
+    }
+    out.emplace_back(change.record_id, change.col_name, std::move(sealed), change.col_version, change.db_version,
+                     change.node_id, change.local_db_version);
+    out.back().wall_time = change.wall_time;
+    out.back().seq = change.seq;
+  }
+  return out;
+}
+
+/// Encrypts the values of `changes` with a single shared key.
+template <typename K, typename V>
+CrdtVector<Change<K, CrdtBytes>> encrypt_changes(const CrdtVector<Change<K, V>> &changes, const CrdtKey &key) {
+  return encrypt_changes(changes, [&](const K &) -> const CrdtKey & { return key; });
+}
+
+/// Reverses `encrypt_changes`. Throws CrdtDecodeError if a value fails authentication.
+template <typename K, typename V, typename KeyFor>
+  requires std::invocable<KeyFor &, const K &>
+CrdtVector<Change<K, V>> decrypt_changes(const CrdtVector<Change<K, CrdtBytes>> &changes, KeyFor &&key_for) {
+  CrdtVector<Change<K, V>> out;
+  out.reserve(changes.size());
+  for (const auto &change : changes) {
+    std::optional<V> value;
+    if (change.value) {
+      CrdtBytes plain =
+          open(key_for(change.record_id), change.value->data(), change.value->size(),
+               change_aad(change.record_id, change.col_name, change.col_version, change.db_version, change.node_id));
+      ByteReader reader(plain);
+      value = CrdtCodec<V>::decode(reader);
+    }
+    out.emplace_back(change.record_id, change.col_name, std::move(value), change.col_version, change.db_version,
+                     change.node_id, change.local_db_version);
+    out.back().wall_time = change.wall_time;
+    out.back().seq = change.seq;
+  }
+  return out;
+}
+
+/// Decrypts changes encrypted with a single shared key.
+template <typename K, typename V>
+CrdtVector<Change<K, V>> decrypt_changes(const CrdtVector<Change<K, CrdtBytes>> &changes, const CrdtKey &key) {
+  return decrypt_changes<K, V>(changes, [&](const K &) -> const CrdtKey & { return key; });
+}
+
+/// Derives a key from a password with scrypt (N = 2^15, r = 8, p = 1).
+inline CrdtKey derive_key(std::string_view password, const uint8_t *salt, size_t salt_size) {
+  CrdtKey key;
+  if (EVP_PBE_scrypt(password.data(), password.size(), salt, salt_size, uint64_t{1} << 15, 8, 1, 64 * 1024 * 1024,
+                     key.data(), key.size()) != 1) {
+    throw std::runtime_error("key derivation failed");
+  }
+  return key;
+}
+
+constexpr char SNAPSHOT_MAGIC[8] = {'C', 'R', 'D', 'T', 'E', 'N', 'C', '1'};
+constexpr size_t SALT_SIZE = 16;
+constexpr size_t SNAPSHOT_HEADER_SIZE = sizeof(SNAPSHOT_MAGIC) + 1 + SALT_SIZE;
+constexpr size_t CHANGES_PER_FRAME = 1024;
+constexpr uint32_t MAX_FRAME_SIZE = 64 * 1024 * 1024;
+
+enum SnapshotKdf : uint8_t {
+  RawKey = 0,
+  Scrypt = 1,
+};
+
+// Frames are bound to the file header, their position and whether they end the snapshot
+inline CrdtBytes frame_aad(const CrdtBytes &header, uint64_t index, bool last) {
+  CrdtBytes aad = header;
+  ByteWriter writer(aad);
+  writer.write_varint(index);
+  writer.write_u8(last ? 1 : 0);
+  return aad;
+}
+
+template <typename CrdtType>
+void save_encrypted(std::ostream &out, const CrdtType &crdt, const CrdtKey &key, SnapshotKdf kdf, const uint8_t *salt) {
+  CrdtBytes header(SNAPSHOT_MAGIC, SNAPSHOT_MAGIC + sizeof(SNAPSHOT_MAGIC));
+  header.push_back(kdf);
+  header.insert(header.end(), salt, salt + SALT_SIZE);
+  out.write(reinterpret_cast<const char *>(header.data()), static_cast<std::streamsize>(header.size()));
+
+  using Ref = ChangeRef<typename CrdtType::KeyType, typename CrdtType::ValueType>;
+  uint64_t index = 0;
+  auto write_frame = [&](const CrdtVector<Ref> &frame, bool last) {
+    CrdtBytes plain = crdt_codec::encode_changes(frame);
+    CrdtBytes sealed = seal(key, plain.data(), plain.size(), frame_aad(header, index++, last));
+    CrdtBytes length;
+    ByteWriter(length).write_u32_be(static_cast<uint32_t>(sealed.size()));
+    out.write(reinterpret_cast<const char *>(length.data()), static_cast<std::streamsize>(length.size()));
+    out.write(reinterpret_cast<const char *>(sealed.data()), static_cast<std::streamsize>(sealed.size()));
+  };
+
+  // A full frame is held back until the next change shows it is not the last one
+  CrdtVector<Ref> frame;
+  frame.reserve(CHANGES_PER_FRAME);
+  crdt.for_each_change_since(0, [&](const Ref &change) {
+    if (frame.size() == CHANGES_PER_FRAME) {
+      write_frame(frame, false);
+      frame.clear();
+    }
+    frame.push_back(change);
+  });
+  write_frame(frame, true);
+  if (!out) {
+    throw std::runtime_error("failed to write snapshot");
+  }
+}
+
+/// Writes the full state of `crdt` to `out`, encrypted with `key`.
+template <typename CrdtType> void save_encrypted(std::ostream &out, const CrdtType &crdt, const CrdtKey &key) {
+  uint8_t salt[SALT_SIZE] = {};
+  save_encrypted(out, crdt, key, RawKey, salt);
+}
+
+/// Writes the full state of `crdt` to `out`, encrypted with a key derived from `password` and a random salt.
+template <typename CrdtType> void save_encrypted(std::ostream &out, const CrdtType &crdt, std::string_view password) {
+  uint8_t salt[SALT_SIZE];
+  random_bytes(salt, SALT_SIZE);
+  save_encrypted(out, crdt, derive_key(password, salt, SALT_SIZE), Scrypt, salt);
+}
+
+inline bool read_exact(std::istream &in, uint8_t *data, size_t size) {
+  in.read(reinterpret_cast<char *>(data), static_cast<std::streamsize>(size));
+  return static_cast<size_t>(in.gcount()) == size;
+}
+
+// Either the key itself, or the password to derive it from
+template <typename CrdtType, typename KeySource>
+CrdtType load_encrypted(std::istream &in, CrdtNodeId node_id, const KeySource &source, SnapshotKdf expected) {
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+  CrdtBytes header(SNAPSHOT_HEADER_SIZE);
+  if (!read_exact(in, header.data(), header.size()) ||
+      !std::equal(SNAPSHOT_MAGIC, SNAPSHOT_MAGIC + sizeof(SNAPSHOT_MAGIC), header.begin())) {
+    throw CrdtDecodeError("not an encrypted snapshot");
+  }
+  if (header[sizeof(SNAPSHOT_MAGIC)] != expected) {
+    throw CrdtDecodeError(expected == Scrypt ? "snapshot was not saved with a password" : "snapshot was saved with a password");
+  }
+  CrdtKey key;
+  if constexpr (std::is_same_v<KeySource, CrdtKey>) {
+    key = source;
+  } else {
+    key = derive_key(source, header.data() + sizeof(SNAPSHOT_MAGIC) + 1, SALT_SIZE);
+  }
+
+  CrdtType crdt(node_id);
+  for (uint64_t index = 0;; ++index) {
+    uint8_t length[4];
+    if (!read_exact(in, length, sizeof(length))) {
+      throw CrdtDecodeError("snapshot is truncated");
+    }
+    uint32_t size = ByteReader(length, length + sizeof(length)).read_u32_be();
+    if (size > MAX_FRAME_SIZE) {
+      throw CrdtDecodeError("snapshot frame too large");
+    }
+    CrdtBytes sealed(size);
+    if (!read_exact(in, sealed.data(), sealed.size())) {
+      throw CrdtDecodeError("snapshot is truncated");
+    }
+    // The final flag is authenticated, so try the common case first and fall back for the last frame
+    CrdtBytes plain;
+    bool last = false;
+    try {
+      plain = open(key, sealed.data(), sealed.size(), frame_aad(header, index, false));
+    } catch (const CrdtDecodeError &) {
+      plain = open(key, sealed.data(), sealed.size(), frame_aad(header, index, true));
+      last = true;
+    }
+    crdt.merge_changes(crdt_codec::decode_changes<K, V>(plain));
+    if (last) {
+      return crdt;
+    }
+  }
+}
+
+/// Loads a CRDT saved with `save_encrypted` and a key, as node `node_id`.
+///
+/// Throws CrdtDecodeError if the key is wrong or the snapshot was modified or truncated.
+template <typename CrdtType> CrdtType load_encrypted(std::istream &in, CrdtNodeId node_id, const CrdtKey &key) {
+  return load_encrypted<CrdtType>(in, node_id, key, RawKey);
+}
+
+/// Loads a CRDT saved with `save_encrypted` and a password, as node `node_id`.
+///
+/// Throws CrdtDecodeError if the password is wrong or the snapshot was modified or truncated.
+template <typename CrdtType> CrdtType load_encrypted(std::istream &in, CrdtNodeId node_id, std::string_view password) {
+  return load_encrypted<CrdtType>(in, node_id, password, Scrypt);
+}
+
+} // namespace crdt_crypto
+
+#endif // CRDT_CRYPTO_HPP
@@ -0,0 +1,144 @@
+// crdt_fuzz.hpp
+#ifndef CRDT_FUZZ_HPP
+#define CRDT_FUZZ_HPP
+
+// Fuzzing entry points for the wire format and the merge logic.
+//
+// Each function takes arbitrary bytes, never throws, and reports what happened as a CrdtFuzzResult, so it can be
+// called from a libFuzzer or AFL harness (see fuzz_targets.cpp) and continuously fuzzed. Malformed input is expected
+// and reported as Rejected; a harness should only treat Failed and InvariantViolated as crashes, since those mean an
+// unexpected exception escaped or replicas that saw the same changes disagree.
+
+#include "crdt_codec.hpp"
+#include "crdt_sync.hpp"
+
+enum class CrdtFuzzResult {
+  Ok,                // the input was processed
+  Rejected,          // the input is malformed and was rejected with CrdtDecodeError or SyncProtocolError
+  Failed,            // another exception escaped: a bug
+  InvariantViolated, // replicas did not converge, or merging was not idempotent: a bug
+};
+
+namespace crdt_fuzz {
+
+using FuzzCrdt = CRDT<CrdtString, CrdtString>;
+
+// Runs `fn`, converting exceptions to results
+template <typename Fn> CrdtFuzzResult guard(Fn &&fn) {
+  try {
+    return fn();
+  } catch (const CrdtDecodeError &) {
+    return CrdtFuzzResult::Rejected;
+  } catch (const SyncProtocolError &) {
+    return CrdtFuzzResult::Rejected;
+  } catch (...) {
+    return CrdtFuzzResult::Failed;
+  }
+}
+
+/// Decodes `data` as an encoded change batch, plain and compressed, and checks that a decoded batch encodes back to
+/// the same changes. Rejected only if neither decodes.
+inline CrdtFuzzResult decode_changes(const uint8_t *data, size_t size) {
+  auto roundtrip = [](const CrdtVector<Change<CrdtString, CrdtString>> &changes) {
+    CrdtBytes encoded = crdt_codec::encode_changes(changes, true);
+    return crdt_codec::encode_changes(crdt_codec::decode_changes<CrdtString, CrdtString>(encoded), true) == encoded
+               ? CrdtFuzzResult::Ok
+               : CrdtFuzzResult::InvariantViolated;
+  };
+  CrdtFuzzResult plain = guard([&] {
+    ByteReader reader(data, data + size);
+    auto changes = crdt_codec::decode_changes<CrdtString, CrdtString>(reader);
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after changes");
+    }
+    return roundtrip(changes);
+  });
+  CrdtFuzzResult compressed = guard([&] {
+    ByteReader reader(data, data + size);
+    return roundtrip(crdt_codec::decode_changes_compressed<CrdtString, CrdtString>(reader));
+  });
+  if (plain == CrdtFuzzResult::Rejected) {
+    return compressed;
+  }
+  if (compressed == CrdtFuzzResult::Rejected) {
+    return plain;
+  }
+  return std::max(plain, compressed); // the worse of both
+}
+
+/// Feeds `data`, split into frames by a leading varint length each, to a sync session as if a peer sent them.
+inline CrdtFuzzResult sync_frames(const uint8_t *data, size_t size) {
+  return guard([&] {
+    FuzzCrdt crdt(1);
+    crdt.insert_or_update("record", {{"field", "value"}});
+    SyncSession<FuzzCrdt> session(crdt, 1);
+    session.start();
+    ByteReader reader(data, data + size);
+    while (!reader.at_end()) {
+      auto [frame, frame_size] = reader.read_blob();
+      session.on_frame(CrdtBytes(frame, frame + frame_size));
+    }
+    return CrdtFuzzResult::Ok;
+  });
+}
+
+/// Interprets `data` as a sequence of operations on three replicas: local writes and deletes, merges between the
+/// replicas, and merges of arbitrary encoded changes into the first one, up to the first one that does not decode.
+/// Afterwards every replica receives every other one's changes, and they must then agree, and stay unchanged when
+/// merging them again.
+inline CrdtFuzzResult merge_sequence(const uint8_t *data, size_t size) {
+  return guard([&] {
+    FuzzCrdt replicas[3] = {FuzzCrdt(1), FuzzCrdt(2), FuzzCrdt(3)};
+    ByteReader reader(data, data + size);
+    auto small = [&](uint8_t modulo) {
+      return reader.at_end() ? uint8_t(0) : static_cast<uint8_t>(reader.read_u8() % modulo);
+    };
+    bool done = false;
+    while (!done && !reader.at_end()) {
+      uint8_t op = small(4);
+      FuzzCrdt &target = replicas[small(3)];
+      CrdtString record = "r" + std::to_string(small(4));
+      switch (op) {
+      case 0:
+        target.insert_or_update(record, {{"c" + std::to_string(small(3)), std::to_string(small(8))}});
+        break;
+      case 1:
+        target.delete_record(record);
+        break;
+      case 2:
+        target.merge_changes(replicas[small(3)].get_changes_since(0));
+        break;
+      default:
+        // Crafted changes only go to the first replica, so all replicas see the same ones. The rest of an input that
+        // does not decode is ignored, so the operations before it are still checked.
+        try {
+          replicas[0].merge_changes({crdt_codec::decode_change<CrdtString, CrdtString>(reader)});
+        } catch (const CrdtDecodeError &) {
+          done = true;
+        }
+        break;
+      }
+    }
+
+    for (int round = 0; round < 2; ++round) {
+      for (FuzzCrdt &to : replicas) {
+        for (const FuzzCrdt &from : replicas) {
+          to.merge_changes(from.get_changes_since(0));
+        }
+      }
+    }
+    uint64_t hash = replicas[0].state_hash();
+    for (FuzzCrdt &replica : replicas) {
+      uint64_t clock = replica.get_clock().current_time();
+      replica.merge_changes(replicas[0].get_changes_since(0));
+      if (replica.state_hash() != hash || replica.get_clock().current_time() < clock) {
+        return CrdtFuzzResult::InvariantViolated;
+      }
+    }
+    return CrdtFuzzResult::Ok;
+  });
+}
+
+} // namespace crdt_fuzz
+
+#endif // CRDT_FUZZ_HPP
@@ -0,0 +1,211 @@
+// crdt_gossip.hpp
+#ifndef CRDT_GOSSIP_HPP
+#define CRDT_GOSSIP_HPP
+
+// Gossip-based anti-entropy.
+//
+// Every round a node picks a random peer and runs a SyncSession with it, resuming from the cursor it holds for that peer.
+// Changes merged from one peer get a fresh local db_version, so they are forwarded to other peers in later rounds and
+// the cluster converges without a central server. The node also tracks how far each peer lags behind it, for alerting
+// when a replica falls behind.
+
+#include "crdt_sync.hpp"
+
+#include <chrono>
+#include <random>
+
+/// How far a peer lags behind us, see `GossipNode::lag`.
+struct CrdtPeerLag {
+  uint64_t acked_version = 0;    // our db_version the peer acknowledged
+  uint64_t versions_behind = 0;  // our current db_version minus `acked_version`
+  uint64_t received_version = 0; // the peer's version we merged up to
+  std::optional<std::chrono::steady_clock::time_point> last_exchange; // last completed session, unset since our start
+};
+
+template <typename CrdtType> class GossipNode {
+public:
+  using Clock = std::chrono::steady_clock;
+
+  GossipNode(CrdtType &crdt, CrdtNodeId node_id, Clock::duration round_interval = std::chrono::seconds(1),
+             uint64_t seed = std::random_device{}())
+      : crdt_(crdt), node_id_(node_id), round_interval_(round_interval), rng_(seed) {}
+
+  CrdtNodeId node_id() const { return node_id_; }
+
+  /// Adds a peer to the membership list. Returns false if it was already known.
+  bool add_peer(CrdtNodeId peer) {
+    if (peer == node_id_ || std::find(peers_.begin(), peers_.end(), peer) != peers_.end()) {
+      return false;
+    }
+    peers_.push_back(peer);
+    return true;
+  }
+
+  /// Removes a peer from the membership list; its cursor is kept in case it rejoins.
+  bool remove_peer(CrdtNodeId peer) {
+    auto it = std::find(peers_.begin(), peers_.end(), peer);
+    if (it == peers_.end()) {
+      return false;
+    }
+    peers_.erase(it);
+    return true;
+  }
+
+  const CrdtVector<CrdtNodeId> &peers() const { return peers_; }
+
+  /// The last version merged from each peer.
+  const CrdtMap<CrdtNodeId, uint64_t> &version_vector() const { return cursors_; }
+
+  /// The highest local db_version every current peer has acknowledged, 0 if one has not acknowledged anything yet.
+  /// Tombstones up to it can be garbage collected (see `CRDT::collect_tombstones`).
+  uint64_t acknowledged_version() const {
+    if (peers_.empty()) {
+      return 0;
+    }
+    uint64_t version = UINT64_MAX;
+    for (CrdtNodeId peer : peers_) {
+      auto it = acked_.find(peer);
+      version = std::min(version, it != acked_.end() ? it->second : 0);
+    }
+    return version;
+  }
+
+  /// Restores the cursors saved in `store` and saves them there after every completed session from now on. The store
+  /// must outlive this node.
+  ///
+  /// Stored cursors only replace lower ones held in memory.
+  void persist_cursors(CrdtCursorStore &store) {
+    for (const auto &[peer, cursor] : store.load()) {
+      uint64_t &received = cursors_[peer];
+      received = std::max(received, cursor.received);
+      uint64_t &acked = acked_[peer];
+      acked = std::max(acked, cursor.acked);
+    }
+    cursor_store_ = &store;
+  }
+
+  /// How far `peer` lags behind our current state, as of its last completed session.
+  CrdtPeerLag lag(CrdtNodeId peer) const {
+    CrdtPeerLag lag;
+    if (auto it = acked_.find(peer); it != acked_.end()) {
+      lag.acked_version = it->second;
+    }
+    uint64_t current = crdt_.get_clock().current_time();
+    lag.versions_behind = current - std::min(lag.acked_version, current);
+    lag.received_version = cursor_for(peer);
+    if (auto it = last_exchange_.find(peer); it != last_exchange_.end()) {
+      lag.last_exchange = it->second;
+    }
+    return lag;
+  }
+
+  /// The current peers to alert about: those more than `max_versions_behind` versions behind us, or whose last
+  /// completed session is older than `max_silence` (or did not happen since we started).
+  CrdtVector<CrdtNodeId> lagging_peers(uint64_t max_versions_behind, Clock::duration max_silence,
+                                       Clock::time_point now = Clock::now()) const {
+    CrdtVector<CrdtNodeId> lagging;
+    for (CrdtNodeId peer : peers_) {
+      CrdtPeerLag peer_lag = lag(peer);
+      if (peer_lag.versions_behind > max_versions_behind || !peer_lag.last_exchange ||
+          now - *peer_lag.last_exchange > max_silence) {
+        lagging.push_back(peer);
+      }
+    }
+    return lagging;
+  }
+
+  /// Picks a uniformly random peer, or std::nullopt if there are none.
+  std::optional<CrdtNodeId> pick_peer() {
+    if (peers_.empty()) {
+      return std::nullopt;
+    }
+    std::uniform_int_distribution<size_t> dist(0, peers_.size() - 1);
+    return peers_[dist(rng_)];
+  }
+
+  /// Returns the peer to gossip with if a round is due at `now`, and schedules the next round.
+  std::optional<CrdtNodeId> next_round(Clock::time_point now = Clock::now()) {
+    if (now < next_round_at_) {
+      return std::nullopt;
+    }
+    next_round_at_ = now + round_interval_;
+    return pick_peer();
+  }
+
+  /// Opens a session with `peer`, resuming from our cursor for it. Pass the session to `close_session` when done.
+  SyncSession<CrdtType> open_session(CrdtNodeId peer) { return SyncSession<CrdtType>(crdt_, node_id_, cursor_for(peer)); }
+
+  /// Records the outcome of a session, which ended at `now`; the cursor only advances if the exchange completed.
+  void close_session(const SyncSession<CrdtType> &session, Clock::time_point now = Clock::now()) {
+    if (session.is_complete() && session.peer_node_id()) {
+      cursors_[*session.peer_node_id()] = session.last_received_version();
+      note_acked(*session.peer_node_id(), session.peer_acked_version(), now);
+      ++rounds_completed_;
+      save_cursors();
+    }
+  }
+
+  /// Runs a round with `peer` over a blocking transport, as the initiating side.
+  template <SyncTransport Transport> SyncResult gossip_with(CrdtNodeId peer, Transport &transport) {
+    SyncSession<CrdtType> session = open_session(peer);
+    bool completed = run_sync_session(session, transport);
+    close_session(session);
+    return make_sync_result(session, completed);
+  }
+
+  /// Answers a round started by a peer over a blocking transport.
+  template <SyncTransport Transport> SyncResult accept(Transport &transport) {
+    SyncResult result = accept_sync_session(transport, crdt_, node_id_, cursors_);
+    if (result.completed) {
+      ++rounds_completed_;
+      if (result.peer_node_id) {
+        add_peer(*result.peer_node_id);
+        note_acked(*result.peer_node_id, result.peer_acked_version, Clock::now());
+      }
+      save_cursors();
+    }
+    return result;
+  }
+
+  size_t rounds_completed() const { return rounds_completed_; }
+
+private:
+  CrdtType &crdt_;
+  CrdtNodeId node_id_;
+  Clock::duration round_interval_;
+  Clock::time_point next_round_at_{};
+  std::mt19937_64 rng_;
+  CrdtVector<CrdtNodeId> peers_;
+  CrdtMap<CrdtNodeId, uint64_t> cursors_;
+  CrdtMap<CrdtNodeId, uint64_t> acked_; // our version each peer acknowledged
+  CrdtMap<CrdtNodeId, Clock::time_point> last_exchange_;
+  CrdtCursorStore *cursor_store_ = nullptr;
+  size_t rounds_completed_ = 0;
+
+  void note_acked(CrdtNodeId peer, uint64_t version, Clock::time_point now) {
+    uint64_t &acked = acked_[peer];
+    acked = std::max(acked, version);
+    last_exchange_[peer] = now;
+  }
+
+  void save_cursors() {
+    if (!cursor_store_) {
+      return;
+    }
+    CrdtMap<CrdtNodeId, CrdtSyncCursor> cursors;
+    for (const auto &[peer, received] : cursors_) {
+      cursors[peer].received = received;
+    }
+    for (const auto &[peer, acked] : acked_) {
+      cursors[peer].acked = acked;
+    }
+    cursor_store_->save(cursors);
+  }
+
+  uint64_t cursor_for(CrdtNodeId peer) const {
+    auto it = cursors_.find(peer);
+    return it != cursors_.end() ? it->second : 0;
+  }
+};
+
+#endif // CRDT_GOSSIP_HPP
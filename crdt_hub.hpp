@@ -0,0 +1,334 @@
+// crdt_hub.hpp
+#ifndef CRDT_HUB_HPP
+#define CRDT_HUB_HPP
+
+// A hub (relay) server for star topologies: clients push their change batches to the hub and pull everyone else's.
+//
+// A CrdtHub stores encoded change batches per topic (a document, a workspace, a tenant) in arrival order and numbers
+// them, so each client only keeps one cursor per topic: the number of the last batch it pulled. The hub never decodes
+// the batches, so it does not need the record types, can relay end-to-end encrypted batches (see crdt_crypto.hpp),
+// and does not merge anything; a client pulling from cursor 0 receives the topic's whole history. Batches are kept in
+// memory and, with a directory, appended to one file per topic and reloaded when the hub restarts.
+//
+// Clients talk to the hub with HubClient through frames on any message transport: a Push frame carries a batch and is
+// answered with Pushed, a Pull frame carries a cursor and is answered with the batches past it (except the client's
+// own), at most `max_batches` per reply.
+//
+// File format: every topic file is a sequence of `crdt_codec::record_header` records (a length and a checksum), each
+// holding the origin node id and the batch. A record cut short by a crash is dropped when the hub is opened; one
+// failing its checksum fails the open rather than being relayed.
+
+#include "crdt_codec.hpp"
+
+#include <cctype>
+#include <filesystem>
+#include <fstream>
+#include <limits>
+#include <stdexcept>
+
+enum class HubMessageType : uint8_t {
+  Push = 1,    // topic and a batch from the client
+  Pushed = 2,  // the number the hub gave the batch
+  Pull = 3,    // topic, cursor and the maximum number of batches wanted
+  Batches = 4, // the cursor after the batches, whether more are left, and the batches
+};
+
+class CrdtHub {
+public:
+  /// A hub that keeps its batches in memory only.
+  CrdtHub() = default;
+
+  /// Opens (or creates) a hub persisting its batches in `directory`.
+  ///
+  /// Throws std::runtime_error if the directory cannot be used, and CrdtDecodeError if a topic file is malformed or
+  /// corrupted.
+  explicit CrdtHub(std::filesystem::path directory) : directory_(std::move(directory)) {
+    std::filesystem::create_directories(*directory_);
+    for (const auto &entry : std::filesystem::directory_iterator(*directory_)) {
+      if (entry.path().extension() == ".hub") {
+        load(entry.path().stem().string(), entry.path());
+      }
+    }
+  }
+
+  CrdtHub(const CrdtHub &) = delete;
+  CrdtHub &operator=(const CrdtHub &) = delete;
+
+  /// Appends a batch from `origin` to `topic`. Topics are made of letters, digits, '-', '_' and '.'.
+  ///
+  /// Throws std::invalid_argument for other topic names, and std::runtime_error if the batch cannot be persisted, in
+  /// which case it is not stored.
+  ///
+  /// # Returns
+  ///
+  /// The number of the batch in the topic, starting at 1.
+  uint64_t push(const CrdtString &topic, CrdtNodeId origin, CrdtBytes batch) {
+    check_topic(topic);
+    Topic &stored = topics_[topic];
+    if (directory_) {
+      std::filesystem::path path = *directory_ / (topic + ".hub");
+      if (!stored.file.is_open()) {
+        stored.file.open(path, std::ios::binary | std::ios::app);
+        if (!stored.file) {
+          throw std::runtime_error("failed to open hub topic file");
+        }
+      }
+      uintmax_t valid = std::filesystem::file_size(path);
+      try {
+        write_record(stored.file, origin, batch);
+        stored.file.flush();
+        if (!stored.file) {
+          throw std::runtime_error("failed to append to hub topic file");
+        }
+      } catch (...) {
+        // Drop the partial record, so later pushes do not follow a torn one
+        stored.file.close();
+        std::filesystem::resize_file(path, valid);
+        throw;
+      }
+    }
+    stored.batches.push_back({origin, std::move(batch)});
+    return stored.batches.size();
+  }
+
+  struct PullResult {
+    CrdtVector<const CrdtBytes *> batches; // valid until the next push
+    uint64_t cursor = 0;                   // the cursor to pull from next
+    bool more = false;                     // batches are left past `cursor`
+  };
+
+  /// The batches of `topic` past `cursor` that `client` did not push itself, at most `max_batches` of them.
+  PullResult pull(const CrdtString &topic, uint64_t cursor, CrdtNodeId client,
+                  size_t max_batches = std::numeric_limits<size_t>::max()) const {
+    PullResult result;
+    result.cursor = cursor;
+    auto it = topics_.find(topic);
+    if (it == topics_.end()) {
+      return result;
+    }
+    const CrdtVector<Batch> &batches = it->second.batches;
+    for (uint64_t i = cursor; i < batches.size(); ++i) {
+      if (result.batches.size() == max_batches) {
+        result.more = true;
+        break;
+      }
+      if (batches[i].origin != client) {
+        result.batches.push_back(&batches[i].bytes);
+      }
+      result.cursor = i + 1;
+    }
+    return result;
+  }
+
+  /// Handles a frame from a client and returns the reply.
+  ///
+  /// Throws CrdtDecodeError on malformed frames and std::invalid_argument on invalid topic names.
+  CrdtBytes on_frame(const CrdtBytes &frame, size_t max_batches = 1000) {
+    ByteReader reader(frame);
+    auto type = static_cast<HubMessageType>(reader.read_u8());
+    CrdtString topic = CrdtCodec<CrdtString>::decode(reader);
+    CrdtNodeId client = CrdtCodec<CrdtNodeId>::decode(reader);
+    CrdtBytes reply;
+    ByteWriter writer(reply);
+    if (type == HubMessageType::Push) {
+      CrdtBytes batch = CrdtCodec<CrdtBytes>::decode(reader);
+      uint64_t local_version = reader.read_varint();
+      require_end(reader);
+      writer.write_u8(static_cast<uint8_t>(HubMessageType::Pushed));
+      writer.write_varint(push(topic, client, std::move(batch)));
+      writer.write_varint(local_version);
+    } else if (type == HubMessageType::Pull) {
+      uint64_t cursor = reader.read_varint();
+      require_end(reader);
+      PullResult result = pull(topic, cursor, client, max_batches);
+      writer.write_u8(static_cast<uint8_t>(HubMessageType::Batches));
+      writer.write_varint(result.cursor);
+      writer.write_u8(result.more ? 1 : 0);
+      writer.write_varint(result.batches.size());
+      for (const CrdtBytes *batch : result.batches) {
+        CrdtCodec<CrdtBytes>::encode(writer, *batch);
+      }
+    } else {
+      throw CrdtDecodeError("unexpected hub message type");
+    }
+    return reply;
+  }
+
+  CrdtVector<CrdtString> topics() const {
+    CrdtVector<CrdtString> names;
+    for (const auto &[name, topic] : topics_) {
+      names.push_back(name);
+    }
+    return names;
+  }
+
+  /// Number of batches stored for `topic`, which is also its latest batch number.
+  uint64_t batch_count(const CrdtString &topic) const {
+    auto it = topics_.find(topic);
+    return it != topics_.end() ? it->second.batches.size() : 0;
+  }
+
+private:
+  struct Batch {
+    CrdtNodeId origin;
+    CrdtBytes bytes;
+  };
+  struct Topic {
+    CrdtVector<Batch> batches;
+    std::ofstream file;
+  };
+
+  static constexpr uint32_t MAX_RECORD_SIZE = 256 * 1024 * 1024;
+
+  std::optional<std::filesystem::path> directory_;
+  CrdtMap<CrdtString, Topic> topics_;
+
+  static void check_topic(const CrdtString &topic) {
+    bool valid = !topic.empty() && topic != "." && topic != "..";
+    for (char c : topic) {
+      valid = valid && (std::isalnum(static_cast<unsigned char>(c)) || c == '-' || c == '_' || c == '.');
+    }
+    if (!valid) {
+      throw std::invalid_argument("invalid hub topic name");
+    }
+  }
+
+  static void require_end(const ByteReader &reader) {
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after hub message");
+    }
+  }
+
+  static void write_record(std::ofstream &out, CrdtNodeId origin, const CrdtBytes &batch) {
+    CrdtBytes payload;
+    ByteWriter writer(payload);
+    CrdtCodec<CrdtNodeId>::encode(writer, origin);
+    CrdtCodec<CrdtBytes>::encode(writer, batch);
+    CrdtBytes header = crdt_codec::record_header(payload);
+    out.write(reinterpret_cast<const char *>(header.data()), static_cast<std::streamsize>(header.size()));
+    if (crdt_fault(CrdtFaultPoint::StorageWrite) != 0) {
+      out.write(reinterpret_cast<const char *>(payload.data()), static_cast<std::streamsize>(payload.size() / 2));
+      out.flush();
+      throw CrdtInjectedFault("injected storage write failure");
+    }
+    out.write(reinterpret_cast<const char *>(payload.data()), static_cast<std::streamsize>(payload.size()));
+  }
+
+  // Loads a topic file, dropping a final record cut short
+  void load(const CrdtString &topic, const std::filesystem::path &path) {
+    std::ifstream in(path, std::ios::binary);
+    if (!in) {
+      throw std::runtime_error("failed to open hub topic file");
+    }
+    Topic &stored = topics_[topic];
+    uint64_t valid = 0;
+    while (std::optional<CrdtBytes> payload = crdt_codec::read_record(in, MAX_RECORD_SIZE)) {
+      ByteReader reader(*payload);
+      CrdtNodeId origin = CrdtCodec<CrdtNodeId>::decode(reader);
+      CrdtBytes batch = CrdtCodec<CrdtBytes>::decode(reader);
+      if (!reader.at_end()) {
+        throw CrdtDecodeError("trailing bytes after hub record");
+      }
+      stored.batches.push_back({origin, std::move(batch)});
+      valid += crdt_codec::RECORD_HEADER_SIZE + payload->size();
+    }
+    in.close();
+    if (std::filesystem::file_size(path) != valid) {
+      std::filesystem::resize_file(path, valid);
+    }
+  }
+};
+
+/// The client side of a hub topic: pushes the local changes of a CRDT and merges the batches of the other clients.
+template <typename CrdtType> class HubClient {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+
+  /// # Arguments
+  ///
+  /// * `pushed_version` - The local db_version pushed so far, as persisted from `pushed_version()`.
+  /// * `cursor` - The hub cursor pulled so far, as persisted from `cursor()`.
+  HubClient(CrdtType &crdt, CrdtNodeId node_id, CrdtString topic, uint64_t pushed_version = 0, uint64_t cursor = 0)
+      : crdt_(crdt), node_id_(node_id), topic_(std::move(topic)), pushed_version_(pushed_version), cursor_(cursor) {}
+
+  /// Returns a Push frame with the local changes made since the last push, or std::nullopt if there are none.
+  /// Changes merged from elsewhere are not pushed: the hub already has them from their author.
+  std::optional<CrdtBytes> push_frame() const {
+    uint64_t version = crdt_.get_clock().current_time();
+    auto changes = crdt_.get_change_refs_since(pushed_version_);
+    std::erase_if(changes, [&](const ChangeRef<K, V> &change) { return change.node_id != node_id_; });
+    if (changes.empty()) {
+      return std::nullopt;
+    }
+    CrdtBytes frame = header(HubMessageType::Push);
+    ByteWriter writer(frame);
+    CrdtCodec<CrdtBytes>::encode(writer, crdt_codec::encode_changes(changes));
+    writer.write_varint(version);
+    return frame;
+  }
+
+  /// Returns a Pull frame for the batches past our cursor.
+  CrdtBytes pull_frame() const {
+    CrdtBytes frame = header(HubMessageType::Pull);
+    ByteWriter(frame).write_varint(cursor_);
+    return frame;
+  }
+
+  /// Handles a reply from the hub, merging pulled batches.
+  ///
+  /// Throws CrdtDecodeError on malformed frames.
+  ///
+  /// # Returns
+  ///
+  /// True if the hub has more batches past the new cursor, so another pull should follow.
+  bool on_frame(const CrdtBytes &frame) {
+    ByteReader reader(frame);
+    auto type = static_cast<HubMessageType>(reader.read_u8());
+    if (type == HubMessageType::Pushed) {
+      reader.read_varint(); // the batch number, which our next pull skips as our own
+      pushed_version_ = std::max(pushed_version_, reader.read_varint());
+      return false;
+    }
+    if (type != HubMessageType::Batches) {
+      throw CrdtDecodeError("unexpected hub message type");
+    }
+    uint64_t cursor = reader.read_varint();
+    bool more = reader.read_u8() != 0;
+    uint64_t count = reader.read_varint();
+    if (count > reader.remaining()) {
+      throw CrdtDecodeError("batch count exceeds input");
+    }
+    for (uint64_t i = 0; i < count; ++i) {
+      crdt_.merge_changes(crdt_codec::decode_changes<K, V>(CrdtCodec<CrdtBytes>::decode(reader)));
+    }
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after batches");
+    }
+    cursor_ = std::max(cursor_, cursor);
+    return more;
+  }
+
+  /// The local db_version pushed and confirmed by the hub; persist it with `cursor()`.
+  uint64_t pushed_version() const { return pushed_version_; }
+
+  uint64_t cursor() const { return cursor_; }
+
+private:
+  CrdtType &crdt_;
+  CrdtNodeId node_id_;
+  CrdtString topic_;
+  uint64_t pushed_version_;
+  uint64_t cursor_;
+
+  CrdtBytes header(HubMessageType type) const {
+    CrdtBytes frame;
+    ByteWriter writer(frame);
+    writer.write_u8(static_cast<uint8_t>(type));
+    CrdtCodec<CrdtString>::encode(writer, topic_);
+    CrdtCodec<CrdtNodeId>::encode(writer, node_id_);
+    return frame;
+  }
+};
+
+#endif // CRDT_HUB_HPP
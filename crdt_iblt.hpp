@@ -0,0 +1,181 @@
+// crdt_iblt.hpp
+#ifndef CRDT_IBLT_HPP
+#define CRDT_IBLT_HPP
+
+// Invertible Bloom lookup table over record ids and versions, for set reconciliation.
+//
+// Every record (and tombstone) is an element: a hash of its id and record digest. Each element is added to one cell in
+// each of HASH_COUNT sub-tables. A cell holds the number of elements it received, the XOR of their hashes and the XOR
+// of a checksum of each. Subtracting a peer's table from ours cancels the elements both replicas hold, and the
+// remaining ones, those of records that differ, are recovered by repeatedly taking out cells holding a single element.
+//
+// Unlike a Merkle tree, this takes one round trip with a sketch whose size depends on the number of differences, not
+// the number of records:
+//
+// 1. Both replicas `build` a table with the same number of cells and exchange `encode()`d tables.
+// 2. Each calls `ours.differing_records(Iblt::decode(theirs))` and ships `crdt.get_changes_for(ids)` for the result,
+//    i.e. its records the peer lacks or holds at another version.
+// 3. If decoding fails (std::nullopt), the replicas differ in more records than the table can tell apart: retry with
+//    more cells or fall back to a MerkleTree.
+
+#include "crdt_codec.hpp"
+
+#include <numeric>
+
+template <typename K> class Iblt {
+public:
+  static constexpr size_t HASH_COUNT = 3;
+
+  struct Cell {
+    int64_t count = 0;
+    uint64_t key_sum = 0;
+    uint64_t check_sum = 0;
+
+    bool empty() const { return count == 0 && key_sum == 0 && check_sum == 0; }
+  };
+
+  /// Builds a table over every record of `crdt`, including tombstones.
+  ///
+  /// # Arguments
+  ///
+  /// * `cells` - Size of the table, rounded up to a multiple of HASH_COUNT. Both replicas must use the same size. A
+  ///   record both replicas hold at different versions counts as two differences, and about 1.5 cells per difference
+  ///   decode reliably, so the default suits up to about 40 differing records.
+  ///
+  /// Complexity: O(n + c), where n is the number of records and c the number of cells
+  template <typename CrdtType> static Iblt build(const CrdtType &crdt, size_t cells = 120) {
+    Iblt table(cells);
+    crdt.for_each_record([&](const K &record_id, const auto &record) {
+      table.insert(record_id, CrdtType::record_digest(record));
+    });
+    crdt.for_each_tombstone([&](const K &record_id, const auto &tombstone) {
+      table.insert(record_id, CrdtType::tombstone_digest(tombstone));
+    });
+    return table;
+  }
+
+  size_t size() const { return cells_.size(); }
+
+  const CrdtVector<Cell> &cells() const { return cells_; }
+
+  /// Serializes the cells for sending to a peer. Record ids are not included.
+  CrdtBytes encode() const {
+    CrdtBytes out;
+    ByteWriter writer(out);
+    writer.write_varint(cells_.size());
+    for (const Cell &cell : cells_) {
+      writer.write_u64_be(static_cast<uint64_t>(cell.count));
+      writer.write_u64_be(cell.key_sum);
+      writer.write_u64_be(cell.check_sum);
+    }
+    return out;
+  }
+
+  /// Reads a table written by `encode`. Throws CrdtDecodeError on malformed input.
+  static Iblt decode(const CrdtBytes &bytes) {
+    ByteReader reader(bytes);
+    uint64_t size = reader.read_varint();
+    if (size == 0 || size % HASH_COUNT != 0 || size > reader.remaining() / 24) {
+      throw CrdtDecodeError("invalid IBLT size");
+    }
+    Iblt table(static_cast<size_t>(size));
+    for (Cell &cell : table.cells_) {
+      cell.count = static_cast<int64_t>(reader.read_u64_be());
+      cell.key_sum = reader.read_u64_be();
+      cell.check_sum = reader.read_u64_be();
+    }
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after IBLT");
+    }
+    return table;
+  }
+
+  /// Record ids this replica holds that the peer lacks or holds at another version, i.e. the records to ship to it.
+  /// This table must have been built locally; `theirs` is typically decoded.
+  ///
+  /// # Returns
+  ///
+  /// The ids in no particular order, or std::nullopt if there are too many differences for the table size.
+  ///
+  /// Complexity: O(c + d), where c is the number of cells and d the number of differences
+  std::optional<CrdtVector<K>> differing_records(const Iblt &theirs) const {
+    if (theirs.size() != size()) {
+      throw std::invalid_argument("IBLTs must have the same size");
+    }
+    CrdtVector<Cell> cells = cells_;
+    for (size_t i = 0; i < cells.size(); ++i) {
+      cells[i].count -= theirs.cells_[i].count;
+      cells[i].key_sum ^= theirs.cells_[i].key_sum;
+      cells[i].check_sum ^= theirs.cells_[i].check_sum;
+    }
+
+    CrdtVector<K> result;
+    CrdtVector<size_t> pending(cells.size());
+    std::iota(pending.begin(), pending.end(), size_t{0});
+    while (!pending.empty()) {
+      size_t index = pending.back();
+      pending.pop_back();
+      const Cell &cell = cells[index];
+      if ((cell.count != 1 && cell.count != -1) || cell.check_sum != check_hash(cell.key_sum)) {
+        continue;
+      }
+      // Elements only we hold (count 1) are ours to ship; the peer ships those only it holds
+      int64_t count = cell.count;
+      uint64_t element = cell.key_sum;
+      if (count == 1) {
+        auto id = ids_.find(element);
+        if (id == ids_.end()) {
+          return std::nullopt;
+        }
+        result.push_back(id->second);
+      }
+      for (size_t i = 0; i < HASH_COUNT; ++i) {
+        size_t other = cell_index(element, i);
+        cells[other].count -= count;
+        cells[other].key_sum ^= element;
+        cells[other].check_sum ^= check_hash(element);
+        pending.push_back(other);
+      }
+    }
+    for (const Cell &cell : cells) {
+      if (!cell.empty()) {
+        return std::nullopt;
+      }
+    }
+    return result;
+  }
+
+private:
+  CrdtVector<Cell> cells_;
+  CrdtMap<uint64_t, K> ids_; // element hash to record id, for tables built locally
+
+  explicit Iblt(size_t cells) {
+    if (cells == 0) {
+      throw std::invalid_argument("IBLT must have cells");
+    }
+    cells_.resize((cells + HASH_COUNT - 1) / HASH_COUNT * HASH_COUNT);
+  }
+
+  void insert(const K &record_id, uint64_t digest) {
+    CrdtHasher hasher;
+    CrdtDigest<K>::add(hasher, record_id);
+    uint64_t element = CrdtHasher::mix(CrdtHasher::mix(hasher.finish()) ^ digest);
+    ids_.emplace(element, record_id);
+    for (size_t i = 0; i < HASH_COUNT; ++i) {
+      Cell &cell = cells_[cell_index(element, i)];
+      ++cell.count;
+      cell.key_sum ^= element;
+      cell.check_sum ^= check_hash(element);
+    }
+  }
+
+  // Each hash function picks a cell in its own sub-table, so an element never lands twice in the same cell
+  size_t cell_index(uint64_t element, size_t hash) const {
+    size_t width = cells_.size() / HASH_COUNT;
+    return hash * width + static_cast<size_t>(CrdtHasher::mix(element + (hash + 1) * 0x9e3779b97f4a7c15ULL) % width);
+  }
+
+  static uint64_t check_hash(uint64_t element) { return CrdtHasher::mix(element ^ 0x5bd1e9955bd1e995ULL); }
+};
+
+#endif // CRDT_IBLT_HPP
@@ -0,0 +1,693 @@
+// crdt_io.hpp
+#ifndef CRDT_IO_HPP
+#define CRDT_IO_HPP
+
+// JSON Lines and CSV import and export, for migrations from legacy systems and quick data dumps.
+//
+// An import goes through `CRDT::bulk_load`, so it is one local operation like any other: every record is written with
+// the same db_version, schema checks and write policies apply, tombstoned records are skipped, and observers and peers
+// receive the import as one batch. Input is parsed as it is loaded, so it does not have to fit in memory; a malformed
+// line throws CrdtImportError, keeping the records before it.
+//
+// JSON Lines hold one flat object per line: the record id under `id_field` and a member per field. Values are strings,
+// numbers or booleans; null members are skipped, and nested objects and arrays are rejected. CSV files (RFC 4180) have
+// a header row; a CrdtCsvMapping names the id column and maps columns to fields, and empty cells are skipped. Keys and
+// values convert through `CrdtScalar<T>`, which is provided for CrdtString, integers, floating point numbers, bool,
+// and variants of these; specialize it for other types.
+
+#include "crdt.hpp"
+
+#include <charconv>
+#include <cstdio>
+#include <functional>
+#include <istream>
+#include <ostream>
+#include <stdexcept>
+
+/// Thrown when an import meets malformed input, with the line it is on (1-based).
+class CrdtImportError : public std::runtime_error {
+public:
+  CrdtImportError(size_t line, const CrdtString &message)
+      : std::runtime_error("line " + std::to_string(line) + ": " + message), line_(line) {}
+
+  size_t line() const { return line_; }
+
+private:
+  size_t line_;
+};
+
+/// A JSON scalar, or a CSV cell (always a string).
+using CrdtScalarValue = std::variant<CrdtString, int64_t, double, bool>;
+
+/// Converts keys and values of type T to and from scalars. `from_scalar` returns std::nullopt for a scalar that does
+/// not convert, which fails the import.
+template <typename T, typename Enable = void> struct CrdtScalar;
+
+template <> struct CrdtScalar<CrdtString> {
+  static CrdtScalarValue to_scalar(const CrdtString &value) { return value; }
+  static std::optional<CrdtString> from_scalar(const CrdtScalarValue &scalar) {
+    return std::visit(
+        [](const auto &value) -> CrdtString {
+          using T = std::decay_t<decltype(value)>;
+          if constexpr (std::is_same_v<T, CrdtString>) {
+            return value;
+          } else if constexpr (std::is_same_v<T, bool>) {
+            return value ? "true" : "false";
+          } else {
+            char buffer[32];
+            return CrdtString(buffer, std::to_chars(buffer, buffer + sizeof(buffer), value).ptr);
+          }
+        },
+        scalar);
+  }
+};
+
+template <> struct CrdtScalar<bool> {
+  static CrdtScalarValue to_scalar(bool value) { return value; }
+  static std::optional<bool> from_scalar(const CrdtScalarValue &scalar) {
+    if (const bool *value = std::get_if<bool>(&scalar)) {
+      return *value;
+    }
+    if (const CrdtString *text = std::get_if<CrdtString>(&scalar)) {
+      if (*text == "true") {
+        return true;
+      }
+      return *text == "false" ? std::optional<bool>(false) : std::nullopt;
+    }
+    return std::nullopt;
+  }
+};
+
+template <typename T> struct CrdtScalar<T, std::enable_if_t<std::is_arithmetic_v<T> && !std::is_same_v<T, bool>>> {
+  static CrdtScalarValue to_scalar(T value) {
+    if constexpr (std::is_integral_v<T>) {
+      return static_cast<int64_t>(value);
+    } else {
+      return static_cast<double>(value);
+    }
+  }
+  static std::optional<T> from_scalar(const CrdtScalarValue &scalar) {
+    if (const int64_t *value = std::get_if<int64_t>(&scalar)) {
+      return static_cast<T>(*value);
+    }
+    if (const double *value = std::get_if<double>(&scalar)) {
+      if constexpr (std::is_integral_v<T>) {
+        return std::nullopt;
+      } else {
+        return static_cast<T>(*value);
+      }
+    }
+    if (const CrdtString *text = std::get_if<CrdtString>(&scalar)) {
+      T parsed{};
+      auto [end, error] = std::from_chars(text->data(), text->data() + text->size(), parsed);
+      return error == std::errc() && end == text->data() + text->size() ? std::optional<T>(parsed) : std::nullopt;
+    }
+    return std::nullopt;
+  }
+};
+
+/// A variant takes a scalar as the alternative of the same type if it has one (so CSV cells stay strings when a string
+/// alternative exists), and as the first alternative it converts to otherwise.
+template <typename... Ts> struct CrdtScalar<std::variant<Ts...>> {
+  using Variant = std::variant<Ts...>;
+  static CrdtScalarValue to_scalar(const Variant &value) {
+    return std::visit(
+        [](const auto &alternative) { return CrdtScalar<std::decay_t<decltype(alternative)>>::to_scalar(alternative); },
+        value);
+  }
+  static std::optional<Variant> from_scalar(const CrdtScalarValue &scalar) {
+    std::optional<Variant> result = std::visit(
+        [](const auto &value) -> std::optional<Variant> {
+          using T = std::decay_t<decltype(value)>;
+          if constexpr ((std::is_same_v<T, Ts> || ...)) {
+            return Variant(value);
+          } else {
+            return std::nullopt;
+          }
+        },
+        scalar);
+    ((result = result ? result : convert<Ts>(scalar)), ...);
+    return result;
+  }
+
+private:
+  template <typename T> static std::optional<Variant> convert(const CrdtScalarValue &scalar) {
+    std::optional<T> value = CrdtScalar<T>::from_scalar(scalar);
+    return value ? std::optional<Variant>(std::move(*value)) : std::nullopt;
+  }
+};
+
+/// A CSV column and the record field it holds.
+struct CrdtCsvColumn {
+  CrdtString header;
+  CrdtString field;
+};
+
+struct CrdtCsvMapping {
+  CrdtString id_header = "id";          // the column holding the record id
+  CrdtVector<CrdtCsvColumn> columns;    // the other columns; if empty, every other column is a field of the same name
+};
+
+namespace crdt_io {
+
+// An input range pulling elements from `next` until it returns std::nullopt, for feeding parsed input to bulk_load
+template <typename T> class PullRange {
+public:
+  explicit PullRange(std::function<std::optional<T>()> next) : next_(std::move(next)) {}
+
+  class iterator {
+  public:
+    using value_type = T;
+    using difference_type = std::ptrdiff_t;
+
+    iterator() = default;
+    explicit iterator(PullRange *range) : range_(range) {}
+
+    T &&operator*() const { return std::move(*range_->current_); }
+    iterator &operator++() {
+      range_->current_ = range_->next_();
+      return *this;
+    }
+    void operator++(int) { ++*this; }
+    bool operator==(std::default_sentinel_t) const { return !range_->current_; }
+
+  private:
+    PullRange *range_ = nullptr;
+  };
+
+  iterator begin() {
+    current_ = next_();
+    return iterator(this);
+  }
+  std::default_sentinel_t end() const { return {}; }
+
+private:
+  std::function<std::optional<T>()> next_;
+  std::optional<T> current_;
+};
+
+inline void write_json_string(std::ostream &out, const CrdtString &text) {
+  out << '"';
+  for (char c : text) {
+    switch (c) {
+    case '"':
+      out << "\\\"";
+      break;
+    case '\\':
+      out << "\\\\";
+      break;
+    case '\n':
+      out << "\\n";
+      break;
+    case '\r':
+      out << "\\r";
+      break;
+    case '\t':
+      out << "\\t";
+      break;
+    default:
+      if (static_cast<unsigned char>(c) < 0x20) {
+        char escaped[8];
+        std::snprintf(escaped, sizeof(escaped), "\\u%04x", static_cast<unsigned>(c));
+        out << escaped;
+      } else {
+        out << c;
+      }
+    }
+  }
+  out << '"';
+}
+
+inline void write_json_scalar(std::ostream &out, const CrdtScalarValue &scalar) {
+  std::visit(
+      [&](const auto &value) {
+        using T = std::decay_t<decltype(value)>;
+        if constexpr (std::is_same_v<T, CrdtString>) {
+          write_json_string(out, value);
+        } else if constexpr (std::is_same_v<T, bool>) {
+          out << (value ? "true" : "false");
+        } else {
+          char buffer[32];
+          out.write(buffer, std::to_chars(buffer, buffer + sizeof(buffer), value).ptr - buffer);
+        }
+      },
+      scalar);
+}
+
+// Parses one line of JSON Lines: a flat object, whose members are returned in order, null ones as std::nullopt
+class JsonLineParser {
+public:
+  JsonLineParser(const CrdtString &text, size_t line) : text_(text), line_(line) {}
+
+  CrdtVector<std::pair<CrdtString, std::optional<CrdtScalarValue>>> parse_object() {
+    CrdtVector<std::pair<CrdtString, std::optional<CrdtScalarValue>>> members;
+    expect('{');
+    if (!consume('}')) {
+      do {
+        CrdtString name = parse_string();
+        expect(':');
+        members.emplace_back(std::move(name), parse_scalar());
+      } while (consume(','));
+      expect('}');
+    }
+    skip_whitespace();
+    if (pos_ != text_.size()) {
+      fail("unexpected text after the object");
+    }
+    return members;
+  }
+
+private:
+  const CrdtString &text_;
+  size_t line_;
+  size_t pos_ = 0;
+
+  [[noreturn]] void fail(const CrdtString &message) const { throw CrdtImportError(line_, message); }
+
+  void skip_whitespace() {
+    while (pos_ < text_.size() && (text_[pos_] == ' ' || text_[pos_] == '\t' || text_[pos_] == '\r')) {
+      ++pos_;
+    }
+  }
+
+  bool consume(char c) {
+    skip_whitespace();
+    if (pos_ < text_.size() && text_[pos_] == c) {
+      ++pos_;
+      return true;
+    }
+    return false;
+  }
+
+  void expect(char c) {
+    if (!consume(c)) {
+      fail(CrdtString("expected '") + c + "'");
+    }
+  }
+
+  bool consume_word(const char *word) {
+    size_t size = std::strlen(word);
+    if (text_.compare(pos_, size, word) == 0) {
+      pos_ += size;
+      return true;
+    }
+    return false;
+  }
+
+  std::optional<CrdtScalarValue> parse_scalar() {
+    skip_whitespace();
+    if (pos_ == text_.size()) {
+      fail("expected a value");
+    }
+    char c = text_[pos_];
+    if (c == '"') {
+      return parse_string();
+    }
+    if (consume_word("true")) {
+      return true;
+    }
+    if (consume_word("false")) {
+      return false;
+    }
+    if (consume_word("null")) {
+      return std::nullopt;
+    }
+    if (c == '{' || c == '[') {
+      fail("nested objects and arrays are not supported");
+    }
+    return parse_number();
+  }
+
+  CrdtScalarValue parse_number() {
+    size_t start = pos_;
+    bool integral = true;
+    while (pos_ < text_.size() && std::strchr("+-0123456789.eE", text_[pos_]) != nullptr && text_[pos_] != '\0') {
+      integral = integral && std::strchr(".eE", text_[pos_]) == nullptr;
+      ++pos_;
+    }
+    const char *begin = text_.data() + start;
+    const char *end = text_.data() + pos_;
+    if (integral) {
+      int64_t value = 0;
+      auto result = std::from_chars(begin, end, value);
+      if (result.ec == std::errc() && result.ptr == end) {
+        return value;
+      }
+    }
+    double value = 0;
+    auto result = std::from_chars(begin, end, value);
+    if (begin == end || result.ec != std::errc() || result.ptr != end) {
+      fail("invalid value");
+    }
+    return value;
+  }
+
+  CrdtString parse_string() {
+    expect('"');
+    CrdtString result;
+    while (true) {
+      if (pos_ == text_.size()) {
+        fail("unterminated string");
+      }
+      char c = text_[pos_++];
+      if (c == '"') {
+        return result;
+      }
+      if (c != '\\') {
+        result += c;
+        continue;
+      }
+      if (pos_ == text_.size()) {
+        fail("unterminated string");
+      }
+      switch (char escape = text_[pos_++]) {
+      case '"':
+      case '\\':
+      case '/':
+        result += escape;
+        break;
+      case 'b':
+        result += '\b';
+        break;
+      case 'f':
+        result += '\f';
+        break;
+      case 'n':
+        result += '\n';
+        break;
+      case 'r':
+        result += '\r';
+        break;
+      case 't':
+        result += '\t';
+        break;
+      case 'u':
+        append_utf8(result, parse_code_point());
+        break;
+      default:
+        fail("invalid escape in string");
+      }
+    }
+  }
+
+  uint32_t parse_hex4() {
+    uint32_t value = 0;
+    if (pos_ + 4 > text_.size() || std::from_chars(text_.data() + pos_, text_.data() + pos_ + 4, value, 16).ptr !=
+                                       text_.data() + pos_ + 4) {
+      fail("invalid \\u escape");
+    }
+    pos_ += 4;
+    return value;
+  }
+
+  // A \u escape, combining surrogate pairs
+  uint32_t parse_code_point() {
+    uint32_t high = parse_hex4();
+    if (high < 0xd800 || high > 0xdbff) {
+      return high;
+    }
+    if (!consume_word("\\u")) {
+      fail("unpaired surrogate in string");
+    }
+    uint32_t low = parse_hex4();
+    if (low < 0xdc00 || low > 0xdfff) {
+      fail("unpaired surrogate in string");
+    }
+    return 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+  }
+
+  static void append_utf8(CrdtString &out, uint32_t code_point) {
+    if (code_point < 0x80) {
+      out += static_cast<char>(code_point);
+    } else if (code_point < 0x800) {
+      out += static_cast<char>(0xc0 | (code_point >> 6));
+      out += static_cast<char>(0x80 | (code_point & 0x3f));
+    } else if (code_point < 0x10000) {
+      out += static_cast<char>(0xe0 | (code_point >> 12));
+      out += static_cast<char>(0x80 | ((code_point >> 6) & 0x3f));
+      out += static_cast<char>(0x80 | (code_point & 0x3f));
+    } else {
+      out += static_cast<char>(0xf0 | (code_point >> 18));
+      out += static_cast<char>(0x80 | ((code_point >> 12) & 0x3f));
+      out += static_cast<char>(0x80 | ((code_point >> 6) & 0x3f));
+      out += static_cast<char>(0x80 | (code_point & 0x3f));
+    }
+  }
+};
+
+template <typename T> T from_scalar(const CrdtScalarValue &scalar, size_t line, const CrdtString &what) {
+  std::optional<T> value = CrdtScalar<T>::from_scalar(scalar);
+  if (!value) {
+    throw CrdtImportError(line, "cannot convert " + what);
+  }
+  return std::move(*value);
+}
+
+/// Writes every live record of `crdt` as a line of JSON: an object with the record id under `id_field` followed by its
+/// fields. A field named like `id_field` is left out.
+///
+/// # Returns
+///
+/// The number of records written.
+///
+/// Complexity: O(n * m), where n is the number of records and m is the average number of fields per record
+template <typename CrdtType>
+size_t export_jsonl(const CrdtType &crdt, std::ostream &out, const CrdtString &id_field = "id") {
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+  size_t records = 0;
+  crdt.iter_live([&](const K &record_id, const Record<V> &record) {
+    out << '{';
+    write_json_string(out, id_field);
+    out << ':';
+    write_json_scalar(out, CrdtScalar<K>::to_scalar(record_id));
+    for (const auto &[col_name, value] : record.fields) {
+      if (col_name != id_field) {
+        out << ',';
+        write_json_string(out, col_name);
+        out << ':';
+        write_json_scalar(out, CrdtScalar<V>::to_scalar(value));
+      }
+    }
+    out << "}\n";
+    ++records;
+  });
+  return records;
+}
+
+/// Imports the JSON Lines read from `in` in one `bulk_load`. Blank lines are skipped.
+///
+/// Throws CrdtImportError for a malformed line, a line without `id_field` or a value that does not convert, and
+/// whatever `bulk_load` throws; the records before it stay written.
+///
+/// # Returns
+///
+/// The number of records written.
+///
+/// Complexity: O(b + r * m), where b is the size of the input, r is the number of records and m is the average number
+/// of fields per record
+template <typename CrdtType> size_t import_jsonl(CrdtType &crdt, std::istream &in, const CrdtString &id_field = "id") {
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+  using Entry = std::pair<K, CrdtMap<CrdtString, V>>;
+  size_t line_number = 0;
+  CrdtString line;
+  PullRange<Entry> records([&]() -> std::optional<Entry> {
+    while (std::getline(in, line)) {
+      ++line_number;
+      if (line.find_first_not_of(" \t\r") == CrdtString::npos) {
+        continue;
+      }
+      std::optional<K> record_id;
+      CrdtMap<CrdtString, V> fields;
+      for (auto &[name, scalar] : JsonLineParser(line, line_number).parse_object()) {
+        if (name == id_field) {
+          if (!scalar) {
+            throw CrdtImportError(line_number, "the record id is null");
+          }
+          record_id = from_scalar<K>(*scalar, line_number, "the record id");
+        } else if (scalar) {
+          fields.insert_or_assign(name, from_scalar<V>(*scalar, line_number, "field " + name));
+        }
+      }
+      if (!record_id) {
+        throw CrdtImportError(line_number, "missing \"" + id_field + "\"");
+      }
+      return Entry(std::move(*record_id), std::move(fields));
+    }
+    return std::nullopt;
+  });
+  return crdt.bulk_load(records);
+}
+
+inline void write_csv_cell(std::ostream &out, const CrdtString &cell) {
+  if (cell.find_first_of(",\"\r\n") == CrdtString::npos) {
+    out << cell;
+    return;
+  }
+  out << '"';
+  for (char c : cell) {
+    out << c;
+    if (c == '"') {
+      out << '"';
+    }
+  }
+  out << '"';
+}
+
+// Reads one CSV row, which may span lines inside quotes, or returns std::nullopt at the end of the input
+inline std::optional<CrdtVector<CrdtString>> read_csv_row(std::istream &in, size_t &line_number) {
+  CrdtString line;
+  if (!std::getline(in, line)) {
+    return std::nullopt;
+  }
+  ++line_number;
+  size_t first_line = line_number;
+  CrdtVector<CrdtString> cells(1);
+  bool quoted = false;
+  for (size_t i = 0;; ++i) {
+    if (i == line.size()) {
+      if (!quoted) {
+        break;
+      }
+      if (!std::getline(in, line)) {
+        throw CrdtImportError(first_line, "unterminated quoted cell");
+      }
+      ++line_number;
+      cells.back() += '\n';
+      i = static_cast<size_t>(-1);
+      continue;
+    }
+    char c = line[i];
+    if (quoted) {
+      if (c != '"') {
+        cells.back() += c;
+      } else if (i + 1 < line.size() && line[i + 1] == '"') {
+        cells.back() += '"';
+        ++i;
+      } else {
+        quoted = false;
+      }
+    } else if (c == '"') {
+      quoted = true;
+    } else if (c == ',') {
+      cells.emplace_back();
+    } else if (c != '\r' || i + 1 != line.size()) {
+      cells.back() += c;
+    }
+  }
+  return cells;
+}
+
+/// Writes every live record of `crdt` as a CSV row, after a header row. Fields are written as their `CrdtScalar`
+/// strings, and missing ones as empty cells. Without mapped columns, every field any record has becomes a column,
+/// sorted by name.
+///
+/// # Returns
+///
+/// The number of records written.
+///
+/// Complexity: O(n * c), where n is the number of records and c is the number of columns
+template <typename CrdtType> size_t export_csv(const CrdtType &crdt, std::ostream &out, CrdtCsvMapping mapping = {}) {
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+  auto text = [](const CrdtScalarValue &scalar) { return *CrdtScalar<CrdtString>::from_scalar(scalar); };
+  if (mapping.columns.empty()) {
+    std::set<CrdtString> fields;
+    crdt.iter_live([&](const K &, const Record<V> &record) {
+      for (const auto &[col_name, value] : record.fields) {
+        fields.insert(col_name);
+      }
+    });
+    for (const CrdtString &field : fields) {
+      mapping.columns.push_back({field, field});
+    }
+  }
+  write_csv_cell(out, mapping.id_header);
+  for (const CrdtCsvColumn &column : mapping.columns) {
+    out << ',';
+    write_csv_cell(out, column.header);
+  }
+  out << "\r\n";
+  size_t records = 0;
+  crdt.iter_live([&](const K &record_id, const Record<V> &record) {
+    write_csv_cell(out, text(CrdtScalar<K>::to_scalar(record_id)));
+    for (const CrdtCsvColumn &column : mapping.columns) {
+      out << ',';
+      auto it = record.fields.find(column.field);
+      if (it != record.fields.end()) {
+        write_csv_cell(out, text(CrdtScalar<V>::to_scalar(it->second)));
+      }
+    }
+    out << "\r\n";
+    ++records;
+  });
+  return records;
+}
+
+/// Imports the CSV rows read from `in` in one `bulk_load`. The first row is the header; columns that are neither the
+/// id column nor mapped are ignored, unless no columns are mapped.
+///
+/// Throws CrdtImportError if the header lacks the id column or a mapped column, a row has a different number of cells
+/// than the header or an empty record id, or a value does not convert, and whatever `bulk_load` throws; the records
+/// before it stay written.
+///
+/// # Returns
+///
+/// The number of records written.
+///
+/// Complexity: O(b + r * c), where b is the size of the input, r is the number of rows and c is the number of columns
+template <typename CrdtType> size_t import_csv(CrdtType &crdt, std::istream &in, const CrdtCsvMapping &mapping = {}) {
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+  using Entry = std::pair<K, CrdtMap<CrdtString, V>>;
+  size_t line_number = 0;
+  std::optional<CrdtVector<CrdtString>> header = read_csv_row(in, line_number);
+  if (!header) {
+    return 0;
+  }
+  auto find = [&](const CrdtString &name) -> size_t {
+    auto it = std::find(header->begin(), header->end(), name);
+    if (it == header->end()) {
+      throw CrdtImportError(1, "missing column \"" + name + "\"");
+    }
+    return static_cast<size_t>(it - header->begin());
+  };
+  size_t id_index = find(mapping.id_header);
+  CrdtVector<std::pair<size_t, CrdtString>> fields; // cell index and field name
+  for (size_t i = 0; i < header->size() && mapping.columns.empty(); ++i) {
+    if (i != id_index) {
+      fields.emplace_back(i, (*header)[i]);
+    }
+  }
+  for (const CrdtCsvColumn &column : mapping.columns) {
+    fields.emplace_back(find(column.header), column.field);
+  }
+
+  PullRange<Entry> records([&]() -> std::optional<Entry> {
+    while (std::optional<CrdtVector<CrdtString>> row = read_csv_row(in, line_number)) {
+      if (row->size() == 1 && row->front().empty()) {
+        continue; // a blank line
+      }
+      if (row->size() != header->size()) {
+        throw CrdtImportError(line_number, "expected " + std::to_string(header->size()) + " cells");
+      }
+      if ((*row)[id_index].empty()) {
+        throw CrdtImportError(line_number, "empty record id");
+      }
+      Entry entry(from_scalar<K>((*row)[id_index], line_number, "the record id"), {});
+      for (const auto &[index, field] : fields) {
+        if (!(*row)[index].empty()) {
+          entry.second.insert_or_assign(field, from_scalar<V>((*row)[index], line_number, "field " + field));
+        }
+      }
+      return entry;
+    }
+    return std::nullopt;
+  });
+  return crdt.bulk_load(records);
+}
+
+} // namespace crdt_io
+
+#endif // CRDT_IO_HPP
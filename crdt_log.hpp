@@ -0,0 +1,314 @@
+// crdt_log.hpp
+#ifndef CRDT_LOG_HPP
+#define CRDT_LOG_HPP
+
+// A durable, append-only log of the changes a CRDT applies.
+//
+// A CrdtChangeLog attached to a CRDT appends every applied batch (local operations and merges) to segment files in a
+// directory. Once a segment grows past `segment_bytes` a new one is started, and once `max_segments` segments are full
+// the log is compacted: the CRDT's current state is written as a snapshot and the segments it covers are deleted,
+// which bounds the disk use to about one snapshot plus `max_segments` segments. Appends are flushed to the operating
+// system after every batch, so they survive a crash of the process (but not necessarily of the machine). A batch
+// purging a record (see CRDT::purge) compacts the log right away, so the record's data leaves the log files.
+//
+// A peer that fell too far behind for an in-memory sync catches up with `read_since`, which streams the changes past
+// its version from disk, and a restarted node rebuilds its CRDT with `CrdtChangeLog::recover`. Both keep the local
+// db_versions the changes were applied at, so peers can keep their sync cursors across restarts. Snapshots are
+// streamed too: a compaction writes the CRDT's changes record by record as it visits them, and `recover` loads one
+// record at a time, so neither holds a second copy of the state in memory.
+//
+// On-disk format: every file is a sequence of `crdt_codec::record_header` records (a length and a checksum), each
+// holding a varint change count and, per change, its local db_version and its `crdt_codec::encode_change` bytes. A
+// record cut short by a crash is dropped when the log is opened, as are files left behind by an interrupted compaction;
+// a complete record failing its checksum is corruption, and opening or recovering the log fails instead of applying it.
+
+#include "crdt_codec.hpp"
+
+#include <cstdio>
+#include <filesystem>
+#include <fstream>
+
+struct CrdtChangeLogOptions {
+  uint64_t segment_bytes = 16 * 1024 * 1024; // a new segment is started once the current one is larger
+  size_t max_segments = 8;                   // the log is compacted into a snapshot once this many segments are full
+};
+
+template <typename CrdtType> class CrdtChangeLog {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+
+  /// Opens (or creates) the log in `directory` and appends every batch applied to `crdt` from now on. An existing log
+  /// must hold the history of `crdt`, e.g. because `crdt` was rebuilt from it with `recover`.
+  ///
+  /// Throws std::runtime_error if the directory cannot be used, and CrdtDecodeError if a log file is malformed.
+  CrdtChangeLog(CrdtType &crdt, std::filesystem::path directory, CrdtChangeLogOptions options = {})
+      : crdt_(crdt), directory_(std::move(directory)), options_(options) {
+    std::filesystem::create_directories(directory_);
+    scan();
+    open_segment();
+    observer_ = crdt_.on_change_batch([this](const CrdtVector<Change<K, V>> &batch) { append(batch); });
+  }
+
+  ~CrdtChangeLog() { crdt_.remove_on_change(observer_); }
+
+  CrdtChangeLog(const CrdtChangeLog &) = delete;
+  CrdtChangeLog &operator=(const CrdtChangeLog &) = delete;
+
+  /// Calls `fn(CrdtVector<Change<K, V>> &&)` with the logged changes applied after local db_version `version`, one
+  /// stored batch at a time, so the log never has to fit in memory. Merging them all brings a peer that synced up to
+  /// `version` up to date.
+  template <typename Fn> void read_since(uint64_t version, Fn &&fn) const {
+    auto filtered = [&](CrdtVector<Change<K, V>> &&batch) {
+      std::erase_if(batch, [&](const Change<K, V> &change) { return change.local_db_version <= version; });
+      if (!batch.empty()) {
+        fn(std::move(batch));
+      }
+    };
+    if (snapshot_ && snapshot_->second > version) {
+      read_file(snapshot_->first, filtered);
+    }
+    for (const Segment &segment : segments_) {
+      if (segment.max_version > version) {
+        read_file(segment.path, filtered);
+      }
+    }
+  }
+
+  /// All logged changes applied after local db_version `version`, see `read_since`.
+  CrdtVector<Change<K, V>> changes_since(uint64_t version) const {
+    CrdtVector<Change<K, V>> changes;
+    read_since(version, [&](CrdtVector<Change<K, V>> &&batch) {
+      std::move(batch.begin(), batch.end(), std::back_inserter(changes));
+    });
+    return changes;
+  }
+
+  /// Writes the CRDT's state as a snapshot and deletes the segments and the snapshot it replaces. The state is streamed
+  /// to the file, so the memory used is bounded by one record rather than the size of the state.
+  void compact() {
+    uint64_t version = crdt_.get_clock().current_time();
+    std::filesystem::path path = directory_ / ("snapshot-" + std::to_string(version) + ".log");
+    std::filesystem::path temporary = directory_ / "snapshot.tmp";
+    {
+      std::ofstream out(temporary, std::ios::binary | std::ios::trunc);
+      CrdtVector<ChangeRef<K, V>> record;
+      record.reserve(CHANGES_PER_RECORD);
+      crdt_.for_each_change_since(0, [&](const ChangeRef<K, V> &change) {
+        record.push_back(change);
+        if (record.size() == CHANGES_PER_RECORD) {
+          write_record(out, record);
+          record.clear();
+        }
+      });
+      if (!record.empty()) {
+        write_record(out, record);
+      }
+      out.flush();
+      if (!out) {
+        throw std::runtime_error("failed to write change log snapshot");
+      }
+    }
+    std::filesystem::rename(temporary, path);
+
+    segment_.close();
+    if (snapshot_ && snapshot_->first != path) {
+      std::filesystem::remove(snapshot_->first);
+    }
+    for (const Segment &segment : segments_) {
+      std::filesystem::remove(segment.path);
+    }
+    snapshot_.emplace(path, version);
+    segments_.clear();
+    open_segment();
+  }
+
+  /// The local db_version the snapshot was taken at, 0 if the log was never compacted.
+  uint64_t snapshot_version() const { return snapshot_ ? snapshot_->second : 0; }
+
+  /// Number of segment files, including the one being appended to.
+  size_t segment_count() const { return segments_.size(); }
+
+  /// Rebuilds node `node_id`'s CRDT from the log in `directory`, keeping the local db_versions the changes were
+  /// applied at. The log is loaded one record at a time. Attach a CrdtChangeLog to the result to keep logging.
+  ///
+  /// Throws CrdtDecodeError, naming the file, if a log file is malformed or corrupted.
+  static CrdtType recover(const std::filesystem::path &directory, CrdtNodeId node_id) {
+    CrdtType crdt(node_id);
+    auto [snapshot, segments] = list_files(directory);
+    uint64_t snapshot_version = 0;
+    if (snapshot) {
+      read_file(snapshot->first, [&](CrdtVector<Change<K, V>> &&batch) { crdt.load_changes(std::move(batch)); });
+      snapshot_version = snapshot->second;
+    }
+    for (const auto &[number, path] : segments) {
+      read_file(path, [&](CrdtVector<Change<K, V>> &&batch) {
+        std::erase_if(batch, [&](const Change<K, V> &change) { return change.local_db_version <= snapshot_version; });
+        crdt.load_changes(std::move(batch));
+      });
+    }
+    return crdt;
+  }
+
+private:
+  static constexpr size_t CHANGES_PER_RECORD = 1024;
+  static constexpr uint32_t MAX_RECORD_SIZE = 256 * 1024 * 1024;
+
+  struct Segment {
+    uint64_t number;
+    std::filesystem::path path;
+    uint64_t bytes;
+    uint64_t max_version; // highest local db_version in the segment
+  };
+
+  CrdtType &crdt_;
+  std::filesystem::path directory_;
+  CrdtChangeLogOptions options_;
+  std::optional<std::pair<std::filesystem::path, uint64_t>> snapshot_; // path and version
+  CrdtVector<Segment> segments_;
+  std::ofstream segment_;
+  size_t observer_ = 0;
+
+  // The newest snapshot (path and version) and the segments (number and path) in order
+  static std::pair<std::optional<std::pair<std::filesystem::path, uint64_t>>,
+                   CrdtVector<std::pair<uint64_t, std::filesystem::path>>>
+  list_files(const std::filesystem::path &directory) {
+    std::optional<std::pair<std::filesystem::path, uint64_t>> snapshot;
+    CrdtVector<std::pair<uint64_t, std::filesystem::path>> segments;
+    for (const auto &entry : std::filesystem::directory_iterator(directory)) {
+      std::string name = entry.path().filename().string();
+      if (name.ends_with(".log") && name.starts_with("snapshot-")) {
+        uint64_t version = std::stoull(name.substr(9, name.size() - 13));
+        if (!snapshot || version > snapshot->second) {
+          snapshot.emplace(entry.path(), version);
+        }
+      } else if (name.ends_with(".log") && name.starts_with("segment-")) {
+        segments.emplace_back(std::stoull(name.substr(8, name.size() - 12)), entry.path());
+      }
+    }
+    std::sort(segments.begin(), segments.end());
+    return {std::move(snapshot), std::move(segments)};
+  }
+
+  void scan() {
+    auto [snapshot, segments] = list_files(directory_);
+    snapshot_ = std::move(snapshot);
+    for (const auto &entry : std::filesystem::directory_iterator(directory_)) {
+      std::string name = entry.path().filename().string();
+      if (snapshot_ && name.starts_with("snapshot-") && entry.path() != snapshot_->first) {
+        std::filesystem::remove(entry.path()); // replaced by a compaction that was interrupted before removing it
+      }
+    }
+    for (const auto &[number, path] : segments) {
+      uint64_t max_version = 0;
+      uint64_t valid = read_file(path, [&](CrdtVector<Change<K, V>> &&batch) {
+        for (const auto &change : batch) {
+          max_version = std::max(max_version, change.local_db_version);
+        }
+      });
+      if (snapshot_ && max_version <= snapshot_->second) {
+        std::filesystem::remove(path); // covered by the snapshot
+        continue;
+      }
+      if (valid < std::filesystem::file_size(path)) {
+        std::filesystem::resize_file(path, valid);
+      }
+      segments_.push_back({number, path, valid, max_version});
+    }
+  }
+
+  // Appends to the last segment, or starts the first one
+  void open_segment() {
+    if (segments_.empty()) {
+      start_segment(1);
+      return;
+    }
+    segment_.open(segments_.back().path, std::ios::binary | std::ios::app);
+    if (!segment_) {
+      throw std::runtime_error("failed to open change log segment");
+    }
+  }
+
+  void start_segment(uint64_t number) {
+    segment_.close();
+    char name[32];
+    std::snprintf(name, sizeof(name), "segment-%08llu.log", static_cast<unsigned long long>(number));
+    segments_.push_back({number, directory_ / name, 0, 0});
+    segment_.open(segments_.back().path, std::ios::binary | std::ios::trunc);
+    if (!segment_) {
+      throw std::runtime_error("failed to create change log segment");
+    }
+  }
+
+  void append(const CrdtVector<Change<K, V>> &batch) {
+    Segment &segment = segments_.back();
+    try {
+      segment.bytes += write_record(segment_, batch);
+      segment_.flush();
+      if (!segment_) {
+        throw std::runtime_error("failed to append to change log");
+      }
+    } catch (...) {
+      // Drop the partial record, so later appends do not follow a torn one
+      segment_.close();
+      std::filesystem::resize_file(segment.path, segment.bytes);
+      segment_.open(segment.path, std::ios::binary | std::ios::app);
+      throw;
+    }
+    for (const auto &change : batch) {
+      segment.max_version = std::max(segment.max_version, change.local_db_version);
+    }
+    // The segments still hold the values of a purged record, so they are replaced by a snapshot right away
+    if (std::ranges::any_of(batch, [](const Change<K, V> &change) { return CrdtType::is_purge(change); })) {
+      compact();
+    } else if (segment.bytes >= options_.segment_bytes) {
+      if (segments_.size() >= options_.max_segments) {
+        compact();
+      } else {
+        start_segment(segment.number + 1);
+      }
+    }
+  }
+
+  // Returns the number of bytes written; `ChangeType` is `Change` or `ChangeRef`
+  template <typename ChangeType> static uint64_t write_record(std::ofstream &out, const CrdtVector<ChangeType> &batch) {
+    CrdtBytes payload;
+    ByteWriter writer(payload);
+    crdt_codec::encode_stored_changes(writer, batch);
+    CrdtBytes header = crdt_codec::record_header(payload);
+    out.write(reinterpret_cast<const char *>(header.data()), static_cast<std::streamsize>(header.size()));
+    if (crdt_fault(CrdtFaultPoint::StorageWrite) != 0) {
+      out.write(reinterpret_cast<const char *>(payload.data()), static_cast<std::streamsize>(payload.size() / 2));
+      out.flush();
+      throw CrdtInjectedFault("injected storage write failure");
+    }
+    out.write(reinterpret_cast<const char *>(payload.data()), static_cast<std::streamsize>(payload.size()));
+    return header.size() + payload.size();
+  }
+
+  // Calls `fn` with each record's changes and returns the size of the complete records; a final record cut short is
+  // ignored. A record failing its checksum fails the whole file, named in the error
+  template <typename Fn> static uint64_t read_file(const std::filesystem::path &path, Fn &&fn) {
+    std::ifstream in(path, std::ios::binary);
+    if (!in) {
+      throw std::runtime_error("failed to open change log file");
+    }
+    uint64_t valid = 0;
+    try {
+      while (std::optional<CrdtBytes> payload = crdt_codec::read_record(in, MAX_RECORD_SIZE)) {
+        ByteReader reader(*payload);
+        CrdtVector<Change<K, V>> batch = crdt_codec::decode_stored_changes<K, V>(reader);
+        if (!reader.at_end()) {
+          throw CrdtDecodeError("trailing bytes after change log record");
+        }
+        fn(std::move(batch));
+        valid += crdt_codec::RECORD_HEADER_SIZE + payload->size();
+      }
+    } catch (const CrdtDecodeError &error) {
+      throw CrdtDecodeError((path.filename().string() + ": " + error.what()).c_str());
+    }
+    return valid;
+  }
+};
+
+#endif // CRDT_LOG_HPP
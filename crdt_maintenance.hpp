@@ -0,0 +1,173 @@
+// crdt_maintenance.hpp
+#ifndef CRDT_MAINTENANCE_HPP
+#define CRDT_MAINTENANCE_HPP
+
+// Periodic housekeeping for long-running replicas.
+//
+// A CrdtMaintenance runs on its own thread next to a SharedCrdt and, every interval, garbage collects the tombstones
+// all peers have received, compacts the index behind get_changes_since and optionally expires records under the TTL
+// policy and shrinks the record tables. Each step takes the CRDT's write lock briefly, on its own. Passes are skipped
+// while maintenance is paused (e.g. around a bulk import, with a CrdtMaintenance::Pause guard) or while the policy's
+// `defer` hook asks to wait.
+
+#include "shared_crdt.hpp"
+
+#include <condition_variable>
+#include <thread>
+
+/// What a CrdtMaintenance does, and how often. The hooks run on the maintenance thread and must not throw.
+struct CrdtMaintenancePolicy {
+  std::chrono::milliseconds interval = std::chrono::minutes(1);
+
+  /// The highest local db_version every peer has received, or std::nullopt if unknown (tombstones are then kept).
+  /// Unset disables tombstone GC.
+  std::function<std::optional<uint64_t>()> acknowledged_version;
+
+  /// Tombstone GC only runs once at least this many tombstones are eligible, so small ones are not worth the lock.
+  size_t min_collectable = 1;
+
+  bool compact_change_index = true;
+  bool expire = false; // runs CRDT::expire at the CRDT's wall clock
+  bool shrink = false;
+
+  /// Called before each pass; returning true skips it, e.g. while the write rate is high.
+  std::function<bool()> defer;
+};
+
+/// What CrdtMaintenance did so far.
+struct CrdtMaintenanceStats {
+  uint64_t passes = 0;               // passes that ran
+  uint64_t passes_skipped = 0;       // passes skipped while paused or deferred
+  uint64_t tombstones_collected = 0;
+  uint64_t expired = 0; // records and columns deleted by expiry
+};
+
+template <typename SharedType> class CrdtMaintenance {
+public:
+  /// Pauses maintenance for its lifetime; a pass already running finishes first.
+  class Pause {
+  public:
+    explicit Pause(CrdtMaintenance &maintenance) : maintenance_(maintenance) { maintenance_.pause(); }
+    ~Pause() { maintenance_.resume(); }
+    Pause(const Pause &) = delete;
+    Pause &operator=(const Pause &) = delete;
+
+  private:
+    CrdtMaintenance &maintenance_;
+  };
+
+  /// Starts the maintenance thread. `crdt` must outlive this object.
+  CrdtMaintenance(SharedType &crdt, CrdtMaintenancePolicy policy)
+      : crdt_(crdt), policy_(std::move(policy)), thread_([this] { loop(); }) {}
+
+  /// Stops the maintenance thread, waiting for a running pass.
+  ~CrdtMaintenance() {
+    {
+      std::lock_guard lock(mutex_);
+      stopping_ = true;
+    }
+    wake_.notify_all();
+    thread_.join();
+  }
+
+  CrdtMaintenance(const CrdtMaintenance &) = delete;
+  CrdtMaintenance &operator=(const CrdtMaintenance &) = delete;
+
+  /// Skips passes until a matching `resume`. Pauses nest.
+  void pause() {
+    std::lock_guard lock(mutex_);
+    ++pauses_;
+  }
+
+  void resume() {
+    std::lock_guard lock(mutex_);
+    if (pauses_ > 0) {
+      --pauses_;
+    }
+  }
+
+  bool paused() const {
+    std::lock_guard lock(mutex_);
+    return pauses_ > 0;
+  }
+
+  /// Runs a pass on the maintenance thread now instead of at the end of the interval.
+  void trigger() {
+    {
+      std::lock_guard lock(mutex_);
+      triggered_ = true;
+    }
+    wake_.notify_all();
+  }
+
+  /// Runs a pass on the calling thread, unless paused or deferred, and returns whether it ran.
+  bool run_once() {
+    std::lock_guard pass(pass_mutex_);
+    if (paused() || (policy_.defer && policy_.defer())) {
+      std::lock_guard lock(mutex_);
+      ++stats_.passes_skipped;
+      return false;
+    }
+
+    size_t expired = 0;
+    if (policy_.expire) {
+      expired = crdt_.write([](auto &crdt) { return crdt.expire().size(); });
+    }
+    size_t collected = 0;
+    if (policy_.acknowledged_version) {
+      if (std::optional<uint64_t> acknowledged = policy_.acknowledged_version()) {
+        collected = crdt_.write([&](auto &crdt) {
+          if (crdt.tombstone_counts(*acknowledged).gc_eligible < policy_.min_collectable) {
+            return size_t(0);
+          }
+          return crdt.collect_tombstones(*acknowledged);
+        });
+      }
+    }
+    if (policy_.compact_change_index) {
+      crdt_.write([](auto &crdt) { crdt.compact_change_index(); });
+    }
+    if (policy_.shrink) {
+      crdt_.write([](auto &crdt) { crdt.shrink_to_fit(); });
+    }
+
+    std::lock_guard lock(mutex_);
+    ++stats_.passes;
+    stats_.tombstones_collected += collected;
+    stats_.expired += expired;
+    return true;
+  }
+
+  CrdtMaintenanceStats stats() const {
+    std::lock_guard lock(mutex_);
+    return stats_;
+  }
+
+private:
+  SharedType &crdt_;
+  CrdtMaintenancePolicy policy_;
+  mutable std::mutex mutex_;
+  std::mutex pass_mutex_; // one pass at a time, whether from the thread or run_once
+  std::condition_variable wake_;
+  bool stopping_ = false;
+  bool triggered_ = false;
+  size_t pauses_ = 0;
+  CrdtMaintenanceStats stats_;
+  std::thread thread_; // last, so it starts once everything else is initialized
+
+  void loop() {
+    std::unique_lock lock(mutex_);
+    while (true) {
+      wake_.wait_for(lock, policy_.interval, [this] { return stopping_ || triggered_; });
+      if (stopping_) {
+        return;
+      }
+      triggered_ = false;
+      lock.unlock();
+      run_once();
+      lock.lock();
+    }
+  }
+};
+
+#endif // CRDT_MAINTENANCE_HPP
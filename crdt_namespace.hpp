@@ -0,0 +1,109 @@
+// crdt_namespace.hpp
+#ifndef CRDT_NAMESPACE_HPP
+#define CRDT_NAMESPACE_HPP
+
+// Multi-tenant namespaces inside one CRDT.
+//
+// A server hosting many small logical CRDTs (one per tenant, document or workspace) can keep them all in a single
+// CRDT<CrdtString, V>, so they share its clock, its storage (a CrdtChangeLog, a SqliteMirror) and its connections.
+// Each record id is prefixed with its namespace and a separator, e.g. "acme/invoice-17", and a CrdtNamespace is the
+// view of one tenant: it reads and writes its keys unprefixed, extracts only the tenant's changes and drops incoming
+// changes to records of other namespaces, so a tenant's peer can never read or write another tenant's data.
+//
+// A CrdtNamespace can be synced on its own with a SyncSession<CrdtNamespace<...>>. Its versions are those of the shared
+// CRDT, so keep sync cursors per peer and namespace.
+
+#include "crdt.hpp"
+
+#include <stdexcept>
+
+template <typename CrdtType> class CrdtNamespace {
+public:
+  using KeyType = typename CrdtType::KeyType;
+  using ValueType = typename CrdtType::ValueType;
+  using K = KeyType;
+  using V = ValueType;
+  static_assert(std::is_same_v<K, CrdtString>, "namespaces require CrdtString record ids");
+
+  /// # Arguments
+  ///
+  /// * `crdt` - The shared CRDT; it must outlive the view.
+  /// * `name` - The namespace. It must not contain `separator`, so no namespace is a prefix of another.
+  CrdtNamespace(CrdtType &crdt, CrdtString name, char separator = '/')
+      : crdt_(crdt), prefix_(std::move(name) + separator) {
+    if (prefix_.find(separator) != prefix_.size() - 1) {
+      throw std::invalid_argument("namespace must not contain the separator");
+    }
+  }
+
+  /// The namespace name, without the separator.
+  CrdtString name() const { return prefix_.substr(0, prefix_.size() - 1); }
+
+  /// The record id of `key` in the shared CRDT.
+  K qualify(const K &key) const { return prefix_ + key; }
+
+  /// Whether `record_id` of the shared CRDT belongs to this namespace.
+  bool contains(const K &record_id) const { return record_id.starts_with(prefix_); }
+
+  /// See `CRDT::insert_or_update`. The returned changes carry the qualified record id.
+  CrdtVector<Change<K, V>> insert_or_update(const K &key, CrdtMap<CrdtString, V> &&fields) {
+    return crdt_.insert_or_update(qualify(key), std::move(fields));
+  }
+
+  /// See `CRDT::delete_record`.
+  CrdtVector<Change<K, V>> delete_record(const K &key) { return crdt_.delete_record(qualify(key)); }
+
+  const Record<V> *get_record(const K &key) const { return std::as_const(crdt_).get_record(qualify(key)); }
+
+  const V *get_value(const K &key, const CrdtString &col_name) const {
+    return std::as_const(crdt_).get_value(qualify(key), col_name);
+  }
+
+  /// Number of live records in this namespace.
+  ///
+  /// Complexity: O(n), where n is the number of records in the shared CRDT
+  size_t len_live() const {
+    size_t count = 0;
+    crdt_.iter_live([&](const K &record_id, const Record<V> &) { count += contains(record_id); });
+    return count;
+  }
+
+  /// The clock of the shared CRDT.
+  const LogicalClock &get_clock() const { return crdt_.get_clock(); }
+
+  /// See `CRDT::is_applied`; `change` carries the qualified record id.
+  bool is_applied(const Change<K, V> &change) const { return crdt_.is_applied(change); }
+
+  /// The changes to this namespace since `last_db_version`, with qualified record ids.
+  ///
+  /// Complexity: that of `CRDT::get_changes_since` on the shared CRDT
+  CrdtVector<Change<K, V>> get_changes_since(uint64_t last_db_version) const {
+    CrdtVector<Change<K, V>> changes = crdt_.get_changes_since(last_db_version);
+    std::erase_if(changes, [&](const Change<K, V> &change) { return !contains(change.record_id); });
+    return changes;
+  }
+
+  /// Same as `get_changes_since`, borrowing from the shared CRDT.
+  CrdtVector<ChangeRef<K, V>> get_change_refs_since(uint64_t last_db_version) const {
+    CrdtVector<ChangeRef<K, V>> changes = crdt_.get_change_refs_since(last_db_version);
+    std::erase_if(changes, [&](const ChangeRef<K, V> &change) { return !contains(change.record_id.get()); });
+    return changes;
+  }
+
+  /// Merges the changes to this namespace into the shared CRDT and drops the rest.
+  ///
+  /// # Returns
+  ///
+  /// The number of dropped changes, i.e. changes to records of other namespaces.
+  size_t merge_changes(CrdtVector<Change<K, V>> &&changes, bool ignore_parent = false) {
+    size_t dropped = std::erase_if(changes, [&](const Change<K, V> &change) { return !contains(change.record_id); });
+    crdt_.merge_changes(std::move(changes), ignore_parent);
+    return dropped;
+  }
+
+private:
+  CrdtType &crdt_;
+  CrdtString prefix_; // the name followed by the separator
+};
+
+#endif // CRDT_NAMESPACE_HPP
@@ -0,0 +1,234 @@
+// crdt_ops.hpp
+#ifndef CRDT_OPS_HPP
+#define CRDT_OPS_HPP
+
+// Operation-based replication for live collaboration.
+//
+// State-based sync (`get_changes_since`, SyncSession) extracts deltas on demand, which suits catching up after being
+// offline. For live collaboration, an OpPublisher attached to a CRDT captures every local operation as soon as it is
+// applied, together with its causal context, and hands it to a send function right away; peers apply it with
+// `crdt_ops::apply_op`. The changes of an operation go through the usual merge rule, so applying an operation twice or
+// after a later one is harmless. Operations a peer missed, e.g. while disconnected, are recovered with a regular sync.
+//
+// Applying operations in causal order still matters to observers: an OpReceiver holds back an operation that arrives
+// before the ones it depends on (an update before its insert, a child record before its parent), so observers and
+// readers never see an effect before its cause.
+
+#include "crdt_codec.hpp"
+
+#include <chrono>
+#include <deque>
+#include <functional>
+
+/// One local operation (e.g. an `insert_or_update` call) and its causal context.
+template <typename K, typename V> struct CrdtOp {
+  CrdtNodeId origin;
+  uint64_t seq = 0;                   // the origin's db_version for the operation
+  uint64_t prev_seq = 0;              // seq of the origin's previous published operation, 0 for its first
+  CrdtMap<CrdtNodeId, uint64_t> deps; // the versions of other nodes the origin had applied when it made the operation
+  CrdtVector<Change<K, V>> changes;
+};
+
+namespace crdt_ops {
+
+template <typename K, typename V> CrdtBytes encode_op(const CrdtOp<K, V> &op) {
+  CrdtBytes bytes;
+  ByteWriter writer(bytes);
+  CrdtCodec<CrdtNodeId>::encode(writer, op.origin);
+  writer.write_varint(op.seq);
+  writer.write_varint(op.prev_seq);
+  writer.write_varint(op.deps.size());
+  for (const auto &[node_id, version] : op.deps) {
+    CrdtCodec<CrdtNodeId>::encode(writer, node_id);
+    writer.write_varint(version);
+  }
+  crdt_codec::encode_changes(writer, op.changes);
+  return bytes;
+}
+
+/// Throws CrdtDecodeError on malformed input.
+template <typename K, typename V> CrdtOp<K, V> decode_op(const CrdtBytes &bytes) {
+  ByteReader reader(bytes);
+  CrdtOp<K, V> op;
+  op.origin = CrdtCodec<CrdtNodeId>::decode(reader);
+  op.seq = reader.read_varint();
+  op.prev_seq = reader.read_varint();
+  uint64_t deps = reader.read_varint();
+  if (deps > reader.remaining()) {
+    throw CrdtDecodeError("dependency count exceeds input");
+  }
+  for (uint64_t i = 0; i < deps; ++i) {
+    CrdtNodeId node_id = CrdtCodec<CrdtNodeId>::decode(reader);
+    op.deps[node_id] = reader.read_varint();
+  }
+  op.changes = crdt_codec::decode_changes<K, V>(reader);
+  if (!reader.at_end()) {
+    throw CrdtDecodeError("trailing bytes after operation");
+  }
+  return op;
+}
+
+/// Merges an operation received from a peer.
+template <typename CrdtType>
+void apply_op(CrdtType &crdt, CrdtOp<typename CrdtType::KeyType, typename CrdtType::ValueType> op) {
+  crdt.merge_changes(std::move(op.changes));
+}
+
+} // namespace crdt_ops
+
+/// Captures the local operations of a CRDT as CrdtOps and passes each to `send` as soon as it is applied.
+template <typename K, typename V> class OpPublisher {
+public:
+  using Send = std::function<void(const CrdtOp<K, V> &op)>;
+
+  /// # Arguments
+  ///
+  /// * `node_id` - The node id of the CRDT the publisher is attached to.
+  /// * `send` - Called synchronously inside the CRDT operation; it must not modify the CRDT.
+  /// * `last_seq` - The seq of the last operation published before a restart, so the next one links to it.
+  OpPublisher(CrdtNodeId node_id, Send send, uint64_t last_seq = 0)
+      : node_id_(node_id), send_(std::move(send)), last_seq_(last_seq) {}
+
+  /// Publishes every local operation on `crdt`; merged changes are not republished. The publisher must outlive the
+  /// registration; the returned id can be passed to `crdt.remove_on_change`.
+  template <typename CrdtType> size_t attach(CrdtType &crdt) {
+    return crdt.on_change_batch([this, &crdt](const CrdtVector<Change<K, V>> &batch) { publish(crdt, batch); });
+  }
+
+  /// The seq of the last published operation, 0 if none was published.
+  uint64_t last_seq() const { return last_seq_; }
+
+  /// Number of operations published.
+  uint64_t published() const { return published_; }
+
+private:
+  CrdtNodeId node_id_;
+  Send send_;
+  uint64_t last_seq_;
+  uint64_t published_ = 0;
+
+  template <typename CrdtType> void publish(const CrdtType &crdt, const CrdtVector<Change<K, V>> &batch) {
+    // A local operation stamps its changes with our id and the local clock; merged changes get a newer local version
+    for (const auto &change : batch) {
+      if (change.node_id != node_id_ || change.local_db_version != change.db_version) {
+        return;
+      }
+    }
+    CrdtOp<K, V> op{node_id_, batch.front().db_version, last_seq_, crdt.max_seen_versions(), batch};
+    op.deps.erase(node_id_);
+    last_seq_ = op.seq;
+    ++published_;
+    send_(op);
+  }
+};
+
+/// Applies received operations in causal order, buffering those whose dependencies have not been applied yet.
+///
+/// An operation is ready once the CRDT has seen the origin's previous operation and everything the origin had seen
+/// when it made it (as recorded by `max_seen_versions`), whether those arrived as operations or through a regular
+/// sync. Since those are the highest versions seen, a dependency skipped by a partial delivery can go unnoticed.
+/// Operations that wait longer than the timeout are applied anyway by `flush_expired`: merging out of order still
+/// converges, and the dependencies may only ever arrive through a later sync.
+template <typename CrdtType> class OpReceiver {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+  using Clock = std::chrono::steady_clock;
+
+  /// # Arguments
+  ///
+  /// * `timeout` - How long an operation may wait for its dependencies before `flush_expired` applies it.
+  /// * `max_buffered` - Buffered operations beyond this are applied right away, oldest first.
+  explicit OpReceiver(CrdtType &crdt, Clock::duration timeout = std::chrono::seconds(5), size_t max_buffered = 10000)
+      : crdt_(crdt), timeout_(timeout), max_buffered_(max_buffered) {}
+
+  /// Applies `op` if its dependencies are satisfied, along with any buffered operations that were waiting for it, or
+  /// buffers it otherwise.
+  void receive(CrdtOp<K, V> op, Clock::time_point now = Clock::now()) {
+    if (!is_ready(op)) {
+      buffer_.push_back({std::move(op), now});
+      while (buffer_.size() > max_buffered_) {
+        apply(std::move(buffer_.front().op));
+        buffer_.pop_front();
+      }
+    } else {
+      apply(std::move(op));
+    }
+    drain();
+  }
+
+  /// Applies the operations that have waited longer than the timeout, oldest first.
+  void flush_expired(Clock::time_point now = Clock::now()) {
+    while (!buffer_.empty() && now - buffer_.front().received_at >= timeout_) {
+      apply(std::move(buffer_.front().op));
+      buffer_.pop_front();
+      drain();
+    }
+  }
+
+  /// Applies every buffered operation, e.g. before switching to a state-based sync.
+  void flush() {
+    while (!buffer_.empty()) {
+      apply(std::move(buffer_.front().op));
+      buffer_.pop_front();
+    }
+  }
+
+  /// Number of operations waiting for their dependencies.
+  size_t buffered() const { return buffer_.size(); }
+
+  /// Number of operations applied by this receiver, including ones applied by a flush.
+  uint64_t applied() const { return applied_; }
+
+private:
+  struct Pending {
+    CrdtOp<K, V> op;
+    Clock::time_point received_at;
+  };
+
+  CrdtType &crdt_;
+  Clock::duration timeout_;
+  size_t max_buffered_;
+  std::deque<Pending> buffer_;
+  uint64_t applied_ = 0;
+
+  bool is_ready(const CrdtOp<K, V> &op) const {
+    auto seen = [&](const CrdtNodeId &node_id, uint64_t version) {
+      auto it = crdt_.max_seen_versions().find(node_id);
+      return version == 0 || (it != crdt_.max_seen_versions().end() && it->second >= version);
+    };
+    if (!seen(op.origin, op.prev_seq)) {
+      return false;
+    }
+    for (const auto &[node_id, version] : op.deps) {
+      if (!seen(node_id, version)) {
+        return false;
+      }
+    }
+    return true;
+  }
+
+  void apply(CrdtOp<K, V> &&op) {
+    crdt_ops::apply_op(crdt_, std::move(op));
+    ++applied_;
+  }
+
+  // Applies buffered operations that became ready, until none is left that can be applied
+  void drain() {
+    bool progress = true;
+    while (progress) {
+      progress = false;
+      for (auto it = buffer_.begin(); it != buffer_.end(); ++it) {
+        if (is_ready(it->op)) {
+          CrdtOp<K, V> op = std::move(it->op);
+          buffer_.erase(it);
+          apply(std::move(op));
+          progress = true;
+          break;
+        }
+      }
+    }
+  }
+};
+
+#endif // CRDT_OPS_HPP
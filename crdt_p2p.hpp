@@ -0,0 +1,174 @@
+// crdt_p2p.hpp
+#ifndef CRDT_P2P_HPP
+#define CRDT_P2P_HPP
+
+// Peer-to-peer sync over a libp2p-style stack, for deployments without a central server.
+//
+// A P2pSync is the CRDT side of a libp2p behaviour combining the two usual protocols:
+//
+// - request/response on CRDT_P2P_SYNC_PROTOCOL: a node pulls the changes a peer made or received since its cursor for
+//   that peer, e.g. when the peer is discovered (mDNS, Kademlia) and then periodically as anti-entropy;
+// - gossipsub on a topic: every local operation is published as it is applied (see crdt_ops.hpp), so peers receive it
+//   within a few hops. A node that notices it missed an operation of an origin pulls from the peer that relayed the
+//   next one, so gossip losses are repaired without waiting for the next round.
+//
+// This header does not depend on a libp2p implementation: wire the SendRequest and Publish functions to your stack's
+// request/response and gossipsub behaviours (cpp-libp2p, or rust-libp2p/go-libp2p through a C ABI), and feed it the
+// discovered peers, requests, responses and gossip messages. Peers are identified by their libp2p peer id string.
+
+#include "crdt_ops.hpp"
+#include "crdt_sync.hpp"
+
+/// Protocol id of the pull request/response protocol.
+constexpr const char *CRDT_P2P_SYNC_PROTOCOL = "/crdt-lite/sync/1.0.0";
+
+template <typename CrdtType> class P2pSync {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+  using PeerId = CrdtString;
+  /// Sends a request to `peer` on CRDT_P2P_SYNC_PROTOCOL; pass the response to `on_response`.
+  using SendRequest = std::function<void(const PeerId &peer, const CrdtBytes &request)>;
+  /// Publishes a message on the gossipsub topic.
+  using Publish = std::function<void(const CrdtBytes &message)>;
+
+  /// Attaches to `crdt` to publish its local operations. `crdt` must outlive this object, which must not be moved.
+  P2pSync(CrdtType &crdt, CrdtNodeId node_id, SendRequest send_request, Publish publish)
+      : crdt_(crdt), node_id_(node_id), send_request_(std::move(send_request)), publish_(std::move(publish)),
+        publisher_(node_id, [this](const CrdtOp<K, V> &op) { publish_(crdt_ops::encode_op(op)); }) {
+    registration_ = publisher_.attach(crdt_);
+  }
+
+  ~P2pSync() { crdt_.remove_on_change(registration_); }
+
+  P2pSync(const P2pSync &) = delete;
+  P2pSync &operator=(const P2pSync &) = delete;
+
+  /// Call when the stack discovers or connects to a peer: pulls the changes we lack from it.
+  void on_peer_discovered(const PeerId &peer) {
+    cursors_.try_emplace(peer, 0);
+    pull(peer);
+  }
+
+  /// Call when the peer is gone; its cursor is kept in case it comes back.
+  void on_peer_lost(const PeerId &peer) { pulling_.erase(peer); }
+
+  /// Pulls from every known peer, e.g. on a timer as anti-entropy.
+  void pull_all() {
+    for (const auto &[peer, cursor] : cursors_) {
+      pull(peer);
+    }
+  }
+
+  /// Answers a pull request from a peer.
+  ///
+  /// Throws CrdtDecodeError on malformed requests and SyncProtocolError on unsupported versions.
+  ///
+  /// # Returns
+  ///
+  /// The response to send back.
+  CrdtBytes on_request(const PeerId &, const CrdtBytes &request) {
+    ByteReader reader(request);
+    if (reader.read_varint() != PROTOCOL_VERSION) {
+      throw SyncProtocolError("unsupported p2p sync version");
+    }
+    CrdtNodeId requester = CrdtCodec<CrdtNodeId>::decode(reader);
+    uint64_t since = reader.read_varint();
+    CrdtCompression codec = crdt_codec::choose_compression(reader.read_varint() & crdt_codec::supported_compression());
+
+    uint64_t up_to = crdt_.get_clock().current_time();
+    auto changes = crdt_.get_change_refs_since(since);
+    // The requester already has what it wrote
+    std::erase_if(changes, [&](const ChangeRef<K, V> &change) { return change.node_id == requester; });
+    ++requests_served_;
+
+    CrdtBytes response;
+    ByteWriter writer(response);
+    writer.write_varint(up_to);
+    crdt_codec::encode_changes_compressed(writer, changes, codec);
+    return response;
+  }
+
+  /// Handles the response to a pull request sent to `peer`, merging its changes.
+  ///
+  /// Throws CrdtDecodeError on malformed responses.
+  void on_response(const PeerId &peer, const CrdtBytes &response) {
+    ByteReader reader(response);
+    uint64_t up_to = reader.read_varint();
+    auto changes = crdt_codec::decode_changes_compressed<K, V>(reader);
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after pull response");
+    }
+    crdt_.merge_changes(std::move(changes));
+    uint64_t &cursor = cursors_[peer];
+    cursor = std::max(cursor, up_to);
+    pulling_.erase(peer);
+  }
+
+  /// Call when a pull request to `peer` failed (timeout, connection closed), so it can be retried.
+  void on_request_failed(const PeerId &peer) { pulling_.erase(peer); }
+
+  /// Handles a gossip message relayed by `source`, merging its operation. If an earlier operation of its origin is
+  /// missing, pulls from `source`, which had it.
+  ///
+  /// Throws CrdtDecodeError on malformed messages.
+  void on_message(const PeerId &source, const CrdtBytes &message) {
+    CrdtOp<K, V> op = crdt_ops::decode_op<K, V>(message);
+    if (op.origin == node_id_) {
+      return;
+    }
+    auto seen = crdt_.max_seen_versions().find(op.origin);
+    bool gap = op.prev_seq != 0 && (seen == crdt_.max_seen_versions().end() || seen->second < op.prev_seq);
+    crdt_ops::apply_op(crdt_, std::move(op));
+    ++messages_applied_;
+    if (gap) {
+      ++gaps_detected_;
+      cursors_.try_emplace(source, 0);
+      pull(source);
+    }
+  }
+
+  /// The peer's version we pulled up to, per peer; persist these to resume after a restart.
+  const CrdtMap<PeerId, uint64_t> &cursors() const { return cursors_; }
+
+  void set_cursor(const PeerId &peer, uint64_t cursor) { cursors_[peer] = cursor; }
+
+  /// Number of gossip messages merged.
+  uint64_t messages_applied() const { return messages_applied_; }
+
+  /// Number of gossip messages that revealed a missed operation.
+  uint64_t gaps_detected() const { return gaps_detected_; }
+
+  uint64_t requests_served() const { return requests_served_; }
+
+private:
+  static constexpr uint64_t PROTOCOL_VERSION = 1;
+
+  CrdtType &crdt_;
+  CrdtNodeId node_id_;
+  SendRequest send_request_;
+  Publish publish_;
+  OpPublisher<K, V> publisher_;
+  size_t registration_ = 0;
+  CrdtMap<PeerId, uint64_t> cursors_;
+  CrdtSet<PeerId> pulling_; // peers with a pull request in flight
+  uint64_t messages_applied_ = 0;
+  uint64_t gaps_detected_ = 0;
+  uint64_t requests_served_ = 0;
+
+  // Sends a pull request unless one is already in flight to `peer`
+  void pull(const PeerId &peer) {
+    if (!pulling_.insert(peer).second) {
+      return;
+    }
+    CrdtBytes request;
+    ByteWriter writer(request);
+    writer.write_varint(PROTOCOL_VERSION);
+    CrdtCodec<CrdtNodeId>::encode(writer, node_id_);
+    writer.write_varint(cursors_[peer]);
+    writer.write_varint(crdt_codec::supported_compression());
+    send_request_(peer, request);
+  }
+};
+
+#endif // CRDT_P2P_HPP
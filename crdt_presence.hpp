@@ -0,0 +1,195 @@
+// crdt_presence.hpp
+#ifndef CRDT_PRESENCE_HPP
+#define CRDT_PRESENCE_HPP
+
+// Ephemeral presence (awareness) for collaboration UIs: who is online, which record they are viewing or editing, where
+// their cursor is.
+//
+// Presence is not part of the record CRDT: it is never stored, has no tombstones and is not returned by
+// `get_changes_since`. Each node broadcasts its own state as a small map of fields in a Presence frame, which shares
+// the sync transport with SyncSession (route frames for which `is_presence_frame` is true to CrdtPresence; a
+// SyncSession ignores them). Every state carries a sequence number, so a newer state replaces an older one whatever
+// the order of arrival, and frames can be relayed through other nodes: `on_frame` returns true for news worth
+// forwarding. Peers that stop sending heartbeats are dropped after a timeout.
+
+#include "crdt_sync.hpp"
+
+#include <chrono>
+#include <functional>
+
+/// The presence state of one node.
+struct PresenceState {
+  uint64_t seq = 0;
+  CrdtMap<CrdtString, CrdtString> fields; // e.g. {"user": "alice", "viewing": "doc-17"}
+  std::chrono::steady_clock::time_point last_seen;
+};
+
+/// True if `frame` is a presence frame rather than a sync message.
+inline bool is_presence_frame(const CrdtBytes &frame) {
+  return !frame.empty() && frame[0] == static_cast<uint8_t>(SyncMessageType::Presence);
+}
+
+class CrdtPresence {
+public:
+  using Clock = std::chrono::steady_clock;
+
+  /// Called with the node whose presence changed and its new state, or nullptr if it left or timed out.
+  using Observer = std::function<void(const CrdtNodeId &node_id, const PresenceState *state)>;
+
+  /// # Arguments
+  ///
+  /// * `node_id` - The id of this node.
+  /// * `timeout` - How long a peer stays present without a heartbeat; send heartbeats well within it.
+  explicit CrdtPresence(CrdtNodeId node_id, Clock::duration timeout = std::chrono::seconds(30))
+      : node_id_(node_id), timeout_(timeout),
+        // Sequence numbers start at the wall-clock time so a restarted node's states replace its old ones
+        seq_(static_cast<uint64_t>(std::chrono::duration_cast<std::chrono::milliseconds>(
+                                       std::chrono::system_clock::now().time_since_epoch())
+                                       .count())) {}
+
+  /// Replaces this node's state and returns the frame to broadcast.
+  CrdtBytes set_local(CrdtMap<CrdtString, CrdtString> fields) {
+    local_ = std::move(fields);
+    return heartbeat();
+  }
+
+  /// Returns a frame re-announcing this node's state, to broadcast periodically.
+  CrdtBytes heartbeat() { return encode(false); }
+
+  /// Returns the frame announcing that this node leaves, e.g. when closing the document.
+  CrdtBytes leave() { return encode(true); }
+
+  const CrdtMap<CrdtString, CrdtString> &local() const { return local_; }
+
+  /// Handles a presence frame from a peer, or relayed by one.
+  ///
+  /// Throws CrdtDecodeError on malformed frames.
+  ///
+  /// # Returns
+  ///
+  /// True if the frame carried news (a newer state or a leave), in which case a relay should forward it.
+  bool on_frame(const CrdtBytes &frame, Clock::time_point now = Clock::now()) {
+    ByteReader reader(frame);
+    if (static_cast<SyncMessageType>(reader.read_u8()) != SyncMessageType::Presence) {
+      throw CrdtDecodeError("not a presence frame");
+    }
+    CrdtNodeId node_id = CrdtCodec<CrdtNodeId>::decode(reader);
+    uint64_t seq = reader.read_varint();
+    bool leaving = reader.read_u8() != 0;
+    uint64_t count = reader.read_varint();
+    if (count > reader.remaining()) {
+      throw CrdtDecodeError("presence field count exceeds input");
+    }
+    CrdtMap<CrdtString, CrdtString> fields;
+    for (uint64_t i = 0; i < count; ++i) {
+      CrdtString key = CrdtCodec<CrdtString>::decode(reader);
+      fields[std::move(key)] = CrdtCodec<CrdtString>::decode(reader);
+    }
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after presence");
+    }
+    if (node_id == node_id_) {
+      return false;
+    }
+
+    // A leave is remembered as the last seq, so a delayed older state does not bring the node back
+    uint64_t &last_seq = last_seq_[node_id];
+    if (seq <= last_seq) {
+      if (auto it = peers_.find(node_id); it != peers_.end() && seq == last_seq && !leaving) {
+        it->second.last_seen = now;
+      }
+      return false;
+    }
+    last_seq = seq;
+    if (leaving) {
+      bool was_present = peers_.erase(node_id) > 0;
+      if (was_present && observer_) {
+        observer_(node_id, nullptr);
+      }
+      return true;
+    }
+    PresenceState &state = peers_[node_id];
+    bool changed = state.seq == 0 || state.fields != fields;
+    state.seq = seq;
+    state.fields = std::move(fields);
+    state.last_seen = now;
+    if (changed && observer_) {
+      observer_(node_id, &state);
+    }
+    return true;
+  }
+
+  /// Drops the peers not heard from within the timeout.
+  ///
+  /// # Returns
+  ///
+  /// The ids of the dropped peers.
+  CrdtVector<CrdtNodeId> expire(Clock::time_point now = Clock::now()) {
+    CrdtVector<CrdtNodeId> expired;
+    for (auto it = peers_.begin(); it != peers_.end();) {
+      if (now - it->second.last_seen >= timeout_) {
+        expired.push_back(it->first);
+        it = peers_.erase(it);
+      } else {
+        ++it;
+      }
+    }
+    if (observer_) {
+      for (const CrdtNodeId &node_id : expired) {
+        observer_(node_id, nullptr);
+      }
+    }
+    return expired;
+  }
+
+  /// The peers currently present, excluding this node.
+  const CrdtMap<CrdtNodeId, PresenceState> &peers() const { return peers_; }
+
+  const PresenceState *get(const CrdtNodeId &node_id) const {
+    auto it = peers_.find(node_id);
+    return it != peers_.end() ? &it->second : nullptr;
+  }
+
+  /// The present peers whose `field` is `value`, e.g. everyone viewing a record.
+  CrdtVector<CrdtNodeId> who(const CrdtString &field, const CrdtString &value) const {
+    CrdtVector<CrdtNodeId> nodes;
+    for (const auto &[node_id, state] : peers_) {
+      auto it = state.fields.find(field);
+      if (it != state.fields.end() && it->second == value) {
+        nodes.push_back(node_id);
+      }
+    }
+    return nodes;
+  }
+
+  /// Sets the function called when a peer's presence changes, appears or goes away.
+  void set_observer(Observer observer) { observer_ = std::move(observer); }
+
+private:
+  CrdtNodeId node_id_;
+  Clock::duration timeout_;
+  uint64_t seq_;
+  CrdtMap<CrdtString, CrdtString> local_;
+  CrdtMap<CrdtNodeId, PresenceState> peers_;
+  CrdtMap<CrdtNodeId, uint64_t> last_seq_; // highest seq seen per node, kept after it leaves
+  Observer observer_;
+
+  CrdtBytes encode(bool leaving) {
+    CrdtBytes frame;
+    ByteWriter writer(frame);
+    writer.write_u8(static_cast<uint8_t>(SyncMessageType::Presence));
+    CrdtCodec<CrdtNodeId>::encode(writer, node_id_);
+    writer.write_varint(++seq_);
+    writer.write_u8(leaving ? 1 : 0);
+    writer.write_varint(leaving ? 0 : local_.size());
+    if (!leaving) {
+      for (const auto &[key, value] : local_) {
+        CrdtCodec<CrdtString>::encode(writer, key);
+        CrdtCodec<CrdtString>::encode(writer, value);
+      }
+    }
+    return frame;
+  }
+};
+
+#endif // CRDT_PRESENCE_HPP
@@ -0,0 +1,217 @@
+// crdt_quic.hpp
+#ifndef CRDT_QUIC_HPP
+#define CRDT_QUIC_HPP
+
+// QUIC transport for SyncSession, with one stream per table.
+//
+// QUIC suits mobile clients: a connection survives network changes (Wi-Fi to cellular) through connection migration,
+// TLS 1.3 is built in, and streams are independent, so a lost packet only stalls the stream it belongs to. To benefit
+// from the latter, a QuicSync syncs every table on its own bidirectional stream: a table is a namespace of a
+// CRDT<CrdtString, V> (see crdt_namespace.hpp), and a large backlog in one table does not hold up the others.
+//
+// Each stream carries the same length-prefixed frames as crdt_tcp.hpp. The connecting side opens a stream per table
+// and sends the table name as the first frame, followed by a regular SyncSession exchange; the accepting side creates
+// the table's session when the stream's Hello arrives, resuming from its cursor for that peer and table.
+//
+// This header does not implement QUIC itself. Like SyncSession, a QuicSync does no I/O: feed it the bytes your QUIC
+// library (msquic, ngtcp2, quiche, ...) delivers on each stream and write back the bytes it returns, from one thread
+// at a time.
+
+#include "crdt_namespace.hpp"
+#include "crdt_sync.hpp"
+
+#include <memory>
+
+namespace crdt_quic {
+
+/// Frames larger than this are rejected to bound memory use on hostile input.
+constexpr uint32_t DEFAULT_MAX_FRAME_SIZE = 64 * 1024 * 1024;
+
+/// Returns `frame` prefixed by its 4-byte big-endian length.
+inline CrdtBytes encode_frame(const CrdtBytes &frame) {
+  CrdtBytes bytes;
+  ByteWriter writer(bytes);
+  writer.write_u32_be(static_cast<uint32_t>(frame.size()));
+  bytes.insert(bytes.end(), frame.begin(), frame.end());
+  return bytes;
+}
+
+/// Reassembles length-prefixed frames from stream data delivered in arbitrary pieces.
+class FrameReader {
+public:
+  explicit FrameReader(uint32_t max_frame_size = DEFAULT_MAX_FRAME_SIZE) : max_frame_size_(max_frame_size) {}
+
+  void feed(const uint8_t *data, size_t size) { buffer_.insert(buffer_.end(), data, data + size); }
+
+  /// Returns the next complete frame, or std::nullopt if more data is needed.
+  ///
+  /// Throws CrdtDecodeError if the frame exceeds the maximum size.
+  std::optional<CrdtBytes> next() {
+    if (buffer_.size() - offset_ < 4) {
+      return std::nullopt;
+    }
+    uint32_t size = ByteReader(buffer_.data() + offset_, buffer_.data() + offset_ + 4).read_u32_be();
+    if (size > max_frame_size_) {
+      throw CrdtDecodeError("sync frame exceeds maximum size");
+    }
+    if (buffer_.size() - offset_ - 4 < size) {
+      return std::nullopt;
+    }
+    auto begin = buffer_.begin() + static_cast<std::ptrdiff_t>(offset_ + 4);
+    CrdtBytes frame(begin, begin + size);
+    offset_ += 4 + size;
+    if (offset_ == buffer_.size()) {
+      buffer_.clear();
+      offset_ = 0;
+    }
+    return frame;
+  }
+
+private:
+  uint32_t max_frame_size_;
+  CrdtBytes buffer_;
+  size_t offset_ = 0; // start of the first unread frame in buffer_
+};
+
+} // namespace crdt_quic
+
+/// The sync side of a QUIC connection: one SyncSession per stream, each on one table of a shared CRDT.
+template <typename CrdtType> class QuicSync {
+public:
+  using StreamId = uint64_t;
+  using Table = CrdtNamespace<CrdtType>;
+
+  /// # Arguments
+  ///
+  /// * `crdt` - The CRDT holding every table; it must outlive this object.
+  /// * `cursors` - The accepting side's cursors, per table and peer, updated as streams complete; it must outlive this
+  ///   object. The connecting side passes its cursor for each table to `open_stream` instead.
+  QuicSync(CrdtType &crdt, CrdtNodeId node_id, CrdtMap<CrdtString, CrdtMap<CrdtNodeId, uint64_t>> &cursors)
+      : crdt_(crdt), node_id_(node_id), cursors_(cursors) {}
+
+  /// Starts syncing `table` on a stream we opened.
+  ///
+  /// # Returns
+  ///
+  /// The bytes to write on the stream.
+  CrdtBytes open_stream(StreamId stream_id, const CrdtString &table, uint64_t last_received_version = 0) {
+    Stream &stream = streams_[stream_id];
+    stream.table = std::make_unique<Table>(crdt_, table);
+    stream.session = std::make_unique<SyncSession<Table>>(*stream.table, node_id_, last_received_version);
+    CrdtBytes header;
+    ByteWriter writer(header);
+    CrdtCodec<CrdtString>::encode(writer, table);
+    CrdtBytes out = crdt_quic::encode_frame(header);
+    append_frames(out, stream.session->start());
+    return out;
+  }
+
+  /// Handles data received on a stream, whether we opened it or the peer did.
+  ///
+  /// Throws CrdtDecodeError on malformed frames and SyncProtocolError on unexpected messages or table names.
+  ///
+  /// # Returns
+  ///
+  /// The bytes to write back on the stream.
+  CrdtBytes on_stream_data(StreamId stream_id, const uint8_t *data, size_t size) {
+    Stream &stream = streams_[stream_id];
+    stream.reader.feed(data, size);
+    CrdtBytes out;
+    while (std::optional<CrdtBytes> frame = stream.reader.next()) {
+      if (!stream.table) {
+        accept_table(stream, *frame);
+      } else if (!stream.session) {
+        accept_hello(stream, *frame, out);
+      } else {
+        append_frames(out, stream.session->on_frame(*frame));
+      }
+      if (is_complete(stream_id) && stream.accepted && !stream.cursor_saved) {
+        cursors_[stream.table->name()][*stream.session->peer_node_id()] = stream.session->last_received_version();
+        stream.cursor_saved = true;
+      }
+    }
+    return out;
+  }
+
+  /// True once the exchange on the stream completed; the stream can then be closed.
+  bool is_complete(StreamId stream_id) const {
+    auto it = streams_.find(stream_id);
+    return it != streams_.end() && it->second.session && it->second.session->is_complete();
+  }
+
+  /// Forgets a stream, e.g. once complete or when the peer reset it.
+  ///
+  /// # Returns
+  ///
+  /// The outcome of the stream's exchange, or std::nullopt if it never started.
+  std::optional<SyncResult> close_stream(StreamId stream_id) {
+    auto it = streams_.find(stream_id);
+    if (it == streams_.end()) {
+      return std::nullopt;
+    }
+    std::optional<SyncResult> result;
+    if (it->second.session) {
+      result = make_sync_result(*it->second.session, it->second.session->is_complete());
+    }
+    streams_.erase(it);
+    return result;
+  }
+
+  /// The table synced on a stream, or std::nullopt if its name has not arrived yet.
+  std::optional<CrdtString> table_of(StreamId stream_id) const {
+    auto it = streams_.find(stream_id);
+    return it != streams_.end() && it->second.table ? std::optional(it->second.table->name()) : std::nullopt;
+  }
+
+  size_t open_streams() const { return streams_.size(); }
+
+private:
+  struct Stream {
+    crdt_quic::FrameReader reader;
+    std::unique_ptr<Table> table;                // set once the table is known
+    std::unique_ptr<SyncSession<Table>> session; // set once started, or on the peer's Hello for accepted streams
+    bool accepted = false;                       // opened by the peer
+    bool cursor_saved = false;
+  };
+
+  CrdtType &crdt_;
+  CrdtNodeId node_id_;
+  CrdtMap<CrdtString, CrdtMap<CrdtNodeId, uint64_t>> &cursors_;
+  CrdtMap<StreamId, Stream> streams_;
+
+  static void append_frames(CrdtBytes &out, const CrdtVector<CrdtBytes> &frames) {
+    for (const CrdtBytes &frame : frames) {
+      CrdtBytes framed = crdt_quic::encode_frame(frame);
+      out.insert(out.end(), framed.begin(), framed.end());
+    }
+  }
+
+  // The first frame of a stream the peer opened names its table
+  void accept_table(Stream &stream, const CrdtBytes &frame) {
+    ByteReader reader(frame);
+    CrdtString table = CrdtCodec<CrdtString>::decode(reader);
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after table name");
+    }
+    try {
+      stream.table = std::make_unique<Table>(crdt_, std::move(table));
+    } catch (const std::invalid_argument &) {
+      throw SyncProtocolError("invalid table name");
+    }
+    stream.accepted = true;
+  }
+
+  // Answers the peer's Hello once its cursor for the table is known, as `accept_sync_session` does
+  void accept_hello(Stream &stream, const CrdtBytes &hello, CrdtBytes &out) {
+    std::optional<CrdtNodeId> peer = peek_hello_node_id(hello);
+    if (!peer) {
+      throw SyncProtocolError("expected hello");
+    }
+    stream.session = std::make_unique<SyncSession<Table>>(*stream.table, node_id_, cursors_[stream.table->name()][*peer]);
+    CrdtVector<CrdtBytes> replies = stream.session->on_frame(hello);
+    append_frames(out, stream.session->start());
+    append_frames(out, replies);
+  }
+};
+
+#endif // CRDT_QUIC_HPP
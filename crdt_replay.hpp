@@ -0,0 +1,251 @@
+// crdt_replay.hpp
+#ifndef CRDT_REPLAY_HPP
+#define CRDT_REPLAY_HPP
+
+// Recording and deterministic replay of a node's history, for debugging divergence reports from the field.
+//
+// A CrdtRecorder attached to a CRDT writes every batch of changes the CRDT applies (local operations and merges) to
+// a file, along with the batches it received, as passed to `CrdtRecorder::merge`, including the changes the merge
+// rejected. A CrdtReplayer reads the file back and rebuilds the node's state step by step, with the local db_versions
+// it had, so the state at any step can be inspected. `first_divergence` merges every recorded incoming batch again
+// into the state that preceded it and reports the first one whose outcome differs from the recorded one, which points
+// at nondeterministic merge rules, schema differences between builds, or state corrupted outside of the CRDT.
+//
+// File format: a sequence of `crdt_codec::record_header` records (a length and a checksum), each starting with a kind
+// byte. The first record holds the node id; every other one a varint change count and, per change, its local
+// db_version and its `crdt_codec::encode_change` bytes. A record cut short by a crash is ignored; one failing its
+// checksum fails the whole recording.
+
+#include "crdt_codec.hpp"
+
+#include <filesystem>
+#include <fstream>
+
+/// One step of a recorded history.
+template <typename K, typename V> struct CrdtRecordedStep {
+  enum class Kind : uint8_t {
+    Applied = 1,  // changes applied by a local operation or a merge
+    Received = 2, // a batch passed to `CrdtRecorder::merge`, always followed by the batch it applied, maybe empty
+  };
+
+  Kind kind;
+  CrdtVector<Change<K, V>> changes;
+};
+
+namespace crdt_replay {
+
+constexpr uint8_t START_RECORD = 0;
+constexpr uint32_t MAX_RECORD_SIZE = 256 * 1024 * 1024;
+
+inline void write_record(std::ofstream &out, const CrdtBytes &payload) {
+  CrdtBytes header = crdt_codec::record_header(payload);
+  out.write(reinterpret_cast<const char *>(header.data()), static_cast<std::streamsize>(header.size()));
+  out.write(reinterpret_cast<const char *>(payload.data()), static_cast<std::streamsize>(payload.size()));
+  out.flush();
+  if (!out) {
+    throw std::runtime_error("failed to write recording");
+  }
+}
+
+/// Returns the payloads of the complete records in `path`.
+///
+/// Throws std::runtime_error if the file cannot be read and CrdtDecodeError if a record is too large or corrupted.
+inline CrdtVector<CrdtBytes> read_records(const std::filesystem::path &path) {
+  std::ifstream in(path, std::ios::binary);
+  if (!in) {
+    throw std::runtime_error("failed to open recording");
+  }
+  CrdtVector<CrdtBytes> records;
+  while (std::optional<CrdtBytes> payload = crdt_codec::read_record(in, MAX_RECORD_SIZE)) {
+    records.push_back(std::move(*payload));
+  }
+  return records;
+}
+
+} // namespace crdt_replay
+
+/// Records the history of a CRDT to a file.
+template <typename CrdtType> class CrdtRecorder {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+  using Step = CrdtRecordedStep<K, V>;
+
+  /// Starts recording `crdt` to `path`, appending to an existing recording of the same node. Start recording on an
+  /// empty CRDT, or the replay lacks its earlier state. `crdt` must outlive the recorder.
+  ///
+  /// Throws std::runtime_error if the file cannot be written.
+  CrdtRecorder(CrdtType &crdt, CrdtNodeId node_id, const std::filesystem::path &path) : crdt_(crdt) {
+    bool fresh = !std::filesystem::exists(path) || std::filesystem::file_size(path) == 0;
+    out_.open(path, std::ios::binary | std::ios::app);
+    if (!out_) {
+      throw std::runtime_error("failed to open recording");
+    }
+    if (fresh) {
+      CrdtBytes payload;
+      ByteWriter writer(payload);
+      writer.write_u8(crdt_replay::START_RECORD);
+      CrdtCodec<CrdtNodeId>::encode(writer, node_id);
+      crdt_replay::write_record(out_, payload);
+    }
+    observer_ = crdt_.on_change_batch([this](const CrdtVector<Change<K, V>> &batch) {
+      applied_ = true;
+      write(Step::Kind::Applied, batch);
+    });
+  }
+
+  ~CrdtRecorder() { crdt_.remove_on_change(observer_); }
+
+  CrdtRecorder(const CrdtRecorder &) = delete;
+  CrdtRecorder &operator=(const CrdtRecorder &) = delete;
+
+  /// Records a batch received from a peer, then merges it into the CRDT. Merges done on the CRDT directly are recorded
+  /// too, but only with the changes they applied.
+  void merge(CrdtVector<Change<K, V>> changes) {
+    write(Step::Kind::Received, changes);
+    applied_ = false;
+    crdt_.merge_changes(std::move(changes));
+    if (!applied_) {
+      write(Step::Kind::Applied, {});
+    }
+  }
+
+private:
+  CrdtType &crdt_;
+  std::ofstream out_;
+  size_t observer_ = 0;
+  bool applied_ = false; // the observer ran during `merge`
+
+  void write(typename Step::Kind kind, const CrdtVector<Change<K, V>> &changes) {
+    CrdtBytes payload;
+    ByteWriter writer(payload);
+    writer.write_u8(static_cast<uint8_t>(kind));
+    crdt_codec::encode_stored_changes(writer, changes);
+    crdt_replay::write_record(out_, payload);
+  }
+};
+
+/// Replays a recording made by a CrdtRecorder.
+template <typename CrdtType> class CrdtReplayer {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+  using Step = CrdtRecordedStep<K, V>;
+
+  /// Loads a recording.
+  ///
+  /// Throws std::runtime_error if the file cannot be read and CrdtDecodeError if it is malformed.
+  explicit CrdtReplayer(const std::filesystem::path &path) {
+    CrdtVector<CrdtBytes> records = crdt_replay::read_records(path);
+    if (records.empty()) {
+      throw CrdtDecodeError("recording lacks its start record");
+    }
+    ByteReader start(records.front());
+    if (start.read_u8() != crdt_replay::START_RECORD) {
+      throw CrdtDecodeError("recording lacks its start record");
+    }
+    node_id_ = CrdtCodec<CrdtNodeId>::decode(start);
+    for (size_t i = 1; i < records.size(); ++i) {
+      steps_.push_back(decode_step(records[i]));
+    }
+    state_.emplace(node_id_);
+  }
+
+  CrdtNodeId node_id() const { return node_id_; }
+
+  /// The recorded steps, in order.
+  const CrdtVector<Step> &steps() const { return steps_; }
+
+  /// Number of steps replayed so far.
+  size_t position() const { return position_; }
+
+  /// Replays the next step.
+  ///
+  /// # Returns
+  ///
+  /// The step replayed, or nullptr at the end of the recording.
+  const Step *step() {
+    if (position_ == steps_.size()) {
+      return nullptr;
+    }
+    const Step &step = steps_[position_++];
+    if (step.kind == Step::Kind::Applied && !step.changes.empty()) {
+      applied_.insert(applied_.end(), step.changes.begin(), step.changes.end());
+      state_.reset();
+    }
+    return &step;
+  }
+
+  /// Replays the steps up to `position`, rewinding first if it is behind the current one.
+  void seek(size_t position) {
+    if (position < position_) {
+      position_ = 0;
+      applied_.clear();
+      state_.reset();
+    }
+    while (position_ < std::min(position, steps_.size())) {
+      step();
+    }
+  }
+
+  /// The node's state after the steps replayed so far, with the local db_versions the node had.
+  ///
+  /// Complexity: O(n) after a step that applied changes, where n is the number of changes applied so far
+  const CrdtType &state() {
+    if (!state_) {
+      state_.emplace(node_id_, CrdtVector<Change<K, V>>(applied_));
+    }
+    return *state_;
+  }
+
+  /// Merges every recorded incoming batch again into the state that preceded it.
+  ///
+  /// # Returns
+  ///
+  /// The index of the first Received step whose merge now applies other changes than the recorded Applied step that
+  /// follows it, or std::nullopt if the whole recording replays identically. The replay position is left unchanged.
+  std::optional<size_t> first_divergence() {
+    size_t position = position_;
+    std::optional<size_t> divergence;
+    seek(0);
+    while (const Step *current = step()) {
+      if (current->kind != Step::Kind::Received) {
+        continue;
+      }
+      CrdtType replica = state();
+      auto accepted = replica.template merge_changes<true>(CrdtVector<Change<K, V>>(current->changes));
+      const Step *recorded = position_ < steps_.size() ? &steps_[position_] : nullptr;
+      if (!recorded || recorded->kind != Step::Kind::Applied ||
+          crdt_codec::encode_changes(accepted) != crdt_codec::encode_changes(recorded->changes)) {
+        divergence = position_ - 1;
+        break;
+      }
+    }
+    seek(position);
+    return divergence;
+  }
+
+private:
+  CrdtNodeId node_id_;
+  CrdtVector<Step> steps_;
+  size_t position_ = 0;
+  CrdtVector<Change<K, V>> applied_; // every change applied by the steps replayed so far, in order
+  std::optional<CrdtType> state_;    // built from `applied_` when needed
+
+  static Step decode_step(const CrdtBytes &record) {
+    ByteReader reader(record);
+    Step step;
+    uint8_t kind = reader.read_u8();
+    if (kind != static_cast<uint8_t>(Step::Kind::Applied) && kind != static_cast<uint8_t>(Step::Kind::Received)) {
+      throw CrdtDecodeError("unknown recording record kind");
+    }
+    step.kind = static_cast<typename Step::Kind>(kind);
+    step.changes = crdt_codec::decode_stored_changes<K, V>(reader);
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after recording record");
+    }
+    return step;
+  }
+};
+
+#endif // CRDT_REPLAY_HPP
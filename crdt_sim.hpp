@@ -0,0 +1,233 @@
+// crdt_sim.hpp
+#ifndef CRDT_SIM_HPP
+#define CRDT_SIM_HPP
+
+// A deterministic network simulator, to reproduce convergence bugs from a seed.
+//
+// A CrdtSimulator runs several CRDT nodes in one process on a simulated clock counted in ticks. Nodes exchange their
+// changes with an anti-entropy protocol: every `sync_interval` ticks a node pushes the changes a random peer has not
+// acknowledged yet, and the peer merges them and acknowledges the version they cover. Every message goes through a
+// CrdtSimNetwork, which delays it by a random number of ticks (so messages are reordered whenever their delays
+// differ), drops it or delivers it twice. The workload is up to the test: modify the nodes between calls to `run`,
+// drawing from `random()` to keep it reproducible.
+//
+// Everything random is drawn from one generator seeded with `seed`, with its own range reduction instead of the
+// standard distributions (whose output differs between standard libraries), so a failing seed replays the same
+// schedule on every platform. A simulator runs on one thread.
+
+#include "crdt_codec.hpp"
+
+#include <map>
+#include <memory>
+#include <random>
+
+struct CrdtSimOptions {
+  uint64_t seed = 0;
+  uint64_t min_delay = 1;     // ticks a message takes at least
+  uint64_t max_delay = 10;    // ticks a message takes at most
+  double drop_rate = 0;       // probability that a message is lost
+  double duplicate_rate = 0;  // probability that a message is delivered a second time, with its own delay
+  uint64_t sync_interval = 5; // ticks between the anti-entropy rounds of a node
+};
+
+/// Delivers frames between simulated nodes with random delays, drops and duplicates.
+class CrdtSimNetwork {
+public:
+  struct Delivery {
+    CrdtNodeId from;
+    CrdtNodeId to;
+    CrdtBytes frame;
+  };
+
+  explicit CrdtSimNetwork(const CrdtSimOptions &options) : options_(options), rng_(options.seed) {}
+
+  /// Sends a frame, to be delivered after a random delay unless it is dropped.
+  void send(CrdtNodeId from, CrdtNodeId to, const CrdtBytes &frame) {
+    ++sent_;
+    if (chance(options_.drop_rate)) {
+      ++dropped_;
+      return;
+    }
+    schedule({from, to, frame});
+    if (chance(options_.duplicate_rate)) {
+      ++duplicated_;
+      schedule({from, to, frame});
+    }
+  }
+
+  /// Removes and returns the next message due at or before tick `now`, in delivery order.
+  std::optional<Delivery> deliver(uint64_t now) {
+    auto it = in_flight_.begin();
+    if (it == in_flight_.end() || it->first.first > now) {
+      return std::nullopt;
+    }
+    Delivery delivery = std::move(it->second);
+    in_flight_.erase(it);
+    ++delivered_;
+    return delivery;
+  }
+
+  /// Sets the current tick, from which delays are counted.
+  void set_now(uint64_t now) { now_ = now; }
+
+  /// A random number in [0, bound), or 0 if `bound` is 0.
+  uint64_t random(uint64_t bound) { return bound == 0 ? 0 : rng_() % bound; }
+
+  /// True with probability `probability`.
+  bool chance(double probability) {
+    return probability > 0 && static_cast<double>(rng_() >> 11) * 0x1.0p-53 < probability;
+  }
+
+  size_t in_flight() const { return in_flight_.size(); }
+  uint64_t sent() const { return sent_; }
+  uint64_t dropped() const { return dropped_; }
+  uint64_t duplicated() const { return duplicated_; }
+  uint64_t delivered() const { return delivered_; }
+
+private:
+  CrdtSimOptions options_;
+  std::mt19937_64 rng_;
+  uint64_t now_ = 0;
+  uint64_t sequence_ = 0; // breaks ties between messages due at the same tick
+  std::map<std::pair<uint64_t, uint64_t>, Delivery> in_flight_; // keyed by due tick and sequence
+  uint64_t sent_ = 0;
+  uint64_t dropped_ = 0;
+  uint64_t duplicated_ = 0;
+  uint64_t delivered_ = 0;
+
+  void schedule(Delivery delivery) {
+    uint64_t spread = options_.max_delay > options_.min_delay ? options_.max_delay - options_.min_delay + 1 : 1;
+    uint64_t due = now_ + options_.min_delay + random(spread);
+    in_flight_.emplace(std::make_pair(due, sequence_++), std::move(delivery));
+  }
+};
+
+/// Runs CRDT nodes syncing over a CrdtSimNetwork.
+template <typename CrdtType> class CrdtSimulator {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+
+  explicit CrdtSimulator(CrdtSimOptions options = {}) : options_(options), network_(options_) {}
+
+  /// Adds a node, which syncs with every other node from now on.
+  CrdtType &add_node(CrdtNodeId node_id) {
+    Node &node = nodes_[node_id];
+    node.crdt = std::make_unique<CrdtType>(node_id);
+    // Spread the rounds of the nodes over the interval
+    node.next_round = now_ + 1 + network_.random(std::max<uint64_t>(options_.sync_interval, 1));
+    return *node.crdt;
+  }
+
+  /// Throws std::out_of_range if there is no such node.
+  CrdtType &node(CrdtNodeId node_id) { return *nodes_.at(node_id).crdt; }
+
+  /// Runs `ticks` ticks: delivers the messages that are due and starts the rounds that are due, tick by tick.
+  void run(uint64_t ticks) {
+    for (uint64_t i = 0; i < ticks; ++i) {
+      step();
+    }
+  }
+
+  /// Runs until every node holds the same state, for at most `max_ticks` ticks.
+  ///
+  /// # Returns
+  ///
+  /// True if the nodes converged.
+  bool run_until_converged(uint64_t max_ticks) {
+    for (uint64_t i = 0; i < max_ticks && !converged(); ++i) {
+      step();
+    }
+    return converged();
+  }
+
+  /// True if every node holds the same records and tombstones.
+  bool converged() const {
+    std::optional<uint64_t> hash;
+    for (const auto &[node_id, node] : nodes_) {
+      uint64_t node_hash = node.crdt->state_hash();
+      if (hash && *hash != node_hash) {
+        return false;
+      }
+      hash = node_hash;
+    }
+    return true;
+  }
+
+  /// A random number in [0, bound) from the simulation's generator, for workloads that must replay with the seed.
+  uint64_t random(uint64_t bound) { return network_.random(bound); }
+
+  uint64_t now() const { return now_; }
+  uint64_t seed() const { return options_.seed; }
+  const CrdtSimNetwork &network() const { return network_; }
+
+private:
+  enum class MessageType : uint8_t {
+    Push = 1, // the sender's version covered and its changes
+    Ack = 2,  // the receiver's acknowledgement of a push
+  };
+
+  struct Node {
+    std::unique_ptr<CrdtType> crdt;
+    uint64_t next_round = 0;
+    std::map<CrdtNodeId, uint64_t> acked; // our version each peer acknowledged
+  };
+
+  CrdtSimOptions options_;
+  CrdtSimNetwork network_;
+  std::map<CrdtNodeId, Node> nodes_; // ordered, so rounds start in the same order on every run
+  uint64_t now_ = 0;
+
+  void step() {
+    ++now_;
+    network_.set_now(now_);
+    while (std::optional<CrdtSimNetwork::Delivery> delivery = network_.deliver(now_)) {
+      receive(*delivery);
+    }
+    for (auto &[node_id, node] : nodes_) {
+      if (node.next_round <= now_ && nodes_.size() > 1) {
+        node.next_round = now_ + std::max<uint64_t>(options_.sync_interval, 1);
+        push(node_id, node, pick_peer(node_id));
+      }
+    }
+  }
+
+  // Picks one of the other nodes
+  CrdtNodeId pick_peer(CrdtNodeId node_id) {
+    auto it = nodes_.begin();
+    std::advance(it, static_cast<std::ptrdiff_t>(network_.random(nodes_.size() - 1)));
+    if (!(it->first < node_id)) {
+      ++it; // from our position on, shift by one to skip ourselves
+    }
+    return it->first;
+  }
+
+  void push(CrdtNodeId from, Node &node, CrdtNodeId to) {
+    CrdtBytes frame;
+    ByteWriter writer(frame);
+    writer.write_u8(static_cast<uint8_t>(MessageType::Push));
+    writer.write_varint(node.crdt->get_clock().current_time());
+    crdt_codec::encode_changes(writer, node.crdt->get_change_refs_since(node.acked[to]));
+    network_.send(from, to, frame);
+  }
+
+  void receive(const CrdtSimNetwork::Delivery &delivery) {
+    Node &node = nodes_.at(delivery.to);
+    ByteReader reader(delivery.frame);
+    auto type = static_cast<MessageType>(reader.read_u8());
+    uint64_t version = reader.read_varint();
+    if (type == MessageType::Push) {
+      node.crdt->merge_changes(crdt_codec::decode_changes<K, V>(reader));
+      CrdtBytes ack;
+      ByteWriter writer(ack);
+      writer.write_u8(static_cast<uint8_t>(MessageType::Ack));
+      writer.write_varint(version);
+      network_.send(delivery.to, delivery.from, ack);
+    } else {
+      uint64_t &acked = node.acked[delivery.from];
+      acked = std::max(acked, version);
+    }
+  }
+};
+
+#endif // CRDT_SIM_HPP
@@ -0,0 +1,112 @@
+// crdt_sink.hpp
+#ifndef CRDT_SINK_HPP
+#define CRDT_SINK_HPP
+
+// Fans applied changes out to external consumers such as analytics pipelines or cache invalidation.
+//
+// A ChangeSink attached to a CRDT receives every batch of applied changes: one per local operation, and the accepted
+// changes of every merge. EncodedChangeSink encodes each batch with crdt_codec and hands the bytes to a publish
+// function, so a consumer can decode them with `crdt_codec::decode_changes` (and merge them into its own replica).
+//
+// Reference publishers for message brokers are opt-in: define CRDT_WITH_KAFKA and link librdkafka, and/or
+// CRDT_WITH_NATS and link the NATS C client (cnats).
+
+#include "crdt_codec.hpp"
+
+#include <functional>
+
+#ifdef CRDT_WITH_KAFKA
+#include <librdkafka/rdkafka.h>
+#endif
+#ifdef CRDT_WITH_NATS
+#include <nats/nats.h>
+#endif
+
+template <typename K, typename V> class ChangeSink {
+public:
+  virtual ~ChangeSink() = default;
+
+  /// Called with every applied batch of changes. Runs synchronously inside the CRDT operation: it must not modify the
+  /// CRDT, and should hand slow work off (e.g. to a broker's own send queue). Exceptions propagate to the caller of the
+  /// operation, after the changes were applied.
+  virtual void send(const CrdtVector<Change<K, V>> &batch) = 0;
+
+  /// Sends every batch applied to `crdt` to this sink. The sink must outlive the registration; the returned id can be
+  /// passed to `crdt.remove_on_change`.
+  template <typename CrdtType> size_t attach(CrdtType &crdt) {
+    return crdt.on_change_batch([this](const CrdtVector<Change<K, V>> &batch) { send(batch); });
+  }
+};
+
+/// Encodes each batch with crdt_codec and passes it to `publish`. Batches are sent as a single message, so a consumer
+/// sees either all or none of the changes of an operation.
+template <typename K, typename V> class EncodedChangeSink : public ChangeSink<K, V> {
+public:
+  using Publish = std::function<void(const CrdtBytes &message)>;
+
+  explicit EncodedChangeSink(Publish publish) : publish_(std::move(publish)) {}
+
+  void send(const CrdtVector<Change<K, V>> &batch) override { publish_(crdt_codec::encode_changes(batch)); }
+
+private:
+  Publish publish_;
+};
+
+#ifdef CRDT_WITH_KAFKA
+/// Produces the changes of each record in a batch to a Kafka topic as one encoded message keyed by the record id, so the
+/// changes of a record stay ordered within its partition. A batch touching several records becomes several messages,
+/// so unlike EncodedChangeSink a consumer may see part of an operation. The producer and topic stay owned by the caller,
+/// who also polls and flushes them.
+template <typename K, typename V> class KafkaChangeSink : public ChangeSink<K, V> {
+public:
+  explicit KafkaChangeSink(rd_kafka_topic_t *topic) : topic_(topic) {}
+
+  void send(const CrdtVector<Change<K, V>> &batch) override {
+    // Split the batch by record, keeping the order of each record's changes
+    CrdtVector<CrdtVector<Change<K, V>>> records;
+    CrdtMap<K, size_t> record_index;
+    for (const auto &change : batch) {
+      auto [it, inserted] = record_index.try_emplace(change.record_id, records.size());
+      if (inserted) {
+        records.emplace_back();
+      }
+      records[it->second].push_back(change);
+    }
+    for (const auto &changes : records) {
+      CrdtBytes message = crdt_codec::encode_changes(changes);
+      CrdtBytes key;
+      ByteWriter writer(key);
+      CrdtCodec<K>::encode(writer, changes.front().record_id);
+      if (rd_kafka_produce(topic_, RD_KAFKA_PARTITION_UA, RD_KAFKA_MSG_F_COPY, message.data(), message.size(), key.data(),
+                           key.size(), nullptr) != 0) {
+        throw std::runtime_error(CrdtString("kafka produce failed: ") + rd_kafka_err2str(rd_kafka_last_error()));
+      }
+    }
+  }
+
+private:
+  rd_kafka_topic_t *topic_;
+};
+#endif
+
+#ifdef CRDT_WITH_NATS
+/// Publishes each encoded batch to a NATS subject. The connection stays owned by the caller.
+template <typename K, typename V> class NatsChangeSink : public ChangeSink<K, V> {
+public:
+  NatsChangeSink(natsConnection *connection, CrdtString subject) : connection_(connection), subject_(std::move(subject)) {}
+
+  void send(const CrdtVector<Change<K, V>> &batch) override {
+    CrdtBytes message = crdt_codec::encode_changes(batch);
+    natsStatus status = natsConnection_Publish(connection_, subject_.c_str(), message.data(), static_cast<int>(message.size()));
+    if (status != NATS_OK) {
+      throw std::runtime_error(CrdtString("nats publish failed: ") + natsStatus_GetText(status));
+    }
+  }
+
+private:
+  natsConnection *connection_;
+  CrdtString subject_;
+};
+#endif
+
+#endif // CRDT_SINK_HPP
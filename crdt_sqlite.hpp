@@ -0,0 +1,423 @@
+// crdt_sqlite.hpp
+#ifndef CRDT_SQLITE_HPP
+#define CRDT_SQLITE_HPP
+
+// Mirrors a CRDT into an application-defined SQLite table, so SQL-centric code can read and write replicated data.
+//
+// The table must have a PRIMARY KEY (or UNIQUE) key column; every other column mirrors the CRDT column of the same
+// name, and CRDT columns without a table column are not mirrored. Link with -lsqlite3.
+//
+// * Every change to the CRDT (local or merged) is written to the table: column writes upsert the row, column
+//   deletions set the column to NULL and record deletions delete the row.
+// * Writes made to the table through SQL are recorded by TEMP triggers, and `capture()` applies them to the CRDT as
+//   local operations, ready to be sent to other nodes. Setting a column to NULL deletes the CRDT column.
+//
+// SqliteCursorStore keeps a GossipNode's sync cursors in the same database.
+
+#include "crdt.hpp"
+#include "crdt_sync.hpp"
+
+#include <sqlite3.h>
+
+#include <memory>
+#include <stdexcept>
+
+class SqliteError : public std::runtime_error {
+public:
+  SqliteError(sqlite3 *db, const CrdtString &context) : std::runtime_error(context + ": " + sqlite3_errmsg(db)) {}
+};
+
+/// Binds and reads keys and values of type T. Provided for CrdtString, integers and floating point numbers.
+template <typename T, typename Enable = void> struct SqliteValue;
+
+template <> struct SqliteValue<CrdtString> {
+  static int bind(sqlite3_stmt *stmt, int index, const CrdtString &value) {
+    return sqlite3_bind_text(stmt, index, value.data(), static_cast<int>(value.size()), SQLITE_TRANSIENT);
+  }
+  static CrdtString read(sqlite3_stmt *stmt, int column) {
+    const unsigned char *text = sqlite3_column_text(stmt, column);
+    return CrdtString(reinterpret_cast<const char *>(text), static_cast<size_t>(sqlite3_column_bytes(stmt, column)));
+  }
+};
+
+template <typename T> struct SqliteValue<T, std::enable_if_t<std::is_integral_v<T>>> {
+  static int bind(sqlite3_stmt *stmt, int index, T value) {
+    return sqlite3_bind_int64(stmt, index, static_cast<sqlite3_int64>(value));
+  }
+  static T read(sqlite3_stmt *stmt, int column) { return static_cast<T>(sqlite3_column_int64(stmt, column)); }
+};
+
+template <typename T> struct SqliteValue<T, std::enable_if_t<std::is_floating_point_v<T>>> {
+  static int bind(sqlite3_stmt *stmt, int index, T value) { return sqlite3_bind_double(stmt, index, value); }
+  static T read(sqlite3_stmt *stmt, int column) { return static_cast<T>(sqlite3_column_double(stmt, column)); }
+};
+
+template <typename CrdtType> class SqliteMirror {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+
+  /// Starts mirroring `crdt` into `table`. Call `rebuild()` to copy the CRDT's existing state into the table.
+  ///
+  /// Throws SqliteError if the table does not exist or the triggers cannot be created.
+  SqliteMirror(CrdtType &crdt, sqlite3 *db, CrdtString table, CrdtString key_column = "id")
+      : crdt_(crdt), db_(db), table_(std::move(table)), key_column_(std::move(key_column)) {
+    load_columns();
+    install_triggers();
+    observer_id_ = crdt_.on_change([this](const ChangeEvent<K, V> &event) { mirror(event); });
+  }
+
+  ~SqliteMirror() {
+    crdt_.remove_on_change(observer_id_);
+    for (const char *trigger : {"insert", "update", "delete"}) {
+      sqlite3_exec(db_, ("DROP TRIGGER IF EXISTS temp." + quote(table_ + "_crdt_" + trigger)).c_str(), nullptr, nullptr,
+                   nullptr);
+    }
+    sqlite3_exec(db_, ("DROP TABLE IF EXISTS temp." + log_table()).c_str(), nullptr, nullptr, nullptr);
+    sqlite3_exec(db_, ("DROP TABLE IF EXISTS temp." + control_table()).c_str(), nullptr, nullptr, nullptr);
+  }
+
+  SqliteMirror(const SqliteMirror &) = delete;
+  SqliteMirror &operator=(const SqliteMirror &) = delete;
+
+  /// Replaces the table's contents with the CRDT's live records. Pending SQL writes are discarded; call `capture()`
+  /// first to keep them.
+  void rebuild() {
+    Suppress suppress(*this);
+    exec("DELETE FROM " + log_table());
+    exec("DELETE FROM " + quote(table_));
+    crdt_.iter_live([&](const K &record_id, const Record<V> &record) {
+      for (const auto &[col_name, value] : record.fields) {
+        upsert(record_id, col_name, value);
+      }
+      if (record.fields.empty()) {
+        ensure_row(record_id);
+      }
+    });
+  }
+
+  /// Applies writes made to the table through SQL since the last call to the CRDT, as local operations.
+  ///
+  /// Tombstones are permanent: re-inserting a deleted key is ignored by the CRDT and the row is removed again.
+  ///
+  /// # Returns
+  ///
+  /// The changes made to the CRDT, to send to other nodes.
+  CrdtVector<Change<K, V>> capture() {
+    struct Entry {
+      K record_id;
+      std::optional<CrdtString> col_name;
+      std::optional<V> value;
+      int op;
+    };
+    CrdtVector<Entry> entries;
+    {
+      Statement stmt(*this, "SELECT key, col, val, op FROM " + log_table() + " ORDER BY seq");
+      while (stmt.step()) {
+        Entry entry{SqliteValue<K>::read(stmt.get(), 0), std::nullopt, std::nullopt, sqlite3_column_int(stmt.get(), 3)};
+        if (sqlite3_column_type(stmt.get(), 1) != SQLITE_NULL) {
+          entry.col_name = SqliteValue<CrdtString>::read(stmt.get(), 1);
+        }
+        if (sqlite3_column_type(stmt.get(), 2) != SQLITE_NULL) {
+          entry.value = SqliteValue<V>::read(stmt.get(), 2);
+        }
+        entries.push_back(std::move(entry));
+      }
+    }
+    exec("DELETE FROM " + log_table());
+
+    CrdtVector<Change<K, V>> changes;
+    auto append = [&](CrdtVector<Change<K, V>> &&more) {
+      changes.insert(changes.end(), std::make_move_iterator(more.begin()), std::make_move_iterator(more.end()));
+    };
+    // Consecutive column writes to the same row become a single insert_or_update
+    for (size_t i = 0; i < entries.size();) {
+      const Entry &entry = entries[i];
+      if (entry.op == OP_DELETE_RECORD) {
+        append(crdt_.delete_record(entry.record_id));
+        ++i;
+      } else if (entry.op == OP_DELETE_FIELD) {
+        append(crdt_.delete_field(entry.record_id, *entry.col_name));
+        ++i;
+      } else {
+        CrdtMap<CrdtString, V> fields;
+        size_t j = i;
+        for (; j < entries.size() && entries[j].op == OP_SET && entries[j].record_id == entry.record_id; ++j) {
+          fields.insert_or_assign(*entries[j].col_name, *entries[j].value);
+        }
+        append(crdt_.insert_or_update(entry.record_id, std::move(fields)));
+        i = j;
+      }
+    }
+    return changes;
+  }
+
+private:
+  static constexpr int OP_SET = 0;
+  static constexpr int OP_DELETE_FIELD = 1;
+  static constexpr int OP_DELETE_RECORD = 2;
+
+  class Statement {
+  public:
+    Statement(SqliteMirror &mirror, const CrdtString &sql) : db_(mirror.db_) {
+      if (sqlite3_prepare_v2(db_, sql.c_str(), -1, &stmt_, nullptr) != SQLITE_OK) {
+        throw SqliteError(db_, "prepare");
+      }
+    }
+    ~Statement() { sqlite3_finalize(stmt_); }
+    Statement(const Statement &) = delete;
+    Statement &operator=(const Statement &) = delete;
+
+    sqlite3_stmt *get() const { return stmt_; }
+
+    template <typename T> void bind(int index, const T &value) {
+      if (SqliteValue<T>::bind(stmt_, index, value) != SQLITE_OK) {
+        throw SqliteError(db_, "bind");
+      }
+    }
+
+    /// Returns true while rows are available.
+    bool step() {
+      int rc = sqlite3_step(stmt_);
+      if (rc != SQLITE_ROW && rc != SQLITE_DONE) {
+        throw SqliteError(db_, "step");
+      }
+      return rc == SQLITE_ROW;
+    }
+
+  private:
+    sqlite3 *db_;
+    sqlite3_stmt *stmt_ = nullptr;
+  };
+
+  // Disables the capture triggers while the mirror itself writes to the table
+  class Suppress {
+  public:
+    explicit Suppress(SqliteMirror &mirror) : mirror_(mirror) {
+      mirror_.exec("UPDATE " + mirror_.control_table() + " SET suppress = 1");
+    }
+    ~Suppress() {
+      sqlite3_exec(mirror_.db_, ("UPDATE " + mirror_.control_table() + " SET suppress = 0").c_str(), nullptr, nullptr,
+                   nullptr);
+    }
+
+  private:
+    SqliteMirror &mirror_;
+  };
+
+  static CrdtString quote(const CrdtString &identifier) {
+    CrdtString out = "\"";
+    for (char c : identifier) {
+      out += c;
+      if (c == '"') {
+        out += '"';
+      }
+    }
+    return out + "\"";
+  }
+
+  static CrdtString literal(const CrdtString &text) {
+    CrdtString out = "'";
+    for (char c : text) {
+      out += c;
+      if (c == '\'') {
+        out += '\'';
+      }
+    }
+    return out + "'";
+  }
+
+  CrdtString log_table() const { return quote(table_ + "_crdt_log"); }
+  CrdtString control_table() const { return quote(table_ + "_crdt_control"); }
+
+  void exec(const CrdtString &sql) {
+    if (sqlite3_exec(db_, sql.c_str(), nullptr, nullptr, nullptr) != SQLITE_OK) {
+      throw SqliteError(db_, sql);
+    }
+  }
+
+  void load_columns() {
+    Statement stmt(*this, "SELECT name FROM pragma_table_info(?)");
+    stmt.bind(1, table_);
+    bool has_key = false;
+    while (stmt.step()) {
+      CrdtString name = SqliteValue<CrdtString>::read(stmt.get(), 0);
+      if (name == key_column_) {
+        has_key = true;
+      } else {
+        columns_.push_back(std::move(name));
+      }
+    }
+    if (!has_key) {
+      throw std::invalid_argument("table '" + table_ + "' has no column '" + key_column_ + "'");
+    }
+  }
+
+  void install_triggers() {
+    exec("CREATE TEMP TABLE IF NOT EXISTS " + log_table() +
+         " (seq INTEGER PRIMARY KEY AUTOINCREMENT, key, col TEXT, val, op INTEGER NOT NULL)");
+    exec("CREATE TEMP TABLE IF NOT EXISTS " + control_table() + " (suppress INTEGER NOT NULL)");
+    exec("DELETE FROM " + control_table());
+    exec("INSERT INTO " + control_table() + " VALUES (0)");
+
+    const CrdtString log = log_table() + " (key, col, val, op) ";
+    const CrdtString when = " WHEN (SELECT suppress FROM " + control_table() + ") = 0 BEGIN ";
+    const CrdtString on = " ON main." + quote(table_);
+    const CrdtString key = quote(key_column_);
+
+    CrdtVector<CrdtString> inserted;
+    CrdtVector<CrdtString> updated{"SELECT OLD." + key + ", NULL, NULL, " + std::to_string(OP_DELETE_RECORD) +
+                                   " WHERE OLD." + key + " IS NOT NEW." + key};
+    for (const auto &column : columns_) {
+      CrdtString col = quote(column);
+      CrdtString name = literal(column);
+      inserted.push_back("SELECT NEW." + key + ", " + name + ", NEW." + col + ", " + std::to_string(OP_SET) +
+                         " WHERE NEW." + col + " IS NOT NULL");
+      updated.push_back("SELECT NEW." + key + ", " + name + ", NEW." + col + ", CASE WHEN NEW." + col +
+                        " IS NULL THEN " + std::to_string(OP_DELETE_FIELD) + " ELSE " + std::to_string(OP_SET) +
+                        " END WHERE OLD." + col + " IS NOT NEW." + col + " OR (OLD." + key + " IS NOT NEW." + key +
+                        " AND NEW." + col + " IS NOT NULL)");
+    }
+    auto join = [](const CrdtVector<CrdtString> &selects) {
+      CrdtString out;
+      for (const auto &select : selects) {
+        out += (out.empty() ? "" : " UNION ALL ") + select;
+      }
+      return out;
+    };
+    CrdtString on_insert = "INSERT INTO " + log + join(inserted);
+    CrdtString on_update = "INSERT INTO " + log + join(updated);
+    CrdtString on_delete = "INSERT INTO " + log + "VALUES (OLD." + key + ", NULL, NULL, " +
+                           std::to_string(OP_DELETE_RECORD) + ")";
+
+    if (!inserted.empty()) {
+      exec("CREATE TEMP TRIGGER IF NOT EXISTS " + quote(table_ + "_crdt_insert") + " AFTER INSERT" + on + when +
+           on_insert + "; END");
+    }
+    exec("CREATE TEMP TRIGGER IF NOT EXISTS " + quote(table_ + "_crdt_update") + " AFTER UPDATE" + on + when +
+         on_update + "; END");
+    exec("CREATE TEMP TRIGGER IF NOT EXISTS " + quote(table_ + "_crdt_delete") + " AFTER DELETE" + on + when +
+         on_delete + "; END");
+  }
+
+  bool is_mirrored(const CrdtString &col_name) const {
+    return std::find(columns_.begin(), columns_.end(), col_name) != columns_.end();
+  }
+
+  void ensure_row(const K &record_id) {
+    Statement stmt(*this, "INSERT OR IGNORE INTO " + quote(table_) + " (" + quote(key_column_) + ") VALUES (?)");
+    stmt.bind(1, record_id);
+    stmt.step();
+  }
+
+  void upsert(const K &record_id, const CrdtString &col_name, const V &value) {
+    if (!is_mirrored(col_name)) {
+      ensure_row(record_id);
+      return;
+    }
+    CrdtString col = quote(col_name);
+    Statement stmt(*this, "INSERT INTO " + quote(table_) + " (" + quote(key_column_) + ", " + col +
+                              ") VALUES (?, ?) ON CONFLICT(" + quote(key_column_) + ") DO UPDATE SET " + col +
+                              " = excluded." + col);
+    stmt.bind(1, record_id);
+    stmt.bind(2, value);
+    stmt.step();
+  }
+
+  void mirror(const ChangeEvent<K, V> &event) {
+    Suppress suppress(*this);
+    if (!event.col_name) {
+      Statement stmt(*this, "DELETE FROM " + quote(table_) + " WHERE " + quote(key_column_) + " = ?");
+      stmt.bind(1, event.record_id);
+      stmt.step();
+    } else if (event.new_value) {
+      upsert(event.record_id, *event.col_name, *event.new_value);
+    } else if (is_mirrored(*event.col_name)) {
+      Statement stmt(*this, "UPDATE " + quote(table_) + " SET " + quote(*event.col_name) + " = NULL WHERE " +
+                                quote(key_column_) + " = ?");
+      stmt.bind(1, event.record_id);
+      stmt.step();
+    }
+  }
+
+  CrdtType &crdt_;
+  sqlite3 *db_;
+  CrdtString table_;
+  CrdtString key_column_;
+  CrdtVector<CrdtString> columns_;
+  size_t observer_id_ = 0;
+};
+
+/// Stores sync cursors in a table of `db`, created if missing, keyed by the encoded peer node id. Each save is one
+/// transaction, so keep the mirrored table in the same database to save cursors consistently with the merged data.
+class SqliteCursorStore : public CrdtCursorStore {
+public:
+  /// Throws SqliteError if the table cannot be created.
+  explicit SqliteCursorStore(sqlite3 *db, CrdtString table = "crdt_sync_cursors") : db_(db), table_(std::move(table)) {
+    exec("CREATE TABLE IF NOT EXISTS \"" + table_ + "\" (peer BLOB PRIMARY KEY, received INTEGER, acked INTEGER)");
+  }
+
+  CrdtMap<CrdtNodeId, CrdtSyncCursor> load() override {
+    CrdtMap<CrdtNodeId, CrdtSyncCursor> cursors;
+    StatementPtr stmt = prepare("SELECT peer, received, acked FROM \"" + table_ + "\"");
+    int rc;
+    while ((rc = sqlite3_step(stmt.get())) == SQLITE_ROW) {
+      const auto *blob = static_cast<const uint8_t *>(sqlite3_column_blob(stmt.get(), 0));
+      CrdtBytes bytes(blob, blob + sqlite3_column_bytes(stmt.get(), 0));
+      ByteReader reader(bytes);
+      CrdtSyncCursor &cursor = cursors[CrdtCodec<CrdtNodeId>::decode(reader)];
+      cursor.received = static_cast<uint64_t>(sqlite3_column_int64(stmt.get(), 1));
+      cursor.acked = static_cast<uint64_t>(sqlite3_column_int64(stmt.get(), 2));
+    }
+    if (rc != SQLITE_DONE) {
+      throw SqliteError(db_, "load cursors");
+    }
+    return cursors;
+  }
+
+  void save(const CrdtMap<CrdtNodeId, CrdtSyncCursor> &cursors) override {
+    exec("BEGIN");
+    try {
+      exec("DELETE FROM \"" + table_ + "\"");
+      StatementPtr stmt = prepare("INSERT INTO \"" + table_ + "\" (peer, received, acked) VALUES (?, ?, ?)");
+      for (const auto &[peer, cursor] : cursors) {
+        CrdtBytes bytes;
+        ByteWriter writer(bytes);
+        CrdtCodec<CrdtNodeId>::encode(writer, peer);
+        sqlite3_bind_blob(stmt.get(), 1, bytes.data(), static_cast<int>(bytes.size()), SQLITE_TRANSIENT);
+        sqlite3_bind_int64(stmt.get(), 2, static_cast<sqlite3_int64>(cursor.received));
+        sqlite3_bind_int64(stmt.get(), 3, static_cast<sqlite3_int64>(cursor.acked));
+        if (sqlite3_step(stmt.get()) != SQLITE_DONE) {
+          throw SqliteError(db_, "save cursors");
+        }
+        sqlite3_reset(stmt.get());
+      }
+      exec("COMMIT");
+    } catch (...) {
+      sqlite3_exec(db_, "ROLLBACK", nullptr, nullptr, nullptr);
+      throw;
+    }
+  }
+
+private:
+  using StatementPtr = std::unique_ptr<sqlite3_stmt, decltype(&sqlite3_finalize)>;
+
+  sqlite3 *db_;
+  CrdtString table_;
+
+  void exec(const CrdtString &sql) {
+    if (sqlite3_exec(db_, sql.c_str(), nullptr, nullptr, nullptr) != SQLITE_OK) {
+      throw SqliteError(db_, sql);
+    }
+  }
+
+  StatementPtr prepare(const CrdtString &sql) {
+    sqlite3_stmt *stmt = nullptr;
+    if (sqlite3_prepare_v2(db_, sql.c_str(), -1, &stmt, nullptr) != SQLITE_OK) {
+      throw SqliteError(db_, "prepare");
+    }
+    return StatementPtr(stmt, &sqlite3_finalize);
+  }
+};
+
+#endif // CRDT_SQLITE_HPP
@@ -0,0 +1,563 @@
+// crdt_sync.hpp
+#ifndef CRDT_SYNC_HPP
+#define CRDT_SYNC_HPP
+
+#include "crdt_codec.hpp"
+
+#include <filesystem>
+#include <fstream>
+
+/// Version of the sync message schema, sent in the handshake. Two sessions speak the lower of their versions.
+///
+/// 3: Hello carries capability flags, and later versions may append fields to it.
+constexpr uint64_t CRDT_SYNC_PROTOCOL_VERSION = 3;
+
+/// The oldest protocol version a session still speaks.
+constexpr uint64_t CRDT_SYNC_MIN_PROTOCOL_VERSION = 2;
+
+/// Optional features announced in the Hello (from protocol version 3). A session uses a feature only if both sides
+/// announce it. Bits from 32 up are free for applications, see `SyncSession::set_capabilities`.
+enum SyncCapability : uint64_t {
+  SyncFramedValues = 1 << 0, // values are length-prefixed, so changes with value types a peer lacks can be skipped
+};
+
+/// The capabilities this build announces.
+constexpr uint64_t CRDT_SYNC_CAPABILITIES = SyncFramedValues;
+
+enum class SyncMessageType : uint8_t {
+  Hello = 1,       // protocol version, node id, the peer version we already have, compression codecs, capabilities
+  Changes = 2,     // a batch of changes plus the sender's version they cover
+  Ack = 3,         // acknowledges a Changes message up to a version
+  Presence = 4,    // ephemeral presence state (see crdt_presence.hpp), ignored by SyncSession
+  BlobRequest = 5, // hashes of blob chunks wanted (see crdt_blob.hpp), ignored by SyncSession
+  BlobChunks = 6,  // blob chunks, ignored by SyncSession
+};
+
+/// Thrown when the peer violates the sync protocol.
+class SyncProtocolError : public std::runtime_error {
+public:
+  explicit SyncProtocolError(const char *message) : std::runtime_error(message) {}
+};
+
+/// Keeps the changes a node received but could not decode, e.g. values of a type added by a newer app version, and
+/// hands them back to SyncSession so they are passed on to other peers instead of lost. Attach one store to every
+/// session of a CRDT with `SyncSession::set_opaque_store`.
+///
+/// Only the latest change per column is kept, and it is dropped once the CRDT holds a newer write to that column or
+/// the record was deleted since. The CRDT itself keeps its previous value meanwhile, so readers on this node see the
+/// last value they understand.
+template <typename K> class CrdtOpaqueStore {
+public:
+  /// Stores `change`, received when the CRDT's clock was at `local_version`, unless a newer one is stored.
+  void add(CrdtOpaqueChange<K> change, uint64_t local_version) {
+    Entry &entry = columns_[change.record_id][change.col_name];
+    if (entry.local_version != 0 &&
+        std::tie(entry.change.col_version, entry.change.db_version, entry.change.node_id) >=
+            std::tie(change.col_version, change.db_version, change.node_id)) {
+      return;
+    }
+    entry = {std::move(change), local_version};
+  }
+
+  /// Drops the changes `crdt` has superseded.
+  ///
+  /// Complexity: O(n), where n is the number of stored changes
+  template <typename CrdtType> void prune(const CrdtType &crdt) {
+    for (auto record = columns_.begin(); record != columns_.end();) {
+      std::erase_if(record->second, [&](const auto &column) {
+        const CrdtOpaqueChange<K> &change = column.second.change;
+        return crdt.is_applied(Change<K, typename CrdtType::ValueType>(
+            change.record_id, change.col_name, std::nullopt, change.col_version, change.db_version, change.node_id));
+      });
+      record = record->second.empty() ? columns_.erase(record) : std::next(record);
+    }
+  }
+
+  /// The stored changes received after `local_version`.
+  CrdtVector<const CrdtOpaqueChange<K> *> since(uint64_t local_version) const {
+    CrdtVector<const CrdtOpaqueChange<K> *> changes;
+    for (const auto &[record_id, columns] : columns_) {
+      for (const auto &[col_name, entry] : columns) {
+        if (entry.local_version > local_version) {
+          changes.push_back(&entry.change);
+        }
+      }
+    }
+    return changes;
+  }
+
+  size_t size() const {
+    size_t size = 0;
+    for (const auto &[record_id, columns] : columns_) {
+      size += columns.size();
+    }
+    return size;
+  }
+
+  /// Encodes the store, to persist it next to the CRDT.
+  CrdtBytes save() const {
+    CrdtBytes bytes;
+    ByteWriter writer(bytes);
+    writer.write_varint(size());
+    for (const auto &[record_id, columns] : columns_) {
+      for (const auto &[col_name, entry] : columns) {
+        CrdtCodec<K>::encode(writer, record_id);
+        CrdtCodec<CrdtString>::encode(writer, col_name);
+        writer.write_varint(entry.change.col_version);
+        writer.write_varint(entry.change.db_version);
+        CrdtCodec<CrdtNodeId>::encode(writer, entry.change.node_id);
+        writer.write_varint(entry.local_version);
+        CrdtCodec<CrdtBytes>::encode(writer, entry.change.encoded);
+      }
+    }
+    return bytes;
+  }
+
+  /// Restores a store encoded with `save`.
+  ///
+  /// Throws CrdtDecodeError on malformed input.
+  static CrdtOpaqueStore load(const CrdtBytes &bytes) {
+    ByteReader reader(bytes);
+    uint64_t count = reader.read_varint();
+    if (count > reader.remaining()) {
+      throw CrdtDecodeError("opaque change count exceeds input");
+    }
+    CrdtOpaqueStore store;
+    for (uint64_t i = 0; i < count; ++i) {
+      CrdtOpaqueChange<K> change;
+      change.record_id = CrdtCodec<K>::decode(reader);
+      change.col_name = CrdtCodec<CrdtString>::decode(reader);
+      change.col_version = reader.read_varint();
+      change.db_version = reader.read_varint();
+      change.node_id = CrdtCodec<CrdtNodeId>::decode(reader);
+      uint64_t local_version = reader.read_varint();
+      change.encoded = CrdtCodec<CrdtBytes>::decode(reader);
+      store.add(std::move(change), local_version);
+    }
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after opaque changes");
+    }
+    return store;
+  }
+
+private:
+  struct Entry {
+    CrdtOpaqueChange<K> change;
+    uint64_t local_version = 0; // the CRDT's clock when the change was received, plus one
+  };
+
+  CrdtMap<K, CrdtMap<CrdtString, Entry>> columns_;
+};
+
+/// One side of a change exchange between two CRDT nodes.
+///
+/// The session does no I/O itself: feed it the frames received from the peer and send the frames it returns, so it can be
+/// driven by a blocking socket, an event loop, or an in-memory queue. A full exchange is:
+///
+/// 1. Both sides send Hello with the last peer version they merged (`last_received_version`) and the compression
+///    codecs they support.
+/// 2. On Hello, each side sends the changes the peer is missing, tagged with its current version and compressed with the
+///    best codec both sides support.
+/// 3. On Changes, each side merges them and replies with Ack.
+///
+/// The session is complete once the peer's changes are merged and our changes are acknowledged. Persist
+/// `last_received_version()` per peer and pass it to the next session to only receive new changes.
+///
+/// Peers may run different versions, e.g. during a staged rollout. A session reads Hellos from any version since
+/// CRDT_SYNC_MIN_PROTOCOL_VERSION and ignores the fields a later version appends, and once the peer's Hello arrived it
+/// speaks the lower of both versions; a `start()` called after that answers in that version, which is how
+/// `accept_sync_session` keeps serving older clients. Upgrade servers before clients: an older node may reject the
+/// Hello of a newer connecting node. Changes whose values the receiver cannot decode (e.g. a value type added by a
+/// newer version) are skipped rather than failing the batch, when both sides announce SyncFramedValues; with a
+/// CrdtOpaqueStore they are kept and sent on to the next peers.
+template <typename CrdtType> class SyncSession {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+
+  SyncSession(CrdtType &crdt, CrdtNodeId node_id, uint64_t last_received_version = 0)
+      : crdt_(crdt), node_id_(node_id), last_received_version_(last_received_version) {}
+
+  /// Returns the frames to send when the connection opens, or in answer to the peer's Hello.
+  CrdtVector<CrdtBytes> start() {
+    CrdtBytes frame;
+    ByteWriter writer(frame);
+    writer.write_u8(static_cast<uint8_t>(SyncMessageType::Hello));
+    writer.write_varint(protocol_version_);
+    CrdtCodec<CrdtNodeId>::encode(writer, node_id_);
+    writer.write_varint(last_received_version_);
+    writer.write_varint(compression_mask_);
+    if (protocol_version_ >= 3) {
+      writer.write_varint(capabilities_);
+    }
+    return {std::move(frame)};
+  }
+
+  /// Restricts the compression codecs offered to the peer (a mask of `crdt_codec::compression_bit`). Call before `start()`.
+  void set_compression_mask(uint64_t mask) {
+    compression_mask_ = (mask & crdt_codec::supported_compression()) | crdt_codec::compression_bit(CrdtCompression::None);
+  }
+
+  /// The codec used for the changes we send, chosen once the peer's Hello arrives.
+  CrdtCompression compression() const { return compression_; }
+
+  /// Replaces the capabilities announced to the peer (a mask of SyncCapability, plus application bits). Call before
+  /// `start()`.
+  void set_capabilities(uint64_t capabilities) { capabilities_ = capabilities; }
+
+  /// Keeps the changes whose values cannot be decoded in `store` and sends the stored ones to the peer along with our
+  /// changes. `store` must outlive the session. Call before the peer's Hello arrives.
+  void set_opaque_store(CrdtOpaqueStore<K> &store) { opaque_store_ = &store; }
+
+  /// The protocol version spoken: ours until the peer's Hello arrives, then the lower of both.
+  uint64_t protocol_version() const { return protocol_version_; }
+
+  /// The capabilities announced by the peer, 0 for peers older than protocol version 3.
+  uint64_t peer_capabilities() const { return peer_capabilities_; }
+
+  /// True if both sides announced `capability`.
+  bool shares_capability(uint64_t capability) const {
+    return (capabilities_ & peer_capabilities_ & capability) == capability;
+  }
+
+  /// Handles one frame from the peer and returns the frames to send in response.
+  ///
+  /// Throws CrdtDecodeError on malformed frames and SyncProtocolError on unexpected messages.
+  CrdtVector<CrdtBytes> on_frame(const CrdtBytes &frame) {
+    ByteReader reader(frame);
+    auto type = static_cast<SyncMessageType>(reader.read_u8());
+    switch (type) {
+    case SyncMessageType::Hello:
+      return on_hello(reader);
+    case SyncMessageType::Changes:
+      return on_changes(reader);
+    case SyncMessageType::Ack:
+      on_ack(reader);
+      return {};
+    case SyncMessageType::Presence:
+    case SyncMessageType::BlobRequest:
+    case SyncMessageType::BlobChunks:
+      return {};
+    }
+    throw SyncProtocolError("unknown sync message type");
+  }
+
+  /// True once the peer's changes have been merged and ours have been acknowledged.
+  bool is_complete() const { return received_changes_ && acked_; }
+
+  /// The peer's version covered by the changes merged so far; persist this as the cursor for the next session.
+  uint64_t last_received_version() const { return last_received_version_; }
+
+  /// Our version the peer has acknowledged.
+  uint64_t peer_acked_version() const { return peer_acked_version_; }
+
+  std::optional<CrdtNodeId> peer_node_id() const { return peer_node_id_; }
+
+  /// Number of changes merged from the peer during this session.
+  size_t changes_received() const { return changes_received_; }
+
+  /// Number of changes sent to the peer during this session.
+  size_t changes_sent() const { return changes_sent_; }
+
+  /// Number of changes from the peer whose values could not be decoded, skipped or kept in the opaque store.
+  size_t changes_skipped() const { return changes_skipped_; }
+
+private:
+  CrdtType &crdt_;
+  CrdtNodeId node_id_;
+  uint64_t last_received_version_;
+  uint64_t peer_acked_version_ = 0;
+  uint64_t sent_up_to_ = 0;
+  std::optional<CrdtNodeId> peer_node_id_;
+  uint64_t compression_mask_ = crdt_codec::supported_compression();
+  CrdtCompression compression_ = CrdtCompression::None;
+  uint64_t protocol_version_ = CRDT_SYNC_PROTOCOL_VERSION;
+  uint64_t capabilities_ = CRDT_SYNC_CAPABILITIES;
+  uint64_t peer_capabilities_ = 0;
+  CrdtOpaqueStore<K> *opaque_store_ = nullptr;
+  bool received_changes_ = false;
+  bool acked_ = false;
+  size_t changes_received_ = 0;
+  size_t changes_sent_ = 0;
+  size_t changes_skipped_ = 0;
+
+  CrdtVector<CrdtBytes> on_hello(ByteReader &reader) {
+    CrdtTraceSpan span("sync.hello");
+    if (peer_node_id_) {
+      throw SyncProtocolError("duplicate hello");
+    }
+    uint64_t protocol_version = reader.read_varint();
+    if (protocol_version < CRDT_SYNC_MIN_PROTOCOL_VERSION) {
+      throw SyncProtocolError("unsupported sync protocol version");
+    }
+    protocol_version_ = std::min(protocol_version_, protocol_version);
+    peer_node_id_ = CrdtCodec<CrdtNodeId>::decode(reader);
+    uint64_t peer_has = reader.read_varint();
+    compression_ = crdt_codec::choose_compression(reader.read_varint() & compression_mask_);
+    if (protocol_version >= 3) {
+      peer_capabilities_ = reader.read_varint();
+    }
+    // Fields appended by later protocol versions are left unread
+
+    // Capture the version before extracting so nothing written afterwards is skipped by the peer's next cursor
+    sent_up_to_ = crdt_.get_clock().current_time();
+    // Borrowed, so values are encoded without being copied first
+    auto changes = crdt_.get_change_refs_since(peer_has);
+    // Changes the peer wrote itself are already there (or superseded), so don't echo them back
+    std::erase_if(changes, [&](const ChangeRef<K, V> &change) { return change.node_id == *peer_node_id_; });
+    bool framed = shares_capability(SyncFramedValues);
+    // Stored undecodable changes are passed on as received; only peers that can skip them get them
+    CrdtVector<const CrdtOpaqueChange<K> *> opaque;
+    if (opaque_store_ && framed) {
+      opaque_store_->prune(crdt_);
+      opaque = opaque_store_->since(peer_has);
+      std::erase_if(opaque, [&](const CrdtOpaqueChange<K> *change) { return change->node_id == *peer_node_id_; });
+    }
+    changes_sent_ += changes.size() + opaque.size();
+    span.event.changes = changes.size() + opaque.size();
+    span.event.version = peer_has;
+
+    CrdtBytes batch;
+    ByteWriter batch_writer(batch);
+    batch_writer.write_varint(changes.size() + opaque.size());
+    for (const auto &change : changes) {
+      crdt_codec::encode_change(batch_writer, change, framed);
+    }
+    for (const CrdtOpaqueChange<K> *change : opaque) {
+      batch.insert(batch.end(), change->encoded.begin(), change->encoded.end());
+    }
+    CrdtBytes frame;
+    ByteWriter writer(frame);
+    writer.write_u8(static_cast<uint8_t>(SyncMessageType::Changes));
+    writer.write_varint(sent_up_to_);
+    crdt_codec::encode_batch_compressed(writer, std::move(batch), compression_);
+    return {std::move(frame)};
+  }
+
+  CrdtVector<CrdtBytes> on_changes(ByteReader &reader) {
+    if (!peer_node_id_) {
+      throw SyncProtocolError("changes before hello");
+    }
+    CrdtTraceSpan span("sync.changes");
+    uint64_t up_to = reader.read_varint();
+    CrdtVector<CrdtOpaqueChange<K>> undecodable;
+    auto changes = crdt_codec::decode_changes_compressed<K, V>(reader, &undecodable);
+    changes_received_ += changes.size();
+    changes_skipped_ += undecodable.size();
+    span.event.changes = changes.size();
+    span.event.version = up_to;
+    crdt_.merge_changes(std::move(changes));
+    if (opaque_store_) {
+      for (CrdtOpaqueChange<K> &change : undecodable) {
+        opaque_store_->add(std::move(change), crdt_.get_clock().current_time() + 1);
+      }
+    }
+    last_received_version_ = std::max(last_received_version_, up_to);
+    received_changes_ = true;
+
+    CrdtBytes frame;
+    ByteWriter writer(frame);
+    writer.write_u8(static_cast<uint8_t>(SyncMessageType::Ack));
+    writer.write_varint(up_to);
+    return {std::move(frame)};
+  }
+
+  void on_ack(ByteReader &reader) {
+    CrdtTraceSpan span("sync.ack");
+    uint64_t version = reader.read_varint();
+    span.event.version = version;
+    peer_acked_version_ = std::max(peer_acked_version_, version);
+    if (version >= sent_up_to_) {
+      acked_ = true;
+    }
+  }
+};
+
+/// Outcome of a sync exchange over a transport.
+struct SyncResult {
+  bool completed = false;
+  std::optional<CrdtNodeId> peer_node_id;
+  uint64_t last_received_version = 0; // persist this per peer and pass it to the next sync
+  uint64_t peer_acked_version = 0;    // our version the peer acknowledged
+  size_t changes_received = 0;
+  size_t changes_sent = 0;
+  size_t changes_skipped = 0; // changes from the peer whose values could not be decoded
+};
+
+template <typename CrdtType> SyncResult make_sync_result(const SyncSession<CrdtType> &session, bool completed) {
+  SyncResult result;
+  result.completed = completed;
+  result.peer_node_id = session.peer_node_id();
+  result.last_received_version = session.last_received_version();
+  result.peer_acked_version = session.peer_acked_version();
+  result.changes_skipped = session.changes_skipped();
+  result.changes_received = session.changes_received();
+  result.changes_sent = session.changes_sent();
+  return result;
+}
+
+/// The sync cursors kept for one peer.
+struct CrdtSyncCursor {
+  uint64_t received = 0; // the peer's version we merged up to (`last_received_version`)
+  uint64_t acked = 0;    // our version the peer acknowledged (`peer_acked_version`)
+
+  bool operator==(const CrdtSyncCursor &) const = default;
+};
+
+/// Persists sync cursors across restarts.
+///
+/// A received cursor must never be stored before the changes it covers are durable, or a crash in between makes the
+/// next sync skip them. Store cursors next to the CRDT's own storage and save them after it, e.g. after a merge has
+/// been appended to a CrdtChangeLog (which happens before the session completes).
+class CrdtCursorStore {
+public:
+  virtual ~CrdtCursorStore() = default;
+
+  /// The stored cursors, empty if none were saved yet.
+  virtual CrdtMap<CrdtNodeId, CrdtSyncCursor> load() = 0;
+
+  /// Replaces the stored cursors. Must be atomic: after a crash, `load` returns either the old or the new cursors.
+  virtual void save(const CrdtMap<CrdtNodeId, CrdtSyncCursor> &cursors) = 0;
+};
+
+/// Stores cursors in a file, replaced atomically by writing a temporary file and renaming it over the old one.
+class FileCursorStore : public CrdtCursorStore {
+public:
+  explicit FileCursorStore(std::filesystem::path path) : path_(std::move(path)) {}
+
+  /// Throws CrdtDecodeError if the file is malformed.
+  CrdtMap<CrdtNodeId, CrdtSyncCursor> load() override {
+    CrdtMap<CrdtNodeId, CrdtSyncCursor> cursors;
+    std::ifstream in(path_, std::ios::binary);
+    if (!in) {
+      return cursors;
+    }
+    CrdtBytes bytes((std::istreambuf_iterator<char>(in)), std::istreambuf_iterator<char>());
+    ByteReader reader(bytes);
+    uint64_t count = reader.read_varint();
+    if (count > reader.remaining()) {
+      throw CrdtDecodeError("cursor count exceeds input");
+    }
+    for (uint64_t i = 0; i < count; ++i) {
+      CrdtNodeId peer = CrdtCodec<CrdtNodeId>::decode(reader);
+      CrdtSyncCursor &cursor = cursors[peer];
+      cursor.received = reader.read_varint();
+      cursor.acked = reader.read_varint();
+    }
+    if (!reader.at_end()) {
+      throw CrdtDecodeError("trailing bytes after cursors");
+    }
+    return cursors;
+  }
+
+  void save(const CrdtMap<CrdtNodeId, CrdtSyncCursor> &cursors) override {
+    CrdtBytes bytes;
+    ByteWriter writer(bytes);
+    writer.write_varint(cursors.size());
+    for (const auto &[peer, cursor] : cursors) {
+      CrdtCodec<CrdtNodeId>::encode(writer, peer);
+      writer.write_varint(cursor.received);
+      writer.write_varint(cursor.acked);
+    }
+    std::filesystem::path temporary = path_;
+    temporary += ".tmp";
+    {
+      std::ofstream out(temporary, std::ios::binary | std::ios::trunc);
+      out.write(reinterpret_cast<const char *>(bytes.data()), static_cast<std::streamsize>(bytes.size()));
+      out.flush();
+      if (!out) {
+        throw std::runtime_error("failed to write sync cursors");
+      }
+    }
+    crdt_fault_check(CrdtFaultPoint::StorageWrite, 0, "injected storage write failure");
+    std::filesystem::rename(temporary, path_);
+  }
+
+private:
+  std::filesystem::path path_;
+};
+
+/// Returns the sender's node id if `frame` is a Hello, so a server can look up that peer's cursor before starting its
+/// session.
+inline std::optional<CrdtNodeId> peek_hello_node_id(const CrdtBytes &frame) {
+  try {
+    ByteReader reader(frame);
+    if (static_cast<SyncMessageType>(reader.read_u8()) != SyncMessageType::Hello) {
+      return std::nullopt;
+    }
+    reader.read_varint(); // protocol version
+    return CrdtCodec<CrdtNodeId>::decode(reader);
+  } catch (const CrdtDecodeError &) {
+    return std::nullopt;
+  }
+}
+
+/// A message-oriented transport that delivers whole frames.
+template <typename T>
+concept SyncTransport = requires(T transport, const CrdtBytes &frame) {
+  transport.send(frame);
+  { transport.receive() } -> std::convertible_to<std::optional<CrdtBytes>>;
+};
+
+/// Handles incoming frames until the session completes.
+///
+/// # Returns
+///
+/// True if the exchange completed, false if the transport closed early (`receive()` returned std::nullopt).
+template <typename CrdtType, SyncTransport Transport>
+bool continue_sync_session(SyncSession<CrdtType> &session, Transport &transport) {
+  while (!session.is_complete()) {
+    std::optional<CrdtBytes> frame = transport.receive();
+    if (!frame) {
+      return false;
+    }
+    for (auto &reply : session.on_frame(*frame)) {
+      transport.send(reply);
+    }
+  }
+  return true;
+}
+
+/// Runs a session to completion over a blocking transport, as the connecting side.
+///
+/// # Returns
+///
+/// True if the exchange completed, false if the transport closed early (`receive()` returned std::nullopt).
+template <typename CrdtType, SyncTransport Transport> bool run_sync_session(SyncSession<CrdtType> &session, Transport &transport) {
+  for (auto &frame : session.start()) {
+    transport.send(frame);
+  }
+  return continue_sync_session(session, transport);
+}
+
+/// Runs one exchange as the accepting side, resuming from and updating the per-peer cursor in `cursors`.
+///
+/// Waits for the peer's Hello before sending ours, so the right cursor can be chosen for that peer and ours is in the
+/// peer's protocol version if it is older.
+template <typename CrdtType, SyncTransport Transport>
+SyncResult accept_sync_session(Transport &transport, CrdtType &crdt, CrdtNodeId node_id, CrdtMap<CrdtNodeId, uint64_t> &cursors) {
+  std::optional<CrdtBytes> hello = transport.receive();
+  if (!hello) {
+    return {};
+  }
+  std::optional<CrdtNodeId> peer = peek_hello_node_id(*hello);
+  if (!peer) {
+    throw SyncProtocolError("expected hello");
+  }
+
+  SyncSession<CrdtType> session(crdt, node_id, cursors[*peer]);
+  CrdtVector<CrdtBytes> replies = session.on_frame(*hello);
+  for (auto &frame : session.start()) {
+    transport.send(frame);
+  }
+  for (auto &frame : replies) {
+    transport.send(frame);
+  }
+  bool completed = continue_sync_session(session, transport);
+  if (completed) {
+    cursors[*peer] = session.last_received_version();
+  }
+  return make_sync_result(session, completed);
+}
+
+#endif // CRDT_SYNC_HPP
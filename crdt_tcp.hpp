@@ -0,0 +1,221 @@
+// crdt_tcp.hpp
+#ifndef CRDT_TCP_HPP
+#define CRDT_TCP_HPP
+
+// Reference TCP transport for SyncSession (POSIX sockets).
+//
+// Wire format: every sync message is sent as a frame of a 4-byte big-endian length followed by the message bytes.
+// The client connects and both sides run a SyncSession: Hello (protocol version, node id, cursor), Changes, Ack.
+
+#include "crdt_sync.hpp"
+
+#include <cerrno>
+#include <system_error>
+
+#include <netdb.h>
+#include <netinet/in.h>
+#include <netinet/tcp.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+namespace crdt_tcp {
+
+/// Frames larger than this are rejected to bound memory use on hostile input.
+constexpr uint32_t DEFAULT_MAX_FRAME_SIZE = 64 * 1024 * 1024;
+
+/// A connected socket exchanging length-prefixed frames. Satisfies SyncTransport.
+class TcpTransport {
+public:
+  explicit TcpTransport(int fd, uint32_t max_frame_size = DEFAULT_MAX_FRAME_SIZE) : fd_(fd), max_frame_size_(max_frame_size) {}
+  TcpTransport(TcpTransport &&other) noexcept : fd_(other.fd_), max_frame_size_(other.max_frame_size_) { other.fd_ = -1; }
+  TcpTransport(const TcpTransport &) = delete;
+  TcpTransport &operator=(const TcpTransport &) = delete;
+  ~TcpTransport() { close(); }
+
+  void send(const CrdtBytes &frame) {
+    if (frame.size() > max_frame_size_) {
+      throw std::length_error("sync frame exceeds maximum size");
+    }
+    CrdtBytes header;
+    ByteWriter(header).write_u32_be(static_cast<uint32_t>(frame.size()));
+    write_all(header.data(), header.size());
+    write_all(frame.data(), frame.size());
+  }
+
+  /// Returns the next frame, or std::nullopt if the peer closed the connection.
+  std::optional<CrdtBytes> receive() {
+    uint8_t header[4];
+    if (!read_all(header, sizeof(header))) {
+      return std::nullopt;
+    }
+    uint32_t size = ByteReader(header, header + sizeof(header)).read_u32_be();
+    if (size > max_frame_size_) {
+      throw CrdtDecodeError("sync frame exceeds maximum size");
+    }
+    CrdtBytes frame(size);
+    if (!read_all(frame.data(), size)) {
+      return std::nullopt;
+    }
+    return frame;
+  }
+
+  void close() {
+    if (fd_ >= 0) {
+      ::close(fd_);
+      fd_ = -1;
+    }
+  }
+
+private:
+  int fd_;
+  uint32_t max_frame_size_;
+
+  void write_all(const uint8_t *data, size_t size) {
+    while (size > 0) {
+      ssize_t written = ::send(fd_, data, size, MSG_NOSIGNAL);
+      if (written < 0) {
+        if (errno == EINTR) {
+          continue;
+        }
+        throw std::system_error(errno, std::generic_category(), "send");
+      }
+      data += written;
+      size -= static_cast<size_t>(written);
+    }
+  }
+
+  bool read_all(uint8_t *data, size_t size) {
+    while (size > 0) {
+      ssize_t received = ::recv(fd_, data, size, 0);
+      if (received == 0) {
+        return false;
+      }
+      if (received < 0) {
+        if (errno == EINTR) {
+          continue;
+        }
+        throw std::system_error(errno, std::generic_category(), "recv");
+      }
+      data += received;
+      size -= static_cast<size_t>(received);
+    }
+    return true;
+  }
+};
+
+/// A listening socket accepting sync connections.
+class TcpListener {
+public:
+  /// Binds to `port` on all interfaces; port 0 picks a free port (see `port()`).
+  explicit TcpListener(uint16_t port) {
+    fd_ = ::socket(AF_INET6, SOCK_STREAM, 0);
+    if (fd_ < 0) {
+      throw std::system_error(errno, std::generic_category(), "socket");
+    }
+    int enable = 1;
+    ::setsockopt(fd_, SOL_SOCKET, SO_REUSEADDR, &enable, sizeof(enable));
+    int disable = 0;
+    ::setsockopt(fd_, IPPROTO_IPV6, IPV6_V6ONLY, &disable, sizeof(disable));
+
+    sockaddr_in6 addr{};
+    addr.sin6_family = AF_INET6;
+    addr.sin6_addr = in6addr_any;
+    addr.sin6_port = htons(port);
+    if (::bind(fd_, reinterpret_cast<sockaddr *>(&addr), sizeof(addr)) < 0 || ::listen(fd_, SOMAXCONN) < 0) {
+      int error = errno;
+      ::close(fd_);
+      throw std::system_error(error, std::generic_category(), "bind/listen");
+    }
+  }
+  TcpListener(const TcpListener &) = delete;
+  TcpListener &operator=(const TcpListener &) = delete;
+  ~TcpListener() { ::close(fd_); }
+
+  uint16_t port() const {
+    sockaddr_in6 addr{};
+    socklen_t len = sizeof(addr);
+    ::getsockname(fd_, reinterpret_cast<sockaddr *>(&addr), &len);
+    return ntohs(addr.sin6_port);
+  }
+
+  TcpTransport accept() {
+    while (true) {
+      int fd = ::accept(fd_, nullptr, nullptr);
+      if (fd >= 0) {
+        int enable = 1;
+        ::setsockopt(fd, IPPROTO_TCP, TCP_NODELAY, &enable, sizeof(enable));
+        return TcpTransport(fd);
+      }
+      if (errno != EINTR) {
+        throw std::system_error(errno, std::generic_category(), "accept");
+      }
+    }
+  }
+
+private:
+  int fd_;
+};
+
+/// Opens a TCP connection to `host:port`.
+inline TcpTransport open_connection(const CrdtString &host, uint16_t port) {
+  addrinfo hints{};
+  hints.ai_family = AF_UNSPEC;
+  hints.ai_socktype = SOCK_STREAM;
+  addrinfo *results = nullptr;
+  CrdtString service = std::to_string(port);
+  if (int status = ::getaddrinfo(host.c_str(), service.c_str(), &hints, &results); status != 0) {
+    throw std::runtime_error(CrdtString("getaddrinfo: ") + ::gai_strerror(status));
+  }
+
+  int error = 0;
+  for (addrinfo *ai = results; ai != nullptr; ai = ai->ai_next) {
+    int fd = ::socket(ai->ai_family, ai->ai_socktype, ai->ai_protocol);
+    if (fd < 0) {
+      error = errno;
+      continue;
+    }
+    if (::connect(fd, ai->ai_addr, ai->ai_addrlen) == 0) {
+      ::freeaddrinfo(results);
+      int enable = 1;
+      ::setsockopt(fd, IPPROTO_TCP, TCP_NODELAY, &enable, sizeof(enable));
+      return TcpTransport(fd);
+    }
+    error = errno;
+    ::close(fd);
+  }
+  ::freeaddrinfo(results);
+  throw std::system_error(error, std::generic_category(), "connect");
+}
+
+/// Connects to a sync server and runs one exchange.
+///
+/// # Arguments
+///
+/// * `last_received_version` - The cursor returned by the previous sync with this server, or 0.
+template <typename CrdtType>
+SyncResult connect(const CrdtString &host, uint16_t port, CrdtType &crdt, CrdtNodeId node_id, uint64_t last_received_version = 0) {
+  TcpTransport transport = open_connection(host, port);
+  SyncSession<CrdtType> session(crdt, node_id, last_received_version);
+  bool completed = run_sync_session(session, transport);
+  return make_sync_result(session, completed);
+}
+
+/// Accepts one connection and runs one exchange, resuming from and updating the per-peer cursor in `cursors`.
+template <typename CrdtType>
+SyncResult serve_one(TcpListener &listener, CrdtType &crdt, CrdtNodeId node_id, CrdtMap<CrdtNodeId, uint64_t> &cursors) {
+  TcpTransport transport = listener.accept();
+  return accept_sync_session(transport, crdt, node_id, cursors);
+}
+
+/// Serves sync connections one at a time until `max_connections` have been handled.
+template <typename CrdtType>
+void serve(TcpListener &listener, CrdtType &crdt, CrdtNodeId node_id, CrdtMap<CrdtNodeId, uint64_t> &cursors,
+           size_t max_connections = SIZE_MAX) {
+  for (size_t handled = 0; handled < max_connections; ++handled) {
+    serve_one(listener, crdt, node_id, cursors);
+  }
+}
+
+} // namespace crdt_tcp
+
+#endif // CRDT_TCP_HPP
@@ -0,0 +1,149 @@
+// crdt_undo.hpp
+#ifndef CRDT_UNDO_HPP
+#define CRDT_UNDO_HPP
+
+// Undo/redo for local edits.
+//
+// Perform local edits through an UndoManager instead of directly on the CRDT. Each edit records the column values it
+// replaced; `undo()` writes those values back as new local changes (with fresh versions), so the revert replicates to
+// other nodes like any other edit and wins over the change it undoes.
+
+#include "crdt.hpp"
+
+#include <deque>
+
+template <typename CrdtType> class UndoManager {
+public:
+  using K = typename CrdtType::KeyType;
+  using V = typename CrdtType::ValueType;
+
+  /// # Arguments
+  ///
+  /// * `max_depth` - Number of edits kept on the undo stack; older ones are forgotten.
+  explicit UndoManager(CrdtType &crdt, size_t max_depth = 100) : crdt_(crdt), max_depth_(max_depth) {}
+
+  /// Same as `CRDT::insert_or_update`, recording the edit for undo.
+  CrdtVector<Change<K, V>> insert_or_update(const K &record_id, CrdtMap<CrdtString, V> &&fields) {
+    Edit edit{record_id, {}, {}, false};
+    for (const auto &[col_name, value] : fields) {
+      edit.before.emplace(col_name, current_value(record_id, col_name));
+      edit.after.emplace(col_name, value);
+    }
+    auto changes = crdt_.insert_or_update(record_id, std::move(fields));
+    if (!changes.empty()) {
+      push(std::move(edit));
+    }
+    return changes;
+  }
+
+  /// Same as `CRDT::delete_field`, recording the edit for undo.
+  CrdtVector<Change<K, V>> delete_field(const K &record_id, const CrdtString &col_name) {
+    Edit edit{record_id, {}, {}, false};
+    edit.before.emplace(col_name, current_value(record_id, col_name));
+    edit.after.emplace(col_name, std::nullopt);
+    auto changes = crdt_.delete_field(record_id, col_name);
+    if (!changes.empty()) {
+      push(std::move(edit));
+    }
+    return changes;
+  }
+
+  /// Same as `CRDT::delete_record`. A deletion is recorded as a barrier that `undo()` stops at; the record can still be
+  /// re-created with `CRDT::revive_record`.
+  CrdtVector<Change<K, V>> delete_record(const K &record_id) {
+    auto changes = crdt_.delete_record(record_id);
+    if (!changes.empty()) {
+      push(Edit{record_id, {}, {}, true});
+    }
+    return changes;
+  }
+
+  /// True if the most recent edit can be undone.
+  bool can_undo() const { return !undo_.empty() && !undo_.back().is_delete; }
+
+  bool can_redo() const { return !redo_.empty(); }
+
+  /// Reverts the most recent edit.
+  ///
+  /// # Returns
+  ///
+  /// The new changes to replicate, or an empty vector if there is nothing to undo (or the record was deleted since).
+  CrdtVector<Change<K, V>> undo() {
+    if (!can_undo()) {
+      return {};
+    }
+    Edit edit = std::move(undo_.back());
+    undo_.pop_back();
+    auto changes = write(edit.record_id, edit.before);
+    redo_.push_back(std::move(edit));
+    return changes;
+  }
+
+  /// Re-applies the most recently undone edit.
+  ///
+  /// # Returns
+  ///
+  /// The new changes to replicate, or an empty vector if there is nothing to redo.
+  CrdtVector<Change<K, V>> redo() {
+    if (redo_.empty()) {
+      return {};
+    }
+    Edit edit = std::move(redo_.back());
+    redo_.pop_back();
+    auto changes = write(edit.record_id, edit.after);
+    undo_.push_back(std::move(edit));
+    return changes;
+  }
+
+  /// Forgets all recorded edits.
+  void clear() {
+    undo_.clear();
+    redo_.clear();
+  }
+
+private:
+  struct Edit {
+    K record_id;
+    CrdtMap<CrdtString, std::optional<V>> before; // std::nullopt: the column was absent
+    CrdtMap<CrdtString, std::optional<V>> after;
+    bool is_delete;
+  };
+
+  CrdtType &crdt_;
+  size_t max_depth_;
+  std::deque<Edit> undo_;
+  CrdtVector<Edit> redo_;
+
+  std::optional<V> current_value(const K &record_id, const CrdtString &col_name) const {
+    const V *value = static_cast<const CrdtType &>(crdt_).get_value(record_id, col_name);
+    return value ? std::optional<V>(*value) : std::nullopt;
+  }
+
+  void push(Edit edit) {
+    redo_.clear();
+    undo_.push_back(std::move(edit));
+    if (undo_.size() > max_depth_) {
+      undo_.pop_front();
+    }
+  }
+
+  CrdtVector<Change<K, V>> write(const K &record_id, const CrdtMap<CrdtString, std::optional<V>> &values) {
+    CrdtVector<Change<K, V>> changes;
+    CrdtMap<CrdtString, V> fields;
+    for (const auto &[col_name, value] : values) {
+      if (value) {
+        fields.emplace(col_name, *value);
+      } else {
+        auto removed = crdt_.delete_field(record_id, col_name);
+        changes.insert(changes.end(), removed.begin(), removed.end());
+      }
+    }
+    if (!fields.empty()) {
+      auto written = crdt_.insert_or_update(record_id, std::move(fields));
+      changes.insert(changes.end(), written.begin(), written.end());
+    }
+    return changes;
+  }
+};
+
+#endif // CRDT_UNDO_HPP
@@ -0,0 +1,60 @@
+// crdt_websocket.hpp
+#ifndef CRDT_WEBSOCKET_HPP
+#define CRDT_WEBSOCKET_HPP
+
+// WebSocket transport for SyncSession.
+//
+// Uses the same message schema as crdt_tcp.hpp, but since WebSocket is already message-oriented every sync message is
+// sent as exactly one binary WebSocket message, without the 4-byte length prefix. A browser client (e.g. compiled with
+// Emscripten) and a native server can therefore sync the same CRDT.
+//
+// This header does not implement the WebSocket protocol itself; wrap the connection type of your WebSocket library
+// (Boost.Beast, websocketpp, emscripten/websocket.h, ...) so it satisfies WebSocketConnection.
+
+#include "crdt_sync.hpp"
+
+/// A WebSocket connection that sends and receives whole binary messages.
+/// `receive_binary()` blocks until a message arrives and returns std::nullopt once the connection is closed.
+template <typename T>
+concept WebSocketConnection = requires(T connection, const CrdtBytes &message) {
+  connection.send_binary(message);
+  { connection.receive_binary() } -> std::convertible_to<std::optional<CrdtBytes>>;
+};
+
+/// Adapts a WebSocketConnection to SyncTransport, one sync frame per binary message.
+template <WebSocketConnection Connection> class WebSocketTransport {
+public:
+  explicit WebSocketTransport(Connection &connection) : connection_(connection) {}
+
+  void send(const CrdtBytes &frame) { connection_.send_binary(frame); }
+  std::optional<CrdtBytes> receive() { return connection_.receive_binary(); }
+
+private:
+  Connection &connection_;
+};
+
+namespace crdt_websocket {
+
+/// Runs one exchange as the connecting side.
+///
+/// # Arguments
+///
+/// * `last_received_version` - The cursor returned by the previous sync with this peer, or 0.
+template <typename CrdtType, WebSocketConnection Connection>
+SyncResult connect(Connection &connection, CrdtType &crdt, CrdtNodeId node_id, uint64_t last_received_version = 0) {
+  WebSocketTransport<Connection> transport(connection);
+  SyncSession<CrdtType> session(crdt, node_id, last_received_version);
+  bool completed = run_sync_session(session, transport);
+  return make_sync_result(session, completed);
+}
+
+/// Runs one exchange as the accepting side, resuming from and updating the per-peer cursor in `cursors`.
+template <typename CrdtType, WebSocketConnection Connection>
+SyncResult serve_one(Connection &connection, CrdtType &crdt, CrdtNodeId node_id, CrdtMap<CrdtNodeId, uint64_t> &cursors) {
+  WebSocketTransport<Connection> transport(connection);
+  return accept_sync_session(transport, crdt, node_id, cursors);
+}
+
+} // namespace crdt_websocket
+
+#endif // CRDT_WEBSOCKET_HPP
@@ -0,0 +1,140 @@
+// crypto_tests.cpp
+#include "crdt_crypto.hpp"
+
+#include <cstdlib>
+#include <iostream>
+#include <sstream>
+#include <string>
+
+/// Simple assertion helper
+void assert_true(bool condition, const CrdtString &message) {
+  if (!condition) {
+    std::cerr << "Assertion failed: " << message << std::endl;
+    exit(1);
+  }
+}
+
+int main() {
+  // Test Case: Relay Encrypted Changes
+  {
+    CrdtKey key = crdt_crypto::random_key();
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    CRDT<CrdtString, CrdtBytes> relay(100);
+    node1.insert_or_update("doc", {{"title", "secret plans"}, {"body", "from node1"}});
+    node2.insert_or_update("doc", {{"body", "from node2"}});
+    node2.insert_or_update("gone", {{"title", "x"}});
+    node2.delete_record("gone");
+
+    // The relay resolves the conflict on "body" without the key
+    relay.merge_changes(crdt_crypto::encrypt_changes(node1.get_changes_since(0), key));
+    relay.merge_changes(crdt_crypto::encrypt_changes(node2.get_changes_since(0), key));
+    auto forwarded = relay.get_changes_since(0);
+    for (const auto &change : forwarded) {
+      if (change.value) {
+        CrdtString stored(change.value->begin(), change.value->end());
+        assert_true(stored.find("secret") == CrdtString::npos, "Encryption: The relay should not see plaintext");
+      }
+    }
+
+    CRDT<CrdtString, CrdtString> node3(3);
+    node3.merge_changes(crdt_crypto::decrypt_changes<CrdtString, CrdtString>(forwarded, key));
+    node1.merge_changes(node2.get_changes_since(0));
+    assert_true(node3.state_hash() == node1.state_hash(), "Encryption: Relayed changes should converge");
+    assert_true(*node3.get_value("doc", "body") == "from node2", "Encryption: The relay should order changes");
+    std::cout << "Test 'Relay Encrypted Changes' passed." << std::endl;
+  }
+
+  // Test Case: Tampered and Misdirected Values
+  {
+    CrdtKey key_a = crdt_crypto::random_key();
+    CrdtKey key_b = crdt_crypto::random_key();
+    CRDT<CrdtString, CrdtString> node(1);
+    node.insert_or_update("a", {{"role", "admin"}});
+    node.insert_or_update("b", {{"role", "guest"}});
+    auto key_for = [&](const CrdtString &record_id) -> const CrdtKey & { return record_id == "a" ? key_a : key_b; };
+    auto encrypted = crdt_crypto::encrypt_changes(node.get_changes_since(0), key_for);
+    auto decrypted = crdt_crypto::decrypt_changes<CrdtString, CrdtString>(encrypted, key_for);
+    assert_true(decrypted.size() == 2 && *decrypted[0].value != *decrypted[1].value,
+                "Encryption: Per-record keys should round-trip");
+
+    auto expect_rejected = [&](CrdtVector<Change<CrdtString, CrdtBytes>> changes, const CrdtString &message) {
+      bool rejected = false;
+      try {
+        crdt_crypto::decrypt_changes<CrdtString, CrdtString>(changes, key_for);
+      } catch (const CrdtDecodeError &) {
+        rejected = true;
+      }
+      assert_true(rejected, message);
+    };
+
+    auto swapped = encrypted;
+    std::swap(swapped[0].value, swapped[1].value);
+    expect_rejected(swapped, "Encryption: Values moved between records should be rejected");
+    auto bumped = encrypted;
+    ++bumped[0].col_version;
+    expect_rejected(bumped, "Encryption: Values with altered versions should be rejected");
+    auto flipped = encrypted;
+    flipped[0].value->back() ^= 1;
+    expect_rejected(flipped, "Encryption: Modified ciphertext should be rejected");
+    std::cout << "Test 'Tampered and Misdirected Values' passed." << std::endl;
+  }
+
+  // Test Case: Encrypted Snapshots
+  {
+    using Crdt = CRDT<CrdtString, CrdtString>;
+    Crdt node(1);
+    for (int i = 0; i < 2500; ++i) {
+      node.insert_or_update("r" + std::to_string(i), {{"v", "value " + std::to_string(i)}});
+    }
+    node.delete_record("r7");
+
+    CrdtKey key = crdt_crypto::random_key();
+    std::stringstream with_key;
+    crdt_crypto::save_encrypted(with_key, node, key);
+    CrdtString saved = with_key.str();
+    assert_true(saved.find("value 1") == CrdtString::npos, "Snapshot: Values should not be stored in the clear");
+    Crdt loaded = crdt_crypto::load_encrypted<Crdt>(with_key, 1, key);
+    assert_true(loaded.state_hash() == node.state_hash() && loaded.is_tombstoned("r7"), "Snapshot: Key round trip");
+
+    std::stringstream with_password;
+    crdt_crypto::save_encrypted(with_password, node, "correct horse");
+    CrdtString protected_snapshot = with_password.str();
+    std::stringstream reread(protected_snapshot);
+    assert_true(crdt_crypto::load_encrypted<Crdt>(reread, 1, "correct horse").state_hash() == node.state_hash(),
+                "Snapshot: Password round trip");
+
+    auto expect_rejected = [&](const CrdtString &bytes, const auto &secret, const CrdtString &message) {
+      std::stringstream in(bytes);
+      bool rejected = false;
+      try {
+        crdt_crypto::load_encrypted<Crdt>(in, 1, secret);
+      } catch (const CrdtDecodeError &) {
+        rejected = true;
+      }
+      assert_true(rejected, message);
+    };
+    expect_rejected(protected_snapshot, std::string_view("wrong"), "Snapshot: Wrong passwords should be rejected");
+    expect_rejected(saved, crdt_crypto::random_key(), "Snapshot: Wrong keys should be rejected");
+    expect_rejected(saved.substr(0, saved.size() / 2), key, "Snapshot: Truncated snapshots should be rejected");
+    CrdtString tampered = saved;
+    tampered[tampered.size() / 2] ^= 1;
+    expect_rejected(tampered, key, "Snapshot: Modified snapshots should be rejected");
+
+    // Snapshots of exactly whole frames, and of nothing, still end in a last frame
+    for (size_t records : {size_t{0}, crdt_crypto::CHANGES_PER_FRAME * 2}) {
+      Crdt exact(2);
+      for (size_t i = 0; i < records; ++i) {
+        exact.insert_or_update("r" + std::to_string(i), {{"v", "x"}});
+      }
+      std::stringstream stream;
+      crdt_crypto::save_encrypted(stream, exact, key);
+      assert_true(crdt_crypto::load_encrypted<Crdt>(stream, 2, key).state_hash() == exact.state_hash(),
+                  "Snapshot: Whole-frame and empty snapshots should round trip");
+    }
+    std::cout << "Test 'Encrypted Snapshots' passed." << std::endl;
+  }
+
+  std::cout << "All crypto tests passed successfully!" << std::endl;
+  return 0;
+}
@@ -0,0 +1,133 @@
+// fault_tests.cpp
+#define CRDT_FAULT_INJECTION
+#include "crdt.hpp"
+#include "crdt_hub.hpp"
+#include "crdt_log.hpp"
+#include "crdt_sync.hpp"
+
+#include <cstdlib>
+#include <iostream>
+#include <string>
+
+/// Simple assertion helper
+void assert_true(bool condition, const CrdtString &message) {
+  if (!condition) {
+    std::cerr << "Assertion failed: " << message << std::endl;
+    exit(1);
+  }
+}
+
+/// Injects `point` the `nth` time it is reached (1-based), `value` being the hook's result.
+void inject_once(CrdtFaultPoint point, uint64_t nth, uint64_t value = 1) {
+  auto reached = std::make_shared<uint64_t>(0);
+  crdt_fault_hook() = [=](CrdtFaultPoint at, uint64_t) -> uint64_t {
+    return at == point && ++*reached == nth ? value : 0;
+  };
+}
+
+int main() {
+  using Node = CRDT<CrdtString, CrdtString>;
+
+  // Test Case: Clock Jumps
+  {
+    Node node1(1);
+    Node node2(2);
+    node1.insert_or_update("a", {{"v", "1"}});
+    inject_once(CrdtFaultPoint::ClockTick, 1, 1000);
+    node1.insert_or_update("b", {{"v", "2"}});
+    crdt_fault_hook() = nullptr;
+    assert_true(node1.get_clock().current_time() == 1002, "Faults: The clock should jump forward");
+
+    node2.insert_or_update("a", {{"v", "concurrent"}});
+    node2.merge_changes(node1.get_changes_since(0));
+    node1.merge_changes(node2.get_changes_since(0));
+    node1.insert_or_update("c", {{"v", "3"}});
+    node2.merge_changes(node1.get_changes_since(1002));
+    assert_true(node1.get_data() == node2.get_data() && node2.get_clock().current_time() > 1002,
+                "Faults: Nodes should converge across a clock jump");
+    std::cout << "Test 'Clock Jumps' passed." << std::endl;
+  }
+
+  // Test Case: Interrupted Merge
+  {
+    Node source(1);
+    for (int i = 0; i < 5; ++i) {
+      source.insert_or_update("r" + std::to_string(i), {{"v", std::to_string(i)}});
+    }
+    Node reference(2);
+    reference.merge_changes(source.get_changes_since(0));
+
+    Node interrupted(2);
+    inject_once(CrdtFaultPoint::MergeChange, 3);
+    bool threw = false;
+    try {
+      interrupted.merge_changes(source.get_changes_since(0));
+    } catch (const CrdtInjectedFault &) {
+      threw = true;
+    }
+    crdt_fault_hook() = nullptr;
+    assert_true(threw && interrupted.get_data().size() == 2, "Faults: The merge should stop after two changes");
+
+    // Resending the batch, as an unacknowledged sync does, completes the state
+    interrupted.merge_changes(source.get_changes_since(0));
+    assert_true(interrupted.get_data() == reference.get_data() &&
+                    interrupted.get_changes_since(0).size() == reference.get_changes_since(0).size(),
+                "Faults: A resent batch should complete an interrupted merge");
+    std::cout << "Test 'Interrupted Merge' passed." << std::endl;
+  }
+
+  // Test Case: Failed Storage Writes
+  {
+    std::filesystem::path directory = std::filesystem::temp_directory_path() / "crdt_fault_test";
+    std::filesystem::remove_all(directory);
+    Node node(1);
+    {
+      CrdtChangeLog<Node> log(node, directory / "log");
+      node.insert_or_update("before", {{"v", "1"}});
+      inject_once(CrdtFaultPoint::StorageWrite, 1);
+      bool threw = false;
+      try {
+        node.insert_or_update("lost", {{"v", "2"}});
+      } catch (const CrdtInjectedFault &) {
+        threw = true;
+      }
+      crdt_fault_hook() = nullptr;
+      node.insert_or_update("after", {{"v", "3"}});
+      assert_true(threw, "Faults: The log append should fail");
+    }
+    Node recovered = CrdtChangeLog<Node>::recover(directory / "log", 1);
+    assert_true(recovered.get_record("before") && recovered.get_record("after") && !recovered.get_record("lost"),
+                "Faults: A failed append should not corrupt later records");
+
+    FileCursorStore store(directory / "cursors");
+    store.save({{2, CrdtSyncCursor{5, 3}}});
+    inject_once(CrdtFaultPoint::StorageWrite, 1);
+    try {
+      store.save({{2, CrdtSyncCursor{9, 9}}});
+    } catch (const CrdtInjectedFault &) {
+    }
+    crdt_fault_hook() = nullptr;
+    assert_true(store.load().at(2).received == 5, "Faults: A failed cursor save should keep the previous cursors");
+
+    {
+      CrdtHub hub(directory / "hub");
+      hub.push("doc", 1, {1});
+      inject_once(CrdtFaultPoint::StorageWrite, 1);
+      try {
+        hub.push("doc", 1, {2});
+      } catch (const CrdtInjectedFault &) {
+      }
+      crdt_fault_hook() = nullptr;
+      hub.push("doc", 1, {3});
+      assert_true(hub.batch_count("doc") == 2, "Faults: A failed push should not be stored");
+    }
+    CrdtHub reopened(directory / "hub");
+    auto batches = reopened.pull("doc", 0, 2).batches;
+    assert_true(batches.size() == 2 && *batches[1] == CrdtBytes{3}, "Faults: The hub should reload the stored batches");
+    std::filesystem::remove_all(directory);
+    std::cout << "Test 'Failed Storage Writes' passed." << std::endl;
+  }
+
+  std::cout << "All fault injection tests passed successfully!" << std::endl;
+  return 0;
+}
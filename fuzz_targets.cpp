@@ -0,0 +1,25 @@
+// fuzz_targets.cpp
+//
+// libFuzzer harness for the entry points in crdt_fuzz.hpp. Pick the target with CRDT_FUZZ_TARGET, e.g.:
+//
+//   clang++ -std=c++20 -g -O1 -fsanitize=fuzzer,address,undefined -DCRDT_FUZZ_TARGET=merge_sequence -o fuzz-merge
+//     fuzz_targets.cpp
+//   ./fuzz-merge
+//
+// Targets: decode_changes, sync_frames, merge_sequence. AFL++ builds the same file with afl-clang-fast++ and
+// -fsanitize=fuzzer.
+#include "crdt_fuzz.hpp"
+
+#include <cstdlib>
+
+#ifndef CRDT_FUZZ_TARGET
+#define CRDT_FUZZ_TARGET merge_sequence
+#endif
+
+extern "C" int LLVMFuzzerTestOneInput(const uint8_t *data, size_t size) {
+  CrdtFuzzResult result = crdt_fuzz::CRDT_FUZZ_TARGET(data, size);
+  if (result == CrdtFuzzResult::Failed || result == CrdtFuzzResult::InvariantViolated) {
+    std::abort();
+  }
+  return 0;
+}
@@ -0,0 +1,65 @@
+// ordered_tests.cpp
+#define CRDT_ORDERED_COLLECTIONS
+#include "crdt.hpp"
+
+#include <cstdlib>
+#include <iostream>
+#include <string>
+
+/// Simple assertion helper
+void assert_true(bool condition, const CrdtString &message) {
+  if (!condition) {
+    std::cerr << "Assertion failed: " << message << std::endl;
+    exit(1);
+  }
+}
+
+int main() {
+  // Test Case: Range Scans over Ordered Storage
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    for (const char *id : {"user/3", "post/2", "user/1", "post/1", "user/2", "zeta"}) {
+      node.insert_or_update(id, {{"id", id}});
+    }
+    node.delete_record("user/2");
+
+    auto users = node.range("user/", "user0");
+    assert_true(users.size() == 2, "Ordered Range: Should return two live users");
+    assert_true(users[0].first == "user/1" && users[1].first == "user/3", "Ordered Range: Users should be sorted by key");
+
+    auto posts = node.prefix("post/");
+    assert_true(posts.size() == 2 && posts[0].first == "post/1" && posts[1].first == "post/2",
+                "Ordered Prefix: Should return posts in key order");
+
+    auto latest = node.latest(2);
+    assert_true(latest.size() == 2 && latest[0].first == "zeta" && latest[1].first == "user/3",
+                "Ordered Latest: Should return the greatest live keys in descending order");
+    std::cout << "Test 'Range Scans over Ordered Storage' passed." << std::endl;
+  }
+
+  // Test Case: Range Scans Respect Parent Records
+  {
+    auto parent = std::make_shared<CRDT<CrdtString, CrdtString>>(1);
+    parent->insert_or_update("a", {{"v", "parent"}});
+    parent->insert_or_update("b", {{"v", "parent"}});
+    parent->insert_or_update("c", {{"v", "parent"}});
+
+    CRDT<CrdtString, CrdtString> child(2, parent);
+    child.insert_or_update("b", {{"v", "child"}});
+    child.delete_record("c");
+    child.insert_or_update("d", {{"v", "child"}});
+
+    auto all = child.range("a", "z");
+    assert_true(all.size() == 3, "Ordered Parent Range: Should merge parent and child records");
+    assert_true(all[0].first == "a" && all[1].first == "b" && all[2].first == "d",
+                "Ordered Parent Range: Results should be sorted across levels");
+    assert_true(all[1].second->fields.at("v") == "child", "Ordered Parent Range: Child should shadow parent");
+
+    auto latest = child.latest(1);
+    assert_true(latest.size() == 1 && latest[0].first == "d", "Ordered Parent Latest: Should consider child records");
+    std::cout << "Test 'Range Scans Respect Parent Records' passed." << std::endl;
+  }
+
+  std::cout << "All ordered tests passed successfully!" << std::endl;
+  return 0;
+}
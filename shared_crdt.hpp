@@ -0,0 +1,115 @@
+// shared_crdt.hpp
+#ifndef SHARED_CRDT_HPP
+#define SHARED_CRDT_HPP
+
+#include "crdt.hpp"
+
+#include <mutex>
+#include <shared_mutex>
+
+/// Thread-safe handle around a CRDT.
+///
+/// Mutations (local operations and merges) take an exclusive lock, reads take a shared lock, so one thread can merge
+/// incoming changes while others read records concurrently. Read accessors return copies, since references into the
+/// CRDT would outlive the lock.
+template <typename K, typename V, MergeRule<K, V> MergeRuleType = DefaultMergeRule<K, V>,
+          ChangeComparator<K, V> ChangeComparatorType = DefaultChangeComparator<K, V>, typename SortFunctionType = DefaultSort>
+class SharedCrdt {
+public:
+  using CrdtType = CRDT<K, V, MergeRuleType, ChangeComparatorType, SortFunctionType>;
+
+  /// Creates a shared handle, forwarding the arguments to the CRDT constructor.
+  template <typename... Args> explicit SharedCrdt(Args &&...args) : crdt_(std::forward<Args>(args)...) {}
+
+  SharedCrdt(const SharedCrdt &) = delete;
+  SharedCrdt &operator=(const SharedCrdt &) = delete;
+
+  /// Runs `fn(const CrdtType &)` under a shared lock and returns its result.
+  template <typename Fn> auto read(Fn &&fn) const {
+    std::shared_lock lock(mutex_);
+    return fn(static_cast<const CrdtType &>(crdt_));
+  }
+
+  /// Runs `fn(CrdtType &)` under an exclusive lock and returns its result.
+  template <typename Fn> auto write(Fn &&fn) {
+    std::unique_lock lock(mutex_);
+    return fn(crdt_);
+  }
+
+  template <bool ReturnChanges = true>
+  std::conditional_t<ReturnChanges, CrdtVector<Change<K, V>>, void> insert_or_update(const K &record_id,
+                                                                                     CrdtMap<CrdtString, V> &&fields) {
+    std::unique_lock lock(mutex_);
+    return crdt_.template insert_or_update<ReturnChanges>(record_id, std::move(fields));
+  }
+
+  template <bool ReturnChanges = true>
+  std::conditional_t<ReturnChanges, CrdtVector<Change<K, V>>, void> delete_record(const K &record_id) {
+    std::unique_lock lock(mutex_);
+    return crdt_.template delete_record<ReturnChanges>(record_id);
+  }
+
+  template <bool ReturnAcceptedChanges = false>
+  std::conditional_t<ReturnAcceptedChanges, CrdtVector<Change<K, V>>, void> merge_changes(CrdtVector<Change<K, V>> &&changes,
+                                                                                          bool ignore_parent = false) {
+    std::unique_lock lock(mutex_);
+    return crdt_.template merge_changes<ReturnAcceptedChanges>(std::move(changes), ignore_parent);
+  }
+
+  CrdtVector<Change<K, V>> get_changes_since(uint64_t last_db_version) const {
+    std::shared_lock lock(mutex_);
+    return crdt_.get_changes_since(last_db_version);
+  }
+
+  /// Returns a copy of a live record, or std::nullopt if it is missing or tombstoned.
+  std::optional<Record<V>> get_record(const K &record_id) const {
+    std::shared_lock lock(mutex_);
+    const Record<V> *record = crdt_.get_record(record_id);
+    return record ? std::optional<Record<V>>(*record) : std::nullopt;
+  }
+
+  /// Returns a copy of a field value of a live record.
+  std::optional<V> get_value(const K &record_id, const CrdtString &col_name) const {
+    std::shared_lock lock(mutex_);
+    const V *value = crdt_.get_value(record_id, col_name);
+    return value ? std::optional<V>(*value) : std::nullopt;
+  }
+
+  bool contains_record(const K &record_id) const {
+    std::shared_lock lock(mutex_);
+    return crdt_.contains_record(record_id);
+  }
+
+  size_t len_live() const {
+    std::shared_lock lock(mutex_);
+    return crdt_.len_live();
+  }
+
+  /// Returns copies of the live records matching a predicate.
+  template <typename Predicate>
+    requires std::predicate<Predicate &, const K &, const Record<V> &>
+  CrdtVector<std::pair<K, Record<V>>> query(Predicate &&predicate) const {
+    std::shared_lock lock(mutex_);
+    CrdtVector<std::pair<K, Record<V>>> results;
+    for (auto &[record_id, record] : crdt_.query(predicate)) {
+      results.emplace_back(std::move(record_id), *record);
+    }
+    return results;
+  }
+
+  CrdtMap<K, Record<V>> get_data() const {
+    std::shared_lock lock(mutex_);
+    return crdt_.get_data();
+  }
+
+  uint64_t current_time() const {
+    std::shared_lock lock(mutex_);
+    return crdt_.get_clock().current_time();
+  }
+
+private:
+  CrdtType crdt_;
+  mutable std::shared_mutex mutex_;
+};
+
+#endif // SHARED_CRDT_HPP
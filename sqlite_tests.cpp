@@ -0,0 +1,135 @@
+// sqlite_tests.cpp
+#include "crdt_sqlite.hpp"
+
+#include <cstdlib>
+#include <iostream>
+#include <string>
+
+/// Simple assertion helper
+void assert_true(bool condition, const CrdtString &message) {
+  if (!condition) {
+    std::cerr << "Assertion failed: " << message << std::endl;
+    exit(1);
+  }
+}
+
+sqlite3 *open_database() {
+  sqlite3 *db = nullptr;
+  sqlite3_open(":memory:", &db);
+  sqlite3_exec(db, "CREATE TABLE todos (id TEXT PRIMARY KEY, title TEXT, done TEXT)", nullptr, nullptr, nullptr);
+  return db;
+}
+
+void exec(sqlite3 *db, const CrdtString &sql) {
+  char *error = nullptr;
+  if (sqlite3_exec(db, sql.c_str(), nullptr, nullptr, &error) != SQLITE_OK) {
+    std::cerr << "SQL failed: " << error << std::endl;
+    exit(1);
+  }
+}
+
+/// Returns the column of a row, "<null>" for NULL, or "<missing>" if the row does not exist.
+CrdtString query(sqlite3 *db, const CrdtString &id, const CrdtString &column) {
+  sqlite3_stmt *stmt = nullptr;
+  sqlite3_prepare_v2(db, ("SELECT " + column + " FROM todos WHERE id = ?").c_str(), -1, &stmt, nullptr);
+  sqlite3_bind_text(stmt, 1, id.c_str(), -1, SQLITE_TRANSIENT);
+  CrdtString result = "<missing>";
+  if (sqlite3_step(stmt) == SQLITE_ROW) {
+    const unsigned char *text = sqlite3_column_text(stmt, 0);
+    result = text ? reinterpret_cast<const char *>(text) : "<null>";
+  }
+  sqlite3_finalize(stmt);
+  return result;
+}
+
+int main() {
+  // Test Case: Mirror Merged Changes
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node2.insert_or_update("existing", {{"title", "Old"}});
+    sqlite3 *db = open_database();
+    SqliteMirror<CRDT<CrdtString, CrdtString>> mirror(node2, db, "todos");
+    mirror.rebuild();
+    assert_true(query(db, "existing", "title") == "Old", "Mirror: Rebuild should copy existing records");
+
+    node1.insert_or_update("t1", {{"title", "Buy milk"}, {"done", "false"}, {"unmapped", "x"}});
+    node2.merge_changes(node1.get_changes_since(0));
+    assert_true(query(db, "t1", "title") == "Buy milk" && query(db, "t1", "done") == "false",
+                "Mirror: Merged records should be written to the table");
+
+    uint64_t seen = node1.get_clock().current_time();
+    node1.delete_field("t1", "done");
+    node2.merge_changes(node1.get_changes_since(seen));
+    assert_true(query(db, "t1", "done") == "<null>", "Mirror: Column deletions should set NULL");
+
+    seen = node1.get_clock().current_time();
+    node1.delete_record("t1");
+    node2.merge_changes(node1.get_changes_since(seen));
+    assert_true(query(db, "t1", "title") == "<missing>", "Mirror: Record deletions should delete the row");
+    assert_true(mirror.capture().empty(), "Mirror: Mirrored writes should not be captured as local edits");
+    sqlite3_close(db);
+    std::cout << "Test 'Mirror Merged Changes' passed." << std::endl;
+  }
+
+  // Test Case: Capture SQL Writes
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    sqlite3 *db1 = open_database();
+    sqlite3 *db2 = open_database();
+    {
+      SqliteMirror<CRDT<CrdtString, CrdtString>> mirror1(node1, db1, "todos");
+      SqliteMirror<CRDT<CrdtString, CrdtString>> mirror2(node2, db2, "todos");
+
+      exec(db1, "INSERT INTO todos (id, title, done) VALUES ('t1', 'Buy milk', 'false'), ('t2', 'Walk dog', NULL)");
+      auto changes = mirror1.capture();
+      assert_true(*node1.get_value("t1", "title") == "Buy milk" && !node1.get_value("t2", "done"),
+                  "Capture: Inserted rows should become records");
+      node2.merge_changes(std::move(changes));
+      assert_true(query(db2, "t2", "title") == "Walk dog", "Capture: Captured writes should replicate");
+
+      exec(db1, "UPDATE todos SET done = 'true', title = title WHERE id = 't1'");
+      exec(db1, "UPDATE todos SET title = NULL WHERE id = 't2'");
+      exec(db1, "DELETE FROM todos WHERE id = 't2'");
+      uint64_t seen = node1.get_clock().current_time();
+      changes = mirror1.capture();
+      assert_true(changes.size() == 3, "Capture: Only modified columns should produce changes");
+      assert_true(*node1.get_value("t1", "done") == "true" && node1.is_tombstoned("t2"),
+                  "Capture: Updates and deletes should be applied");
+      node2.merge_changes(node1.get_changes_since(seen));
+      assert_true(query(db2, "t1", "done") == "true" && query(db2, "t2", "title") == "<missing>",
+                  "Capture: Updates and deletes should replicate");
+      assert_true(mirror1.capture().empty(), "Capture: The log should be cleared");
+    }
+    exec(db1, "INSERT INTO todos (id, title) VALUES ('t3', 'After')");
+    assert_true(!node1.get_record("t3"), "Capture: Triggers should be removed with the mirror");
+    sqlite3_close(db1);
+    sqlite3_close(db2);
+    std::cout << "Test 'Capture SQL Writes' passed." << std::endl;
+  }
+
+  // Test Case: SQLite Cursor Store
+  {
+    sqlite3 *db = open_database();
+    {
+      SqliteCursorStore store(db);
+      assert_true(store.load().empty(), "Cursor Store: A new table should be empty");
+      CrdtMap<CrdtNodeId, CrdtSyncCursor> cursors;
+      cursors[2] = {10, 7};
+      cursors[3] = {4, 0};
+      store.save(cursors);
+      cursors.erase(3);
+      cursors[2].received = 12;
+      store.save(cursors);
+    }
+    SqliteCursorStore reopened(db);
+    auto loaded = reopened.load();
+    assert_true(loaded.size() == 1 && loaded[2] == CrdtSyncCursor{12, 7}, "Cursor Store: Saves should replace cursors");
+    sqlite3_close(db);
+    std::cout << "Test 'SQLite Cursor Store' passed." << std::endl;
+  }
+
+  std::cout << "All SQLite tests passed successfully!" << std::endl;
+  return 0;
+}
@@ -1,9 +1,43 @@
 // tests.cpp
 #include "crdt.hpp"
-
+#include "crdt_archive.hpp"
+#include "crdt_arrow.hpp"
+#include "crdt_backpressure.hpp"
+#include "crdt_bootstrap.hpp"
+#include "crdt_broadcast.hpp"
+#include "crdt_chain.hpp"
+#include "crdt_crsqlite.hpp"
+#include "crdt_sim.hpp"
+#include "crdt_sink.hpp"
+#include "crdt_fuzz.hpp"
+#include "crdt_gossip.hpp"
+#include "crdt_hub.hpp"
+#include "crdt_iblt.hpp"
+#include "crdt_io.hpp"
+#include "crdt_log.hpp"
+#include "crdt_blob.hpp"
+#include "crdt_maintenance.hpp"
+#include "crdt_namespace.hpp"
+#include "crdt_presence.hpp"
+#include "crdt_quic.hpp"
+#include "crdt_replay.hpp"
+#include "crdt_merkle.hpp"
+#include "crdt_ops.hpp"
+#include "crdt_p2p.hpp"
+#include "crdt_sync.hpp"
+#include "crdt_tcp.hpp"
+#include "crdt_undo.hpp"
+#include "crdt_websocket.hpp"
+#include "shared_crdt.hpp"
+
+#include <atomic>
+#include <condition_variable>
 #include <cstdlib>
+#include <deque>
 #include <iostream>
+#include <mutex>
 #include <string>
+#include <thread>
 #include <unordered_map>
 
 // Helper function to generate unique IDs (simulating UUIDs)
@@ -20,6 +54,71 @@ void assert_true(bool condition, const CrdtString &message) {
   }
 }
 
+// Blocking in-memory message queue used to test transports
+struct MessageQueue {
+  std::mutex mutex;
+  std::condition_variable ready;
+  std::deque<CrdtBytes> messages;
+
+  void push(const CrdtBytes &message) {
+    std::lock_guard lock(mutex);
+    messages.push_back(message);
+    ready.notify_one();
+  }
+
+  std::optional<CrdtBytes> pop() {
+    std::unique_lock lock(mutex);
+    ready.wait(lock, [&] { return !messages.empty(); });
+    CrdtBytes message = std::move(messages.front());
+    messages.pop_front();
+    return message;
+  }
+};
+
+// Fake WebSocket connection delivering binary messages through in-memory queues
+struct FakeWebSocket {
+  MessageQueue &incoming;
+  MessageQueue &outgoing;
+
+  void send_binary(const CrdtBytes &message) { outgoing.push(message); }
+  std::optional<CrdtBytes> receive_binary() { return incoming.pop(); }
+};
+
+// Pumps frames between two sync sessions until neither has anything left to send
+template <typename CrdtA, typename CrdtB> void pump_sync_sessions(SyncSession<CrdtA> &a, SyncSession<CrdtB> &b) {
+  CrdtVector<CrdtBytes> to_a, to_b = a.start();
+  for (auto &frame : b.start()) {
+    to_a.push_back(std::move(frame));
+  }
+  while (!to_a.empty() || !to_b.empty()) {
+    CrdtVector<CrdtBytes> next_a, next_b;
+    for (auto &frame : to_a) {
+      for (auto &reply : a.on_frame(frame)) {
+        next_b.push_back(std::move(reply));
+      }
+    }
+    for (auto &frame : to_b) {
+      for (auto &reply : b.on_frame(frame)) {
+        next_a.push_back(std::move(reply));
+      }
+    }
+    to_a = std::move(next_a);
+    to_b = std::move(next_b);
+  }
+}
+
+// Struct used to test typed record mapping
+struct TestUser {
+  CrdtString name;
+  int age = 0;
+  double score = 0.0;
+};
+
+template <> struct CrdtRecordTraits<TestUser> {
+  static constexpr auto fields =
+      std::make_tuple(CRDT_FIELD(TestUser, name), CRDT_FIELD(TestUser, age), CRDT_FIELD(TestUser, score));
+};
+
 int main() {
   // Test Case: Basic Insert and Merge using insert_or_update
   {
@@ -113,14 +212,10 @@ int main() {
     node2.merge_changes(std::move(changes_delete));
 
     // Both nodes should reflect the deletion
-    assert_true(node1.get_data().at(record_id).fields.empty(), "Delete and Merge: Node1 should have empty fields");
-    assert_true(node2.get_data().at(record_id).fields.empty(), "Delete and Merge: Node2 should have empty fields");
-    assert_true(node1.get_data().at(record_id).column_versions.find("__deleted__") !=
-                    node1.get_data().at(record_id).column_versions.end(),
-                "Delete and Merge: Node1 should have '__deleted__' column version");
-    assert_true(node2.get_data().at(record_id).column_versions.find("__deleted__") !=
-                    node2.get_data().at(record_id).column_versions.end(),
-                "Delete and Merge: Node2 should have '__deleted__' column version");
+    assert_true(!node1.get_data().contains(record_id), "Delete and Merge: Node1 should not list the record as live");
+    assert_true(!node2.get_data().contains(record_id), "Delete and Merge: Node2 should not list the record as live");
+    assert_true(node1.get_tombstone(record_id) != nullptr, "Delete and Merge: Node1 should have a tombstone");
+    assert_true(node2.get_tombstone(record_id) != nullptr, "Delete and Merge: Node2 should have a tombstone");
     std::cout << "Test 'Delete and Merge' passed." << std::endl;
   }
 
@@ -146,10 +241,8 @@ int main() {
     node1.merge_changes(std::move(changes_attempt_insert));
 
     // Node2 should respect the tombstone
-    assert_true(node2.get_data().at(record_id).fields.empty(), "Tombstone Handling: Node2 should have empty fields");
-    assert_true(node2.get_data().at(record_id).column_versions.find("__deleted__") !=
-                    node2.get_data().at(record_id).column_versions.end(),
-                "Tombstone Handling: Node2 should have '__deleted__' column version");
+    assert_true(!node2.get_data().contains(record_id), "Tombstone Handling: Node2 should not list the record as live");
+    assert_true(node2.get_tombstone(record_id) != nullptr, "Tombstone Handling: Node2 should have a tombstone");
     std::cout << "Test 'Tombstone Handling' passed." << std::endl;
   }
 
@@ -303,14 +396,12 @@ int main() {
     node1.merge_changes(std::move(changes_attempt_insert));
 
     // The deletion should prevail
-    assert_true(node1.get_data().at(record_id).fields.empty(), "Inserting After Deletion: Node1 should have empty fields");
-    assert_true(node2.get_data().at(record_id).fields.empty(), "Inserting After Deletion: Node2 should have empty fields");
-    assert_true(node1.get_data().at(record_id).column_versions.find("__deleted__") !=
-                    node1.get_data().at(record_id).column_versions.end(),
-                "Inserting After Deletion: Node1 should have '__deleted__' column version");
-    assert_true(node2.get_data().at(record_id).column_versions.find("__deleted__") !=
-                    node2.get_data().at(record_id).column_versions.end(),
-                "Inserting After Deletion: Node2 should have '__deleted__' column version");
+    assert_true(!node1.get_data().contains(record_id),
+                "Inserting After Deletion: Node1 should not list the record as live");
+    assert_true(!node2.get_data().contains(record_id),
+                "Inserting After Deletion: Node2 should not list the record as live");
+    assert_true(node1.get_tombstone(record_id) != nullptr, "Inserting After Deletion: Node1 should have a tombstone");
+    assert_true(node2.get_tombstone(record_id) != nullptr, "Inserting After Deletion: Node2 should have a tombstone");
     std::cout << "Test 'Inserting After Deletion' passed." << std::endl;
   }
 
@@ -687,6 +778,16 @@ int main() {
     assert_true(parent_crdt.get_data().at(record_id_parent).fields.at("child_field") == "child_value",
                 "Parent-Child Overlay: Parent should reflect child's update");
 
+    // Children inherit the replicated configuration as well as the policies
+    CRDT<CrdtString, CrdtString> configured(5);
+    configured.enable_migrations("__schema__");
+    configured.migrate(CrdtMigration<CrdtString>::drop_column(1, "secret"));
+    configured.add_unique_constraint("name");
+    configured.insert_or_update("a", {{"name", "taken"}});
+    CRDT<CrdtString, CrdtString> layered(6, std::make_shared<CRDT<CrdtString, CrdtString>>(configured));
+    assert_true(layered.schema_version() == 1 && layered.unique_owner("name", "taken") == CrdtString("a"),
+                "Parent-Child Overlay: Child should inherit migrations and unique columns");
+
     std::cout << "Test 'Parent-Child Overlay Functionality' passed." << std::endl;
   }
 
@@ -868,11 +969,9 @@ int main() {
     child_crdt.merge_changes(std::move(parent_delete_changes), true);
 
     // Child should now have the record tombstoned
-    assert_true(child_crdt.get_data().at(record_id).fields.empty(),
-                "Tombstone Propagation: Child should have empty fields after deletion");
-    assert_true(child_crdt.get_data().at(record_id).column_versions.find("__deleted__") !=
-                    child_crdt.get_data().at(record_id).column_versions.end(),
-                "Tombstone Propagation: Child should have '__deleted__' column version");
+    assert_true(!child_crdt.get_data().contains(record_id),
+                "Tombstone Propagation: Child should not list the record as live after deletion");
+    assert_true(child_crdt.get_tombstone(record_id) != nullptr, "Tombstone Propagation: Child should have a tombstone");
 
     std::cout << "Test 'Tombstone Propagation from Parent to Child' passed." << std::endl;
   }
@@ -1015,15 +1114,13 @@ int main() {
     parent_crdt.merge_changes(std::move(child_delete_changes));
 
     // Parent should still have the record (since child deletion should not affect parent)
-    assert_true(parent_crdt.get_data().find(record_id) != parent_crdt.get_data().end(),
+    assert_true(parent_crdt.get_data().contains(record_id) || parent_crdt.get_tombstone(record_id) != nullptr,
                 "Child Deletion: Parent should still have the record after child deletion");
 
     // Child should have the record tombstoned
-    assert_true(child_crdt.get_data().at(record_id).fields.empty(),
-                "Child Deletion: Child should have empty fields after deletion");
-    assert_true(child_crdt.get_data().at(record_id).column_versions.find("__deleted__") !=
-                    child_crdt.get_data().at(record_id).column_versions.end(),
-                "Child Deletion: Child should have '__deleted__' column version");
+    assert_true(!child_crdt.get_data().contains(record_id),
+                "Child Deletion: Child should not list the record as live after deletion");
+    assert_true(child_crdt.get_tombstone(record_id) != nullptr, "Child Deletion: Child should have a tombstone");
 
     std::cout << "Test 'Child Deletion Does Not Affect Parent' passed." << std::endl;
   }
@@ -1092,18 +1189,14 @@ int main() {
     parent_crdt.merge_changes(std::move(child_change_insert));
 
     // Parent should still have the record tombstoned without the new field
-    assert_true(parent_crdt.get_data().at(record_id).fields.empty(),
-                "Parent Deletion: Parent should still have empty fields after child insertion attempt");
-    assert_true(parent_crdt.get_data().at(record_id).column_versions.find("__deleted__") !=
-                    parent_crdt.get_data().at(record_id).column_versions.end(),
-                "Parent Deletion: Parent should have '__deleted__' column version");
+    assert_true(!parent_crdt.get_data().contains(record_id),
+                "Parent Deletion: Parent should still not list the record as live after child insertion attempt");
+    assert_true(parent_crdt.get_tombstone(record_id) != nullptr, "Parent Deletion: Parent should have a tombstone");
 
     // Child should also respect the tombstone
-    assert_true(child_crdt.get_data().at(record_id).fields.empty(),
-                "Parent Deletion: Child should have empty fields after parent's deletion");
-    assert_true(child_crdt.get_data().at(record_id).column_versions.find("__deleted__") !=
-                    child_crdt.get_data().at(record_id).column_versions.end(),
-                "Parent Deletion: Child should have '__deleted__' column version");
+    assert_true(!child_crdt.get_data().contains(record_id),
+                "Parent Deletion: Child should not list the record as live after parent's deletion");
+    assert_true(child_crdt.get_tombstone(record_id) != nullptr, "Parent Deletion: Child should have a tombstone");
 
     std::cout << "Test 'Parent Deletion Prevents Child Insertions' passed." << std::endl;
   }
@@ -1291,7 +1384,7 @@ int main() {
     CRDT<CrdtString, CrdtString>::compress_changes(changes);
 
     // Expected compressed changes:
-    // - record1, __deleted__: latest deletion
+    // - record1: latest deletion
     assert_true(changes.size() == 1, "Compress Changes: Multiple deletions should compress to latest.");
     assert_true(!changes[0].col_name.has_value(), "Compress Changes: Deletion should have no column name.");
     assert_true(!changes[0].value.has_value(), "Compress Changes: Deletion should have no value.");
@@ -1382,8 +1475,8 @@ int main() {
     CRDT<CrdtString, CrdtString>::compress_changes(changes);
 
     // Expected compressed changes:
-    // - record1, __deleted__ with latest version
-    // - record2, __deleted__ with latest version
+    // - record1 deletion with latest version
+    // - record2 deletion with latest version
     assert_true(changes.size() == 2, "Compress Changes: Only latest deletions per record should remain.");
     for (const auto &change : changes) {
       assert_true(!change.col_name.has_value(), "Compress Changes: Deletion should have no column name.");
@@ -1510,6 +1603,3889 @@ int main() {
     std::cout << "Test 'Multiple Insertions with No Overwrites' passed." << std::endl;
   }
 
+  // Test Case: Query Only Returns Live Records
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    node.insert_or_update("r1", {{"tag", "keep"}});
+    node.insert_or_update("r2", {{"tag", "drop"}});
+    node.insert_or_update("r3", {{"tag", "keep"}});
+    node.delete_record("r3");
+
+    auto all = node.query([](const CrdtString &, const Record<CrdtString> &) { return true; });
+    assert_true(all.size() == 2, "Query: Tombstoned records should not be returned");
+
+    auto kept = node.query([](const CrdtString &, const Record<CrdtString> &record) {
+      auto it = record.fields.find("tag");
+      return it != record.fields.end() && it->second == "keep";
+    });
+    assert_true(kept.size() == 1 && kept[0].first == "r1", "Query: Predicate should select only 'r1'");
+    assert_true(kept[0].second->fields.at("tag") == "keep", "Query: Returned record should point at live data");
+
+    // Records inherited from a parent are visible, unless the child shadows or deletes them
+    auto parent_ptr = std::make_shared<CRDT<CrdtString, CrdtString>>(node);
+    CRDT<CrdtString, CrdtString> child(2, parent_ptr);
+    child.insert_or_update("r2", {{"tag", "keep"}});
+    child.delete_record("r1");
+    auto child_kept = child.query([](const CrdtString &, const Record<CrdtString> &record) {
+      auto it = record.fields.find("tag");
+      return it != record.fields.end() && it->second == "keep";
+    });
+    assert_true(child_kept.size() == 1 && child_kept[0].first == "r2", "Query: Child should see its own override only");
+    std::cout << "Test 'Query Only Returns Live Records' passed." << std::endl;
+  }
+
+  // Test Case: Range Scans over Unordered Storage
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    for (const char *id : {"k3", "k1", "k5", "k2", "k4"}) {
+      node.insert_or_update(id, {{"id", id}});
+    }
+    node.delete_record("k2");
+
+    auto in_range = node.range("k1", "k4");
+    assert_true(in_range.size() == 2 && in_range[0].first == "k1" && in_range[1].first == "k3",
+                "Range Scan: Should return sorted live keys in [k1, k4)");
+    assert_true(node.prefix("k").size() == 4, "Prefix Scan: Should return all live keys with the prefix");
+    auto latest = node.latest(2);
+    assert_true(latest.size() == 2 && latest[0].first == "k5" && latest[1].first == "k4",
+                "Latest: Should return the greatest keys in descending order");
+    std::cout << "Test 'Range Scans over Unordered Storage' passed." << std::endl;
+  }
+
+  // Test Case: Accessors Hide Tombstones
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    node.insert_or_update("r1", {{"name", "Alice"}});
+    node.insert_or_update("r2", {{"name", "Bob"}});
+    node.delete_record("r2");
+
+    assert_true(node.contains_record("r1"), "Accessors: 'r1' should be live");
+    assert_true(!node.contains_record("r2"), "Accessors: 'r2' should be hidden after deletion");
+    assert_true(!node.contains_record("missing"), "Accessors: Unknown record should not be contained");
+    assert_true(node.get_record("r2") == nullptr, "Accessors: get_record should hide tombstoned records");
+    assert_true(node.get_value("r1", "name") && *node.get_value("r1", "name") == "Alice",
+                "Accessors: get_value should return the field value");
+    assert_true(node.get_value("r1", "age") == nullptr, "Accessors: Missing column should return nullptr");
+    assert_true(node.get_value("r2", "name") == nullptr, "Accessors: Tombstoned record should return nullptr");
+    assert_true(node.len_live() == 1, "Accessors: len_live should not count tombstones");
+
+    size_t visited = 0;
+    node.iter_live([&](const CrdtString &record_id, const Record<CrdtString> &) {
+      assert_true(record_id == "r1", "Accessors: iter_live should only visit live records");
+      ++visited;
+    });
+    assert_true(visited == 1, "Accessors: iter_live should visit exactly one record");
+    std::cout << "Test 'Accessors Hide Tombstones' passed." << std::endl;
+  }
+
+  // Test Case: Typed Struct Mapping
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+
+    auto insert_changes = node1.insert_struct("u1", TestUser{"Alice", 30, 1.5});
+    assert_true(insert_changes.size() == 3, "Struct Mapping: Insert should write every mapped field");
+    assert_true(*node1.get_value("u1", "age") == "30", "Struct Mapping: Numbers should be stored as text");
+
+    auto update_changes = node1.update_struct("u1", TestUser{"Alice", 31, 1.5});
+    assert_true(update_changes.size() == 1 && update_changes[0].col_name == "age",
+                "Struct Mapping: Update should only write changed fields");
+
+    node2.merge_changes(std::move(insert_changes));
+    node2.merge_changes(std::move(update_changes));
+    auto user = node2.get_struct<TestUser>("u1");
+    assert_true(user.has_value(), "Struct Mapping: Merged record should be readable as a struct");
+    assert_true(user->name == "Alice" && user->age == 31 && user->score == 1.5,
+                "Struct Mapping: Struct fields should round-trip through the CRDT");
+
+    node2.insert_or_update("u2", {{"age", "not a number"}});
+    assert_true(!node2.get_struct<TestUser>("u2").has_value(), "Struct Mapping: Invalid values should fail to convert");
+    assert_true(!node2.get_struct<TestUser>("missing").has_value(), "Struct Mapping: Missing record should return nullopt");
+    std::cout << "Test 'Typed Struct Mapping' passed." << std::endl;
+  }
+
+  // Test Case: Shared CRDT Concurrent Merge and Read
+  {
+    SharedCrdt<CrdtString, CrdtString> shared(1);
+    CRDT<CrdtString, CrdtString> remote(2);
+
+    std::thread writer([&]() {
+      for (int i = 0; i < 200; ++i) {
+        auto changes = remote.insert_or_update("r" + std::to_string(i), {{"n", std::to_string(i)}});
+        shared.merge_changes(std::move(changes));
+      }
+    });
+    std::thread reader([&]() {
+      size_t last_seen = 0;
+      for (int i = 0; i < 200; ++i) {
+        size_t seen = shared.len_live();
+        assert_true(seen >= last_seen, "Shared CRDT: Live count should never go backwards");
+        last_seen = seen;
+      }
+    });
+    writer.join();
+    reader.join();
+
+    assert_true(shared.len_live() == 200, "Shared CRDT: All merged records should be visible");
+    assert_true(shared.get_value("r42", "n") == CrdtString("42"), "Shared CRDT: get_value should return a copy");
+    auto count = shared.read([](const auto &crdt) { return crdt.query([](const auto &, const auto &) { return true; }).size(); });
+    assert_true(count == 200, "Shared CRDT: read() should expose the underlying CRDT");
+    std::cout << "Test 'Shared CRDT Concurrent Merge and Read' passed." << std::endl;
+  }
+
+  // Test Case: Sync Session Exchange
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    Node node1(1);
+    Node node2(2);
+    node1.insert_or_update("r1", {{"from", "node1"}});
+    node2.insert_or_update("r2", {{"from", "node2"}});
+
+    SyncSession<Node> session1(node1, 1);
+    SyncSession<Node> session2(node2, 2);
+    pump_sync_sessions(session1, session2);
+    assert_true(session1.is_complete() && session2.is_complete(), "Sync Session: Both sides should complete");
+    assert_true(node1.get_data() == node2.get_data(), "Sync Session: Nodes should converge");
+    assert_true(session1.peer_node_id() == CrdtNodeId(2), "Sync Session: Peer node id should be learned from hello");
+
+    // A second session resuming from the persisted cursors only ships new changes
+    node2.insert_or_update("r3", {{"from", "node2"}});
+    SyncSession<Node> resume1(node1, 1, session1.last_received_version());
+    SyncSession<Node> resume2(node2, 2, session2.last_received_version());
+    pump_sync_sessions(resume1, resume2);
+    assert_true(resume1.changes_received() == 1, "Sync Session: Resumed session should only receive the new change");
+    assert_true(node1.contains_record("r3"), "Sync Session: New record should be merged");
+
+    CrdtBytes garbage = {0x7f};
+    bool threw = false;
+    try {
+      SyncSession<Node>(node1, 1).on_frame(garbage);
+    } catch (const SyncProtocolError &) {
+      threw = true;
+    }
+    assert_true(threw, "Sync Session: Unknown message types should be rejected");
+
+    // A count the input cannot hold is rejected before anything is reserved
+    CrdtBytes hostile;
+    ByteWriter hostile_writer(hostile);
+    hostile_writer.write_varint(100);
+    hostile.resize(hostile.size() + 100);
+    threw = false;
+    try {
+      crdt_codec::decode_changes<CrdtString, CrdtString>(hostile);
+    } catch (const CrdtDecodeError &) {
+      threw = true;
+    }
+    assert_true(threw, "Sync Session: Change counts beyond the input should be rejected");
+
+    CrdtBytes encoded_float;
+    ByteWriter float_writer(encoded_float);
+    CrdtCodec<double>::encode(float_writer, 1.0);
+    CrdtCodec<float>::encode(float_writer, -2.0f);
+    assert_true(encoded_float == CrdtBytes{0, 0, 0, 0, 0, 0, 0xf0, 0x3f, 0, 0, 0, 0xc0},
+                "Sync Session: Floats should be encoded little-endian");
+    ByteReader float_reader(encoded_float);
+    assert_true(CrdtCodec<double>::decode(float_reader) == 1.0 && CrdtCodec<float>::decode(float_reader) == -2.0f,
+                "Sync Session: Floats should round-trip");
+    std::cout << "Test 'Sync Session Exchange' passed." << std::endl;
+  }
+
+  // Test Case: TCP Sync over Loopback
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    Node server_node(1);
+    Node client_node(2);
+    server_node.insert_or_update("server", {{"v", "1"}});
+    client_node.insert_or_update("client", {{"v", "2"}});
+
+    crdt_tcp::TcpListener listener(0);
+    CrdtMap<CrdtNodeId, uint64_t> cursors;
+    SyncResult server_result;
+    std::thread server([&]() { server_result = crdt_tcp::serve_one(listener, server_node, 1, cursors); });
+    auto client_result = crdt_tcp::connect("localhost", listener.port(), client_node, 2);
+    server.join();
+
+    assert_true(client_result.completed && server_result.completed, "TCP Sync: Both sides should complete");
+    assert_true(server_node.get_data() == client_node.get_data(), "TCP Sync: Nodes should converge");
+    assert_true(cursors.at(2) == server_result.last_received_version, "TCP Sync: Server should record the client cursor");
+    assert_true(client_result.peer_node_id == CrdtNodeId(1), "TCP Sync: Client should learn the server node id");
+
+    // Resuming from the cursors only transfers the new record
+    client_node.insert_or_update("client2", {{"v", "3"}});
+    std::thread server2([&]() { server_result = crdt_tcp::serve_one(listener, server_node, 1, cursors); });
+    client_result = crdt_tcp::connect("localhost", listener.port(), client_node, 2, client_result.last_received_version);
+    server2.join();
+    assert_true(server_result.changes_received == 1 && client_result.changes_received == 0,
+                "TCP Sync: Resumed sync should only transfer new changes");
+    std::cout << "Test 'TCP Sync over Loopback' passed." << std::endl;
+  }
+
+  // Test Case: WebSocket Sync with One Frame per Message
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    Node server_node(1);
+    Node browser_node(2);
+    server_node.insert_or_update("doc", {{"title", "Server"}});
+    browser_node.insert_or_update("note", {{"text", "Browser"}});
+
+    MessageQueue to_server, to_browser;
+    FakeWebSocket server_socket{to_server, to_browser};
+    FakeWebSocket browser_socket{to_browser, to_server};
+
+    CrdtMap<CrdtNodeId, uint64_t> cursors;
+    SyncResult server_result;
+    std::thread server([&]() { server_result = crdt_websocket::serve_one(server_socket, server_node, 1, cursors); });
+    auto browser_result = crdt_websocket::connect(browser_socket, browser_node, 2);
+    server.join();
+
+    assert_true(browser_result.completed && server_result.completed, "WebSocket Sync: Both sides should complete");
+    assert_true(server_node.get_data() == browser_node.get_data(), "WebSocket Sync: Nodes should converge");
+    assert_true(cursors.count(2) == 1, "WebSocket Sync: Server should record the browser cursor");
+    std::cout << "Test 'WebSocket Sync with One Frame per Message' passed." << std::endl;
+  }
+
+  // Test Case: Gossip Anti-Entropy Converges a Cluster
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    constexpr size_t cluster_size = 5;
+    std::vector<std::unique_ptr<Node>> nodes;
+    std::vector<std::unique_ptr<GossipNode<Node>>> gossipers;
+    for (size_t i = 0; i < cluster_size; ++i) {
+      CrdtNodeId id = i + 1;
+      nodes.push_back(std::make_unique<Node>(id));
+      nodes.back()->insert_or_update("record" + std::to_string(id), {{"owner", std::to_string(id)}});
+      gossipers.push_back(std::make_unique<GossipNode<Node>>(*nodes.back(), id, std::chrono::milliseconds(0), 42 + i));
+    }
+    // Ring membership: each node only knows its neighbours
+    for (size_t i = 0; i < cluster_size; ++i) {
+      gossipers[i]->add_peer(((i + 1) % cluster_size) + 1);
+      gossipers[i]->add_peer(((i + cluster_size - 1) % cluster_size) + 1);
+    }
+
+    size_t rounds = 0;
+    auto converged = [&]() {
+      for (const auto &node : nodes) {
+        if (node->len_live() != cluster_size) {
+          return false;
+        }
+      }
+      return true;
+    };
+    while (!converged() && rounds < 100) {
+      for (auto &gossiper : gossipers) {
+        auto peer = gossiper->next_round();
+        if (!peer) {
+          continue;
+        }
+        auto initiator = gossiper->open_session(*peer);
+        auto responder = gossipers[*peer - 1]->open_session(gossiper->node_id());
+        pump_sync_sessions(initiator, responder);
+        gossiper->close_session(initiator);
+        gossipers[*peer - 1]->close_session(responder);
+      }
+      ++rounds;
+    }
+
+    assert_true(converged(), "Gossip: Cluster should converge");
+    for (const auto &node : nodes) {
+      assert_true(node->get_data() == nodes[0]->get_data(), "Gossip: All nodes should hold the same data");
+    }
+    assert_true(!gossipers[0]->version_vector().empty(), "Gossip: Cursors should be recorded for contacted peers");
+    assert_true(gossipers[0]->remove_peer(2) && gossipers[0]->peers().size() == 1, "Gossip: Peers can be removed");
+    std::cout << "Test 'Gossip Anti-Entropy Converges a Cluster' passed." << std::endl;
+  }
+
+  // Test Case: Merkle Tree Finds Differing Records
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    Node node1(1);
+    Node node2(2);
+    for (int i = 0; i < 500; ++i) {
+      node1.insert_or_update("rec" + std::to_string(i), {{"v", std::to_string(i)}});
+    }
+    node2.merge_changes(node1.get_changes_since(0));
+    assert_true(MerkleTree<CrdtString>::build(node1).root() == MerkleTree<CrdtString>::build(node2).root(),
+                "Merkle: Converged replicas should have equal roots");
+
+    // Diverge on a handful of records while "offline"
+    node2.insert_or_update("rec7", {{"v", "changed"}});
+    node2.delete_record("rec300");
+    node1.insert_or_update("new", {{"v", "added"}});
+
+    auto tree1 = MerkleTree<CrdtString>::build(node1);
+    auto tree2 = MerkleTree<CrdtString>::build(node2);
+    auto differing = tree1.diff(tree2);
+    std::sort(differing.begin(), differing.end());
+    assert_true(differing == CrdtVector<CrdtString>({"new", "rec300", "rec7"}), "Merkle: Diff should find exactly the changed ids");
+
+    node1.merge_changes(node2.get_changes_for(differing));
+    node2.merge_changes(node1.get_changes_for(differing));
+    assert_true(node1.get_data() == node2.get_data(), "Merkle: Shipping differing records should converge the replicas");
+    assert_true(MerkleTree<CrdtString>::build(node1).root() == MerkleTree<CrdtString>::build(node2).root(),
+                "Merkle: Roots should match after reconciliation");
+    std::cout << "Test 'Merkle Tree Finds Differing Records' passed." << std::endl;
+  }
+
+  // Test Case: State Hash Verifies Convergence
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    auto changes1 = node1.insert_or_update("a", {{"x", "1"}, {"y", "2"}});
+    auto changes2 = node2.insert_or_update("b", {{"z", "3"}});
+    auto changes3 = node2.delete_record("b");
+
+    assert_true(node1.state_hash() != node2.state_hash(), "State Hash: Diverged replicas should differ");
+
+    // Apply the same changes in different orders
+    node1.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(changes2));
+    node1.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(changes3));
+    node2.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(changes1));
+    assert_true(node1.state_hash() == node2.state_hash(), "State Hash: Converged replicas should have equal hashes");
+
+    node1.insert_or_update("a", {{"x", "changed"}});
+    assert_true(node1.state_hash() != node2.state_hash(), "State Hash: A local edit should change the hash");
+    std::cout << "Test 'State Hash Verifies Convergence' passed." << std::endl;
+  }
+
+  // Test Case: Diff Two Instances
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node2.merge_changes(node1.insert_or_update("shared", {{"a", "1"}, {"b", "2"}}));
+    node2.merge_changes(node1.insert_or_update("deleted", {{"a", "1"}}));
+    assert_true(node1.diff(node2).empty(), "Diff: Converged replicas should not diverge");
+
+    node1.insert_or_update("shared", {{"a", "changed"}});
+    node1.insert_or_update("only_local", {{"a", "1"}});
+    node2.insert_or_update("only_remote", {{"a", "1"}});
+    node2.delete_record("deleted");
+
+    auto divergences = node1.diff(node2);
+    assert_true(divergences.size() == 4, "Diff: Should report four divergences");
+    size_t seen = 0;
+    for (const auto &divergence : divergences) {
+      if (divergence.record_id == "shared") {
+        assert_true(divergence.kind == DivergenceKind::ValueMismatch && divergence.col_name == "a",
+                    "Diff: 'shared.a' should be a value mismatch");
+        assert_true(divergence.local_value == "changed" && divergence.remote_value == "1",
+                    "Diff: Value mismatch should carry both values");
+      } else if (divergence.record_id == "only_local") {
+        assert_true(divergence.kind == DivergenceKind::MissingRemotely, "Diff: 'only_local' should be missing remotely");
+      } else if (divergence.record_id == "only_remote") {
+        assert_true(divergence.kind == DivergenceKind::MissingLocally, "Diff: 'only_remote' should be missing locally");
+      } else if (divergence.record_id == "deleted") {
+        assert_true(divergence.kind == DivergenceKind::TombstoneMismatch, "Diff: 'deleted' should be a tombstone mismatch");
+      }
+      ++seen;
+    }
+    assert_true(seen == 4, "Diff: All divergences should be recognised");
+    std::cout << "Test 'Diff Two Instances' passed." << std::endl;
+  }
+
+  // Test Case: Change Observers
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    CrdtVector<ChangeEvent<CrdtString, CrdtString>> events;
+    size_t id = node1.on_change([&](const ChangeEvent<CrdtString, CrdtString> &event) { events.push_back(event); });
+
+    node1.insert_or_update("r", {{"a", "1"}});
+    assert_true(events.size() == 1 && !events[0].old_value && events[0].new_value == "1" && !events[0].is_remote,
+                "Observers: Insert should report the new value");
+    node1.insert_or_update("r", {{"a", "2"}});
+    assert_true(events.size() == 2 && events[1].old_value == "1" && events[1].new_value == "2",
+                "Observers: Update should report the old and new values");
+
+    auto remote = node2.insert_or_update("r", {{"b", "x"}});
+    node1.merge_changes(std::move(remote));
+    assert_true(events.size() == 3 && events[2].is_remote && events[2].col_name == "b" && events[2].node_id == 2,
+                "Observers: Merge should report remote changes");
+
+    node1.delete_record("r");
+    assert_true(events.size() == 6, "Observers: Delete should report each column and the record");
+    assert_true(!events[5].col_name && events[3].old_value && !events[3].new_value,
+                "Observers: Delete should report old values and a record-level event");
+
+    assert_true(node1.remove_on_change(id), "Observers: Removing a registered observer should succeed");
+    node1.insert_or_update("s", {{"a", "1"}});
+    assert_true(events.size() == 6, "Observers: Removed observers should not be called");
+    std::cout << "Test 'Change Observers' passed." << std::endl;
+  }
+
+  // Test Case: Broadcast Change Feed
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    ChangeBroadcast<CrdtString, CrdtString> feed(4);
+    feed.attach(node);
+    auto ui = feed.subscribe();
+    auto persistence = feed.subscribe();
+
+    std::thread consumer([&] {
+      uint64_t received = 0;
+      while (auto event = persistence.recv()) {
+        ++received;
+      }
+      assert_true(received + persistence.take_lagged() == 8, "Broadcast: Blocking receiver should account for every event");
+    });
+
+    node.insert_or_update("r", {{"a", "1"}});
+    node.insert_or_update("r", {{"a", "2"}});
+    auto first = ui.try_recv();
+    assert_true(first && first->new_value == "1", "Broadcast: Receiver should see the first event");
+
+    for (int i = 0; i < 6; ++i) {
+      node.insert_or_update("s", {{"a", std::to_string(i)}});
+    }
+    feed.close();
+    consumer.join();
+
+    auto next = ui.try_recv();
+    assert_true(next && next->record_id == "s" && next->new_value == "2",
+                "Broadcast: Lagging receiver should resume at the oldest retained event");
+    assert_true(ui.take_lagged() == 3, "Broadcast: Lagging receiver should report skipped events");
+    std::cout << "Test 'Broadcast Change Feed' passed." << std::endl;
+  }
+
+  // Test Case: Undo and Redo
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    UndoManager<CRDT<CrdtString, CrdtString>> history(node1);
+
+    node2.merge_changes(history.insert_or_update("doc", {{"title", "Draft"}}));
+    node2.merge_changes(history.insert_or_update("doc", {{"title", "Final"}, {"author", "Ann"}}));
+
+    auto undone = history.undo();
+    assert_true(undone.size() == 2, "Undo: Should revert both columns of the last edit");
+    assert_true(*node1.get_value("doc", "title") == "Draft" && node1.get_value("doc", "author") == nullptr,
+                "Undo: Should restore previous values and remove added columns");
+    node2.merge_changes(std::move(undone));
+    assert_true(*node2.get_value("doc", "title") == "Draft" && node2.get_value("doc", "author") == nullptr,
+                "Undo: Reverting changes should replicate");
+
+    node2.merge_changes(history.redo());
+    assert_true(*node2.get_value("doc", "author") == "Ann", "Undo: Redo should re-apply the edit");
+    assert_true(node1.get_changes_since(0).size() == node2.get_changes_since(0).size(), "Undo: Nodes should converge");
+
+    history.delete_record("doc");
+    assert_true(!history.can_undo() && history.undo().empty(), "Undo: Deletions should not be undoable");
+    std::cout << "Test 'Undo and Redo' passed." << std::endl;
+  }
+
+  // Test Case: Column History
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node1.set_history_depth(3);
+
+    node1.insert_or_update("r", {{"a", "1"}});
+    node1.merge_changes(node2.insert_or_update("r", {{"a", "2"}, {"b", "x"}}));
+    node1.insert_or_update("r", {{"a", "3"}});
+    node1.delete_field("r", "a");
+
+    auto history = node1.history("r", "a");
+    assert_true(history.size() == 3, "History: Should keep the configured number of entries");
+    assert_true(history[0].value == "2" && history[0].version.node_id == 2, "History: Oldest kept entry should be the merged write");
+    assert_true(history[1].value == "3" && !history[2].value, "History: Newest entry should be the current state");
+    assert_true(node1.history("r", "missing").empty(), "History: Unknown columns should have no history");
+
+    node1.set_history_depth(1);
+    assert_true(node1.history("r", "a").size() == 1, "History: Lowering the depth should trim entries");
+    std::cout << "Test 'Column History' passed." << std::endl;
+  }
+
+  // Test Case: Time-Travel Reads
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    node.set_history_depth(10);
+
+    node.insert_or_update("r", {{"a", "1"}});
+    uint64_t v1 = node.get_clock().current_time();
+    node.insert_or_update("r", {{"a", "2"}, {"b", "x"}});
+    uint64_t v2 = node.get_clock().current_time();
+    node.insert_or_update("other", {{"a", "1"}});
+    node.delete_record("r");
+
+    auto at_v1 = node.as_of("r", v1);
+    assert_true(at_v1 && at_v1->fields.size() == 1 && at_v1->fields.at("a") == "1", "As Of: Should see the first write");
+    auto at_v2 = node.as_of("r", v2);
+    assert_true(at_v2 && at_v2->fields.at("a") == "2" && at_v2->fields.at("b") == "x",
+                "As Of: Should see values from before the deletion");
+    assert_true(!node.as_of("r", 0), "As Of: Record should not exist before its first write");
+    assert_true(!node.as_of("r", node.get_clock().current_time()), "As Of: Record should be absent after its deletion");
+
+    auto dataset = node.as_of(v2);
+    assert_true(dataset.size() == 1 && dataset.contains("r"), "As Of: Dataset view should contain records live at that version");
+    std::cout << "Test 'Time-Travel Reads' passed." << std::endl;
+  }
+
+  // Test Case: Compressed Change Batches
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    for (int i = 0; i < 50; ++i) {
+      node.insert_or_update("record" + std::to_string(i), {{"text", "the same repetitive payload"}});
+    }
+    auto changes = node.get_changes_since(0);
+
+    CrdtBytes bytes;
+    ByteWriter writer(bytes);
+    CrdtCompression codec = crdt_codec::choose_compression(crdt_codec::supported_compression());
+    crdt_codec::encode_changes_compressed(writer, changes, codec);
+    ByteReader reader(bytes);
+    auto decoded = crdt_codec::decode_changes_compressed<CrdtString, CrdtString>(reader);
+    assert_true(decoded.size() == changes.size() && reader.at_end(), "Compression: Batch should round-trip");
+
+    assert_true(crdt_codec::choose_compression(crdt_codec::compression_bit(CrdtCompression::None)) == CrdtCompression::None,
+                "Compression: Should fall back to no compression");
+    assert_true(crdt_codec::choose_compression(crdt_codec::compression_bit(CrdtCompression::Lz4) |
+                                               crdt_codec::compression_bit(CrdtCompression::Zstd)) == CrdtCompression::Zstd,
+                "Compression: Should prefer zstd");
+
+    CrdtBytes bogus;
+    ByteWriter(bogus).write_u8(0x7f);
+    ByteWriter(bogus).write_varint(0);
+    ByteWriter(bogus).write_varint(0);
+    bool threw = false;
+    try {
+      ByteReader bogus_reader(bogus);
+      crdt_codec::decode_changes_compressed<CrdtString, CrdtString>(bogus_reader);
+    } catch (const CrdtDecodeError &) {
+      threw = true;
+    }
+    assert_true(threw, "Compression: Unknown codecs should be rejected");
+    std::cout << "Test 'Compressed Change Batches' passed." << std::endl;
+  }
+
+  // Test Case: Superseded Changes Are Not Sent
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    CrdtVector<Change<CrdtString, CrdtString>> accumulated;
+    for (int i = 0; i < 10; ++i) {
+      auto changes = node.insert_or_update("doc", {{"title", "v" + std::to_string(i)}});
+      accumulated.insert(accumulated.end(), changes.begin(), changes.end());
+    }
+    node.insert_or_update("gone", {{"a", "1"}});
+    node.delete_record("gone");
+
+    auto changes = node.get_changes_since(0);
+    assert_true(changes.size() == 2, "Coalesce: Should send one change per column and one per deleted record");
+    for (const auto &change : changes) {
+      if (change.record_id == "doc") {
+        assert_true(change.value == "v9" && change.col_version == 10, "Coalesce: Should send only the latest value");
+      } else {
+        assert_true(!change.col_name, "Coalesce: Deleted record should only send its deletion");
+      }
+    }
+
+    CRDT<CrdtString, CrdtString>::compress_changes(accumulated);
+    assert_true(accumulated.size() == 1 && accumulated[0].value == "v9", "Coalesce: Accumulated batches should compress");
+    std::cout << "Test 'Superseded Changes Are Not Sent' passed." << std::endl;
+  }
+
+  // Test Case: Duplicate and Reordered Delivery
+  {
+    CRDT<CrdtString, CrdtString> source(1);
+    source.insert_or_update("r", {{"a", "1"}});
+    source.insert_or_update("r", {{"a", "2"}, {"b", "x"}});
+    source.insert_or_update("s", {{"a", "1"}});
+    source.delete_record("s");
+    auto batch = source.get_changes_since(0);
+
+    CRDT<CrdtString, CrdtString> in_order(2);
+    in_order.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(batch));
+
+    CRDT<CrdtString, CrdtString> shuffled(3);
+    auto reversed = batch;
+    std::reverse(reversed.begin(), reversed.end());
+    shuffled.merge_changes(std::move(reversed));
+    shuffled.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(batch));
+    auto accepted = shuffled.merge_changes<true>(CrdtVector<Change<CrdtString, CrdtString>>(batch));
+
+    assert_true(accepted.empty(), "Delivery: Duplicated batches should be rejected");
+    assert_true(in_order.state_hash() == shuffled.state_hash(), "Delivery: Reordered and duplicated batches should converge");
+    assert_true(shuffled.max_seen_versions().at(1) == source.get_clock().current_time(),
+                "Delivery: Seen versions should track the highest version per node");
+    for (const auto &change : batch) {
+      assert_true(shuffled.is_applied(change), "Delivery: Merged changes should report as applied");
+    }
+    auto fresh = source.insert_or_update("r", {{"a", "3"}});
+    assert_true(!shuffled.is_applied(fresh[0]), "Delivery: New changes should not report as applied");
+    std::cout << "Test 'Duplicate and Reordered Delivery' passed." << std::endl;
+  }
+
+  // Test Case: One Clock Tick per Operation
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    auto first = node1.insert_or_update("r", {{"a", "1"}, {"b", "1"}, {"c", "1"}});
+    assert_true(first[0].db_version == first[1].db_version && first[1].db_version == first[2].db_version,
+                "Clock Ticks: All columns of one operation should share a db_version");
+    auto second = node1.insert_or_update("r", {{"a", "2"}});
+    assert_true(second[0].db_version == first[0].db_version + 1,
+                "Clock Ticks: Each operation should tick the clock once");
+
+    node1.delete_record("r");
+    uint64_t before = node1.get_clock().current_time();
+    assert_true(node1.insert_or_update("r", {{"a", "3"}}).empty(),
+                "Clock Ticks: Writes to deleted records should be ignored");
+    assert_true(node1.get_clock().current_time() == before, "Clock Ticks: Ignored writes should not tick the clock");
+
+    node2.insert_or_update("x", {{"a", "1"}});
+    node2.merge_changes(node1.get_changes_since(0));
+    auto after_merge = node2.insert_or_update("x", {{"a", "2"}});
+    assert_true(after_merge[0].db_version > node1.get_clock().current_time(),
+                "Clock Ticks: The clock should stay monotonic across merges");
+    std::cout << "Test 'One Clock Tick per Operation' passed." << std::endl;
+  }
+
+  // Test Case: Per-Site Sequence Numbers
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    Node node1(1);
+    Node node2(2);
+    auto first = node1.insert_or_update("r", {{"a", "1"}, {"b", "1"}});
+    assert_true(first[0].seq != 0 && first[0].seq == first[1].seq,
+                "Sequence: All writes of one operation should share a sequence number");
+    for (int i = 0; i < 50; ++i) {
+      node2.insert_or_update("x", {{"a", std::to_string(i)}});
+    }
+    node1.merge_changes(node2.get_changes_since(0));
+    auto second = node1.delete_field("r", "b");
+    assert_true(second[0].seq == first[0].seq + 1 && second[0].db_version > first[0].db_version + 1,
+                "Sequence: Merges should move the clock but not the sequence");
+    auto decoded = crdt_codec::decode_changes<CrdtString, CrdtString>(crdt_codec::encode_changes(second));
+    assert_true(decoded[0].seq == second[0].seq, "Sequence: Should be encoded with the change");
+
+    // Even a merge rule that always takes the remote write recognizes a write it already holds
+    struct RemoteWins {
+      bool operator()(const Change<CrdtString, CrdtString> &, const Change<CrdtString, CrdtString> &) const {
+        return true;
+      }
+    };
+    CRDT<CrdtString, CrdtString, RemoteWins> eager(3);
+    eager.merge_changes(node1.get_changes_since(0));
+    uint64_t rejected = eager.stats().changes_rejected;
+    auto again = eager.merge_changes<true>(node1.get_changes_since(0));
+    assert_true(again.empty() && eager.stats().changes_rejected == rejected + node1.get_changes_since(0).size() &&
+                    eager.is_applied(second[0]),
+                "Sequence: Writes already held should be recognized as duplicates");
+
+    Change<CrdtString, CrdtString> earlier("r", "a", std::nullopt, 1, 5, 1);
+    earlier.seq = 3;
+    Change<CrdtString, CrdtString> later = earlier;
+    later.seq = 4;
+    DefaultMergeRule<CrdtString, CrdtString> rule;
+    assert_true(rule(earlier, later) && !rule(later, earlier),
+                "Sequence: Should break ties between writes of one node");
+
+    Node restored(1, node1.get_changes_since(0));
+    auto third = restored.insert_or_update("r", {{"a", "2"}});
+    assert_true(third[0].seq > second[0].seq, "Sequence: A restored node should not reuse sequence numbers");
+    std::cout << "Test 'Per-Site Sequence Numbers' passed." << std::endl;
+  }
+
+  // Test Case: Restore from Parts
+  {
+    CRDT<CrdtString, CrdtString> original(1);
+    CRDT<CrdtString, CrdtString> peer(2);
+    original.insert_or_update("r", {{"a", "1"}});
+    original.insert_or_update("r", {{"a", "2"}});
+    original.insert_or_update("gone", {{"a", "1"}});
+    original.delete_record("gone");
+    peer.merge_changes(original.get_changes_since(0));
+
+    auto restored = CRDT<CrdtString, CrdtString>::from_parts(1, original.get_clock().current_time(), original.get_data(),
+                                                             original.get_tombstones());
+    assert_true(restored.get_clock().current_time() == original.get_clock().current_time(),
+                "From Parts: Clock should resume where it left off");
+    assert_true(restored.state_hash() == original.state_hash(), "From Parts: State should be restored exactly");
+    assert_true(restored.is_tombstoned("gone"), "From Parts: Tombstones should be restored");
+
+    auto next = restored.insert_or_update("r", {{"a", "3"}});
+    assert_true(next[0].db_version > original.get_clock().current_time() && next[0].col_version == 3,
+                "From Parts: New changes should continue the versions");
+    peer.merge_changes(std::move(next));
+    assert_true(*peer.get_value("r", "a") == "3", "From Parts: Changes after restoring should win over older ones");
+
+    bool threw = false;
+    try {
+      CrdtMap<CrdtString, CrdtTombstone> bogus_tombstones{{"r", CrdtTombstone{ColumnVersion(1, 1, 1), 0}}};
+      CRDT<CrdtString, CrdtString>::from_parts(1, 0, original.get_data(), bogus_tombstones);
+    } catch (const std::invalid_argument &) {
+      threw = true;
+    }
+    assert_true(threw, "From Parts: Inconsistent tombstones should be rejected");
+    std::cout << "Test 'Restore from Parts' passed." << std::endl;
+  }
+
+  // Test Case: Replay Change Log
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    CrdtVector<Change<CrdtString, CrdtString>> log;
+    auto append = [&](CrdtVector<Change<CrdtString, CrdtString>> changes) {
+      log.insert(log.end(), changes.begin(), changes.end());
+    };
+    for (int i = 0; i < 1500; ++i) {
+      append(node1.insert_or_update("r" + std::to_string(i % 700), {{"a", std::to_string(i)}}));
+    }
+    append(node2.insert_or_update("r1", {{"b", "x"}}));
+    append(node1.delete_record("r2"));
+    node1.merge_changes(node2.get_changes_since(0));
+
+    std::reverse(log.begin(), log.end());
+    auto replayed = CRDT<CrdtString, CrdtString>::from_changes(3, log);
+    assert_true(replayed.state_hash() == node1.state_hash(), "Replay: Replaying an unordered log should converge");
+    assert_true(*replayed.get_value("r5", "a") == "1405", "Replay: Superseded changes in the log should lose");
+
+    auto from_view = CRDT<CrdtString, CrdtString>::from_changes(
+        3, log | std::views::filter([](const auto &change) { return change.record_id != "r1"; }));
+    assert_true(!from_view.contains_record("r1") && from_view.contains_record("r3"), "Replay: Should accept range views");
+    std::cout << "Test 'Replay Change Log' passed." << std::endl;
+  }
+
+  // Test Case: Stats
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node1.insert_or_update("a", {{"x", "1"}, {"y", "1"}});
+    node1.insert_or_update("b", {{"x", "1"}});
+    node1.delete_record("b");
+    node2.insert_or_update("a", {{"x", "2"}});
+    node2.insert_or_update("a", {{"x", "3"}});
+
+    node1.merge_changes(node2.get_changes_since(0));
+    node1.merge_changes(node2.get_changes_since(0));
+    auto stats = node1.stats();
+    assert_true(stats.live_records == 1 && stats.tombstones == 1 && stats.columns == 2, "Stats: Should count records");
+    assert_true(stats.clock == node1.get_clock().current_time(), "Stats: Should report the clock");
+    assert_true(stats.merges == 2 && stats.changes_accepted == 1 && stats.changes_rejected == 1,
+                "Stats: Should count merged changes");
+    assert_true(stats.conflicts_resolved == 1, "Stats: Should count conflicting versions");
+    assert_true(stats.bytes_estimate > 0, "Stats: Should estimate memory");
+    std::cout << "Test 'Stats' passed." << std::endl;
+  }
+
+  // Test Case: Memory Estimation
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    size_t empty = node.estimated_memory_bytes();
+    node.insert_or_update("r", {{"a", "short"}});
+    size_t one_short = node.estimated_memory_bytes();
+    assert_true(one_short > empty, "Memory: Records should add to the estimate");
+
+    node.insert_or_update("r", {{"a", CrdtString(10000, 'x')}});
+    size_t one_long = node.estimated_memory_bytes();
+    assert_true(one_long >= one_short + 10000, "Memory: Should account for heap-allocated values");
+
+    node.delete_record("r");
+    assert_true(node.estimated_memory_bytes() < one_long, "Memory: Deleting should release value memory");
+    assert_true(node.stats().bytes_estimate == node.estimated_memory_bytes(), "Memory: Stats should report the estimate");
+    std::cout << "Test 'Memory Estimation' passed." << std::endl;
+  }
+
+  // Test Case: Capacity Management
+  {
+    auto node = CRDT<CrdtString, CrdtString>::with_capacity(1, 5000);
+    size_t reserved = node.estimated_memory_bytes();
+    assert_true(reserved > CRDT<CrdtString, CrdtString>(1).estimated_memory_bytes(), "Capacity: Should pre-size the tables");
+    for (int i = 0; i < 5000; ++i) {
+      node.insert_or_update(std::to_string(i), {{"a", "1"}});
+    }
+    assert_true(node.len_live() == 5000, "Capacity: Records should be stored normally");
+
+    CRDT<CrdtString, CrdtString> restored = CRDT<CrdtString, CrdtString>::from_changes(2, node.get_changes_since(0));
+    restored.reserve(100000);
+    size_t grown = restored.estimated_memory_bytes();
+    restored.shrink_to_fit();
+    assert_true(restored.estimated_memory_bytes() < grown, "Capacity: Shrinking should release table memory");
+    assert_true(restored.state_hash() == node.state_hash(), "Capacity: Resizing should not change the state");
+    std::cout << "Test 'Capacity Management' passed." << std::endl;
+  }
+
+  // Test Case: Borrowed Change Extraction
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    node.insert_or_update("doc", {{"body", CrdtString(4096, 'x')}, {"title", "t"}});
+    node.insert_or_update("gone", {{"a", "1"}});
+    node.delete_record("gone");
+    node.insert_or_update("doc", {{"title", "u"}});
+
+    auto owned = node.get_changes_since(0);
+    auto refs = node.get_change_refs_since(0);
+    assert_true(refs.size() == owned.size(), "Change Refs: Should return the same number of changes");
+    assert_true(crdt_codec::encode_changes(refs) == crdt_codec::encode_changes(owned),
+                "Change Refs: Should encode identically to owned changes");
+    for (const auto &ref : refs) {
+      if (ref.col_name && *ref.col_name == "body") {
+        assert_true(ref.value == node.get_value("doc", "body"), "Change Refs: Values should be borrowed, not copied");
+      }
+    }
+
+    auto since = node.get_change_refs_since(owned[0].local_db_version);
+    CrdtVector<Change<CrdtString, CrdtString>> converted;
+    for (const auto &ref : since) {
+      converted.push_back(ref.to_owned());
+    }
+    assert_true(converted.size() == node.get_changes_since(owned[0].local_db_version).size(),
+                "Change Refs: Should respect the version cursor");
+    auto last = node.get_changes_since(owned.back().local_db_version - 1);
+    assert_true(node.get_change_refs_since(owned.back().local_db_version - 1).size() == last.size(),
+                "Change Refs: Incremental reads should only visit the latest writes");
+
+    CRDT<CrdtString, CrdtString> layer(4, std::make_shared<CRDT<CrdtString, CrdtString>>(node));
+    layer.insert_or_update("doc", {{"title", "v"}});
+    assert_true(layer.get_change_refs_since(0).size() == layer.get_changes_since(0).size(),
+                "Change Refs: Records in a layer should shadow the parent's");
+
+    CRDT<CrdtString, CrdtString> clocked(2);
+    clocked.set_wall_clock([] { return uint64_t{12345}; });
+    clocked.insert_or_update("r", {{"a", "1"}});
+    clocked.delete_record("r");
+    CrdtVector<Change<CrdtString, CrdtString>> deletions;
+    for (const auto &ref : clocked.get_change_refs_since(0)) {
+      deletions.push_back(ref.to_owned());
+    }
+    CRDT<CrdtString, CrdtString> receiver(3);
+    receiver.merge_changes(std::move(deletions));
+    assert_true(receiver.deletion_info("r") && receiver.deletion_info("r")->wall_time == 12345,
+                "Change Refs: Owned copies should keep the deletion time");
+    std::cout << "Test 'Borrowed Change Extraction' passed." << std::endl;
+  }
+
+  // Test Case: Copy-on-Write Snapshots
+  {
+    CRDT<CrdtString, CrdtString> writer(1);
+    CRDT<CrdtString, CrdtString> peer(2);
+    writer.insert_or_update("a", {{"v", "1"}});
+    writer.insert_or_update("b", {{"v", "1"}});
+
+    auto first = writer.snapshot();
+    assert_true(writer.snapshot() == first, "Snapshot: Without writes the same snapshot should be returned");
+    writer.insert_or_update("a", {{"v", "2"}});
+    writer.delete_record("b");
+    writer.merge_changes(peer.insert_or_update("c", {{"v", "1"}}));
+
+    assert_true(*first->get_value("a", "v") == "1" && first->contains_record("b") && !first->contains_record("c"),
+                "Snapshot: Snapshot should not see later writes");
+    assert_true(*writer.get_value("a", "v") == "2" && !writer.contains_record("b") && writer.contains_record("c"),
+                "Snapshot: Writer should see its own writes");
+
+    std::thread reader([first] {
+      for (int i = 0; i < 1000; ++i) {
+        assert_true(first->len_live() == 2, "Snapshot: Concurrent reads should stay consistent");
+      }
+    });
+    for (int i = 0; i < 20; ++i) {
+      writer.insert_or_update("a", {{"v", std::to_string(i)}});
+      writer.snapshot();
+    }
+    reader.join();
+
+    peer.merge_changes(writer.get_changes_since(0));
+    assert_true(peer.state_hash() == writer.state_hash(), "Snapshot: Layered state should still sync");
+    assert_true(writer.snapshot()->state_hash() == writer.state_hash(), "Snapshot: Folded layers should keep the state");
+    std::cout << "Test 'Copy-on-Write Snapshots' passed." << std::endl;
+  }
+
+  // Test Case: cr-sqlite Changeset Conversion
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    node1.insert_or_update("abc", {{"title", "hello"}});
+    node1.insert_or_update("gone", {{"title", "bye"}});
+    node1.delete_record("gone");
+
+    auto rows = crdt_crsqlite::to_rows(node1.get_changes_since(0), "todos");
+    assert_true(rows.size() == 2, "cr-sqlite: Should produce one row per change");
+    for (const auto &row : rows) {
+      assert_true(row.site_id.size() == 16 && row.site_id.back() == 1, "cr-sqlite: Site id should encode the node id");
+      if (row.cid == crdt_crsqlite::SENTINEL_CID) {
+        assert_true(row.cl % 2 == 0, "cr-sqlite: Deletions should have an even causal length");
+      } else {
+        assert_true(row.pk == CrdtBytes({0x01, 0x0b, 0x03, 'a', 'b', 'c'}), "cr-sqlite: Text keys should use the packed format");
+      }
+    }
+
+    crdt_crsqlite::ChangeRow<CrdtString> other_table;
+    other_table.table = "users";
+    rows.push_back(other_table);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node2.merge_changes(crdt_crsqlite::from_rows<CrdtString, CrdtString>(rows, "todos"));
+    assert_true(node2.state_hash() == node1.state_hash(), "cr-sqlite: Round trip should converge");
+
+    for (int64_t key : {0LL, 1LL, 300LL, -5LL, 1LL << 40}) {
+      auto packed = crdt_crsqlite::PrimaryKey<int64_t>::pack(key);
+      assert_true(crdt_crsqlite::PrimaryKey<int64_t>::unpack(packed) == key, "cr-sqlite: Integer keys should round-trip");
+    }
+    std::cout << "Test 'cr-sqlite Changeset Conversion' passed." << std::endl;
+  }
+
+  // Test Case: Change Sink
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    CrdtVector<CrdtBytes> messages;
+    EncodedChangeSink<CrdtString, CrdtString> sink([&](const CrdtBytes &message) { messages.push_back(message); });
+    size_t id = sink.attach(node1);
+
+    node1.insert_or_update<false>("r1", {{"a", "1"}, {"b", "2"}});
+    node1.delete_field<false>("r1", "b");
+    assert_true(messages.size() == 2, "Change Sink: Each local operation should send one batch");
+    assert_true(crdt_codec::decode_changes<CrdtString, CrdtString>(messages[0]).size() == 2,
+                "Change Sink: A batch should hold every change of the operation");
+
+    node2.insert_or_update("r2", {{"a", "remote"}});
+    node1.merge_changes(node2.get_changes_since(0));
+    node1.merge_changes(node2.get_changes_since(0));
+    assert_true(messages.size() == 3, "Change Sink: Merges should send accepted changes only");
+    node1.delete_record("r2");
+
+    CRDT<CrdtString, CrdtString> consumer(3);
+    for (const auto &message : messages) {
+      consumer.merge_changes(crdt_codec::decode_changes<CrdtString, CrdtString>(message));
+    }
+    assert_true(consumer.state_hash() == node1.state_hash(), "Change Sink: Consumers should be able to rebuild the state");
+
+    assert_true(node1.remove_on_change(id), "Change Sink: Detaching should succeed");
+    node1.insert_or_update("r3", {{"a", "x"}});
+    assert_true(messages.size() == 4, "Change Sink: Detached sinks should not receive batches");
+    std::cout << "Test 'Change Sink' passed." << std::endl;
+  }
+
+  // Test Case: Write Policy
+  {
+    CrdtWritePolicy policy;
+    policy.protect("role", {1}).restrict(3, {"name"});
+    CRDT<CrdtString, CrdtString> admin(1);
+    CRDT<CrdtString, CrdtString> user(2);
+    CRDT<CrdtString, CrdtString> guest(3);
+    admin.set_write_policy(policy);
+    user.set_write_policy(policy);
+    guest.set_write_policy(policy);
+
+    admin.insert_or_update("u", {{"role", "member"}, {"name", "Ann"}});
+    user.merge_changes(admin.get_changes_since(0));
+    bool denied = false;
+    try {
+      user.insert_or_update("u", {{"name", "Bob"}, {"role", "admin"}});
+    } catch (const std::invalid_argument &) {
+      denied = true;
+    }
+    assert_true(denied && *user.get_value("u", "name") == "Ann", "Write Policy: Denied local writes should change nothing");
+
+    // A misbehaving replica without the policy produces changes every other replica drops
+    CRDT<CrdtString, CrdtString> rogue(2);
+    rogue.merge_changes(admin.get_changes_since(0));
+    rogue.insert_or_update("u", {{"role", "admin"}, {"name", "Bob"}});
+    rogue.delete_record("other");
+    rogue.insert_or_update("x", {{"name", "y"}});
+    guest.insert_or_update("g", {{"name", "Gus"}});
+    auto from_rogue = rogue.get_changes_since(0);
+    auto from_guest = guest.get_changes_since(0);
+    admin.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(from_rogue));
+    admin.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(from_guest));
+    user.merge_changes(std::move(from_guest));
+    user.merge_changes(std::move(from_rogue));
+    assert_true(*admin.get_value("u", "role") == "member" && *admin.get_value("u", "name") == "Bob",
+                "Write Policy: Only permitted columns should be merged");
+    assert_true(admin.stats().changes_denied == 1, "Write Policy: Denied changes should be counted");
+    assert_true(admin.state_hash() == user.state_hash(), "Write Policy: Replicas with the same policy should converge");
+
+    denied = false;
+    try {
+      guest.delete_record("g");
+    } catch (const std::invalid_argument &) {
+      denied = true;
+    }
+    assert_true(denied && !guest.is_tombstoned("g"), "Write Policy: Restricted nodes should not delete records");
+    std::cout << "Test 'Write Policy' passed." << std::endl;
+  }
+
+  // Test Case: Read-Only Replica
+  {
+    CRDT<CrdtString, CrdtString> leader(1);
+    CRDT<CrdtString, CrdtString> follower(2);
+    follower.set_read_only(true);
+    leader.insert_or_update("r", {{"a", "1"}});
+    follower.merge_changes(leader.get_changes_since(0));
+    assert_true(*follower.get_value("r", "a") == "1", "Read-Only: Merges should be applied");
+
+    size_t rejected = 0;
+    uint64_t clock = follower.get_clock().current_time();
+    auto attempt = [&](auto &&write) {
+      try {
+        write();
+      } catch (const std::logic_error &) {
+        ++rejected;
+      }
+    };
+    attempt([&] { follower.insert_or_update("r", {{"a", "2"}}); });
+    attempt([&] { follower.delete_field("r", "a"); });
+    attempt([&] { follower.delete_record("r"); });
+    assert_true(rejected == 3, "Read-Only: Local operations should throw");
+    assert_true(follower.get_clock().current_time() == clock && *follower.get_value("r", "a") == "1",
+                "Read-Only: Rejected operations should not create versions");
+
+    follower.set_read_only(false);
+    follower.insert_or_update("r", {{"a", "2"}});
+    assert_true(*follower.get_value("r", "a") == "2", "Read-Only: Writes should succeed once disabled");
+    std::cout << "Test 'Read-Only Replica' passed." << std::endl;
+  }
+
+  // Test Case: Schema Validation
+  {
+    CrdtSchema<CrdtString> schema;
+    schema.column("name", {crdt_column_type<CrdtString, CrdtString>(), false, std::nullopt})
+        .column("age", {crdt_column_type<int64_t, CrdtString>(), true, std::nullopt})
+        .column("done", {crdt_column_type<bool, CrdtString>(), false, "false"})
+        .strict()
+        .invalid_policy(CrdtInvalidChangePolicy::Quarantine);
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node1.set_schema(schema);
+
+    node1.insert_or_update("r", {{"name", "Ann"}, {"age", "41"}});
+    assert_true(*node1.get_value("r", "done") == "false", "Schema: Defaults should be filled in on insert");
+    auto rejects = [&](auto &&write) {
+      try {
+        write();
+      } catch (const std::invalid_argument &) {
+        return true;
+      }
+      return false;
+    };
+    assert_true(rejects([&] { node1.insert_or_update("r", {{"age", "forty"}}); }),
+                "Schema: Mistyped values should be rejected");
+    assert_true(rejects([&] { node1.insert_or_update("r", {{"color", "red"}}); }),
+                "Schema: Unknown columns should be rejected");
+    assert_true(rejects([&] { node1.insert_or_update("s", {{"age", "3"}}); }),
+                "Schema: Required columns should be enforced");
+    assert_true(rejects([&] { node1.delete_field("r", "name"); }), "Schema: Required columns should not be deleted");
+    node1.delete_field("r", "age");
+
+    // An unconstrained client produces changes of every shape
+    node2.insert_or_update("r", {{"name", "Bob"}, {"age", "old"}, {"color", "red"}});
+    node2.delete_field("r", "name");
+    node1.merge_changes(node2.get_changes_since(0));
+    assert_true(*node1.get_value("r", "name") == "Ann" && !node1.get_value("r", "age") && !node1.get_value("r", "color"),
+                "Schema: Invalid remote changes should not be merged");
+    assert_true(node1.stats().changes_invalid == 3 && node1.quarantined().size() == 3,
+                "Schema: Invalid remote changes should be quarantined");
+    assert_true(node1.take_quarantined().size() == 3 && node1.quarantined().empty(), "Schema: Quarantine should drain");
+    std::cout << "Test 'Schema Validation' passed." << std::endl;
+  }
+
+  // Test Case: Replicated Schema Migrations
+  {
+    using Migration = CrdtMigration<CrdtString>;
+    CRDT<CrdtString, CrdtString> upgraded(1);
+    CRDT<CrdtString, CrdtString> old_client(2);
+    CRDT<CrdtString, CrdtString> observer(3);
+    for (auto *node : {&upgraded, &old_client, &observer}) {
+      node->enable_migrations("__migrations__");
+    }
+    upgraded.insert_or_update("r1", {{"name", "Ann"}, {"legacy", "x"}});
+    old_client.merge_changes(upgraded.get_changes_since(0));
+
+    upgraded.migrate(Migration::rename_column(1, "name", "full_name"));
+    upgraded.migrate(Migration::add_column(2, "status", "active"));
+    upgraded.migrate(Migration::drop_column(3, "legacy"));
+    bool rejected = false;
+    try {
+      upgraded.migrate(Migration::drop_column(5, "other"));
+    } catch (const std::invalid_argument &) {
+      rejected = true;
+    }
+    assert_true(rejected, "Migrations: Versions should be consecutive");
+    assert_true(upgraded.schema_version() == 3 && *upgraded.get_value("r1", "full_name") == "Ann" &&
+                    *upgraded.get_value("r1", "status") == "active" && !upgraded.get_value("r1", "legacy"),
+                "Migrations: Should be applied locally");
+
+    // The old client keeps writing the old shape while the migrations are in flight
+    old_client.insert_or_update("r1", {{"name", "Ann B."}});
+    old_client.insert_or_update("r2", {{"name", "Cat"}, {"legacy", "y"}});
+    upgraded.insert_or_update("r3", {{"full_name", "Dan"}});
+
+    auto from_upgraded = upgraded.get_changes_since(0);
+    auto from_old = old_client.get_changes_since(0);
+    upgraded.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(from_old));
+    old_client.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(from_upgraded));
+    observer.merge_changes(std::move(from_old));
+    observer.merge_changes(std::move(from_upgraded));
+
+    assert_true(old_client.schema_version() == 3 && observer.schema_version() == 3, "Migrations: Should replicate");
+    assert_true(*upgraded.get_value("r1", "full_name") == "Ann B." && !upgraded.get_value("r2", "legacy"),
+                "Migrations: Writes under the old schema should be migrated on merge");
+    assert_true(*upgraded.get_value("r2", "status") == "active" && *upgraded.get_value("r3", "status") == "active",
+                "Migrations: New records should get added defaults");
+    assert_true(upgraded.state_hash() == old_client.state_hash() && upgraded.state_hash() == observer.state_hash(),
+                "Migrations: Replicas should converge regardless of order");
+    std::cout << "Test 'Replicated Schema Migrations' passed." << std::endl;
+  }
+
+  // Test Case: Validator
+  {
+    auto clamp_scores = [](const CrdtString &, const std::optional<CrdtString> &col_name, std::optional<CrdtString> &value,
+                           const CrdtNodeId &) {
+      if (!col_name || *col_name != "score" || !value) {
+        return !col_name || *col_name != "locked";
+      }
+      int score = 0;
+      auto [end, error] = std::from_chars(value->data(), value->data() + value->size(), score);
+      if (error != std::errc() || end != value->data() + value->size()) {
+        return false;
+      }
+      value = std::to_string(std::clamp(score, 0, 100));
+      return true;
+    };
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    CRDT<CrdtString, CrdtString> unchecked(3);
+    node1.set_validator(clamp_scores);
+    node2.set_validator(clamp_scores);
+
+    auto changes = node1.insert_or_update("r", {{"score", "250"}});
+    assert_true(*node1.get_value("r", "score") == "100" && changes[0].value == "100",
+                "Validator: Local writes should be rewritten");
+    unchecked.insert_or_update("s", {{"score", "-5"}, {"locked", "x"}, {"name", "ok"}});
+    unchecked.insert_or_update("t", {{"score", "abc"}});
+    node1.merge_changes(unchecked.get_changes_since(0));
+    node2.merge_changes(unchecked.get_changes_since(0));
+    node2.merge_changes(node1.get_changes_since(0));
+    assert_true(*node1.get_value("s", "score") == "0" && !node1.get_value("s", "locked") && !node1.get_value("t", "score"),
+                "Validator: Incoming changes should be rewritten or dropped");
+    assert_true(node1.stats().changes_invalid == 2, "Validator: Dropped changes should be counted");
+    assert_true(node1.state_hash() == node2.state_hash(), "Validator: Replicas with the same validator should converge");
+
+    bool rejected = false;
+    try {
+      node1.insert_or_update("r", {{"score", "many"}});
+    } catch (const std::invalid_argument &) {
+      rejected = true;
+    }
+    assert_true(rejected && *node1.get_value("r", "score") == "100", "Validator: Rejected local writes should throw");
+    std::cout << "Test 'Validator' passed." << std::endl;
+  }
+
+  // Test Case: Unique Constraint
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node1.add_unique_constraint("username");
+    node2.add_unique_constraint("username");
+    node1.insert_or_update("u1", {{"username", "alice"}});
+    node2.insert_or_update("u2", {{"username", "alice"}});
+    assert_true(node2.unique_conflicts("username").empty(), "Unique: A single claim should not conflict");
+
+    node1.merge_changes(node2.get_changes_since(0));
+    node2.merge_changes(node1.get_changes_since(0));
+    assert_true(*node1.unique_owner("username", "alice") == "u1" && *node2.unique_owner("username", "alice") == "u1",
+                "Unique: Replicas should agree on the winning claim");
+    assert_true(node1.unique_conflicts("username") == CrdtVector<CrdtString>{"u2"} &&
+                    node2.unique_conflicts("username") == CrdtVector<CrdtString>{"u2"},
+                "Unique: Replicas should agree on the losing claim");
+
+    auto rename = [](const CrdtString &value, const CrdtString &record_id) { return value + "-" + record_id; };
+    assert_true(node1.resolve_unique_conflicts("username", rename).empty(),
+                "Unique: Only the losing claim's node should resolve it");
+    auto fixes = node2.resolve_unique_conflicts("username", rename);
+    node1.merge_changes(std::move(fixes));
+    assert_true(node1.unique_conflicts("username").empty() && node2.unique_conflicts("username").empty(),
+                "Unique: Resolved conflicts should clear");
+    assert_true(*node1.get_value("u2", "username") == "alice-u2" && node1.state_hash() == node2.state_hash(),
+                "Unique: Replicas should converge after resolution");
+
+    node2.delete_record("u1");
+    assert_true(*node2.unique_owner("username", "alice-u2") == "u2" && !node2.unique_owner("username", "alice"),
+                "Unique: Deleted records should release their values");
+    std::cout << "Test 'Unique Constraint' passed." << std::endl;
+  }
+
+  // Test Case: Record Resurrection
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node1.insert_or_update("item", {{"title", "Old"}, {"tag", "a"}});
+    node2.merge_changes(node1.get_changes_since(0));
+    auto stale_delete = node1.delete_record("item");
+    auto stale_write = node2.insert_or_update("item", {{"title", "Concurrent"}});
+    node2.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(stale_delete));
+    assert_true(node1.insert_or_update("item", {{"title", "x"}}).empty(), "Resurrection: Plain writes should not revive");
+
+    auto revival = node1.revive_record("item", {{"title", "New"}});
+    assert_true(revival.size() == 2 && !revival[0].col_name, "Resurrection: Should replicate the revival and its fields");
+    assert_true(node1.contains_record("item") && !node1.get_value("item", "tag"),
+                "Resurrection: The new incarnation should start without the old fields");
+
+    // Deliver the new incarnation before the stale changes, with the field ahead of the revival
+    CRDT<CrdtString, CrdtString> node3(3);
+    node3.merge_changes({revival[1]});
+    assert_true(*node3.get_value("item", "title") == "New", "Resurrection: Writes of a newer incarnation should revive");
+    node3.merge_changes({revival[0]});
+    node3.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(stale_delete));
+    node3.merge_changes(std::move(stale_write));
+    node2.merge_changes(node1.get_changes_since(0));
+    node1.merge_changes(node2.get_changes_since(0));
+    for (auto *node : {&node1, &node2, &node3}) {
+      assert_true(node->contains_record("item") && *node->get_value("item", "title") == "New",
+                  "Resurrection: Stale deletes and writes should not override the revival");
+    }
+    assert_true(node1.state_hash() == node2.state_hash() && node1.state_hash() == node3.state_hash(),
+                "Resurrection: Replicas should converge");
+
+    node2.delete_record("item");
+    node1.merge_changes(node2.get_changes_since(0));
+    assert_true(!node1.contains_record("item"), "Resurrection: A revived record can be deleted again");
+    auto rows = crdt_crsqlite::to_rows(node1.revive_record("item"), "items");
+    assert_true(rows.size() == 1 && rows[0].cl == 5, "Resurrection: Revivals should map to an odd causal length");
+    node2.merge_changes(crdt_crsqlite::from_rows<CrdtString, CrdtString>(rows, "items"));
+    assert_true(node2.contains_record("item") && node2.state_hash() == node1.state_hash(),
+                "Resurrection: Revivals should round-trip through cr-sqlite rows");
+    std::cout << "Test 'Record Resurrection' passed." << std::endl;
+  }
+
+  // Test Case: Deletion Info
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node1.set_wall_clock([] { return uint64_t(1700000000000); });
+    node1.insert_or_update("a", {{"v", "1"}});
+    node1.insert_or_update("b", {{"v", "2"}});
+    assert_true(!node1.deletion_info("a"), "Deletion Info: Live records should have none");
+
+    auto deletion = node1.delete_record("a");
+    assert_true(deletion[0].node_id == 1 && deletion[0].wall_time == 1700000000000,
+                "Deletion Info: Delete should return it");
+    auto encoded = crdt_codec::encode_changes(node1.get_changes_since(0));
+    node2.merge_changes(crdt_codec::decode_changes<CrdtString, CrdtString>(encoded));
+    auto info = node2.deletion_info("a");
+    assert_true(info && info->node_id == 1 && info->db_version == deletion[0].db_version &&
+                    info->wall_time == 1700000000000,
+                "Deletion Info: Should replicate who deleted the record and when");
+
+    node2.delete_record("b");
+    info = node2.deletion_info("b");
+    assert_true(info && info->node_id == 2 && !info->wall_time, "Deletion Info: Wall time should be optional");
+    std::cout << "Test 'Deletion Info' passed." << std::endl;
+  }
+
+  // Test Case: Tombstone Inspection
+  {
+    uint64_t wall_now = 1700000005000;
+    CRDT<CrdtString, CrdtString> node(1);
+    node.set_wall_clock([&] { return wall_now; });
+    for (const char *id : {"a", "b", "c", "d"}) {
+      node.insert_or_update(id, {{"v", id}});
+    }
+    wall_now = 1700000000000;
+    node.delete_record("b");
+    uint64_t acknowledged = node.get_clock().current_time();
+    node.delete_record("a");
+    node.insert_or_update("c", {{"v", "again"}});
+    wall_now = 1700000005000;
+
+    auto listed = node.tombstones();
+    assert_true(listed.size() == 2 && listed[0].record_id == "b" && listed[1].record_id == "a",
+                "Tombstone Inspection: Tombstones should be listed oldest first");
+    assert_true(listed[0].age == 2 && listed[1].age == 1, "Tombstone Inspection: Ages should count local versions");
+    assert_true(listed[0].wall_time == 1700000000000 && listed[0].wall_age == 5000,
+                "Tombstone Inspection: Wall ages should be reported when known");
+
+    auto counts = node.tombstone_counts(acknowledged);
+    assert_true(counts.total == 2 && counts.gc_eligible == 1,
+                "Tombstone Inspection: Only acknowledged tombstones should be eligible for GC");
+    assert_true(node.tombstone_counts(0).gc_eligible == 0, "Tombstone Inspection: Nothing should be eligible yet");
+
+    auto parent = std::make_shared<CRDT<CrdtString, CrdtString>>(node);
+    CRDT<CrdtString, CrdtString> child(2, parent);
+    child.delete_record("d");
+    assert_true(child.tombstones().size() == 3 && child.tombstone_counts(UINT64_MAX).gc_eligible == 3,
+                "Tombstone Inspection: Inherited tombstones should be included");
+    std::cout << "Test 'Tombstone Inspection' passed." << std::endl;
+  }
+
+  // Test Case: Record Status
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    node.insert_or_update("live", {{"v", "1"}});
+    node.insert_or_update("gone", {{"v", "1"}});
+    node.delete_record("gone");
+    assert_true(node.status("live") == RecordStatus::Live && !node.is_deleted("live"), "Status: Live record");
+    assert_true(node.status("gone") == RecordStatus::Tombstoned && node.is_deleted("gone"), "Status: Deleted record");
+    assert_true(node.status("never") == RecordStatus::Unknown && !node.is_deleted("never"), "Status: Unknown record");
+
+    auto parent = std::make_shared<CRDT<CrdtString, CrdtString>>(node);
+    CRDT<CrdtString, CrdtString> child(2, parent);
+    child.delete_record("live");
+    assert_true(child.is_deleted("live") && child.is_deleted("gone") && parent->status("live") == RecordStatus::Live,
+                "Status: Children should see their own and inherited tombstones");
+    child.revive_record("gone", {{"v", "2"}});
+    assert_true(child.status("gone") == RecordStatus::Live, "Status: Revived records should be live");
+    std::cout << "Test 'Record Status' passed." << std::endl;
+  }
+
+  // Test Case: Bulk Load
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    node.insert_or_update("existing", {{"name", "old"}});
+    node.insert_or_update("deleted", {{"name", "x"}});
+    node.delete_record("deleted");
+    node.add_unique_constraint("name");
+    size_t batches = 0;
+    node.on_change_batch([&](const CrdtVector<Change<CrdtString, CrdtString>> &) { ++batches; });
+    uint64_t before = node.get_clock().current_time();
+
+    CrdtVector<std::pair<CrdtString, CrdtMap<CrdtString, CrdtString>>> rows;
+    for (int i = 0; i < 1000; ++i) {
+      rows.push_back({"r" + std::to_string(i), {{"name", "n" + std::to_string(i)}, {"n", std::to_string(i)}}});
+    }
+    rows.push_back({"existing", {{"name", "new"}}});
+    rows.push_back({"deleted", {{"name", "revived?"}}});
+    assert_true(node.bulk_load(std::move(rows)) == 1001, "Bulk Load: Tombstoned records should be skipped");
+    assert_true(node.get_clock().current_time() == before + 1, "Bulk Load: The import should take a single tick");
+    assert_true(batches == 1 && node.len_live() == 1001 && *node.get_value("existing", "name") == "new",
+                "Bulk Load: Records should be written in one batch");
+    assert_true(node.unique_owner("name", "n500") == CrdtString("r500"),
+                "Bulk Load: Unique constraints should be indexed");
+
+    CRDT<CrdtString, CrdtString> peer(2);
+    peer.merge_changes(node.get_changes_since(before));
+    assert_true(peer.len_live() == 1001, "Bulk Load: Imported records should replicate");
+    std::cout << "Test 'Bulk Load' passed." << std::endl;
+  }
+
+  // Test Case: Delete Where
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    for (int i = 0; i < 10; ++i) {
+      node.insert_or_update("r" + std::to_string(i), {{"age", std::to_string(i)}});
+    }
+    auto parent = std::make_shared<CRDT<CrdtString, CrdtString>>(node);
+    CRDT<CrdtString, CrdtString> child(2, parent);
+    uint64_t before = child.get_clock().current_time();
+
+    auto deletions = child.delete_where([](const CrdtString &, const Record<CrdtString> &record) {
+      return std::stoi(record.fields.at("age")) < 4;
+    });
+    CrdtVector<CrdtString> deleted;
+    for (const auto &change : deletions) {
+      deleted.push_back(change.record_id);
+    }
+    std::sort(deleted.begin(), deleted.end());
+    assert_true(deleted == CrdtVector<CrdtString>{"r0", "r1", "r2", "r3"} && deletions[0].kind() == ChangeKind::Delete,
+                "Delete Where: The deletions should be returned");
+    CRDT<CrdtString, CrdtString> peer(3);
+    peer.merge_changes(node.get_changes_since(0));
+    peer.merge_changes(std::move(deletions));
+    assert_true(peer.len_live() == 6 && peer.is_deleted("r3"), "Delete Where: The returned deletions should replicate");
+    assert_true(child.len_live() == 6 && child.is_deleted("r0") && parent->contains_record("r0"),
+                "Delete Where: Matches should be tombstoned");
+    auto changes = child.get_changes_since(before);
+    assert_true(changes.size() == 4 && changes[0].db_version == changes[3].db_version,
+                "Delete Where: Deletions should share one db_version");
+    assert_true(child.delete_where([](const CrdtString &, const Record<CrdtString> &) { return false; }).empty() &&
+                    child.get_clock().current_time() == before + 1,
+                "Delete Where: No match should not tick the clock");
+
+    node.set_validator([](const CrdtString &record_id, const std::optional<CrdtString> &, std::optional<CrdtString> &,
+                          CrdtNodeId) { return record_id != "r9"; });
+    bool threw = false;
+    try {
+      node.delete_where([](const CrdtString &, const Record<CrdtString> &) { return true; });
+    } catch (const std::invalid_argument &) {
+      threw = true;
+    }
+    assert_true(threw && node.len_live() == 10, "Delete Where: A rejected deletion should leave the CRDT unchanged");
+    std::cout << "Test 'Delete Where' passed." << std::endl;
+  }
+
+  // Test Case: Move Record
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node1.insert_or_update("user:old", {{"name", "Ann"}, {"email", "ann@example.com"}});
+    node1.insert_or_update("user:taken", {{"name", "Bob"}});
+    node1.insert_or_update("user:gone", {{"name", "Cy"}});
+    node1.delete_record("user:gone");
+    node2.merge_changes(node1.get_changes_since(0));
+
+    uint64_t seen = node1.get_clock().current_time();
+    auto changes = node1.move_record("user:old", "user:new");
+    assert_true(changes.size() == 3 && changes[0].db_version == changes[2].db_version,
+                "Move Record: The move should be a single operation");
+    assert_true(node1.is_deleted("user:old") && *node1.get_value("user:new", "email") == "ann@example.com",
+                "Move Record: Fields should move to the new id");
+    node2.merge_changes(node1.get_changes_since(seen));
+    assert_true(node2.state_hash() == node1.state_hash() && node2.is_deleted("user:old"),
+                "Move Record: The move should replicate");
+
+    bool threw = false;
+    try {
+      node1.move_record("user:new", "user:taken");
+    } catch (const std::invalid_argument &) {
+      threw = true;
+    }
+    assert_true(threw && node1.contains_record("user:new"), "Move Record: Live targets should be rejected");
+    assert_true(node1.move_record("user:missing", "user:other").empty(), "Move Record: Missing records are not moved");
+
+    changes = node1.move_record("user:new", "user:gone");
+    node2.merge_changes(std::move(changes));
+    assert_true(*node2.get_value("user:gone", "name") == "Ann" && node2.state_hash() == node1.state_hash(),
+                "Move Record: Tombstoned targets should be revived");
+    std::cout << "Test 'Move Record' passed." << std::endl;
+  }
+
+  // Test Case: Record Version
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node1.insert_or_update("doc", {{"title", "Draft"}, {"body", "..."}});
+    node2.merge_changes(node1.get_changes_since(0));
+    auto rendered = node2.record_version("doc");
+    assert_true(rendered && rendered->node_id == 1 && rendered == node1.record_version("doc"),
+                "Record Version: Replicas should agree on the version");
+    assert_true(!node1.record_version("missing"), "Record Version: Unknown records have no version");
+
+    node2.insert_or_update("doc", {{"body", "Edited"}});
+    node1.merge_changes(node2.get_changes_since(0));
+    auto edited = node1.record_version("doc");
+    assert_true(edited != rendered && edited->node_id == 2 && edited == node2.record_version("doc"),
+                "Record Version: Edits should change the version");
+
+    node1.delete_record("doc");
+    auto deleted = node1.record_version("doc");
+    assert_true(deleted && deleted != edited && deleted->node_id == 1, "Record Version: Deletions should count");
+    std::cout << "Test 'Record Version' passed." << std::endl;
+  }
+
+  // Test Case: Last Writer
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node1.insert_or_update("doc", {{"title", "Draft"}, {"body", "..."}});
+    node2.merge_changes(node1.get_changes_since(0));
+    node2.insert_or_update("doc", {{"body", "Edited"}});
+    node2.delete_field("doc", "title");
+    node1.merge_changes(node2.get_changes_since(0));
+
+    auto body = node1.last_writer("doc", "body");
+    const ColumnVersion &stored = node1.get_record("doc")->column_versions.at("body");
+    assert_true(body && body->node_id == 2 && body->col_version == stored.col_version,
+                "Last Writer: Should report the node that wrote the column");
+    auto title = node1.last_writer("doc", "title");
+    assert_true(title && title->node_id == 2 && title->db_version > body->db_version,
+                "Last Writer: Column deletions should count as writes");
+    assert_true(!node1.last_writer("doc", "missing") && !node1.last_writer("other", "body"),
+                "Last Writer: Unknown columns and records have no writer");
+    node1.delete_record("doc");
+    assert_true(!node1.last_writer("doc", "body"), "Last Writer: Tombstoned records have no writer");
+    std::cout << "Test 'Last Writer' passed." << std::endl;
+  }
+
+  // Test Case: Manual Conflict Resolution
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node1.require_manual_resolution("price");
+    node2.require_manual_resolution("price");
+    node1.insert_or_update("item", {{"price", "10"}, {"name", "Widget"}});
+    node2.merge_changes(node1.get_changes_since(0));
+    uint64_t seen1 = node1.get_clock().current_time();
+    uint64_t seen2 = node2.get_clock().current_time();
+
+    // Concurrent edits: the protected column is parked, the other one merges as usual
+    node1.insert_or_update("item", {{"price", "12"}, {"name", "Widget 2"}});
+    node2.insert_or_update("item", {{"price", "15"}, {"name", "Widget 3"}});
+    node1.merge_changes(node2.get_changes_since(seen2));
+    node2.merge_changes(node1.get_changes_since(seen1));
+    node1.merge_changes(node2.get_changes_since(seen2));
+    assert_true(*node1.get_value("item", "price") == "12" && *node2.get_value("item", "price") == "15",
+                "Manual Resolution: Conflicting writes should not be auto-resolved");
+    assert_true(*node1.get_value("item", "name") == *node2.get_value("item", "name"),
+                "Manual Resolution: Unprotected columns should merge as usual");
+    auto pending = node1.pending_conflicts();
+    assert_true(pending.size() == 1 && *pending[0].remote.value == "15" && *pending[0].local_value == "12",
+                "Manual Resolution: The conflict should be listed once");
+    assert_true(node1.stats().conflicts_parked == 1 && node2.pending_conflicts().size() == 1,
+                "Manual Resolution: Both replicas should park the conflict");
+
+    // The decision supersedes both sides everywhere
+    uint64_t before_decision = node1.get_clock().current_time();
+    auto decision = node1.resolve_conflict("item", "price", "14");
+    assert_true(decision.size() == 1 && node1.pending_conflicts().empty(),
+                "Manual Resolution: Resolving should settle the conflict");
+    node2.merge_changes(node1.get_changes_since(before_decision));
+    assert_true(*node2.get_value("item", "price") == "14" && node2.pending_conflicts().empty() &&
+                    node2.state_hash() == node1.state_hash(),
+                "Manual Resolution: The decision should replicate and settle remote conflicts");
+
+    // Sequential edits are not conflicts
+    seen1 = node1.get_clock().current_time();
+    node2.insert_or_update("item", {{"price", "16"}});
+    node1.merge_changes(node2.get_changes_since(seen2));
+    assert_true(*node1.get_value("item", "price") == "16" && node1.pending_conflicts().empty(),
+                "Manual Resolution: Sequential writes should merge as usual");
+    std::cout << "Test 'Manual Conflict Resolution' passed." << std::endl;
+  }
+
+  // Test Case: Site Priority
+  {
+    CRDT<CrdtString, CrdtString> server(1);
+    CRDT<CrdtString, CrdtString> phone(7);
+    CRDT<CrdtString, CrdtString> tablet(9);
+    for (auto *node : {&server, &phone, &tablet}) {
+      node->set_site_priority({1});
+    }
+
+    // Concurrent first writes tie on col_version; the server wins although its node id is lowest
+    server.insert_or_update("settings", {{"theme", "dark"}});
+    phone.insert_or_update("settings", {{"theme", "light"}});
+    tablet.insert_or_update("settings", {{"theme", "blue"}});
+    for (auto *node : {&server, &phone, &tablet}) {
+      node->merge_changes(server.get_changes_since(0));
+      node->merge_changes(phone.get_changes_since(0));
+      node->merge_changes(tablet.get_changes_since(0));
+    }
+    assert_true(*phone.get_value("settings", "theme") == "dark" && phone.state_hash() == server.state_hash() &&
+                    tablet.state_hash() == server.state_hash(),
+                "Site Priority: The prioritized site should win ties on every replica");
+
+    // Higher col_versions still win, whatever the weights
+    phone.insert_or_update("settings", {{"theme", "light"}});
+    server.merge_changes(phone.get_changes_since(0));
+    assert_true(*server.get_value("settings", "theme") == "light", "Site Priority: Newer writes should still win");
+
+    // Equal weights fall back to the merge rule
+    CRDT<CrdtString, CrdtString> a(2);
+    CRDT<CrdtString, CrdtString> b(3);
+    a.set_site_weights({{2, 5}, {3, 5}});
+    a.insert_or_update("r", {{"v", "a"}});
+    b.insert_or_update("r", {{"v", "b"}});
+    a.merge_changes(b.get_changes_since(0));
+    assert_true(*a.get_value("r", "v") == "b", "Site Priority: Equal weights should fall back to the merge rule");
+    std::cout << "Test 'Site Priority' passed." << std::endl;
+  }
+
+  // Test Case: Column Groups
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node1.add_column_group({"lat", "lon"});
+    node2.add_column_group({"lat", "lon"});
+    node1.insert_or_update("pin", {{"lat", "0"}, {"lon", "0"}, {"label", "home"}});
+    node2.merge_changes(node1.get_changes_since(0));
+    uint64_t seen1 = node1.get_clock().current_time();
+    uint64_t seen2 = node2.get_clock().current_time();
+
+    // A write to one column of the group carries the others along
+    node1.insert_or_update("pin", {{"lat", "1"}, {"lon", "1"}});
+    auto partial = node2.insert_or_update("pin", {{"lat", "2"}});
+    assert_true(partial.size() == 2 && partial[1].col_name == "lon" && partial[1].col_version == partial[0].col_version,
+                "Column Groups: Writes should re-stamp the rest of the group");
+
+    // Concurrent writes resolve to a single writer's pair on both replicas
+    node1.merge_changes(node2.get_changes_since(seen2));
+    node2.merge_changes(node1.get_changes_since(seen1));
+    for (auto *node : {&node1, &node2}) {
+      assert_true(*node->get_value("pin", "lat") == "2" && *node->get_value("pin", "lon") == "0",
+                  "Column Groups: The group should not mix values from different writes");
+    }
+    assert_true(node1.state_hash() == node2.state_hash(), "Column Groups: Replicas should converge");
+
+    // Stale writes of a single grouped column lose to the group's latest write
+    auto stale = node1.get_changes_since(0);
+    std::erase_if(stale, [](const auto &change) { return change.col_name != "lon"; });
+    stale[0].value = "9";
+    stale[0].col_version -= 1;
+    stale[0].node_id = 3;
+    node2.merge_changes(std::move(stale));
+    assert_true(*node2.get_value("pin", "lon") == "0", "Column Groups: Older group members should be rejected");
+
+    seen1 = node1.get_clock().current_time();
+    auto deletion = node1.delete_field("pin", "lon");
+    assert_true(deletion.size() == 2 && *deletion[1].value == "2",
+                "Column Groups: Deletions should re-stamp the group");
+    node2.merge_changes(node1.get_changes_since(seen1));
+    assert_true(node1.state_hash() == node2.state_hash() && !node2.get_value("pin", "lon"),
+                "Column Groups: Group deletions should replicate");
+
+    bool threw = false;
+    try {
+      node1.add_column_group({"lon", "alt"});
+    } catch (const std::invalid_argument &) {
+      threw = true;
+    }
+    assert_true(threw, "Column Groups: A column should belong to one group");
+    std::cout << "Test 'Column Groups' passed." << std::endl;
+  }
+
+  // Test Case: Operation-Based Sync
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    CrdtVector<CrdtBytes> wire;
+    OpPublisher<CrdtString, CrdtString> publisher(1, [&](const auto &op) { wire.push_back(crdt_ops::encode_op(op)); });
+    publisher.attach(node1);
+
+    node2.insert_or_update("note", {{"title", "from node2"}});
+    node1.merge_changes(node2.get_changes_since(0));
+    assert_true(wire.empty(), "Ops: Merged changes should not be published");
+
+    node1.insert_or_update("doc", {{"title", "Live"}, {"body", "typing"}});
+    node1.delete_field("doc", "body");
+    assert_true(wire.size() == 2 && publisher.last_seq() == node1.get_clock().current_time(),
+                "Ops: Every local operation should be published at once");
+    auto first = crdt_ops::decode_op<CrdtString, CrdtString>(wire[0]);
+    auto second = crdt_ops::decode_op<CrdtString, CrdtString>(wire[1]);
+    assert_true(first.origin == 1 && first.changes.size() == 2 && first.prev_seq == 0 && second.prev_seq == first.seq,
+                "Ops: Operations should carry their origin and sequence");
+    assert_true(first.deps.size() == 1 && first.deps.at(2) == node2.get_clock().current_time(),
+                "Ops: Operations should carry the versions the origin had applied");
+
+    // Operations commute with each other and with state-based sync
+    crdt_ops::apply_op(node2, second);
+    crdt_ops::apply_op(node2, first);
+    crdt_ops::apply_op(node2, first);
+    assert_true(node2.state_hash() == node1.state_hash(), "Ops: Applying operations should converge");
+    CRDT<CrdtString, CrdtString> late(3);
+    late.merge_changes(node1.get_changes_since(0));
+    assert_true(late.state_hash() == node1.state_hash(), "Ops: Catch-up sync should still work");
+    std::cout << "Test 'Operation-Based Sync' passed." << std::endl;
+  }
+
+  // Test Case: Causal Delivery Buffer
+  {
+    using Crdt = CRDT<CrdtString, CrdtString>;
+    Crdt node1(1);
+    Crdt node2(2);
+    Crdt receiver(3);
+    CrdtVector<CrdtOp<CrdtString, CrdtString>> ops;
+    OpPublisher<CrdtString, CrdtString> publisher1(1, [&](const auto &op) { ops.push_back(op); });
+    OpPublisher<CrdtString, CrdtString> publisher2(2, [&](const auto &op) { ops.push_back(op); });
+    publisher1.attach(node1);
+    publisher2.attach(node2);
+
+    node1.insert_or_update("folder", {{"name", "Docs"}});
+    node2.merge_changes(node1.get_changes_since(0));
+    node2.insert_or_update("file", {{"parent", "folder"}});
+    node1.insert_or_update("folder", {{"name", "Documents"}});
+
+    auto start = OpReceiver<Crdt>::Clock::now();
+    OpReceiver<Crdt> buffer(receiver, std::chrono::seconds(5));
+    size_t seen_file_before_folder = 0;
+    receiver.on_change([&](const ChangeEvent<CrdtString, CrdtString> &event) {
+      if (event.record_id == "file" && !receiver.contains_record("folder")) {
+        ++seen_file_before_folder;
+      }
+    });
+    buffer.receive(ops[2], start); // node1's rename depends on its insert
+    buffer.receive(ops[1], start); // node2's file depends on node1's insert
+    assert_true(buffer.buffered() == 2 && !receiver.contains_record("file"),
+                "Causal Buffer: Operations should wait for their dependencies");
+    buffer.receive(ops[0], start);
+    assert_true(buffer.buffered() == 0 && buffer.applied() == 3 && seen_file_before_folder == 0,
+                "Causal Buffer: Buffered operations should be applied once their dependencies arrive");
+    assert_true(*receiver.get_value("folder", "name") == "Documents", "Causal Buffer: Operations should be applied");
+
+    // Dependencies that never arrive as operations are given up on after the timeout
+    Crdt other(4);
+    OpReceiver<Crdt> waiting(other, std::chrono::seconds(5));
+    waiting.receive(ops[1], start);
+    waiting.flush_expired(start + std::chrono::seconds(1));
+    assert_true(waiting.buffered() == 1, "Causal Buffer: Operations should be kept until the timeout");
+    waiting.flush_expired(start + std::chrono::seconds(5));
+    assert_true(waiting.buffered() == 0 && other.contains_record("file"),
+                "Causal Buffer: Expired operations should be applied anyway");
+    std::cout << "Test 'Causal Delivery Buffer' passed." << std::endl;
+  }
+
+  // Test Case: Owned Merge
+  {
+    CRDT<CrdtString, CrdtString> source(1);
+    source.insert_or_update("a", {{"name", "Alice"}, {"city", "Paris"}});
+    source.insert_or_update("b", {{"name", "Bob"}});
+    source.delete_record("b");
+
+    auto changes = source.get_changes_since(0);
+    std::deque<Change<CrdtString, CrdtString>> queued(changes.begin(), changes.end());
+    CRDT<CrdtString, CrdtString> from_deque(2);
+    from_deque.merge_changes_owned(std::move(queued));
+    assert_true(from_deque.state_hash() == source.state_hash(), "Owned Merge: A deque should merge like a vector");
+
+    CRDT<CrdtString, CrdtString> from_view(3);
+    auto moved = std::ranges::subrange(std::make_move_iterator(changes.begin()), std::make_move_iterator(changes.end()));
+    auto accepted = from_view.merge_changes_owned<true>(moved);
+    assert_true(accepted.size() == source.get_changes_since(0).size(), "Owned Merge: Every change should be accepted");
+    assert_true(from_view.state_hash() == source.state_hash(), "Owned Merge: A moving view should merge like a vector");
+
+    const auto shared = source.get_changes_since(0);
+    CRDT<CrdtString, CrdtString> from_const(4);
+    from_const.merge_changes_owned(shared);
+    assert_true(from_const.state_hash() == source.state_hash() && shared.front().col_name,
+                "Owned Merge: Const ranges should be copied from");
+    from_const.merge_changes_owned(std::deque<Change<CrdtString, CrdtString>>{});
+    std::cout << "Test 'Owned Merge' passed." << std::endl;
+  }
+
+  // Test Case: Parallel Merge
+  {
+    CRDT<CrdtString, CrdtString> source(1);
+    for (int i = 0; i < 20000; ++i) {
+      source.insert_or_update("r" + std::to_string(i), {{"v", std::to_string(i)}, {"w", "x"}});
+    }
+    for (int i = 0; i < 20000; i += 7) {
+      source.delete_record("r" + std::to_string(i));
+    }
+    CRDT<CrdtString, CrdtString> other(2);
+    other.insert_or_update("r1", {{"v", "other"}});
+    other.insert_or_update("r7", {{"v", "revived"}});
+    other.insert_or_update("extra", {{"v", "kept"}});
+
+    CRDT<CrdtString, CrdtString> sequential(3);
+    sequential.merge_changes(other.get_changes_since(0));
+    sequential.merge_changes(source.get_changes_since(0));
+    CRDT<CrdtString, CrdtString> parallel(3);
+    parallel.merge_changes(other.get_changes_since(0));
+    uint64_t before = parallel.get_clock().current_time();
+    size_t observed = 0;
+    parallel.on_change_batch([&](const CrdtVector<Change<CrdtString, CrdtString>> &batch) { observed += batch.size(); });
+    auto accepted = parallel.merge_changes_parallel<true>(source.get_changes_since(0), 4);
+
+    assert_true(parallel.state_hash() == sequential.state_hash(), "Parallel Merge: Should match a sequential merge");
+    assert_true(observed == accepted.size() && parallel.stats().changes_accepted == sequential.stats().changes_accepted,
+                "Parallel Merge: Accepted changes should be reported and counted");
+    uint64_t local_db_version = parallel.get_clock().current_time();
+    assert_true(local_db_version > before && parallel.get_changes_since(before).size() == accepted.size(),
+                "Parallel Merge: Accepted changes should be visible to get_changes_since");
+    for (const auto &change : accepted) {
+      assert_true(change.local_db_version == local_db_version, "Parallel Merge: The clock should be updated once");
+    }
+    assert_true(parallel.max_seen_versions() == sequential.max_seen_versions(), "Parallel Merge: Seen versions");
+    assert_true(parallel.len_live() == sequential.len_live(), "Parallel Merge: Live records should be counted");
+    assert_true(parallel.len_tombstones() == sequential.len_tombstones(), "Parallel Merge: Tombstones should be counted");
+
+    CRDT<CrdtString, CrdtString> small(4);
+    small.merge_changes_parallel(other.get_changes_since(0), 4);
+    assert_true(small.state_hash() == other.state_hash(), "Parallel Merge: Small batches should merge sequentially");
+    uint64_t merged = parallel.get_clock().current_time();
+    auto replayed = parallel.merge_changes_parallel<true>(source.get_changes_since(0), 4);
+    assert_true(replayed.empty() && parallel.get_changes_since(merged).empty() &&
+                    parallel.state_hash() == sequential.state_hash(),
+                "Parallel Merge: Rejected batches should leave the records untouched");
+    std::cout << "Test 'Parallel Merge' passed." << std::endl;
+  }
+
+  // Test Case: Change Index
+  {
+    using Crdt = CRDT<CrdtString, CrdtString>;
+    // What get_changes_since returned before the index: every column written after `since`
+    auto scanned = [](const Crdt &crdt, uint64_t since) {
+      size_t count = 0;
+      for (const auto &[record_id, record] : crdt.get_data()) {
+        for (const auto &[col_name, version] : record.column_versions) {
+          count += version.local_db_version > since;
+        }
+      }
+      for (const auto &[record_id, tombstone] : crdt.get_tombstones()) {
+        count += tombstone.version.local_db_version > since;
+      }
+      return count;
+    };
+    Crdt node(1);
+    Crdt peer(2);
+    node.add_column_group({"x", "y"});
+    peer.add_column_group({"x", "y"});
+    node.enable_migrations("__schema__");
+    for (int round = 0; round < 600; ++round) {
+      CrdtString id = "r" + std::to_string(round % 40);
+      node.insert_or_update(id, {{"x", std::to_string(round)}, {"z", "n"}});
+      peer.insert_or_update(id, {{"y", std::to_string(round)}});
+      if (round % 7 == 0) {
+        node.delete_record("r" + std::to_string(round % 13));
+      }
+      if (round % 11 == 0) {
+        node.delete_field(id, "z");
+      }
+      if (round % 17 == 0) {
+        node.move_record(id, "moved" + std::to_string(round));
+      }
+      if (round % 5 == 0) {
+        node.merge_changes(peer.get_changes_since(0));
+        peer.merge_changes(node.get_changes_since(0));
+      }
+    }
+    node.migrate(CrdtMigration<CrdtString>::rename_column(1, "z", "w"));
+    uint64_t clock = node.get_clock().current_time();
+    for (uint64_t since = 0; since <= clock; since += 13) {
+      auto changes = node.get_changes_since(since);
+      assert_true(changes.size() == scanned(node, since), "Change Index: Should find every change since a version");
+      for (size_t i = 1; i < changes.size(); ++i) {
+        if (changes[i].record_id != changes[i - 1].record_id) {
+          assert_true(changes[i].local_db_version > since, "Change Index: Should only return newer changes");
+        }
+      }
+    }
+    assert_true(node.get_changes_since(clock).empty(), "Change Index: Nothing is newer than the clock");
+
+    Crdt copy = node;
+    Crdt loaded = Crdt::from_parts(3, clock, node.get_data(), node.get_tombstones());
+    assert_true(copy.get_changes_since(clock / 2).size() == scanned(node, clock / 2) &&
+                    loaded.get_changes_since(clock / 2).size() == scanned(node, clock / 2),
+                "Change Index: Copies and restored replicas should be indexed");
+    std::cout << "Test 'Change Index' passed." << std::endl;
+  }
+
+  // Test Case: Background Maintenance
+  {
+    CRDT<CrdtString, CrdtString> plain(1);
+    plain.insert_or_update("a", {{"v", "1"}});
+    plain.insert_or_update("b", {{"v", "2"}});
+    plain.delete_record("a");
+    uint64_t acknowledged = plain.get_clock().current_time();
+    plain.delete_record("b");
+    assert_true(plain.collect_tombstones(acknowledged) == 1 && !plain.get_tombstone("a") && plain.get_tombstone("b"),
+                "Maintenance: Only acknowledged tombstones should be collected");
+
+    SharedCrdt<CrdtString, CrdtString> shared(1);
+    for (int i = 0; i < 100; ++i) {
+      shared.insert_or_update("r" + std::to_string(i), {{"v", "x"}});
+      shared.delete_record("r" + std::to_string(i));
+    }
+    std::atomic<uint64_t> horizon = 0;
+    std::atomic<bool> busy = false;
+    CrdtMaintenancePolicy policy;
+    policy.interval = std::chrono::hours(1);
+    policy.acknowledged_version = [&]() -> std::optional<uint64_t> { return horizon.load(); };
+    policy.min_collectable = 10;
+    policy.shrink = true;
+    policy.defer = [&] { return busy.load(); };
+    CrdtMaintenance maintenance(shared, policy);
+
+    horizon = 10;
+    assert_true(maintenance.run_once() && maintenance.stats().tombstones_collected == 0,
+                "Maintenance: Too few eligible tombstones should be kept");
+    horizon = shared.current_time();
+    {
+      CrdtMaintenance<SharedCrdt<CrdtString, CrdtString>>::Pause pause(maintenance);
+      assert_true(!maintenance.run_once(), "Maintenance: Paused passes should be skipped");
+    }
+    busy = true;
+    assert_true(!maintenance.run_once(), "Maintenance: Deferred passes should be skipped");
+    busy = false;
+
+    maintenance.trigger();
+    for (int i = 0; i < 500 && maintenance.stats().tombstones_collected == 0; ++i) {
+      std::this_thread::sleep_for(std::chrono::milliseconds(2));
+    }
+    assert_true(maintenance.stats().tombstones_collected == 100 && maintenance.stats().passes_skipped == 2,
+                "Maintenance: A triggered pass should collect the tombstones");
+    assert_true(shared.read([](const auto &crdt) { return crdt.tombstone_counts(0).total; }) == 0,
+                "Maintenance: The tombstones should be gone");
+    std::cout << "Test 'Background Maintenance' passed." << std::endl;
+  }
+
+  // Test Case: Durable Change Log
+  {
+    using Crdt = CRDT<CrdtString, CrdtString>;
+    std::filesystem::path directory = std::filesystem::temp_directory_path() / "crdt_change_log_test";
+    std::filesystem::remove_all(directory);
+    Crdt node(1);
+    Crdt peer(2);
+    Crdt follower(3);
+    uint64_t synced = 0;
+    {
+      CrdtChangeLog<Crdt> log(node, directory, CrdtChangeLogOptions{512, 3});
+      for (int i = 0; i < 200; ++i) {
+        node.insert_or_update("r" + std::to_string(i % 30), {{"v", std::to_string(i)}});
+        if (i % 9 == 0) {
+          node.delete_record("r" + std::to_string(i % 30));
+        }
+        if (i % 20 == 0) {
+          peer.insert_or_update("p" + std::to_string(i), {{"v", "peer"}});
+          node.merge_changes(peer.get_changes_since(0));
+        }
+        if (i == 50) {
+          follower.merge_changes(node.get_changes_since(0));
+          synced = node.get_clock().current_time();
+        }
+      }
+      assert_true(log.snapshot_version() > synced && log.segment_count() <= 3,
+                  "Change Log: Segments should be compacted into a snapshot");
+      size_t batches = 0;
+      log.read_since(synced, [&](CrdtVector<Change<CrdtString, CrdtString>> &&batch) {
+        ++batches;
+        follower.merge_changes(std::move(batch));
+      });
+      assert_true(batches > 1 && follower.state_hash() == node.state_hash(),
+                  "Change Log: A peer behind the snapshot should catch up from disk");
+    }
+
+    // A crash in the middle of an append leaves a partial record behind
+    {
+      CrdtVector<std::filesystem::path> segments;
+      for (const auto &entry : std::filesystem::directory_iterator(directory)) {
+        if (entry.path().filename().string().starts_with("segment-")) {
+          segments.push_back(entry.path());
+        }
+      }
+      std::sort(segments.begin(), segments.end());
+      std::ofstream(segments.back(), std::ios::binary | std::ios::app) << "\x00\x00\x01";
+    }
+    Crdt recovered = CrdtChangeLog<Crdt>::recover(directory, 1);
+    assert_true(recovered.state_hash() == node.state_hash(), "Change Log: Recovery should restore the state");
+    assert_true(recovered.get_clock().current_time() >= node.get_clock().current_time() &&
+                    recovered.get_changes_since(synced).size() == node.get_changes_since(synced).size(),
+                "Change Log: Recovery should keep the local versions");
+    {
+      CrdtChangeLog<Crdt> log(recovered, directory, CrdtChangeLogOptions{512, 3});
+      recovered.insert_or_update("after", {{"v", "restart"}});
+      auto latest = log.changes_since(node.get_clock().current_time());
+      assert_true(latest.size() == 1 && latest[0].record_id == "after", "Change Log: Logging should resume");
+    }
+    assert_true(CrdtChangeLog<Crdt>::recover(directory, 1).state_hash() == recovered.state_hash(),
+                "Change Log: A reopened log should recover the latest state");
+
+    // A complete record that does not match its checksum fails the recovery rather than being applied
+    {
+      std::filesystem::path last;
+      for (const auto &entry : std::filesystem::directory_iterator(directory)) {
+        if (entry.path().filename().string().starts_with("segment-") && entry.path() > last) {
+          last = entry.path();
+        }
+      }
+      std::fstream file(last, std::ios::binary | std::ios::in | std::ios::out);
+      file.seekp(crdt_codec::RECORD_HEADER_SIZE + 1);
+      file.put('\xff');
+    }
+    bool corrupted = false;
+    try {
+      CrdtChangeLog<Crdt>::recover(directory, 1);
+    } catch (const CrdtDecodeError &) {
+      corrupted = true;
+    }
+    assert_true(corrupted, "Change Log: Corrupted records should fail the recovery");
+    std::filesystem::remove_all(directory);
+    std::cout << "Test 'Durable Change Log' passed." << std::endl;
+  }
+
+  // Test Case: Persisted Sync Cursors
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    std::filesystem::path path = std::filesystem::temp_directory_path() / "crdt_sync_cursors_test";
+    std::filesystem::remove(path);
+    FileCursorStore store(path);
+    assert_true(store.load().empty(), "Cursors: A missing file should load as empty");
+
+    Node node1(1);
+    Node node2(2);
+    node1.insert_or_update("a", {{"v", "1"}});
+    node2.insert_or_update("b", {{"v", "2"}});
+    GossipNode<Node> peer(node2, 2);
+    uint64_t peer_version = node2.get_clock().current_time();
+    auto sync = [&](GossipNode<Node> &gossiper) {
+      auto initiator = gossiper.open_session(2);
+      auto responder = peer.open_session(1);
+      pump_sync_sessions(initiator, responder);
+      gossiper.close_session(initiator);
+      peer.close_session(responder);
+      return initiator.changes_received();
+    };
+    {
+      GossipNode<Node> gossiper(node1, 1);
+      gossiper.add_peer(2);
+      gossiper.persist_cursors(store);
+      assert_true(gossiper.acknowledged_version() == 0, "Cursors: Nothing should be acknowledged before a sync");
+      uint64_t sent = node1.get_clock().current_time();
+      assert_true(sync(gossiper) == 1, "Cursors: The first sync should receive the peer's changes");
+      assert_true(gossiper.acknowledged_version() == sent,
+                  "Cursors: The peer should acknowledge our changes");
+    }
+    auto saved = store.load();
+    assert_true(saved.size() == 1 && saved[2].received == peer_version && saved[2].acked > 0,
+                "Cursors: A completed session should save the cursors");
+
+    // A restarted gossip node resumes where it left off instead of receiving everything again
+    node2.insert_or_update("c", {{"v", "3"}});
+    GossipNode<Node> restarted(node1, 1);
+    restarted.add_peer(2);
+    restarted.persist_cursors(store);
+    assert_true(restarted.version_vector().at(2) == saved[2].received, "Cursors: Restarting should restore cursors");
+    assert_true(sync(restarted) == 1, "Cursors: A restarted node should only receive new changes");
+    assert_true(node1.get_data() == node2.get_data(), "Cursors: The nodes should converge");
+
+    std::ofstream(path, std::ios::binary | std::ios::app) << "x";
+    bool threw = false;
+    try {
+      store.load();
+    } catch (const CrdtDecodeError &) {
+      threw = true;
+    }
+    assert_true(threw, "Cursors: A malformed file should be rejected");
+    std::filesystem::remove(path);
+    std::cout << "Test 'Persisted Sync Cursors' passed." << std::endl;
+  }
+
+  // Test Case: Namespaces
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    Node server1(1);
+    Node server2(2);
+    CrdtNamespace<Node> acme1(server1, "acme");
+    CrdtNamespace<Node> globex1(server1, "globex");
+    CrdtNamespace<Node> acme2(server2, "acme");
+    CrdtNamespace<Node> globex2(server2, "globex");
+    acme1.insert_or_update("invoice", {{"total", "10"}});
+    globex1.insert_or_update("invoice", {{"total", "99"}});
+    globex2.insert_or_update("secret", {{"v", "x"}});
+    assert_true(server1.get_record("acme/invoice") && *acme1.get_value("invoice", "total") == "10" &&
+                    *globex1.get_value("invoice", "total") == "99",
+                "Namespaces: Keys should be prefixed with the namespace");
+    assert_true(acme1.len_live() == 1 && acme1.get_changes_since(0).size() == 1,
+                "Namespaces: Reads should only see the namespace");
+
+    // Syncing one namespace leaves the others untouched in both directions
+    SyncSession<CrdtNamespace<Node>> session1(acme1, 1, 0);
+    SyncSession<CrdtNamespace<Node>> session2(acme2, 2, 0);
+    pump_sync_sessions(session1, session2);
+    assert_true(*acme2.get_value("invoice", "total") == "10", "Namespaces: The synced namespace should replicate");
+    assert_true(!globex2.get_record("invoice") && !globex1.get_record("secret"),
+                "Namespaces: Other namespaces should not leak");
+
+    auto changes = server2.get_changes_since(0);
+    assert_true(acme1.merge_changes(std::move(changes)) == 1 && !server1.get_record("globex/secret"),
+                "Namespaces: Changes to other namespaces should be dropped");
+    acme1.delete_record("invoice");
+    assert_true(acme1.len_live() == 0 && globex1.len_live() == 1, "Namespaces: Deletes should stay in the namespace");
+
+    bool threw = false;
+    try {
+      CrdtNamespace<Node> invalid(server1, "a/b");
+    } catch (const std::invalid_argument &) {
+      threw = true;
+    }
+    assert_true(threw, "Namespaces: Names containing the separator should be rejected");
+    std::cout << "Test 'Namespaces' passed." << std::endl;
+  }
+
+  // Test Case: Record TTL
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    uint64_t now = 1000;
+    CrdtTtlPolicy ttl;
+    ttl.expire_records("created_at", 1000).expire_column("token", "issued_at", 500);
+    Node node1(1);
+    Node node2(2);
+    for (Node *node : {&node1, &node2}) {
+      node->set_ttl_policy(ttl);
+      node->set_wall_clock([&] { return now; });
+    }
+    node1.insert_or_update("s1", {{"created_at", "1000"}, {"issued_at", "900"}, {"token", "abc"}});
+    node1.insert_or_update("s2", {{"created_at", "1000"}});
+    node1.insert_or_update("pinned", {{"name", "no timestamp"}});
+    node2.merge_changes(node1.get_changes_since(0));
+
+    auto expired = node1.expire(1500);
+    assert_true(expired.size() == 1 && expired[0].col_name == "token" && !expired[0].value,
+                "TTL: An expired column should be deleted");
+    assert_true(node1.expire(1500).empty(), "TTL: Expiring again should change nothing");
+    assert_true(!node1.is_expired("s1", 1999) && node1.is_expired("s1", 2000), "TTL: Records expire after the TTL");
+
+    // s2 is refreshed before it expires; both replicas then expire s1 on their own and still converge
+    node2.insert_or_update("s2", {{"created_at", "2400"}});
+    node1.merge_changes(node2.get_changes_since(0));
+    now = 2500;
+    uint64_t seen1 = node1.get_clock().current_time();
+    uint64_t seen2 = node2.get_clock().current_time();
+    assert_true(node1.expire().size() == 1 && node2.expire().size() == 1, "TTL: Each replica should expire on its own");
+    node2.merge_changes(node1.get_changes_since(seen1));
+    node1.merge_changes(node2.get_changes_since(seen2));
+    assert_true(node1.is_tombstoned("s1") && node1.get_record("s2") && node1.get_record("pinned"),
+                "TTL: Only expired records should be deleted");
+    assert_true(node1.state_hash() == node2.state_hash(), "TTL: Replicas should converge after expiring");
+
+    SharedCrdt<CrdtString, CrdtString> shared(3);
+    shared.write([&](auto &crdt) {
+      crdt.set_ttl_policy(ttl);
+      crdt.set_wall_clock([] { return uint64_t(5000); });
+      crdt.insert_or_update("old", {{"created_at", "100"}});
+    });
+    CrdtMaintenancePolicy policy;
+    policy.interval = std::chrono::hours(1);
+    policy.expire = true;
+    CrdtMaintenance<SharedCrdt<CrdtString, CrdtString>> maintenance(shared, policy);
+    assert_true(maintenance.run_once() && maintenance.stats().expired == 1 &&
+                    shared.read([](const auto &crdt) { return crdt.is_tombstoned("old"); }),
+                "TTL: Maintenance should expire records");
+    std::cout << "Test 'Record TTL' passed." << std::endl;
+  }
+
+  // Test Case: Local-Only Columns
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    Node node1(1);
+    Node node2(2);
+    for (Node *node : {&node1, &node2}) {
+      node->add_local_column("expanded");
+    }
+    size_t events = 0;
+    node1.on_change([&](const ChangeEvent<CrdtString, CrdtString> &) { ++events; });
+    auto changes = node1.insert_or_update("doc", {{"title", "Notes"}, {"expanded", "true"}});
+    assert_true(changes.size() == 1 && changes[0].col_name == "title" && events == 2,
+                "Local Columns: Local-only writes should not produce changes");
+    assert_true(*node1.get_value("doc", "expanded") == "true" && node1.get_changes_since(0).size() == 1,
+                "Local Columns: Local-only values should be stored but not extracted");
+
+    node2.merge_changes(node1.get_changes_since(0));
+    node2.insert_or_update("doc", {{"expanded", "false"}});
+    assert_true(node1.state_hash() == node2.state_hash(), "Local Columns: Digests should ignore local-only values");
+
+    uint64_t denied = node2.stats().changes_denied;
+    node2.merge_changes({Change<CrdtString, CrdtString>("doc", "expanded", "true", 5, 9, 1)});
+    assert_true(*node2.get_value("doc", "expanded") == "false" && node2.stats().changes_denied == denied + 1,
+                "Local Columns: Incoming changes to local-only columns should be dropped");
+
+    assert_true(node2.delete_field("doc", "expanded").empty() && !node2.get_value("doc", "expanded") &&
+                    node2.get_value("doc", "title"),
+                "Local Columns: Deleting a local-only value should not produce changes");
+    std::cout << "Test 'Local-Only Columns' passed." << std::endl;
+  }
+
+  // Test Case: Presence
+  {
+    using Clock = CrdtPresence::Clock;
+    Clock::time_point start = Clock::now();
+    CrdtPresence alice(1, std::chrono::seconds(10));
+    CrdtPresence bob(2, std::chrono::seconds(10));
+    CrdtPresence carol(3, std::chrono::seconds(10));
+    size_t changes = 0;
+    bob.set_observer([&](const CrdtNodeId &, const PresenceState *) { ++changes; });
+
+    CrdtBytes joined = alice.set_local({{"user", "alice"}, {"viewing", "doc-1"}});
+    CrdtBytes moved = alice.set_local({{"user", "alice"}, {"viewing", "doc-2"}});
+    assert_true(is_presence_frame(moved) && bob.on_frame(moved, start), "Presence: A new state should be news");
+    assert_true(!bob.on_frame(joined, start), "Presence: An older state arriving late should be ignored");
+    assert_true(bob.get(1)->fields.at("viewing") == "doc-2" && bob.who("viewing", "doc-2") == CrdtVector<CrdtNodeId>{1},
+                "Presence: Peers should see the latest state");
+    assert_true(!alice.on_frame(moved, start) && alice.peers().empty(), "Presence: Our own state should not be a peer");
+
+    // Bob relays what he hears to Carol
+    assert_true(carol.on_frame(moved, start) && !carol.on_frame(moved, start), "Presence: Relayed frames are deduped");
+    carol.on_frame(bob.set_local({{"user", "bob"}}), start + std::chrono::seconds(5));
+    assert_true(carol.peers().size() == 2, "Presence: Relayed and direct peers should be present");
+
+    bob.on_frame(alice.heartbeat(), start + std::chrono::seconds(8));
+    assert_true(bob.expire(start + std::chrono::seconds(12)).empty(), "Presence: Heartbeats should keep peers present");
+    assert_true(carol.expire(start + std::chrono::seconds(12)) == CrdtVector<CrdtNodeId>{1} && carol.get(2),
+                "Presence: Silent peers should time out");
+
+    assert_true(bob.on_frame(alice.leave(), start) && bob.peers().empty() && changes == 2,
+                "Presence: Leaving should remove the peer");
+
+    // Presence frames share the sync transport; sessions ignore them
+    CRDT<CrdtString, CrdtString> node(1);
+    SyncSession<CRDT<CrdtString, CrdtString>> session(node, 1);
+    assert_true(session.on_frame(moved).empty(), "Presence: Sync sessions should ignore presence frames");
+    std::cout << "Test 'Presence' passed." << std::endl;
+  }
+
+  // Test Case: Merge Function Registry
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    // Longest duration wins; "set:" values are unions of their characters
+    CrdtMergeRegistry<CrdtString> registry;
+    registry
+        .register_type("max",
+                       [](const CrdtString &a, const CrdtString &b, const ColumnVersion &, const ColumnVersion &) {
+                         return std::stoll(a) >= std::stoll(b) ? a : b;
+                       })
+        .register_type("set",
+                       [](const CrdtString &a, const CrdtString &b, const ColumnVersion &, const ColumnVersion &) {
+                         std::set<char> chars(a.begin() + 4, a.end());
+                         chars.insert(b.begin() + 4, b.end());
+                         return "set:" + CrdtString(chars.begin(), chars.end());
+                       })
+        .assign_column("duration", "max")
+        .set_tagger([](const CrdtString &value) -> std::optional<CrdtString> {
+          return value.starts_with("set:") ? std::optional<CrdtString>("set") : std::nullopt;
+        });
+
+    std::vector<std::unique_ptr<Node>> nodes;
+    for (CrdtNodeId id = 1; id <= 3; ++id) {
+      nodes.push_back(std::make_unique<Node>(id));
+      nodes.back()->set_merge_registry(registry);
+    }
+    nodes[0]->insert_or_update("task", {{"duration", "90"}, {"flags", "set:ab"}, {"title", "one"}});
+    nodes[1]->insert_or_update("task", {{"duration", "30"}, {"flags", "set:bc"}, {"title", "two"}});
+    nodes[2]->insert_or_update("task", {{"duration", "60"}, {"flags", "set:d"}, {"title", "three"}});
+
+    // Merge in different orders
+    auto all = [&](size_t i) { return nodes[i]->get_changes_since(0); };
+    auto c0 = all(0), c1 = all(1), c2 = all(2);
+    nodes[0]->merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(c2));
+    nodes[0]->merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(c1));
+    nodes[1]->merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(c0));
+    nodes[1]->merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(c2));
+    nodes[2]->merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(c1));
+    nodes[2]->merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(c0));
+    for (int round = 0; round < 2; ++round) {
+      for (size_t i = 0; i < nodes.size(); ++i) {
+        for (size_t j = 0; j < nodes.size(); ++j) {
+          if (i != j) {
+            nodes[j]->merge_changes(nodes[i]->get_changes_since(0));
+          }
+        }
+      }
+    }
+    for (const auto &node : nodes) {
+      assert_true(*node->get_value("task", "duration") == "90", "Merge Registry: Assigned columns should be merged");
+      assert_true(node->get_data() == nodes[0]->get_data(), "Merge Registry: Replicas should converge");
+    }
+    assert_true(*nodes[0]->get_value("task", "title") == "three", "Merge Registry: Other columns merge as usual");
+    assert_true(*nodes[0]->get_value("task", "flags") == "set:abcd", "Merge Registry: Tagged values should be merged");
+
+    // Merging what is already known changes nothing
+    Node node4(4);
+    node4.set_merge_registry(registry);
+    node4.merge_changes(nodes[0]->get_changes_since(0));
+    assert_true(nodes[1]->merge_changes<true>(node4.get_changes_since(0)).empty(),
+                "Merge Registry: Merged values should be stable");
+    std::cout << "Test 'Merge Function Registry' passed." << std::endl;
+  }
+
+  // Test Case: Blob Columns
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    Node node1(1);
+    Node node2(2);
+    CrdtBlobStore blobs1(1024);
+    CrdtBlobStore blobs2(1024);
+    CrdtBytes image(5000);
+    for (size_t i = 0; i < image.size(); ++i) {
+      image[i] = static_cast<uint8_t>(i % 251);
+    }
+    CrdtString manifest = blobs1.put(image);
+    assert_true(CrdtBlobStore::is_manifest(manifest) && manifest.size() < 200 && blobs1.chunk_count() == 5,
+                "Blobs: A blob should be stored as chunks behind a small manifest");
+    node1.insert_or_update("photo", {{"image", manifest}, {"caption", "beach"}});
+    assert_true(blobs1.get(*node1.get_value("photo", "image")) == image, "Blobs: The blob should be readable back");
+
+    node2.merge_changes(node1.get_changes_since(0));
+    assert_true(!blobs2.get(manifest) && blobs2.missing(manifest).size() == 5,
+                "Blobs: Chunks should not travel in changes");
+    auto request = blobs2.request_missing(node2);
+    assert_true(request && is_blob_frame(*request), "Blobs: Missing chunks should be requested");
+    SyncSession<Node> session(node1, 1);
+    assert_true(session.on_frame(*request).empty(), "Blobs: Sync sessions should ignore blob frames");
+    for (const auto &reply : blobs1.on_frame(*request)) {
+      assert_true(blobs2.on_frame(reply).empty(), "Blobs: Chunks should not be answered");
+    }
+    assert_true(blobs2.get(manifest) == image && !blobs2.request_missing(node2),
+                "Blobs: Fetched blobs should be complete");
+
+    // Identical content is stored once; forged chunks are dropped
+    CrdtBytes copy = image;
+    copy[4999] ^= 1;
+    blobs1.put(copy);
+    assert_true(blobs1.chunk_count() == 6, "Blobs: Unchanged chunks should be shared");
+    CrdtBytes forged{static_cast<uint8_t>(SyncMessageType::BlobChunks), 1, 3, 'x', 'y', 'z'};
+    blobs2.on_frame(forged);
+    assert_true(blobs2.chunk_count() == 6 && blobs2.missing(manifest).empty(), "Blobs: Chunks are keyed by their hash");
+
+    node1.delete_record("photo");
+    assert_true(blobs1.collect_garbage(node1) == 6 && blobs1.stored_bytes() == 0,
+                "Blobs: Unreferenced chunks should be collected");
+    std::cout << "Test 'Blob Columns' passed." << std::endl;
+  }
+
+  // Test Case: Purge
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    Node node1(1);
+    Node node2(2);
+    Node stale(3);
+    node2.set_history_depth(4);
+    node1.insert_or_update("user", {{"email", "secret@example.com"}});
+    node1.insert_or_update("user", {{"email", "other@example.com"}});
+    node1.insert_or_update("keep", {{"v", "1"}});
+    node2.merge_changes(node1.get_changes_since(0));
+    stale.merge_changes(node1.get_changes_since(0));
+    assert_true(node2.history("user", "email").size() == 1, "Purge: History should be recorded before the purge");
+
+    std::filesystem::path directory = std::filesystem::temp_directory_path() / "crdt_purge_test";
+    std::filesystem::remove_all(directory);
+    CrdtChangeLog<Node> log(node2, directory);
+    node2.insert_or_update("user", {{"name", "Secret Name"}});
+
+    auto purge = node1.purge("user");
+    assert_true(purge.size() == 1 && Node::is_purge(purge[0]) && node1.is_purged("user") && node1.purge("user").empty(),
+                "Purge: Purging should leave a marker");
+    node2.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(purge));
+    assert_true(node2.is_purged("user") && node2.history("user", "email").empty(),
+                "Purge: Merging a purge should erase the record and its history");
+    for (const auto &entry : std::filesystem::directory_iterator(directory)) {
+      std::ifstream in(entry.path(), std::ios::binary);
+      CrdtString contents((std::istreambuf_iterator<char>(in)), std::istreambuf_iterator<char>());
+      assert_true(contents.find("Secret Name") == CrdtString::npos, "Purge: The change log should forget the record");
+    }
+
+    // Writes and revivals from a replica that has not seen the purge cannot bring the record back
+    stale.insert_or_update("user", {{"email", "again@example.com"}});
+    stale.delete_record("user");
+    stale.revive_record("user", {{"email", "revived@example.com"}});
+    node1.merge_changes(stale.get_changes_since(0));
+    assert_true(node1.is_purged("user") && !node1.get_record("user"), "Purge: A purged record should stay erased");
+    stale.merge_changes(node1.get_changes_since(0));
+    assert_true(stale.is_purged("user") && stale.state_hash() == node1.state_hash(), "Purge: Replicas should converge");
+
+    bool threw = false;
+    try {
+      node1.revive_record("user");
+    } catch (const std::invalid_argument &) {
+      threw = true;
+    }
+    assert_true(threw && node1.insert_or_update("user", {{"email", "x"}}).empty(),
+                "Purge: A purged record cannot be written again");
+    node1.delete_record("keep");
+    assert_true(node1.collect_tombstones(node1.get_clock().current_time()) == 1 && node1.is_purged("user"),
+                "Purge: Tombstone collection should keep purge markers");
+    std::filesystem::remove_all(directory);
+    std::cout << "Test 'Purge' passed." << std::endl;
+  }
+
+  // Test Case: Hash-Chained Operations
+  {
+    using Crdt = CRDT<CrdtString, CrdtString>;
+    using Verifier = CrdtChainVerifier<Crdt>;
+    Crdt site(1);
+    CrdtVector<CrdtBytes> frames;
+    ChainPublisher<CrdtString, CrdtString> publisher(1, [&](const CrdtBytes &frame) { frames.push_back(frame); });
+    size_t registration = publisher.attach(site);
+    site.insert_or_update("a", {{"amount", "10"}});
+    site.insert_or_update("b", {{"amount", "20"}});
+    site.insert_or_update("a", {{"amount", "15"}});
+    assert_true(frames.size() == 3 && publisher.head().seq == site.get_clock().current_time(),
+                "Chain: Every operation should be published");
+
+    // In-order and out-of-order delivery both verify
+    Crdt auditor(2);
+    Verifier verifier(auditor);
+    assert_true(verifier.receive(frames[0]) == Verifier::Status::Applied, "Chain: The first link should apply");
+    assert_true(verifier.receive(frames[2]) == Verifier::Status::Pending, "Chain: A link ahead should wait");
+    assert_true(verifier.gaps().at(1) == 1, "Chain: The gap should be reported");
+    assert_true(verifier.receive(frames[1]) == Verifier::Status::Applied && verifier.applied() == 3 &&
+                    verifier.gaps().empty(),
+                "Chain: The waiting link should apply after its predecessor");
+    assert_true(verifier.receive(frames[1]) == Verifier::Status::Duplicate, "Chain: Replays should be ignored");
+    assert_true(verifier.verify_head(1, publisher.head()) && auditor.state_hash() == site.state_hash(),
+                "Chain: The auditor should prove it received every operation");
+
+    // An intermediary that alters a value, with or without fixing up the hash, is caught
+    auto link = crdt_chain::decode_link(frames[1]);
+    auto op = crdt_ops::decode_op<CrdtString, CrdtString>(link.op);
+    op.changes[0].value = "2000";
+    link.op = crdt_ops::encode_op(op);
+    Crdt victim(3);
+    Verifier checked(victim);
+    checked.receive(frames[0]);
+    assert_true(checked.receive(crdt_chain::encode_link(link)) == Verifier::Status::Rejected,
+                "Chain: Altered content should be rejected");
+    link.hash = crdt_chain::link_hash(CrdtBlobStore::fnv_hash, link.prev_hash, link.op);
+    assert_true(checked.receive(crdt_chain::encode_link(link)) == Verifier::Status::Applied,
+                "Chain: A rehashed link still extends the chain");
+    assert_true(checked.receive(frames[2]) == Verifier::Status::Rejected && checked.violations().size() == 2 &&
+                    !checked.verify_head(1, publisher.head()),
+                "Chain: The next link should expose the rewrite");
+
+    // A dropped operation leaves its successors pending and the head unproven
+    Crdt lossy(4);
+    Verifier dropped(lossy);
+    dropped.receive(frames[0]);
+    dropped.receive(frames[2]);
+    assert_true(!dropped.verify_head(1, publisher.head()) && dropped.gaps().size() == 1 && !lossy.get_record("b"),
+                "Chain: A dropped operation should be detected");
+
+    // A restarted site continues its chain from the saved head
+    ChainPublisher<CrdtString, CrdtString> restarted(1, [&](const CrdtBytes &frame) { frames.push_back(frame); },
+                                                     publisher.head());
+    site.remove_on_change(registration);
+    restarted.attach(site);
+    site.delete_record("b");
+    assert_true(verifier.receive(frames.back()) == Verifier::Status::Applied &&
+                    verifier.verify_head(1, restarted.head()),
+                "Chain: A restarted site should continue its chain");
+
+    // A verifier joining late starts from a trusted head
+    Crdt late(5);
+    late.merge_changes(site.get_changes_since(0));
+    Verifier joined(late);
+    joined.trust(1, publisher.head());
+    assert_true(joined.receive(frames.back()) == Verifier::Status::Applied, "Chain: A trusted head should anchor");
+    std::cout << "Test 'Hash-Chained Operations' passed." << std::endl;
+  }
+
+  // Test Case: Sync Protocol Negotiation
+  {
+    using Node = CRDT<CrdtString, int64_t>;
+    Node server(1);
+    Node client(2);
+    server.insert_or_update("a", {{"n", 1}});
+    SyncSession<Node> server_session(server, 1);
+    SyncSession<Node> client_session(client, 2);
+    pump_sync_sessions(server_session, client_session);
+    assert_true(server_session.protocol_version() == CRDT_SYNC_PROTOCOL_VERSION &&
+                    server_session.shares_capability(SyncFramedValues) && client.get_data() == server.get_data(),
+                "Negotiation: Current peers should use the current version and capabilities");
+
+    // A client on protocol version 2 gets an answer in version 2, without framed values
+    auto hello = [](uint64_t version, bool extra) {
+      CrdtBytes frame;
+      ByteWriter writer(frame);
+      writer.write_u8(static_cast<uint8_t>(SyncMessageType::Hello));
+      writer.write_varint(version);
+      CrdtCodec<CrdtNodeId>::encode(writer, CrdtNodeId(3));
+      writer.write_varint(0);
+      writer.write_varint(1);
+      if (version >= 3) {
+        writer.write_varint(SyncFramedValues | (uint64_t{1} << 40));
+      }
+      if (extra) {
+        writer.write_varint(12345); // a field from a later version
+      }
+      return frame;
+    };
+    SyncSession<Node> old_peer(server, 1);
+    auto changes = old_peer.on_frame(hello(2, false));
+    CrdtBytes answer = old_peer.start()[0];
+    ByteReader answer_reader(answer);
+    answer_reader.read_u8();
+    assert_true(answer_reader.read_varint() == 2 && old_peer.protocol_version() == 2 &&
+                    old_peer.peer_capabilities() == 0,
+                "Negotiation: The answer should be in the older version");
+    ByteReader changes_reader(changes[0]);
+    changes_reader.read_u8();
+    changes_reader.read_varint();
+    assert_true(changes_reader.read_u8() == static_cast<uint8_t>(CrdtCompression::None), "Negotiation: Small batch");
+    changes_reader.read_varint();
+    changes_reader.read_varint(); // blob size
+    changes_reader.read_varint(); // change count
+    assert_true((changes_reader.read_u8() & crdt_codec::FramedValue) == 0,
+                "Negotiation: Older peers should not get framed values");
+
+    // Newer peers' extra Hello fields are ignored; versions older than the minimum are rejected
+    SyncSession<Node> new_peer(server, 1);
+    new_peer.on_frame(hello(CRDT_SYNC_PROTOCOL_VERSION + 1, true));
+    assert_true(new_peer.protocol_version() == CRDT_SYNC_PROTOCOL_VERSION &&
+                    new_peer.peer_capabilities() == (SyncFramedValues | (uint64_t{1} << 40)),
+                "Negotiation: A newer peer should be answered in our version");
+    bool threw = false;
+    try {
+      SyncSession<Node>(server, 1).on_frame(hello(CRDT_SYNC_MIN_PROTOCOL_VERSION - 1, false));
+    } catch (const SyncProtocolError &) {
+      threw = true;
+    }
+    assert_true(threw, "Negotiation: Too old a version should be rejected");
+
+    // A change whose value this node cannot decode is skipped, not fatal
+    CrdtBytes batch;
+    ByteWriter writer(batch);
+    writer.write_varint(2);
+    crdt_codec::encode_change(writer, Change<CrdtString, CrdtString>("b", "n", "a new type", 1, 1, 4), true);
+    crdt_codec::encode_change(writer, Change<CrdtString, int64_t>("c", "n", 7, 1, 2, 4), true);
+    CrdtBytes frame;
+    ByteWriter frame_writer(frame);
+    frame_writer.write_u8(static_cast<uint8_t>(SyncMessageType::Changes));
+    frame_writer.write_varint(2);
+    frame_writer.write_u8(static_cast<uint8_t>(CrdtCompression::None));
+    frame_writer.write_varint(batch.size());
+    frame_writer.write_blob(batch.data(), batch.size());
+    SyncSession<Node> newer(client, 2);
+    newer.on_frame(hello(3, false));
+    newer.on_frame(frame);
+    assert_true(newer.changes_skipped() == 1 && newer.changes_received() == 1 && *client.get_value("c", "n") == 7,
+                "Negotiation: Undecodable values should be skipped");
+
+    // Extension fields of later versions are skipped
+    CrdtBytes extended = crdt_codec::encode_changes(CrdtVector<Change<CrdtString, int64_t>>{{"d", "n", 5, 1, 1, 4}});
+    extended[1] |= crdt_codec::HasExtensions;
+    CrdtBytes extension = {1, 2, 3};
+    ByteWriter(extended).write_blob(extension.data(), extension.size());
+    auto decoded = crdt_codec::decode_changes<CrdtString, int64_t>(extended);
+    assert_true(decoded.size() == 1 && *decoded[0].value == 5, "Negotiation: Unknown extensions should be skipped");
+    std::cout << "Test 'Sync Protocol Negotiation' passed." << std::endl;
+  }
+
+  // Test Case: Unknown Data Preservation
+  {
+    // The newer app version writes text values the older one, which only knows integers, cannot decode
+    using Newer = CRDT<CrdtString, CrdtString>;
+    using Older = CRDT<CrdtString, int64_t>;
+    Newer writer(1);
+    Older old_node(2);
+    Newer reader(3);
+    CrdtOpaqueStore<CrdtString> store;
+    auto sync = [&](auto &newer, uint64_t newer_id) {
+      SyncSession<std::remove_reference_t<decltype(newer)>> newer_session(newer, newer_id);
+      SyncSession<Older> old_session(old_node, 2);
+      old_session.set_opaque_store(store);
+      pump_sync_sessions(newer_session, old_session);
+      return old_session.changes_skipped();
+    };
+    old_node.insert_or_update("counter", {{"n", 3}});
+    writer.insert_or_update("doc", {{"title", "Hello"}});
+    assert_true(sync(writer, 1) == 1 && store.size() == 1 && !old_node.get_record("doc"),
+                "Preservation: Undecodable changes should be kept aside");
+    sync(reader, 3);
+    assert_true(reader.get_value("doc", "title") && *reader.get_value("doc", "title") == "Hello",
+                "Preservation: Kept changes should be passed on to other peers");
+
+    writer.insert_or_update("doc", {{"title", "Hello again"}});
+    sync(writer, 1);
+    CrdtOpaqueStore<CrdtString> restored = CrdtOpaqueStore<CrdtString>::load(store.save());
+    assert_true(store.size() == 1 && restored.size() == 1 &&
+                    restored.since(0).front()->encoded == store.since(0).front()->encoded,
+                "Preservation: Only the latest change per column should be kept, and survive a restart");
+    sync(reader, 3);
+    assert_true(*reader.get_value("doc", "title") == "Hello again",
+                "Preservation: The newer change should be passed on");
+
+    writer.delete_record("doc");
+    sync(writer, 1);
+    store.prune(old_node);
+    assert_true(store.size() == 0, "Preservation: Superseded changes should be dropped");
+
+    // A strict schema can keep columns added by a newer version instead of rejecting them
+    Newer strict(4);
+    strict.set_schema(CrdtSchema<CrdtString>().column("title", {}).strict().preserve_unknown());
+    Newer newer(5);
+    newer.insert_or_update("note", {{"title", "Plan"}, {"tags", "work"}});
+    strict.merge_changes(newer.get_changes_since(0));
+    Newer third(6);
+    third.merge_changes(strict.get_changes_since(0));
+    assert_true(third.get_value("note", "tags") && *third.get_value("note", "tags") == "work",
+                "Preservation: Unknown columns should be stored and passed on");
+    bool threw = false;
+    try {
+      strict.insert_or_update("note", {{"tags", "home"}});
+    } catch (const std::invalid_argument &) {
+      threw = true;
+    }
+    assert_true(threw, "Preservation: Local writes to unknown columns should still be rejected");
+    std::cout << "Test 'Unknown Data Preservation' passed." << std::endl;
+  }
+
+  // Test Case: QUIC Table Streams
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    Node server_node(1);
+    Node client_node(2);
+    server_node.insert_or_update("notes/s", {{"v", "server"}});
+    client_node.insert_or_update("notes/a", {{"v", "client"}});
+    client_node.insert_or_update("tasks/b", {{"v", "client"}});
+    client_node.insert_or_update("drafts/c", {{"v", "client"}});
+
+    CrdtMap<CrdtString, CrdtMap<CrdtNodeId, uint64_t>> server_cursors, client_cursors;
+    QuicSync<Node> server(server_node, 1, server_cursors);
+    QuicSync<Node> client(client_node, 2, client_cursors);
+    CrdtMap<uint64_t, CrdtBytes> to_server, to_client;
+    uint64_t client_version = client_node.get_clock().current_time();
+    to_server[0] = client.open_stream(0, "notes");
+    to_server[4] = client.open_stream(4, "tasks");
+
+    // Streams progress independently: "tasks" completes while "notes" data trickles in one byte at a time
+    auto pump = [&](uint64_t stream, bool byte_by_byte) {
+      while (!to_server[stream].empty() || !to_client[stream].empty()) {
+        CrdtBytes data = std::exchange(to_server[stream], {});
+        for (size_t i = 0; i < data.size(); i += byte_by_byte ? 1 : data.size()) {
+          CrdtBytes reply = server.on_stream_data(stream, data.data() + i, byte_by_byte ? 1 : data.size());
+          to_client[stream].insert(to_client[stream].end(), reply.begin(), reply.end());
+        }
+        data = std::exchange(to_client[stream], {});
+        CrdtBytes reply = client.on_stream_data(stream, data.data(), data.size());
+        to_server[stream].insert(to_server[stream].end(), reply.begin(), reply.end());
+      }
+    };
+    pump(4, false);
+    assert_true(server.is_complete(4) && !server.is_complete(0) && server_node.contains_record("tasks/b") &&
+                    !server_node.contains_record("notes/a"),
+                "QUIC: A table should sync on its own stream");
+    pump(0, true);
+    assert_true(server.is_complete(0) && client.is_complete(0) && server_node.contains_record("notes/a") &&
+                    client_node.contains_record("notes/s") && !server_node.contains_record("drafts/c"),
+                "QUIC: Each stream should sync only its table");
+    assert_true(server.table_of(0) == "notes" && server_cursors["tasks"][2] == client_version,
+                "QUIC: The accepting side should keep a cursor per table and peer");
+    auto result = client.close_stream(0);
+    assert_true(result && result->completed && result->changes_received == 1 && client.open_streams() == 1,
+                "QUIC: Closing a stream should report its exchange");
+
+    bool threw = false;
+    try {
+      CrdtBytes header;
+      ByteWriter writer(header);
+      CrdtCodec<CrdtString>::encode(writer, CrdtString("a/b"));
+      CrdtBytes data = crdt_quic::encode_frame(header);
+      server.on_stream_data(8, data.data(), data.size());
+    } catch (const SyncProtocolError &) {
+      threw = true;
+    }
+    assert_true(threw, "QUIC: Table names that are not namespaces should be rejected");
+    std::cout << "Test 'QUIC Table Streams' passed." << std::endl;
+  }
+
+  // Test Case: Peer-to-Peer Sync
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    using Sync = P2pSync<Node>;
+    // An in-memory network: requests are answered at once, gossip reaches every subscriber unless dropped
+    CrdtMap<CrdtString, Sync *> network;
+    bool drop_gossip = false;
+    CrdtString dropped_for;
+    auto node_sync = [&](Node &node, CrdtNodeId node_id, const CrdtString &peer_id) {
+      return std::make_unique<Sync>(
+          node, node_id,
+          [&network, peer_id](const CrdtString &peer, const CrdtBytes &request) {
+            network.at(peer_id)->on_response(peer, network.at(peer)->on_request(peer_id, request));
+          },
+          [&, peer_id](const CrdtBytes &message) {
+            for (auto &[id, sync] : network) {
+              if (id != peer_id && !(drop_gossip && id == dropped_for)) {
+                sync->on_message(peer_id, message);
+              }
+            }
+          });
+    };
+    Node a(1), b(2), c(3);
+    auto sync_a = node_sync(a, 1, "peer-a");
+    auto sync_b = node_sync(b, 2, "peer-b");
+    auto sync_c = node_sync(c, 3, "peer-c");
+    network = {{"peer-a", sync_a.get()}, {"peer-b", sync_b.get()}, {"peer-c", sync_c.get()}};
+
+    a.insert_or_update("doc", {{"title", "Draft"}});
+    assert_true(b.get_data() == a.get_data() && c.get_data() == a.get_data() && sync_b->messages_applied() == 1,
+                "P2P: Local operations should be gossiped");
+
+    // A lost gossip message is repaired by pulling from the peer that relayed the next one
+    drop_gossip = true;
+    dropped_for = "peer-c";
+    a.insert_or_update("doc", {{"body", "lost"}});
+    drop_gossip = false;
+    b.insert_or_update("note", {{"title", "from b"}});
+    assert_true(!c.get_value("doc", "body"), "P2P: The dropped operation should be missing");
+    a.insert_or_update("doc", {{"title", "Final"}});
+    assert_true(sync_c->gaps_detected() == 1 && c.get_data() == a.get_data() && sync_c->cursors().at("peer-a") > 0,
+                "P2P: A gap should trigger a pull");
+
+    // A newly discovered peer catches up with a pull, without echoing its own writes back
+    Node d(4);
+    d.insert_or_update("own", {{"v", "d"}});
+    auto sync_d = node_sync(d, 4, "peer-d");
+    network["peer-d"] = sync_d.get();
+    sync_d->on_peer_discovered("peer-b");
+    assert_true(d.contains_record("doc") && d.contains_record("note") && sync_b->requests_served() == 1,
+                "P2P: A discovered peer should be pulled from");
+    sync_b->on_peer_discovered("peer-d");
+    assert_true(b.contains_record("own"), "P2P: Pulls should work both ways");
+    std::cout << "Test 'Peer-to-Peer Sync' passed." << std::endl;
+  }
+
+  // Test Case: Hub Relay
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    std::filesystem::path directory = std::filesystem::temp_directory_path() / "crdt_hub_test";
+    std::filesystem::remove_all(directory);
+    Node a(1), b(2), c(3);
+    {
+      CrdtHub hub(directory);
+      HubClient<Node> client_a(a, 1, "doc"), client_b(b, 2, "doc");
+      a.insert_or_update("r1", {{"title", "from a"}});
+      b.insert_or_update("r2", {{"title", "from b"}});
+      client_a.on_frame(hub.on_frame(*client_a.push_frame()));
+      client_b.on_frame(hub.on_frame(*client_b.push_frame()));
+      assert_true(!client_a.push_frame() && hub.batch_count("doc") == 2, "Hub: Pushed changes should not be resent");
+
+      assert_true(!client_a.on_frame(hub.on_frame(client_a.pull_frame())) && client_a.cursor() == 2,
+                  "Hub: A pull should move the cursor past every batch");
+      client_b.on_frame(hub.on_frame(client_b.pull_frame()));
+      assert_true(a.get_data() == b.get_data(), "Hub: Clients should converge through the hub");
+      // Changes merged from the hub are not pushed back
+      assert_true(!client_b.push_frame(), "Hub: Merged changes should not be pushed");
+      assert_true(hub.pull("doc", 0, 1).batches.size() == 1, "Hub: A client should not pull its own batches");
+
+      bool threw = false;
+      try {
+        hub.push("../escape", 1, {});
+      } catch (const std::invalid_argument &) {
+        threw = true;
+      }
+      assert_true(threw, "Hub: Topic names should be validated");
+    }
+
+    // Batches survive a restart, and a torn record at the end is dropped
+    {
+      std::ofstream torn(directory / "doc.hub", std::ios::binary | std::ios::app);
+      torn.write("\0\0\0\x10\x01", 5);
+    }
+    CrdtHub hub(directory);
+    assert_true(hub.batch_count("doc") == 2 && hub.topics().size() == 1, "Hub: Batches should be reloaded");
+    HubClient<Node> client_c(c, 3, "doc");
+    client_c.on_frame(hub.on_frame(client_c.pull_frame(), 1));
+    assert_true(c.contains_record("r1") && !c.contains_record("r2") && client_c.cursor() == 1,
+                "Hub: Replies should be limited to the maximum batch count");
+    assert_true(!client_c.on_frame(hub.on_frame(client_c.pull_frame(), 1)) && c.get_data() == a.get_data(),
+                "Hub: A later pull should resume at the cursor");
+    c.insert_or_update("r3", {{"title", "from c"}});
+    client_c.on_frame(hub.on_frame(*client_c.push_frame()));
+    assert_true(hub.batch_count("doc") == 3, "Hub: Pushes should append after reloaded batches");
+
+    {
+      std::fstream file(directory / "doc.hub", std::ios::binary | std::ios::in | std::ios::out);
+      file.seekp(crdt_codec::RECORD_HEADER_SIZE + 2);
+      file.put('\xff');
+    }
+    bool corrupted = false;
+    try {
+      CrdtHub reopened(directory);
+    } catch (const CrdtDecodeError &) {
+      corrupted = true;
+    }
+    assert_true(corrupted, "Hub: Corrupted topic files should not be relayed");
+    std::filesystem::remove_all(directory);
+    std::cout << "Test 'Hub Relay' passed." << std::endl;
+  }
+
+  // Test Case: Merge Rate Limiting
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    using Queue = CrdtMergeQueue<Node>;
+    Node source(1), target(2);
+    for (int i = 0; i < 30; ++i) {
+      source.insert_or_update("r" + std::to_string(i), {{"v", std::to_string(i)}});
+    }
+    auto changes = source.get_changes_since(0);
+    CrdtVector<bool> signals;
+    CrdtMergeLimits limits;
+    limits.changes_per_second = 10;
+    limits.max_batch_bytes = 1000;
+    limits.max_pending_changes = 40;
+    limits.pause_above = 20;
+    Queue queue(target, limits, [&](bool paused) { signals.push_back(paused); });
+
+    auto start = Queue::Clock::time_point{} + std::chrono::hours(1);
+    assert_true(queue.offer(changes, 500) == Queue::Admission::Paused && signals == CrdtVector<bool>{true},
+                "Rate Limit: A long backlog should pause the transport");
+    assert_true(queue.offer(changes, 2000) == Queue::Admission::Rejected && queue.rejected_batches() == 1,
+                "Rate Limit: Oversized batches should be rejected");
+    assert_true(queue.process(start) == 10 && queue.process(start) == 0 && target.get_data().size() == 10,
+                "Rate Limit: A burst should be limited to a second's worth");
+    assert_true(queue.time_until_ready(start) == std::chrono::milliseconds(100),
+                "Rate Limit: The next merge should be ready once a change is allowed");
+    assert_true(queue.process(start + std::chrono::milliseconds(500)) == 5 && signals.size() == 1,
+                "Rate Limit: Changes should be merged at the configured rate");
+    assert_true(queue.process(start + std::chrono::seconds(2)) == 10 && signals == CrdtVector<bool>{true, false},
+                "Rate Limit: The transport should resume once the queue drains");
+    queue.offer(changes, 500);
+    assert_true(queue.offer(changes, 500) == Queue::Admission::Rejected, "Rate Limit: A full queue should reject");
+    for (int second = 3; second < 10; ++second) {
+      queue.process(start + std::chrono::seconds(second));
+    }
+    assert_true(queue.pending_changes() == 0 && target.get_data() == source.get_data() && queue.merged_changes() == 60,
+                "Rate Limit: Every queued change should eventually be merged");
+    std::cout << "Test 'Merge Rate Limiting' passed." << std::endl;
+  }
+
+  // Test Case: Resumable Snapshot Transfer
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    Node server(1);
+    for (int i = 0; i < 25; ++i) {
+      server.insert_or_update("r" + std::to_string(i), {{"v", std::to_string(i)}});
+    }
+    SnapshotServer<Node> snapshots(server, 10);
+    Node replica(2);
+    CrdtSnapshotProgress saved;
+    {
+      SnapshotReceiver<Node> receiver(replica);
+      snapshots.on_frame(*receiver.request_frame()); // the reply is lost with the connection
+      assert_true(receiver.on_frame(snapshots.on_frame(*receiver.request_frame())) ==
+                      SnapshotReceiver<Node>::Status::Merged,
+                  "Snapshot: The first chunk should be merged");
+      // A corrupted chunk is dropped and requested again
+      CrdtBytes corrupted = snapshots.on_frame(*receiver.request_frame());
+      corrupted.back() ^= 1;
+      assert_true(receiver.on_frame(corrupted) == SnapshotReceiver<Node>::Status::Corrupt &&
+                      receiver.progress().next_chunk == 1 && receiver.corrupt_chunks() == 1,
+                  "Snapshot: A chunk failing its checksum should be rejected");
+      saved = CrdtSnapshotProgress::load(receiver.progress().save());
+    }
+    assert_true(replica.get_data().size() == 10 && saved.total_chunks == 3,
+                "Snapshot: Chunks should be merged as they arrive");
+
+    // Changes made during the transfer arrive with the tail
+    server.insert_or_update("late", {{"v", "tail"}});
+    SnapshotReceiver<Node> resumed(replica, saved);
+    while (std::optional<CrdtBytes> request = resumed.request_frame()) {
+      resumed.on_frame(snapshots.on_frame(*request));
+    }
+    assert_true(replica.get_data() == server.get_data() && snapshots.chunks_served() == 5,
+                "Snapshot: A resumed transfer should continue at the next chunk");
+    assert_true(resumed.last_received_version() == server.get_clock().current_time() && snapshots.snapshot_count() == 1,
+                "Snapshot: The tail should cover the server's current version");
+
+    // A receiver holding an unknown snapshot id starts over on a new snapshot
+    Node other(3);
+    CrdtSnapshotProgress stale;
+    stale.snapshot_id = "gone";
+    stale.next_chunk = 2;
+    stale.total_chunks = 3;
+    SnapshotReceiver<Node> restarted(other, stale);
+    restarted.on_frame(snapshots.on_frame(*restarted.request_frame()));
+    assert_true(restarted.progress().snapshot_id != "gone" && restarted.progress().next_chunk == 1,
+                "Snapshot: An unknown snapshot should restart the transfer");
+    std::cout << "Test 'Resumable Snapshot Transfer' passed." << std::endl;
+  }
+
+  // Test Case: Replication Lag Reporting
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    using Gossip = GossipNode<Node>;
+    Node node1(1), node2(2), node3(3);
+    Gossip gossip1(node1, 1), gossip2(node2, 2);
+    gossip1.add_peer(2);
+    gossip1.add_peer(3);
+    node1.insert_or_update("a", {{"v", "1"}});
+    node1.insert_or_update("b", {{"v", "2"}});
+    auto start = Gossip::Clock::time_point{} + std::chrono::hours(1);
+
+    CrdtPeerLag before = gossip1.lag(2);
+    assert_true(before.acked_version == 0 && before.versions_behind == node1.get_clock().current_time() &&
+                    !before.last_exchange,
+                "Lag: A peer that never synced should lag by everything");
+    auto initiator = gossip1.open_session(2);
+    auto responder = gossip2.open_session(1);
+    pump_sync_sessions(initiator, responder);
+    gossip1.close_session(initiator, start);
+    CrdtPeerLag synced = gossip1.lag(2);
+    assert_true(synced.versions_behind == 0 && synced.last_exchange == start,
+                "Lag: A completed session should clear the lag");
+
+    node1.insert_or_update("c", {{"v", "3"}});
+    assert_true(gossip1.lag(2).versions_behind == 1, "Lag: New local changes should count against the peer");
+    assert_true(gossip1.lagging_peers(5, std::chrono::minutes(1), start) == CrdtVector<CrdtNodeId>{3},
+                "Lag: Peers that never synced should be reported");
+    assert_true(gossip1.lagging_peers(0, std::chrono::minutes(1), start).size() == 2,
+                "Lag: Peers behind the version threshold should be reported");
+    assert_true(gossip1.lagging_peers(5, std::chrono::minutes(1), start + std::chrono::minutes(2)).size() == 2,
+                "Lag: Peers silent for too long should be reported");
+    std::cout << "Test 'Replication Lag Reporting' passed." << std::endl;
+  }
+
+  // Test Case: Deterministic Network Simulator
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    // Random writes on four nodes over a lossy network, then quiescence
+    auto simulate = [](uint64_t seed) {
+      CrdtSimOptions options;
+      options.seed = seed;
+      options.drop_rate = 0.2;
+      options.duplicate_rate = 0.1;
+      auto sim = std::make_unique<CrdtSimulator<Node>>(options);
+      for (CrdtNodeId id = 1; id <= 4; ++id) {
+        sim->add_node(id);
+      }
+      for (int i = 0; i < 40; ++i) {
+        Node &node = sim->node(sim->random(4) + 1);
+        CrdtString record = "r" + std::to_string(sim->random(8));
+        if (sim->random(5) == 0) {
+          node.delete_record(record);
+        } else {
+          node.insert_or_update(record, {{"v", std::to_string(i)}});
+        }
+        sim->run(sim->random(4));
+      }
+      return sim;
+    };
+    auto sim = simulate(7);
+    assert_true(sim->run_until_converged(10000), "Sim: Nodes should converge despite drops and duplicates");
+    assert_true(sim->network().dropped() > 0 && sim->network().duplicated() > 0,
+                "Sim: The network should drop and duplicate messages");
+
+    auto replay = simulate(7);
+    replay->run_until_converged(10000);
+    assert_true(replay->now() == sim->now() && replay->network().sent() == sim->network().sent() &&
+                    replay->node(1).state_hash() == sim->node(1).state_hash(),
+                "Sim: The same seed should replay the same schedule");
+    auto other = simulate(8);
+    assert_true(other->run_until_converged(10000) && other->network().sent() != sim->network().sent(),
+                "Sim: Another seed should give another schedule");
+    std::cout << "Test 'Deterministic Network Simulator' passed." << std::endl;
+  }
+
+  // Test Case: Recorded History Replay
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    std::filesystem::path path = std::filesystem::temp_directory_path() / "crdt_replay_test.rec";
+    std::filesystem::remove(path);
+    Node node(1), peer(2);
+    peer.insert_or_update("shared", {{"title", "from peer"}});
+    {
+      CrdtRecorder<Node> recorder(node, 1, path);
+      node.insert_or_update("local", {{"v", "1"}});
+      recorder.merge(peer.get_changes_since(0));
+      recorder.merge(peer.get_changes_since(0)); // a duplicate applies nothing
+      node.delete_record("local");
+    }
+    {
+      std::ofstream torn(path, std::ios::binary | std::ios::app);
+      torn.write("\0\0\0\x20\x01", 5);
+    }
+
+    CrdtReplayer<Node> replayer(path);
+    assert_true(replayer.node_id() == 1 && replayer.steps().size() == 6, "Replay: Every step should be recorded");
+    assert_true(replayer.steps()[1].kind == CrdtRecordedStep<CrdtString, CrdtString>::Kind::Received &&
+                    replayer.steps()[4].changes.empty(),
+                "Replay: Received batches should be followed by what they applied");
+    replayer.seek(1);
+    assert_true(replayer.state().get_record("local") && !replayer.state().get_record("shared"),
+                "Replay: The state should be rebuilt step by step");
+    replayer.seek(6);
+    assert_true(replayer.state().get_data() == node.get_data() &&
+                    replayer.state().get_changes_since(0).size() == node.get_changes_since(0).size() &&
+                    replayer.state().get_clock().current_time() == node.get_clock().current_time(),
+                "Replay: The final state should match the node");
+    assert_true(!replayer.first_divergence() && replayer.position() == 6,
+                "Replay: A faithful recording should replay identically");
+    {
+      std::fstream file(path, std::ios::binary | std::ios::in | std::ios::out);
+      file.seekp(crdt_codec::RECORD_HEADER_SIZE);
+      file.put('\x7f');
+    }
+    bool corrupted = false;
+    try {
+      CrdtReplayer<Node> unreadable(path);
+    } catch (const CrdtDecodeError &) {
+      corrupted = true;
+    }
+    assert_true(corrupted, "Replay: Corrupted recordings should be rejected");
+
+    // A merge that applied something else than the replay does is reported
+    {
+      std::ofstream out(path, std::ios::binary | std::ios::trunc);
+    }
+    {
+      CrdtRecorder<Node> recorder(node, 1, path);
+    }
+    Node tampered(3);
+    tampered.insert_or_update("x", {{"v", "1"}});
+    {
+      std::ofstream out(path, std::ios::binary | std::ios::app);
+      auto append = [&](uint8_t kind, const CrdtVector<Change<CrdtString, CrdtString>> &changes) {
+        CrdtBytes payload;
+        ByteWriter writer(payload);
+        writer.write_u8(kind);
+        writer.write_varint(changes.size());
+        for (const auto &change : changes) {
+          writer.write_varint(change.local_db_version);
+          crdt_codec::encode_change(writer, change);
+        }
+        crdt_replay::write_record(out, payload);
+      };
+      append(2, tampered.get_changes_since(0));
+      append(1, {});
+    }
+    assert_true(CrdtReplayer<Node>(path).first_divergence() == 0, "Replay: A diverging merge should be reported");
+    std::filesystem::remove(path);
+    std::cout << "Test 'Recorded History Replay' passed." << std::endl;
+  }
+
+  // Test Case: Fuzzing Entry Points
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    node.insert_or_update("r", {{"a", "1"}, {"b", "2"}});
+    node.delete_record("r");
+    CrdtBytes batch = crdt_codec::encode_changes(node.get_changes_since(0));
+    assert_true(crdt_fuzz::decode_changes(batch.data(), batch.size()) == CrdtFuzzResult::Ok,
+                "Fuzz: A valid batch should decode");
+    assert_true(crdt_fuzz::decode_changes(batch.data(), batch.size() - 1) == CrdtFuzzResult::Rejected,
+                "Fuzz: A truncated batch should be rejected");
+
+    // Random inputs may be rejected but must never fail or break an invariant
+    std::mt19937_64 rng(11);
+    for (int i = 0; i < 3000; ++i) {
+      CrdtBytes input(rng() % 128);
+      for (uint8_t &byte : input) {
+        byte = static_cast<uint8_t>(rng());
+      }
+      for (auto target : {crdt_fuzz::decode_changes, crdt_fuzz::sync_frames, crdt_fuzz::merge_sequence}) {
+        CrdtFuzzResult result = target(input.data(), input.size());
+        assert_true(result == CrdtFuzzResult::Ok || result == CrdtFuzzResult::Rejected,
+                    "Fuzz: Arbitrary input should be handled");
+      }
+    }
+    std::cout << "Test 'Fuzzing Entry Points' passed." << std::endl;
+  }
+
+  // Test Case: Live Record Counts
+  {
+    using Node = CRDT<CrdtString, CrdtString>;
+    auto check = [](const Node &node, const std::string &what) {
+      size_t live = 0;
+      size_t tombstones = 0;
+      node.for_each_record([&](const CrdtString &, const Record<CrdtString> &) { ++live; });
+      node.for_each_tombstone([&](const CrdtString &, const CrdtTombstone &) { ++tombstones; });
+      assert_true(node.len_live() == live && node.len_tombstones() == tombstones && node.is_empty_live() == (live == 0),
+                  "Live Counts: " + what);
+    };
+    Node node1(1);
+    Node node2(2);
+    check(node1, "An empty CRDT should count nothing");
+    std::mt19937_64 rng(7);
+    for (int i = 0; i < 2000; ++i) {
+      Node &node = rng() % 2 ? node1 : node2;
+      CrdtString record = "r" + std::to_string(rng() % 50);
+      switch (rng() % 6) {
+      case 0:
+      case 1:
+        node.insert_or_update(record, {{"c", std::to_string(i)}});
+        break;
+      case 2:
+        node.delete_record(record);
+        break;
+      case 3:
+        node.merge_changes((&node == &node1 ? node2 : node1).get_changes_since(0));
+        break;
+      case 4:
+        node.snapshot();
+        break;
+      default:
+        node.collect_tombstones(node.get_clock().current_time() / 2);
+        break;
+      }
+      check(node, "Counts should follow writes, deletions, merges, snapshots and collection");
+    }
+    node1.purge("r1");
+    check(node1, "Purging should count a tombstone");
+
+    Node rebuilt(3, node1.get_changes_since(0));
+    check(rebuilt, "Changes loaded at construction should be counted");
+    rebuilt.reset({});
+    check(rebuilt, "Resetting should clear the counts");
+
+    auto parent = std::make_shared<Node>(node1);
+    Node child(4, parent);
+    child.insert_or_update("child", {{"c", "1"}});
+    parent->insert_or_update("parent only", {{"c", "1"}});
+    check(child, "A child should count its parent's current records");
+    std::cout << "Test 'Live Record Counts' passed." << std::endl;
+  }
+
+  // Test Case: Streaming Snapshots
+  {
+    using Crdt = CRDT<CrdtString, CrdtString>;
+    std::filesystem::path directory = std::filesystem::temp_directory_path() / "crdt_streaming_snapshot_test";
+    std::filesystem::remove_all(directory);
+    Crdt node(1);
+    for (int i = 0; i < 3000; ++i) {
+      node.insert_or_update("r" + std::to_string(i), {{"a", std::to_string(i)}, {"b", "x"}});
+    }
+    for (int i = 0; i < 3000; i += 10) {
+      node.delete_record("r" + std::to_string(i));
+    }
+    size_t streamed = 0;
+    node.for_each_change_since(0, [&](const ChangeRef<CrdtString, CrdtString> &) { ++streamed; });
+    assert_true(streamed == node.get_change_refs_since(0).size(), "Streaming Snapshots: Should visit every change");
+
+    {
+      CrdtChangeLog<Crdt> log(node, directory);
+      log.compact();
+      node.insert_or_update("after", {{"a", "1"}});
+    }
+    Crdt recovered = CrdtChangeLog<Crdt>::recover(directory, 1);
+    assert_true(recovered.state_hash() == node.state_hash() &&
+                    recovered.get_clock().current_time() == node.get_clock().current_time(),
+                "Streaming Snapshots: A streamed snapshot should recover the state and clock");
+    assert_true(recovered.get_changes_since(3200).size() == node.get_changes_since(3200).size(),
+                "Streaming Snapshots: Recovery should keep the local versions");
+
+    auto changes = node.get_changes_since(0);
+    Crdt loaded(1);
+    loaded.load_changes(CrdtVector<Change<CrdtString, CrdtString>>(changes.begin() + 2000, changes.end()));
+    loaded.load_changes(CrdtVector<Change<CrdtString, CrdtString>>(changes.begin(), changes.begin() + 2000));
+    assert_true(loaded.state_hash() == node.state_hash() &&
+                    loaded.get_clock().current_time() == node.get_clock().current_time(),
+                "Streaming Snapshots: Loading in batches should not move the clock back");
+    std::filesystem::remove_all(directory);
+    std::cout << "Test 'Streaming Snapshots' passed." << std::endl;
+  }
+
+  // Test Case: Archived Snapshots
+  {
+    using Crdt = CRDT<CrdtString, CrdtString>;
+    std::filesystem::path path = std::filesystem::temp_directory_path() / "crdt_archive_test.arc";
+    Crdt node(1);
+    for (int i = 0; i < 500; ++i) {
+      node.insert_or_update("r" + std::to_string(i), {{"name", "n" + std::to_string(i)}, {"tag", "t"}});
+    }
+    node.delete_record("r7");
+    node.delete_field("r8", "tag");
+    crdt_archive::save(node, path);
+
+    CrdtArchive<Crdt> archive(path);
+    assert_true(archive.len_live() == 499 && archive.len_tombstones() == 1 &&
+                    archive.clock_time() == node.get_clock().current_time(),
+                "Archive: The header should hold the counts and clock");
+    assert_true(archive.get_value("r42", "name") == std::optional<CrdtString>("n42") &&
+                    !archive.get_value("r8", "tag") && !archive.get_value("r7", "name"),
+                "Archive: Values should be read in place");
+    assert_true(archive.status("r7") == RecordStatus::Tombstoned && archive.status("r1") == RecordStatus::Live &&
+                    archive.status("missing") == RecordStatus::Unknown,
+                "Archive: Statuses should be looked up");
+    auto record = archive.get_record("r8");
+    const Record<CrdtString> *original = node.get_record("r8");
+    assert_true(record && *record == *original &&
+                    record->column_versions.at("tag").col_version == original->column_versions.at("tag").col_version,
+                "Archive: Records should decode with their versions");
+    assert_true(record->column_versions.at("tag").seq != 0 &&
+                    record->column_versions.at("tag").seq == original->column_versions.at("tag").seq,
+                "Archive: Versions should keep their sequence numbers");
+    size_t visited = 0;
+    archive.for_each_record([&](const CrdtString &, const Record<CrdtString> &) { ++visited; });
+    assert_true(visited == 499, "Archive: Iteration should visit the live records");
+
+    Crdt restored = archive.to_crdt(1);
+    assert_true(restored.state_hash() == node.state_hash() &&
+                    restored.get_changes_since(0).size() == node.get_changes_since(0).size(),
+                "Archive: Decoding the archive should restore a writable CRDT");
+
+    std::ofstream(path, std::ios::binary | std::ios::trunc) << "CRDTARC1 truncated";
+    bool rejected = false;
+    try {
+      CrdtArchive<Crdt> truncated(path);
+    } catch (const CrdtDecodeError &) {
+      rejected = true;
+    }
+    assert_true(rejected, "Archive: A truncated archive should be rejected");
+    std::filesystem::remove(path);
+    std::cout << "Test 'Archived Snapshots' passed." << std::endl;
+  }
+
+  // Test Case: Arrow Export
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    for (int i = 0; i < 10; ++i) {
+      CrdtString id = std::to_string(i);
+      node.insert_or_update("r" + id, {{"name", "n" + id}, {"score", std::to_string(i * 10)}});
+    }
+    node.insert_or_update("r3", {{"score", "not a number"}, {"active", "true"}});
+    node.delete_record("r9");
+    CrdtVector<CrdtArrowColumn> columns = {
+        {"name", CrdtArrowType::Utf8}, {"score", CrdtArrowType::Int64}, {"active", CrdtArrowType::Boolean}};
+
+    ArrowSchema schema;
+    crdt_arrow::export_schema<CrdtString>(columns, &schema);
+    assert_true(std::string(schema.format) == "+s" && schema.n_children == 4 &&
+                    std::string(schema.children[0]->name) == "record_id" &&
+                    std::string(schema.children[2]->format) == "l" && schema.children[2]->flags == ARROW_FLAG_NULLABLE,
+                "Arrow Export: The schema should list the record id and the columns");
+    schema.release(&schema);
+    assert_true(schema.release == nullptr, "Arrow Export: Releasing the schema should mark it released");
+
+    std::map<std::string, std::pair<std::optional<int64_t>, std::optional<bool>>> rows;
+    size_t batches = 0;
+    size_t exported = crdt_arrow::export_batches(node, columns, 4, [&](ArrowArray &batch) {
+      ++batches;
+      assert_true(batch.n_children == 4 && batch.length <= 4, "Arrow Export: Batches should hold up to 4 rows");
+      const ArrowArray &ids = *batch.children[0];
+      const ArrowArray &scores = *batch.children[2];
+      const ArrowArray &active = *batch.children[3];
+      auto valid = [](const ArrowArray &array, int64_t row) {
+        return (static_cast<const uint8_t *>(array.buffers[0])[row / 8] >> (row % 8)) & 1;
+      };
+      for (int64_t row = 0; row < batch.length; ++row) {
+        const int32_t *offsets = static_cast<const int32_t *>(ids.buffers[1]);
+        std::string id(static_cast<const char *>(ids.buffers[2]) + offsets[row], offsets[row + 1] - offsets[row]);
+        std::optional<int64_t> score;
+        if (valid(scores, row)) {
+          score = static_cast<const int64_t *>(scores.buffers[1])[row];
+        }
+        std::optional<bool> flag;
+        if (valid(active, row)) {
+          flag = (static_cast<const uint8_t *>(active.buffers[1])[row / 8] >> (row % 8)) & 1;
+        }
+        rows[id] = {score, flag};
+      }
+    });
+    assert_true(exported == 9 && rows.size() == 9 && batches == 3 && !rows.contains("r9"),
+                "Arrow Export: Only live records should be exported");
+    assert_true(rows["r4"].first == 40 && !rows["r4"].second, "Arrow Export: Missing fields should be null");
+    assert_true(!rows["r3"].first && rows["r3"].second == true, "Arrow Export: Unconvertible values should be null");
+
+    CRDT<int64_t, std::variant<int64_t, double, CrdtString>> typed(1);
+    typed.insert_or_update(7, {{"value", 2.5}});
+    typed.insert_or_update(8, {{"value", int64_t(3)}});
+    CrdtVector<double> values;
+    crdt_arrow::export_batches(typed, {{"value", CrdtArrowType::Float64}}, 100, [&](ArrowArray &batch) {
+      const double *data = static_cast<const double *>(batch.children[1]->buffers[1]);
+      values.assign(data, data + batch.length);
+    });
+    std::sort(values.begin(), values.end());
+    assert_true(values == CrdtVector<double>{2.5, 3.0}, "Arrow Export: Variant values should convert");
+    std::cout << "Test 'Arrow Export' passed." << std::endl;
+  }
+
+  // Test Case: CSV and JSONL Import/Export
+  {
+    CRDT<CrdtString, CrdtString> source(1);
+    source.insert_or_update("r1", {{"name", "Ann \"A\", Jr."}, {"note", "line\nbreak"}});
+    source.insert_or_update("r2", {{"name", "Bob"}});
+    source.insert_or_update("r3", {{"name", "Gone"}});
+    source.delete_record("r3");
+
+    std::stringstream jsonl;
+    assert_true(crdt_io::export_jsonl(source, jsonl) == 2, "CSV and JSONL: Only live records should be exported");
+    CRDT<CrdtString, CrdtString> from_jsonl(2);
+    uint64_t clock_before = from_jsonl.get_clock().current_time();
+    assert_true(crdt_io::import_jsonl(from_jsonl, jsonl) == 2 &&
+                    from_jsonl.get_clock().current_time() == clock_before + 1,
+                "CSV and JSONL: A JSONL import should be one transaction");
+    assert_true(from_jsonl.get_record("r2")->fields.at("name") == "Bob" && !from_jsonl.contains_record("r3") &&
+                    from_jsonl.get_record("r1")->fields.at("name") == "Ann \"A\", Jr." &&
+                    from_jsonl.get_record("r1")->fields.at("note") == "line\nbreak",
+                "CSV and JSONL: Records and escaped strings should round-trip through JSONL");
+
+    std::stringstream csv;
+    assert_true(crdt_io::export_csv(source, csv) == 2, "CSV and JSONL: Only live records should be exported to CSV");
+    CRDT<CrdtString, CrdtString> from_csv(3);
+    assert_true(crdt_io::import_csv(from_csv, csv) == 2 &&
+                    from_csv.get_record("r1")->fields.at("note") == "line\nbreak" &&
+                    !from_csv.get_record("r2")->fields.contains("note"),
+                "CSV and JSONL: CSV should round-trip, skipping empty cells");
+
+    CRDT<int64_t, std::variant<int64_t, double, bool, CrdtString>> typed(4);
+    std::stringstream legacy("{\"key\": 7, \"qty\": 3, \"price\": 2.5, \"ok\": true, \"gone\": null, "
+                             "\"s\": \"\\u00e9\"}\n\n{\"key\": 8}\n");
+    assert_true(crdt_io::import_jsonl(typed, legacy, "key") == 2, "CSV and JSONL: Blank lines should be skipped");
+    const auto &fields = typed.get_record(7)->fields;
+    assert_true(std::get<int64_t>(fields.at("qty")) == 3 && std::get<double>(fields.at("price")) == 2.5 &&
+                    std::get<bool>(fields.at("ok")) && !fields.contains("gone") &&
+                    std::get<CrdtString>(fields.at("s")) == "\xc3\xa9",
+                "CSV and JSONL: JSON scalars should keep their types");
+
+    CRDT<int64_t, double> mapped(5);
+    std::stringstream sheet("Id,Price,Ignored\r\n1,9.5,x\r\n2,,y\r\n");
+    CrdtCsvMapping mapping{"Id", {{"Price", "price"}}};
+    assert_true(crdt_io::import_csv(mapped, sheet, mapping) == 2 && mapped.get_record(1)->fields.at("price") == 9.5 &&
+                    mapped.get_record(2)->fields.empty(),
+                "CSV and JSONL: The column mapping should pick and rename columns");
+
+    std::stringstream bad("{\"id\": \"a\", \"x\": \"1\"}\n{\"id\": \"b\", \"x\": [1]}\n");
+    CRDT<CrdtString, CrdtString> partial(6);
+    try {
+      crdt_io::import_jsonl(partial, bad);
+      assert_true(false, "CSV and JSONL: A malformed line should throw");
+    } catch (const CrdtImportError &error) {
+      assert_true(error.line() == 2 && partial.len_live() == 1,
+                  "CSV and JSONL: The error should name the line and keep the records before it");
+    }
+    std::cout << "Test 'CSV and JSONL Import/Export' passed." << std::endl;
+  }
+
+  // Test Case: State and Diff Formatting
+  {
+    CRDT<CrdtString, CrdtString> left(1);
+    left.insert_or_update("r1", {{"name", "Ann"}, {"age", "3"}});
+    left.insert_or_update("r2", {{"name", "Bob"}});
+    CRDT<CrdtString, CrdtString> right(2);
+    right.merge_changes(left.get_changes_since(0));
+    right.insert_or_update("r1", {{"name", "Anne"}});
+    right.delete_record("r2");
+
+    CrdtString record = right.format_record("r1");
+    assert_true(record.starts_with("\"r1\"\n  age: \"3\" (col_version 1") &&
+                    record.find("  name: \"Anne\" (col_version 2, db_version 5, node 2") != CrdtString::npos,
+                "Formatting: Records should list their columns in name order with versions");
+    assert_true(right.format_record("r2").starts_with("\"r2\": deleted (col_version") &&
+                    right.format_record("r9") == "\"r9\": unknown\n",
+                "Formatting: Tombstones and unknown records should be reported");
+
+    CrdtString table = right.format_record("r1", CrdtFormatStyle::Table);
+    assert_true(table.starts_with("record | column | value  | col_version |") &&
+                    table.find("\"r1\"   | name   | \"Anne\" | 2           |") != CrdtString::npos,
+                "Formatting: Tables should pad their columns");
+
+    CrdtString diff = left.format_diff(right);
+    assert_true(diff.find("\"r1\" name: value mismatch\n  local:  \"Ann\" (col_version 1") != CrdtString::npos &&
+                    diff.find("\"r2\": tombstone mismatch") != CrdtString::npos &&
+                    diff.ends_with("2 differences\n"),
+                "Formatting: Diffs should show both sides of each difference");
+    assert_true(left.format_diff(left) == "0 differences\n", "Formatting: Equal states should have no differences");
+    assert_true(left.format_diff(right, CrdtFormatStyle::Table).find("| value mismatch     | \"Ann\" | \"Anne\" |") !=
+                    CrdtString::npos,
+                "Formatting: Diff tables should have a row per difference");
+
+    std::ostringstream state;
+    state << right;
+    assert_true(state.str().starts_with("Node 2 (clock 6, live 1, tombstones 1)\n\"r1\"\n"),
+                "Formatting: Streaming a CRDT should list its state in record id order");
+
+    CRDT<int64_t, std::variant<int64_t, CrdtString>> typed(3);
+    typed.insert_or_update(10, {{"n", int64_t(5)}, {"s", CrdtString("x")}});
+    assert_true(typed.format_record(10).find("  n: 5 (") != CrdtString::npos &&
+                    typed.format_record(10).find("  s: \"x\" (") != CrdtString::npos,
+                "Formatting: Variant values should render as their alternative");
+    std::cout << "Test 'State and Diff Formatting' passed." << std::endl;
+  }
+
+  // Test Case: Causal Ordering
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    auto version_of = [](const Change<CrdtString, CrdtString> &change) {
+      return ColumnVersion(change.col_version, change.db_version, change.node_id);
+    };
+    auto first = node1.insert_or_update("r", {{"x", "a"}});
+    ColumnVersion a = version_of(first.front());
+    CrdtVersionVector a_history = node1.max_seen_versions();
+    node2.merge_changes(std::move(first));
+    auto second = node2.insert_or_update("r", {{"x", "b"}});
+    ColumnVersion b = version_of(second.front());
+    CrdtVersionVector b_history = node2.max_seen_versions();
+    auto third = node1.insert_or_update("r", {{"x", "c"}});
+    ColumnVersion c = version_of(third.front());
+    CrdtVersionVector c_history = node1.max_seen_versions();
+
+    assert_true(crdt_causal_order(a, a_history, b, b_history) == CrdtCausality::Before &&
+                    crdt_causal_order(b, b_history, a, a_history) == CrdtCausality::After &&
+                    crdt_causal_order(b, b_history, c, c_history) == CrdtCausality::Concurrent &&
+                    crdt_causal_order(a, a_history, a, a_history) == CrdtCausality::Equal,
+                "Causal Ordering: Writes should be ordered by the histories of their writers");
+    assert_true(crdt_happened_before(a, node2.max_seen_versions()) &&
+                    !crdt_happened_before(c, node2.max_seen_versions()),
+                "Causal Ordering: A write should be in the history of the nodes that saw it");
+    assert_true(crdt_column_order(a, b) == CrdtCausality::Before &&
+                    crdt_column_order(b, c) == CrdtCausality::Concurrent &&
+                    crdt_column_order(second.front(), second.front()) == CrdtCausality::Equal,
+                "Causal Ordering: Column versions should order writes of the same column");
+
+    assert_true(crdt_causal_order(node1.max_seen_versions(), node2.max_seen_versions()) == CrdtCausality::Concurrent,
+                "Causal Ordering: Replicas that missed each other's writes should be concurrent");
+    CrdtVersionVector before_merge = node2.max_seen_versions();
+    node1.merge_changes(std::move(second));
+    assert_true(crdt_causal_order(before_merge, node1.max_seen_versions()) == CrdtCausality::Before &&
+                    crdt_causal_order(node1.max_seen_versions(), before_merge) == CrdtCausality::After &&
+                    crdt_causal_order(before_merge, before_merge) == CrdtCausality::Equal,
+                "Causal Ordering: A replica that saw everything another saw should be after it");
+    std::cout << "Test 'Causal Ordering' passed." << std::endl;
+  }
+
+  // Test Case: Epochs
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    CRDT<CrdtString, CrdtString> straggler(3);
+    auto initial = node1.insert_or_update("r", {{"x", "a"}, {"y", "b"}});
+    node2.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(initial));
+    straggler.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(initial));
+    auto missed = straggler.insert_or_update("r", {{"y", "late"}});
+
+    node1.start_epoch(1);
+    node2.start_epoch(1);
+    const ColumnVersion &rebased = node1.get_record("r")->column_versions.at("x");
+    assert_true(node1.epoch() == 1 && node1.get_clock().current_time() == crdt_epoch_start(1) &&
+                    rebased.db_version == crdt_epoch_start(1) && rebased.col_version == 1 && node1.diff(node2).empty(),
+                "Epochs: Starting an epoch should rebase every version the same way on every replica");
+    assert_true(node1.get_changes_since(0).size() == 2 && node1.max_seen_versions().empty(),
+                "Epochs: The rebased state should be returned to peers and the seen versions reset");
+
+    auto update = node1.insert_or_update("r", {{"x", "new"}});
+    assert_true(update.front().db_version == crdt_epoch_start(1) + 1, "Epochs: Counters should restart in the epoch");
+    straggler.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(update));
+    assert_true(straggler.get_record("r")->fields.at("x") == "a" && straggler.stats().changes_other_epoch == 1,
+                "Epochs: Changes from a later epoch should be dropped");
+    node2.merge_changes(std::move(update));
+    assert_true(node2.get_record("r")->fields.at("x") == "new", "Epochs: Changes within the epoch should merge");
+
+    node1.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(missed));
+    assert_true(node1.get_record("r")->fields.at("y") == "b" && node1.stats().changes_other_epoch == 1,
+                "Epochs: Changes from an earlier epoch should be dropped by default");
+    node1.set_epoch_policy(CrdtEpochPolicy::Translate);
+    node2.set_epoch_policy(CrdtEpochPolicy::Translate);
+    node1.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(missed));
+    node2.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(initial));
+    node2.merge_changes(std::move(missed));
+    assert_true(node1.get_record("r")->fields.at("y") == "late" &&
+                    node1.get_record("r")->column_versions.at("y").db_version == crdt_epoch_start(1) &&
+                    node2.get_record("r")->fields.at("x") == "new" && node1.diff(node2).empty(),
+                "Epochs: Translated changes should win only with a newer column version, on every replica alike");
+
+    bool threw = false;
+    try {
+      node1.start_epoch(1);
+    } catch (const std::invalid_argument &) {
+      threw = true;
+    }
+    assert_true(threw, "Epochs: Epochs should only increase");
+    std::cout << "Test 'Epochs' passed." << std::endl;
+  }
+
+  // Test Case: Clock Anomalies
+  {
+    CRDT<CrdtString, CrdtString> node(1);
+    CrdtVector<CrdtClockAnomaly> anomalies;
+    node.set_clock_anomaly_handler([&](const CrdtClockAnomaly &anomaly) { anomalies.push_back(anomaly); });
+    node.set_clock_guard(CrdtClockGuard{1000});
+    auto change_at = [](const CrdtString &record_id, uint64_t db_version) {
+      return CrdtVector<Change<CrdtString, CrdtString>>{
+          Change<CrdtString, CrdtString>(record_id, CrdtString("x"), CrdtString("v"), 1, db_version, 2)};
+    };
+
+    node.merge_changes(change_at("near", 500));
+    node.merge_changes(change_at("far", 1000000));
+    assert_true(node.contains_record("near") && !node.contains_record("far") && node.get_clock().current_time() == 501,
+                "Clock Anomalies: Changes too far ahead should be dropped without moving the clock");
+    assert_true(anomalies.size() == 1 && anomalies[0].kind == CrdtClockAnomalyKind::FarAhead &&
+                    anomalies[0].node_id == CrdtNodeId(2) && anomalies[0].received_time == 1000000 &&
+                    anomalies[0].rejected && node.stats().clock_anomalies == 1,
+                "Clock Anomalies: Changes too far ahead should be reported");
+
+    node.set_clock_guard(CrdtClockGuard{1000, CrdtClockAnomalyAction::Report});
+    node.merge_changes(change_at("far", 1000000));
+    assert_true(node.contains_record("far") && anomalies.size() == 2 && !anomalies[1].rejected,
+                "Clock Anomalies: Reported changes should still be merged");
+
+    node.set_clock_guard(std::nullopt);
+    uint64_t last = crdt_epoch_start(1) - 1;
+    node.merge_changes(change_at("last", last));
+    assert_true(!node.contains_record("last") && anomalies.size() == 3 &&
+                    anomalies[2].kind == CrdtClockAnomalyKind::Exhausted && node.get_clock().current_time() < last,
+                "Clock Anomalies: Changes that would exhaust the clock should be dropped");
+    node.merge_changes(change_at("almost", last - 1));
+    assert_true(node.get_clock().current_time() == last,
+                "Clock Anomalies: The clock should reach the end of the epoch");
+    bool threw = false;
+    try {
+      node.insert_or_update("local", {{"x", "v"}});
+    } catch (const std::overflow_error &) {
+      threw = true;
+    }
+    assert_true(threw && !node.contains_record("local") && anomalies.size() == 4 && !anomalies[3].node_id,
+                "Clock Anomalies: Local operations should refuse to overflow the clock");
+    node.start_epoch(1);
+    node.insert_or_update("local", {{"x", "v"}});
+    assert_true(node.contains_record("local"), "Clock Anomalies: A new epoch should make room for the clock");
+    std::cout << "Test 'Clock Anomalies' passed." << std::endl;
+  }
+
+  // Test Case: Site Registry
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    CRDT<CrdtString, CrdtString> node3(3);
+    for (auto *node : {&node1, &node2, &node3}) {
+      node->enable_site_registry("__sites__");
+    }
+    auto sync = [&](CRDT<CrdtString, CrdtString> &from, CRDT<CrdtString, CrdtString> &to) {
+      to.merge_changes(from.get_changes_since(0));
+    };
+
+    node1.join_site();
+    node2.join_site();
+    node3.join_site();
+    assert_true(node1.join_site().empty(), "Site Registry: Joining again should be a no-op");
+    sync(node2, node1);
+    sync(node3, node1);
+    sync(node1, node2);
+    sync(node1, node3);
+    assert_true(node2.active_sites() == CrdtVector<CrdtNodeId>{1, 2, 3},
+                "Site Registry: Joins should replicate");
+
+    CrdtMap<CrdtNodeId, uint64_t> acks{{2, 2}};
+    assert_true(!node1.acknowledged_version(acks), "Site Registry: Active sites without an ack should be waited for");
+    acks[3] = 1;
+    assert_true(node1.acknowledged_version(acks) == 1u, "Site Registry: The lowest ack should be used");
+
+    node3.insert_or_update("r", {{"x", "last"}});
+    node3.retire_site(3);
+    uint64_t final_version = node3.get_clock().current_time() - 1;
+    assert_true(node3.site(3).status == CrdtSiteStatus::Retired && node3.site(3).final_version == final_version,
+                "Site Registry: A site retiring itself should use its clock as final version");
+    bool threw = false;
+    try {
+      node3.join_site();
+    } catch (const std::invalid_argument &) {
+      threw = true;
+    }
+    assert_true(threw, "Site Registry: A retired site should not rejoin");
+
+    // An operator retires node3 on node1 before node1 has its last write, so node1 keeps waiting
+    node1.retire_site(3, final_version);
+    acks.erase(3);
+    assert_true(node1.active_sites() == CrdtVector<CrdtNodeId>{1, 2} && !node1.acknowledged_version(acks) &&
+                    node1.forget_retired_sites() == 0,
+                "Site Registry: Retired sites should be waited for until drained");
+    sync(node3, node1);
+    assert_true(node1.acknowledged_version(acks) == 2u, "Site Registry: Drained retired sites should be skipped");
+    assert_true(node1.forget_retired_sites() == 1 && node1.max_seen_versions().count(3) == 0 &&
+                    node1.acknowledged_version(acks) == 2u,
+                "Site Registry: Forgetting should prune seen versions");
+
+    threw = false;
+    try {
+      node3.retire_site(3, final_version - 1);
+      node1.retire_site(3, final_version + 1);
+    } catch (const std::exception &) {
+      threw = true;
+    }
+    assert_true(!threw && node1.site(3).final_version == final_version + 1,
+                "Site Registry: Retiring again with a higher final version should raise it");
+
+    CRDT<CrdtString, CrdtString> plain(4);
+    threw = false;
+    try {
+      plain.join_site();
+    } catch (const std::logic_error &) {
+      threw = true;
+    }
+    assert_true(threw, "Site Registry: Joining without a registry should throw");
+    std::cout << "Test 'Site Registry' passed." << std::endl;
+  }
+
+  // Test Case: Strict Site Mode
+  {
+    CRDT<CrdtString, CrdtString> server(1);
+    CRDT<CrdtString, CrdtString> admin(2);
+    CRDT<CrdtString, CrdtString> rogue(3);
+    for (auto *node : {&server, &admin, &rogue}) {
+      node->enable_site_registry("__sites__");
+    }
+    server.set_strict_sites(true);
+
+    server.merge_changes(admin.join_site());
+    admin.insert_or_update("a", {{"x", "1"}});
+    server.merge_changes(admin.get_changes_since(0));
+    assert_true(server.active_sites() == CrdtVector<CrdtNodeId>{2} && server.contains_record("a"),
+                "Strict Site Mode: The first site should register itself");
+
+    uint64_t clock = server.get_clock().current_time();
+    rogue.join_site();
+    rogue.insert_or_update("b", {{"x", "2"}});
+    server.merge_changes(rogue.get_changes_since(0));
+    assert_true(!server.contains_record("b") && server.active_sites() == CrdtVector<CrdtNodeId>{2} &&
+                    server.quarantined().size() == 2 && server.stats().changes_unregistered == 2 &&
+                    server.get_clock().current_time() == clock,
+                "Strict Site Mode: Changes of unregistered sites should be quarantined");
+
+    CrdtVector<Change<CrdtString, CrdtString>> batch = admin.join_site(3);
+    server.merge_changes(std::move(batch));
+    server.merge_changes(server.take_quarantined());
+    assert_true(server.contains_record("b") && server.quarantined().empty(),
+                "Strict Site Mode: Changes of registered sites should be merged");
+
+    server.merge_changes(admin.retire_site(3, rogue.get_clock().current_time()));
+    rogue.insert_or_update("c", {{"x", "3"}});
+    server.merge_changes(rogue.get_changes_since(0));
+    assert_true(!server.contains_record("c") && server.quarantined().size() == 1,
+                "Strict Site Mode: Changes of retired sites after their final version should be quarantined");
+    std::cout << "Test 'Strict Site Mode' passed." << std::endl;
+  }
+
+  // Test Case: Missing Change Sync
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    CRDT<CrdtString, CrdtString> node3(3);
+    node1.insert_or_update("a", {{"x", "1"}});
+    node2.merge_changes(node1.get_changes_since(0));
+    node3.merge_changes(node2.get_changes_since(0));
+    assert_true(node1.changes_missing_for(node3.record_digests()).empty(),
+                "Missing Change Sync: Changes received through another node should not be sent again");
+
+    node2.insert_or_update("b", {{"x", "2"}});
+    node2.delete_record("a");
+    node1.merge_changes(node2.get_changes_since(0));
+    node1.insert_or_update("c", {{"x", "3"}});
+    CrdtVector<Change<CrdtString, CrdtString>> missing = node1.changes_missing_for(node3.record_digests());
+    assert_true(missing.size() == 3, "Missing Change Sync: Only the changes the peer lacks should be returned");
+    node3.merge_changes(std::move(missing));
+    assert_true(node3.diff(node1).empty() && node1.changes_missing_for(node3.record_digests()).empty(),
+                "Missing Change Sync: The peer should converge");
+    assert_true(node1.changes_missing_for({}).size() == node1.get_changes_since(0).size(),
+                "Missing Change Sync: An empty peer should get every change");
+
+    // A partial delivery of a later write must not hide an earlier one
+    CRDT<CrdtString, CrdtString> writer(4);
+    CRDT<CrdtString, CrdtString> reader(5);
+    writer.insert_or_update("d", {{"x", "1"}});
+    writer.insert_or_update("e", {{"x", "2"}});
+    reader.merge_changes(writer.get_changes_for({"e"}));
+    missing = writer.changes_missing_for(reader.record_digests());
+    assert_true(missing.size() == 1 && missing[0].record_id == "d",
+                "Missing Change Sync: Records skipped by a partial delivery should be found");
+    reader.merge_changes(std::move(missing));
+    assert_true(reader.state_hash() == writer.state_hash(),
+                "Missing Change Sync: The partial delivery should be completed");
+    std::cout << "Test 'Missing Change Sync' passed." << std::endl;
+  }
+
+  // Test Case: IBLT Set Reconciliation
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    for (int i = 0; i < 1000; ++i) {
+      node1.insert_or_update("rec" + std::to_string(i), {{"x", std::to_string(i)}});
+    }
+    node2.merge_changes(node1.get_changes_since(0));
+    auto sketch1 = Iblt<CrdtString>::build(node1, 60);
+    auto sketch2 = Iblt<CrdtString>::build(node2, 60);
+    assert_true(sketch1.differing_records(sketch2) == CrdtVector<CrdtString>{},
+                "IBLT: Converged replicas should have nothing to ship");
+
+    node1.insert_or_update("rec7", {{"x", "changed"}});
+    node1.insert_or_update("only1", {{"x", "a"}});
+    node2.delete_record("rec300");
+    node2.insert_or_update("only2", {{"x", "b"}});
+    sketch1 = Iblt<CrdtString>::build(node1, 60);
+    sketch2 = Iblt<CrdtString>::build(node2, 60);
+    CrdtBytes wire = sketch2.encode();
+    assert_true(wire.size() < 2000, "IBLT: The sketch size should not depend on the number of records");
+    std::optional<CrdtVector<CrdtString>> to_node2 = sketch1.differing_records(Iblt<CrdtString>::decode(wire));
+    std::optional<CrdtVector<CrdtString>> to_node1 =
+        sketch2.differing_records(Iblt<CrdtString>::decode(sketch1.encode()));
+    assert_true(to_node2 && to_node1, "IBLT: A few differences should decode");
+    std::sort(to_node2->begin(), to_node2->end());
+    std::sort(to_node1->begin(), to_node1->end());
+    assert_true(*to_node2 == CrdtVector<CrdtString>({"only1", "rec300", "rec7"}) &&
+                    *to_node1 == CrdtVector<CrdtString>({"only2", "rec300", "rec7"}),
+                "IBLT: Each side should find the records the other lacks or holds at another version");
+    node2.merge_changes(node1.get_changes_for(*to_node2));
+    node1.merge_changes(node2.get_changes_for(*to_node1));
+    assert_true(node1.diff(node2).empty(), "IBLT: Shipping the differing records should converge the replicas");
+
+    CRDT<CrdtString, CrdtString> node3(3);
+    assert_true(!Iblt<CrdtString>::build(node3, 60).differing_records(Iblt<CrdtString>::build(node1, 60)),
+                "IBLT: Too many differences should fail to decode");
+    std::cout << "Test 'IBLT Set Reconciliation' passed." << std::endl;
+  }
+
+  // Test Case: Archive Checksums
+  {
+    using Crdt = CRDT<CrdtString, CrdtString>;
+    std::filesystem::path path = std::filesystem::temp_directory_path() / "crdt_checksum_test.arc";
+    Crdt node(1);
+    for (int i = 0; i < 100; ++i) {
+      node.insert_or_update("r" + std::to_string(i), {{"name", "value" + std::to_string(i)}});
+    }
+    crdt_archive::save(node, path);
+    CrdtArchive<Crdt>(path).verify();
+
+    // Flip a bit in one record's value, as bit rot would
+    CrdtString contents;
+    {
+      std::ifstream in(path, std::ios::binary);
+      contents.assign(std::istreambuf_iterator<char>(in), std::istreambuf_iterator<char>());
+    }
+    size_t position = contents.find("value42");
+    assert_true(position != CrdtString::npos, "Archive Checksums: The value should be stored");
+    contents[position + 5] ^= 0x01;
+    std::ofstream(path, std::ios::binary | std::ios::trunc) << contents;
+
+    auto throws_checksum_error = [](auto &&fn) {
+      try {
+        fn();
+      } catch (const CrdtChecksumError &) {
+        return true;
+      }
+      return false;
+    };
+    CrdtArchive<Crdt> archive(path);
+    assert_true(archive.has_checksums() && archive.get_value("r42", "name") == std::optional<CrdtString>("value52"),
+                "Archive Checksums: Reads should not verify by default");
+    assert_true(throws_checksum_error([&] { archive.verify(); }) && throws_checksum_error([&] { archive.to_crdt(1); }),
+                "Archive Checksums: Verifying and loading should detect the corruption");
+    CrdtArchive<Crdt> verified(path, true);
+    assert_true(throws_checksum_error([&] { verified.get_record("r42"); }) &&
+                    verified.get_value("r41", "name") == std::optional<CrdtString>("value41"),
+                "Archive Checksums: Verified reads should detect only the corrupted record");
+    std::filesystem::remove(path);
+    std::cout << "Test 'Archive Checksums' passed." << std::endl;
+  }
+
+  // Test Case: Invariant Checks
+  {
+    using Crdt = CRDT<CrdtString, CrdtString>;
+    Crdt node1(1);
+    Crdt node2(2);
+    node1.insert_or_update("a", {{"x", "1"}});
+    node1.delete_record("a");
+    node1.insert_or_update("a", {{"x", "2"}});
+    node1.delete_record("b");
+    node2.merge_changes(node1.get_changes_since(0));
+    assert_true(node1.check_invariants().empty() && node2.check_invariants().empty(),
+                "Invariant Checks: A consistent state should pass");
+
+    CrdtMap<CrdtString, Record<CrdtString>> data;
+    data["live"].fields.emplace("x", "1");
+    data["live"].column_versions.emplace("x", ColumnVersion((uint64_t{1} << 32) + 1, 4, 1, 4));
+    data["live"].fields.emplace("y", "unversioned");
+    data["dead"].revival = ColumnVersion(1, 5, 1, 5);
+    CrdtMap<CrdtString, CrdtTombstone> tombstones;
+    tombstones.emplace("gone", CrdtTombstone{ColumnVersion(2, 3, 1, 3)});
+    Crdt broken = Crdt::from_parts(1, 10, std::move(data), std::move(tombstones));
+    CrdtVector<CrdtInvariantViolation<CrdtString>> violations = broken.check_invariants();
+    std::sort(violations.begin(), violations.end(),
+              [](const auto &a, const auto &b) { return a.kind < b.kind; });
+    assert_true(violations.size() == 4 && violations[0].kind == CrdtInvariantKind::TombstoneNotDeletion &&
+                    violations[1].kind == CrdtInvariantKind::LiveRecordDeleted && violations[1].record_id == "dead" &&
+                    violations[2].kind == CrdtInvariantKind::FieldWithoutVersion && violations[2].col_name == "y" &&
+                    violations[3].kind == CrdtInvariantKind::ColumnAfterRecord && violations[3].clock == 10,
+                "Invariant Checks: Violations should be reported with their location");
+    std::ostringstream text;
+    text << violations[3];
+    assert_true(text.str() == "\"live\" x: column from a later incarnation than its record (col_version 4294967297, "
+                              "db_version 4, node 1, local_db_version 4), clock 10",
+                "Invariant Checks: Violations should format as diagnostics");
+    std::cout << "Test 'Invariant Checks' passed." << std::endl;
+  }
+
+  // Test Case: Deletion Model
+  {
+    using Crdt = CRDT<CrdtString, CrdtString>;
+    Crdt node1(1);
+    Crdt node2(2);
+    node1.insert_or_update("a", {{"__deleted__", "user data"}, {"x", "1"}});
+    node2.merge_changes(node1.get_changes_since(0));
+    assert_true(node2.get_record("a") && node2.get_record("a")->fields.at("__deleted__") == "user data" &&
+                    !node2.get_record("a")->revival,
+                "Deletion Model: A column named __deleted__ should be ordinary user data");
+
+    node1.delete_record("a");
+    node1.revive_record("a", {{"x", "2"}});
+    auto changes = node1.get_changes_since(0);
+    size_t updates = 0, deletes = 0, revives = 0;
+    for (const auto &change : changes) {
+      switch (change.kind()) {
+      case ChangeKind::Update:
+        updates += change.col_name.has_value();
+        break;
+      case ChangeKind::Delete:
+        ++deletes;
+        break;
+      case ChangeKind::Revive:
+        revives += !change.col_name.has_value();
+        break;
+      }
+    }
+    assert_true(updates == 1 && deletes == 0 && revives == 1,
+                "Deletion Model: Changes should be told apart by kind");
+    assert_true(node1.get_record("a")->revival && node1.get_record("a")->revival->col_version % 2 == 0 &&
+                    !node1.get_record("a")->column_versions.contains("__deleted__"),
+                "Deletion Model: A revived record should keep its revival version apart from its columns");
+    node2.merge_changes(std::move(changes));
+    assert_true(node2.get_record("a") && node2.get_record("a")->revival && node2.state_hash() == node1.state_hash(),
+                "Deletion Model: Revivals should merge");
+
+    node1.delete_record("b");
+    auto deletion = node1.get_changes_since(node1.get_clock().current_time() - 1);
+    assert_true(deletion.size() == 1 && deletion[0].kind() == ChangeKind::Delete && !deletion[0].col_name,
+                "Deletion Model: A deletion should be a record-level change");
+
+    std::filesystem::path path = std::filesystem::temp_directory_path() / "crdt_deletion_model_test.arc";
+    crdt_archive::save(node1, path);
+    CrdtArchive<Crdt> archive(path);
+    auto archived = archive.get_record("a");
+    assert_true(archived && archived->revival &&
+                    archived->revival->col_version == node1.get_record("a")->revival->col_version &&
+                    Crdt::record_digest(*archived) == Crdt::record_digest(*node1.get_record("a")) &&
+                    archive.to_crdt(1).state_hash() == node1.state_hash(),
+                "Deletion Model: Revivals should survive archiving");
+    std::filesystem::remove(path);
+    std::cout << "Test 'Deletion Model' passed." << std::endl;
+  }
+
+  // Test Case: Column Version Exhaustion
+  {
+    using Crdt = CRDT<CrdtString, CrdtString>;
+    Crdt node1(1);
+    Crdt node2(2);
+    CrdtVector<Change<CrdtString, CrdtString>> changes;
+    changes.emplace_back("r", "a", "1", 0xFFFFFFFF, 1, 1);
+    changes.emplace_back("r", "b", "1", 1, 1, 1);
+    node1.merge_changes(CrdtVector<Change<CrdtString, CrdtString>>(changes));
+    node2.merge_changes(std::move(changes));
+
+    bool threw = false;
+    try {
+      node1.insert_or_update("r", {{"a", "2"}, {"b", "2"}});
+    } catch (const std::overflow_error &) {
+      threw = true;
+    }
+    assert_true(threw && *node1.get_value("r", "a") == "1" &&
+                    *node1.get_value("r", "b") == "1",
+                "Column Exhaustion: Writing an exhausted column should throw and write nothing");
+    threw = false;
+    try {
+      node1.delete_field("r", "a");
+    } catch (const std::overflow_error &) {
+      threw = true;
+    }
+    assert_true(threw && node1.get_record("r")->column_versions.at("a").col_version == 0xFFFFFFFF,
+                "Column Exhaustion: Deleting an exhausted column should throw");
+
+    node2.merge_changes(node1.insert_or_update("r", {{"b", "3"}}));
+    assert_true(*node2.get_value("r", "a") == "1" &&
+                    *node2.get_value("r", "b") == "3" && !node2.get_record("r")->revival,
+                "Column Exhaustion: Peers should not see a new incarnation");
+
+    node1.delete_record("r");
+    auto revived = node1.revive_record("r", {{"a", "4"}});
+    node2.merge_changes(std::move(revived));
+    assert_true(*node2.get_value("r", "a") == "4" && node2.state_hash() == node1.state_hash(),
+                "Column Exhaustion: A revived record should write the column again");
+    std::cout << "Test 'Column Version Exhaustion' passed." << std::endl;
+  }
+
+  // Test Case: Placeholder Revivals
+  {
+    using Crdt = CRDT<CrdtString, CrdtString>;
+    Crdt node1(1);
+    Crdt node2(2);
+    Crdt node3(3);
+    node1.insert_or_update("r", {{"a", "1"}});
+    node2.merge_changes(node1.get_changes_since(0));
+    node3.merge_changes(node1.get_changes_since(0));
+    uint64_t since = node1.get_clock().current_time();
+    node1.delete_record("r");
+    auto revived = node1.revive_record("r", {{"a", "2"}});
+
+    // The column of the new incarnation reaches node2 before the revival
+    CrdtVector<Change<CrdtString, CrdtString>> columns;
+    for (const auto &change : revived) {
+      if (change.col_name) {
+        columns.push_back(change);
+      }
+    }
+    node2.merge_changes(std::move(columns));
+    assert_true(node2.get_record("r") && node2.get_record("r")->revival && *node2.get_value("r", "a") == "2",
+                "Placeholder Revivals: A write of a newer incarnation should revive the record");
+    auto relayed = node2.get_changes_since(0);
+    bool sent = false;
+    for (const auto &change : relayed) {
+      sent = sent || !change.col_name;
+    }
+    size_t refs = 0;
+    node2.for_each_change_since(0, [&](const ChangeRef<CrdtString, CrdtString> &change) { refs += !change.col_name; });
+    assert_true(!sent && refs == 0 && node2.get_changes_for({"r"}).size() == 1,
+                "Placeholder Revivals: Placeholders should not be sent on");
+
+    node3.merge_changes(std::move(relayed));
+    assert_true(*node3.get_value("r", "a") == "2" && node3.max_seen_versions().count(CrdtNodeId{}) == 0,
+                "Placeholder Revivals: Relaying should not note a made-up node");
+    node2.merge_changes(node1.get_changes_since(since));
+    node3.merge_changes(node1.get_changes_since(since));
+    assert_true(node2.state_hash() == node1.state_hash() && node3.state_hash() == node1.state_hash(),
+                "Placeholder Revivals: The real revival should replace the placeholder");
+    std::cout << "Test 'Placeholder Revivals' passed." << std::endl;
+  }
+
   std::cout << "All tests passed successfully!" << std::endl;
   return 0;
 }
\ No newline at end of file
@@ -0,0 +1,80 @@
+// trace_tests.cpp
+#define CRDT_TRACING
+#include "crdt.hpp"
+#include "crdt_sync.hpp"
+
+#include <cstdlib>
+#include <iostream>
+#include <string>
+
+/// Simple assertion helper
+void assert_true(bool condition, const CrdtString &message) {
+  if (!condition) {
+    std::cerr << "Assertion failed: " << message << std::endl;
+    exit(1);
+  }
+}
+
+int main() {
+  CrdtVector<CrdtTraceEvent> events;
+  crdt_trace_sink() = [&](const CrdtTraceEvent &event) { events.push_back(event); };
+  auto find = [&](const CrdtString &name) -> const CrdtTraceEvent * {
+    for (const auto &event : events) {
+      if (name == event.name) {
+        return &event;
+      }
+    }
+    return nullptr;
+  };
+
+  // Test Case: Merge and Extraction Spans
+  {
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node1.insert_or_update("r", {{"a", "1"}, {"b", "1"}});
+    node2.insert_or_update("r", {{"a", "2"}});
+    node2.insert_or_update("r", {{"a", "3"}});
+
+    auto changes = node1.get_changes_since(0);
+    const CrdtTraceEvent *extract = find("get_changes_since");
+    assert_true(extract && extract->changes == 2, "Tracing: Extraction should report the number of changes");
+
+    node2.merge_changes(std::move(changes));
+    const CrdtTraceEvent *merge = find("merge_changes");
+    assert_true(merge && merge->changes == 2 && merge->accepted == 1 && merge->rejected == 1,
+                "Tracing: Merge should report accepted and rejected changes");
+    assert_true(merge->duration.count() >= 0, "Tracing: Spans should be timed");
+    std::cout << "Test 'Merge and Extraction Spans' passed." << std::endl;
+  }
+
+  // Test Case: Sync Session Spans
+  {
+    events.clear();
+    CRDT<CrdtString, CrdtString> node1(1);
+    CRDT<CrdtString, CrdtString> node2(2);
+    node1.insert_or_update("r", {{"a", "1"}});
+    SyncSession<CRDT<CrdtString, CrdtString>> session1(node1, 1);
+    SyncSession<CRDT<CrdtString, CrdtString>> session2(node2, 2);
+    session2.on_frame(session1.start()[0]);
+    auto changes_frame = session1.on_frame(session2.start()[0]);
+    auto ack_frame = session2.on_frame(changes_frame[0]);
+    session1.on_frame(ack_frame[0]);
+
+    const CrdtTraceEvent *hello = nullptr;
+    for (const auto &event : events) {
+      if (CrdtString(event.name) == "sync.hello" && event.changes == 1) {
+        hello = &event;
+      }
+    }
+    const CrdtTraceEvent *received = find("sync.changes");
+    assert_true(hello && hello->changes == 1, "Tracing: Hello should report the changes sent");
+    assert_true(received && received->changes == 1 && received->version > 0,
+                "Tracing: Changes should report the changes received");
+    assert_true(find("sync.ack") != nullptr, "Tracing: Ack should be traced");
+    std::cout << "Test 'Sync Session Spans' passed." << std::endl;
+  }
+
+  crdt_trace_sink() = nullptr;
+  std::cout << "All tracing tests passed successfully!" << std::endl;
+  return 0;
+}